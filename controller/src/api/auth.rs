@@ -0,0 +1,200 @@
+use crate::api::request_path;
+use crate::api::response::error_response;
+use crate::api::RikError;
+use crate::database::RikRepository;
+use rand::distributions::{Alphanumeric, DistString};
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::io;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use tracing::{event, Level};
+
+/// Error code of the requests without the token of the API
+pub const UNAUTHORIZED_CODE: &str = "Unauthorized";
+/// Paths answered without a token, probed by the supervisors of the controller
+const UNAUTHENTICATED_PATHS: [&str; 2] = ["/healthz", "/readyz"];
+
+/// Field of the value of a tenant holding the SHA-256 of its API key, the key
+/// itself is only given to the client
+pub const API_KEY_FIELD: &str = "api_key_sha256";
+/// Prefix of the API keys, telling them apart from the token of the API
+const API_KEY_PREFIX: &str = "rik_";
+const API_KEY_LENGTH: usize = 40;
+
+thread_local! {
+    /// Tenant whose key authenticated the request handled by the current thread
+    static TENANT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Static bearer token clients must send, the API is open when there is none
+#[derive(Debug, Clone, Default)]
+pub struct ApiAuth {
+    token: Option<String>,
+}
+
+impl ApiAuth {
+    pub fn new(token: Option<String>) -> ApiAuth {
+        ApiAuth {
+            token: token.filter(|token| !token.is_empty()),
+        }
+    }
+
+    /// Token read at startup from `API_TOKEN`
+    fn from_env() -> ApiAuth {
+        let auth = ApiAuth::new(std::env::var("API_TOKEN").ok());
+        if auth.token.is_none() {
+            event!(Level::WARN, "No API_TOKEN set, the API is open to anyone");
+        }
+        auth
+    }
+
+    /// Refuse a request with a `401` unless it sends `Authorization: Bearer
+    /// <token>` with the token of the API or the key of a tenant, or its path
+    /// is one of the probes. Gives the tenant of the key, if one was used.
+    ///
+    /// A tenant key is also accepted when the API is open, a key which is not
+    /// known anymore is always refused.
+    pub fn check(
+        &self,
+        request: &tiny_http::Request,
+        connection: &Connection,
+    ) -> Result<Option<String>, tiny_http::Response<io::Cursor<Vec<u8>>>> {
+        let path = request_path(request.url());
+        if UNAUTHENTICATED_PATHS.contains(&path) {
+            return Ok(None);
+        }
+        let sent = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Authorization"))
+            .and_then(|header| bearer_token(header.value.as_str()));
+        let message = match (sent, &self.token) {
+            (Some(sent), Some(token)) if constant_time_eq(sent.as_bytes(), token.as_bytes()) => {
+                return Ok(None)
+            }
+            (Some(sent), _) => match find_tenant_by_key(connection, sent) {
+                Some(tenant_id) => return Ok(Some(tenant_id)),
+                None => "The bearer token is neither the one of the API nor a tenant key",
+            },
+            (None, None) => return Ok(None),
+            (None, Some(_)) => "An Authorization header with a bearer token is required",
+        };
+        event!(Level::WARN, "Request refused, {}", message);
+        Err(error_response(401, UNAUTHORIZED_CODE, message)
+            .with_header(tiny_http::Header::from_str("WWW-Authenticate: Bearer").unwrap()))
+    }
+}
+
+/// Refuse with a `403` a route acting on the whole cluster, as flagged in the
+/// route table, to a request authenticated with the key of a tenant
+pub fn check_tenant_route(
+    tenant_id: Option<&str>,
+    path: &str,
+    cluster_wide: bool,
+) -> Result<(), tiny_http::Response<io::Cursor<Vec<u8>>>> {
+    match tenant_id {
+        Some(tenant_id) if cluster_wide => {
+            event!(
+                Level::WARN,
+                "Request of tenant {} to {} refused",
+                tenant_id,
+                path
+            );
+            Err(error_response(
+                403,
+                "Forbidden",
+                "A tenant key cannot act on the whole cluster",
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Run a request handler, the elements it lists, creates and deletes are the
+/// ones of the tenant authenticated by its key
+pub fn with_tenant<R>(tenant_id: Option<String>, f: impl FnOnce() -> R) -> R {
+    let previous = TENANT.with(|current| current.replace(tenant_id));
+    let result = f();
+    TENANT.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// Tenant authenticated by the key of the request being handled
+pub fn authenticated_tenant() -> Option<String> {
+    TENANT.with(|current| current.borrow().clone())
+}
+
+/// Token of an `Authorization` header value, whatever the case of its scheme
+fn bearer_token(value: &str) -> Option<&str> {
+    let (scheme, token) = value.trim().split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("Bearer")
+        .then(|| token.trim())
+        .filter(|token| !token.is_empty())
+}
+
+/// Compare tokens in a time which does not depend on where they differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Authentication shared by every server thread
+pub fn api_auth() -> &'static ApiAuth {
+    static AUTH: OnceLock<ApiAuth> = OnceLock::new();
+    AUTH.get_or_init(ApiAuth::from_env)
+}
+
+/// New API key of a tenant, with the value to store, its SHA-256 replacing
+/// the previous one. Values which are not JSON objects cannot hold a key.
+pub fn with_new_api_key(value: &str) -> Result<(String, String), RikError> {
+    let mut value: serde_json::Value = match value.trim() {
+        "" => serde_json::json!({}),
+        value => serde_json::from_str(value).unwrap_or_default(),
+    };
+    let Some(fields) = value.as_object_mut() else {
+        return Err(RikError::InvalidBody(String::from(
+            "The value of a tenant must be a JSON object",
+        )));
+    };
+    let key = format!(
+        "{}{}",
+        API_KEY_PREFIX,
+        Alphanumeric.sample_string(&mut rand::thread_rng(), API_KEY_LENGTH)
+    );
+    fields.insert(API_KEY_FIELD.to_string(), hash_api_key(&key).into());
+    Ok((key, value.to_string()))
+}
+
+/// Tenant whose API key is `key`, keys are looked up by their SHA-256
+pub fn find_tenant_by_key(connection: &Connection, key: &str) -> Option<String> {
+    if !key.starts_with(API_KEY_PREFIX) {
+        return None;
+    }
+    RikRepository::find_by_value_field(connection, "/tenant", API_KEY_FIELD, &hash_api_key(key))
+        .ok()?
+        .into_iter()
+        .next()
+        .map(|tenant| tenant.id)
+}
+
+fn hash_api_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(bearer_token("Bearer secret"), Some("secret"));
+        assert_eq!(bearer_token("bearer  secret "), Some("secret"));
+        assert_eq!(bearer_token("Basic c2VjcmV0"), None);
+        assert_eq!(bearer_token("Bearer "), None);
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+    }
+}
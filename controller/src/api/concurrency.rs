@@ -0,0 +1,383 @@
+use crate::api::limits::limit_from_env;
+use crate::api::response::error_response;
+use serde::Serialize;
+use std::io;
+use std::str::FromStr;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{event, Level};
+
+/// Error code of the requests refused because too many are in flight
+pub const TOO_MANY_REQUESTS_CODE: &str = "TooManyRequests";
+/// Seconds clients are told to wait before sending a refused request again
+const RETRY_AFTER_SECONDS: u64 = 1;
+
+/// Kind of work a request does, each class has its own cap
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteClass {
+    /// `GET` requests
+    Read,
+    /// Every other request, they mutate the cluster
+    Write,
+    /// Requests holding their connection, such as event watches
+    Stream,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConcurrencyLimits {
+    /// Reads and writes in flight at once, streams have their own pool
+    pub max_requests: usize,
+    /// Kept below `max_requests` so reads cannot starve the writes
+    pub max_reads: usize,
+    pub max_writes: usize,
+    pub max_streams: usize,
+    /// Writes waiting for a slot, the others are refused
+    pub write_queue: usize,
+    #[serde(rename = "write_wait_ms", serialize_with = "serialize_millis")]
+    pub write_wait: Duration,
+}
+
+impl Default for ConcurrencyLimits {
+    fn default() -> Self {
+        ConcurrencyLimits {
+            max_requests: 32,
+            max_reads: 24,
+            max_writes: 16,
+            max_streams: 64,
+            write_queue: 16,
+            write_wait: Duration::from_millis(2000),
+        }
+    }
+}
+
+impl ConcurrencyLimits {
+    pub fn from_env() -> ConcurrencyLimits {
+        let defaults = ConcurrencyLimits::default();
+        ConcurrencyLimits {
+            max_requests: limit_from_env("MAX_CONCURRENT_REQUESTS", defaults.max_requests),
+            max_reads: limit_from_env("MAX_CONCURRENT_READS", defaults.max_reads),
+            max_writes: limit_from_env("MAX_CONCURRENT_WRITES", defaults.max_writes),
+            max_streams: limit_from_env("MAX_CONCURRENT_STREAMS", defaults.max_streams),
+            write_queue: limit_from_env("WRITE_QUEUE_SIZE", defaults.write_queue),
+            write_wait: Duration::from_millis(limit_from_env(
+                "WRITE_QUEUE_WAIT_MS",
+                defaults.write_wait.as_millis() as usize,
+            ) as u64),
+        }
+    }
+}
+
+fn serialize_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u128(duration.as_millis())
+}
+
+/// Requests in flight, by class, and the ones refused since startup
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct InFlight {
+    pub reads: usize,
+    pub writes: usize,
+    pub streams: usize,
+    pub queued_writes: usize,
+    pub rejected: u64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConcurrencySnapshot {
+    pub limits: ConcurrencyLimits,
+    pub in_flight: InFlight,
+}
+
+/// Caps the requests handled at once, checked before they are dispatched to
+/// their handler
+#[derive(Debug)]
+pub struct ConcurrencyLimiter {
+    limits: ConcurrencyLimits,
+    in_flight: Mutex<InFlight>,
+    released: Condvar,
+}
+
+/// Outcome of the admission of a request
+#[derive(Debug)]
+pub enum Admission<'a> {
+    Admitted(Permit<'a>),
+    /// A write waiting for a slot, see `Admission::permit`
+    Queued(&'a ConcurrencyLimiter),
+    Rejected,
+}
+
+impl<'a> Admission<'a> {
+    /// Slot of the request, queued writes wait for one at most `write_wait`
+    pub fn permit(self) -> Option<Permit<'a>> {
+        match self {
+            Admission::Admitted(permit) => Some(permit),
+            Admission::Queued(limiter) => limiter.wait_write(),
+            Admission::Rejected => None,
+        }
+    }
+}
+
+/// Slot of a request in flight, given back when dropped
+#[derive(Debug)]
+pub struct Permit<'a> {
+    limiter: &'a ConcurrencyLimiter,
+    class: RouteClass,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.limiter.in_flight.lock().unwrap();
+        *in_flight.count(self.class) -= 1;
+        self.limiter.released.notify_all();
+    }
+}
+
+impl InFlight {
+    fn count(&mut self, class: RouteClass) -> &mut usize {
+        match class {
+            RouteClass::Read => &mut self.reads,
+            RouteClass::Write => &mut self.writes,
+            RouteClass::Stream => &mut self.streams,
+        }
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(limits: ConcurrencyLimits) -> ConcurrencyLimiter {
+        ConcurrencyLimiter {
+            limits,
+            in_flight: Mutex::new(InFlight::default()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Take a slot for a request, a write is queued when there is none left
+    /// and the queue is not full
+    pub fn admit(&self, class: RouteClass) -> Admission<'_> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if self.has_room(&in_flight, class) {
+            *in_flight.count(class) += 1;
+            return Admission::Admitted(Permit {
+                limiter: self,
+                class,
+            });
+        }
+        if class == RouteClass::Write && in_flight.queued_writes < self.limits.write_queue {
+            in_flight.queued_writes += 1;
+            return Admission::Queued(self);
+        }
+        in_flight.rejected += 1;
+        event!(Level::WARN, "Too many {:?} requests in flight", class);
+        Admission::Rejected
+    }
+
+    fn has_room(&self, in_flight: &InFlight, class: RouteClass) -> bool {
+        let requests = in_flight.reads + in_flight.writes;
+        match class {
+            RouteClass::Read => {
+                in_flight.reads < self.limits.max_reads && requests < self.limits.max_requests
+            }
+            RouteClass::Write => {
+                in_flight.writes < self.limits.max_writes && requests < self.limits.max_requests
+            }
+            RouteClass::Stream => in_flight.streams < self.limits.max_streams,
+        }
+    }
+
+    fn wait_write(&self) -> Option<Permit<'_>> {
+        let deadline = Instant::now() + self.limits.write_wait;
+        let mut in_flight = self.in_flight.lock().unwrap();
+        loop {
+            if self.has_room(&in_flight, RouteClass::Write) {
+                in_flight.queued_writes -= 1;
+                in_flight.writes += 1;
+                return Some(Permit {
+                    limiter: self,
+                    class: RouteClass::Write,
+                });
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                in_flight.queued_writes -= 1;
+                in_flight.rejected += 1;
+                self.released.notify_all();
+                event!(
+                    Level::WARN,
+                    "Write request refused after waiting for a slot"
+                );
+                return None;
+            }
+            in_flight = self
+                .released
+                .wait_timeout(in_flight, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Wait for the reads and writes in flight, queued ones included, to be
+    /// answered. Streams are not waited for, they are not meant to end.
+    /// `false` when some are still in flight after `timeout`.
+    pub fn wait_idle(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut in_flight = self.in_flight.lock().unwrap();
+        loop {
+            if in_flight.reads + in_flight.writes + in_flight.queued_writes == 0 {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            in_flight = self
+                .released
+                .wait_timeout(in_flight, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    pub fn snapshot(&self) -> ConcurrencySnapshot {
+        ConcurrencySnapshot {
+            limits: self.limits.clone(),
+            in_flight: self.in_flight.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Answer given to a request refused by the limiter
+pub fn too_many_requests() -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    error_response(
+        429,
+        TOO_MANY_REQUESTS_CODE,
+        String::from("Too many requests in flight"),
+    )
+    .with_header(
+        tiny_http::Header::from_str(&format!("Retry-After: {}", RETRY_AFTER_SECONDS)).unwrap(),
+    )
+}
+
+/// Limiter shared by every server thread
+pub fn concurrency() -> &'static ConcurrencyLimiter {
+    static LIMITER: OnceLock<ConcurrencyLimiter> = OnceLock::new();
+    LIMITER.get_or_init(|| ConcurrencyLimiter::new(ConcurrencyLimits::from_env()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn limiter() -> ConcurrencyLimiter {
+        ConcurrencyLimiter::new(ConcurrencyLimits {
+            max_requests: 4,
+            max_reads: 3,
+            max_writes: 2,
+            max_streams: 1,
+            write_queue: 1,
+            write_wait: Duration::from_secs(5),
+        })
+    }
+
+    #[test]
+    fn test_saturated_reads_leave_room_for_writes() {
+        let limiter = limiter();
+        let reads: Vec<Permit> = (0..3)
+            .map(|_| limiter.admit(RouteClass::Read).permit().unwrap())
+            .collect();
+        assert!(matches!(
+            limiter.admit(RouteClass::Read),
+            Admission::Rejected
+        ));
+
+        let write = limiter.admit(RouteClass::Write).permit();
+        assert!(write.is_some());
+        // Streams do not take from the budget of the requests
+        let stream = limiter.admit(RouteClass::Stream).permit();
+        assert!(stream.is_some());
+        assert!(matches!(
+            limiter.admit(RouteClass::Stream),
+            Admission::Rejected
+        ));
+
+        let in_flight = limiter.snapshot().in_flight;
+        assert_eq!(
+            (in_flight.reads, in_flight.writes, in_flight.streams),
+            (3, 1, 1)
+        );
+        assert_eq!(in_flight.rejected, 2);
+        drop(reads);
+        assert_eq!(limiter.snapshot().in_flight.reads, 0);
+    }
+
+    #[test]
+    fn test_queued_write_waits_for_a_slot() {
+        let limiter = limiter();
+        let reads: Vec<Permit> = (0..3)
+            .map(|_| limiter.admit(RouteClass::Read).permit().unwrap())
+            .collect();
+        let write = limiter.admit(RouteClass::Write).permit().unwrap();
+
+        // The global cap is reached, a single write may wait
+        let queued = limiter.admit(RouteClass::Write);
+        assert!(matches!(queued, Admission::Queued(_)));
+        assert!(matches!(
+            limiter.admit(RouteClass::Write),
+            Admission::Rejected
+        ));
+        assert_eq!(limiter.snapshot().in_flight.queued_writes, 1);
+
+        thread::scope(|scope| {
+            let waiting = scope.spawn(move || queued.permit().is_some());
+            drop(reads);
+            assert!(waiting.join().unwrap());
+        });
+        drop(write);
+        let in_flight = limiter.snapshot().in_flight;
+        assert_eq!((in_flight.writes, in_flight.queued_writes), (0, 0));
+    }
+
+    #[test]
+    fn test_queued_write_is_refused_after_waiting() {
+        let limiter = ConcurrencyLimiter::new(ConcurrencyLimits {
+            write_wait: Duration::from_millis(10),
+            ..limiter().limits
+        });
+        let _writes: Vec<Permit> = (0..2)
+            .map(|_| limiter.admit(RouteClass::Write).permit().unwrap())
+            .collect();
+
+        assert!(limiter.admit(RouteClass::Write).permit().is_none());
+        let in_flight = limiter.snapshot().in_flight;
+        assert_eq!((in_flight.queued_writes, in_flight.rejected), (0, 1));
+    }
+
+    #[test]
+    fn test_wait_idle() {
+        let limiter = limiter();
+        let stream = limiter.admit(RouteClass::Stream).permit().unwrap();
+        assert!(limiter.wait_idle(Duration::ZERO));
+
+        let write = limiter.admit(RouteClass::Write).permit().unwrap();
+        assert!(!limiter.wait_idle(Duration::from_millis(10)));
+        thread::scope(|scope| {
+            let waiting = scope.spawn(|| limiter.wait_idle(Duration::from_secs(5)));
+            drop(write);
+            assert!(waiting.join().unwrap());
+        });
+        drop(stream);
+    }
+
+    #[test]
+    fn test_too_many_requests_response() {
+        let response = too_many_requests();
+        assert_eq!(response.status_code().0, 429);
+        assert!(response
+            .headers()
+            .iter()
+            .any(|header| header.field.equiv("Retry-After") && header.value == "1"));
+    }
+}
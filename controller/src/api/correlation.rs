@@ -0,0 +1,72 @@
+use std::cell::RefCell;
+use uuid::Uuid;
+
+/// Header carrying the correlation id of an API request, given back in the answer
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Longest correlation id taken from a client, longer ones are replaced
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+thread_local! {
+    /// Correlation id of the request handled by the current server thread
+    static CURRENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Correlation id of an operation the controller starts by itself
+pub fn new_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// Correlation id of a request, the one given by the client is kept so its
+/// own logs can be searched with it
+pub fn request_id(req: &tiny_http::Request) -> String {
+    req.headers()
+        .iter()
+        .find(|header| header.field.equiv(REQUEST_ID_HEADER))
+        .map(|header| header.value.as_str().trim().to_string())
+        .filter(|id| is_valid(id))
+        .unwrap_or_else(new_id)
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+/// Run a request handler, the messages it sends to the core carry the id
+pub fn with_correlation_id<R>(id: &str, f: impl FnOnce() -> R) -> R {
+    let previous = CURRENT.with(|current| current.replace(Some(id.to_string())));
+    let result = f();
+    CURRENT.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// Correlation id of the request being handled, a new one outside of requests
+pub fn current() -> String {
+    CURRENT
+        .with(|current| current.borrow().clone())
+        .unwrap_or_else(new_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_id_is_scoped_to_the_handler() {
+        let id = with_correlation_id("req-1", current);
+        assert_eq!(id, "req-1");
+        assert_ne!(current(), "req-1");
+    }
+
+    #[test]
+    fn test_client_ids_are_checked() {
+        assert!(is_valid("4bf92f35-77b3-4da6-a3ce-929d0e0e4736"));
+        assert!(!is_valid(""));
+        assert!(!is_valid("id with spaces"));
+        assert!(!is_valid(&"a".repeat(MAX_REQUEST_ID_LENGTH + 1)));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::api::correlation::REQUEST_ID_HEADER;
+use std::io;
+use std::str::FromStr;
+use tiny_http::Header;
+
+/// Headers browsers are allowed to send along with their requests
+const ALLOWED_HEADERS: &str = "Authorization, Content-Type";
+/// Seconds browsers may keep the answer of a preflight request
+const MAX_AGE_SECONDS: u64 = 600;
+
+/// Origins of the web pages allowed to call the API from a browser, none
+/// unless configured
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cors {
+    origins: Vec<String>,
+}
+
+impl Cors {
+    /// Origins such as `https://dashboard.example.com`, `*` allowing every one
+    pub fn new(origins: Vec<String>) -> Cors {
+        Cors {
+            origins: origins
+                .into_iter()
+                .map(|origin| origin.trim().trim_end_matches('/').to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Comma separated origins, e.g. `RIK_CORS_ORIGINS`
+    pub fn parse(origins: &str) -> Cors {
+        Cors::new(origins.split(',').map(String::from).collect())
+    }
+
+    /// Value of `Access-Control-Allow-Origin` for the `Origin` of a request
+    fn allowed_origin<'a>(&'a self, origin: &'a str) -> Option<&'a str> {
+        if self.origins.iter().any(|allowed| allowed == "*") {
+            return Some("*");
+        }
+        self.origins
+            .iter()
+            .find(|allowed| allowed.as_str() == origin)
+            .map(|_| origin)
+    }
+
+    /// Add the CORS headers to the answer of a request from an allowed
+    /// origin, `methods` being the ones routed on its path
+    pub fn apply(
+        &self,
+        request: &tiny_http::Request,
+        methods: &[String],
+        response: tiny_http::Response<io::Cursor<Vec<u8>>>,
+    ) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+        let Some(origin) = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Origin"))
+            .and_then(|header| self.allowed_origin(header.value.as_str()))
+        else {
+            return response;
+        };
+        let mut response = response
+            .with_header(header("Access-Control-Allow-Origin", origin))
+            .with_header(header("Access-Control-Allow-Methods", &methods.join(", ")))
+            .with_header(header("Access-Control-Allow-Headers", ALLOWED_HEADERS))
+            .with_header(header("Access-Control-Expose-Headers", REQUEST_ID_HEADER))
+            .with_header(header(
+                "Access-Control-Max-Age",
+                &MAX_AGE_SECONDS.to_string(),
+            ));
+        // Caches must not give the answer of an origin to another one
+        if origin != "*" {
+            response = response.with_header(header("Vary", "Origin"));
+        }
+        response
+    }
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_str(&format!("{}: {}", field, value)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowed_origin() {
+        let cors = Cors::parse("https://ui.example.com/, http://localhost:3000");
+        assert_eq!(
+            cors.allowed_origin("https://ui.example.com"),
+            Some("https://ui.example.com")
+        );
+        assert_eq!(cors.allowed_origin("https://evil.example.com"), None);
+        assert_eq!(
+            Cors::default().allowed_origin("http://localhost:3000"),
+            None
+        );
+        assert_eq!(Cors::parse("*").allowed_origin("http://any"), Some("*"));
+        assert_eq!(Cors::parse(" , "), Cors::default());
+    }
+}
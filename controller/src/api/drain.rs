@@ -0,0 +1,200 @@
+use crate::api::response::error_response;
+use crate::database::event_hub::Subscriber;
+use crate::database::revisions::change_notifier;
+use std::cell::RefCell;
+use std::io;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tracing::{event, Level};
+
+/// Error code of the streams refused while the server shuts down
+pub const SHUTTING_DOWN_CODE: &str = "ShuttingDown";
+/// Reason given to the streams closed by a shutdown
+pub const SHUTDOWN_REASON: &str = "server shutting down";
+/// Seconds clients are told to wait before opening a refused stream again
+const RETRY_AFTER_SECONDS: u64 = 1;
+
+thread_local! {
+    /// Streams of the server whose request the current thread handles
+    static CURRENT: RefCell<Option<Arc<StreamDrain>>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Default)]
+struct Streams {
+    open: usize,
+    /// Event watches, closed when the server shuts down
+    subscribers: Vec<Weak<Subscriber>>,
+}
+
+/// Streams open on a server, such as the watches. When the server shuts
+/// down they are asked to end, and no new one is taken, while the other
+/// requests are still answered.
+#[derive(Debug, Default)]
+pub struct StreamDrain {
+    draining: AtomicBool,
+    streams: Mutex<Streams>,
+    closed: Condvar,
+}
+
+/// Place of an open stream, given back when dropped
+#[derive(Debug)]
+pub struct StreamGuard {
+    drain: Arc<StreamDrain>,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.drain.streams.lock().unwrap().open -= 1;
+        self.drain.closed.notify_all();
+    }
+}
+
+impl StreamDrain {
+    pub fn new() -> Arc<StreamDrain> {
+        Arc::new(StreamDrain::default())
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Place of a stream starting, None once the server shuts down
+    pub fn open(self: &Arc<Self>) -> Option<StreamGuard> {
+        let mut streams = self.streams.lock().unwrap();
+        if self.is_draining() {
+            return None;
+        }
+        streams.open += 1;
+        Some(StreamGuard {
+            drain: self.clone(),
+        })
+    }
+
+    /// Close the subscriber of an event watch when the server shuts down, at
+    /// once if it already does
+    pub fn follow(&self, subscriber: &Arc<Subscriber>) {
+        let mut streams = self.streams.lock().unwrap();
+        if self.is_draining() {
+            subscriber.close();
+            return;
+        }
+        streams
+            .subscribers
+            .retain(|subscriber| subscriber.strong_count() > 0);
+        streams.subscribers.push(Arc::downgrade(subscriber));
+    }
+
+    /// Stop taking streams and ask the open ones to end, waiting for them at
+    /// most `timeout`. `false` when some are still open after it.
+    pub fn drain(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut streams = self.streams.lock().unwrap();
+        self.draining.store(true, Ordering::SeqCst);
+        for subscriber in streams.subscribers.drain(..) {
+            if let Some(subscriber) = subscriber.upgrade() {
+                subscriber.close();
+            }
+        }
+        // The long polls wait for a change, they check the drain once woken
+        change_notifier().notify();
+        if streams.open > 0 {
+            event!(Level::INFO, "Closing {} streams", streams.open);
+        }
+        loop {
+            if streams.open == 0 {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            streams = self.closed.wait_timeout(streams, deadline - now).unwrap().0;
+        }
+    }
+}
+
+/// Run a request handler, the streams it serves can tell whether their
+/// server shuts down
+pub fn with_drain<R>(drain: &Arc<StreamDrain>, f: impl FnOnce() -> R) -> R {
+    let previous = CURRENT.with(|current| current.replace(Some(drain.clone())));
+    let result = f();
+    CURRENT.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// Whether the server of the request being handled shuts down, never
+/// outside of a request
+pub fn is_draining() -> bool {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .is_some_and(|drain| drain.is_draining())
+    })
+}
+
+/// Answer to a stream refused because the server shuts down, the client
+/// opens it again on another server or once this one is back
+pub fn shutting_down() -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    error_response(
+        503,
+        SHUTTING_DOWN_CODE,
+        String::from("The server is shutting down, open the stream again"),
+    )
+    .with_header(
+        tiny_http::Header::from_str(&format!("Retry-After: {}", RETRY_AFTER_SECONDS)).unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::event_hub::{EventHub, Received};
+    use std::thread;
+
+    #[test]
+    fn test_drain_closes_the_streams() {
+        let drain = StreamDrain::new();
+        let hub = EventHub::new(10);
+        let stream = drain.open().unwrap();
+        let subscriber = hub.subscribe(None);
+        drain.follow(&subscriber);
+        assert!(!with_drain(&drain, is_draining));
+
+        let draining = {
+            let drain = drain.clone();
+            thread::spawn(move || drain.drain(Duration::from_secs(10)))
+        };
+        assert_eq!(
+            subscriber.receive(Duration::from_secs(10)),
+            Received::Closed
+        );
+        // No new stream is taken meanwhile
+        assert!(drain.open().is_none());
+        assert!(with_drain(&drain, is_draining));
+        assert!(!is_draining());
+        drop(stream);
+        assert!(draining.join().unwrap());
+
+        // Watches starting late are closed at once
+        let late = hub.subscribe(None);
+        drain.follow(&late);
+        assert_eq!(late.receive(Duration::ZERO), Received::Closed);
+    }
+
+    #[test]
+    fn test_drain_gives_up_after_the_timeout() {
+        let drain = StreamDrain::new();
+        let _stream = drain.open().unwrap();
+        assert!(!drain.drain(Duration::from_millis(20)));
+
+        let response = shutting_down();
+        assert_eq!(response.status_code().0, 503);
+        assert!(response
+            .headers()
+            .iter()
+            .any(|header| header.field.equiv("Retry-After")));
+    }
+}
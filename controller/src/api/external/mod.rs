@@ -1,68 +1,814 @@
 mod routes;
 mod services;
 
-use crate::api::ApiChannel;
+use crate::api::auth::{api_auth, with_tenant};
+use crate::api::concurrency::{
+    concurrency, too_many_requests, Admission, ConcurrencyLimits, RouteClass,
+};
+use crate::api::correlation::{self, REQUEST_ID_HEADER};
+use crate::api::cors::Cors;
+use crate::api::drain::{self, shutting_down, StreamDrain};
+use crate::api::limits::limit_from_env;
+use crate::api::rate_limit::{rate_limited, RateLimiter, RateLimits};
+use crate::api::response::error_response;
+use crate::api::{ApiChannel, RikError};
+use crate::database::pool::{ConnectionPool, PoolError};
 use crate::database::RikDataBase;
 use dotenv::dotenv;
+use rusqlite::Connection;
+use std::io::Cursor;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
 use std::thread;
-use tiny_http::{Request, Server as TinyServer};
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Server as TinyServer};
 
-use tracing::{event, Level};
+use tracing::{event, info_span, Level};
+
+/// Target of the access log, one line per answered request, silenced with
+/// e.g. `RUST_LOG=info,access=warn`
+const ACCESS_LOG_TARGET: &str = "access";
+/// Error code of the requests refused because no database connection was free
+const DATABASE_BUSY_CODE: &str = "DatabaseBusy";
+
+/// Address the API listens on, threads taking its requests, origins of the
+/// browsers allowed to call it, requests each client may send, and database
+/// connections shared by the requests
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    pub workers: usize,
+    pub cors: Cors,
+    pub rate_limits: RateLimits,
+    /// Database connections open at once, one per request in flight
+    pub pool_size: usize,
+    /// Longest wait of a request for a free database connection
+    pub pool_timeout: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            host: String::from("0.0.0.0"),
+            port: 5000,
+            workers: 4,
+            cors: Cors::default(),
+            rate_limits: RateLimits::default(),
+            pool_size: ConcurrencyLimits::default().max_requests,
+            pool_timeout: Duration::from_millis(5000),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Address given by `RIK_LISTEN_ADDR` as `host:port`, or else the port
+    /// given by `PORT` on every interface, with `HTTP_WORKERS` threads, the
+    /// origins of `RIK_CORS_ORIGINS`, the limits of `RATE_LIMIT_PER_SECOND`
+    /// and `RATE_LIMIT_BURST`, and the `DATABASE_POOL_SIZE` connections
+    /// waited for at most `DATABASE_POOL_TIMEOUT_MS`
+    pub fn from_env() -> Result<ServerConfig, String> {
+        dotenv().ok();
+        let config = ServerConfig::parse(
+            std::env::var("RIK_LISTEN_ADDR").ok().as_deref(),
+            std::env::var("PORT").ok().as_deref(),
+        )?;
+        Ok(ServerConfig {
+            workers: limit_from_env("HTTP_WORKERS", config.workers).max(1),
+            cors: Cors::parse(&std::env::var("RIK_CORS_ORIGINS").unwrap_or_default()),
+            rate_limits: RateLimits::from_env(),
+            // As many connections as requests handled at once, none waits for one
+            pool_size: limit_from_env(
+                "DATABASE_POOL_SIZE",
+                ConcurrencyLimits::from_env().max_requests,
+            ),
+            pool_timeout: Duration::from_millis(limit_from_env(
+                "DATABASE_POOL_TIMEOUT_MS",
+                config.pool_timeout.as_millis() as usize,
+            ) as u64),
+            ..config
+        })
+    }
+
+    fn parse(listen: Option<&str>, port: Option<&str>) -> Result<ServerConfig, String> {
+        let parse_port = |port: &str| {
+            port.parse::<u16>()
+                .map_err(|_| format!("{} is not a valid port", port))
+        };
+        match (listen.filter(|listen| !listen.is_empty()), port) {
+            (Some(listen), _) => {
+                let (host, port) = listen
+                    .rsplit_once(':')
+                    .filter(|(host, _)| !host.is_empty())
+                    .ok_or_else(|| {
+                        format!("RIK_LISTEN_ADDR {} is not of the form host:port", listen)
+                    })?;
+                Ok(ServerConfig {
+                    host: host.to_string(),
+                    port: parse_port(port)?,
+                    ..ServerConfig::default()
+                })
+            }
+            (None, Some(port)) => Ok(ServerConfig {
+                port: parse_port(port)?,
+                ..ServerConfig::default()
+            }),
+            (None, None) => Ok(ServerConfig::default()),
+        }
+    }
+}
 
 pub struct Server {
     internal_sender: Sender<ApiChannel>,
+    config: ServerConfig,
+    /// Shared by the server threads, so a client has a single bucket
+    rate_limiter: Arc<RateLimiter>,
 }
 
 impl Server {
-    pub fn new(internal_sender: Sender<ApiChannel>) -> Server {
-        Server { internal_sender }
+    pub fn new(internal_sender: Sender<ApiChannel>, config: ServerConfig) -> Server {
+        Server {
+            internal_sender,
+            rate_limiter: Arc::new(RateLimiter::new(config.rate_limits.clone())),
+            config,
+        }
     }
 
-    pub fn run(&self, db: Arc<RikDataBase>) {
-        self.run_server(db);
+    /// Start the threads answering the API, fails when its address cannot
+    /// be listened on
+    pub fn run(&self, db: Arc<RikDataBase>) -> Result<ServerHandle, String> {
+        self.run_server(db)
     }
 
-    fn run_server(&self, db: Arc<RikDataBase>) {
-        let host = String::from("0.0.0.0");
-        dotenv().ok();
-        let port: usize = match std::env::var("PORT") {
-            Ok(val) => val.parse().unwrap(),
-            Err(_e) => 5000,
-        };
-        let server = TinyServer::http(format!("{}:{}", host, port)).unwrap();
+    fn run_server(&self, db: Arc<RikDataBase>) -> Result<ServerHandle, String> {
+        let ServerConfig {
+            host,
+            port,
+            workers,
+            cors,
+            pool_size,
+            pool_timeout,
+            ..
+        } = &self.config;
+        let server = TinyServer::http(format!("{}:{}", host, port))
+            .map_err(|e| format!("Cannot listen on {}:{}: {}", host, port, e))?;
         let server = Arc::new(server);
+        let stopping = Arc::new(AtomicBool::new(false));
+        let pool = ConnectionPool::new(db, *pool_size, *pool_timeout);
+        let drain = StreamDrain::new();
 
-        let mut guards = Vec::with_capacity(4);
-
-        for _ in 0..4 {
+        let spawn_worker = {
             let server = server.clone();
-            let db = db.clone();
+            let stopping = stopping.clone();
+            let drain = drain.clone();
             let internal_sender = self.internal_sender.clone();
+            let cors = cors.clone();
+            let rate_limiter = self.rate_limiter.clone();
+            move || {
+                let server = server.clone();
+                let stopping = stopping.clone();
+                let pool = pool.clone();
+                let drain = drain.clone();
+                let internal_sender = internal_sender.clone();
+                let cors = cors.clone();
+                let rate_limiter = rate_limiter.clone();
+                thread::spawn(move || loop {
+                    let req: Request = match server.recv() {
+                        Ok(req) => req,
+                        // Unblocked by the shutdown
+                        Err(_) if stopping.load(Ordering::SeqCst) => break,
+                        Err(e) => {
+                            event!(Level::WARN, "Could not receive a request: {}", e);
+                            continue;
+                        }
+                    };
+                    // A request dropped by a panic is answered with an empty `500`
+                    let dispatched = panic::catch_unwind(AssertUnwindSafe(|| {
+                        dispatch(
+                            req,
+                            pool.clone(),
+                            drain.clone(),
+                            internal_sender.clone(),
+                            cors.clone(),
+                            &rate_limiter,
+                        )
+                    }));
+                    if dispatched.is_err() {
+                        event!(
+                            Level::ERROR,
+                            "A server thread panicked dispatching a request"
+                        );
+                    }
+                })
+            }
+        };
+        let workers = (0..*workers).map(|_| spawn_worker()).collect();
 
-            let guard = thread::spawn(move || loop {
-                let router = routes::Router::new();
-                let connection = db.open().unwrap();
+        event!(Level::INFO, "Server running on http://{}:{}", host, port);
+        Ok(ServerHandle {
+            server,
+            stopping,
+            drain,
+            workers,
+            spawn_worker: Box::new(spawn_worker),
+        })
+    }
+}
+
+/// Admit a request and handle it on its own thread
+fn dispatch(
+    req: Request,
+    pool: Arc<ConnectionPool>,
+    drain: Arc<StreamDrain>,
+    internal_sender: Sender<ApiChannel>,
+    cors: Cors,
+    rate_limiter: &RateLimiter,
+) {
+    // Before the admission and the body, slow uploads are part of the latency
+    let started = Instant::now();
+    let request_id = correlation::request_id(&req);
+    let _span = info_span!("request", correlation_id = %request_id).entered();
+
+    // Before the admission, so a client in a loop cannot take every slot
+    let client = req.remote_addr().map(|address| address.ip());
+    if let Some(client) = client.filter(|_| routes::is_rate_limited(&req)) {
+        if let Err(wait) = rate_limiter.check(client, started) {
+            event!(Level::DEBUG, "Request of {} refused, rate limited", client);
+            respond(req, rate_limited(wait), &request_id, started);
+            return;
+        }
+    }
 
-                let mut req: Request = server.recv().unwrap();
+    // Watches are held open, they are admitted as streams, apart from the
+    // reads and writes
+    let is_stream = routes::events::is_watch(&req) || routes::instance::is_watch(&req);
+    let class = if is_stream {
+        RouteClass::Stream
+    } else if matches!(req.method(), Method::Get | Method::Options) {
+        RouteClass::Read
+    } else {
+        RouteClass::Write
+    };
+    let admission = concurrency().admit(class);
+    if let Admission::Rejected = admission {
+        respond(req, too_many_requests(), &request_id, started);
+        return;
+    }
 
-                if let Some(res) = router.handle(&mut req, &connection, &internal_sender) {
-                    req.respond(res).unwrap();
-                    continue;
+    // Requests are handled on their own thread, the permit caps them
+    thread::spawn(move || {
+        let _span = info_span!("request", correlation_id = %request_id).entered();
+        let Some(_permit) = admission.permit() else {
+            respond(req, too_many_requests(), &request_id, started);
+            return;
+        };
+        // Streams are refused once the server shuts down, the other
+        // requests are still answered until it stops
+        let _stream = match is_stream.then(|| drain.open()) {
+            Some(None) => {
+                respond(req, shutting_down(), &request_id, started);
+                return;
+            }
+            stream => stream.flatten(),
+        };
+        if is_stream {
+            // Watches hold their connection as long as they are open, they
+            // would take the connections of the pool from the other requests
+            let connection = match pool.database().open() {
+                Ok(connection) => connection,
+                Err(e) => {
+                    let response = database_busy(&PoolError::Open(e));
+                    respond(req, response, &request_id, started);
+                    return;
                 }
-                event!(
-                    Level::INFO,
-                    "Route {} ({}) could not be found",
-                    req.url(),
-                    req.method()
-                );
-                req.respond(tiny_http::Response::empty(tiny_http::StatusCode::from(404)))
-                    .unwrap();
+            };
+            if routes::events::is_watch(&req) {
+                // Event watches are not routed, the router checks the other requests
+                let tenant_id = match api_auth().check(&req, &connection) {
+                    Ok(tenant_id) => tenant_id,
+                    Err(response) => {
+                        respond(req, response, &request_id, started);
+                        return;
+                    }
+                };
+                with_tenant(tenant_id, || {
+                    routes::events::watch(req, &connection, &request_id, &drain)
+                });
+                return;
+            }
+            drain::with_drain(&drain, || {
+                handle(
+                    req,
+                    &connection,
+                    &internal_sender,
+                    &cors,
+                    &request_id,
+                    started,
+                )
             });
+            return;
+        }
+        // Given back to the pool once the request is answered
+        let connection = match pool.get() {
+            Ok(connection) => connection,
+            Err(e) => {
+                event!(Level::WARN, "Request refused: {}", e);
+                respond(req, database_busy(&e), &request_id, started);
+                return;
+            }
+        };
+        drain::with_drain(&drain, || {
+            handle(
+                req,
+                &connection,
+                &internal_sender,
+                &cors,
+                &request_id,
+                started,
+            )
+        });
+    });
+}
+
+/// Answer to a request no database connection could be given to
+fn database_busy(error: &PoolError) -> tiny_http::Response<Cursor<Vec<u8>>> {
+    error_response(503, DATABASE_BUSY_CODE, error.to_string())
+        .with_header(Header::from_str("Retry-After: 1").unwrap())
+}
 
-            guards.push(guard);
+/// Threads answering the API, stopped by `shutdown`
+pub struct ServerHandle {
+    server: Arc<TinyServer>,
+    stopping: Arc<AtomicBool>,
+    drain: Arc<StreamDrain>,
+    workers: Vec<thread::JoinHandle<()>>,
+    spawn_worker: Box<dyn Fn() -> thread::JoinHandle<()> + Send>,
+}
+
+impl ServerHandle {
+    /// Start again the threads taking requests which stopped, so the server
+    /// keeps its capacity. Gives the amount of threads started.
+    pub fn respawn_workers(&mut self) -> usize {
+        if self.stopping.load(Ordering::SeqCst) {
+            return 0;
         }
-        event!(Level::INFO, "Server running on http://{}:{}", host, port);
+        let mut respawned = 0;
+        for worker in self
+            .workers
+            .iter_mut()
+            .filter(|worker| worker.is_finished())
+        {
+            *worker = (self.spawn_worker)();
+            respawned += 1;
+        }
+        if respawned > 0 {
+            event!(
+                Level::ERROR,
+                "{} server threads stopped, started again",
+                respawned
+            );
+        }
+        respawned
+    }
+
+    /// Stop taking requests and wait for the ones in flight to be answered,
+    /// for at most `grace`. The streams are closed first, new ones are
+    /// refused while the other requests are still taken. `false` when some
+    /// requests were still in flight at the end of the grace period.
+    pub fn shutdown(self, grace: Duration) -> bool {
+        let deadline = Instant::now() + grace;
+        let streams_closed = self.drain.drain(grace);
+        if !streams_closed {
+            event!(Level::WARN, "Streams were still open after {:?}", grace);
+        }
+        self.stopping.store(true, Ordering::SeqCst);
+        // Each unblocks a single thread, once the requests already received are taken
+        for _ in &self.workers {
+            self.server.unblock();
+        }
+        for worker in self.workers {
+            if worker.join().is_err() {
+                event!(Level::ERROR, "A server thread panicked");
+            }
+        }
+        let drained = concurrency().wait_idle(deadline.saturating_duration_since(Instant::now()))
+            && streams_closed;
+        if drained {
+            event!(Level::INFO, "Server stopped, every request was answered");
+        } else {
+            event!(
+                Level::WARN,
+                "Server stopped after {:?}, requests were still in flight",
+                grace
+            );
+        }
+        drained
+    }
+}
+
+fn handle(
+    mut req: Request,
+    connection: &Connection,
+    internal_sender: &Sender<ApiChannel>,
+    cors: &Cors,
+    request_id: &str,
+    started: Instant,
+) {
+    let router = routes::Router::new().with_cors(cors.clone());
+    let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+        correlation::with_correlation_id(request_id, || {
+            router.handle(&mut req, connection, internal_sender)
+        })
+    }));
+    let handled = handled.unwrap_or_else(|_| {
+        event!(
+            Level::ERROR,
+            "Handler of {} ({}) panicked",
+            req.url(),
+            req.method()
+        );
+        Some(RikError::Internal(String::from("The request could not be handled")).response())
+    });
+    if let Some(res) = handled {
+        respond(req, res, request_id, started);
+        return;
+    }
+    event!(
+        Level::WARN,
+        "Route {} ({}) could not be found",
+        req.url(),
+        req.method()
+    );
+    let not_found = router.not_found(req.url());
+    respond(req, not_found, request_id, started);
+}
+
+/// Answer a request and log it on the access log, with its latency since
+/// it was received
+fn respond(
+    req: Request,
+    res: tiny_http::Response<Cursor<Vec<u8>>>,
+    request_id: &str,
+    started: Instant,
+) {
+    let method = req.method().to_string();
+    let url = req.url().to_string();
+    let status = res.status_code().0;
+    let bytes = res.data_length().unwrap_or_default();
+    let request_id_header =
+        Header::from_str(&format!("{}: {}", REQUEST_ID_HEADER, request_id)).unwrap();
+    if let Err(e) = req.respond(res.with_header(request_id_header)) {
+        event!(Level::WARN, "Could not answer the request: {}", e);
+    }
+    let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+    event!(
+        target: ACCESS_LOG_TARGET,
+        Level::INFO,
+        method,
+        path = url,
+        status,
+        bytes,
+        elapsed_ms,
+        "{} {} {} {}B {:.1}ms",
+        method,
+        url,
+        status,
+        bytes,
+        elapsed_ms
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::events::EventRepository;
+    use crate::database::RikRepository;
+    use crate::tests::fixtures::db_connection;
+    use rstest::rstest;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::channel;
+    use uuid::Uuid;
+
+    /// Server listening on a free port of the loopback, with `config`
+    /// otherwise, along with its port
+    fn start_server(db: Arc<RikDataBase>, config: ServerConfig) -> (ServerHandle, u16) {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = ServerConfig {
+            host: String::from("127.0.0.1"),
+            port,
+            ..config
+        };
+        let (sender, _receiver) = channel();
+        let handle = Server::new(sender, config).run(db).unwrap();
+        (handle, port)
+    }
+
+    /// Whole answer to a raw HTTP request
+    fn send(port: u16, request: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[rstest]
+    fn test_healthz_while_the_database_is_locked(db_connection: Arc<RikDataBase>) {
+        let (handle, port) = start_server(db_connection.clone(), ServerConfig::default());
+
+        let lock = db_connection.open().unwrap();
+        lock.execute_batch("BEGIN EXCLUSIVE").unwrap();
+        let response = send(
+            port,
+            "GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        lock.execute_batch("ROLLBACK").unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with(r#"{"status":"ok"}"#));
+        handle.shutdown(Duration::from_secs(5));
+    }
+
+    #[rstest]
+    fn test_shutdown(db_connection: Arc<RikDataBase>) {
+        let (handle, port) = start_server(db_connection, ServerConfig::default());
+        // A request whose body is being uploaded when the shutdown starts,
+        // large enough for the handler to read it rather than the server
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream
+            .write_all(
+                b"POST /api/v0/tenants.create HTTP/1.1\r\nHost: localhost\r\n\
+                  Connection: close\r\nContent-Length: 2000\r\n\r\n{\"na",
+            )
+            .unwrap();
+        while concurrency().snapshot().in_flight.writes == 0 {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let shutdown = thread::spawn(move || handle.shutdown(Duration::from_secs(5)));
+        thread::sleep(Duration::from_millis(100));
+        assert!(!shutdown.is_finished());
+        let rest = format!("me\": 1{}", " ".repeat(1990));
+        stream.write_all(rest.as_bytes()).unwrap();
+        assert!(shutdown.join().unwrap());
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400"));
+    }
+
+    #[rstest]
+    fn test_shutdown_closes_the_streams(db_connection: Arc<RikDataBase>) {
+        let (handle, port) = start_server(db_connection, ServerConfig::default());
+        let open = |path: &str| {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let request = format!(
+                "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                path
+            );
+            stream.write_all(request.as_bytes()).unwrap();
+            stream
+        };
+        let mut events = open("/api/v0/events.watch");
+        // Answered once the watch follows the events
+        let mut head = [0; 15];
+        events.read_exact(&mut head).unwrap();
+        assert_eq!(&head, b"HTTP/1.1 200 OK");
+        let mut instances = open("/api/v0/instances.watch?timeout=30");
+        while concurrency().snapshot().in_flight.streams < 2 {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let started = Instant::now();
+        let shutdown = thread::spawn(move || handle.shutdown(Duration::from_secs(10)));
+        let mut response = String::new();
+        events.read_to_string(&mut response).unwrap();
+        assert!(response.contains("event: bookmark\n"));
+        assert!(response.contains(r#""type":"BOOKMARK""#));
+        let mut response = String::new();
+        instances.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(shutdown.join().unwrap());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[rstest]
+    fn test_watches_leave_the_pool_to_the_other_requests(db_connection: Arc<RikDataBase>) {
+        let config = ServerConfig {
+            pool_size: 2,
+            pool_timeout: Duration::from_millis(200),
+            ..ServerConfig::default()
+        };
+        let (handle, port) = start_server(db_connection, config);
+        let watches: Vec<TcpStream> = (0..2)
+            .map(|_| {
+                let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+                stream
+                    .write_all(
+                        b"GET /api/v0/instances.watch?timeout=30 HTTP/1.1\r\n\
+                          Host: localhost\r\nConnection: close\r\n\r\n",
+                    )
+                    .unwrap();
+                stream
+            })
+            .collect();
+        while concurrency().snapshot().in_flight.streams < 2 {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let response = send(
+            port,
+            "GET /api/v0/instances.list HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(handle.shutdown(Duration::from_secs(10)));
+        drop(watches);
+    }
+
+    #[rstest]
+    fn test_event_watches_of_a_tenant_key(db_connection: Arc<RikDataBase>) {
+        let (handle, port) = start_server(db_connection.clone(), ServerConfig::default());
+        let body = format!(
+            r#"{{"id": "", "name": "/tenant/{}", "value": "{{}}"}}"#,
+            Uuid::new_v4()
+        );
+        let response = send(
+            port,
+            &format!(
+                "POST /api/v0/tenants.create HTTP/1.1\r\nHost: localhost\r\n\
+                 Connection: close\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            ),
+        );
+        let tenant: serde_json::Value =
+            serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let request = format!(
+            "GET /api/v0/events.watch HTTP/1.1\r\nHost: localhost\r\n\
+             Authorization: Bearer {}\r\nConnection: close\r\n\r\n",
+            tenant["api_key"].as_str().unwrap()
+        );
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut head = [0; 15];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(&head, b"HTTP/1.1 200 OK");
+
+        let connection = db_connection.open().unwrap();
+        let owned = format!(r#"{{"tenant_id": "{}"}}"#, tenant["id"].as_str().unwrap());
+        let owned = RikRepository::insert(&connection, "/workload/Pod/lab/owned", &owned).unwrap();
+        let other = RikRepository::insert(&connection, "/workload/Pod/lab/other", "{}").unwrap();
+        EventRepository::insert(&connection, &other, "Running", "").unwrap();
+        let sent = EventRepository::insert(&connection, &owned, "Running", "").unwrap();
+
+        assert!(handle.shutdown(Duration::from_secs(10)));
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(
+            response.contains(&format!("id: {}\n", sent)),
+            "{}",
+            response
+        );
+        assert!(!response.contains(&other));
+    }
+
+    #[rstest]
+    fn test_panicking_handler(db_connection: Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        // The definition of the workload cannot be read when deleting the instance
+        let workload_id =
+            RikRepository::insert(&connection, "/workload/Pod/lab/web", "{}").unwrap();
+        let value = format!(r#"{{"workload_id": "{}"}}"#, workload_id);
+        let instance_id =
+            RikRepository::insert(&connection, "/instance/Pod/lab/web-0", &value).unwrap();
+
+        let config = ServerConfig {
+            workers: 1,
+            ..ServerConfig::default()
+        };
+        let (handle, port) = start_server(db_connection, config);
+
+        let body = format!(r#"{{"id": "{}"}}"#, instance_id);
+        let response = send(
+            port,
+            &format!(
+                "POST /api/v0/instances.delete HTTP/1.1\r\nHost: localhost\r\n\
+                 Connection: close\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            ),
+        );
+        assert!(response.starts_with("HTTP/1.1 500"));
+        assert!(response.contains(r#""code":"Internal""#));
+        // The single server thread still takes requests
+        let response = send(
+            port,
+            "GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(handle.shutdown(Duration::from_secs(5)));
+    }
+
+    #[rstest]
+    fn test_rate_limit(db_connection: Arc<RikDataBase>) {
+        let config = ServerConfig {
+            rate_limits: RateLimits {
+                requests_per_second: 1,
+                burst: 2,
+            },
+            ..ServerConfig::default()
+        };
+        let (handle, port) = start_server(db_connection, config);
+        let get = |path: &str| {
+            let request = format!(
+                "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                path
+            );
+            send(port, &request)
+        };
+
+        assert!(get("/api/v0/version").starts_with("HTTP/1.1 200"));
+        assert!(get("/api/v0/version").starts_with("HTTP/1.1 200"));
+        let response = get("/api/v0/version");
+        assert!(response.starts_with("HTTP/1.1 429"));
+        assert!(response.contains("Retry-After: 1\r\n"));
+        assert!(response.contains(r#""code":"RateLimited""#));
+        // The probes and the metrics are scraped whatever the limit
+        for path in ["/healthz", "/metrics", "/api/v0/metrics"] {
+            assert!(get(path).starts_with("HTTP/1.1 200"));
+        }
+        assert!(handle.shutdown(Duration::from_secs(5)));
+    }
+
+    #[rstest]
+    fn test_request_id_round_trip(db_connection: Arc<RikDataBase>) {
+        let (handle, port) = start_server(db_connection, ServerConfig::default());
+        let request_id = |path: &str, header: &str| {
+            let request = format!(
+                "GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n",
+                path, header
+            );
+            let response = send(port, &request);
+            let status = response[9..12].to_string();
+            let id = response
+                .lines()
+                .find_map(|line| line.strip_prefix("X-Request-Id: "))
+                .map(String::from);
+            (status, id)
+        };
+
+        // Given back as sent, on answers and errors alike
+        let sent = "X-Request-Id: req-42\r\n";
+        for (path, status) in [
+            ("/api/v0/version", "200"),
+            ("/api/v0/workloads.get/unknown", "400"),
+            ("/api/v0/instances.get/unknown", "404"),
+            ("/api/v0/unknown", "404"),
+        ] {
+            assert_eq!(
+                request_id(path, sent),
+                (status.to_string(), Some(String::from("req-42")))
+            );
+        }
+        // Or else a new one, such as for an id which cannot be logged as is
+        for header in ["", "X-Request-Id: not valid\r\n"] {
+            let (_, id) = request_id("/api/v0/version", header);
+            assert!(Uuid::parse_str(&id.unwrap()).is_ok());
+        }
+        assert!(handle.shutdown(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_listen_address() {
+        let parse = ServerConfig::parse;
+        assert_eq!(parse(None, None), Ok(ServerConfig::default()));
+        assert_eq!(parse(None, Some("8080")).unwrap().port, 8080);
+        let config = parse(Some("127.0.0.1:6000"), Some("8080")).unwrap();
+        assert_eq!((config.host.as_str(), config.port), ("127.0.0.1", 6000));
+        assert_eq!(parse(Some("[::1]:6000"), None).unwrap().host, "[::1]");
+        assert!(parse(Some("127.0.0.1"), None).is_err());
+        assert!(parse(None, Some("port")).is_err());
+    }
+
+    #[rstest]
+    fn test_address_in_use(db_connection: Arc<RikDataBase>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = ServerConfig {
+            host: String::from("127.0.0.1"),
+            port: listener.local_addr().unwrap().port(),
+            ..ServerConfig::default()
+        };
+        let (sender, _receiver) = channel();
+        let Err(error) = Server::new(sender, config.clone()).run(db_connection) else {
+            panic!("The address of the listener was taken");
+        };
+        assert!(error.starts_with(&format!("Cannot listen on 127.0.0.1:{}", config.port)));
     }
 }
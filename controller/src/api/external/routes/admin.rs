@@ -0,0 +1,127 @@
+use route_recognizer;
+use rusqlite::Connection;
+use serde_json::json;
+use std::io;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+
+use crate::api;
+use crate::api::external::services::request::extract_request;
+use crate::api::read_only::read_only;
+use crate::api::types::admin::ReadOnlyChange;
+use crate::api::{correlation, ApiChannel, Crud};
+
+/// Path of the route still accepting mutations while the API is read-only
+pub const READ_ONLY_PATH: &str = "/api/v0/admin.read_only";
+/// Path of the liveness probe, outside of the API
+pub const HEALTHZ_PATH: &str = "/healthz";
+/// Path of the readiness probe, outside of the API
+pub const READYZ_PATH: &str = "/readyz";
+
+pub fn get_read_only(
+    _: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    _: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    Ok(
+        tiny_http::Response::from_string(serde_json::to_string(&read_only().status()).unwrap())
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200)),
+    )
+}
+
+pub fn set_read_only(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    _: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let ReadOnlyChange { enabled, reason } = match extract_request(req) {
+        Ok(request) => request,
+        Err(response) => return Ok(response),
+    };
+
+    let status = read_only().set(enabled, reason);
+    Ok(
+        tiny_http::Response::from_string(serde_json::to_string(&status).unwrap())
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200)),
+    )
+}
+
+pub fn version(
+    _: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    _: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let version = json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "read_only": read_only().is_enabled(),
+    });
+    Ok(tiny_http::Response::from_string(version.to_string())
+        .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+        .with_status_code(tiny_http::StatusCode::from(200)))
+}
+
+/// Liveness probe, answered without the database nor the core so it stays
+/// cheap and is answered while the database is locked
+pub fn healthz(
+    _: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    _: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    Ok(
+        tiny_http::Response::from_string(json!({ "status": "ok" }).to_string())
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200)),
+    )
+}
+
+/// Readiness probe, a `503` listing the failing checks until the database can
+/// be queried and the core receives the messages of the API
+pub fn readyz(
+    _: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    internal_sender: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let mut failing = Vec::new();
+    // Fails as well while the tables are not created
+    if let Err(e) = connection
+        .prepare_cached("SELECT 1 FROM cluster LIMIT 1")
+        .and_then(|mut stmt| stmt.exists([]))
+    {
+        failing.push(json!({ "check": "database", "message": e.to_string() }));
+    }
+    let ping = ApiChannel {
+        action: Crud::Ping,
+        workload_id: None,
+        instance_id: None,
+        workload_definition: None,
+        overrides: None,
+        namespace: None,
+        tenant_id: None,
+        correlation_id: correlation::current(),
+    };
+    if internal_sender.send(ping).is_err() {
+        failing.push(json!({
+            "check": "core_channel",
+            "message": "The core does not receive the messages of the API",
+        }));
+    }
+
+    let (status, body) = if failing.is_empty() {
+        (
+            200,
+            json!({ "status": "ready", "read_only": read_only().is_enabled() }),
+        )
+    } else {
+        (503, json!({ "status": "not_ready", "failing": failing }))
+    };
+    Ok(tiny_http::Response::from_string(body.to_string())
+        .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+        .with_status_code(tiny_http::StatusCode::from(status)))
+}
@@ -0,0 +1,54 @@
+use route_recognizer;
+use rusqlite::Connection;
+use serde_json::json;
+use std::io;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use tracing::{event, Level};
+
+use crate::api;
+use crate::api::external::services::namespace::request_namespace;
+use crate::api::external::services::tenant::client_tenant;
+use crate::api::ApiChannel;
+use crate::core::discovery::find_endpoints;
+
+/// Addresses of the running instances of a workload, of the tenant the
+/// client acts for
+pub fn get(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let workload_name = params.find("workload_name").unwrap_or_default();
+
+    let namespace = match request_namespace(req, connection, None) {
+        Ok(namespace) => namespace,
+        Err(e) => {
+            event!(Level::WARN, "discovery.get, {}", e);
+            return Err(api::RikError::InvalidBody(e));
+        }
+    };
+
+    let tenant_id = client_tenant(req);
+    if let Some(endpoints) =
+        find_endpoints(connection, tenant_id.as_deref(), &namespace, workload_name)
+    {
+        event!(Level::INFO, "discovery.get, workload found");
+        let endpoints_json = json!({
+            "workload": workload_name,
+            "namespace": namespace,
+            "endpoints": endpoints,
+        })
+        .to_string();
+        Ok(tiny_http::Response::from_string(endpoints_json)
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200)))
+    } else {
+        event!(Level::WARN, "discovery.get, workload not found");
+        Err(api::RikError::NotFound(format!(
+            "Workload {} not found",
+            workload_name
+        )))
+    }
+}
@@ -0,0 +1,224 @@
+use route_recognizer;
+use rusqlite::Connection;
+use serde_json::json;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use tracing::{event, Level};
+
+use crate::api;
+use crate::api::correlation::REQUEST_ID_HEADER;
+use crate::api::drain::{StreamDrain, SHUTDOWN_REASON};
+use crate::api::external::services::element::{query_parameter, QueryParams};
+use crate::api::external::services::list::{invalid_parameters_response, ListParams, EVENT_LIST};
+use crate::api::external::services::tenant::client_tenant;
+use crate::api::request_path;
+use crate::api::response::error_response;
+use crate::api::types::event::Event;
+use crate::api::validation::FieldError;
+use crate::api::ApiChannel;
+use crate::database::event_hub::{event_hub, Received, WATCH_BUFFER_SIZE};
+use crate::database::events::{EventCursor, EventQuery, EventRepository, DEFAULT_PAGE_SIZE};
+
+pub const WATCH_PATH: &str = "/api/v0/events.watch";
+/// Interval between two comments sent on an idle watch, to notice the
+/// clients gone
+const WATCH_HEARTBEAT: Duration = Duration::from_secs(15);
+
+pub fn get(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let url = req.url().to_string();
+    let params = match ListParams::parse(&url, &EVENT_LIST) {
+        Ok(params) => params,
+        Err(errors) => return Ok(invalid_parameters_response(errors)),
+    };
+    // `since` is kept for the clients written before the list parameters
+    let cursor = params
+        .cursor
+        .or_else(|| QueryParams::parse(&url).decoded("since"));
+    let since = match cursor.as_deref().map(EventCursor::parse) {
+        Some(Some(cursor)) => Some(cursor),
+        Some(None) => {
+            event!(Level::WARN, "events.list, invalid cursor");
+            return Ok(invalid_parameters_response(vec![FieldError::new(
+                "cursor",
+                "The cursor must be an event id or an RFC 3339 date",
+            )]));
+        }
+        None => None,
+    };
+    let query = EventQuery {
+        since,
+        element_id: QueryParams::parse(&url).decoded("element_id"),
+        tenant_id: client_tenant(req),
+        limit: params.limit.unwrap_or(DEFAULT_PAGE_SIZE),
+    };
+
+    if let Ok(page) = EventRepository::list(connection, &query) {
+        Ok(
+            tiny_http::Response::from_string(serde_json::to_string(&page).unwrap())
+                .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+                .with_status_code(tiny_http::StatusCode::from(200)),
+        )
+    } else {
+        event!(Level::ERROR, "events.list, cannot list events");
+        Err(api::RikError::Internal(String::from("Cannot list events")))
+    }
+}
+
+pub fn is_watch(req: &tiny_http::Request) -> bool {
+    *req.method() == tiny_http::Method::Get && request_path(req.url()) == WATCH_PATH
+}
+
+/// Stream the events as server-sent events, each with its id as version.
+///
+/// A watch resumes after `?resume_from=<id>`, or the `Last-Event-ID` header,
+/// with the events it missed when they fit in a page, and is answered 410 so
+/// the client lists the events again otherwise. A watch whose events are not
+/// read fast enough is closed with a `close` event. When the server shuts
+/// down the watch ends with a `bookmark` event, the client watches again
+/// from it on another server. A tenant is only sent the events of its
+/// elements. The connection is held until the client leaves, so it is meant
+/// to be served on its own thread.
+pub fn watch(
+    req: tiny_http::Request,
+    connection: &Connection,
+    request_id: &str,
+    drain: &StreamDrain,
+) {
+    let url = req.url().to_string();
+    let last_event_id = req
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Last-Event-ID"))
+        .map(|header| header.value.to_string());
+    let resume_from = match query_parameter(&url, "resume_from")
+        .map(str::to_string)
+        .or(last_event_id)
+        .map(|version| version.parse::<i64>())
+    {
+        Some(Ok(version)) => Some(version),
+        Some(Err(_)) => {
+            event!(Level::WARN, "events.watch, invalid resume version");
+            let _ = req.respond(invalid_parameters_response(vec![FieldError::new(
+                "resume_from",
+                "resume_from must be an event id",
+            )]));
+            return;
+        }
+        None => None,
+    };
+    let element_id = QueryParams::parse(&url).decoded("element_id");
+    let tenant_id = client_tenant(&req);
+
+    // Subscribed first, so no event falls between the replay and the stream
+    let subscriber = event_hub().subscribe(element_id.clone());
+    drain.follow(&subscriber);
+    let missed = match resume_from {
+        Some(version) => {
+            match EventRepository::replay(connection, version, element_id, tenant_id.clone()) {
+                Ok(Some(page)) => page.events,
+                Ok(None) => {
+                    event!(Level::INFO, "events.watch, version {} too old", version);
+                    let _ = req.respond(error_response(
+                        410,
+                        "EventsCompacted",
+                        format!(
+                            "Cannot resume from event {}, list the events again",
+                            version
+                        ),
+                    ));
+                    return;
+                }
+                Err(e) => {
+                    event!(Level::ERROR, "events.watch, cannot replay events: {}", e);
+                    let error = api::RikError::Internal(String::from("Cannot list events"));
+                    let _ = req.respond(error.response());
+                    return;
+                }
+            }
+        }
+        None => vec![],
+    };
+
+    // The response is written by hand, tiny_http buffers streamed bodies
+    let mut writer = req.into_writer();
+    let mut last = resume_from.unwrap_or(0);
+    let result = (|| -> io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n{}: {}\r\n\r\n",
+            REQUEST_ID_HEADER, request_id
+        )?;
+        send_events(&mut writer, missed, tenant_id.as_deref(), &mut last)?;
+        loop {
+            match subscriber.receive(WATCH_HEARTBEAT) {
+                Received::Events(events) => {
+                    send_events(&mut writer, events, tenant_id.as_deref(), &mut last)?
+                }
+                Received::Timeout => {
+                    writer.write_all(b": keep-alive\n\n")?;
+                    writer.flush()?;
+                }
+                Received::Lagging => {
+                    event!(Level::WARN, "events.watch, closing a watch falling behind");
+                    let reason = json!({
+                        "reason": "SlowConsumer",
+                        "message": format!(
+                            "More than {} events were waiting to be sent, resume from event {}",
+                            WATCH_BUFFER_SIZE, last
+                        ),
+                    });
+                    write!(writer, "event: close\ndata: {}\n\n", reason)?;
+                    return writer.flush();
+                }
+                Received::Closed => {
+                    let bookmark = json!({
+                        "type": "BOOKMARK",
+                        "reason": SHUTDOWN_REASON,
+                        "resume_from": last,
+                    });
+                    write!(
+                        writer,
+                        "event: bookmark\nid: {}\ndata: {}\n\n",
+                        last, bookmark
+                    )?;
+                    return writer.flush();
+                }
+            }
+        }
+    })();
+    if let Err(e) = result {
+        event!(Level::DEBUG, "events.watch, watch closed: {}", e);
+    }
+}
+
+/// Events already sent, replayed and buffered both, are skipped, as well as
+/// the ones of the other tenants
+fn send_events(
+    writer: &mut impl Write,
+    events: Vec<Event>,
+    tenant_id: Option<&str>,
+    last: &mut i64,
+) -> io::Result<()> {
+    for event in events {
+        if event.id <= *last
+            || tenant_id.is_some_and(|tenant_id| event.tenant_id.as_deref() != Some(tenant_id))
+        {
+            continue;
+        }
+        write!(
+            writer,
+            "id: {}\ndata: {}\n\n",
+            event.id,
+            serde_json::to_string(&event).unwrap()
+        )?;
+        *last = event.id;
+    }
+    writer.flush()
+}
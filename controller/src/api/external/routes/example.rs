@@ -0,0 +1,47 @@
+use route_recognizer;
+use rusqlite::Connection;
+use std::io;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use tracing::{event, Level};
+
+use crate::api;
+use crate::api::external::services::examples::{find_example, ExampleSummary, EXAMPLES};
+use crate::api::ApiChannel;
+
+pub fn get(
+    _: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    _: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let examples: Vec<ExampleSummary> = EXAMPLES.iter().map(|example| example.summary()).collect();
+    Ok(
+        tiny_http::Response::from_string(serde_json::to_string(&examples).unwrap())
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200)),
+    )
+}
+
+pub fn get_one(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    _: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let name = params.find("name").unwrap_or_default();
+    match find_example(name) {
+        Some(example) => Ok(tiny_http::Response::from_string(example.manifest)
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200))),
+        None => {
+            event!(Level::WARN, "examples.get, example {} not found", name);
+            let names: Vec<&str> = EXAMPLES.iter().map(|example| example.name).collect();
+            Err(api::RikError::NotFound(format!(
+                "Example {} not found, available examples: {}",
+                name,
+                names.join(", ")
+            )))
+        }
+    }
+}
@@ -1,36 +1,339 @@
 use definition::workload::WorkloadDefinition;
+use definition::InstanceStatus;
 use route_recognizer;
 use rusqlite::Connection;
 use std::io;
 use std::str::FromStr;
 use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
 use tracing::{event, Level};
 
-use crate::api;
-use crate::api::external::services::element::elements_set_right_name;
-use crate::api::external::services::instance::send_create_instance;
-use crate::api::types::element::OnlyId;
-use crate::api::types::instance::InstanceDefinition;
-use crate::api::{ApiChannel, Crud};
-use crate::core::instance::Instance;
+use crate::api::external::services::csv::{page_response, INSTANCE_COLUMNS};
+use crate::api::external::services::element::{element_set_right_name, query_parameter};
+use crate::api::external::services::instance::{
+    fetch_logs, generate_instance_name, record_restart, riklet_logs_address, send_create_instance,
+    strip_conditions, LogsFailure, DEFAULT_LOGS_TAIL, MAX_LOGS_TAIL,
+};
+use crate::api::external::services::list::{
+    invalid_parameters_response, read_list, ListParams, INSTANCE_LIST,
+};
+use crate::api::external::services::namespace::request_namespace;
+use crate::api::external::services::request::{
+    dry_run_response, extract_id, extract_request, is_dry_run, validation_response,
+};
+use crate::api::external::services::tenant::{
+    caller_owns, check_quota, client_tenant, resolve_tenant, QuotaResource,
+};
+use crate::api::limits::env_limits;
+use crate::api::request_path;
+use crate::api::response::{api_error_response, error_response};
+use crate::api::types::element::{Element, ElementPath};
+use crate::api::types::error::ApiError;
+use crate::api::types::instance::{InstanceDefinition, StatusChange};
+use crate::api::validation::FieldError;
+use crate::api::{correlation, drain, ApiChannel, Crud, RikError};
+use crate::core::worker_repository::worker_address;
+use crate::database::events::EventRepository;
+use crate::database::revisions::{change_notifier, RevisionRepository};
+use crate::database::workload_cache::find_workload;
 use crate::database::RikRepository;
 
 pub fn get(
-    _: &mut tiny_http::Request,
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    list(req, connection, false)
+}
+
+/// Same as `get` but instances include their conditions
+pub fn get_v1(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    list(req, connection, true)
+}
+
+pub const WATCH_PATH: &str = "/api/v0/instances.watch";
+/// Longest wait of a watch, and the one when not asked otherwise
+const WATCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn is_watch(req: &tiny_http::Request) -> bool {
+    *req.method() == tiny_http::Method::Get && request_path(req.url()) == WATCH_PATH
+}
+
+/// Changes of the instances after the revision `since`, waiting at most
+/// `timeout` seconds for one, along with the revision to watch from next.
+/// No change is answered when none happened in time, the client watches
+/// again at once.
+///
+/// Watches are woken by the writes of the controller, not by polling the
+/// database. A revision whose deletions are not kept anymore is answered
+/// with a `410`, the instances must be listed again. When the server shuts
+/// down the watch is answered at once.
+pub fn watch(
+    req: &mut tiny_http::Request,
     _: &route_recognizer::Params,
     connection: &Connection,
     _: &Sender<ApiChannel>,
-) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
-    if let Ok(mut instances) = RikRepository::find_all(connection, "/instance") {
-        instances = elements_set_right_name(instances.clone());
-        let instances_json = serde_json::to_string(&instances).unwrap();
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    let since = match query_parameter(req.url(), "since").map(str::parse::<i64>) {
+        None => 0,
+        Some(Ok(since)) if since >= 0 => since,
+        Some(_) => {
+            return Ok(invalid_parameters_response(vec![FieldError::new(
+                "since",
+                "The revision must be a positive integer",
+            )]))
+        }
+    };
+    let timeout = match query_parameter(req.url(), "timeout").map(str::parse::<u64>) {
+        None => WATCH_TIMEOUT,
+        Some(Ok(seconds)) => Duration::from_secs(seconds).min(WATCH_TIMEOUT),
+        Some(Err(_)) => {
+            return Ok(invalid_parameters_response(vec![FieldError::new(
+                "timeout",
+                "The timeout must be a number of seconds",
+            )]))
+        }
+    };
+    let tenant = client_tenant(req);
+    let deadline = Instant::now() + timeout;
+    loop {
+        // Taken before reading, a change made meanwhile ends the wait at once
+        let generation = change_notifier().generation();
+        let read = RevisionRepository::current(connection).and_then(|current| {
+            RevisionRepository::changes_since(connection, since, "/instance/")
+                .map(|changes| changes.map(|changes| (current, changes)))
+        });
+        let (current, mut changes) = match read {
+            Ok(Some(read)) => read,
+            Ok(None) => {
+                event!(Level::INFO, "instances.watch, revision {} too old", since);
+                return Ok(error_response(
+                    410,
+                    "RevisionCompacted",
+                    format!(
+                        "Cannot watch from revision {}, list the instances again",
+                        since
+                    ),
+                ));
+            }
+            Err(e) => {
+                return Err(RikError::Internal(format!(
+                    "Cannot read the changes of the instances: {}",
+                    e
+                )))
+            }
+        };
+        // Clients acting for a tenant only see its instances
+        changes.retain(|change| {
+            let (path, _) = ElementPath::parse(&change.element.name);
+            caller_owns(path.tenant.as_deref())
+                && tenant
+                    .as_ref()
+                    .is_none_or(|tenant| path.tenant.as_ref() == Some(tenant))
+        });
+        if !changes.is_empty() || Instant::now() >= deadline || drain::is_draining() {
+            let revision = changes
+                .iter()
+                .map(|change| change.revision)
+                .fold(current.max(since), i64::max);
+            for change in changes.iter_mut() {
+                element_set_right_name(&mut change.element);
+            }
+            let body = serde_json::json!({ "revision": revision, "changes": changes });
+            return Ok(tiny_http::Response::from_string(body.to_string())
+                .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+                .with_status_code(tiny_http::StatusCode::from(200)));
+        }
+        change_notifier().wait(generation, deadline);
+    }
+}
+
+/// Status changes kept in the history of `instances.get/:id`
+const MAX_STATUS_HISTORY: usize = 10;
+
+/// Whole value of an instance, with its conditions, the image it booted from
+/// and its last status changes
+pub fn get_one(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    let instance_id = params.find("instance_id").unwrap_or_default().to_string();
+    match RikRepository::find_one(connection, &instance_id, "/instance")
+        .ok()
+        .filter(|instance| caller_owns(instance.value["tenant_id"].as_str()))
+    {
+        Some(mut instance) => {
+            element_set_right_name(&mut instance);
+            let history = status_history(connection, &instance)?;
+            instance.value["status_history"] = serde_json::to_value(history).unwrap();
+            Ok(
+                tiny_http::Response::from_string(serde_json::to_string(&instance).unwrap())
+                    .with_header(
+                        tiny_http::Header::from_str("Content-Type: application/json").unwrap(),
+                    )
+                    .with_status_code(tiny_http::StatusCode::from(200)),
+            )
+        }
+        None => {
+            event!(Level::WARN, "instances.get, instance not found");
+            Err(RikError::NotFound(format!(
+                "Instance id {} not found",
+                instance_id
+            )))
+        }
+    }
+}
+
+/// Last lines of the logs of an instance, `?tail=N` of them, read from the
+/// riklet of the node it runs on
+pub fn logs(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    let instance_id = params.find("instance_id").unwrap_or_default().to_string();
+    let tail = match query_parameter(req.url(), "tail").map(str::parse::<usize>) {
+        None => DEFAULT_LOGS_TAIL,
+        Some(Ok(tail)) if (1..=MAX_LOGS_TAIL).contains(&tail) => tail,
+        Some(_) => {
+            return Ok(invalid_parameters_response(vec![FieldError::new(
+                "tail",
+                format!("tail must be a number from 1 to {}", MAX_LOGS_TAIL),
+            )]))
+        }
+    };
+    let Some(instance) = RikRepository::find_one(connection, &instance_id, "/instance")
+        .ok()
+        .filter(|instance| caller_owns(instance.value["tenant_id"].as_str()))
+    else {
+        event!(Level::WARN, "instances.logs, instance not found");
+        return Err(RikError::NotFound(format!(
+            "Instance id {} not found",
+            instance_id
+        )));
+    };
+    let Some(node) = instance.value["worker_id"].as_str() else {
+        return Ok(error_response(
+            409,
+            "NotScheduled",
+            format!("Instance {} is not placed on a node yet", instance_id),
+        ));
+    };
+
+    let unreachable = |reason: String| {
+        event!(Level::WARN, "instances.logs, node {} unreachable", node);
+        Ok(api_error_response(
+            502,
+            &ApiError {
+                details: Some(serde_json::json!({ "node": node })),
+                ..ApiError::new(
+                    "NodeUnreachable",
+                    format!("Node {} cannot be reached, {}", node, reason),
+                )
+            },
+        ))
+    };
+    let address = RikRepository::find_one(connection, &node.to_string(), "/worker/any/")
+        .ok()
+        .and_then(|worker| worker_address(worker.value))
+        .and_then(|address| riklet_logs_address(&address));
+    let Some(address) = address else {
+        return unreachable(String::from("its address is unknown"));
+    };
+    match fetch_logs(address, &instance.id, tail) {
+        Ok(lines) => Ok(tiny_http::Response::from_string(lines)
+            .with_header(
+                tiny_http::Header::from_str("Content-Type: text/plain; charset=utf-8").unwrap(),
+            )
+            .with_status_code(tiny_http::StatusCode::from(200))),
+        Err(LogsFailure::NotKept) => Err(RikError::NotFound(format!(
+            "Node {} keeps no logs for instance {}",
+            node, instance_id
+        ))),
+        Err(LogsFailure::Unreachable(reason)) => unreachable(reason),
+    }
+}
+
+/// Status changes of an instance, the oldest first. Status changes are
+/// recorded as events whose reason is the new status, instances start
+/// `Pending` when they are created.
+fn status_history(
+    connection: &Connection,
+    instance: &Element,
+) -> Result<Vec<StatusChange>, RikError> {
+    let id = instance.value["id"].as_str().unwrap_or_default();
+    let statuses: Vec<String> = InstanceStatus::ALL
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    let events = EventRepository::latest(connection, id, &statuses, MAX_STATUS_HISTORY)
+        .map_err(|e| RikError::Internal(format!("Cannot read status history: {}", e)))?;
+    let created = instance.value["created_at"]
+        .as_str()
+        .map(|created_at| StatusChange {
+            status: InstanceStatus::Pending.to_string(),
+            at: created_at.to_string(),
+        });
+    let changes = events.into_iter().map(|event| StatusChange {
+        status: event.reason,
+        at: event.created_at,
+    });
+    let mut history: Vec<StatusChange> = created.into_iter().chain(changes).collect();
+    // Only the last changes are kept, the creation goes with the older ones
+    if history.len() > MAX_STATUS_HISTORY {
+        history.remove(0);
+    }
+    Ok(history)
+}
+
+/// List the summaries of the instances, or their whole value with `?detail=full`
+fn list(
+    req: &tiny_http::Request,
+    connection: &Connection,
+    with_conditions: bool,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    let mut params = match ListParams::parse(req.url(), &INSTANCE_LIST) {
+        Ok(params) => params,
+        Err(errors) => return Ok(invalid_parameters_response(errors)),
+    };
+    // Clients acting for a tenant only see its instances
+    if let Some(tenant) = client_tenant(req) {
+        params.tenant = Some(tenant);
+    }
+    let full = query_parameter(req.url(), "detail") == Some("full");
+    let list = read_list(connection, |connection| {
+        params.find_page("/instance/", |query, limit, offset| {
+            if full {
+                let (items, total) = RikRepository::find_page(connection, query, limit, offset)?;
+                if with_conditions {
+                    return Ok((items, total));
+                }
+                return Ok((strip_conditions(items), total));
+            }
+            let summaries = RikRepository::find_instance_summaries(
+                connection,
+                query,
+                with_conditions,
+                limit,
+                offset,
+            )?;
+            Ok((summaries, RikRepository::count_matching(connection, query)?))
+        })
+    });
+    if let Ok(list) = list {
         event!(Level::INFO, "instances.get, instances found");
-        Ok(tiny_http::Response::from_string(instances_json)
-            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
-            .with_status_code(tiny_http::StatusCode::from(200)))
+        Ok(page_response(req, &list, INSTANCE_COLUMNS))
     } else {
-        Ok(tiny_http::Response::from_string("Cannot find instances")
-            .with_status_code(tiny_http::StatusCode::from(500)))
+        Err(RikError::Internal(String::from("Cannot find instances")))
     }
 }
 
@@ -39,62 +342,133 @@ pub fn create(
     _: &route_recognizer::Params,
     connection: &Connection,
     internal_sender: &Sender<ApiChannel>,
-) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
-    let mut content = String::new();
-    req.as_reader().read_to_string(&mut content).unwrap();
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    let mut instance: InstanceDefinition = match extract_request(req) {
+        Ok(instance) => instance,
+        Err(response) => return Ok(response),
+    };
 
-    let mut instance: InstanceDefinition = serde_json::from_str(&content)?;
+    let namespace = match request_namespace(req, connection, instance.namespace.as_deref()) {
+        Ok(namespace) => namespace,
+        Err(e) => {
+            event!(Level::WARN, "instances.create, {}", e);
+            return Err(RikError::InvalidBody(e));
+        }
+    };
 
-    //Workload not found
-    if RikRepository::find_one(connection, &instance.workload_id, "/workload").is_err() {
-        event!(
-            Level::WARN,
-            "Workload id {} not found",
-            &instance.workload_id
-        );
-        return Ok(tiny_http::Response::from_string(format!(
-            "Workload id {} not found",
-            &instance.workload_id
-        ))
-        .with_status_code(tiny_http::StatusCode::from(404)));
+    let tenant_id = resolve_tenant(connection, instance.tenant_id.as_deref(), req)?;
+
+    // Checked before anything is sent to the core, a workload deleted in the
+    // meantime is left to the reconciliation of the scheduler. Instances
+    // belong to the tenant of their workload, which other tenants cannot see.
+    let workload = match find_workload(connection, &instance.workload_id) {
+        Ok(workload) if tenant_id.is_none() || tenant_id == workload.tenant_id => workload,
+        Ok(_) => {
+            event!(
+                Level::WARN,
+                "Workload id {} not found for tenant",
+                &instance.workload_id
+            );
+            return Err(RikError::NotFound(format!(
+                "Workload id {} not found",
+                &instance.workload_id
+            )));
+        }
+        Err(RikError::InvalidName(_)) => {
+            event!(
+                Level::WARN,
+                "Workload id {} not found",
+                &instance.workload_id
+            );
+            return Err(RikError::NotFound(format!(
+                "Workload id {} not found",
+                &instance.workload_id
+            )));
+        }
+        Err(e) => {
+            event!(Level::ERROR, "instances.create, {}", e);
+            return Err(RikError::Internal(format!(
+                "Cannot read workload {}",
+                &instance.workload_id
+            )));
+        }
+    };
+
+    // Overridden variables must fit in the same limits as the workload ones
+    if let Some(overrides) = &instance.overrides {
+        let mut overridden = workload.clone();
+        overrides.apply(&mut overridden);
+        if let Err(e) = overridden.validate(&env_limits()) {
+            event!(Level::WARN, "instances.create, {}", e);
+            return Ok(validation_response(vec![FieldError::new(
+                "overrides.env",
+                e,
+            )]));
+        }
     }
 
     if instance.name.is_some() {
         // Check name is not used
-        if RikRepository::check_duplicate_name(
+        if let Ok(existing) = RikRepository::check_duplicate_name(
             connection,
-            &format!("/instance/%/default/{}", instance.get_name()),
-        )
-        .is_ok()
-        {
+            &format!("/instance/%/{}/{}", namespace, instance.get_name()),
+        ) {
             event!(
                 Level::WARN,
                 "Instance name {} is already used",
                 instance.get_name()
             );
-            return Ok(tiny_http::Response::from_string("Name already used")
-                .with_status_code(tiny_http::StatusCode::from(404)));
-        }
-
-        // Name cannot be used with multiple replicas
-        if instance.get_replicas() > 1 {
-            return Ok(
-                tiny_http::Response::from_string("Cannot use name with multiple replicas")
-                    .with_status_code(tiny_http::StatusCode::from(400)),
-            );
+            return Err(RikError::Conflict(
+                format!(
+                    "Instance {} already exists in namespace {}",
+                    instance.get_name(),
+                    namespace
+                ),
+                existing.id,
+            ));
         }
     }
 
+    // A named instance is a single one, otherwise the workload gives the count
+    let replicas = match (&instance.name, instance.replicas) {
+        (_, Some(replicas)) => replicas,
+        (Some(_), None) => 1,
+        (None, None) => workload.replicas.map_or(1, usize::from),
+    };
+    check_quota(
+        connection,
+        workload.tenant_id.as_deref(),
+        QuotaResource::Instances,
+        replicas,
+    )?;
     let mut instance_names: Vec<String> = vec![];
-
-    for _ in 0..instance.get_replicas() {
-        let instance_name = instance.name.clone().unwrap_or(Instance::generate_name());
+    for _ in 0..replicas {
+        let instance_name = match &instance.name {
+            Some(name) => name.clone(),
+            None => {
+                generate_instance_name(connection, &workload.name, &namespace, &instance_names)?
+            }
+        };
         instance_names.push(instance_name.clone());
+    }
+
+    // The names generated are not kept, the core may be given others
+    if is_dry_run(req) {
+        event!(Level::INFO, "instances.create, dry run, nothing created");
+        return Ok(dry_run_response(
+            201,
+            serde_json::json!({ "instances": instance_names }),
+        ));
+    }
+
+    for instance_name in &instance_names {
         send_create_instance(
-            connection,
             internal_sender,
             instance.workload_id.clone(),
-            &Some(instance_name),
+            workload.clone(),
+            &Some(instance_name.clone()),
+            &instance.overrides,
+            &namespace,
         );
     }
 
@@ -105,43 +479,136 @@ pub fn create(
     )
 }
 
+/// Bring an instance down then up again on its node, keeping its id. An
+/// instance which is not running is booted again rather than refused.
+pub fn restart(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    connection: &Connection,
+    internal_sender: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    let restart_id = match extract_id(req, params) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
+    let Some(instance) = RikRepository::find_one(connection, &restart_id, "/instance")
+        .ok()
+        .filter(|instance| caller_owns(instance.value["tenant_id"].as_str()))
+    else {
+        event!(Level::WARN, "instances.restart, instance not found");
+        return Err(RikError::NotFound(format!(
+            "Instance id {} not found",
+            restart_id
+        )));
+    };
+    // Instances being deleted are not brought back
+    if matches!(
+        instance.value["status"].as_str(),
+        Some("Destroying") | Some("Terminated")
+    ) {
+        return Ok(error_response(
+            409,
+            "InstanceTerminating",
+            format!("Instance {} is being deleted", restart_id),
+        ));
+    }
+    let workload_id = instance.value["workload_id"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let Ok(workload) = RikRepository::find_one(connection, &workload_id, "/workload") else {
+        return Err(RikError::NotFound(format!(
+            "Workload {} matching the instance ID is not found",
+            workload_id
+        )));
+    };
+    let workload_def: WorkloadDefinition = serde_json::from_value(workload.value).unwrap();
+    if is_dry_run(req) {
+        event!(Level::INFO, "instances.restart, dry run, nothing restarted");
+        return Ok(dry_run_response(
+            202,
+            serde_json::json!({ "id": instance.id }),
+        ));
+    }
+
+    let mut restarting = record_restart(connection, instance)?;
+    internal_sender
+        .send(ApiChannel {
+            action: Crud::Restart,
+            workload_id: Some(workload_id),
+            workload_definition: Some(workload_def),
+            instance_id: Some(restart_id),
+            overrides: None,
+            namespace: None,
+            tenant_id: None,
+            correlation_id: correlation::current(),
+        })
+        .unwrap();
+    event!(
+        Level::INFO,
+        "Instance {} has been requested to be restarted",
+        restarting.id
+    );
+    element_set_right_name(&mut restarting);
+    Ok(
+        tiny_http::Response::from_string(serde_json::to_string(&restarting).unwrap())
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(202)),
+    )
+}
+
 pub fn delete(
     req: &mut tiny_http::Request,
-    _: &route_recognizer::Params,
+    params: &route_recognizer::Params,
     connection: &Connection,
     internal_sender: &Sender<ApiChannel>,
-) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
-    let mut content = String::new();
-    req.as_reader().read_to_string(&mut content).unwrap();
-    let OnlyId { id: delete_id } = serde_json::from_str(&content)?;
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    let delete_id = match extract_id(req, params) {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
 
-    if let Ok(instance) = RikRepository::find_one(connection, &delete_id, "/instance") {
-        let instance_def: InstanceDefinition =
-            serde_json::from_value(instance.value.clone()).unwrap();
+    if let Some(instance) = RikRepository::find_one(connection, &delete_id, "/instance")
+        .ok()
+        .filter(|instance| caller_owns(instance.value["tenant_id"].as_str()))
+    {
+        let workload_id = instance.value["workload_id"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
 
-        let workload_def_rs =
-            RikRepository::find_one(connection, &instance_def.workload_id, "/workload");
+        let workload_def_rs = RikRepository::find_one(connection, &workload_id, "/workload");
         if let Err(e) = workload_def_rs {
             event!(
                 Level::ERROR,
                 "Could not find workload id {} while should have been able to, error: {}",
-                instance_def.workload_id,
+                workload_id,
                 e
             );
-            return Ok(tiny_http::Response::from_string(format!(
+            return Err(RikError::NotFound(format!(
                 "Workload {} matching the instance ID is not found",
-                instance_def.workload_id
-            ))
-            .with_status_code(tiny_http::StatusCode::from(404)));
+                workload_id
+            )));
         }
         let workload_def: WorkloadDefinition =
             serde_json::from_value(workload_def_rs.unwrap().value).unwrap();
+        if is_dry_run(req) {
+            event!(Level::INFO, "instances.delete, dry run, nothing deleted");
+            return Ok(dry_run_response(
+                200,
+                serde_json::json!({ "id": instance.id }),
+            ));
+        }
         internal_sender
             .send(ApiChannel {
                 action: Crud::Delete,
-                workload_id: Some(instance_def.workload_id),
+                workload_id: Some(workload_id),
                 workload_definition: Some(workload_def),
                 instance_id: Some(delete_id),
+                overrides: None,
+                namespace: None,
+                tenant_id: None,
+                correlation_id: correlation::current(),
             })
             .unwrap();
 
@@ -153,9 +620,9 @@ pub fn delete(
         Ok(tiny_http::Response::from_string("").with_status_code(tiny_http::StatusCode::from(204)))
     } else {
         event!(Level::ERROR, "Instance id {} not found", delete_id);
-        Ok(
-            tiny_http::Response::from_string(format!("Instance id {} not found", delete_id))
-                .with_status_code(tiny_http::StatusCode::from(404)),
-        )
+        Err(RikError::NotFound(format!(
+            "Instance id {} not found",
+            delete_id
+        )))
     }
 }
@@ -0,0 +1,66 @@
+use route_recognizer;
+use rusqlite::Connection;
+use serde_json::json;
+use std::io;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+
+use crate::api;
+use crate::api::concurrency::concurrency;
+use crate::api::metrics::api_metrics;
+use crate::api::read_only::read_only;
+use crate::api::ApiChannel;
+use crate::database::event_hub::event_hub;
+use crate::database::metrics::database_metrics;
+use crate::database::workload_cache::workload_cache;
+use crate::database::RikRepository;
+
+/// Path of the metrics in the Prometheus text format, outside of the API
+pub const PROMETHEUS_PATH: &str = "/metrics";
+/// Path of the metrics of the controller, as JSON
+pub const METRICS_PATH: &str = "/api/v0/metrics";
+
+pub fn get(
+    _: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    _: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let metrics = json!({
+        "workload_cache": workload_cache().stats(),
+        "database": database_metrics().snapshot(),
+        "event_watches": event_hub().subscribers(),
+        "read_only": read_only().status(),
+        "concurrency": concurrency().snapshot(),
+    });
+    Ok(tiny_http::Response::from_string(metrics.to_string())
+        .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+        .with_status_code(tiny_http::StatusCode::from(200)))
+}
+
+/// Requests answered by the API and amount of elements stored, in the
+/// Prometheus text format. The elements are counted on each scrape.
+pub fn prometheus(
+    _: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let count = |element_type: &str| {
+        RikRepository::count(connection, element_type)
+            .map(|count| count as u64)
+            .map_err(|e| api::RikError::Internal(format!("Cannot count elements: {}", e)))
+    };
+    let gauges = [
+        ("rik_workloads", "Workloads stored", count("/workload")?),
+        ("rik_instances", "Instances stored", count("/instance")?),
+        ("rik_tenants", "Tenants stored", count("/tenant")?),
+    ];
+    Ok(
+        tiny_http::Response::from_string(api_metrics().render(&gauges))
+            .with_header(
+                tiny_http::Header::from_str("Content-Type: text/plain; version=0.0.4").unwrap(),
+            )
+            .with_status_code(tiny_http::StatusCode::from(200)),
+    )
+}
@@ -1,15 +1,35 @@
 use route_recognizer;
 use rusqlite::Connection;
 use std::io;
+use std::str::FromStr;
 use std::sync::mpsc::Sender;
 use tiny_http::Method;
 use tracing::{event, Level};
 
 use crate::api;
+use crate::api::auth::{api_auth, check_tenant_route, with_tenant, ApiAuth};
+use crate::api::cors::Cors;
+use crate::api::external::services::element::decode_path_segment;
+use crate::api::metrics::{api_metrics, UNMATCHED_ROUTE};
+use crate::api::read_only::read_only;
+use crate::api::request_path;
+use crate::api::response::{api_error_response, error_response, with_api_version};
+use crate::api::types::error::ApiError;
 use crate::api::ApiChannel;
 
-mod instance;
+mod admin;
+mod discovery;
+pub(super) mod events;
+mod example;
+pub(super) mod instance;
+mod metrics;
+mod node;
+mod openapi;
+mod search;
 mod tenant;
+mod usage;
+mod v1;
+mod volume;
 mod workload;
 
 type Handler = fn(
@@ -19,69 +39,2720 @@ type Handler = fn(
     &Sender<ApiChannel>,
 ) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError>;
 
+/// Route a request was answered by, if any, and its answer
+type Dispatched = (
+    Option<String>,
+    Option<tiny_http::Response<io::Cursor<Vec<u8>>>>,
+);
+
+/// Handler of a route, and whether the route acts on the whole cluster
+struct Route {
+    handler: Handler,
+    cluster_wide: bool,
+}
+
+/// Routes of a method, their paths kept to list them
+struct MethodRoutes {
+    router: route_recognizer::Router<Route>,
+    paths: Vec<String>,
+}
+
+impl MethodRoutes {
+    fn new() -> MethodRoutes {
+        MethodRoutes {
+            router: route_recognizer::Router::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, path: &str, handler: Handler) {
+        self.insert(path, handler, false);
+    }
+
+    /// Add a route acting on the whole cluster, refused to the tenant keys
+    fn add_cluster_wide(&mut self, path: &str, handler: Handler) {
+        self.insert(path, handler, true);
+    }
+
+    fn insert(&mut self, path: &str, handler: Handler, cluster_wide: bool) {
+        self.router.add(
+            path,
+            Route {
+                handler,
+                cluster_wide,
+            },
+        );
+        self.paths.push(path.to_string());
+    }
+}
+
+/// Most edits between a path and a route for the route to be suggested
+const MAX_HINT_DISTANCE: usize = 3;
+
 pub struct Router {
-    routes: Vec<(tiny_http::Method, route_recognizer::Router<Handler>)>,
+    routes: Vec<(tiny_http::Method, MethodRoutes)>,
+    auth: ApiAuth,
+    cors: Cors,
 }
 
 impl Router {
     pub fn new() -> Router {
-        let mut get = route_recognizer::Router::<Handler>::new();
-        let mut post = route_recognizer::Router::<Handler>::new();
+        let mut get = MethodRoutes::new();
+        let mut post = MethodRoutes::new();
+        let mut delete = MethodRoutes::new();
+        let mut put = MethodRoutes::new();
 
         let base_path = "/api/v0";
 
         // Workload related routes
         get.add(&format!("{}/workloads.list", base_path), workload::get);
+        get.add(
+            &format!("{}/workloads.get/:workloadid", base_path),
+            workload::get_one,
+        );
         get.add(
             &format!("{}/workloads.instances/:workloadid", base_path),
             workload::get_instances,
         );
         post.add(&format!("{}/workloads.create", base_path), workload::create);
+        post.add(
+            &format!("{}/workloads.create_bulk", base_path),
+            workload::create_bulk,
+        );
+        get.add(
+            &format!("{}/workloads.get", base_path),
+            workload::get_by_name,
+        );
+        post.add(&format!("{}/workloads.update", base_path), workload::update);
         post.add(&format!("{}/workloads.delete", base_path), workload::delete);
+        post.add(&format!("{}/workloads.scale", base_path), workload::scale);
+        post.add(
+            &format!("{}/workloads.delete_collection", base_path),
+            workload::delete_collection,
+        );
+        put.add(&format!("{}/workloads/:id", base_path), workload::update);
+        delete.add(&format!("{}/workloads/:id", base_path), workload::delete);
 
         // Tenant related routes
-        get.add(&format!("{}/tenants.list", base_path), tenant::get);
-        post.add(&format!("{}/tenants.create", base_path), tenant::create);
-        post.add(&format!("{}/tenants.delete", base_path), tenant::delete);
+        get.add_cluster_wide(&format!("{}/tenants.list", base_path), tenant::get);
+        get.add_cluster_wide(&format!("{}/tenants.get/:id", base_path), tenant::get_one);
+        post.add_cluster_wide(&format!("{}/tenants.create", base_path), tenant::create);
+        post.add_cluster_wide(&format!("{}/tenants.delete", base_path), tenant::delete);
+        post.add_cluster_wide(
+            &format!("{}/tenants.rotate_key", base_path),
+            tenant::rotate_key,
+        );
+        post.add_cluster_wide(
+            &format!("{}/tenants.set_quota", base_path),
+            tenant::set_quota,
+        );
 
         // Instance related routes
         get.add(&format!("{}/instances.list", base_path), instance::get);
+        get.add(
+            &format!("{}/instances.get/:instance_id", base_path),
+            instance::get_one,
+        );
+        get.add(
+            &format!("{}/instances.logs/:instance_id", base_path),
+            instance::logs,
+        );
+        get.add(instance::WATCH_PATH, instance::watch);
         post.add(&format!("{}/instances.create", base_path), instance::create);
         post.add(&format!("{}/instances.delete", base_path), instance::delete);
+        post.add(
+            &format!("{}/instances.restart", base_path),
+            instance::restart,
+        );
+        delete.add(&format!("{}/instances/:id", base_path), instance::delete);
+
+        // Volume related routes
+        get.add(&format!("{}/volumes.list", base_path), volume::get);
+        post.add(&format!("{}/volumes.create", base_path), volume::create);
+        post.add(&format!("{}/volumes.delete", base_path), volume::delete);
+
+        // Node related routes
+        get.add_cluster_wide(&format!("{}/nodes.list", base_path), node::list);
+        get.add_cluster_wide(&format!("{}/nodes.get/:id", base_path), node::get_one);
+        post.add_cluster_wide(
+            &format!("{}/nodes.maintenance", base_path),
+            node::maintenance,
+        );
+        post.add_cluster_wide(&format!("{}/nodes.cordon", base_path), node::cordon);
+
+        // Event related routes
+        get.add(&format!("{}/events.list", base_path), events::get);
+
+        // Usage rollups
+        get.add(&format!("{}/usage", base_path), usage::get);
+
+        // Discovery related routes
+        get.add(
+            &format!("{}/discovery/:workload_name", base_path),
+            discovery::get,
+        );
+
+        // Name search, for completions
+        get.add(&format!("{}/search", base_path), search::get);
+
+        // Example manifests
+        get.add(&format!("{}/examples", base_path), example::get);
+        get.add(&format!("{}/examples/:name", base_path), example::get_one);
+
+        // Controller metrics
+        get.add_cluster_wide(metrics::METRICS_PATH, metrics::get);
+
+        // Administration
+        get.add_cluster_wide(admin::READ_ONLY_PATH, admin::get_read_only);
+        post.add_cluster_wide(admin::READ_ONLY_PATH, admin::set_read_only);
+        get.add(&format!("{}/version", base_path), admin::version);
+        get.add(openapi::OPENAPI_PATH, openapi::get);
+
+        // Probes
+        get.add(admin::HEALTHZ_PATH, admin::healthz);
+        get.add(admin::READYZ_PATH, admin::readyz);
+        get.add_cluster_wide(metrics::PROMETHEUS_PATH, metrics::prometheus);
+
+        // The v1 API, whose routes are resources acted on with the verb of the
+        // request. Its handlers are the ones of v0, adapted to the v1 paths.
+        let v1_base_path = v1::V1_BASE_PATH;
+        get.add(&format!("{}/workloads", v1_base_path), workload::get);
+        post.add(&format!("{}/workloads", v1_base_path), workload::create);
+        get.add(&format!("{}/workloads/:id", v1_base_path), v1::get_workload);
+        put.add(&format!("{}/workloads/:id", v1_base_path), workload::update);
+        delete.add(&format!("{}/workloads/:id", v1_base_path), workload::delete);
+        get.add(&format!("{}/instances", v1_base_path), instance::get_v1);
+        post.add(&format!("{}/instances", v1_base_path), instance::create);
+        get.add(&format!("{}/instances/:id", v1_base_path), v1::get_instance);
+        get.add(
+            &format!("{}/instances/:id/logs", v1_base_path),
+            v1::get_instance_logs,
+        );
+        post.add(
+            &format!("{}/instances/:id/restart", v1_base_path),
+            instance::restart,
+        );
+        delete.add(&format!("{}/instances/:id", v1_base_path), instance::delete);
+        // Kept for the clients of the v1 API while it was staged
+        get.add(
+            &format!("{}/instances.list", v1_base_path),
+            instance::get_v1,
+        );
 
         Router {
-            routes: vec![(Method::Get, get), (Method::Post, post)],
+            routes: vec![
+                (Method::Get, get),
+                (Method::Post, post),
+                (Method::Delete, delete),
+                (Method::Put, put),
+            ],
+            auth: api_auth().clone(),
+            cors: Cors::default(),
         }
     }
 
+    /// Router answering the browsers of the given origins
+    pub fn with_cors(mut self, cors: Cors) -> Router {
+        self.cors = cors;
+        self
+    }
+
+    /// Paths of the routes along with their method, their parameters given by
+    /// name such as `/api/v0/workloads.get/:workloadid`
+    pub fn paths(&self) -> impl Iterator<Item = (&Method, &str)> {
+        self.routes.iter().flat_map(|(method, routes)| {
+            routes.paths.iter().map(move |path| (method, path.as_str()))
+        })
+    }
+
+    /// Answer to a request no route matches, suggesting the route nearest to
+    /// its path if there is one
+    pub fn not_found(&self, url: &str) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+        let path = request_path(url);
+        let mut details = serde_json::json!({ "path": path });
+        if let Some(hint) = route_hint(path, self.paths().map(|(_, route)| route)) {
+            details["hint"] = format!("did you mean {}?", hint).into();
+        }
+        let error = ApiError {
+            details: Some(details),
+            ..ApiError::new("RouteNotFound", format!("No route matches {}", path))
+        };
+        with_api_version(v1::path_version(path), || api_error_response(404, &error))
+    }
+
+    /// Router checking the given token rather than the one of the server
+    #[cfg(test)]
+    pub fn with_auth(mut self, auth: ApiAuth) -> Router {
+        self.auth = auth;
+        self
+    }
+
+    /// Answer a request with the handler of its route, `None` when no route
+    /// has its path. A path only routed for other methods is answered with a
+    /// `405` listing them in its `Allow` header.
+    ///
+    /// Every request is counted in the metrics of the API, a request without
+    /// an answer as a `404`. The answers to browsers of an allowed origin
+    /// carry the CORS headers.
     pub fn handle(
         &self,
         request: &mut tiny_http::Request,
         connection: &Connection,
         internal_sender: &Sender<ApiChannel>,
     ) -> Option<tiny_http::Response<io::Cursor<Vec<u8>>>> {
-        self.routes
+        let method = request.method().to_string();
+        let version = v1::path_version(request_path(request.url()));
+        let (route, response) = with_api_version(version, || {
+            self.dispatch(request, connection, internal_sender)
+        });
+        api_metrics().record(
+            route.as_deref().unwrap_or(UNMATCHED_ROUTE),
+            &method,
+            response
+                .as_ref()
+                .map_or(404, |response| response.status_code().0),
+        );
+        let path = request_path(request.url());
+        let methods = self.allowed_methods(path);
+        response.map(|response| self.cors.apply(request, &methods, response))
+    }
+
+    /// Answer a request, along with the route it was answered by
+    fn dispatch(
+        &self,
+        request: &mut tiny_http::Request,
+        connection: &Connection,
+        internal_sender: &Sender<ApiChannel>,
+    ) -> Dispatched {
+        // The query string is left to the handlers
+        let path = request_path(request.url());
+        // Browsers send preflight requests without credentials
+        if request.method() == &Method::Options {
+            return self.preflight(path);
+        }
+        // Checked first, so routes cannot be probed without a token
+        let tenant_id = match self.auth.check(request, connection) {
+            Ok(tenant_id) => tenant_id,
+            Err(response) => return (None, Some(response)),
+        };
+        let Some(res) = self
+            .routes
             .iter()
             .find(|&(method, _)| method == request.method())
-            .and_then(|(_, routes)| {
-                if let Ok(res) = routes.recognize(request.url()) {
-                    event!(
-                        Level::INFO,
-                        "Route found, method: {}, path: {}",
-                        request.method(),
-                        request.url()
-                    );
-                    Some(
-                        res.handler()(request, res.params(), connection, internal_sender)
-                            .unwrap_or_else(|error| {
-                                event!(Level::ERROR, "Could not handle route: {}", error);
-                                tiny_http::Response::from_string(error.to_string())
-                                    .with_status_code(tiny_http::StatusCode::from(400))
-                            }),
-                    )
-                } else {
-                    None
-                }
+            .and_then(|(_, routes)| routes.router.recognize(path).ok())
+        else {
+            return (None, self.method_not_allowed(request.method(), path));
+        };
+        let route = Some(route_pattern(path, res.params()));
+        event!(
+            Level::INFO,
+            "Route found, method: {}, path: {}",
+            request.method(),
+            request.url()
+        );
+        if let Err(response) =
+            check_tenant_route(tenant_id.as_deref(), path, res.handler().cluster_wide)
+        {
+            return (route, Some(response));
+        }
+        // Every route but the reads mutates the cluster
+        if request.method() != &Method::Get
+            && path != admin::READ_ONLY_PATH
+            && read_only().is_enabled()
+        {
+            event!(Level::WARN, "Mutation refused, the API is read-only");
+            return (route, Some(read_only().response()));
+        }
+        let Some(params) = decoded_params(res.params()) else {
+            return (
+                route,
+                Some(error_response(
+                    400,
+                    "InvalidPath",
+                    format!("{} is not correctly percent-encoded", path),
+                )),
+            );
+        };
+        let result = with_tenant(tenant_id, || {
+            (res.handler().handler)(request, &params, connection, internal_sender)
+        });
+        let response = result.unwrap_or_else(|error| {
+            if error.status().0 >= 500 {
+                event!(Level::ERROR, "Could not handle route: {}", error);
+            } else {
+                event!(Level::WARN, "Could not handle route: {}", error);
+            }
+            error.response()
+        });
+        (route, Some(response))
+    }
+
+    /// Methods routed on a path
+    fn allowed_methods(&self, path: &str) -> Vec<String> {
+        self.routes
+            .iter()
+            .filter(|(_, routes)| routes.router.recognize(path).is_ok())
+            .map(|(method, _)| method.to_string())
+            .collect()
+    }
+
+    /// Answer an `OPTIONS` request with the methods routed on its path, the
+    /// CORS headers being added by `handle`
+    fn preflight(&self, path: &str) -> Dispatched {
+        let Some(res) = self
+            .routes
+            .iter()
+            .find_map(|(_, routes)| routes.router.recognize(path).ok())
+        else {
+            return (None, None);
+        };
+        let mut allowed = self.allowed_methods(path);
+        allowed.push(Method::Options.to_string());
+        (
+            Some(route_pattern(path, res.params())),
+            Some(
+                tiny_http::Response::from_data(Vec::new())
+                    .with_status_code(204)
+                    .with_header(
+                        tiny_http::Header::from_str(&format!("Allow: {}", allowed.join(", ")))
+                            .unwrap(),
+                    ),
+            ),
+        )
+    }
+
+    fn method_not_allowed(
+        &self,
+        method: &Method,
+        path: &str,
+    ) -> Option<tiny_http::Response<io::Cursor<Vec<u8>>>> {
+        let allowed = self.allowed_methods(path);
+        if allowed.is_empty() {
+            return None;
+        }
+        event!(
+            Level::INFO,
+            "Method {} not allowed on {}, only {}",
+            method,
+            path,
+            allowed.join(", ")
+        );
+        Some(
+            error_response(
+                405,
+                "MethodNotAllowed",
+                format!("{} is not allowed on {}", method, path),
+            )
+            .with_header(
+                tiny_http::Header::from_str(&format!("Allow: {}", allowed.join(", "))).unwrap(),
+            ),
+        )
+    }
+}
+
+/// Whether a request counts against the rate limit of its client, the
+/// probes and the metrics being scraped on a schedule of their own
+pub(super) fn is_rate_limited(req: &tiny_http::Request) -> bool {
+    let path = request_path(req.url());
+    ![
+        admin::HEALTHZ_PATH,
+        admin::READYZ_PATH,
+        metrics::PROMETHEUS_PATH,
+        metrics::METRICS_PATH,
+    ]
+    .contains(&path)
+}
+
+/// Parameters of a route, percent-decoded, `None` when one is badly encoded
+fn decoded_params(params: &route_recognizer::Params) -> Option<route_recognizer::Params> {
+    let mut decoded = route_recognizer::Params::new();
+    for (name, value) in params.iter() {
+        decoded.insert(name.to_string(), decode_path_segment(value)?);
+    }
+    Some(decoded)
+}
+
+/// Route nearest to a path no route matches, with the values of the path as
+/// parameters: a route with as many segments and a few typos, or else the
+/// shortest route starting with the path
+fn route_hint<'a>(path: &str, routes: impl Iterator<Item = &'a str>) -> Option<String> {
+    let segments: Vec<&str> = path.split('/').collect();
+    let mut nearest: Option<(usize, String)> = None;
+    let mut longer: Option<&str> = None;
+    for route in routes {
+        if route.starts_with(path) && longer.is_none_or(|longer| route.len() < longer.len()) {
+            longer = Some(route);
+        }
+        let route_segments: Vec<&str> = route.split('/').collect();
+        if route_segments.len() != segments.len() {
+            continue;
+        }
+        let mut distance = 0;
+        let mut hint = Vec::with_capacity(segments.len());
+        for (segment, route_segment) in segments.iter().zip(route_segments) {
+            if route_segment.starts_with(':') {
+                hint.push(*segment);
+            } else {
+                distance += edit_distance(segment, route_segment);
+                hint.push(route_segment);
+            }
+        }
+        if distance <= MAX_HINT_DISTANCE
+            && nearest
+                .as_ref()
+                .is_none_or(|(nearest, _)| distance < *nearest)
+        {
+            nearest = Some((distance, hint.join("/")));
+        }
+    }
+    nearest
+        .map(|(_, hint)| hint)
+        .or_else(|| longer.map(String::from))
+}
+
+/// Characters to insert, delete or replace to turn a string into another
+fn edit_distance(from: &str, to: &str) -> usize {
+    let to: Vec<char> = to.chars().collect();
+    let mut previous: Vec<usize> = (0..=to.len()).collect();
+    for (i, from_char) in from.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, to_char) in to.iter().enumerate() {
+            let replace = previous[j] + usize::from(from_char != *to_char);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[to.len()]
+}
+
+/// Route of a path, its parameters given by name such as
+/// `/api/v0/workloads.get/:workloadid`, so metrics do not get a label per id
+fn route_pattern(path: &str, params: &route_recognizer::Params) -> String {
+    path.split('/')
+        .map(
+            |segment| match params.iter().find(|(_, value)| *value == segment) {
+                Some((name, _)) => format!(":{}", name),
+                None => segment.to_string(),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::auth::API_KEY_FIELD;
+    use crate::api::limits::max_body_bytes;
+    use crate::api::types::list::ListResponse;
+    use crate::api::Crud;
+    use crate::database::revisions::RevisionRepository;
+    use crate::database::{RikDataBase, RikRepository};
+    use crate::tests::fixtures::db_connection;
+    use rstest::rstest;
+    use std::sync::mpsc::channel;
+    use tiny_http::TestRequest;
+
+    const MANIFEST: &str = r#"{"apiVersion": "v0", "kind": "Pod", "name": "web", "namespace": "lab", "replicas": 1, "spec": {"containers": [{"name": "web", "image": "nginx"}]}}"#;
+
+    fn insert_workload(connection: &Connection) -> String {
+        RikRepository::insert(connection, "/workload/Pod/lab/web", MANIFEST).unwrap()
+    }
+
+    /// Request to the router. `TestRequest` only takes `'static` bodies, the
+    /// body of a test is leaked here.
+    fn request(method: Method, path: &str, body: &str) -> TestRequest {
+        TestRequest::new()
+            .with_method(method)
+            .with_path(path)
+            .with_body(Box::leak(body.into()))
+    }
+
+    fn status(
+        router: &Router,
+        connection: &Connection,
+        sender: &Sender<ApiChannel>,
+        method: Method,
+        path: &str,
+        body: &str,
+    ) -> Option<u16> {
+        let mut request = request(method, path, body).into();
+        router
+            .handle(&mut request, connection, sender)
+            .map(|response| response.status_code().0)
+    }
+
+    #[rstest]
+    fn test_delete_verb_and_legacy_route(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+
+        let id = insert_workload(&connection);
+        let path = format!("/api/v0/workloads/{}", id);
+        assert_eq!(
+            status(&router, &connection, &sender, Method::Delete, &path, ""),
+            Some(204)
+        );
+        assert_eq!(
+            status(&router, &connection, &sender, Method::Delete, &path, ""),
+            Some(404)
+        );
+
+        let id = insert_workload(&connection);
+        let body = format!(r#"{{"id": "{}"}}"#, id);
+        let path = "/api/v0/workloads.delete";
+        assert_eq!(
+            status(&router, &connection, &sender, Method::Post, path, &body),
+            Some(204)
+        );
+
+        let path = "/api/v0/instances/unknown";
+        assert_eq!(
+            status(&router, &connection, &sender, Method::Delete, path, ""),
+            Some(404)
+        );
+        let path = "/api/v0/instances.delete";
+        let body = r#"{"id": "unknown"}"#;
+        assert_eq!(
+            status(&router, &connection, &sender, Method::Post, path, body),
+            Some(404)
+        );
+    }
+
+    #[rstest]
+    fn test_put_verb_and_legacy_route(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let id = insert_workload(&connection);
+
+        let path = format!("/api/v0/workloads/{}", id);
+        assert_eq!(
+            status(&router, &connection, &sender, Method::Put, &path, MANIFEST),
+            Some(200)
+        );
+        let path = "/api/v0/workloads.update";
+        assert_eq!(
+            status(&router, &connection, &sender, Method::Post, path, MANIFEST),
+            Some(200)
+        );
+
+        // The name of the manifest must be the one of the workload
+        let other = RikRepository::insert(
+            &connection,
+            "/workload/Pod/lab/api",
+            &MANIFEST.replace(r#""web""#, r#""api""#),
+        )
+        .unwrap();
+        let path = format!("/api/v0/workloads/{}", other);
+        assert_eq!(
+            status(&router, &connection, &sender, Method::Put, &path, MANIFEST),
+            Some(400)
+        );
+        let path = "/api/v0/workloads/unknown";
+        assert_eq!(
+            status(&router, &connection, &sender, Method::Put, path, MANIFEST),
+            Some(404)
+        );
+    }
+
+    #[rstest]
+    fn test_unknown_path_is_not_handled(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+
+        for method in [Method::Delete, Method::Put, Method::Post, Method::Get] {
+            let path = "/api/v0/unknown/id";
+            assert_eq!(
+                status(&router, &connection, &sender, method, path, ""),
+                None
+            );
+        }
+    }
+
+    #[rstest]
+    fn test_wrong_method_is_not_allowed(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+
+        let allow = |method: Method, path: &'static str| {
+            let mut request = TestRequest::new()
+                .with_method(method)
+                .with_path(path)
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            assert_eq!(response.status_code().0, 405);
+            response
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("Allow"))
+                .map(|header| header.value.to_string())
+        };
+        assert_eq!(
+            allow(Method::Post, "/api/v0/workloads.list"),
+            Some(String::from("GET"))
+        );
+        assert_eq!(
+            allow(Method::Get, "/api/v0/workloads.create?fast=true"),
+            Some(String::from("POST"))
+        );
+        assert_eq!(
+            allow(Method::Get, "/api/v0/workloads/id"),
+            Some(String::from("DELETE, PUT"))
+        );
+        assert_eq!(
+            allow(Method::Delete, admin::READ_ONLY_PATH),
+            Some(String::from("GET, POST"))
+        );
+    }
+
+    /// Status and JSON body of the answer to a `POST`
+    fn post(
+        router: &Router,
+        connection: &Connection,
+        sender: &Sender<ApiChannel>,
+        path: &str,
+        body: &str,
+    ) -> (u16, serde_json::Value) {
+        let mut request = request(Method::Post, path, body).into();
+        let response = router.handle(&mut request, connection, sender).unwrap();
+        let status = response.status_code().0;
+        let body = serde_json::from_reader(response.into_reader()).unwrap_or_default();
+        (status, body)
+    }
+
+    #[rstest]
+    fn test_duplicate_create_conflicts(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+
+        let path = "/api/v0/workloads.create";
+        let (status, created) = post(&router, &connection, &sender, path, MANIFEST);
+        assert_eq!(status, 201);
+        let (status, conflict) = post(&router, &connection, &sender, path, MANIFEST);
+        assert_eq!(status, 409);
+        assert_eq!(conflict["code"], "AlreadyExists");
+        assert_eq!(conflict["id"], created["id"]);
+
+        let path = "/api/v0/volumes.create";
+        let body = r#"{"name": "data", "size_mb": 10, "namespace": "lab"}"#;
+        assert_eq!(post(&router, &connection, &sender, path, body).0, 200);
+        let (status, conflict) = post(&router, &connection, &sender, path, body);
+        assert_eq!(status, 409);
+        assert_eq!(conflict["code"], "AlreadyExists");
+        assert!(conflict["id"].is_string());
+        // Names are compared whole, not as prefixes
+        let body = r#"{"name": "dat", "size_mb": 10, "namespace": "lab"}"#;
+        assert_eq!(post(&router, &connection, &sender, path, body).0, 200);
+
+        let path = "/api/v0/tenants.create";
+        let body = r#"{"id": "", "name": "/tenant/acme", "value": "{}"}"#;
+        assert_eq!(post(&router, &connection, &sender, path, body).0, 201);
+        assert_eq!(post(&router, &connection, &sender, path, body).0, 409);
+
+        // Instances are stored by the core once created, as done here
+        let workload_id = created["id"].as_str().unwrap();
+        let instance = RikRepository::insert(&connection, "/instance/Pod/lab/web-0", "{}").unwrap();
+        let body = format!(
+            r#"{{"name": "web-0", "workload_id": "{}", "namespace": "lab"}}"#,
+            workload_id
+        );
+        let path = "/api/v0/instances.create";
+        let (status, conflict) = post(&router, &connection, &sender, path, &body);
+        assert_eq!(status, 409);
+        assert_eq!(conflict["id"], instance);
+    }
+
+    #[rstest]
+    fn test_errors_have_a_code(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+
+        let path = "/api/v0/tenants.create";
+        let (status, error) = post(&router, &connection, &sender, path, r#"{"name": "#);
+        assert_eq!(status, 400);
+        assert_eq!(error["code"], "InvalidBody");
+        assert_eq!(error["details"]["line"], 1);
+
+        let body = r#"{"id": "unknown"}"#;
+        for path in [
+            "/api/v0/workloads.delete",
+            "/api/v0/instances.delete",
+            "/api/v0/tenants.delete",
+            "/api/v0/volumes.delete",
+        ] {
+            let (status, error) = post(&router, &connection, &sender, path, body);
+            assert_eq!(status, 404);
+            assert_eq!(error["code"], "NotFound");
+            assert!(error["message"]
+                .as_str()
+                .unwrap()
+                .ends_with("id unknown not found"));
+        }
+
+        let path = "/api/v0/volumes.create";
+        let (status, error) = post(
+            &router,
+            &connection,
+            &sender,
+            path,
+            r#"{"name": "data", "size_mb": 0}"#,
+        );
+        assert_eq!((status, error["code"].as_str()), (400, Some("InvalidBody")));
+    }
+
+    #[rstest]
+    fn test_invalid_workload_fields(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let path = "/api/v0/workloads.create";
+
+        let body = r#"{"apiVersion": "v0", "kind": "banana", "name": "web", "spec": {}}"#;
+        let (status, invalid) = post(&router, &connection, &sender, path, body);
+        assert_eq!(status, 422);
+        assert_eq!(invalid["errors"][0]["field"], "kind");
+
+        let body = r#"{"apiVersion": "v0", "kind": "Pod", "name": "Web", "spec": {"containers": [
+            {"name": "web", "image": "nginx"},
+            {"name": "web", "image": "nginx", "ports": {"port": 0, "target_port": 80, "type": "NodePort"}}
+        ]}}"#;
+        let (status, invalid) = post(&router, &connection, &sender, path, body);
+        assert_eq!(status, 422);
+        let fields: Vec<&str> = invalid["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|error| error["field"].as_str())
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "name",
+                "spec.containers[1].name",
+                "spec.containers[1].ports.port"
+            ]
+        );
+
+        let body =
+            r#"{"apiVersion": "v0", "kind": "Pod", "name": "web", "spec": {"containers": []}}"#;
+        let (status, invalid) = post(&router, &connection, &sender, path, body);
+        assert_eq!(status, 422);
+        assert_eq!(invalid["errors"][0]["field"], "spec.containers");
+    }
+
+    #[rstest]
+    fn test_yaml_bodies(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let post_yaml = |path: &'static str, body: &'static str| {
+            let mut request = TestRequest::new()
+                .with_method(Method::Post)
+                .with_path(path)
+                .with_header(
+                    "Content-Type: application/yaml; charset=utf-8"
+                        .parse()
+                        .unwrap(),
+                )
+                .with_body(body)
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            let status = response.status_code().0;
+            let body: serde_json::Value =
+                serde_json::from_reader(response.into_reader()).unwrap_or_default();
+            (status, body)
+        };
+
+        let manifest = "apiVersion: v0
+kind: Pod
+name: web
+namespace: lab
+spec:
+  containers:
+    - name: web
+      image: nginx
+";
+        let (status, created) = post_yaml("/api/v0/workloads.create?fast=true", manifest);
+        assert_eq!(status, 201);
+        let id = created["id"].as_str().unwrap();
+        let workload = RikRepository::find_one(&connection, &id.to_string(), "/workload").unwrap();
+        assert_eq!(workload.value["spec"]["containers"][0]["image"], "nginx");
+
+        let (status, invalid) = post_yaml("/api/v0/workloads.create", "kind: [Pod");
+        assert_eq!(status, 422);
+        assert!(invalid["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("not valid YAML"));
+
+        let (status, error) = post_yaml("/api/v0/tenants.create", "name: [acme");
+        assert_eq!(status, 400);
+        assert_eq!(error["code"], "InvalidBody");
+        assert_eq!(error["details"]["line"], 1);
+    }
+
+    #[rstest]
+    fn test_body_too_large(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+
+        let body = " ".repeat(max_body_bytes() + 1);
+        for path in ["/api/v0/workloads.create", "/api/v0/instances.create"] {
+            let (status, error) = post(&router, &connection, &sender, path, &body);
+            assert_eq!(status, 413);
+            assert_eq!(error["code"], "PayloadTooLarge");
+        }
+    }
+
+    #[rstest]
+    fn test_instance_of_unknown_workload(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, receiver) = channel();
+        let router = Router::new();
+        let path = "/api/v0/instances.create";
+        let create = |workload_id: &str| {
+            let body = format!(
+                r#"{{"workload_id": "{}", "namespace": "lab"}}"#,
+                workload_id
+            );
+            post(&router, &connection, &sender, path, &body)
+        };
+
+        let workload_id = insert_workload(&connection);
+        assert_eq!(create(&workload_id).0, 201);
+        let notification = receiver.try_recv().unwrap();
+        assert!(matches!(notification.action, Crud::Create));
+        assert_eq!(notification.workload_id, Some(workload_id));
+
+        let volume_id = RikRepository::insert(&connection, "/volume/lab/data", "{}").unwrap();
+        for workload_id in ["unknown", volume_id.as_str()] {
+            let (status, error) = create(workload_id);
+            assert_eq!(status, 404);
+            assert_eq!(error["code"], "NotFound");
+        }
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[rstest]
+    fn test_instances_of_a_workload(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        for (name, workload_id) in [("web-1", "web"), ("api-1", "api"), ("web-2", "web")] {
+            let value = serde_json::json!({
+                "workload_id": workload_id,
+                "namespace": "lab",
+                "labels": { "app": workload_id, "env": "staging" }
+            });
+            let name = format!("/instance/Pod/lab/{}", name);
+            RikRepository::insert(&connection, &name, &value.to_string()).unwrap();
+        }
+        let list = |query: &str| {
+            let mut request = TestRequest::new()
+                .with_method(Method::Get)
+                .with_path(&format!("/api/v0/instances.list?{}", query))
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            assert_eq!(response.status_code().0, 200);
+            let body: serde_json::Value = serde_json::from_reader(response.into_reader()).unwrap();
+            body["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["name"].as_str().unwrap().to_string())
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(list("workload_id=web"), ["web-1", "web-2"]);
+        assert_eq!(list("workload_id=web&detail=full&limit=1"), ["web-1"]);
+        assert!(list("workload_id=unknown").is_empty());
+        assert_eq!(list("label_selector=app%3Dweb"), ["web-1", "web-2"]);
+        assert_eq!(list("label_selector=env%3Dstaging,app%3Dapi"), ["api-1"]);
+        assert!(list("label_selector=env%3Dprod").is_empty());
+
+        let path = "/api/v0/instances.list?label_selector=env%3Dstaging,app";
+        assert_eq!(
+            status(&router, &connection, &sender, Method::Get, path, ""),
+            Some(400)
+        );
+    }
+
+    #[rstest]
+    fn test_scale_workload(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, receiver) = channel();
+        let router = Router::new();
+        let path = "/api/v0/workloads.scale";
+        let workload_id = insert_workload(&connection);
+        let scale = |id: &str, replicas: i64| {
+            let body = format!(r#"{{"id": "{}", "replicas": {}}}"#, id, replicas);
+            post(&router, &connection, &sender, path, &body)
+        };
+
+        for replicas in [3, 0] {
+            let (status, scaled) = scale(&workload_id, replicas);
+            assert_eq!(status, 200);
+            assert_eq!(scaled["value"]["replicas"], replicas);
+            let stored = RikRepository::find_one(&connection, &workload_id, "/workload").unwrap();
+            assert_eq!(stored.value["replicas"], replicas);
+            assert_eq!(stored.value["spec"]["containers"][0]["image"], "nginx");
+            let notification = receiver.try_recv().unwrap();
+            assert!(matches!(notification.action, Crud::Scale));
+            assert_eq!(
+                notification.workload_id.as_deref(),
+                Some(workload_id.as_str())
+            );
+            let definition = notification.workload_definition.unwrap();
+            assert_eq!(definition.replicas, Some(replicas as u16));
+        }
+
+        let (status, error) = scale(&workload_id, -1);
+        assert_eq!(status, 400);
+        assert_eq!(error["code"], "InvalidBody");
+        let (status, error) = scale("unknown", 2);
+        assert_eq!(status, 404);
+        assert_eq!(error["code"], "NotFound");
+        let stored = RikRepository::find_one(&connection, &workload_id, "/workload").unwrap();
+        assert_eq!(stored.value["replicas"], 0);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[rstest]
+    fn test_replicas_of_the_workload(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, receiver) = channel();
+        let router = Router::new();
+        let manifest = MANIFEST.replace(r#""replicas": 1"#, r#""replicas": 3"#);
+        let workload_id =
+            RikRepository::insert(&connection, "/workload/Pod/lab/web", &manifest).unwrap();
+        let path = "/api/v0/instances.create";
+        let create = |body: String| post(&router, &connection, &sender, path, &body);
+
+        let (status, names) = create(format!(r#"{{"workload_id": "{}"}}"#, workload_id));
+        assert_eq!(status, 201);
+        let mut names: Vec<String> = serde_json::from_value(names).unwrap();
+        assert_eq!(names.len(), 3);
+        for name in &names {
+            let suffix = name.strip_prefix("web-").unwrap();
+            assert_eq!(suffix.len(), 5);
+            assert!(suffix
+                .bytes()
+                .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit()));
+            let notification = receiver.try_recv().unwrap();
+            assert_eq!(notification.instance_id.as_ref(), Some(name));
+        }
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), 3);
+
+        // The request and the name take precedence over the workload
+        let (_, names) = create(format!(
+            r#"{{"workload_id": "{}", "replicas": 2}}"#,
+            workload_id
+        ));
+        assert_eq!(names.as_array().unwrap().len(), 2);
+        let (_, names) = create(format!(
+            r#"{{"workload_id": "{}", "name": "single"}}"#,
+            workload_id
+        ));
+        assert_eq!(names, serde_json::json!(["single"]));
+    }
+
+    #[rstest]
+    fn test_concurrent_tenant_creates(db_connection: std::sync::Arc<RikDataBase>) {
+        let body = r#"{"id": "", "name": "/tenant/acme", "value": "{}"}"#;
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let db_connection = db_connection.clone();
+                std::thread::spawn(move || {
+                    let connection = db_connection.open().unwrap();
+                    let (sender, _receiver) = channel();
+                    let router = Router::new();
+                    let path = "/api/v0/tenants.create";
+                    post(&router, &connection, &sender, path, body).0
+                })
             })
+            .collect();
+        let mut statuses: Vec<u16> = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+        statuses.sort_unstable();
+        assert_eq!(statuses, [201, 409, 409, 409, 409, 409, 409, 409]);
+
+        let connection = db_connection.open().unwrap();
+        let tenants = RikRepository::find_all(&connection, "/tenant").unwrap();
+        assert_eq!(tenants.len(), 1);
+    }
+
+    #[rstest]
+    fn test_tenant_scoping(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, receiver) = channel();
+        let router = Router::new();
+        let acme = RikRepository::insert(&connection, "/tenant/acme", "{}").unwrap();
+        let other = RikRepository::insert(&connection, "/tenant/other", "{}").unwrap();
+        insert_workload(&connection);
+        let send = |method: Method, path: String, tenant: Option<&str>, body: String| {
+            let mut request = request(method, &path, &body);
+            if let Some(tenant) = tenant {
+                let header = format!("X-Rik-Tenant: {}", tenant);
+                request = request.with_header(header.parse().unwrap());
+            }
+            let response = router
+                .handle(&mut request.into(), &connection, &sender)
+                .unwrap();
+            let status = response.status_code().0;
+            let body: serde_json::Value =
+                serde_json::from_reader(response.into_reader()).unwrap_or_default();
+            (status, body)
+        };
+        let list = |path: &str, tenant: Option<&str>| {
+            let (_, body) = send(Method::Get, path.to_string(), tenant, String::new());
+            body["items"].as_array().unwrap().len()
+        };
+
+        let manifest = MANIFEST.replace(r#""web""#, r#""api""#);
+        let create = String::from("/api/v0/workloads.create");
+        let (status, created) = send(Method::Post, create.clone(), Some(&acme), manifest);
+        assert_eq!(status, 201);
+        let workload_id = created["id"].as_str().unwrap().to_string();
+        let stored = RikRepository::find_one(&connection, &workload_id, "/workload").unwrap();
+        assert_eq!(stored.name, format!("/workload/{}/Pod/lab/api", acme));
+        let (status, _) = send(Method::Post, create, Some("unknown"), MANIFEST.to_string());
+        assert_eq!(status, 404);
+
+        assert_eq!(list("/api/v0/workloads.list", None), 2);
+        assert_eq!(list("/api/v0/workloads.list", Some(&acme)), 1);
+        assert_eq!(list("/api/v0/workloads.list?kind=pod", Some(&other)), 0);
+        assert_eq!(list("/api/v0/workloads.list?kind=pod", None), 2);
+
+        // Instances belong to the tenant of their workload
+        let instances = String::from("/api/v0/instances.create");
+        let body = format!(r#"{{"workload_id": "{}"}}"#, workload_id);
+        let (status, _) = send(Method::Post, instances.clone(), Some(&other), body.clone());
+        assert_eq!(status, 404);
+        let (status, _) = send(Method::Post, instances, None, body);
+        assert_eq!(status, 201);
+        let notification = receiver.try_recv().unwrap();
+        assert_eq!(notification.tenant_id.as_deref(), Some(acme.as_str()));
+
+        let delete = |query: &str| {
+            let path = format!("/api/v0/tenants.delete{}", query);
+            send(Method::Post, path, None, format!(r#"{{"id": "{}"}}"#, acme))
+        };
+        let (status, error) = delete("");
+        assert_eq!(status, 409);
+        assert_eq!(error["code"], "TenantNotEmpty");
+        assert_eq!(delete("?force=true").0, 204);
+        assert!(RikRepository::find_one(&connection, &workload_id, "/workload").is_err());
+        assert_eq!(list("/api/v0/workloads.list", None), 1);
+    }
+
+    #[rstest]
+    fn test_bearer_token(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new().with_auth(ApiAuth::new(Some(String::from("secret"))));
+        let get = |path: &'static str, authorization: Option<&str>| {
+            let mut request = TestRequest::new().with_method(Method::Get).with_path(path);
+            if let Some(authorization) = authorization {
+                let header = format!("Authorization: {}", authorization);
+                request = request.with_header(header.parse().unwrap());
+            }
+            router
+                .handle(&mut request.into(), &connection, &sender)
+                .map(|response| response.status_code().0)
+        };
+
+        let path = "/api/v0/workloads.list";
+        assert_eq!(get(path, None), Some(401));
+        assert_eq!(get(path, Some("Bearer wrong")), Some(401));
+        assert_eq!(get(path, Some("Basic c2VjcmV0")), Some(401));
+        assert_eq!(get(path, Some("Bearer secret")), Some(200));
+        // Unknown routes are refused as well, the probes are not
+        assert_eq!(get("/api/v0/unknown", None), Some(401));
+        assert_eq!(get("/healthz", None), Some(200));
+
+        let open = Router::new().with_auth(ApiAuth::new(None));
+        let request = TestRequest::new().with_method(Method::Get).with_path(path);
+        let response = open.handle(&mut request.into(), &connection, &sender);
+        assert_eq!(response.map(|response| response.status_code().0), Some(200));
+    }
+
+    #[rstest]
+    fn test_tenant_api_keys(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new().with_auth(ApiAuth::new(Some(String::from("secret"))));
+        let send = |method: Method, path: &str, key: &str, body: String| {
+            let header = format!("Authorization: Bearer {}", key);
+            let mut request = request(method, path, &body)
+                .with_header(header.parse().unwrap())
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            let status = response.status_code().0;
+            let body: serde_json::Value =
+                serde_json::from_reader(response.into_reader()).unwrap_or_default();
+            (status, body)
+        };
+        let create_tenant = |name: &str| {
+            let body = format!(
+                r#"{{"id": "", "name": "/tenant/{}", "value": "{{}}"}}"#,
+                name
+            );
+            let (status, tenant) = send(Method::Post, "/api/v0/tenants.create", "secret", body);
+            assert_eq!(status, 201);
+            (
+                tenant["id"].as_str().unwrap().to_string(),
+                tenant["api_key"].as_str().unwrap().to_string(),
+            )
+        };
+        let (acme, acme_key) = create_tenant("acme");
+        let (_, other_key) = create_tenant("other");
+        let list = |key: &str| {
+            let (status, body) = send(Method::Get, "/api/v0/workloads.list", key, String::new());
+            assert_eq!(status, 200);
+            body["items"].as_array().unwrap().len()
+        };
+
+        // Created with a key, the workload is the one of its tenant
+        let path = "/api/v0/workloads.create";
+        let (status, workload) = send(Method::Post, path, &acme_key, MANIFEST.to_string());
+        assert_eq!(status, 201);
+        let id = workload["id"].as_str().unwrap().to_string();
+        let stored = RikRepository::find_one(&connection, &id, "/workload").unwrap();
+        assert_eq!(stored.value["tenant_id"], acme.as_str());
+        assert_eq!(
+            (list(&acme_key), list(&other_key), list("secret")),
+            (1, 0, 1)
+        );
+
+        // The workloads of other tenants cannot be deleted
+        let body = format!(r#"{{"id": "{}"}}"#, id);
+        let path = "/api/v0/workloads.delete";
+        assert_eq!(send(Method::Post, path, &other_key, body.clone()).0, 404);
+        assert_eq!(send(Method::Post, path, &acme_key, body).0, 204);
+
+        // Tenant keys cannot act on the whole cluster, nor be read back
+        let (status, error) = send(
+            Method::Get,
+            "/api/v0/tenants.list",
+            &acme_key,
+            String::new(),
+        );
+        assert_eq!((status, error["code"].as_str()), (403, Some("Forbidden")));
+        let (_, tenants) = send(Method::Get, "/api/v0/tenants.list", "secret", String::new());
+        assert!(tenants["items"][0]["value"].get(API_KEY_FIELD).is_none());
+
+        let body = format!(r#"{{"id": "{}"}}"#, acme);
+        let (status, rotated) = send(Method::Post, "/api/v0/tenants.rotate_key", "secret", body);
+        assert_eq!(status, 200);
+        let new_key = rotated["api_key"].as_str().unwrap();
+        assert_ne!(new_key, acme_key);
+        assert_eq!(list(new_key), 0);
+        let path = "/api/v0/workloads.list";
+        assert_eq!(send(Method::Get, path, &acme_key, String::new()).0, 401);
+    }
+
+    #[rstest]
+    fn test_tenant_keys_only_reach_their_tenant(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new().with_auth(ApiAuth::new(Some(String::from("secret"))));
+        let send = |method: Method, path: &str, key: &str, body: &str| {
+            let header = format!("Authorization: Bearer {}", key);
+            let mut request = request(method, path, body)
+                .with_header(header.parse().unwrap())
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            let status = response.status_code().0;
+            let body: serde_json::Value =
+                serde_json::from_reader(response.into_reader()).unwrap_or_default();
+            (status, body)
+        };
+        let create_tenant = |name: &str| {
+            let body = format!(
+                r#"{{"id": "", "name": "/tenant/{}", "value": "{{}}"}}"#,
+                name
+            );
+            let (_, tenant) = send(Method::Post, "/api/v0/tenants.create", "secret", &body);
+            (
+                tenant["id"].as_str().unwrap().to_string(),
+                tenant["api_key"].as_str().unwrap().to_string(),
+            )
+        };
+        let (acme, acme_key) = create_tenant("acme");
+        let (_, other_key) = create_tenant("other");
+        let (_, workload) = send(
+            Method::Post,
+            "/api/v0/workloads.create",
+            &acme_key,
+            MANIFEST,
+        );
+        let id = workload["id"].as_str().unwrap().to_string();
+
+        // The workloads of other tenants are not found
+        for path in ["workloads.get", "workloads.instances"] {
+            let path = format!("/api/v0/{}/{}", path, id);
+            assert_eq!(send(Method::Get, &path, &other_key, "").0, 404);
+            assert_ne!(send(Method::Get, &path, &acme_key, "").0, 404);
+        }
+        let body = format!(r#"{{"id": "{}", "replicas": 3}}"#, id);
+        let path = "/api/v0/workloads.scale";
+        assert_eq!(send(Method::Post, path, &other_key, &body).0, 404);
+        assert_eq!(send(Method::Post, path, &acme_key, &body).0, 200);
+
+        // Nor their events, or their names
+        let events = |key: &str| {
+            let (_, page) = send(Method::Get, "/api/v0/events.list", key, "");
+            page["events"].as_array().unwrap().len()
+        };
+        assert!(events(&acme_key) > 0);
+        assert_eq!(events(&other_key), 0);
+        assert_eq!(events("secret"), events(&acme_key));
+        let path = "/api/v0/search?kind=workload&q=we&namespace=lab";
+        let names = |key: &str| {
+            send(Method::Get, path, key, "").1["items"]
+                .as_array()
+                .unwrap()
+                .len()
+        };
+        assert_eq!(
+            (names(&acme_key), names(&other_key), names("secret")),
+            (1, 0, 1)
+        );
+
+        // Nor their volumes
+        let body = r#"{"name": "data", "size_mb": 64, "namespace": "lab"}"#;
+        let (_, volume) = send(Method::Post, "/api/v0/volumes.create", &acme_key, body);
+        let volume_id = volume["id"].as_str().unwrap().to_string();
+        let stored = RikRepository::find_one(&connection, &volume_id, "/volume").unwrap();
+        assert_eq!(stored.value["tenant_id"], acme.as_str());
+        let (status, error) = send(Method::Post, "/api/v0/volumes.create", &other_key, body);
+        assert_eq!((status, error.get("id")), (409, None));
+        let volumes = |key: &str| {
+            let (_, volumes) = send(Method::Get, "/api/v0/volumes.list", key, "");
+            volumes.as_array().unwrap().len()
+        };
+        assert_eq!((volumes(&acme_key), volumes(&other_key)), (1, 0));
+        let body = format!(r#"{{"id": "{}"}}"#, volume_id);
+        assert_eq!(
+            send(Method::Post, "/api/v0/volumes.delete", &other_key, &body).0,
+            404
+        );
+
+        // The usage is the one of the tenant of the key
+        let path = format!("/api/v0/usage?tenant={}", acme);
+        assert_eq!(send(Method::Get, &path, &acme_key, "").0, 200);
+        let (status, error) = send(Method::Get, &path, &other_key, "");
+        assert_eq!(
+            (status, error["errors"][0]["field"].as_str()),
+            (400, Some("tenant"))
+        );
+
+        // The routes acting on the whole cluster are flagged in the route table
+        for path in ["/api/v0/metrics", "/metrics", "/api/v0/nodes.list"] {
+            assert_eq!(send(Method::Get, path, &acme_key, "").0, 403);
+        }
+    }
+
+    #[rstest]
+    fn test_discovery_of_a_tenant_key(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new().with_auth(ApiAuth::new(Some(String::from("secret"))));
+        let send = |path: &str, key: &str, body: &str| {
+            let mut request = request(Method::Post, path, body)
+                .with_header(format!("Authorization: Bearer {}", key).parse().unwrap())
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            serde_json::from_reader::<_, serde_json::Value>(response.into_reader()).unwrap()
+        };
+        let keys = ["acme", "other"].map(|name| {
+            let body = format!(
+                r#"{{"id": "", "name": "/tenant/{}", "value": "{{}}"}}"#,
+                name
+            );
+            let tenant = send("/api/v0/tenants.create", "secret", &body);
+            tenant["api_key"].as_str().unwrap().to_string()
+        });
+        send("/api/v0/workloads.create", &keys[0], MANIFEST);
+        let discover = |key: &str| {
+            let mut request = request(Method::Get, "/api/v0/discovery/web", "")
+                .with_header(format!("Authorization: Bearer {}", key).parse().unwrap())
+                .with_header("X-Rik-Namespace: lab".parse().unwrap())
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            response.status_code().0
+        };
+
+        // The workload of another tenant is not found, even by name
+        assert_eq!(discover(&keys[0]), 200);
+        assert_eq!(discover(&keys[1]), 404);
+        // Nor is it taken for the workload of the same name of the tenant
+        send("/api/v0/workloads.create", &keys[1], MANIFEST);
+        assert_eq!(discover(&keys[1]), 200);
+    }
+
+    #[rstest]
+    fn test_default_namespace_of_a_tenant_key(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new().with_auth(ApiAuth::new(Some(String::from("secret"))));
+        let send = |path: &str, key: &str, namespace: Option<&str>, body: &str| {
+            let mut request = request(Method::Post, path, body)
+                .with_header(format!("Authorization: Bearer {}", key).parse().unwrap());
+            if let Some(namespace) = namespace {
+                let header = format!("X-Rik-Namespace: {}", namespace);
+                request = request.with_header(header.parse().unwrap());
+            }
+            let response = router
+                .handle(&mut request.into(), &connection, &sender)
+                .unwrap();
+            let status = response.status_code().0;
+            let body: serde_json::Value =
+                serde_json::from_reader(response.into_reader()).unwrap_or_default();
+            (status, body)
+        };
+        let tenant = |name: &str, value: &str| {
+            let body =
+                serde_json::json!({"id": "", "name": format!("/tenant/{}", name), "value": value});
+            send("/api/v0/tenants.create", "secret", None, &body.to_string())
+        };
+        let (status, error) = tenant("broken", r#"{"default_namespace": "a/b"}"#);
+        assert_eq!((status, error["code"].as_str()), (400, Some("InvalidBody")));
+        let (status, created) = tenant("acme", r#"{"default_namespace": "team"}"#);
+        assert_eq!(status, 201);
+        let key = created["api_key"].as_str().unwrap();
+        let manifest = MANIFEST.replace(r#""namespace": "lab", "#, "");
+
+        // The namespace of the key applies when the manifest gives none
+        let (status, workload) = send("/api/v0/workloads.create", key, None, &manifest);
+        assert_eq!(status, 201);
+        assert_eq!(workload["namespace"], "team");
+        // The one sent by the client must be the same
+        let path = "/api/v0/workloads.create";
+        assert_eq!(send(path, key, Some("lab"), &manifest).0, 400);
+        // The one of the manifest wins
+        let (status, workload) = send(path, key, Some("team"), MANIFEST);
+        assert_eq!((status, workload["namespace"].as_str()), (201, Some("lab")));
+        // The token of the API has no default namespace
+        let (status, workload) = send(path, "secret", None, &manifest);
+        assert_eq!(
+            (status, workload["namespace"].as_str()),
+            (201, Some("default"))
+        );
+    }
+
+    #[rstest]
+    fn test_healthz(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        // Answered without the core, nor a token
+        let (sender, receiver) = channel();
+        drop(receiver);
+        let router = Router::new().with_auth(ApiAuth::new(Some(String::from("secret"))));
+        let request = TestRequest::new()
+            .with_method(Method::Get)
+            .with_path("/healthz");
+        let response = router
+            .handle(&mut request.into(), &connection, &sender)
+            .unwrap();
+        assert_eq!(response.status_code().0, 200);
+        let body: serde_json::Value = serde_json::from_reader(response.into_reader()).unwrap();
+        assert_eq!(body, serde_json::json!({ "status": "ok" }));
+    }
+
+    #[rstest]
+    fn test_readyz(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let router = Router::new();
+        let readyz = |connection: &rusqlite::Connection, sender: &Sender<ApiChannel>| {
+            let request = TestRequest::new()
+                .with_method(Method::Get)
+                .with_path("/readyz");
+            let response = router
+                .handle(&mut request.into(), connection, sender)
+                .unwrap();
+            let status = response.status_code().0;
+            let body: serde_json::Value = serde_json::from_reader(response.into_reader()).unwrap();
+            (status, body)
+        };
+
+        let (sender, receiver) = channel();
+        let (status, body) = readyz(&connection, &sender);
+        assert_eq!((status, body["status"].as_str()), (200, Some("ready")));
+        assert!(matches!(receiver.try_recv().unwrap().action, Crud::Ping));
+
+        // Without the tables, nor the core
+        drop(receiver);
+        let (status, body) = readyz(&rusqlite::Connection::open_in_memory().unwrap(), &sender);
+        assert_eq!(status, 503);
+        let failing: Vec<&str> = body["failing"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|check| check["check"].as_str().unwrap())
+            .collect();
+        assert_eq!(failing, ["database", "core_channel"]);
+    }
+
+    #[rstest]
+    fn test_prometheus_metrics(db_connection: std::sync::Arc<RikDataBase>) {
+        use std::io::Read;
+
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        insert_workload(&connection);
+        let get = |router: &Router, path: &'static str| {
+            let request = TestRequest::new().with_method(Method::Get).with_path(path);
+            let response = router
+                .handle(&mut request.into(), &connection, &sender)
+                .unwrap();
+            let mut body = String::new();
+            response.into_reader().read_to_string(&mut body).unwrap();
+            body
+        };
+        // Counted with the parameters of the route, not a label per id
+        get(
+            &Router::new(),
+            "/api/v0/workloads.get/7b0f5c1e-3d2a-4c8e-9f61-2a5d8e4b9c30",
+        );
+
+        // Other threads have routers of their own
+        let metrics = get(&Router::new(), "/metrics");
+        let requests = metrics
+            .lines()
+            .find(|line| {
+                line.starts_with(
+                    r#"rik_http_requests_total{route="/api/v0/workloads.get/:workloadid",method="GET",status="404"}"#,
+                )
+            })
+            .and_then(|line| line.rsplit(' ').next()?.parse::<u64>().ok());
+        assert!(requests >= Some(1));
+        assert!(!metrics.contains("7b0f5c1e-3d2a-4c8e-9f61-2a5d8e4b9c30"));
+        assert!(metrics.contains("\nrik_workloads 1\n"));
+        assert!(metrics.contains("\nrik_tenants 0\n"));
+    }
+
+    #[rstest]
+    fn test_cors(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new()
+            .with_auth(ApiAuth::new(Some(String::from("secret"))))
+            .with_cors(Cors::parse("https://ui.example.com"));
+        let send = |method: Method, path: &'static str, origin: &'static str| {
+            let mut request = TestRequest::new()
+                .with_method(method)
+                .with_path(path)
+                .with_header(tiny_http::Header::from_str(origin).unwrap())
+                .into();
+            router.handle(&mut request, &connection, &sender)
+        };
+        let header = |response: &tiny_http::Response<io::Cursor<Vec<u8>>>, field: &'static str| {
+            response
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv(field))
+                .map(|header| header.value.to_string())
+        };
+
+        // Preflights are answered without the token
+        let preflight = send(
+            Method::Options,
+            "/api/v0/workloads/7b8f3d2e-1c4a-4e5b-9f6a-0d1e2f3a4b5c",
+            "Origin: https://ui.example.com",
+        )
+        .unwrap();
+        assert_eq!(preflight.status_code().0, 204);
+        assert_eq!(
+            header(&preflight, "Allow").as_deref(),
+            Some("DELETE, PUT, OPTIONS")
+        );
+        assert_eq!(
+            header(&preflight, "Access-Control-Allow-Origin").as_deref(),
+            Some("https://ui.example.com")
+        );
+        assert_eq!(
+            header(&preflight, "Access-Control-Allow-Methods").as_deref(),
+            Some("DELETE, PUT")
+        );
+        assert!(header(&preflight, "Access-Control-Allow-Headers")
+            .unwrap()
+            .contains("Authorization"));
+        assert!(send(
+            Method::Options,
+            "/api/v0/unknown",
+            "Origin: https://ui.example.com"
+        )
+        .is_none());
+
+        // Other answers carry the headers too, errors included
+        let refused = send(
+            Method::Get,
+            "/api/v0/workloads.list",
+            "Origin: https://ui.example.com",
+        )
+        .unwrap();
+        assert_eq!(refused.status_code().0, 401);
+        assert_eq!(
+            header(&refused, "Access-Control-Allow-Origin").as_deref(),
+            Some("https://ui.example.com")
+        );
+        assert_eq!(header(&refused, "Vary").as_deref(), Some("Origin"));
+
+        // Other origins get no header, browsers refuse the answer
+        let other = send(
+            Method::Options,
+            "/api/v0/workloads.list",
+            "Origin: https://evil.example.com",
+        )
+        .unwrap();
+        assert_eq!(other.status_code().0, 204);
+        assert_eq!(header(&other, "Access-Control-Allow-Origin"), None);
+
+        let router = Router::new().with_cors(Cors::parse("*"));
+        let mut request = TestRequest::new()
+            .with_method(Method::Get)
+            .with_path("/healthz")
+            .with_header(tiny_http::Header::from_str("Origin: http://localhost:3000").unwrap())
+            .into();
+        let response = router.handle(&mut request, &connection, &sender).unwrap();
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Origin").as_deref(),
+            Some("*")
+        );
+        assert_eq!(header(&response, "Vary"), None);
+    }
+
+    #[rstest]
+    fn test_usage_parameters(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let usage = |query: &str| {
+            let path = format!("/api/v0/usage?{}", query);
+            let mut request = request(Method::Get, &path, "").into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            let status = response.status_code().0;
+            let body: serde_json::Value = serde_json::from_reader(response.into_reader()).unwrap();
+            (status, body)
+        };
+
+        for (query, field) in [
+            ("from=yesterday", "from"),
+            ("to=2023-06-01", "to"),
+            ("group_by=node", "group_by"),
+            ("from=2023-06-02T00:00:00Z&to=2023-06-01T00:00:00Z", "from"),
+        ] {
+            let (code, invalid) = usage(query);
+            assert_eq!(code, 400, "{}", query);
+            assert_eq!(invalid["errors"][0]["field"], field, "{}", query);
+        }
+
+        connection
+            .execute_batch("DROP TABLE instance_usage")
+            .unwrap();
+        let (code, error) = usage("from=2001-01-01T00:00:00Z&to=2001-01-02T00:00:00Z");
+        assert_eq!((code, error["code"].as_str()), (500, Some("Internal")));
+    }
+
+    #[rstest]
+    fn test_instances_watch(db_connection: std::sync::Arc<RikDataBase>) {
+        use std::io::Read;
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let watch = |query: String| {
+            let mut request = TestRequest::new()
+                .with_method(Method::Get)
+                .with_path(&format!("/api/v0/instances.watch?{}", query))
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            assert_eq!(response.status_code().0, 200);
+            let mut body = String::new();
+            response.into_reader().read_to_string(&mut body).unwrap();
+            serde_json::from_str::<serde_json::Value>(&body).unwrap()
+        };
+        RikRepository::insert(&connection, "/workload/Pod/lab/web", MANIFEST).unwrap();
+        let id = RikRepository::insert(&connection, "/instance/Pod/lab/web-1", "{}").unwrap();
+
+        let listed = watch(String::from("timeout=0"));
+        assert_eq!(listed["changes"].as_array().unwrap().len(), 1);
+        assert_eq!(listed["changes"][0]["type"], "put");
+        assert_eq!(listed["changes"][0]["element"]["name"], "web-1");
+        let revision = listed["revision"].as_i64().unwrap();
+
+        // Nothing happened in time
+        let idle = watch(format!("since={}&timeout=0", revision));
+        assert_eq!(idle["changes"], serde_json::json!([]));
+        assert_eq!(idle["revision"], revision);
+
+        // A change wakes the watch up
+        let writer = {
+            let db = db_connection.clone();
+            let id = id.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                RikRepository::delete(&db.open().unwrap(), &id).unwrap();
+            })
+        };
+        let started = std::time::Instant::now();
+        let changed = watch(format!("since={}&timeout=10", revision));
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        writer.join().unwrap();
+        assert_eq!(changed["changes"][0]["type"], "deleted");
+        assert_eq!(changed["changes"][0]["element"]["id"], id.as_str());
+        assert_eq!(changed["revision"], revision + 1);
+
+        assert_eq!(
+            status(
+                &router,
+                &connection,
+                &sender,
+                Method::Get,
+                "/api/v0/instances.watch?since=-1",
+                ""
+            ),
+            Some(400)
+        );
+    }
+
+    #[rstest]
+    fn test_create_bulk(db_connection: std::sync::Arc<RikDataBase>) {
+        use std::io::Read;
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let manifest = |name: &str| MANIFEST.replace("\"web\"", &format!("\"{}\"", name));
+        let create = |body: String| {
+            let mut request = request(Method::Post, "/api/v0/workloads.create_bulk", &body).into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            let status = response.status_code().0;
+            let mut body = String::new();
+            response.into_reader().read_to_string(&mut body).unwrap();
+            (
+                status,
+                serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            )
+        };
+        let count = || RikRepository::count(&connection, "/workload/").unwrap();
+
+        let (status, body) = create(format!("[{}, {}]", manifest("api"), manifest("front")));
+        assert_eq!(status, 200);
+        let ids: Vec<&str> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|id| id["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            RikRepository::find_one(&connection, &ids[1].to_string(), "/workload")
+                .unwrap()
+                .name,
+            "/workload/Pod/lab/front"
+        );
+
+        // A name already used rolls the whole batch back
+        let (status, body) = create(format!("[{}, {}]", manifest("db"), manifest("front")));
+        assert_eq!(status, 409);
+        assert_eq!(body["details"]["index"], 1);
+        assert_eq!(body["id"], ids[1]);
+        assert_eq!(count(), 2);
+
+        let (status, body) = create(format!("[{}, {}]", manifest("db"), manifest("db")));
+        assert_eq!(status, 409);
+        assert_eq!(body["details"]["index"], 1);
+        assert_eq!(count(), 2);
+
+        // Every definition is checked before any is inserted
+        let (status, body) = create(format!("[{}, {{\"kind\": \"Pod\"}}]", manifest("db")));
+        assert_eq!(status, 422);
+        assert!(body["errors"][0]["field"]
+            .as_str()
+            .unwrap()
+            .starts_with("[1]"));
+        assert_eq!(count(), 2);
+        assert_eq!(create(String::from("[]")).0, 422);
+        assert_eq!(create(manifest("db")).0, 422);
+    }
+
+    #[rstest]
+    fn test_dry_run(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, receiver) = channel();
+        let router = Router::new();
+        let dry_run =
+            |path: &'static str, body: String| post(&router, &connection, &sender, path, &body);
+        let count = |prefix| RikRepository::count(&connection, prefix).unwrap();
+
+        let (code, body) = dry_run(
+            "/api/v0/workloads.create?dry_run=true",
+            MANIFEST.to_string(),
+        );
+        assert_eq!(code, 200);
+        assert_eq!(body, serde_json::json!({"id": "0", "dry_run": true}));
+        assert_eq!(count("/workload/"), 0);
+
+        // The checks of the real call still apply
+        let id = insert_workload(&connection);
+        let path = "/api/v0/workloads.create?dry_run=true";
+        assert_eq!(dry_run(path, MANIFEST.to_string()).0, 409);
+        let invalid = MANIFEST.replace("\"web\"", "\"Web\"");
+        assert_eq!(dry_run(path, invalid).0, 422);
+
+        let (code, body) = dry_run(
+            "/api/v0/instances.create?dry_run=true",
+            format!(r#"{{"workload_id": "{}", "replicas": 2}}"#, id),
+        );
+        assert_eq!(code, 201);
+        assert_eq!(body["instances"].as_array().unwrap().len(), 2);
+        assert_eq!(body["dry_run"], true);
+        let (code, _) = dry_run(
+            "/api/v0/instances.create?dry_run=true",
+            String::from(r#"{"workload_id": "unknown"}"#),
+        );
+        assert_eq!(code, 404);
+
+        let (code, body) = dry_run(
+            "/api/v0/workloads.delete?dry_run=true",
+            format!(r#"{{"id": "{}"}}"#, id),
+        );
+        assert_eq!(code, 200);
+        assert_eq!(body, serde_json::json!({"id": id, "dry_run": true}));
+        let path = format!("/api/v0/workloads/{}?dry_run=true", id);
+        assert_eq!(
+            status(&router, &connection, &sender, Method::Delete, &path, ""),
+            Some(200)
+        );
+        assert_eq!(count("/workload/"), 1);
+
+        let instance = RikRepository::insert(
+            &connection,
+            "/instance/Pod/lab/web-0",
+            &format!(r#"{{"workload_id": "{}"}}"#, id),
+        )
+        .unwrap();
+        let (code, body) = dry_run(
+            "/api/v0/instances.delete?dry_run=true",
+            format!(r#"{{"id": "{}"}}"#, instance),
+        );
+        assert_eq!(code, 200);
+        assert_eq!(body["id"], instance);
+        assert_eq!(count("/instance/"), 1);
+
+        // Nothing was sent to the core
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[rstest]
+    fn test_list_order(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let mut ids = std::collections::HashMap::new();
+        for name in ["c", "a", "b"] {
+            let manifest = MANIFEST.replace("\"web\"", &format!("\"{}\"", name));
+            let id = RikRepository::insert(
+                &connection,
+                &format!("/workload/Pod/lab/{}", name),
+                &manifest,
+            )
+            .unwrap();
+            ids.insert(name, id);
+        }
+        RikRepository::delete(&connection, &ids["a"]).unwrap();
+        let manifest = MANIFEST.replace("\"web\"", "\"d\"");
+        ids.insert(
+            "d",
+            RikRepository::insert(&connection, "/workload/Pod/lab/d", &manifest).unwrap(),
+        );
+        let list = |query: &str| {
+            let mut request = TestRequest::new()
+                .with_method(Method::Get)
+                .with_path(&format!("/api/v0/workloads.list?{}", query))
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            assert_eq!(response.status_code().0, 200);
+            let body: serde_json::Value = serde_json::from_reader(response.into_reader()).unwrap();
+            body["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["name"].as_str().unwrap().to_string())
+                .collect::<Vec<String>>()
+        };
+
+        // Read a page at a time by the database, or all at once when filtered
+        assert_eq!(list(""), ["c", "b", "d"]);
+        assert_eq!(list("namespace=lab"), ["c", "b", "d"]);
+        assert_eq!(list("sort=name"), ["b", "c", "d"]);
+        assert_eq!(list("sort=name&order=desc"), ["d", "c", "b"]);
+        let mut by_id = vec!["b", "c", "d"];
+        by_id.sort_by_key(|name| ids[name].clone());
+        assert_eq!(list("sort=id"), by_id);
+        by_id.reverse();
+        assert_eq!(list("sort=id&order=desc"), by_id);
+        assert_eq!(list("sort=created_at"), ["c", "b", "d"]);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        RikRepository::update(
+            &connection,
+            &ids["c"],
+            &MANIFEST.replace("\"web\"", "\"c\""),
+        )
+        .unwrap();
+        assert_eq!(list("sort=updated_at")[2], "c");
+
+        for query in ["sort=size", "sort=name&order=random"] {
+            let path = format!("/api/v0/workloads.list?{}", query);
+            assert_eq!(
+                status(&router, &connection, &sender, Method::Get, &path, ""),
+                Some(400)
+            );
+        }
+    }
+
+    #[rstest]
+    fn test_workload_by_name(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let get = |query: &str| {
+            let mut request = TestRequest::new()
+                .with_method(Method::Get)
+                .with_path(&format!("/api/v0/workloads.get?{}", query))
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            let status = response.status_code().0;
+            let body = serde_json::from_reader(response.into_reader()).unwrap_or_default();
+            (status, body)
+        };
+
+        let id = insert_workload(&connection);
+        let (code, workload): (u16, serde_json::Value) = get("name=web&namespace=lab");
+        assert_eq!(code, 200);
+        assert_eq!(workload["id"], id);
+        assert_eq!(workload["value"]["name"], "web");
+        assert_eq!(get("name=web&namespace=default").0, 404);
+        assert_eq!(get("name=we&namespace=lab").0, 404);
+        assert_eq!(get("namespace=lab").0, 400);
+
+        // Databases from before the names were unique may hold duplicates
+        let duplicate = insert_workload(&connection);
+        let (code, conflict) = get("name=web&namespace=lab");
+        assert_eq!(code, 409);
+        assert_eq!(conflict["code"], "AmbiguousName");
+        assert_eq!(
+            conflict["details"]["ids"],
+            serde_json::json!([id, duplicate])
+        );
+        let path = "/api/v0/workloads.delete";
+        let body = r#"{"name": "web", "namespace": "lab"}"#;
+        assert_eq!(post(&router, &connection, &sender, path, body).0, 409);
+
+        RikRepository::delete(&connection, &duplicate).unwrap();
+        assert_eq!(post(&router, &connection, &sender, path, body).0, 204);
+        assert_eq!(post(&router, &connection, &sender, path, body).0, 404);
+        let body = r#"{"id": "x", "name": "web"}"#;
+        assert_eq!(post(&router, &connection, &sender, path, body).0, 422);
+        let body = r#"{"id": "x", "namespace": "lab"}"#;
+        assert_eq!(post(&router, &connection, &sender, path, body).0, 422);
+    }
+
+    #[rstest]
+    fn test_delete_workload_with_instances(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, receiver) = channel();
+        let router = Router::new();
+        let id = insert_workload(&connection);
+        let instances: Vec<String> = ["web-1", "web-2"]
+            .iter()
+            .map(|name| {
+                let value = serde_json::json!({ "workload_id": id, "namespace": "lab" });
+                let name = format!("/instance/Pod/lab/{}", name);
+                RikRepository::insert(&connection, &name, &value.to_string()).unwrap()
+            })
+            .collect();
+        let body = format!(r#"{{"id": "{}"}}"#, id);
+
+        let (code, conflict) = post(
+            &router,
+            &connection,
+            &sender,
+            "/api/v0/workloads.delete",
+            &body,
+        );
+        assert_eq!(code, 409);
+        assert_eq!(conflict["code"], "WorkloadHasInstances");
+        assert_eq!(conflict["details"]["count"], 2);
+        assert_eq!(
+            conflict["details"]["instance_ids"],
+            serde_json::json!(instances)
+        );
+        assert!(RikRepository::find_one(&connection, &id, "/workload").is_ok());
+
+        let path = "/api/v0/workloads.delete?force=true";
+        assert_eq!(post(&router, &connection, &sender, path, &body).0, 204);
+        let deleted: Vec<String> = receiver
+            .try_iter()
+            .map(|message| message.instance_id.unwrap())
+            .collect();
+        assert_eq!(deleted, instances);
+        assert!(RikRepository::find_one(&connection, &id, "/workload").is_err());
+    }
+
+    #[rstest]
+    fn test_delete_collection_of_workloads_with_instances(
+        db_connection: std::sync::Arc<RikDataBase>,
+    ) {
+        let connection = db_connection.open().unwrap();
+        let (sender, receiver) = channel();
+        let router = Router::new();
+        let web = insert_workload(&connection);
+        let api = RikRepository::insert(
+            &connection,
+            "/workload/Pod/lab/api",
+            &MANIFEST.replace(r#""web""#, r#""api""#),
+        )
+        .unwrap();
+        let value = serde_json::json!({ "workload_id": web, "namespace": "lab" });
+        let instance =
+            RikRepository::insert(&connection, "/instance/Pod/lab/web-1", &value.to_string())
+                .unwrap();
+        let body = format!(r#"{{"ids": ["{}", "{}"]}}"#, web, api);
+        let statuses = |results: &serde_json::Value| -> Vec<String> {
+            results["results"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|result| result["status"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let path = "/api/v0/workloads.delete_collection";
+        let (code, results) = post(&router, &connection, &sender, path, &body);
+        assert_eq!(code, 409);
+        assert_eq!(statuses(&results), ["has_instances", "deleted"]);
+        assert!(results["results"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("?force=true"));
+        assert!(RikRepository::find_one(&connection, &web, "/workload").is_ok());
+        assert!(receiver.try_recv().is_err());
+
+        let path = "/api/v0/workloads.delete_collection?force=true";
+        let body = format!(r#"{{"ids": ["{}"]}}"#, web);
+        let (code, results) = post(&router, &connection, &sender, path, &body);
+        assert_eq!(code, 200);
+        assert_eq!(statuses(&results), ["deleted"]);
+        assert_eq!(receiver.try_recv().unwrap().instance_id, Some(instance));
+    }
+
+    #[rstest]
+    fn test_node_detail(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let get = |id: &str| {
+            let mut request = TestRequest::new()
+                .with_method(Method::Get)
+                .with_path(&format!("/api/v0/nodes.get/{}", id))
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            let status = response.status_code().0;
+            let body = serde_json::from_reader(response.into_reader()).unwrap_or_default();
+            (status, body)
+        };
+        let worker = serde_json::json!({
+            "address": "10.0.0.12:4995",
+            "last_heartbeat": chrono::Utc::now().to_rfc3339(),
+            "capacity": { "cpu_cores": 4, "memory_bytes": 8589934592u64 }
+        });
+        RikRepository::upsert(
+            &connection,
+            &String::from("worker-1"),
+            &String::from("/worker/any/worker-1"),
+            &worker.to_string(),
+            "/worker",
+        )
+        .unwrap();
+
+        let (code, node): (u16, serde_json::Value) = get("worker-1");
+        assert_eq!(code, 200);
+        assert_eq!(node["name"], "worker-1");
+        assert_eq!(node["value"]["status"], "Ready");
+        assert_eq!(node["value"]["capacity"]["cpu_cores"], 4);
+        assert_eq!(node["value"]["instance_count"], 0);
+        assert_eq!(node["value"]["instance_ids"], serde_json::json!([]));
+
+        let value = serde_json::json!({ "workload_id": "web", "worker_id": "worker-1" });
+        let instance =
+            RikRepository::insert(&connection, "/instance/Pod/lab/web-1", &value.to_string())
+                .unwrap();
+        let value = serde_json::json!({ "workload_id": "web", "worker_id": "worker-2" });
+        RikRepository::insert(&connection, "/instance/Pod/lab/web-2", &value.to_string()).unwrap();
+        let (_, node) = get("worker-1");
+        assert_eq!(node["value"]["instance_count"], 1);
+        assert_eq!(node["value"]["instance_ids"], serde_json::json!([instance]));
+
+        assert_eq!(get("worker-2").0, 404);
+    }
+
+    #[rstest]
+    fn test_instance_logs(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let logs = |path: String| {
+            let mut request = TestRequest::new()
+                .with_method(Method::Get)
+                .with_path(&path)
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            let status = response.status_code().0;
+            let body = serde_json::from_reader(response.into_reader()).unwrap_or_default();
+            (status, body)
+        };
+        let pending = serde_json::json!({ "workload_id": "web", "namespace": "lab" });
+        let pending =
+            RikRepository::insert(&connection, "/instance/Pod/lab/web-1", &pending.to_string())
+                .unwrap();
+        let placed = serde_json::json!({ "workload_id": "web", "worker_id": "worker-1" });
+        let placed =
+            RikRepository::insert(&connection, "/instance/Pod/lab/web-2", &placed.to_string())
+                .unwrap();
+        RikRepository::upsert(
+            &connection,
+            &String::from("worker-1"),
+            &String::from("/worker/any/worker-1"),
+            &String::from(r#"{"address": "unknown"}"#),
+            "/worker",
+        )
+        .unwrap();
+
+        assert_eq!(logs(String::from("/api/v0/instances.logs/unknown")).0, 404);
+        let (code, error): (u16, serde_json::Value) =
+            logs(format!("/api/v0/instances.logs/{}", pending));
+        assert_eq!(code, 409);
+        assert_eq!(error["code"], "NotScheduled");
+        let (code, error) = logs(format!("/api/v0/instances.logs/{}?tail=20", placed));
+        assert_eq!(code, 502);
+        assert_eq!(error["code"], "NodeUnreachable");
+        assert_eq!(error["details"]["node"], "worker-1");
+        for tail in ["0", "all", "1001"] {
+            let path = format!("/api/v0/instances.logs/{}?tail={}", placed, tail);
+            assert_eq!(logs(path).0, 400);
+        }
+    }
+
+    #[rstest]
+    fn test_v1_routes(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let call = |method: Method, path: String, body: &'static str| {
+            let mut request = TestRequest::new()
+                .with_method(method)
+                .with_path(&path)
+                .with_body(body)
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            let status = response.status_code().0;
+            let body = serde_json::from_reader(response.into_reader()).unwrap_or_default();
+            (status, body)
+        };
+
+        let (code, created): (u16, serde_json::Value) =
+            call(Method::Post, String::from("/api/v1/workloads"), MANIFEST);
+        assert_eq!(code, 201);
+        let id = created["id"].as_str().unwrap().to_string();
+        let (code, page) = call(Method::Get, String::from("/api/v1/workloads"), "");
+        assert_eq!(code, 200);
+        assert_eq!(page["items"][0]["id"], id.as_str());
+        let (code, workload) = call(Method::Get, format!("/api/v1/workloads/{}", id), "");
+        assert_eq!(code, 200);
+        assert_eq!(workload["name"], "web");
+
+        let instance = RikRepository::insert(
+            &connection,
+            "/instance/Pod/lab/web-1",
+            &serde_json::json!({ "workload_id": id, "id": "web-1" }).to_string(),
+        )
+        .unwrap();
+        let (code, page) = call(Method::Get, String::from("/api/v1/instances"), "");
+        assert_eq!(code, 200);
+        assert_eq!(page["items"][0]["id"], instance.as_str());
+        let (code, _) = call(Method::Get, format!("/api/v1/instances/{}", instance), "");
+        assert_eq!(code, 200);
+
+        // Errors of v1 name their version, the ones of v0 are unchanged
+        let (code, error) = call(Method::Delete, format!("/api/v1/workloads/{}", id), "");
+        assert_eq!(code, 409);
+        assert_eq!(error["code"], "WorkloadHasInstances");
+        assert_eq!(error["version"], "v1");
+        let (code, error) = call(Method::Delete, format!("/api/v0/workloads/{}", id), "");
+        assert_eq!(code, 409);
+        assert_eq!(error.get("version"), None);
+        let (code, error) = call(Method::Get, String::from("/api/v1/instances/unknown"), "");
+        assert_eq!(code, 404);
+        assert_eq!(error["version"], "v1");
+
+        let (code, _) = call(
+            Method::Delete,
+            format!("/api/v1/workloads/{}?force=true", id),
+            "",
+        );
+        assert_eq!(code, 204);
+        let (code, _) = call(Method::Get, format!("/api/v1/workloads/{}", id), "");
+        assert_eq!(code, 404);
+    }
+
+    #[rstest]
+    fn test_route_matching(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let get = |path: &str| status(&router, &connection, &sender, Method::Get, path, "");
+
+        let id = insert_workload(&connection);
+        for path in [
+            "/api/v0/workloads.list?foo=bar",
+            "/api/v0/workloads.list/",
+            "/api/v0/workloads.list/?limit=1",
+            "/api/v1/workloads/",
+        ] {
+            assert_eq!(get(path), Some(200), "{}", path);
+        }
+        assert_eq!(get(&format!("/api/v0/workloads.get/{}/", id)), Some(200));
+        assert_eq!(get("/api/v0/workloads.list//"), None);
+
+        // Path parameters are given decoded to the handlers
+        assert_eq!(get("/api/v0/examples/p%6Fd"), Some(200));
+        assert_eq!(get("/api/v0/examples/%zz"), Some(400));
+        assert_eq!(get("/api/v0/examples/po%64+"), Some(404));
+    }
+
+    #[rstest]
+    fn test_errors_are_json(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let error = |method: Method, path: &str, body: &str| {
+            let mut request = request(method, path, body).into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            let status = response.status_code().0;
+            let body: serde_json::Value = serde_json::from_reader(response.into_reader()).unwrap();
+            (status, body["code"].as_str().unwrap().to_string())
+        };
+
+        for (method, path, body, status, code) in [
+            (Method::Get, "/api/v0/discovery/web", "", 404, "NotFound"),
+            (Method::Get, "/api/v0/examples/job", "", 404, "NotFound"),
+            (
+                Method::Post,
+                "/api/v0/nodes.cordon",
+                r#"{"node": "missing", "cordoned": true}"#,
+                404,
+                "NotFound",
+            ),
+        ] {
+            assert_eq!(
+                error(method, path, body),
+                (status, code.to_string()),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_not_found_hint() {
+        let router = Router::new();
+        let not_found = |url: &str| {
+            let response = router.not_found(url);
+            assert_eq!(response.status_code().0, 404);
+            let error: serde_json::Value = serde_json::from_reader(response.into_reader()).unwrap();
+            assert_eq!(error["code"], "RouteNotFound");
+            error["details"].clone()
+        };
+
+        let details = not_found("/api/v0/worklads.list?limit=5");
+        assert_eq!(details["path"], "/api/v0/worklads.list");
+        assert_eq!(details["hint"], "did you mean /api/v0/workloads.list?");
+        // Parameters keep the value of the path
+        let details = not_found("/api/v0/instance.get/web-1");
+        assert_eq!(details["hint"], "did you mean /api/v0/instances.get/web-1?");
+        let details = not_found("/api/v0/workloads.l");
+        assert_eq!(details["hint"], "did you mean /api/v0/workloads.list?");
+        assert_eq!(not_found("/api/v0/unrelated/path").get("hint"), None);
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("workloads", "workloads"), 0);
+        assert_eq!(edit_distance("worklads", "workloads"), 1);
+        assert_eq!(edit_distance("instance", "instances"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[rstest]
+    fn test_create_answers_the_element(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let create = |path: &str, body: &str| {
+            let mut request = request(Method::Post, path, body).into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            let location = response
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("Location"))
+                .map(|header| header.value.to_string());
+            let status = response.status_code().0;
+            let body: serde_json::Value = serde_json::from_reader(response.into_reader()).unwrap();
+            (status, location, body)
+        };
+
+        let (code, location, workload) = create("/api/v0/workloads.create", MANIFEST);
+        assert_eq!(code, 201);
+        let id = workload["id"].as_str().unwrap();
+        assert_eq!(location, Some(format!("/api/v0/workloads.get/{}", id)));
+        assert_eq!(workload["name"], "web");
+        assert_eq!(workload["value"]["namespace"], "lab");
+        assert!(workload["created_at"].is_string());
+        let location = location.unwrap();
+        assert_eq!(
+            status(&router, &connection, &sender, Method::Get, &location, ""),
+            Some(200)
+        );
+
+        let manifest = MANIFEST.replace(r#""web""#, r#""api""#);
+        let (code, location, workload) = create("/api/v1/workloads", &manifest);
+        assert_eq!(code, 201);
+        let id = workload["id"].as_str().unwrap();
+        assert_eq!(location, Some(format!("/api/v1/workloads/{}", id)));
+
+        let body = r#"{"id": "", "name": "/tenant/acme", "value": "{\"plan\": \"gold\"}"}"#;
+        let (code, location, tenant) = create("/api/v0/tenants.create", body);
+        assert_eq!(code, 201);
+        let id = tenant["id"].as_str().unwrap();
+        assert_eq!(location, Some(format!("/api/v0/tenants.get/{}", id)));
+        assert_eq!(tenant["name"], "acme");
+        assert_eq!(tenant["value"]["plan"], "gold");
+        assert!(tenant["api_key"].is_string());
+        assert_eq!(tenant["value"].get(API_KEY_FIELD), None);
+        let location = location.unwrap();
+        let mut request = TestRequest::new().with_path(&location).into();
+        let response = router.handle(&mut request, &connection, &sender).unwrap();
+        assert_eq!(response.status_code().0, 200);
+        let stored: serde_json::Value = serde_json::from_reader(response.into_reader()).unwrap();
+        assert_eq!(stored["value"].get(API_KEY_FIELD), None);
+        assert_eq!(stored.get("api_key"), None);
+    }
+
+    #[rstest]
+    fn test_restart_instance(db_connection: std::sync::Arc<RikDataBase>) {
+        use crate::core::instance::{Instance, RESTARTED_REASON};
+        use crate::database::events::EventRepository;
+        use definition::workload::{Spec, WorkloadKind};
+        use definition::InstanceStatus;
+
+        let connection = db_connection.open().unwrap();
+        let (sender, receiver) = channel();
+        let router = Router::new();
+        let workload_id = insert_workload(&connection);
+        for (id, instance_status) in [
+            ("web-1", InstanceStatus::Running),
+            ("web-2", InstanceStatus::Failed),
+            ("web-3", InstanceStatus::Destroying),
+        ] {
+            let spec = Spec {
+                containers: vec![],
+                function: None,
+            };
+            let mut instance = Instance::new(
+                workload_id.clone(),
+                WorkloadKind::Pod,
+                Some(id.to_string()),
+                spec,
+            );
+            instance.status = instance_status;
+            RikRepository::upsert(
+                &connection,
+                &instance.id,
+                &instance.get_full_name(),
+                &serde_json::to_string(&instance).unwrap(),
+                "/instance",
+            )
+            .unwrap();
+        }
+
+        let (code, restarted) = post(
+            &router,
+            &connection,
+            &sender,
+            "/api/v0/instances.restart",
+            r#"{"id": "web-1"}"#,
+        );
+        assert_eq!(code, 202);
+        assert_eq!(restarted["value"]["status"], "Restarting");
+        let notification = receiver.try_recv().unwrap();
+        assert!(matches!(notification.action, Crud::Restart));
+        assert_eq!(notification.instance_id.as_deref(), Some("web-1"));
+        assert_eq!(notification.workload_id, Some(workload_id));
+        let reasons = [
+            RESTARTED_REASON.to_string(),
+            InstanceStatus::Restarting.to_string(),
+        ];
+        let events = EventRepository::latest(&connection, "web-1", &reasons, 10).unwrap();
+        assert_eq!(events.len(), 2);
+
+        // Instances which are not running are booted again
+        let code = status(
+            &router,
+            &connection,
+            &sender,
+            Method::Post,
+            "/api/v1/instances/web-2/restart",
+            "",
+        );
+        assert_eq!(code, Some(202));
+        assert!(matches!(receiver.try_recv().unwrap().action, Crud::Restart));
+
+        let (code, answer) = post(
+            &router,
+            &connection,
+            &sender,
+            "/api/v0/instances.restart",
+            r#"{"id": "web-3"}"#,
+        );
+        assert_eq!(code, 409);
+        assert_eq!(answer["code"], "InstanceTerminating");
+        let (code, _) = post(
+            &router,
+            &connection,
+            &sender,
+            "/api/v0/instances.restart",
+            r#"{"id": "unknown"}"#,
+        );
+        assert_eq!(code, 404);
+        let (code, answer) = post(
+            &router,
+            &connection,
+            &sender,
+            "/api/v0/instances.restart?dry_run=true",
+            r#"{"id": "web-1"}"#,
+        );
+        assert_eq!(code, 202);
+        assert_eq!(answer["dry_run"], true);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[rstest]
+    fn test_tenant_quota(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let send = |path: &str, tenant: Option<&str>, body: String| {
+            let mut request = request(Method::Post, path, &body);
+            if let Some(tenant) = tenant {
+                let header = format!("X-Rik-Tenant: {}", tenant);
+                request = request.with_header(header.parse().unwrap());
+            }
+            let response = router
+                .handle(&mut request.into(), &connection, &sender)
+                .unwrap();
+            let code = response.status_code().0;
+            let body: serde_json::Value =
+                serde_json::from_reader(response.into_reader()).unwrap_or_default();
+            (code, body)
+        };
+
+        let invalid = r#"{"id": "", "name": "/tenant/acme", "value": "{\"quota\": {\"max_workloads\": -1}}"}"#;
+        assert_eq!(
+            send("/api/v0/tenants.create", None, invalid.to_string()).0,
+            400
+        );
+        let body = r#"{"id": "", "name": "/tenant/acme", "value": "{\"quota\": {\"max_workloads\": 1, \"max_instances\": 2}}"}"#;
+        let (code, tenant) = send("/api/v0/tenants.create", None, body.to_string());
+        assert_eq!(code, 201);
+        assert_eq!(tenant["value"]["quota"]["max_workloads"], 1);
+        let acme = tenant["id"].as_str().unwrap().to_string();
+
+        let create = "/api/v0/workloads.create";
+        let (code, created) = send(create, Some(&acme), MANIFEST.to_string());
+        assert_eq!(code, 201);
+        let workload_id = created["id"].as_str().unwrap().to_string();
+        let manifest = MANIFEST.replace(r#""web""#, r#""api""#);
+        let (code, error) = send(create, Some(&acme), manifest.clone());
+        assert_eq!(code, 403);
+        assert_eq!(error["code"], "QuotaExceeded");
+        assert_eq!(error["details"]["resource"], "workloads");
+        assert_eq!(error["details"]["usage"], 1);
+        assert_eq!(error["details"]["limit"], 1);
+        // Other tenants and elements without a tenant are not limited
+        assert_eq!(send(create, None, manifest.clone()).0, 201);
+
+        let instances = "/api/v0/instances.create";
+        let body = |replicas: usize| {
+            format!(
+                r#"{{"workload_id": "{}", "replicas": {}}}"#,
+                workload_id, replicas
+            )
+        };
+        RikRepository::insert(
+            &connection,
+            &format!("/instance/{}/Pod/lab/web-1", acme),
+            "{}",
+        )
+        .unwrap();
+        let (code, error) = send(instances, None, body(2));
+        assert_eq!(code, 403);
+        assert_eq!(error["details"]["usage"], 1);
+        assert_eq!(error["details"]["requested"], 2);
+        assert_eq!(send(instances, None, body(1)).0, 201);
+        let scale = format!(r#"{{"id": "{}", "replicas": 3}}"#, workload_id);
+        assert_eq!(send("/api/v0/workloads.scale", None, scale).0, 403);
+
+        // A quota below the usage only blocks new creations
+        let set_quota = |quota: &str| {
+            let body = format!(r#"{{"id": "{}", "quota": {}}}"#, acme, quota);
+            send("/api/v0/tenants.set_quota", None, body)
+        };
+        let (code, tenant) = set_quota(r#"{"max_workloads": 0}"#);
+        assert_eq!(code, 200);
+        assert_eq!(
+            tenant["value"]["quota"],
+            serde_json::json!({"max_workloads": 0})
+        );
+        assert!(RikRepository::find_one(&connection, &workload_id, "/workload").is_ok());
+        let (code, error) = send(create, Some(&acme), manifest.clone());
+        assert_eq!(code, 403);
+        assert_eq!(error["details"]["limit"], 0);
+        assert_eq!(set_quota(r#"{"max_pods": 1}"#).0, 422);
+        assert_eq!(set_quota("null").0, 200);
+        assert_eq!(send(create, Some(&acme), manifest).0, 201);
+    }
+
+    #[rstest]
+    #[case("/workload/Pod/lab/", "/api/v1/workloads?limit=1")]
+    #[case("/workload/Pod/lab/", "/api/v0/workloads.list?limit=1&sort=name")]
+    #[case("/instance/Pod/lab/", "/api/v1/instances?limit=1")]
+    #[case("/instance/Pod/lab/", "/api/v0/instances.list?limit=1&detail=full")]
+    #[case("/tenant/", "/api/v0/tenants.list?limit=1")]
+    #[case("/tenant/", "/api/v0/tenants.list?limit=1&name=a")]
+    fn test_list_envelope(
+        db_connection: std::sync::Arc<RikDataBase>,
+        #[case] prefix: &str,
+        #[case] path: &'static str,
+    ) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        for name in ["api", "web", "worker"] {
+            let value = if prefix.starts_with("/workload/") {
+                MANIFEST.replace("\"web\"", &format!("\"{}\"", name))
+            } else {
+                String::from("{}")
+            };
+            RikRepository::insert(&connection, &format!("{}{}", prefix, name), &value).unwrap();
+        }
+        let revision = RevisionRepository::current(&connection).unwrap();
+
+        let mut request = TestRequest::new()
+            .with_method(Method::Get)
+            .with_path(path)
+            .into();
+        let response = router.handle(&mut request, &connection, &sender).unwrap();
+        assert_eq!(response.status_code().0, 200);
+        let page: ListResponse<serde_json::Value> =
+            serde_json::from_reader(response.into_reader()).unwrap();
+        // The total is the one before the page is cut
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0]["name"], "api");
+        let expected = if path.contains("name=a") { 1 } else { 3 };
+        assert_eq!(page.total, expected);
+        assert_eq!(page.revision, revision as u64);
+    }
+
+    #[rstest]
+    fn test_update_at_version(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, _receiver) = channel();
+        let router = Router::new();
+        let id = insert_workload(&connection);
+        let path = format!("/api/v1/workloads/{}", id);
+        let put = |if_match: &str| {
+            let mut request = TestRequest::new()
+                .with_method(Method::Put)
+                .with_path(&path)
+                .with_header(format!("If-Match: {}", if_match).parse().unwrap())
+                .with_body(MANIFEST)
+                .into();
+            let response = router.handle(&mut request, &connection, &sender).unwrap();
+            let code = response.status_code().0;
+            let body: serde_json::Value = serde_json::from_reader(response.into_reader()).unwrap();
+            (code, body)
+        };
+
+        let (code, updated) = put("1");
+        assert_eq!((code, updated["version"].as_i64()), (200, Some(2)));
+        // Read before the update, the workload is not written
+        let (code, error) = put("\"1\"");
+        assert_eq!(
+            (code, error["code"].as_str()),
+            (409, Some("VersionConflict"))
+        );
+        assert_eq!(error["details"]["current"], 2);
+        assert_eq!(put("latest").0, 400);
+
+        let body = format!(r#"{{"id": "{}", "replicas": 3}}"#, id);
+        let (code, scaled) = post(
+            &router,
+            &connection,
+            &sender,
+            "/api/v0/workloads.scale",
+            &body,
+        );
+        assert_eq!((code, scaled["version"].as_i64()), (200, Some(3)));
+    }
+
+    #[rstest]
+    fn test_failed_writes_are_rolled_back(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, receiver) = channel();
+        let router = Router::new();
+        let count = || RikRepository::count(&connection, "/workload/").unwrap();
+        // Events matching `condition` cannot be recorded
+        let fail_events = |condition: &str| {
+            connection
+                .execute_batch(&format!(
+                    "DROP TRIGGER IF EXISTS fail_event;
+                    CREATE TEMP TRIGGER fail_event BEFORE INSERT ON events WHEN {}
+                    BEGIN SELECT RAISE(ABORT, 'injected failure'); END;",
+                    condition
+                ))
+                .unwrap();
+        };
+
+        fail_events("NEW.reason = 'Created'");
+        let (code, _) = post(
+            &router,
+            &connection,
+            &sender,
+            "/api/v0/workloads.create",
+            MANIFEST,
+        );
+        assert_eq!((code, count()), (500, 0));
+        // The second workload cannot be recorded, the first is not kept either
+        fail_events("NEW.message LIKE 'Workload front %'");
+        let bulk = format!(
+            "[{}, {}]",
+            MANIFEST.replace("\"web\"", "\"api\""),
+            MANIFEST.replace("\"web\"", "\"front\"")
+        );
+        let (code, _) = post(
+            &router,
+            &connection,
+            &sender,
+            "/api/v0/workloads.create_bulk",
+            &bulk,
+        );
+        assert_eq!((code, count()), (500, 0));
+
+        // The core is not told to delete instances which are kept
+        let tenant = RikRepository::insert(&connection, "/tenant/acme", "{}").unwrap();
+        let workloads: Vec<String> = ["api", "front"]
+            .iter()
+            .map(|name| {
+                let name = format!("/workload/{}/Pod/lab/{}", tenant, name);
+                RikRepository::insert(&connection, &name, MANIFEST).unwrap()
+            })
+            .collect();
+        let instance = serde_json::json!({ "workload_id": workloads[0], "namespace": "lab" });
+        let instance = RikRepository::insert(
+            &connection,
+            &format!("/instance/{}/Pod/lab/api-1", tenant),
+            &instance.to_string(),
+        )
+        .unwrap();
+        fail_events("NEW.reason = 'Deleted'");
+        let body = format!(r#"{{"id": "{}"}}"#, workloads[0]);
+        let path = "/api/v0/workloads.delete?force=true";
+        assert_eq!(post(&router, &connection, &sender, path, &body).0, 500);
+        assert_eq!(count(), 2);
+        assert!(receiver.try_recv().is_err());
+
+        // Nor when a workload of a tenant deleted cannot be
+        fail_events(&format!("NEW.element_id = '{}'", workloads[1]));
+        let body = format!(r#"{{"id": "{}"}}"#, tenant);
+        let path = "/api/v0/tenants.delete?force=true";
+        assert_eq!(post(&router, &connection, &sender, path, &body).0, 500);
+        assert_eq!(count(), 2);
+        assert!(RikRepository::find_one(&connection, &tenant, "/tenant").is_ok());
+        assert!(receiver.try_recv().is_err());
+
+        connection.execute_batch("DROP TRIGGER fail_event").unwrap();
+        assert_eq!(post(&router, &connection, &sender, path, &body).0, 204);
+        assert_eq!(count(), 0);
+        assert_eq!(receiver.try_recv().unwrap().instance_id, Some(instance));
+    }
+
+    #[rstest]
+    fn test_atomic_delete_collection_is_rolled_back(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let (sender, receiver) = channel();
+        let router = Router::new();
+        let count = || RikRepository::count(&connection, "/workload/").unwrap();
+        let workloads: Vec<String> = ["web", "api", "db"]
+            .iter()
+            .map(|name| {
+                let manifest = MANIFEST.replace(r#""web""#, &format!(r#""{}""#, name));
+                let name = format!("/workload/Pod/lab/{}", name);
+                RikRepository::insert(&connection, &name, &manifest).unwrap()
+            })
+            .collect();
+        let value = serde_json::json!({ "workload_id": workloads[0], "namespace": "lab" });
+        let instance =
+            RikRepository::insert(&connection, "/instance/Pod/lab/web-1", &value.to_string())
+                .unwrap();
+        // The deletion of the second workload cannot be recorded
+        connection
+            .execute_batch(&format!(
+                "CREATE TEMP TRIGGER fail_event BEFORE INSERT ON events
+                WHEN NEW.element_id = '{}'
+                BEGIN SELECT RAISE(ABORT, 'injected failure'); END;",
+                workloads[1]
+            ))
+            .unwrap();
+        let body = serde_json::json!({ "ids": workloads }).to_string();
+        let path = "/api/v0/workloads.delete_collection?atomic=true&force=true";
+
+        let (code, results) = post(&router, &connection, &sender, path, &body);
+        assert_eq!(code, 409);
+        let statuses: Vec<&str> = results["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["status"].as_str().unwrap())
+            .collect();
+        assert_eq!(statuses, ["skipped", "failed", "skipped"]);
+        // The first workload is not kept deleted, nor its instance stopped
+        assert_eq!(count(), 3);
+        assert!(receiver.try_recv().is_err());
+
+        connection.execute_batch("DROP TRIGGER fail_event").unwrap();
+        let (code, _) = post(&router, &connection, &sender, path, &body);
+        assert_eq!((code, count()), (200, 0));
+        assert_eq!(receiver.try_recv().unwrap().instance_id, Some(instance));
     }
 }
@@ -0,0 +1,139 @@
+use route_recognizer;
+use rusqlite::Connection;
+use std::io;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use tracing::{event, Level};
+
+use crate::api;
+use crate::api::external::services::csv::{list_response, NODE_COLUMNS};
+use crate::api::external::services::element::{element_set_right_name, elements_set_right_name};
+use crate::api::external::services::request::extract_request;
+use crate::api::types::element::OnlyId;
+use crate::api::types::node::NodeCordon;
+use crate::api::ApiChannel;
+use crate::core::maintenance::MaintenanceWindow;
+use crate::core::worker_repository::{node_view, set_manual_cordon};
+use crate::database::RikRepository;
+
+/// List the nodes which registered with the scheduler, along with whether
+/// they still send their metrics
+pub fn list(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let mut workers = RikRepository::find_all(connection, "/worker/any/")
+        .map_err(|_| api::RikError::Internal(String::from("Cannot find nodes")))?;
+    elements_set_right_name(&mut workers);
+    let now = chrono::Utc::now();
+    let nodes: Vec<_> = workers
+        .into_iter()
+        .map(|worker| node_view(worker, now))
+        .collect();
+    event!(Level::INFO, "nodes.list, {} nodes found", nodes.len());
+    Ok(list_response(req, &nodes, NODE_COLUMNS))
+}
+
+/// Show a node along with the ids of the instances placed on it
+pub fn get_one(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let node_id = params.find("id").unwrap_or_default().to_string();
+    let Ok(mut worker) = RikRepository::find_one(connection, &node_id, "/worker/any/") else {
+        event!(Level::WARN, "nodes.get, node not found");
+        return Err(api::RikError::NotFound(format!(
+            "Node {} not found",
+            node_id
+        )));
+    };
+    element_set_right_name(&mut worker);
+    let instance_ids: Vec<String> =
+        RikRepository::find_by_value_field(connection, "/instance/", "worker_id", &node_id)
+            .map_err(|_| api::RikError::Internal(String::from("Cannot find node instances")))?
+            .into_iter()
+            .map(|instance| instance.id)
+            .collect();
+    let mut node = node_view(worker, chrono::Utc::now());
+    node.value["instance_count"] = instance_ids.len().into();
+    node.value["instance_ids"] = instance_ids.into();
+    Ok(
+        tiny_http::Response::from_string(serde_json::to_string(&node).unwrap())
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200)),
+    )
+}
+
+pub fn maintenance(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let window: MaintenanceWindow = match extract_request(req) {
+        Ok(window) => window,
+        Err(response) => return Ok(response),
+    };
+
+    // A node may have several windows, they are told apart by their id
+    if let Ok(inserted_id) = RikRepository::insert(
+        connection,
+        &format!("/maintenance/{}", window.node),
+        &serde_json::to_string(&window).unwrap(),
+    ) {
+        event!(
+            Level::INFO,
+            "nodes.maintenance, window scheduled for node {}",
+            window.node
+        );
+        Ok(tiny_http::Response::from_string(
+            serde_json::to_string(&OnlyId { id: inserted_id }).unwrap(),
+        )
+        .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+        .with_status_code(tiny_http::StatusCode::from(201)))
+    } else {
+        event!(Level::ERROR, "nodes.maintenance, cannot create window");
+        Err(api::RikError::Internal(String::from(
+            "Cannot create maintenance window",
+        )))
+    }
+}
+
+pub fn cordon(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let NodeCordon { node, cordoned } = match extract_request(req) {
+        Ok(request) => request,
+        Err(response) => return Ok(response),
+    };
+
+    match set_manual_cordon(connection, &node, cordoned) {
+        Ok(state) => {
+            event!(
+                Level::INFO,
+                "nodes.cordon, node {} {}",
+                node,
+                if cordoned { "cordoned" } else { "uncordoned" }
+            );
+            Ok(
+                tiny_http::Response::from_string(serde_json::to_string(&state).unwrap())
+                    .with_header(
+                        tiny_http::Header::from_str("Content-Type: application/json").unwrap(),
+                    )
+                    .with_status_code(tiny_http::StatusCode::from(200)),
+            )
+        }
+        Err(api::RikError::InvalidName(_)) => {
+            event!(Level::WARN, "nodes.cordon, node not found");
+            Err(api::RikError::NotFound(format!("Node {} not found", node)))
+        }
+        Err(e) => Err(e),
+    }
+}
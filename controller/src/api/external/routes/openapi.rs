@@ -0,0 +1,153 @@
+use route_recognizer;
+use rusqlite::Connection;
+use std::io;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use std::sync::OnceLock;
+
+use crate::api;
+use crate::api::ApiChannel;
+
+pub const OPENAPI_PATH: &str = "/api/v0/openapi.json";
+
+/// Description of the API, kept next to the crate so it is reviewed with the
+/// routes it describes
+const OPENAPI_YAML: &str = include_str!("../../../../openapi.yaml");
+
+/// The OpenAPI document as JSON, converted once from its YAML source
+fn document() -> &'static str {
+    static DOCUMENT: OnceLock<String> = OnceLock::new();
+    DOCUMENT.get_or_init(|| {
+        let document: serde_json::Value =
+            serde_yaml::from_str(OPENAPI_YAML).expect("openapi.yaml is not valid YAML");
+        document.to_string()
+    })
+}
+
+pub fn get(
+    _: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    _: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    Ok(tiny_http::Response::from_string(document())
+        .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+        .with_status_code(tiny_http::StatusCode::from(200)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::external::routes::Router;
+    use serde_json::Value;
+    use std::collections::BTreeSet;
+
+    /// `$ref`s found anywhere in a part of the document
+    fn references<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object {
+                    match (key.as_str(), value) {
+                        ("$ref", Value::String(reference)) => found.push(reference),
+                        _ => references(value, found),
+                    }
+                }
+            }
+            Value::Array(values) => values.iter().for_each(|value| references(value, found)),
+            _ => {}
+        }
+    }
+
+    fn resolve<'a>(document: &'a Value, value: &'a Value) -> &'a Value {
+        match value["$ref"].as_str() {
+            Some(reference) => document
+                .pointer(reference.trim_start_matches('#'))
+                .unwrap_or_else(|| panic!("{} does not resolve", reference)),
+            None => value,
+        }
+    }
+
+    #[test]
+    fn test_document_matches_routes() {
+        let document: Value = serde_json::from_str(document()).unwrap();
+        assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+        for schema in ["WorkloadDefinition", "Element", "OnlyId"] {
+            assert!(document["components"]["schemas"][schema].is_object());
+        }
+
+        let routed: BTreeSet<(String, String)> = Router::new()
+            .paths()
+            .map(|(method, path)| {
+                let path = path
+                    .split('/')
+                    .map(|segment| match segment.strip_prefix(':') {
+                        Some(name) => format!("{{{}}}", name),
+                        None => segment.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/");
+                (method.to_string().to_lowercase(), path)
+            })
+            .collect();
+        let documented: BTreeSet<(String, String)> = document["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, operations)| {
+                operations
+                    .as_object()
+                    .unwrap()
+                    .keys()
+                    .map(move |method| (method.clone(), path.clone()))
+            })
+            .collect();
+        assert_eq!(
+            routed.difference(&documented).collect::<Vec<_>>(),
+            Vec::<&(String, String)>::new(),
+            "routes missing from openapi.yaml"
+        );
+        assert_eq!(
+            documented.difference(&routed).collect::<Vec<_>>(),
+            Vec::<&(String, String)>::new(),
+            "operations of openapi.yaml without a route"
+        );
+
+        for (method, path) in &documented {
+            let operation = &document["paths"][path][method];
+            assert!(
+                operation["responses"]
+                    .as_object()
+                    .is_some_and(|responses| !responses.is_empty()),
+                "{} {} has no responses",
+                method,
+                path
+            );
+            let declared: Vec<&str> = operation["parameters"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|parameter| resolve(&document, parameter))
+                .filter(|parameter| parameter["in"] == "path")
+                .filter_map(|parameter| parameter["name"].as_str())
+                .collect();
+            for name in path
+                .split('/')
+                .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+            {
+                assert!(
+                    declared.contains(&name),
+                    "{} {} does not declare {}",
+                    method,
+                    path,
+                    name
+                );
+            }
+        }
+
+        let mut found = Vec::new();
+        references(&document, &mut found);
+        for reference in found {
+            resolve(&document, &serde_json::json!({ "$ref": reference }));
+        }
+    }
+}
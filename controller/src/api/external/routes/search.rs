@@ -0,0 +1,78 @@
+use route_recognizer;
+use rusqlite::Connection;
+use serde_json::json;
+use std::io;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use tracing::{event, Level};
+
+use crate::api;
+use crate::api::external::services::list::invalid_parameters_response;
+use crate::api::external::services::namespace::request_namespace;
+use crate::api::external::services::search::SearchParams;
+use crate::api::external::services::tenant::client_tenant;
+use crate::api::types::element::tenant_segment;
+use crate::api::types::element::{ElementPath, NameMatch};
+use crate::api::ApiChannel;
+use crate::database::RikRepository;
+
+/// Ids and names of the workloads or instances of a namespace matching a text,
+/// the ones of its tenant for a client acting for one
+pub fn get(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let params = match SearchParams::parse(req.url()) {
+        Ok(params) => params,
+        Err(errors) => {
+            event!(Level::WARN, "search.get, invalid parameters");
+            return Ok(invalid_parameters_response(errors));
+        }
+    };
+
+    let namespace = match request_namespace(req, connection, params.namespace.as_deref()) {
+        Ok(namespace) => namespace,
+        Err(e) => {
+            event!(Level::WARN, "search.get, {}", e);
+            return Err(api::RikError::InvalidBody(e));
+        }
+    };
+
+    // Clients acting for a tenant only find the names of its elements
+    let element_type = format!(
+        "{}{}",
+        params.element_type,
+        tenant_segment(client_tenant(req).as_deref())
+    );
+    match RikRepository::search_names(
+        connection,
+        &element_type,
+        &namespace,
+        &params.query,
+        params.limit,
+    ) {
+        Ok(names) => {
+            let items: Vec<NameMatch> = names
+                .into_iter()
+                .map(|(id, full_name)| NameMatch {
+                    name: ElementPath::parse(&full_name).1.to_string(),
+                    id,
+                })
+                .collect();
+            event!(Level::INFO, "search.get, {} names found", items.len());
+            Ok(
+                tiny_http::Response::from_string(json!({ "items": items }).to_string())
+                    .with_header(
+                        tiny_http::Header::from_str("Content-Type: application/json").unwrap(),
+                    )
+                    .with_status_code(tiny_http::StatusCode::from(200)),
+            )
+        }
+        Err(e) => {
+            event!(Level::ERROR, "search.get, cannot search names: {}", e);
+            Err(api::RikError::Internal(String::from("Cannot search names")))
+        }
+    }
+}
@@ -5,30 +5,78 @@ use std::str::FromStr;
 use std::sync::mpsc::Sender;
 use tracing::{event, Level};
 
-use crate::api;
-use crate::api::external::services::element::elements_set_right_name;
-use crate::api::types::element::OnlyId;
-use crate::api::types::tenant::Tenant;
-use crate::api::ApiChannel;
+use crate::api::auth::{with_new_api_key, API_KEY_FIELD};
+use crate::api::external::services::csv::{page_response, TENANT_COLUMNS};
+use crate::api::external::services::element::{
+    element_set_right_name, elements_set_right_name, query_parameter,
+};
+use crate::api::external::services::list::{
+    invalid_parameters_response, read_list, ListParams, TENANT_LIST,
+};
+use crate::api::external::services::request::{
+    created_response, extract_request, read_body, BodyFormat,
+};
+use crate::api::external::services::tenant::{parse_default_namespace, parse_quota, QUOTA_FIELD};
+use crate::api::external::services::workload::{
+    protection_error, protection_override, remove_workload, send_deletions,
+};
+use crate::api::response::error_response;
+use crate::api::types::element::tenant_segment;
+use crate::api::types::element::{Element, OnlyId};
+use crate::api::types::tenant::{Tenant, TenantQuota};
+use crate::api::{ApiChannel, RikError};
 use crate::database::RikRepository;
 
 pub fn get(
-    _: &mut tiny_http::Request,
+    req: &mut tiny_http::Request,
     _: &route_recognizer::Params,
     connection: &Connection,
     _: &Sender<ApiChannel>,
-) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
-    if let Ok(mut tenants) = RikRepository::find_all(connection, "/tenant") {
-        tenants = elements_set_right_name(tenants.clone());
-        let tenants_json = serde_json::to_string(&tenants).unwrap();
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    let params = match ListParams::parse(req.url(), &TENANT_LIST) {
+        Ok(params) => params,
+        Err(errors) => return Ok(invalid_parameters_response(errors)),
+    };
+    let page = read_list(connection, |connection| {
+        params.find_page("/tenant", |query, limit, offset| {
+            RikRepository::find_page(connection, query, limit, offset)
+        })
+    });
+    if let Ok(mut page) = page {
+        for tenant in &mut page.items {
+            if let Some(fields) = tenant.value.as_object_mut() {
+                fields.remove(API_KEY_FIELD);
+            }
+        }
         event!(Level::INFO, "tenants.get, tenants found");
-        Ok(tiny_http::Response::from_string(tenants_json)
-            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
-            .with_status_code(tiny_http::StatusCode::from(200)))
+        Ok(page_response(req, &page, TENANT_COLUMNS))
     } else {
-        Ok(tiny_http::Response::from_string("Cannot find tenant")
-            .with_status_code(tiny_http::StatusCode::from(500)))
+        Err(RikError::Internal(String::from("Cannot find tenant")))
+    }
+}
+
+/// A tenant, without the hash of its key
+pub fn get_one(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    let id = params.find("id").unwrap_or_default();
+    let Ok(tenant) = RikRepository::find_one(connection, &id.to_string(), "/tenant") else {
+        event!(Level::WARN, "Tenant id {} not found", id);
+        return Err(RikError::NotFound(format!("Tenant id {} not found", id)));
+    };
+    Ok(json_response(&tenant_view(tenant)))
+}
+
+/// Tenant as answered, its key is never given back
+fn tenant_view(mut tenant: Element) -> serde_json::Value {
+    element_set_right_name(&mut tenant);
+    if let Some(fields) = tenant.value.as_object_mut() {
+        fields.remove(API_KEY_FIELD);
     }
+    serde_json::to_value(tenant).unwrap()
 }
 
 pub fn create(
@@ -36,42 +84,199 @@ pub fn create(
     _: &route_recognizer::Params,
     connection: &Connection,
     _: &Sender<ApiChannel>,
-) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
-    let mut content = String::new();
-    req.as_reader().read_to_string(&mut content).unwrap();
-    let tenant: Tenant = serde_json::from_str(&content)?;
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    let content = read_body(req)?;
+    let tenant: Tenant = BodyFormat::of(req).deserialize(&content)?;
 
-    if RikRepository::insert(connection, &tenant.name, &tenant.value).is_ok() {
-        event!(Level::INFO, "Create tenant");
-        Ok(tiny_http::Response::from_string(content)
-            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
-            .with_status_code(tiny_http::StatusCode::from(200)))
-    } else {
-        event!(Level::ERROR, "Cannot create tenant");
-        Ok(tiny_http::Response::from_string("Cannot create tenant")
-            .with_status_code(tiny_http::StatusCode::from(500)))
+    if let Ok(existing) = RikRepository::find_by_name(connection, &tenant.name) {
+        return Err(tenant_conflict(&tenant, existing.id));
+    }
+    let value = serde_json::from_str(&tenant.value).unwrap_or_default();
+    parse_quota(&value)?;
+    parse_default_namespace(&value)?;
+
+    // The key is only given in this response, the tenant keeps its SHA-256
+    let (api_key, value) = with_new_api_key(&tenant.value)?;
+    match RikRepository::insert(connection, &tenant.name, &value) {
+        Ok(id) => {
+            event!(Level::INFO, "Create tenant");
+            let stored = RikRepository::find_one(connection, &id, "/tenant")
+                .map_err(|_| RikError::Internal(String::from("Cannot read the tenant created")))?;
+            let mut created = tenant_view(stored);
+            created["api_key"] = api_key.into();
+            Ok(created_response(
+                &format!("/api/v0/tenants.get/{}", id),
+                &created,
+            ))
+        }
+        // Created by another thread since the name was checked
+        Err(rusqlite::Error::SqliteFailure(error, _))
+            if error.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE =>
+        {
+            let existing = RikRepository::find_by_name(connection, &tenant.name)
+                .map_err(|_| RikError::Internal(String::from("Cannot create tenant")))?;
+            Err(tenant_conflict(&tenant, existing.id))
+        }
+        Err(e) => {
+            event!(Level::ERROR, "Cannot create tenant: {}", e);
+            Err(RikError::Internal(String::from("Cannot create tenant")))
+        }
     }
 }
 
-pub fn delete(
+/// Give a tenant a new API key, the previous one is refused from then on
+pub fn rotate_key(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    let OnlyId { id } = match extract_request(req) {
+        Ok(request) => request,
+        Err(response) => return Ok(response),
+    };
+    let Ok(tenant) = RikRepository::find_one(connection, &id, "/tenant") else {
+        event!(Level::WARN, "Tenant id {} not found", id);
+        return Err(RikError::NotFound(format!("Tenant id {} not found", id)));
+    };
+    let (api_key, value) = with_new_api_key(&tenant.value.to_string())?;
+    RikRepository::update(connection, &tenant.id, &value).map_err(|e| {
+        event!(
+            Level::ERROR,
+            "Cannot rotate the key of tenant {}: {}",
+            id,
+            e
+        );
+        RikError::Internal(String::from("Cannot rotate the key of the tenant"))
+    })?;
+    event!(Level::INFO, "Rotate the key of tenant {}", id);
+    Ok(json_response(
+        &serde_json::json!({ "id": tenant.id, "api_key": api_key }),
+    ))
+}
+
+/// Set the quota of a tenant, or lift it with a `null` one. A quota below what
+/// the tenant owns is accepted, it only refuses the new elements.
+pub fn set_quota(
     req: &mut tiny_http::Request,
     _: &route_recognizer::Params,
     connection: &Connection,
     _: &Sender<ApiChannel>,
-) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
-    let mut content = String::new();
-    req.as_reader().read_to_string(&mut content).unwrap();
-    let OnlyId { id: delete_id } = serde_json::from_str(&content)?;
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    let TenantQuota { id, quota } = match extract_request(req) {
+        Ok(request) => request,
+        Err(response) => return Ok(response),
+    };
+    let Ok(mut tenant) = RikRepository::find_one(connection, &id, "/tenant") else {
+        event!(Level::WARN, "Tenant id {} not found", id);
+        return Err(RikError::NotFound(format!("Tenant id {} not found", id)));
+    };
+    let Some(fields) = tenant.value.as_object_mut() else {
+        return Err(RikError::Internal(format!(
+            "The value of tenant {} is not an object",
+            id
+        )));
+    };
+    match quota {
+        Some(quota) => fields.insert(QUOTA_FIELD.to_string(), serde_json::to_value(quota)?),
+        None => fields.remove(QUOTA_FIELD),
+    };
+    RikRepository::update(connection, &tenant.id, &tenant.value.to_string()).map_err(|e| {
+        event!(Level::ERROR, "Cannot set the quota of tenant {}: {}", id, e);
+        RikError::Internal(String::from("Cannot set the quota of the tenant"))
+    })?;
+    event!(Level::INFO, "Set the quota of tenant {}", id);
+    Ok(json_response(&tenant_view(tenant)))
+}
+
+fn json_response(body: &serde_json::Value) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    tiny_http::Response::from_string(body.to_string())
+        .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+        .with_status_code(tiny_http::StatusCode::from(200))
+}
+
+fn tenant_conflict(tenant: &Tenant, existing_id: String) -> RikError {
+    event!(Level::WARN, "Tenant name {} is already used", tenant.name);
+    RikError::Conflict(
+        format!("Tenant {} already exists", tenant.name),
+        existing_id,
+    )
+}
+
+/// Delete a tenant, refused while it owns workloads unless `?force=true`
+/// deletes them along with it
+pub fn delete(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    internal_sender: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, RikError> {
+    let OnlyId { id: delete_id } = match extract_request(req) {
+        Ok(request) => request,
+        Err(response) => return Ok(response),
+    };
 
     if let Ok(tenant) = RikRepository::find_one(connection, &delete_id, "/tenant") {
-        RikRepository::delete(connection, &tenant.id).unwrap();
+        let mut workloads = RikRepository::find_all(
+            connection,
+            &format!("/workload/{}", tenant_segment(Some(&tenant.id))),
+        )
+        .map_err(|_| RikError::Internal(String::from("Cannot find tenant workloads")))?;
+        elements_set_right_name(&mut workloads);
+        let overridden_by = protection_override(req);
+        if !workloads.is_empty() {
+            if query_parameter(req.url(), "force") != Some("true") {
+                event!(Level::WARN, "Tenant {} still owns workloads", tenant.id);
+                return Ok(error_response(
+                    409,
+                    "TenantNotEmpty",
+                    format!(
+                        "Tenant {} owns {} workloads, delete them first or use ?force=true",
+                        tenant.id,
+                        workloads.len()
+                    ),
+                ));
+            }
+            // Nothing is deleted when a workload cannot be
+            if let Some(error) = workloads
+                .iter()
+                .find_map(|workload| protection_error(workload, overridden_by.as_deref()))
+            {
+                event!(
+                    Level::WARN,
+                    "Tenant {} owns a protected workload",
+                    tenant.id
+                );
+                return Ok(error_response(409, "Protected", error));
+            }
+        }
+        // The tenant and its workloads are deleted together, the core is only
+        // told to delete their instances once they are
+        let deletions = RikRepository::transaction(connection, |tx| {
+            let mut deletions = Vec::new();
+            for workload in &workloads {
+                deletions.extend(remove_workload(
+                    tx,
+                    workload,
+                    overridden_by.as_deref(),
+                    true,
+                )?);
+            }
+            RikRepository::delete(tx, &tenant.id)?;
+            Ok::<_, RikError>(deletions)
+        })
+        .map_err(|e| {
+            event!(Level::ERROR, "Cannot delete tenant: {}", e);
+            e
+        })?;
+        send_deletions(internal_sender, deletions).map_err(RikError::Internal)?;
         event!(Level::INFO, "Delete tenant");
         Ok(tiny_http::Response::from_string("").with_status_code(tiny_http::StatusCode::from(204)))
     } else {
         event!(Level::WARN, "Tenant id {} not found", delete_id);
-        Ok(
-            tiny_http::Response::from_string(format!("Tenant id {} not found", delete_id))
-                .with_status_code(tiny_http::StatusCode::from(404)),
-        )
+        Err(RikError::NotFound(format!(
+            "Tenant id {} not found",
+            delete_id
+        )))
     }
 }
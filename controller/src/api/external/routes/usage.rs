@@ -0,0 +1,83 @@
+use chrono::{DateTime, Utc};
+use route_recognizer;
+use rusqlite::Connection;
+use std::io;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use tracing::{event, Level};
+
+use crate::api;
+use crate::api::auth::authenticated_tenant;
+use crate::api::external::services::element::{decode_query_value, query_parameter, QueryParams};
+use crate::api::external::services::list::invalid_parameters_response;
+use crate::api::types::usage::UsageGrouping;
+use crate::api::validation::FieldError;
+use crate::api::ApiChannel;
+use crate::database::usage::{usage_cache, UsageQuery, UsageRepository};
+
+pub fn get(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let bad_request = |field: &str, message: &str| {
+        event!(Level::WARN, "usage, {}", message);
+        Ok(invalid_parameters_response(vec![FieldError::new(
+            field, message,
+        )]))
+    };
+    let url = req.url().to_string();
+    let date = |key: &str| {
+        query_parameter(&url, key).map(|date| {
+            decode_query_value(date)
+                .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+                .map(|date| date.with_timezone(&Utc))
+        })
+    };
+    let (from, to) = match (date("from"), date("to")) {
+        (Some(None), _) => return bad_request("from", "The date must be an RFC 3339 date"),
+        (_, Some(None)) => return bad_request("to", "The date must be an RFC 3339 date"),
+        (from, to) => (from.flatten(), to.flatten()),
+    };
+    let group_by = match query_parameter(&url, "group_by").map(UsageGrouping::parse) {
+        Some(Some(group_by)) => group_by,
+        Some(None) => return bad_request("group_by", "The grouping must be tenant or workload"),
+        None => UsageGrouping::default(),
+    };
+    // A tenant key only sees the usage of its own tenant
+    let tenant = QueryParams::parse(&url).decoded("tenant");
+    let tenant = match authenticated_tenant() {
+        Some(own) if tenant.as_ref().is_some_and(|tenant| *tenant != own) => {
+            return bad_request("tenant", "A tenant key only reads the usage of its tenant")
+        }
+        Some(own) => Some(own),
+        None => tenant,
+    };
+    let query = UsageQuery {
+        tenant,
+        from,
+        to,
+        group_by,
+    };
+    let (from, to) = query.range(Utc::now());
+    if from >= to {
+        return bad_request("from", "The date must be before to");
+    }
+
+    match usage_cache().get_or_compute(&query, || {
+        UsageRepository::report(connection, &query, Utc::now())
+    }) {
+        Ok(report) => Ok(
+            tiny_http::Response::from_string(serde_json::to_string(&report).unwrap())
+                .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+                .with_status_code(tiny_http::StatusCode::from(200)),
+        ),
+        Err(e) => {
+            event!(Level::ERROR, "usage, cannot compute the usage: {}", e);
+            Err(api::RikError::Internal(String::from(
+                "Cannot compute the usage",
+            )))
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use route_recognizer;
+use rusqlite::Connection;
+use std::io;
+use std::sync::mpsc::Sender;
+
+use super::{instance, workload};
+use crate::api;
+use crate::api::ApiChannel;
+
+/// Routes of the v1 API, elements are resources acted on with the verb of
+/// the request rather than with an action in the path
+pub const V1_BASE_PATH: &str = "/api/v1";
+const V1_VERSION: &str = "v1";
+
+/// Version of the API a path is sent to, `None` for the v0 one
+pub fn path_version(path: &str) -> Option<&'static str> {
+    path.strip_prefix(V1_BASE_PATH)
+        .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+        .map(|_| V1_VERSION)
+}
+
+/// Parameters of a v1 route, with its `:id` named as the v0 handler expects
+fn renamed_id(params: &route_recognizer::Params, name: &str) -> route_recognizer::Params {
+    let mut renamed = route_recognizer::Params::new();
+    renamed.insert(
+        name.to_string(),
+        params.find("id").unwrap_or_default().to_string(),
+    );
+    renamed
+}
+
+/// `GET /api/v1/workloads/:id`
+pub fn get_workload(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    connection: &Connection,
+    sender: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    workload::get_one(req, &renamed_id(params, "workloadid"), connection, sender)
+}
+
+/// `GET /api/v1/instances/:id`
+pub fn get_instance(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    connection: &Connection,
+    sender: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    instance::get_one(req, &renamed_id(params, "instance_id"), connection, sender)
+}
+
+/// `GET /api/v1/instances/:id/logs`
+pub fn get_instance_logs(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    connection: &Connection,
+    sender: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    instance::logs(req, &renamed_id(params, "instance_id"), connection, sender)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_version() {
+        assert_eq!(path_version("/api/v1/workloads"), Some("v1"));
+        assert_eq!(path_version("/api/v1"), Some("v1"));
+        assert_eq!(path_version("/api/v0/workloads.list"), None);
+        assert_eq!(path_version("/api/v10/workloads"), None);
+    }
+}
@@ -0,0 +1,131 @@
+use route_recognizer;
+use rusqlite::Connection;
+use std::io;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use tracing::{event, Level};
+
+use crate::api;
+use crate::api::external::services::csv::{list_response, VOLUME_COLUMNS};
+use crate::api::external::services::element::elements_set_right_name;
+use crate::api::external::services::namespace::request_namespace;
+use crate::api::external::services::request::{conflict_response, extract_request, read_body};
+use crate::api::external::services::tenant::{caller_owns, resolve_tenant};
+use crate::api::response::error_response;
+use crate::api::types::element::OnlyId;
+use crate::api::types::volume::Volume;
+use crate::api::{ApiChannel, RikError, ALREADY_EXISTS_CODE};
+use crate::core::volume::{find_volume, volume_element_name, VOLUME_ALREADY_BOUND};
+use crate::database::RikRepository;
+
+pub fn get(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    if let Ok(mut volumes) = RikRepository::find_all(connection, "/volume") {
+        volumes.retain(|volume| caller_owns(volume.value["tenant_id"].as_str()));
+        elements_set_right_name(&mut volumes);
+        event!(Level::INFO, "volumes.get, volumes found");
+        Ok(list_response(req, &volumes, VOLUME_COLUMNS))
+    } else {
+        Err(RikError::Internal(String::from("Cannot find volumes")))
+    }
+}
+
+pub fn create(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let content = read_body(req)?;
+    let mut volume: Volume = serde_json::from_str(&content)?;
+
+    if volume.size_mb == 0 {
+        return Err(RikError::InvalidBody(String::from(
+            "Volume size must be greater than 0",
+        )));
+    }
+
+    let namespace = match request_namespace(req, connection, volume.namespace.as_deref()) {
+        Ok(namespace) => namespace,
+        Err(e) => {
+            event!(Level::WARN, "volumes.create, {}", e);
+            return Err(RikError::InvalidBody(e));
+        }
+    };
+    volume.tenant_id = resolve_tenant(connection, volume.tenant_id.as_deref(), req)?;
+    let name = volume_element_name(&namespace, &volume.name);
+
+    // Check name is not used, the volumes of the other tenants are not shown
+    if let Some((existing, _)) = find_volume(connection, &namespace, &volume.name) {
+        event!(Level::WARN, "volumes.create, name already used");
+        let message = format!(
+            "Volume {} already exists in namespace {}",
+            volume.name, namespace
+        );
+        if !caller_owns(existing.value["tenant_id"].as_str()) {
+            return Ok(error_response(409, ALREADY_EXISTS_CODE, message));
+        }
+        return Ok(conflict_response(message, &existing.id));
+    }
+
+    // Volumes are only bound when instances are scheduled
+    volume.namespace = Some(namespace);
+    volume.bound_to = None;
+    if let Ok(inserted_id) =
+        RikRepository::insert(connection, &name, &serde_json::to_string(&volume).unwrap())
+    {
+        event!(Level::INFO, "volumes.create, volume successfully created");
+        Ok(tiny_http::Response::from_string(
+            serde_json::to_string(&OnlyId { id: inserted_id }).unwrap(),
+        )
+        .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+        .with_status_code(tiny_http::StatusCode::from(200)))
+    } else {
+        event!(Level::ERROR, "volumes.create, cannot create volume");
+        Err(RikError::Internal(String::from("Cannot create volume")))
+    }
+}
+
+pub fn delete(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let OnlyId { id: delete_id } = match extract_request(req) {
+        Ok(request) => request,
+        Err(response) => return Ok(response),
+    };
+
+    if let Some(element) = RikRepository::find_one(connection, &delete_id, "/volume")
+        .ok()
+        .filter(|volume| caller_owns(volume.value["tenant_id"].as_str()))
+    {
+        let volume: Volume = serde_json::from_value(element.value)?;
+        if let Some(instance_id) = volume.bound_to {
+            event!(Level::WARN, "volumes.delete, volume is bound");
+            return Ok(error_response(
+                409,
+                VOLUME_ALREADY_BOUND,
+                format!(
+                    "Volume {} is bound to instance {}",
+                    volume.name, instance_id
+                ),
+            ));
+        }
+
+        RikRepository::delete(connection, &element.id).unwrap();
+        event!(Level::INFO, "volumes.delete, volume successfully deleted");
+        Ok(tiny_http::Response::from_string("").with_status_code(tiny_http::StatusCode::from(204)))
+    } else {
+        event!(Level::WARN, "volumes.delete, volume not found");
+        Err(RikError::NotFound(format!(
+            "Volume id {} not found",
+            delete_id
+        )))
+    }
+}
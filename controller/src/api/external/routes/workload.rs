@@ -1,38 +1,227 @@
-use crate::api;
-use crate::api::external::services::element::elements_set_right_name;
-use crate::api::types::element::OnlyId;
-use crate::api::{ApiChannel, Crud};
+use crate::api::external::services::admission::{AdmissionContext, AdmissionPipeline};
+use crate::api::external::services::csv::{page_response, WORKLOAD_COLUMNS};
+use crate::api::external::services::element::{
+    element_set_right_name, is_element_id, query_parameter, QueryParams,
+};
+use crate::api::external::services::instance::workload_instances;
+use crate::api::external::services::list::{
+    invalid_parameters_response, read_list, ListParams, WORKLOAD_LIST,
+};
+use crate::api::external::services::namespace::request_namespace;
+use crate::api::external::services::request::{
+    created_response, dry_run_response, expected_version, extract_request, is_dry_run, parse_body,
+    read_body, validation_response, BodyFormat, DRY_RUN_ID,
+};
+use crate::api::external::services::tenant::{
+    caller_owns, check_quota, client_tenant, resolve_tenant, QuotaResource,
+};
+use crate::api::external::services::workload::{
+    delete_workload, find_deletion_targets, find_workload_by_name, find_workloads_named,
+    find_workloads_page, instances_error, protection_error, protection_override, raw_manifest,
+    remove_workload, send_deletions, stored_value, wants_raw, workload_view,
+};
+use crate::api::limits::limit_from_env;
+use crate::api::response::{api_error_response, api_version, error_response};
+use crate::api::types::element::tenant_segment;
+use crate::api::types::element::{Element, OnlyId};
+use crate::api::types::error::ApiError;
+use crate::api::types::workload::parse_selector;
+use crate::api::types::workload::{
+    DeleteCollection, DeleteResult, DeleteStatus, ScaleWorkload, WorkloadReference,
+};
+use crate::api::validation::FieldError;
+use crate::api::ALREADY_EXISTS_CODE;
+use crate::api::{correlation, ApiChannel, Crud, RikError};
 use crate::core::instance::Instance;
-use crate::database::RikRepository;
+use crate::database::events::{EventRepository, CREATED_REASON};
+use crate::database::{RikRepository, UpdateError};
 use definition::workload::WorkloadDefinition;
 use route_recognizer;
 use rusqlite::Connection;
-use serde_json::json;
+use serde_json::{json, Value};
 use std::io;
 use std::str::FromStr;
 use std::sync::mpsc::Sender;
 use tiny_http::Response;
 use tracing::{event, Level};
 
-type HttpResult<T = io::Cursor<Vec<u8>>> = Result<Response<T>, api::RikError>;
+type HttpResult<T = io::Cursor<Vec<u8>>> = Result<Response<T>, RikError>;
+
+/// Workloads deleted at once without confirmation by `workloads.delete_collection`
+const DEFAULT_MAX_DELETE_COLLECTION: usize = 20;
 
 pub fn get(
-    _: &mut tiny_http::Request,
+    req: &mut tiny_http::Request,
     _: &route_recognizer::Params,
     connection: &Connection,
     _: &Sender<ApiChannel>,
 ) -> HttpResult {
-    if let Ok(mut workloads) = RikRepository::find_all(connection, "/workload") {
-        workloads = elements_set_right_name(workloads.clone());
-        let workloads_json = serde_json::to_string(&workloads).unwrap();
+    let mut params = match ListParams::parse(req.url(), &WORKLOAD_LIST) {
+        Ok(params) => params,
+        Err(errors) => return Ok(invalid_parameters_response(errors)),
+    };
+    // Clients acting for a tenant only see its workloads
+    if let Some(tenant) = client_tenant(req) {
+        params.tenant = Some(tenant);
+    }
+    let page = read_list(connection, |connection| {
+        find_workloads_page(connection, &params)
+    });
+    if let Ok(mut page) = page {
+        let raw = wants_raw(req.url());
+        // Filtered on the normalized definitions, whatever the view asked for
+        page.items = page
+            .items
+            .into_iter()
+            .map(|workload| workload_view(workload, raw))
+            .collect();
         event!(Level::INFO, "workloads.get, workloads found");
 
-        Ok(tiny_http::Response::from_string(workloads_json)
-            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
-            .with_status_code(tiny_http::StatusCode::from(200)))
+        Ok(page_response(req, &page, WORKLOAD_COLUMNS))
     } else {
-        Ok(tiny_http::Response::from_string("Cannot find workloads")
-            .with_status_code(tiny_http::StatusCode::from(500)))
+        Err(RikError::Internal(String::from("Cannot find workloads")))
+    }
+}
+
+/// Workload of an id, the ones of other tenants are not seen by a tenant key
+fn find_owned(connection: &Connection, id: &str) -> Option<Element> {
+    RikRepository::find_one(connection, &id.to_string(), "/workload")
+        .ok()
+        .filter(|workload| caller_owns(workload.value["tenant_id"].as_str()))
+}
+
+/// Definition of a workload, or the manifest as submitted with `?raw=true`
+pub fn get_one(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> HttpResult {
+    let workload_id = params.find("workloadid").unwrap_or_default().to_string();
+    if !is_element_id(&workload_id) {
+        return Ok(error_response(
+            400,
+            "InvalidId",
+            format!("Workload id {} is not a valid id", workload_id),
+        ));
+    }
+    let workload = match find_owned(connection, &workload_id) {
+        Some(workload) => workload,
+        None => {
+            event!(Level::WARN, "workloads.get, workload not found");
+            return Err(RikError::NotFound(format!(
+                "Workload id {} not found",
+                workload_id
+            )));
+        }
+    };
+
+    let mut content_type = "application/json";
+    let body = if wants_raw(req.url()) {
+        // Sent byte for byte, so it can be diffed with the applied file
+        match raw_manifest(&workload) {
+            Some(manifest) => {
+                if serde_json::from_str::<serde_json::Value>(manifest).is_err() {
+                    content_type = "application/yaml";
+                }
+                manifest.to_string()
+            }
+            None => {
+                return Err(RikError::NotFound(format!(
+                    "No manifest kept as submitted for workload {}",
+                    workload_id
+                )))
+            }
+        }
+    } else {
+        workload_view(workload, false).value.to_string()
+    };
+
+    Ok(tiny_http::Response::from_string(body)
+        .with_header(
+            tiny_http::Header::from_str(&format!("Content-Type: {}", content_type)).unwrap(),
+        )
+        .with_status_code(tiny_http::StatusCode::from(200)))
+}
+
+/// Workload found by its name with `?name=web`, in the namespace given by
+/// `?namespace=` or else the default one of the request
+pub fn get_by_name(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    _: &Sender<ApiChannel>,
+) -> HttpResult {
+    let url = req.url().to_string();
+    let parameters = QueryParams::parse(&url);
+    let parameter = |key| parameters.decoded(key);
+    let Some(name) = parameter("name").filter(|name| !name.is_empty()) else {
+        return Ok(invalid_parameters_response(vec![FieldError::new(
+            "name",
+            "The name of the workload must be given",
+        )]));
+    };
+    let mut workload = match find_named(req, connection, &name, parameter("namespace").as_deref()) {
+        Ok(workload) => workload,
+        Err(response) => return Ok(response),
+    };
+    element_set_right_name(&mut workload);
+    Ok(tiny_http::Response::from_string(
+        serde_json::to_string(&workload_view(workload, wants_raw(&url))).unwrap(),
+    )
+    .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+    .with_status_code(tiny_http::StatusCode::from(200)))
+}
+
+/// Workload of that name in a namespace, resolved from the defaults of the
+/// request when not given, and in the tenant of the request.
+///
+/// Answered with a `404` when there is none, and with a `409` listing their
+/// ids when several share the name, which only databases from before the
+/// names were unique hold.
+fn find_named(
+    req: &tiny_http::Request,
+    connection: &Connection,
+    name: &str,
+    namespace: Option<&str>,
+) -> Result<Element, Response<io::Cursor<Vec<u8>>>> {
+    let namespace = request_namespace(req, connection, namespace)
+        .map_err(|e| RikError::InvalidBody(e).response())?;
+    let mut found =
+        find_workloads_named(connection, client_tenant(req).as_deref(), &namespace, name).map_err(
+            |e| {
+                event!(Level::ERROR, "workloads.get, cannot find workload: {}", e);
+                RikError::Internal(String::from("Cannot find workload")).response()
+            },
+        )?;
+    match found.len() {
+        0 => Err(RikError::NotFound(format!(
+            "Workload {} not found in namespace {}",
+            name, namespace
+        ))
+        .response()),
+        1 => Ok(found.remove(0)),
+        _ => {
+            event!(
+                Level::WARN,
+                "workloads.get, several workloads named {}",
+                name
+            );
+            let ids: Vec<String> = found.into_iter().map(|workload| workload.id).collect();
+            Err(api_error_response(
+                409,
+                &ApiError {
+                    details: Some(json!({ "ids": ids })),
+                    ..ApiError::new(
+                        "AmbiguousName",
+                        format!(
+                            "Several workloads are named {} in namespace {}, use their id",
+                            name, namespace
+                        ),
+                    )
+                },
+            ))
+        }
     }
 }
 
@@ -45,16 +234,27 @@ pub fn get_instances(
     let workload_id = params.find("workloadid").unwrap_or_default();
 
     if workload_id.is_empty() {
-        return Ok(tiny_http::Response::from_string("No workload id provided")
-            .with_status_code(tiny_http::StatusCode::from(400)));
+        return Err(RikError::InvalidBody(String::from(
+            "No workload id provided",
+        )));
+    }
+    if find_owned(connection, workload_id).is_none() {
+        event!(Level::WARN, "workloads.instances, workload not found");
+        return Err(RikError::NotFound(format!(
+            "Workload id {} not found",
+            workload_id
+        )));
     }
 
-    // That's dirty and we know it, however it's the easiest way to do for now.
-    if let Ok(elements) = RikRepository::find_all(connection, "/instance") {
+    if let Ok(elements) = workload_instances(connection, workload_id) {
         let instances: Vec<Instance> = elements
             .iter()
             .map(|e| serde_json::from_value(e.clone().value).unwrap())
-            .filter(|instance: &Instance| instance.workload_id == workload_id)
+            .map(|mut instance: Instance| {
+                // Conditions are only exposed by the v1 API
+                instance.conditions.clear();
+                instance
+            })
             .collect();
 
         if instances.is_empty() {
@@ -69,10 +269,83 @@ pub fn get_instances(
             .with_status_code(tiny_http::StatusCode::from(200)));
     }
 
-    Ok(
-        tiny_http::Response::from_string("Could not find workload instances")
-            .with_status_code(tiny_http::StatusCode::from(404)),
-    )
+    Err(RikError::NotFound(String::from(
+        "Could not find workload instances",
+    )))
+}
+
+/// Parse a workload definition and run it through the admission checks,
+/// along with the namespace it goes in
+fn admit_workload(
+    req: &tiny_http::Request,
+    content: &str,
+    connection: &Connection,
+    route: &str,
+    update: bool,
+) -> Result<(WorkloadDefinition, String), Response<io::Cursor<Vec<u8>>>> {
+    let workload: WorkloadDefinition =
+        parse_body(content, BodyFormat::of(req)).map_err(validation_response)?;
+    admit_definition(req, workload, connection, route, update).map_err(Refusal::response)
+}
+
+/// Why a workload definition is not admitted
+enum Refusal {
+    /// The namespace cannot be used, answered with a `400`
+    Namespace(String),
+    /// Denied by an admission check, answered with a `422`
+    Denied(String),
+}
+
+impl Refusal {
+    fn response(self) -> Response<io::Cursor<Vec<u8>>> {
+        match self {
+            Refusal::Namespace(message) => RikError::InvalidBody(message).response(),
+            Refusal::Denied(reason) => validation_response(vec![FieldError::body(reason)]),
+        }
+    }
+
+    fn field_error(self) -> FieldError {
+        match self {
+            Refusal::Namespace(message) => FieldError::new("namespace", message),
+            Refusal::Denied(reason) => FieldError::body(reason),
+        }
+    }
+}
+
+/// Run a parsed workload definition through the admission checks, along
+/// with the namespace it goes in
+fn admit_definition(
+    req: &tiny_http::Request,
+    workload: WorkloadDefinition,
+    connection: &Connection,
+    route: &str,
+    update: bool,
+) -> Result<(WorkloadDefinition, String), Refusal> {
+    let namespace =
+        request_namespace(req, connection, workload.namespace.as_deref()).map_err(|e| {
+            event!(Level::WARN, "{}, {}", route, e);
+            Refusal::Namespace(e)
+        })?;
+
+    let context = AdmissionContext {
+        connection,
+        namespace: &namespace,
+        update,
+    };
+    let fast = query_parameter(req.url(), "fast") == Some("true");
+    let workload = AdmissionPipeline::from_env()
+        .run(&context, workload, fast)
+        .map_err(|denied| {
+            event!(
+                Level::WARN,
+                "{}, denied by {}: {}",
+                route,
+                denied.check,
+                denied.reason
+            );
+            Refusal::Denied(denied.reason)
+        })?;
+    Ok((workload, namespace))
 }
 
 pub fn create(
@@ -81,69 +354,555 @@ pub fn create(
     connection: &Connection,
     _: &Sender<ApiChannel>,
 ) -> HttpResult {
-    let mut content = String::new();
-    req.as_reader().read_to_string(&mut content).unwrap();
+    let content = read_body(req)?;
 
-    let mut workload: WorkloadDefinition = serde_json::from_str(&content)?;
-    if workload.replicas.is_none() {
-        workload.replicas = Some(1);
-    }
-    let namespace = "default";
-    let name = format!(
-        "/workload/{}/{}/{}",
-        workload.kind, namespace, workload.name
-    );
+    let (mut workload, namespace) =
+        match admit_workload(req, &content, connection, "workload.create", false) {
+            Ok(admitted) => admitted,
+            Err(response) => return Ok(response),
+        };
+    workload.tenant_id = resolve_tenant(connection, workload.tenant_id.as_deref(), req)?;
+    check_quota(
+        connection,
+        workload.tenant_id.as_deref(),
+        QuotaResource::Workloads,
+        1,
+    )?;
+    let name = full_name(&workload, &namespace);
 
     // Check name is not used
-    if RikRepository::check_duplicate_name(connection, &name).is_ok() {
+    if let Ok(existing) = RikRepository::check_duplicate_name(connection, &name) {
         event!(Level::WARN, "workload.create, name already used");
-        return Ok(tiny_http::Response::from_string("Name already used")
-            .with_status_code(tiny_http::StatusCode::from(404)));
+        return Err(RikError::Conflict(
+            format!(
+                "Workload {} already exists in namespace {}",
+                workload.name, namespace
+            ),
+            existing.id,
+        ));
+    }
+    if is_dry_run(req) {
+        event!(Level::INFO, "workload.create, dry run, nothing created");
+        return Ok(dry_run_response(200, json!({ "id": DRY_RUN_ID })));
     }
 
-    if let Ok(inserted_id) = RikRepository::insert(
-        connection,
-        &name,
-        &serde_json::to_string(&workload).unwrap(),
-    ) {
-        let workload_id: OnlyId = OnlyId { id: inserted_id };
+    // The workload is only kept along with the event of its creation
+    let inserted = RikRepository::transaction(connection, |tx| {
+        let inserted_id =
+            RikRepository::insert(tx, &name, &stored_value(&workload, &content).to_string())?;
+        EventRepository::insert(
+            tx,
+            &inserted_id,
+            CREATED_REASON,
+            &format!(
+                "Workload {} created in namespace {}",
+                workload.name, namespace
+            ),
+        )?;
+        Ok::<_, rusqlite::Error>(inserted_id)
+    });
+    if let Ok(inserted_id) = inserted {
         event!(
             Level::INFO,
             "workload.create, workload successfully created"
         );
-        Ok(
-            tiny_http::Response::from_string(serde_json::to_string(&workload_id).unwrap())
-                .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
-                .with_status_code(tiny_http::StatusCode::from(200)),
-        )
+        let mut created = RikRepository::find_one(connection, &inserted_id, "/workload")
+            .map_err(|_| RikError::Internal(String::from("Cannot read the workload created")))?;
+        element_set_right_name(&mut created);
+        Ok(created_response(
+            &workload_location(&inserted_id),
+            &serde_json::to_value(workload_view(created, false)).unwrap(),
+        ))
     } else {
         event!(Level::ERROR, "workload.create, cannot create workload");
-        Ok(tiny_http::Response::from_string("Cannot create workload")
-            .with_status_code(tiny_http::StatusCode::from(500)))
+        Err(RikError::Internal(String::from("Cannot create workload")))
     }
 }
 
-pub fn delete(
+/// Path a workload is read from, in the version of the API of the request
+fn workload_location(id: &str) -> String {
+    match api_version() {
+        Some(version) => format!("/api/{}/workloads/{}", version, id),
+        None => format!("/api/v0/workloads.get/{}", id),
+    }
+}
+
+/// Name of a workload element, e.g. `/workload/Pod/default/web`
+fn full_name(workload: &WorkloadDefinition, namespace: &str) -> String {
+    format!(
+        "/workload/{}{}/{}/{}",
+        tenant_segment(workload.tenant_id.as_deref()),
+        workload.kind,
+        namespace,
+        workload.name
+    )
+}
+
+/// Create several workloads from an array of definitions, all of them or
+/// none. Every definition is admitted before any is inserted, the errors
+/// name the index of their definition, e.g. `[2].name`.
+///
+/// The names must not be used, nor given twice in the array, the `409`
+/// gives the index of the first definition in the way. The ids of the
+/// workloads are answered in the order of the definitions.
+pub fn create_bulk(
     req: &mut tiny_http::Request,
     _: &route_recognizer::Params,
     connection: &Connection,
-    internal_sender: &Sender<ApiChannel>,
+    _: &Sender<ApiChannel>,
 ) -> HttpResult {
-    let mut content = String::new();
-    req.as_reader().read_to_string(&mut content).unwrap();
-    let OnlyId { id: delete_id } = serde_json::from_str(&content)?;
-
-    if let Ok(workload) = RikRepository::find_one(connection, &delete_id, "/workload") {
-        let definition: WorkloadDefinition = serde_json::from_value(workload.value).unwrap();
-        internal_sender
-            .send(ApiChannel {
-                action: Crud::Delete,
-                workload_id: Some(delete_id),
-                workload_definition: Some(definition),
-                instance_id: None,
+    let content = read_body(req)?;
+    let items = match parse_bulk(&content, BodyFormat::of(req)) {
+        Ok(items) => items,
+        Err(error) => return Ok(validation_response(vec![error])),
+    };
+
+    let mut errors = Vec::new();
+    let mut admitted = Vec::new();
+    for (index, item) in items.iter().enumerate() {
+        let in_entry = |error: FieldError| FieldError {
+            field: Some(match error.field {
+                Some(field) => format!("[{}].{}", index, field),
+                None => format!("[{}]", index),
+            }),
+            message: error.message,
+        };
+        let workload = match parse_body::<WorkloadDefinition>(&item.to_string(), BodyFormat::Json) {
+            Ok(workload) => workload,
+            Err(item_errors) => {
+                errors.extend(item_errors.into_iter().map(in_entry));
+                continue;
+            }
+        };
+        match admit_definition(req, workload, connection, "workload.create_bulk", false) {
+            Ok((mut workload, namespace)) => {
+                workload.tenant_id =
+                    resolve_tenant(connection, workload.tenant_id.as_deref(), req)?;
+                admitted.push((full_name(&workload, &namespace), workload, item));
+            }
+            Err(refusal) => errors.push(in_entry(refusal.field_error())),
+        }
+    }
+    if !errors.is_empty() {
+        event!(Level::WARN, "workload.create_bulk, invalid definitions");
+        return Ok(validation_response(errors));
+    }
+
+    for (index, (name, workload, _)) in admitted.iter().enumerate() {
+        if let Some(first) = admitted[..index]
+            .iter()
+            .position(|(other, _, _)| other == name)
+        {
+            event!(Level::WARN, "workload.create_bulk, name given twice");
+            return Ok(bulk_conflict(
+                index,
+                format!(
+                    "Workload {} of entry {} is already given by entry {}",
+                    workload.name, index, first
+                ),
+                None,
+            ));
+        }
+    }
+    // Every tenant gets all of its workloads, or none
+    let mut by_tenant: Vec<(Option<&str>, usize)> = Vec::new();
+    for (_, workload, _) in &admitted {
+        let tenant_id = workload.tenant_id.as_deref();
+        match by_tenant.iter_mut().find(|(other, _)| *other == tenant_id) {
+            Some((_, count)) => *count += 1,
+            None => by_tenant.push((tenant_id, 1)),
+        }
+    }
+    for (tenant_id, count) in by_tenant {
+        check_quota(connection, tenant_id, QuotaResource::Workloads, count)?;
+    }
+
+    let created = RikRepository::transaction(connection, |tx| {
+        for (index, (name, workload, _)) in admitted.iter().enumerate() {
+            if let Ok(existing) = RikRepository::check_duplicate_name(tx, name) {
+                return Err(BulkFailure::Conflict(bulk_conflict(
+                    index,
+                    format!(
+                        "Workload {} of entry {} already exists in its namespace",
+                        workload.name, index
+                    ),
+                    Some(existing.id),
+                )));
+            }
+        }
+        let items: Vec<(String, String)> = admitted
+            .iter()
+            .map(|(name, workload, item)| {
+                let value = stored_value(workload, &item.to_string()).to_string();
+                (name.clone(), value)
             })
-            .unwrap();
-        RikRepository::delete(connection, &workload.id).unwrap();
+            .collect();
+        let ids = RikRepository::insert_many(tx, &items)?;
+        for (id, (_, workload, _)) in ids.iter().zip(&admitted) {
+            EventRepository::insert(
+                tx,
+                id,
+                CREATED_REASON,
+                &format!("Workload {} created", workload.name),
+            )?;
+        }
+        Ok(ids)
+    });
+    let ids = match created {
+        Ok(ids) => ids,
+        Err(BulkFailure::Conflict(conflict)) => {
+            event!(Level::WARN, "workload.create_bulk, name already used");
+            return Ok(conflict);
+        }
+        Err(BulkFailure::Database(e)) => {
+            event!(
+                Level::ERROR,
+                "workload.create_bulk, cannot create workloads"
+            );
+            return Err(RikError::Internal(format!(
+                "Cannot create workloads: {}",
+                e
+            )));
+        }
+    };
+
+    event!(
+        Level::INFO,
+        "workload.create_bulk, {} workloads created",
+        ids.len()
+    );
+    let ids: Vec<OnlyId> = ids.into_iter().map(|id| OnlyId { id }).collect();
+    Ok(
+        tiny_http::Response::from_string(serde_json::to_string(&ids).unwrap())
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200)),
+    )
+}
+
+/// Why the transaction of a bulk creation was rolled back
+enum BulkFailure {
+    Conflict(Response<io::Cursor<Vec<u8>>>),
+    Database(rusqlite::Error),
+}
+
+impl From<rusqlite::Error> for BulkFailure {
+    fn from(error: rusqlite::Error) -> Self {
+        BulkFailure::Database(error)
+    }
+}
+
+/// Definitions of a bulk creation, a non empty array
+fn parse_bulk(content: &str, format: BodyFormat) -> Result<Vec<Value>, FieldError> {
+    let value: Result<Value, String> = match format {
+        BodyFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+        BodyFormat::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+    };
+    match value {
+        Ok(Value::Array(items)) if !items.is_empty() => Ok(items),
+        Ok(Value::Array(_)) => Err(FieldError::body(
+            "The array of workload definitions is empty",
+        )),
+        Ok(_) => Err(FieldError::body(format!(
+            "The request body must be a {} array of workload definitions",
+            format
+        ))),
+        Err(e) => Err(FieldError::body(format!(
+            "The request body is not valid {}: {}",
+            format, e
+        ))),
+    }
+}
+
+/// Answer to a bulk creation with a name already used, naming its entry
+fn bulk_conflict(
+    index: usize,
+    message: String,
+    existing_id: Option<String>,
+) -> Response<io::Cursor<Vec<u8>>> {
+    api_error_response(
+        409,
+        &ApiError {
+            details: Some(json!({ "index": index })),
+            id: existing_id,
+            ..ApiError::new(ALREADY_EXISTS_CODE, message)
+        },
+    )
+}
+
+/// Replace the definition of a workload, found by its name, keeping its id.
+///
+/// Its instances are rolled out to the new definition, the kind of a workload
+/// cannot be changed. On `PUT /api/v0/workloads/:id`, the name of the manifest
+/// must be the one of the workload with that id.
+pub fn update(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    connection: &Connection,
+    internal_sender: &Sender<ApiChannel>,
+) -> HttpResult {
+    let content = read_body(req)?;
+
+    let (mut workload, namespace) =
+        match admit_workload(req, &content, connection, "workload.update", true) {
+            Ok(admitted) => admitted,
+            Err(response) => return Ok(response),
+        };
+    let found = find_workload_by_name(
+        connection,
+        client_tenant(req).as_deref(),
+        &namespace,
+        &workload.name,
+    );
+    if let Some(id) = params.find("id") {
+        if found.as_ref().map(|element| element.id.as_str()) != Some(id) {
+            if find_owned(connection, id).is_none() {
+                event!(Level::WARN, "workload.update, workload not found");
+                return Err(RikError::NotFound(format!("Workload {} not found", id)));
+            }
+            event!(Level::WARN, "workload.update, name changed");
+            return Ok(error_response(
+                400,
+                "NameChanged",
+                format!(
+                    "Workload {} is not named {} in namespace {}, its name cannot be changed",
+                    id, workload.name, namespace
+                ),
+            ));
+        }
+    }
+    let Some(mut element) = found else {
+        event!(Level::WARN, "workload.update, workload not found");
+        return Err(RikError::NotFound(format!(
+            "Workload {} not found in namespace {}",
+            workload.name, namespace
+        )));
+    };
+    let current: WorkloadDefinition = match serde_json::from_value(element.value.clone()) {
+        Ok(current) => current,
+        Err(e) => {
+            event!(
+                Level::ERROR,
+                "workload.update, cannot parse workload: {}",
+                e
+            );
+            return Err(RikError::Internal(String::from("Cannot update workload")));
+        }
+    };
+    // The tenant of a workload is given at its creation only
+    workload.tenant_id = current.tenant_id.clone();
+    if current.kind != workload.kind {
+        event!(Level::WARN, "workload.update, kind changed");
+        return Ok(error_response(
+            400,
+            "KindChanged",
+            format!(
+                "Workload {} is a {}, its kind cannot be changed to {}",
+                workload.name, current.kind, workload.kind
+            ),
+        ));
+    }
+
+    let value = stored_value(&workload, &content);
+    // Written as read, unless the client read it at another version
+    let version = expected_version(req)?
+        .or(element.version)
+        .unwrap_or_default();
+    match RikRepository::update_if_version(connection, &element.id, &value.to_string(), version) {
+        Ok(version) => element.version = Some(version),
+        Err(UpdateError::Conflict(conflict)) => {
+            event!(Level::WARN, "workload.update, workload changed meanwhile");
+            return Err(RikError::VersionConflict(conflict));
+        }
+        Err(UpdateError::Database(e)) => {
+            event!(
+                Level::ERROR,
+                "workload.update, cannot update workload: {}",
+                e
+            );
+            return Err(RikError::Internal(String::from("Cannot update workload")));
+        }
+    }
+    // Instances are only replaced when their definition changed
+    if current != workload {
+        let notification = ApiChannel {
+            action: Crud::Update,
+            workload_id: Some(element.id.clone()),
+            workload_definition: Some(workload),
+            instance_id: None,
+            overrides: None,
+            namespace: Some(namespace),
+            tenant_id: None,
+            correlation_id: correlation::current(),
+        };
+        if let Err(e) = internal_sender.send(notification) {
+            event!(Level::ERROR, "workload.update, cannot roll out: {}", e);
+            return Err(RikError::Internal(String::from("Cannot roll out workload")));
+        }
+    }
+
+    event!(
+        Level::INFO,
+        "workload.update, workload successfully updated"
+    );
+    element.value = value;
+    element_set_right_name(&mut element);
+    Ok(tiny_http::Response::from_string(
+        serde_json::to_string(&workload_view(element, false)).unwrap(),
+    )
+    .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+    .with_status_code(tiny_http::StatusCode::from(200)))
+}
+
+/// Change the replica count of a workload without submitting its definition
+/// again, its instances are created or deleted to match it
+pub fn scale(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    internal_sender: &Sender<ApiChannel>,
+) -> HttpResult {
+    let request: ScaleWorkload = match extract_request(req) {
+        Ok(request) => request,
+        Err(response) => return Ok(response),
+    };
+    let Some(replicas) = request.replicas() else {
+        event!(Level::WARN, "workload.scale, invalid replica count");
+        return Err(RikError::InvalidBody(format!(
+            "The replica count must be between 0 and {}, not {}",
+            u16::MAX,
+            request.replicas
+        )));
+    };
+    let Some(mut element) = find_owned(connection, &request.id) else {
+        event!(Level::WARN, "workload.scale, workload not found");
+        return Err(RikError::NotFound(format!(
+            "Workload id {} not found",
+            request.id
+        )));
+    };
+    let mut workload: WorkloadDefinition = match serde_json::from_value(element.value.clone()) {
+        Ok(workload) => workload,
+        Err(e) => {
+            event!(Level::ERROR, "workload.scale, cannot parse workload: {}", e);
+            return Err(RikError::Internal(String::from("Cannot scale workload")));
+        }
+    };
+    // Only the instances added count against the quota of the tenant
+    let added = replicas.saturating_sub(workload.replicas.unwrap_or(1));
+    if added > 0 {
+        check_quota(
+            connection,
+            workload.tenant_id.as_deref(),
+            QuotaResource::Instances,
+            usize::from(added),
+        )?;
+    }
+
+    // The manifest as submitted is kept, only the definition is scaled
+    workload.replicas = Some(replicas);
+    element.value["replicas"] = json!(replicas);
+    let version = expected_version(req)?
+        .or(element.version)
+        .unwrap_or_default();
+    let value = element.value.to_string();
+    match RikRepository::update_if_version(connection, &element.id, &value, version) {
+        Ok(version) => element.version = Some(version),
+        Err(UpdateError::Conflict(conflict)) => {
+            event!(Level::WARN, "workload.scale, workload changed meanwhile");
+            return Err(RikError::VersionConflict(conflict));
+        }
+        Err(UpdateError::Database(e)) => {
+            event!(
+                Level::ERROR,
+                "workload.scale, cannot update workload: {}",
+                e
+            );
+            return Err(RikError::Internal(String::from("Cannot scale workload")));
+        }
+    }
+    let notification = ApiChannel {
+        action: Crud::Scale,
+        workload_id: Some(element.id.clone()),
+        namespace: workload.namespace.clone(),
+        workload_definition: Some(workload),
+        instance_id: None,
+        overrides: None,
+        tenant_id: None,
+        correlation_id: correlation::current(),
+    };
+    if let Err(e) = internal_sender.send(notification) {
+        event!(
+            Level::ERROR,
+            "workload.scale, cannot scale instances: {}",
+            e
+        );
+        return Err(RikError::Internal(String::from("Cannot scale workload")));
+    }
+
+    event!(
+        Level::INFO,
+        "workload.scale, workload scaled to {} replicas",
+        replicas
+    );
+    element_set_right_name(&mut element);
+    Ok(tiny_http::Response::from_string(
+        serde_json::to_string(&workload_view(element, false)).unwrap(),
+    )
+    .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+    .with_status_code(tiny_http::StatusCode::from(200)))
+}
+
+pub fn delete(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    connection: &Connection,
+    internal_sender: &Sender<ApiChannel>,
+) -> HttpResult {
+    let delete_id = match params.find("id") {
+        Some(id) => id.to_string(),
+        None => match extract_request::<WorkloadReference>(req) {
+            Ok(WorkloadReference { id: Some(id), .. }) => id,
+            Ok(WorkloadReference {
+                name: Some(name),
+                namespace,
+                ..
+            }) => match find_named(req, connection, &name, namespace.as_deref()) {
+                Ok(workload) => workload.id,
+                Err(response) => return Ok(response),
+            },
+            Ok(_) => unreachable!("an id or a name is validated"),
+            Err(response) => return Ok(response),
+        },
+    };
+
+    if let Some(mut workload) = find_owned(connection, &delete_id) {
+        element_set_right_name(&mut workload);
+        let overridden_by = protection_override(req);
+        if let Some(error) = protection_error(&workload, overridden_by.as_deref()) {
+            event!(Level::WARN, "workload.delete, workload protected");
+            return Ok(error_response(409, "Protected", error));
+        }
+        // Its instances are only deleted along with it when asked for
+        let force = query_parameter(req.url(), "force") == Some("true");
+        if let Some(error) = instances_error(connection, &workload, force)? {
+            event!(Level::WARN, "workload.delete, workload has instances");
+            return Ok(api_error_response(409, &error));
+        }
+        // A workload which would be deleted is answered with its id, a `204` has no body
+        if is_dry_run(req) {
+            event!(Level::INFO, "workload.delete, dry run, nothing deleted");
+            return Ok(dry_run_response(200, json!({ "id": workload.id })));
+        }
+        if let Err(e) = delete_workload(
+            connection,
+            internal_sender,
+            &workload,
+            overridden_by.as_deref(),
+            force,
+        ) {
+            event!(Level::ERROR, "workload.delete, {}", e);
+            return Err(RikError::Internal(e));
+        }
 
         event!(
             Level::INFO,
@@ -152,9 +911,166 @@ pub fn delete(
         Ok(tiny_http::Response::from_string("").with_status_code(tiny_http::StatusCode::from(204)))
     } else {
         event!(Level::WARN, "workload.delete, workload not found");
-        Ok(
-            tiny_http::Response::from_string(format!("Workload id {} not found", delete_id))
-                .with_status_code(tiny_http::StatusCode::from(404)),
-        )
+        Err(RikError::NotFound(format!(
+            "Workload id {} not found",
+            delete_id
+        )))
+    }
+}
+
+/// Delete several workloads, by id, by name or by label selector.
+///
+/// Deleting more than `MAX_DELETE_COLLECTION` workloads must be confirmed with
+/// `?confirm_count=N`, `?dry_run=true` gives the workloads that would be deleted
+/// without needing a confirmation.
+/// Failures are reported per workload, `?atomic=true` deletes nothing when a
+/// workload is not found and stops at the first failure.
+pub fn delete_collection(
+    req: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    connection: &Connection,
+    internal_sender: &Sender<ApiChannel>,
+) -> HttpResult {
+    let request: DeleteCollection = match extract_request(req) {
+        Ok(request) => request,
+        Err(response) => return Ok(response),
+    };
+
+    let bad_request = |message: String| {
+        event!(Level::WARN, "workload.delete_collection, {}", message);
+        Err(RikError::InvalidBody(message))
+    };
+    let namespace = match request_namespace(req, connection, request.namespace.as_deref()) {
+        Ok(namespace) => namespace,
+        Err(e) => return bad_request(e),
+    };
+    // The selector is checked when extracting the request
+    let selector = request
+        .selector
+        .as_deref()
+        .and_then(|selector| parse_selector(selector).ok())
+        .unwrap_or_default();
+
+    let (targets, mut results) = find_deletion_targets(connection, &request, &namespace, &selector);
+
+    let url = req.url().to_string();
+    let confirm_count = match query_parameter(&url, "confirm_count").map(str::parse::<usize>) {
+        Some(Ok(count)) => Some(count),
+        Some(Err(_)) => return bad_request(String::from("confirm_count must be a number")),
+        None => None,
+    };
+    let dry_run = is_dry_run(req);
+    let max_count = limit_from_env("MAX_DELETE_COLLECTION", DEFAULT_MAX_DELETE_COLLECTION);
+    if let Some(count) = confirm_count.filter(|count| *count != targets.len()) {
+        return bad_request(format!(
+            "confirm_count {} does not match the {} workloads to delete",
+            count,
+            targets.len()
+        ));
+    }
+    if !dry_run && targets.len() > max_count && confirm_count.is_none() {
+        return bad_request(format!(
+            "{} workloads would be deleted, more than {}, confirm with ?confirm_count={}",
+            targets.len(),
+            max_count,
+            targets.len()
+        ));
+    }
+
+    let atomic = query_parameter(&url, "atomic") == Some("true");
+    let force = query_parameter(&url, "force") == Some("true");
+    let overridden_by = protection_override(req);
+    // Known before deleting anything, an atomic deletion refusing a workload
+    // deletes none
+    let mut refusals = Vec::new();
+    for workload in &targets {
+        refusals.push(match protection_error(workload, overridden_by.as_deref()) {
+            Some(error) => Some((DeleteStatus::Protected, error)),
+            None => instances_error(connection, workload, force)?
+                .map(|error| (DeleteStatus::HasInstances, error.message)),
+        });
     }
+    let refused = refusals.iter().any(Option::is_some);
+    let mut aborted = atomic && (!results.is_empty() || refused);
+
+    // An atomic deletion deletes every workload in a single transaction, the
+    // core is only told to delete their instances once it is committed
+    let mut failure: Option<(Option<String>, String)> = None;
+    if atomic && !aborted && !dry_run {
+        let mut failed_id = None;
+        let deleted = RikRepository::transaction(connection, |tx| {
+            let mut deletions = Vec::new();
+            for workload in &targets {
+                failed_id = Some(workload.id.clone());
+                deletions.extend(remove_workload(
+                    tx,
+                    workload,
+                    overridden_by.as_deref(),
+                    force,
+                )?);
+            }
+            failed_id = None;
+            Ok::<_, RikError>(deletions)
+        });
+        match deleted {
+            Ok(deletions) => {
+                send_deletions(internal_sender, deletions).map_err(RikError::Internal)?
+            }
+            Err(e) => {
+                event!(Level::ERROR, "workload.delete_collection, {}", e);
+                aborted = true;
+                failure = Some((failed_id, e.to_string()));
+            }
+        }
+    }
+
+    for (workload, refusal) in targets.into_iter().zip(refusals) {
+        let (status, message) = match refusal {
+            Some((status, error)) => (status, Some(error)),
+            None if dry_run => (DeleteStatus::Matched, None),
+            // The workload failing an atomic deletion, every one when the
+            // commit failed
+            None if aborted => match &failure {
+                Some((failed_id, e))
+                    if failed_id.is_none() || failed_id.as_ref() == Some(&workload.id) =>
+                {
+                    (DeleteStatus::Failed, Some(e.clone()))
+                }
+                _ => (DeleteStatus::Skipped, None),
+            },
+            None if atomic => (DeleteStatus::Deleted, None),
+            None => match delete_workload(
+                connection,
+                internal_sender,
+                &workload,
+                overridden_by.as_deref(),
+                force,
+            ) {
+                Ok(()) => (DeleteStatus::Deleted, None),
+                Err(e) => (DeleteStatus::Failed, Some(e)),
+            },
+        };
+        results.push(DeleteResult {
+            id: workload.id,
+            name: workload.name,
+            status,
+            message,
+        });
+    }
+
+    event!(
+        Level::INFO,
+        "workload.delete_collection, {} workloads processed",
+        results.len()
+    );
+    let status_code = if aborted || (refused && !dry_run) {
+        409
+    } else {
+        200
+    };
+    Ok(
+        tiny_http::Response::from_string(json!({ "results": results }).to_string())
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(status_code)),
+    )
 }
@@ -0,0 +1,583 @@
+use crate::api::limits::env_limits;
+use crate::database::RikRepository;
+use definition::workload::{EnvLimits, WorkloadDefinition};
+use rusqlite::Connection;
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// Time given to the host of a function rootfs to accept a connection
+const ROOTFS_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether a check can run on every request or may wait on the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdmissionCost {
+    Cheap,
+    MayDoIo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdmissionDecision {
+    Allow,
+    Deny(String),
+    /// Replace the workload, the next checks see the mutated one
+    Mutate(Box<WorkloadDefinition>),
+}
+
+/// Check denying the workload and its reason
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdmissionDenied {
+    pub check: &'static str,
+    pub reason: String,
+}
+
+/// Request a workload is admitted for
+pub struct AdmissionContext<'a> {
+    pub connection: &'a Connection,
+    pub namespace: &'a str,
+    /// The workload replaces a stored one of the same name
+    pub update: bool,
+}
+
+pub trait AdmissionCheck {
+    fn name(&self) -> &'static str;
+    fn cost(&self) -> AdmissionCost;
+    fn admit(&self, context: &AdmissionContext, workload: &WorkloadDefinition)
+        -> AdmissionDecision;
+}
+
+/// Ordered checks a workload goes through before being stored
+pub struct AdmissionPipeline {
+    checks: Vec<Box<dyn AdmissionCheck>>,
+}
+
+impl AdmissionPipeline {
+    pub fn new(checks: Vec<Box<dyn AdmissionCheck>>) -> AdmissionPipeline {
+        AdmissionPipeline { checks }
+    }
+
+    /// Checks configured for the cluster, cheap ones first
+    pub fn from_env() -> AdmissionPipeline {
+        AdmissionPipeline::new(vec![
+            Box::new(DefaultReplicas),
+            Box::new(SchemaValidation {
+                limits: env_limits(),
+            }),
+            Box::new(NamePolicy),
+            Box::new(NamespaceQuota {
+                max_workloads: std::env::var("MAX_WORKLOADS_PER_NAMESPACE")
+                    .ok()
+                    .and_then(|value| value.parse().ok()),
+            }),
+            Box::new(DenyList {
+                denied: std::env::var("DENIED_IMAGES")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|prefix| !prefix.is_empty())
+                    .map(String::from)
+                    .collect(),
+            }),
+            Box::new(RootfsVerification {
+                timeout: ROOTFS_CONNECT_TIMEOUT,
+            }),
+        ])
+    }
+
+    /// Run the checks in order, stopping at the first denial.
+    ///
+    /// Checks that may do IO are skipped when `fast` is set.
+    pub fn run(
+        &self,
+        context: &AdmissionContext,
+        mut workload: WorkloadDefinition,
+        fast: bool,
+    ) -> Result<WorkloadDefinition, AdmissionDenied> {
+        for check in &self.checks {
+            if fast && check.cost() == AdmissionCost::MayDoIo {
+                continue;
+            }
+            match check.admit(context, &workload) {
+                AdmissionDecision::Allow => {}
+                AdmissionDecision::Deny(reason) => {
+                    return Err(AdmissionDenied {
+                        check: check.name(),
+                        reason,
+                    })
+                }
+                AdmissionDecision::Mutate(mutated) => workload = *mutated,
+            }
+        }
+        Ok(workload)
+    }
+}
+
+/// Run a single replica when none is asked
+pub struct DefaultReplicas;
+
+impl AdmissionCheck for DefaultReplicas {
+    fn name(&self) -> &'static str {
+        "DefaultReplicas"
+    }
+
+    fn cost(&self) -> AdmissionCost {
+        AdmissionCost::Cheap
+    }
+
+    fn admit(&self, _: &AdmissionContext, workload: &WorkloadDefinition) -> AdmissionDecision {
+        if workload.replicas.is_some() {
+            return AdmissionDecision::Allow;
+        }
+        let mut workload = workload.clone();
+        workload.replicas = Some(1);
+        AdmissionDecision::Mutate(Box::new(workload))
+    }
+}
+
+/// Limits on the definition itself, see [`WorkloadDefinition::validate`]
+pub struct SchemaValidation {
+    pub limits: EnvLimits,
+}
+
+impl AdmissionCheck for SchemaValidation {
+    fn name(&self) -> &'static str {
+        "SchemaValidation"
+    }
+
+    fn cost(&self) -> AdmissionCost {
+        AdmissionCost::Cheap
+    }
+
+    fn admit(&self, _: &AdmissionContext, workload: &WorkloadDefinition) -> AdmissionDecision {
+        match workload.validate(&self.limits) {
+            Ok(()) => AdmissionDecision::Allow,
+            Err(reason) => AdmissionDecision::Deny(reason),
+        }
+    }
+}
+
+/// Names and namespaces are part of the element path and of its lookups, so
+/// they can't hold path separators or SQL wildcards
+pub struct NamePolicy;
+
+pub fn forbidden_character(value: &str) -> Option<char> {
+    value
+        .chars()
+        .find(|character| matches!(character, '/' | '%') || character.is_whitespace())
+}
+
+impl AdmissionCheck for NamePolicy {
+    fn name(&self) -> &'static str {
+        "NamePolicy"
+    }
+
+    fn cost(&self) -> AdmissionCost {
+        AdmissionCost::Cheap
+    }
+
+    fn admit(
+        &self,
+        context: &AdmissionContext,
+        workload: &WorkloadDefinition,
+    ) -> AdmissionDecision {
+        let name = &workload.name;
+        if name.is_empty() {
+            return AdmissionDecision::Deny(String::from("Workload name is empty"));
+        }
+        if let Some(character) = forbidden_character(name) {
+            return AdmissionDecision::Deny(format!(
+                "Workload name {:?} contains the forbidden character {:?}",
+                name, character
+            ));
+        }
+        let namespace = context.namespace;
+        if namespace.is_empty() {
+            return AdmissionDecision::Deny(String::from("Namespace is empty"));
+        }
+        if let Some(character) = forbidden_character(namespace) {
+            return AdmissionDecision::Deny(format!(
+                "Namespace {:?} contains the forbidden character {:?}",
+                namespace, character
+            ));
+        }
+        AdmissionDecision::Allow
+    }
+}
+
+/// Maximum amount of workloads in a namespace, unlimited when `None`
+pub struct NamespaceQuota {
+    pub max_workloads: Option<usize>,
+}
+
+impl AdmissionCheck for NamespaceQuota {
+    fn name(&self) -> &'static str {
+        "NamespaceQuota"
+    }
+
+    fn cost(&self) -> AdmissionCost {
+        AdmissionCost::Cheap
+    }
+
+    fn admit(&self, context: &AdmissionContext, _: &WorkloadDefinition) -> AdmissionDecision {
+        let max_workloads = match self.max_workloads {
+            // An update does not add a workload
+            Some(max_workloads) if !context.update => max_workloads,
+            _ => return AdmissionDecision::Allow,
+        };
+        let workloads = RikRepository::find_all(
+            context.connection,
+            &format!("/workload/%/{}/", context.namespace),
+        )
+        .map(|workloads| workloads.len())
+        .unwrap_or_default();
+        if workloads >= max_workloads {
+            return AdmissionDecision::Deny(format!(
+                "Namespace {} already holds {} workloads, the maximum is {}",
+                context.namespace, workloads, max_workloads
+            ));
+        }
+        AdmissionDecision::Allow
+    }
+}
+
+/// Container images and function rootfs URLs starting with a denied prefix
+pub struct DenyList {
+    pub denied: Vec<String>,
+}
+
+impl AdmissionCheck for DenyList {
+    fn name(&self) -> &'static str {
+        "DenyList"
+    }
+
+    fn cost(&self) -> AdmissionCost {
+        AdmissionCost::Cheap
+    }
+
+    fn admit(&self, _: &AdmissionContext, workload: &WorkloadDefinition) -> AdmissionDecision {
+        let images = workload
+            .spec
+            .containers
+            .iter()
+            .map(|container| container.image.as_str());
+        let rootfs = workload
+            .spec
+            .function
+            .iter()
+            .map(|function| function.execution.rootfs.as_str());
+        for source in images.chain(rootfs) {
+            if let Some(prefix) = self
+                .denied
+                .iter()
+                .find(|prefix| source.starts_with(*prefix))
+            {
+                return AdmissionDecision::Deny(format!(
+                    "{} is denied by the {} prefix",
+                    source, prefix
+                ));
+            }
+        }
+        AdmissionDecision::Allow
+    }
+}
+
+/// The host serving the rootfs of a function accepts connections
+pub struct RootfsVerification {
+    pub timeout: Duration,
+}
+
+impl AdmissionCheck for RootfsVerification {
+    fn name(&self) -> &'static str {
+        "RootfsVerification"
+    }
+
+    fn cost(&self) -> AdmissionCost {
+        AdmissionCost::MayDoIo
+    }
+
+    fn admit(&self, _: &AdmissionContext, workload: &WorkloadDefinition) -> AdmissionDecision {
+        let rootfs = match &workload.spec.function {
+            Some(function) => &function.execution.rootfs,
+            None => return AdmissionDecision::Allow,
+        };
+        // Local files and buckets are only reachable from the nodes, which hold
+        // the credentials of the buckets
+        if matches!(rootfs.scheme(), "file" | "s3") {
+            return AdmissionDecision::Allow;
+        }
+
+        let addresses = match rootfs.socket_addrs(|| None) {
+            Ok(addresses) => addresses,
+            Err(e) => {
+                return AdmissionDecision::Deny(format!("Could not resolve {}: {}", rootfs, e))
+            }
+        };
+        if addresses
+            .iter()
+            .any(|address| TcpStream::connect_timeout(address, self.timeout).is_ok())
+        {
+            AdmissionDecision::Allow
+        } else {
+            AdmissionDecision::Deny(format!("Could not reach the host of {}", rootfs))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::RikDataBase;
+    use crate::tests::fixtures::db_connection;
+    use rstest::rstest;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    fn workload(name: &str) -> WorkloadDefinition {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "v0",
+            "kind": "Pod",
+            "name": name,
+            "spec": {"containers": [{"name": "web", "image": "registry.local/web:1.0"}]}
+        }))
+        .unwrap()
+    }
+
+    fn function(rootfs: &str) -> WorkloadDefinition {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "v0",
+            "kind": "Function",
+            "name": "function",
+            "spec": {"function": {"execution": {"rootfs": rootfs}, "exposure": null}}
+        }))
+        .unwrap()
+    }
+
+    fn admit(check: &dyn AdmissionCheck, workload: &WorkloadDefinition) -> AdmissionDecision {
+        let connection = Connection::open_in_memory().unwrap();
+        check.admit(
+            &AdmissionContext {
+                connection: &connection,
+                namespace: "default",
+                update: false,
+            },
+            workload,
+        )
+    }
+
+    /// Check recording its calls, to follow the pipeline order
+    struct Recorder {
+        name: &'static str,
+        cost: AdmissionCost,
+        decision: AdmissionDecision,
+        calls: Arc<std::sync::Mutex<Vec<&'static str>>>,
+    }
+
+    impl AdmissionCheck for Recorder {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn cost(&self) -> AdmissionCost {
+            self.cost
+        }
+
+        fn admit(&self, _: &AdmissionContext, _: &WorkloadDefinition) -> AdmissionDecision {
+            self.calls.lock().unwrap().push(self.name);
+            self.decision.clone()
+        }
+    }
+
+    #[test]
+    fn test_default_replicas() {
+        let decision = admit(&DefaultReplicas, &workload("web"));
+        let mut expected = workload("web");
+        expected.replicas = Some(1);
+        assert_eq!(
+            decision,
+            AdmissionDecision::Mutate(Box::new(expected.clone()))
+        );
+        assert_eq!(admit(&DefaultReplicas, &expected), AdmissionDecision::Allow);
+    }
+
+    #[test]
+    fn test_schema_validation() {
+        let check = SchemaValidation {
+            limits: EnvLimits {
+                max_entries_per_container: 0,
+                ..EnvLimits::default()
+            },
+        };
+        let mut with_env = workload("web");
+        with_env.spec.containers[0].env = Some(vec![definition::workload::EnvConfig {
+            name: String::from("MODE"),
+            value: String::from("debug"),
+        }]);
+        assert_eq!(admit(&check, &workload("web")), AdmissionDecision::Allow);
+        assert!(matches!(
+            admit(&check, &with_env),
+            AdmissionDecision::Deny(_)
+        ));
+    }
+
+    #[test]
+    fn test_name_policy() {
+        assert_eq!(
+            admit(&NamePolicy, &workload("web-1.v2")),
+            AdmissionDecision::Allow
+        );
+        for name in ["", "team/web", "web%", "my web"] {
+            assert!(matches!(
+                admit(&NamePolicy, &workload(name)),
+                AdmissionDecision::Deny(_)
+            ));
+        }
+
+        let connection = Connection::open_in_memory().unwrap();
+        for namespace in ["", "team/lab", "lab%"] {
+            let context = AdmissionContext {
+                connection: &connection,
+                namespace,
+                update: false,
+            };
+            assert!(matches!(
+                NamePolicy.admit(&context, &workload("web")),
+                AdmissionDecision::Deny(_)
+            ));
+        }
+    }
+
+    #[rstest]
+    fn test_namespace_quota(db_connection: Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        RikRepository::insert(&connection, "/workload/Pod/team/web", "{}").unwrap();
+        RikRepository::insert(&connection, "/workload/Pod/other/web", "{}").unwrap();
+        let context = |namespace| AdmissionContext {
+            connection: &connection,
+            namespace,
+            update: false,
+        };
+        let check = NamespaceQuota {
+            max_workloads: Some(1),
+        };
+
+        assert!(matches!(
+            check.admit(&context("team"), &workload("api")),
+            AdmissionDecision::Deny(_)
+        ));
+        assert_eq!(
+            check.admit(&context("empty"), &workload("api")),
+            AdmissionDecision::Allow
+        );
+        let update = AdmissionContext {
+            update: true,
+            ..context("team")
+        };
+        assert_eq!(
+            check.admit(&update, &workload("web")),
+            AdmissionDecision::Allow
+        );
+        let unlimited = NamespaceQuota {
+            max_workloads: None,
+        };
+        assert_eq!(
+            unlimited.admit(&context("team"), &workload("api")),
+            AdmissionDecision::Allow
+        );
+    }
+
+    #[test]
+    fn test_deny_list() {
+        let check = DenyList {
+            denied: vec![String::from("docker.io/"), String::from("http://untrusted")],
+        };
+        assert_eq!(admit(&check, &workload("web")), AdmissionDecision::Allow);
+
+        let mut denied = workload("web");
+        denied.spec.containers[0].image = String::from("docker.io/library/nginx");
+        assert!(matches!(admit(&check, &denied), AdmissionDecision::Deny(_)));
+        assert!(matches!(
+            admit(&check, &function("http://untrusted.example/rootfs.ext4")),
+            AdmissionDecision::Deny(_)
+        ));
+    }
+
+    #[test]
+    fn test_rootfs_verification() {
+        let check = RootfsVerification {
+            timeout: Duration::from_millis(500),
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let reachable = format!(
+            "http://127.0.0.1:{}/rootfs.ext4",
+            listener.local_addr().unwrap().port()
+        );
+        let closed_port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let unreachable = format!("http://127.0.0.1:{}/rootfs.ext4", closed_port);
+
+        assert_eq!(
+            admit(&check, &function(&reachable)),
+            AdmissionDecision::Allow
+        );
+        assert!(matches!(
+            admit(&check, &function(&unreachable)),
+            AdmissionDecision::Deny(_)
+        ));
+        assert_eq!(
+            admit(&check, &function("s3://images/rootfs.ext4")),
+            AdmissionDecision::Allow
+        );
+        assert_eq!(admit(&check, &workload("web")), AdmissionDecision::Allow);
+    }
+
+    #[test]
+    fn test_pipeline_order_and_short_circuit() {
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorder = |name, cost, decision| -> Box<dyn AdmissionCheck> {
+            Box::new(Recorder {
+                name,
+                cost,
+                decision,
+                calls: calls.clone(),
+            })
+        };
+        let mut mutated = workload("web");
+        mutated.replicas = Some(3);
+        let pipeline = AdmissionPipeline::new(vec![
+            recorder(
+                "mutate",
+                AdmissionCost::Cheap,
+                AdmissionDecision::Mutate(Box::new(mutated.clone())),
+            ),
+            recorder("io", AdmissionCost::MayDoIo, AdmissionDecision::Allow),
+            recorder(
+                "deny",
+                AdmissionCost::Cheap,
+                AdmissionDecision::Deny(String::from("denied")),
+            ),
+            recorder("never", AdmissionCost::Cheap, AdmissionDecision::Allow),
+        ]);
+        let connection = Connection::open_in_memory().unwrap();
+        let context = AdmissionContext {
+            connection: &connection,
+            namespace: "default",
+            update: false,
+        };
+
+        assert_eq!(
+            pipeline.run(&context, workload("web"), false),
+            Err(AdmissionDenied {
+                check: "deny",
+                reason: String::from("denied")
+            })
+        );
+        assert_eq!(*calls.lock().unwrap(), vec!["mutate", "io", "deny"]);
+
+        calls.lock().unwrap().clear();
+        let pipeline = AdmissionPipeline::new(pipeline.checks.into_iter().take(2).collect());
+        assert_eq!(pipeline.run(&context, workload("web"), true), Ok(mutated));
+        assert_eq!(*calls.lock().unwrap(), vec!["mutate"]);
+    }
+}
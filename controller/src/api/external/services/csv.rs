@@ -0,0 +1,342 @@
+use crate::api::external::services::element::QueryParams;
+use crate::api::external::services::list::invalid_parameters_response;
+use crate::api::types::element::Element;
+use crate::api::types::list::ListResponse;
+use crate::api::validation::FieldError;
+use crate::api::RikError;
+use std::io;
+use std::str::FromStr;
+use tiny_http::{Request, Response};
+
+/// A CSV column, read from the JSON representation of a listed element so the
+/// columns always follow the JSON fields
+pub struct Column {
+    pub name: &'static str,
+    /// JSON pointer of the field in the element
+    pointer: &'static str,
+    /// Only given in the wide format, or when requested
+    wide: bool,
+}
+
+const fn column(name: &'static str, pointer: &'static str) -> Column {
+    Column {
+        name,
+        pointer,
+        wide: false,
+    }
+}
+
+const fn wide_column(name: &'static str, pointer: &'static str) -> Column {
+    Column {
+        name,
+        pointer,
+        wide: true,
+    }
+}
+
+/// Columns of a CSV response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvFormat {
+    /// The default columns, `Accept: text/csv`
+    Default,
+    /// The default columns then the wide ones, `Accept: text/csv; format=wide`
+    Wide,
+}
+
+/// Columns of `instances.list`, in their default order
+pub const INSTANCE_COLUMNS: &[Column] = &[
+    column("id", "/id"),
+    column("name", "/name"),
+    column("namespace", "/value/namespace"),
+    column("workload_id", "/value/workload_id"),
+    column("kind", "/value/kind"),
+    column("status", "/value/status"),
+    column("created_at", "/value/created_at"),
+    wide_column("node", "/value/worker_id"),
+    wide_column("tenant", "/tenant"),
+    wide_column("correlation_id", "/value/correlation_id"),
+    wide_column("updated_at", "/updated_at"),
+];
+
+/// Columns of `workloads.list`, in their default order
+pub const WORKLOAD_COLUMNS: &[Column] = &[
+    column("id", "/id"),
+    column("name", "/name"),
+    column("kind", "/value/kind"),
+    column("replicas", "/value/replicas"),
+    column(
+        "max_instance_lifetime_seconds",
+        "/value/max_instance_lifetime_seconds",
+    ),
+    wide_column("namespace", "/namespace"),
+    wide_column("tenant", "/tenant"),
+    wide_column("protected", "/value/protected"),
+    wide_column("created_at", "/created_at"),
+    wide_column("updated_at", "/updated_at"),
+];
+
+/// Columns of `volumes.list`, in their default order
+pub const VOLUME_COLUMNS: &[Column] = &[
+    column("id", "/id"),
+    column("name", "/name"),
+    column("namespace", "/value/namespace"),
+    column("size_mb", "/value/size_mb"),
+    column("node", "/value/node"),
+    column("bound_to", "/value/bound_to"),
+    wide_column("tenant", "/value/tenant_id"),
+    wide_column("created_at", "/created_at"),
+];
+
+/// Columns of `nodes.list`, in their default order
+pub const NODE_COLUMNS: &[Column] = &[
+    column("id", "/id"),
+    column("name", "/name"),
+    column("address", "/value/address"),
+    column("status", "/value/status"),
+    column("last_heartbeat", "/value/last_heartbeat"),
+    column("cordoned", "/value/cordoned"),
+    wide_column("created_at", "/created_at"),
+    wide_column("updated_at", "/updated_at"),
+];
+
+/// Columns of `tenants.list`, in their default order
+pub const TENANT_COLUMNS: &[Column] = &[
+    column("id", "/id"),
+    column("name", "/name"),
+    wide_column("created_at", "/created_at"),
+    wide_column("updated_at", "/updated_at"),
+];
+
+/// CSV format the client asked for, if any
+pub fn csv_format(req: &Request) -> Option<CsvFormat> {
+    req.headers()
+        .iter()
+        .filter(|header| header.field.equiv("Accept"))
+        .flat_map(|header| header.value.as_str().split(','))
+        .find_map(|media_type| {
+            let mut parts = media_type.split(';');
+            if !parts.next()?.trim().eq_ignore_ascii_case("text/csv") {
+                return None;
+            }
+            let wide = parts.any(|parameter| {
+                parameter.split_once('=').is_some_and(|(key, value)| {
+                    key.trim().eq_ignore_ascii_case("format")
+                        && value.trim().trim_matches('"').eq_ignore_ascii_case("wide")
+                })
+            });
+            Some(if wide {
+                CsvFormat::Wide
+            } else {
+                CsvFormat::Default
+            })
+        })
+}
+
+/// Columns given with the `columns` query parameter, if any, decoded as the
+/// other query parameters
+pub fn requested_columns(url: &str) -> Result<Option<Vec<String>>, String> {
+    let parameters = QueryParams::parse(url);
+    if parameters.raw("columns").is_none() {
+        return Ok(None);
+    }
+    let value = parameters
+        .decoded("columns")
+        .ok_or_else(|| String::from("This value is badly encoded"))?;
+    Ok(Some(
+        value
+            .split(',')
+            .filter(|column| !column.is_empty())
+            .map(String::from)
+            .collect(),
+    ))
+}
+
+/// Columns of a CSV response. `requested` selects and orders them among
+/// every column, wide ones included, the default ones are used otherwise,
+/// followed by the wide ones in the wide format.
+pub fn select_columns(
+    columns: &[Column],
+    requested: Option<Vec<String>>,
+    format: CsvFormat,
+) -> Result<Vec<&Column>, String> {
+    match requested {
+        Some(requested) => requested
+            .iter()
+            .map(|name| {
+                columns
+                    .iter()
+                    .find(|column| column.name == name)
+                    .ok_or_else(|| format!("Unknown column {}", name))
+            })
+            .collect(),
+        None => Ok(columns
+            .iter()
+            .filter(|column| format == CsvFormat::Wide || !column.wide)
+            .collect()),
+    }
+}
+
+/// Render elements as CSV, one row per element with a header row first
+pub fn elements_to_csv(elements: &[Element], selected: &[&Column]) -> Result<String, String> {
+    let mut csv = String::new();
+    push_row(
+        &mut csv,
+        selected.iter().map(|column| column.name.to_string()),
+    );
+    for element in elements {
+        let element = serde_json::to_value(element).map_err(|e| e.to_string())?;
+        push_row(
+            &mut csv,
+            selected
+                .iter()
+                .map(|column| match element.pointer(column.pointer) {
+                    None | Some(serde_json::Value::Null) => String::new(),
+                    Some(serde_json::Value::String(value)) => value.clone(),
+                    Some(value) => value.to_string(),
+                }),
+        );
+    }
+    Ok(csv)
+}
+
+/// Response to a list request, as CSV if the client asked for it and JSON otherwise
+pub fn list_response(
+    req: &Request,
+    elements: &[Element],
+    columns: &[Column],
+) -> Response<io::Cursor<Vec<u8>>> {
+    let Some(format) = csv_format(req) else {
+        return Response::from_string(serde_json::to_string(elements).unwrap())
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200));
+    };
+
+    let csv = requested_columns(req.url())
+        .and_then(|requested| select_columns(columns, requested, format))
+        .map_err(|e| invalid_parameters_response(vec![FieldError::new("columns", e)]))
+        .and_then(|selected| {
+            elements_to_csv(elements, &selected).map_err(|e| {
+                RikError::Internal(format!("Cannot render the elements as CSV: {}", e)).response()
+            })
+        });
+    match csv {
+        Ok(csv) => Response::from_string(csv)
+            .with_header(tiny_http::Header::from_str("Content-Type: text/csv").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200)),
+        Err(response) => response,
+    }
+}
+
+/// Response to a paginated list request, a `{"items": [...], "total": n,
+/// "revision": r}` envelope in JSON and the rows of the page in CSV. Both
+/// give the total in the `X-Total-Count` header.
+pub fn page_response(
+    req: &Request,
+    page: &ListResponse<Element>,
+    columns: &[Column],
+) -> Response<io::Cursor<Vec<u8>>> {
+    let response = if csv_format(req).is_some() {
+        list_response(req, &page.items, columns)
+    } else {
+        Response::from_string(serde_json::to_string(page).unwrap())
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200))
+    };
+    response.with_header(
+        tiny_http::Header::from_str(&format!("X-Total-Count: {}", page.total)).unwrap(),
+    )
+}
+
+fn push_row(csv: &mut String, fields: impl Iterator<Item = String>) {
+    let fields: Vec<String> = fields.map(|field| quote(&field)).collect();
+    csv.push_str(&fields.join(","));
+    csv.push_str("\r\n");
+}
+
+/// Quote a field as described in RFC 4180
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn instance_element() -> Element {
+        Element {
+            id: "1".to_string(),
+            name: "big-bird-1234".to_string(),
+            value: json!({
+                "workload_id": "42",
+                "namespace": "default",
+                "kind": "Pod",
+                "status": "Running",
+            }),
+            path: Default::default(),
+            created_at: None,
+            updated_at: None,
+            version: None,
+        }
+    }
+
+    fn to_csv(requested: Option<Vec<String>>, format: CsvFormat) -> Result<String, String> {
+        let selected = select_columns(INSTANCE_COLUMNS, requested, format)?;
+        elements_to_csv(&[instance_element()], &selected)
+    }
+
+    #[test]
+    fn test_elements_to_csv_with_all_columns() {
+        let csv = to_csv(None, CsvFormat::Default).unwrap();
+        assert_eq!(
+            csv,
+            "id,name,namespace,workload_id,kind,status,created_at\r\n\
+             1,big-bird-1234,default,42,Pod,Running,\r\n"
+        );
+    }
+
+    #[test]
+    fn test_elements_to_csv_with_requested_columns() {
+        let requested = requested_columns("/api/v0/instances.list?columns=status,id").unwrap();
+        let csv = to_csv(requested, CsvFormat::Default).unwrap();
+        assert_eq!(csv, "status,id\r\nRunning,1\r\n");
+
+        // Decoded as the other query parameters
+        let requested = requested_columns("/api/v0/instances.list?columns=status%2Cid").unwrap();
+        assert_eq!(
+            requested,
+            Some(vec![String::from("status"), String::from("id")])
+        );
+        assert!(requested_columns("/api/v0/instances.list?columns=status%2").is_err());
+
+        let requested = Some(vec!["size".to_string()]);
+        assert!(to_csv(requested, CsvFormat::Default).is_err());
+        // Wide columns are given when requested
+        let requested = Some(vec!["node".to_string()]);
+        assert_eq!(
+            to_csv(requested, CsvFormat::Default).unwrap(),
+            "node\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_elements_to_csv_in_the_wide_format() {
+        let csv = to_csv(None, CsvFormat::Wide).unwrap();
+        assert_eq!(
+            csv,
+            "id,name,namespace,workload_id,kind,status,created_at,node,tenant,correlation_id,updated_at\r\n\
+             1,big-bird-1234,default,42,Pod,Running,,,,,\r\n"
+        );
+    }
+
+    #[test]
+    fn test_quote_fields() {
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("a,b"), "\"a,b\"");
+        assert_eq!(quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
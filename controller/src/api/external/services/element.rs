@@ -1,17 +1,125 @@
-use crate::api::types::element::Element;
+use crate::api::types::element::{Element, ElementPath};
+use std::collections::HashMap;
 
-pub fn elements_set_right_name(elements: Vec<Element>) -> Vec<Element> {
-    let mut result: Vec<Element> = Vec::new();
-    for element in elements {
-        result.push(element_set_right_name(element));
+/// Replace the hierarchical names of elements by their short name, the other
+/// segments being given as separate fields
+pub fn elements_set_right_name(elements: &mut [Element]) {
+    elements.iter_mut().for_each(element_set_right_name);
+}
+
+pub fn element_set_right_name(element: &mut Element) {
+    let (path, name) = ElementPath::parse(&element.name);
+    element.name = name.to_string();
+    element.path = path;
+}
+
+/// Whether an id may be the one of an element stored by the controller,
+/// which are UUIDs
+pub fn is_element_id(id: &str) -> bool {
+    uuid::Uuid::parse_str(id).is_ok()
+}
+
+/// Parameters of the query string of a request URL, parsed once, by name.
+/// The first value of a parameter given twice is kept.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct QueryParams<'a> {
+    parameters: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> QueryParams<'a> {
+    pub fn parse(url: &'a str) -> QueryParams<'a> {
+        let mut parameters = HashMap::new();
+        if let Some((_, query)) = url.split_once('?') {
+            for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+                parameters.entry(key).or_insert(value);
+            }
+        }
+        QueryParams { parameters }
+    }
+
+    /// Value of a parameter as sent, still percent-encoded
+    pub fn raw(&self, key: &str) -> Option<&'a str> {
+        self.parameters.get(key).copied()
+    }
+
+    /// Value of a parameter, `None` as well when it is badly encoded
+    pub fn decoded(&self, key: &str) -> Option<String> {
+        self.raw(key).and_then(decode_query_value)
+    }
+}
+
+/// Value of a query parameter of a request URL, if given
+pub fn query_parameter<'a>(url: &'a str, key: &str) -> Option<&'a str> {
+    QueryParams::parse(url).raw(key)
+}
+
+/// Decode a percent-encoded query parameter value, `+` standing for a space
+pub fn decode_query_value(value: &str) -> Option<String> {
+    percent_decode(value, true)
+}
+
+/// Decode a percent-encoded segment of a path, where `+` is itself
+pub fn decode_path_segment(value: &str) -> Option<String> {
+    percent_decode(value, false)
+}
+
+fn percent_decode(value: &str, plus_is_space: bool) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'%' => {
+                let high = (input.next()? as char).to_digit(16)?;
+                let low = (input.next()? as char).to_digit(16)?;
+                bytes.push((high * 16 + low) as u8);
+            }
+            b'+' if plus_is_space => bytes.push(b' '),
+            byte => bytes.push(byte),
+        }
     }
-    result
+    String::from_utf8(bytes).ok()
 }
 
-pub fn element_set_right_name(mut element: Element) -> Element {
-    let mut split: Vec<&str> = element.name.split('/').collect();
-    if let Some(v) = split.pop() {
-        let _ = &element.set_name(v.to_string());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_query_value() {
+        let url = "/api/v0/events.list?since=2023-06-01T02%3A00%3A00%2B02%3A00&limit=10";
+        assert_eq!(
+            query_parameter(url, "since").and_then(decode_query_value),
+            Some(String::from("2023-06-01T02:00:00+02:00"))
+        );
+        assert_eq!(decode_query_value("a+b"), Some(String::from("a b")));
+        assert_eq!(decode_query_value("%2"), None);
+    }
+
+    #[test]
+    fn test_query_params() {
+        let query = QueryParams::parse("/api/v0/search?q=pay+ments&limit=5&limit=6&flag&bad=%zz");
+        assert_eq!(query.raw("q"), Some("pay+ments"));
+        assert_eq!(query.decoded("q"), Some(String::from("pay ments")));
+        assert_eq!(query.raw("limit"), Some("5"));
+        assert_eq!(query.raw("flag"), None);
+        assert_eq!(query.decoded("bad"), None);
+        assert_eq!(QueryParams::parse("/api/v0/search"), QueryParams::default());
+    }
+
+    #[test]
+    fn test_decode_path_segment() {
+        assert_eq!(
+            decode_path_segment("web%2Dapp"),
+            Some(String::from("web-app"))
+        );
+        assert_eq!(decode_path_segment("a+b"), Some(String::from("a+b")));
+        assert_eq!(decode_path_segment("%zz"), None);
+    }
+
+    #[test]
+    fn test_element_id() {
+        assert!(is_element_id("67e55044-10b1-426f-9247-bb680e5fe0c8"));
+        assert!(!is_element_id("42"));
+        assert!(!is_element_id("' OR '1'='1"));
     }
-    element
 }
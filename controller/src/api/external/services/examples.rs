@@ -0,0 +1,146 @@
+use serde::Serialize;
+
+/// Manifest embedded in the binary, served to scaffold new workloads
+pub struct Example {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub manifest: &'static str,
+    /// Code run by the instances of the example, if they need some
+    pub client_snippet: Option<&'static str>,
+}
+
+/// Curated examples, each one is checked against the admission rules by the tests
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        name: "pod",
+        description: "Replicated container with labels, environment, a forwarded port and a maximum lifetime",
+        manifest: include_str!("examples/pod.json"),
+        client_snippet: None,
+    },
+    Example {
+        name: "sidecar",
+        description: "Pod running a web server next to a log shipper",
+        manifest: include_str!("examples/sidecar.json"),
+        client_snippet: None,
+    },
+    Example {
+        name: "function",
+        description: "Function exposed on a node port, denied access to the metadata service and a database network",
+        manifest: include_str!("examples/function.json"),
+        client_snippet: Some(include_str!("examples/function-status.sh")),
+    },
+];
+
+/// Entry of the examples list, the manifest is fetched by name
+#[derive(Serialize, Debug)]
+pub struct ExampleSummary {
+    pub name: &'static str,
+    pub kind: String,
+    pub description: &'static str,
+    /// Reports the status of the instance from inside it, see `client_snippet`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_snippet: Option<&'static str>,
+}
+
+impl Example {
+    pub fn summary(&self) -> ExampleSummary {
+        let manifest: serde_json::Value = serde_json::from_str(self.manifest).unwrap_or_default();
+        ExampleSummary {
+            name: self.name,
+            kind: manifest["kind"].as_str().unwrap_or_default().to_string(),
+            description: self.description,
+            client_snippet: self.client_snippet,
+        }
+    }
+}
+
+pub fn find_example(name: &str) -> Option<&'static Example> {
+    EXAMPLES.iter().find(|example| example.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::external::services::admission::{AdmissionContext, AdmissionPipeline};
+    use crate::api::external::services::request::{parse_body, BodyFormat};
+    use crate::database::RikDataBase;
+    use crate::tests::fixtures::db_connection;
+    use definition::workload::WorkloadDefinition;
+    use rstest::rstest;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    /// Paths of the fields of the manifest that are not found as is once parsed,
+    /// i.e. renamed or removed from the definition
+    fn dropped_fields(manifest: &Value, parsed: &Value, path: &str, dropped: &mut Vec<String>) {
+        match (manifest, parsed) {
+            (Value::Object(manifest), Value::Object(parsed)) => {
+                for (key, value) in manifest {
+                    let field = format!("{}.{}", path, key);
+                    match parsed.get(key) {
+                        Some(parsed) => dropped_fields(value, parsed, &field, dropped),
+                        None if value.is_null() => {}
+                        None => dropped.push(field),
+                    }
+                }
+            }
+            (Value::Array(manifest), Value::Array(parsed)) if manifest.len() == parsed.len() => {
+                for (index, (value, parsed)) in manifest.iter().zip(parsed).enumerate() {
+                    dropped_fields(value, parsed, &format!("{}[{}]", path, index), dropped);
+                }
+            }
+            (manifest, parsed) if manifest != parsed => dropped.push(path.to_string()),
+            _ => {}
+        }
+    }
+
+    #[rstest]
+    fn test_examples_are_admitted(db_connection: Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let context = AdmissionContext {
+            connection: &connection,
+            namespace: "default",
+            update: false,
+        };
+        for example in EXAMPLES {
+            let workload: WorkloadDefinition = parse_body(example.manifest, BodyFormat::Json)
+                .unwrap_or_else(|errors| panic!("Example {}: {:?}", example.name, errors));
+            // The rootfs of the function example is not hosted anywhere
+            if let Err(denied) = AdmissionPipeline::from_env().run(&context, workload, true) {
+                panic!("Example {} denied by {}", example.name, denied.check);
+            }
+        }
+    }
+
+    #[test]
+    fn test_examples_fields_are_kept() {
+        for example in EXAMPLES {
+            let manifest: Value = serde_json::from_str(example.manifest).unwrap();
+            let workload: WorkloadDefinition = serde_json::from_value(manifest.clone()).unwrap();
+            let mut dropped = Vec::new();
+            dropped_fields(
+                &manifest,
+                &serde_json::to_value(workload).unwrap(),
+                "",
+                &mut dropped,
+            );
+            assert!(
+                dropped.is_empty(),
+                "Example {} has fields the definition ignores: {:?}",
+                example.name,
+                dropped
+            );
+        }
+    }
+
+    #[test]
+    fn test_example_names_are_unique() {
+        for (index, example) in EXAMPLES.iter().enumerate() {
+            assert!(find_example(example.name).is_some());
+            assert!(EXAMPLES[..index]
+                .iter()
+                .all(|other| other.name != example.name));
+            assert!(!example.summary().kind.is_empty());
+        }
+    }
+}
@@ -0,0 +1,8 @@
+#!/bin/sh
+# Report the status of a function from inside its microVM, the node serves
+# the endpoint on the gateway of the guest. Reports are limited to one per
+# second, functions which never report are only probed.
+NODE=$(ip route show default | awk '{ print $3 }')
+curl -s -X POST "http://$NODE:8053/status" \
+  -H 'Content-Type: application/json' \
+  -d '{"status": "warming cache", "ready": false}'
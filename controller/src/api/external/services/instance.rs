@@ -1,23 +1,116 @@
-use crate::api::{ApiChannel, Crud};
-use crate::core::instance::Instance;
+use crate::api::types::element::Element;
+use crate::api::{correlation, ApiChannel, Crud, RikError};
+use crate::core::instance::{Instance, RESTARTED_REASON};
+use crate::database::events::EventRepository;
 use crate::database::RikRepository;
-use definition::workload::WorkloadDefinition;
+use definition::workload::{InstanceOverrides, WorkloadDefinition};
+use definition::InstanceStatus;
+use rand::Rng;
 use rusqlite::Connection;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
 use std::sync::mpsc::Sender;
+use std::time::Duration;
+use tracing::{event, Level};
 
-pub fn send_create_instance(
+/// Characters of the random suffix of the generated instance names
+const NAME_SUFFIX_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+const NAME_SUFFIX_LENGTH: usize = 5;
+/// Generated names are DNS labels, as the workload names they start with
+const MAX_INSTANCE_NAME_LENGTH: usize = 63;
+/// Names drawn before giving up, a collision is already unlikely
+const MAX_NAME_ATTEMPTS: usize = 10;
+/// Port of the riklets on which the logs of their instances are read
+const DEFAULT_RIKLET_LOGS_PORT: u16 = 8054;
+/// Longest time a riklet has to answer with the logs of an instance
+const LOGS_TIMEOUT: Duration = Duration::from_secs(5);
+/// Lines of logs given unless another `tail` is asked for
+pub const DEFAULT_LOGS_TAIL: usize = 100;
+/// Largest `tail` asked for, the riklets keep no more lines
+pub const MAX_LOGS_TAIL: usize = 1000;
+
+/// Why the logs of an instance could not be read from its node
+#[derive(Debug, PartialEq, Eq)]
+pub enum LogsFailure {
+    /// The node keeps no logs for the instance
+    NotKept,
+    Unreachable(String),
+}
+
+/// Remove the conditions of instances, they are only exposed by the v1 API
+pub fn strip_conditions(elements: Vec<Element>) -> Vec<Element> {
+    elements
+        .into_iter()
+        .map(|mut element| {
+            if let Some(value) = element.value.as_object_mut() {
+                value.remove("conditions");
+            }
+            element
+        })
+        .collect()
+}
+
+/// Instances of a workload, in the order they were created
+pub fn workload_instances(
     connection: &Connection,
+    workload_id: &str,
+) -> rusqlite::Result<Vec<Element>> {
+    RikRepository::find_by_value_field(connection, "/instance/", "workload_id", workload_id)
+}
+
+/// Name of the form `web-ab12c` for a new instance of the workload `web`.
+///
+/// The name is used neither by an instance of the namespace nor by one of
+/// `taken`, the names given to the other instances of the same request.
+pub fn generate_instance_name(
+    connection: &Connection,
+    workload_name: &str,
+    namespace: &str,
+    taken: &[String],
+) -> Result<String, RikError> {
+    let prefix_length = MAX_INSTANCE_NAME_LENGTH - NAME_SUFFIX_LENGTH - 1;
+    let prefix = workload_name
+        .get(..prefix_length)
+        .unwrap_or(workload_name)
+        .trim_end_matches('-');
+    let mut rng = rand::thread_rng();
+    for _ in 0..MAX_NAME_ATTEMPTS {
+        let suffix: String = (0..NAME_SUFFIX_LENGTH)
+            .map(|_| NAME_SUFFIX_CHARS[rng.gen_range(0..NAME_SUFFIX_CHARS.len())] as char)
+            .collect();
+        let name = format!("{}-{}", prefix, suffix);
+        let used = taken.contains(&name)
+            || RikRepository::check_duplicate_name(
+                connection,
+                &format!("/instance/%/{}/{}", namespace, name),
+            )
+            .is_ok();
+        if !used {
+            return Ok(name);
+        }
+    }
+    Err(RikError::Internal(format!(
+        "Cannot find a free name for an instance of {}",
+        workload_name
+    )))
+}
+
+/// Ask the core to create an instance of a workload, found beforehand by the caller
+pub fn send_create_instance(
     internal_sender: &Sender<ApiChannel>,
     workload_id: String,
+    mut workload: WorkloadDefinition,
     name: &Option<String>,
+    overrides: &Option<InstanceOverrides>,
+    namespace: &str,
 ) {
-    let workload_db = match RikRepository::find_one(connection, &workload_id, "/workload") {
-        Ok(workload) => workload,
-        Err(err) => panic!("{}", err),
-    };
-    let workload: WorkloadDefinition =
-        serde_json::from_str(&workload_db.value.to_string()).unwrap();
+    // Overrides only apply to this instance, the workload itself is left untouched
+    let overrides = overrides.clone().filter(|overrides| !overrides.is_empty());
+    if let Some(overrides) = &overrides {
+        overrides.apply(&mut workload);
+    }
     let instance_name = name.clone().unwrap_or(Instance::generate_name());
+    let tenant_id = workload.tenant_id.clone();
 
     internal_sender
         .send(ApiChannel {
@@ -25,6 +118,146 @@ pub fn send_create_instance(
             workload_id: Some(workload_id),
             workload_definition: Some(workload),
             instance_id: Some(instance_name),
+            overrides,
+            namespace: Some(namespace.to_string()),
+            tenant_id,
+            correlation_id: correlation::current(),
         })
         .unwrap();
 }
+
+/// Mark a stored instance `Restarting` and record the restart in its events,
+/// giving the instance as stored now
+pub fn record_restart(connection: &Connection, mut element: Element) -> Result<Element, RikError> {
+    let mut instance: Instance = serde_json::from_value(element.value.clone())
+        .map_err(|e| RikError::Internal(format!("Cannot read instance: {}", e)))?;
+    let previous = instance.status.clone();
+    instance.update_conditions(&InstanceStatus::Restarting, &[]);
+    instance.status = InstanceStatus::Restarting;
+    instance.correlation_id = Some(correlation::current());
+    element.value = serde_json::to_value(&instance).unwrap();
+    RikRepository::update(connection, &element.id, &element.value.to_string())
+        .map_err(|e| RikError::Internal(format!("Cannot update instance: {}", e)))?;
+
+    let mut events = vec![(
+        RESTARTED_REASON.to_string(),
+        format!("Restart requested while {}", previous),
+    )];
+    // The status history of the instance
+    if previous != InstanceStatus::Restarting {
+        events.push((
+            InstanceStatus::Restarting.to_string(),
+            format!(
+                "Status changed from {} to {}",
+                previous,
+                InstanceStatus::Restarting
+            ),
+        ));
+    }
+    for (reason, message) in events {
+        // The restart is asked for, a missing event is not worth failing it
+        if let Err(e) = EventRepository::insert(connection, &element.id, &reason, &message) {
+            event!(Level::WARN, "instances.restart, cannot record event: {}", e);
+        }
+    }
+    Ok(element)
+}
+
+/// Address the logs of the instances of a node are read from, on the host
+/// of the address its worker connected from
+pub fn riklet_logs_address(worker_address: &str) -> Option<SocketAddr> {
+    let port = std::env::var("RIKLET_LOGS_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_RIKLET_LOGS_PORT);
+    let address: SocketAddr = worker_address.parse().ok()?;
+    Some(SocketAddr::new(address.ip(), port))
+}
+
+/// Last lines of the logs of an instance, read from the riklet of its node
+pub fn fetch_logs(
+    address: SocketAddr,
+    instance_id: &str,
+    tail: usize,
+) -> Result<String, LogsFailure> {
+    let unreachable = |e: io::Error| LogsFailure::Unreachable(e.to_string());
+    let mut stream = TcpStream::connect_timeout(&address, LOGS_TIMEOUT).map_err(unreachable)?;
+    stream
+        .set_read_timeout(Some(LOGS_TIMEOUT))
+        .map_err(unreachable)?;
+    write!(
+        stream,
+        "GET /logs/{}?tail={} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        instance_id, tail, address
+    )
+    .map_err(unreachable)?;
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer).map_err(unreachable)?;
+
+    let (head, body) = answer
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| LogsFailure::Unreachable(String::from("the answer is incomplete")))?;
+    match head.split_whitespace().nth(1) {
+        Some("200") => Ok(body.to_string()),
+        Some("404") => Err(LogsFailure::NotKept),
+        _ => Err(LogsFailure::Unreachable(format!(
+            "it answered {}",
+            head.lines().next().unwrap_or_default()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Riklet answering a single request with `answer`, giving the request line
+    fn riklet(answer: &'static str) -> (SocketAddr, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request_line = String::new();
+            BufReader::new(&stream)
+                .read_line(&mut request_line)
+                .unwrap();
+            stream.write_all(answer.as_bytes()).unwrap();
+            request_line
+        });
+        (address, handle)
+    }
+
+    #[test]
+    fn test_fetch_logs() {
+        let (address, request) = riklet("HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nready\n");
+        assert_eq!(
+            fetch_logs(address, "web-ab12c", 20),
+            Ok(String::from("ready\n"))
+        );
+        assert!(request
+            .join()
+            .unwrap()
+            .starts_with("GET /logs/web-ab12c?tail=20 HTTP/1.1"));
+
+        let (address, _) = riklet("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(
+            fetch_logs(address, "web-ab12c", 20),
+            Err(LogsFailure::NotKept)
+        );
+        let (address, _) = riklet("HTTP/1.1 500 Internal Server Error\r\n\r\n");
+        assert!(matches!(
+            fetch_logs(address, "web-ab12c", 20),
+            Err(LogsFailure::Unreachable(_))
+        ));
+    }
+
+    #[test]
+    fn test_riklet_logs_address() {
+        let address = riklet_logs_address("10.0.0.12:49152").unwrap();
+        assert_eq!(address.ip().to_string(), "10.0.0.12");
+        assert_eq!(riklet_logs_address("worker-1"), None);
+    }
+}
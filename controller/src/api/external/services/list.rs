@@ -0,0 +1,589 @@
+use crate::api::external::services::element::{
+    decode_query_value, elements_set_right_name, QueryParams,
+};
+use crate::api::external::services::request::validation_response;
+use crate::api::types::element::Element;
+use crate::api::types::list::ListResponse;
+use crate::api::types::workload::parse_selector;
+use crate::api::validation::FieldError;
+use crate::database::query::ElementQuery;
+use crate::database::revisions::RevisionRepository;
+use rusqlite::Connection;
+use std::io;
+
+/// Query parameters shared by the list routes, the ones a route does not
+/// support are refused
+const PARAMETERS: [&str; 12] = [
+    "limit",
+    "offset",
+    "cursor",
+    "sort",
+    "order",
+    "name",
+    "selector",
+    "label_selector",
+    "namespace",
+    "tenant",
+    "kind",
+    "workload_id",
+];
+
+/// Elements in a page of the paginated lists when no limit is given
+pub const DEFAULT_LIMIT: usize = 100;
+
+/// Parameters and sort keys supported by a list route
+pub struct ListSpec {
+    /// Name of the route, used in the error messages
+    pub route: &'static str,
+    pub parameters: &'static [&'static str],
+    /// Sort keys, along with the JSON pointer of the field they sort on
+    pub sort_keys: &'static [(&'static str, &'static str)],
+    /// Limit used when none is given, every element is listed otherwise
+    pub default_limit: Option<usize>,
+}
+
+pub const WORKLOAD_LIST: ListSpec = ListSpec {
+    route: "workloads.list",
+    parameters: &[
+        "limit",
+        "offset",
+        "sort",
+        "order",
+        "name",
+        "selector",
+        "label_selector",
+        "namespace",
+        "tenant",
+        "kind",
+    ],
+    sort_keys: &[
+        ("name", "/name"),
+        ("id", "/id"),
+        ("kind", "/value/kind"),
+        ("replicas", "/value/replicas"),
+        ("created_at", "/created_at"),
+        ("updated_at", "/updated_at"),
+    ],
+    default_limit: Some(DEFAULT_LIMIT),
+};
+
+pub const INSTANCE_LIST: ListSpec = ListSpec {
+    route: "instances.list",
+    parameters: &[
+        "limit",
+        "offset",
+        "sort",
+        "order",
+        "name",
+        "label_selector",
+        "namespace",
+        "tenant",
+        "workload_id",
+    ],
+    sort_keys: &[
+        ("name", "/name"),
+        ("id", "/id"),
+        ("status", "/value/status"),
+        ("workload_id", "/value/workload_id"),
+        ("created_at", "/value/created_at"),
+        ("updated_at", "/updated_at"),
+    ],
+    default_limit: Some(DEFAULT_LIMIT),
+};
+
+pub const TENANT_LIST: ListSpec = ListSpec {
+    route: "tenants.list",
+    parameters: &["limit", "offset", "sort", "order", "name"],
+    sort_keys: &[
+        ("name", "/name"),
+        ("id", "/id"),
+        ("created_at", "/created_at"),
+        ("updated_at", "/updated_at"),
+    ],
+    default_limit: None,
+};
+
+/// Events are always listed in the order they were recorded
+pub const EVENT_LIST: ListSpec = ListSpec {
+    route: "events.list",
+    parameters: &["limit", "cursor"],
+    sort_keys: &[],
+    default_limit: None,
+};
+
+/// Page of a paginated list, along with the amount of elements listed
+/// across every page
+#[derive(Debug)]
+pub struct Page {
+    pub items: Vec<Element>,
+    pub total: usize,
+}
+
+impl Page {
+    /// Answer of a list route, the page being read at `revision`
+    fn at_revision(self, revision: u64) -> ListResponse<Element> {
+        ListResponse {
+            items: self.items,
+            total: self.total,
+            revision,
+        }
+    }
+}
+
+/// Answer of a list route, the page read by `read` along with the revision it
+/// is at. Both are read in one transaction, so the page holds every change up
+/// to the revision and none after it, and watching from it misses none.
+pub fn read_list(
+    connection: &Connection,
+    read: impl FnOnce(&Connection) -> rusqlite::Result<Page>,
+) -> rusqlite::Result<ListResponse<Element>> {
+    let read_at_revision = |connection: &Connection| {
+        let revision = RevisionRepository::current(connection)?.max(0) as u64;
+        read(connection).map(|page| page.at_revision(revision))
+    };
+    if !connection.is_autocommit() {
+        return read_at_revision(connection);
+    }
+    let transaction = connection.unchecked_transaction()?;
+    let list = read_at_revision(&transaction)?;
+    transaction.commit()?;
+    Ok(list)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    /// JSON pointer of the field sorted on
+    pub pointer: &'static str,
+    pub descending: bool,
+}
+
+/// Pagination, filters and sort of a list request
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ListParams {
+    pub limit: Option<usize>,
+    pub offset: usize,
+    /// Position given by a previous page, its format depends on the route
+    pub cursor: Option<String>,
+    pub sort: Option<Sort>,
+    /// Prefix of the names of the elements listed
+    pub name: Option<String>,
+    /// Labels the elements must all have
+    pub selector: Vec<(String, String)>,
+    pub namespace: Option<String>,
+    pub tenant: Option<String>,
+    /// Kind of the workloads listed, whatever its case
+    pub kind: Option<String>,
+    /// Workload of the instances listed
+    pub workload_id: Option<String>,
+}
+
+impl ListParams {
+    /// Parse the list parameters of a request URL, giving every error found
+    pub fn parse(url: &str, spec: &ListSpec) -> Result<ListParams, Vec<FieldError>> {
+        let mut errors = vec![];
+        let parameters = QueryParams::parse(url);
+        let mut value = |parameter: &'static str| {
+            let raw = parameters.raw(parameter)?;
+            if !spec.parameters.contains(&parameter) {
+                errors.push(FieldError::new(
+                    parameter,
+                    format!("This parameter is not supported by {}", spec.route),
+                ));
+                return None;
+            }
+            match decode_query_value(raw) {
+                Some(value) => Some(value),
+                None => {
+                    errors.push(FieldError::new(parameter, "This value is badly encoded"));
+                    None
+                }
+            }
+        };
+        let [limit, offset, cursor, sort, order, name, selector, label_selector, namespace, tenant, kind, workload_id] =
+            PARAMETERS.map(&mut value);
+
+        let mut params = ListParams {
+            limit: spec.default_limit,
+            cursor,
+            name,
+            namespace,
+            tenant,
+            kind,
+            workload_id,
+            ..Default::default()
+        };
+        if let Some(limit) = limit {
+            match limit.parse::<usize>() {
+                Ok(limit) if limit > 0 => params.limit = Some(limit),
+                _ => errors.push(FieldError::new(
+                    "limit",
+                    "The limit must be a positive number",
+                )),
+            }
+        }
+        if let Some(offset) = offset {
+            match offset.parse::<usize>() {
+                Ok(offset) => params.offset = offset,
+                Err(_) => errors.push(FieldError::new(
+                    "offset",
+                    "The offset must be a number, 0 or more",
+                )),
+            }
+            if params.cursor.is_some() {
+                errors.push(FieldError::new(
+                    "offset",
+                    "The offset cannot be given along with a cursor",
+                ));
+            }
+        }
+        // `order` spells out the direction a `-` prefix gives
+        let order = match order.as_deref() {
+            None => None,
+            Some("asc") => Some(false),
+            Some("desc") => Some(true),
+            Some(order) => {
+                errors.push(FieldError::new(
+                    "order",
+                    format!("Unknown order {}, expected asc or desc", order),
+                ));
+                None
+            }
+        };
+        if order.is_some() && sort.as_ref().is_none_or(|sort| sort.starts_with('-')) {
+            errors.push(FieldError::new(
+                "order",
+                "The order is only given along with a sort key without a - prefix",
+            ));
+        }
+        if let Some(sort) = sort {
+            let (key, descending) = match sort.strip_prefix('-') {
+                Some(key) => (key, true),
+                None => (sort.as_str(), order.unwrap_or(false)),
+            };
+            match spec.sort_keys.iter().find(|(name, _)| *name == key) {
+                Some((_, pointer)) => {
+                    params.sort = Some(Sort {
+                        pointer,
+                        descending,
+                    })
+                }
+                None => errors.push(FieldError::new(
+                    "sort",
+                    format!(
+                        "Unknown sort key {}, {} sorts on {}",
+                        key,
+                        spec.route,
+                        sort_keys(spec)
+                    ),
+                )),
+            }
+        }
+        // `selector` is kept for the clients written before `label_selector`
+        for (parameter, selector) in [("selector", selector), ("label_selector", label_selector)] {
+            match selector.as_deref().map(parse_selector) {
+                Some(Ok(selector)) => params.selector.extend(selector),
+                Some(Err(e)) => errors.push(FieldError::new(parameter, e)),
+                None => {}
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(params)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Filters and order of the elements of `element_type` listed
+    pub fn query(&self, element_type: &str) -> ElementQuery {
+        ElementQuery {
+            name: self.name.clone(),
+            tenant: self.tenant.clone(),
+            kind: self.kind.clone(),
+            namespace: self.namespace.clone(),
+            labels: self.selector.clone(),
+            workload_id: self.workload_id.clone(),
+            sort: self.sort.map(|sort| (sort.pointer, sort.descending)),
+            ..ElementQuery::of_type(element_type)
+        }
+    }
+
+    /// Read a page of the elements of `element_type`.
+    ///
+    /// `read` reads the page with the filters and order of the request, the
+    /// limit and the offset, along with the amount of elements the filters
+    /// leave, so the other elements are never loaded. The names of the
+    /// elements are split with `elements_set_right_name`.
+    pub fn find_page(
+        &self,
+        element_type: &str,
+        read: impl FnOnce(&ElementQuery, usize, usize) -> rusqlite::Result<(Vec<Element>, usize)>,
+    ) -> rusqlite::Result<Page> {
+        let limit = self.limit.unwrap_or(usize::MAX);
+        let (mut items, total) = read(&self.query(element_type), limit, self.offset)?;
+        elements_set_right_name(&mut items);
+        Ok(Page { items, total })
+    }
+}
+
+/// Answer to a list request with bad parameters
+pub fn invalid_parameters_response(
+    errors: Vec<FieldError>,
+) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    validation_response(errors).with_status_code(tiny_http::StatusCode::from(400))
+}
+
+fn sort_keys(spec: &ListSpec) -> String {
+    let keys: Vec<&str> = spec.sort_keys.iter().map(|(key, _)| *key).collect();
+    if keys.is_empty() {
+        String::from("nothing")
+    } else {
+        keys.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{RikDataBase, RikRepository};
+    use crate::tests::fixtures::db_connection;
+    use rstest::rstest;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    fn parse(query: &str, spec: &ListSpec) -> Result<ListParams, Vec<FieldError>> {
+        ListParams::parse(&format!("/api/v0/list?{}", query), spec)
+    }
+
+    fn fields(errors: Vec<FieldError>) -> Vec<String> {
+        errors
+            .into_iter()
+            .map(|error| error.field.unwrap_or_default())
+            .collect()
+    }
+
+    fn workload(id: &str, name: &str, replicas: Option<u16>, labels: Value) -> Element {
+        let mut value = json!({ "kind": "Pod", "labels": labels });
+        if let Some(replicas) = replicas {
+            value["replicas"] = json!(replicas);
+        }
+        Element::new(id.to_string(), name.to_string(), value.to_string())
+    }
+
+    /// Workloads as stored, their names are not split
+    fn stored_workloads() -> Vec<Element> {
+        vec![
+            workload(
+                "1",
+                "/workload/pod/default/web",
+                Some(3),
+                json!({"tier": "front"}),
+            ),
+            workload(
+                "2",
+                "/workload/acme/pod/prod/api",
+                Some(1),
+                json!({"tier": "back"}),
+            ),
+            workload("3", "/workload/pod/default/worker", None, json!({})),
+            workload(
+                "4",
+                "/workload/pod/default/web-canary",
+                Some(2),
+                json!({"tier": "front"}),
+            ),
+        ]
+    }
+
+    /// Database holding the workloads
+    fn database(db: &RikDataBase) -> Connection {
+        let connection = db.open().unwrap();
+        for workload in stored_workloads() {
+            let value = workload.value.to_string();
+            RikRepository::upsert(
+                &connection,
+                &workload.id,
+                &workload.name,
+                &value,
+                "/workload/",
+            )
+            .unwrap();
+        }
+        connection
+    }
+
+    fn find_page(connection: &Connection, query: &str) -> Page {
+        parse(query, &WORKLOAD_LIST)
+            .unwrap()
+            .find_page("/workload/", |query, limit, offset| {
+                RikRepository::find_page(connection, query, limit, offset)
+            })
+            .unwrap()
+    }
+
+    fn ids(elements: Vec<Element>) -> Vec<String> {
+        elements.into_iter().map(|element| element.id).collect()
+    }
+
+    #[rstest]
+    fn test_no_parameters(db_connection: Arc<RikDataBase>) {
+        assert_eq!(parse("", &TENANT_LIST).unwrap(), ListParams::default());
+        // Parameters of the routes themselves are left to them
+        assert_eq!(
+            parse("raw=true&columns=id,name", &TENANT_LIST).unwrap(),
+            ListParams::default()
+        );
+        // Paginated lists have a default limit
+        assert_eq!(
+            parse("", &WORKLOAD_LIST).unwrap().limit,
+            Some(DEFAULT_LIMIT)
+        );
+        let connection = database(&db_connection);
+        assert_eq!(ids(find_page(&connection, "").items), ["1", "2", "3", "4"]);
+    }
+
+    #[test]
+    fn test_bad_numbers() {
+        for query in ["limit=0", "limit=-1", "limit=ten", "limit="] {
+            assert_eq!(fields(parse(query, &WORKLOAD_LIST).unwrap_err()), ["limit"]);
+        }
+        for query in ["offset=-1", "offset=1.5", "offset=%zz"] {
+            assert_eq!(
+                fields(parse(query, &WORKLOAD_LIST).unwrap_err()),
+                ["offset"]
+            );
+        }
+        let params = parse("limit=2&offset=0", &WORKLOAD_LIST).unwrap();
+        assert_eq!((params.limit, params.offset), (Some(2), 0));
+    }
+
+    #[test]
+    fn test_unknown_sort_keys() {
+        let errors = parse("sort=size", &WORKLOAD_LIST).unwrap_err();
+        assert_eq!(errors[0].field.as_deref(), Some("sort"));
+        assert!(errors[0].message.contains("name, id, kind, replicas"));
+        assert!(parse("sort=-", &WORKLOAD_LIST).is_err());
+        // Keys are per route
+        assert!(parse("sort=status", &WORKLOAD_LIST).is_err());
+        assert!(parse("sort=-status", &INSTANCE_LIST).is_ok());
+
+        let params = parse("sort=-replicas", &WORKLOAD_LIST).unwrap();
+        assert_eq!(
+            params.sort,
+            Some(Sort {
+                pointer: "/value/replicas",
+                descending: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_conflicting_cursor_and_offset() {
+        let spec = ListSpec {
+            route: "test",
+            parameters: &PARAMETERS,
+            sort_keys: &[],
+            default_limit: None,
+        };
+        assert_eq!(
+            fields(parse("cursor=42&offset=10", &spec).unwrap_err()),
+            ["offset"]
+        );
+        assert_eq!(
+            parse("cursor=42", &spec).unwrap().cursor.as_deref(),
+            Some("42")
+        );
+    }
+
+    #[test]
+    fn test_unsupported_parameters() {
+        assert_eq!(
+            fields(parse("cursor=42&selector=tier%3Dfront", &TENANT_LIST).unwrap_err()),
+            ["cursor", "selector"]
+        );
+        assert_eq!(
+            fields(parse("offset=10", &EVENT_LIST).unwrap_err()),
+            ["offset"]
+        );
+        assert_eq!(
+            fields(parse("selector=tier", &WORKLOAD_LIST).unwrap_err()),
+            ["selector"]
+        );
+        assert_eq!(
+            fields(parse("label_selector=tier%3Dfront%2Cenv", &INSTANCE_LIST).unwrap_err()),
+            ["label_selector"]
+        );
+    }
+
+    #[test]
+    fn test_every_error_is_given() {
+        let errors = parse("limit=0&offset=x&sort=size", &WORKLOAD_LIST).unwrap_err();
+        assert_eq!(fields(errors), ["limit", "offset", "sort"]);
+    }
+
+    #[rstest]
+    fn test_filters(db_connection: Arc<RikDataBase>) {
+        let connection = database(&db_connection);
+        let apply = |query: &str| ids(find_page(&connection, query).items);
+        assert_eq!(apply("name=web"), ["1", "4"]);
+        assert_eq!(apply("namespace=prod"), ["2"]);
+        assert_eq!(apply("tenant=acme"), ["2"]);
+        assert_eq!(apply("kind=POD&namespace=default"), ["1", "3", "4"]);
+        assert_eq!(apply("kind=function"), Vec::<String>::new());
+        assert_eq!(apply("selector=tier%3Dfront"), ["1", "4"]);
+        assert_eq!(apply("selector=tier%3Dfront&name=web-"), ["4"]);
+        assert_eq!(apply("label_selector=tier%3Dfront"), ["1", "4"]);
+        assert_eq!(apply("namespace=staging"), Vec::<String>::new());
+    }
+
+    #[rstest]
+    fn test_sort_and_pages(db_connection: Arc<RikDataBase>) {
+        let connection = database(&db_connection);
+        let apply = |query: &str| ids(find_page(&connection, query).items);
+        assert_eq!(apply("sort=name"), ["2", "1", "4", "3"]);
+        // Workloads without replicas come last
+        assert_eq!(apply("sort=replicas"), ["2", "4", "1", "3"]);
+        assert_eq!(apply("sort=-replicas"), ["1", "4", "2", "3"]);
+        assert_eq!(apply("sort=-name&limit=2"), ["3", "4"]);
+        assert_eq!(apply("sort=name&offset=1&limit=2"), ["1", "4"]);
+        assert_eq!(apply("offset=10"), Vec::<String>::new());
+        assert_eq!(apply("sort=name&order=desc&limit=2"), ["3", "4"]);
+        assert_eq!(apply("sort=replicas&order=asc"), ["2", "4", "1", "3"]);
+        assert_eq!(apply("sort=-id"), ["4", "3", "2", "1"]);
+    }
+
+    #[test]
+    fn test_invalid_order() {
+        let fields = |query: &str| fields(parse(query, &INSTANCE_LIST).unwrap_err());
+        assert_eq!(fields("sort=name&order=up"), ["order"]);
+        assert_eq!(fields("order=desc"), ["order"]);
+        assert_eq!(fields("sort=-name&order=asc"), ["order"]);
+        assert_eq!(fields("sort=size&order=desc"), ["sort"]);
+    }
+
+    #[rstest]
+    fn test_find_page(db_connection: Arc<RikDataBase>) {
+        let connection = database(&db_connection);
+        let page = find_page(&connection, "limit=1&offset=1");
+        assert_eq!((page.items[0].name.as_str(), page.total), ("api", 4));
+
+        // The total counts the workloads left by the filters
+        let page = find_page(&connection, "name=web&limit=1");
+        assert_eq!((ids(page.items), page.total), (vec![String::from("1")], 2));
+        let page = find_page(&connection, "selector=tier%3Dfront&offset=5");
+        assert_eq!((page.items.len(), page.total), (0, 2));
+        assert_eq!(find_page(&connection, "namespace=staging").total, 0);
+    }
+
+    #[rstest]
+    fn test_read_list(db_connection: Arc<RikDataBase>) {
+        let connection = database(&db_connection);
+        let revision = RevisionRepository::current(&connection).unwrap() as u64;
+        let list = read_list(&connection, |connection| {
+            Ok(find_page(connection, "tenant=acme"))
+        })
+        .unwrap();
+        assert_eq!((list.total, list.revision), (1, revision));
+        assert_eq!(list.items[0].path.tenant.as_deref(), Some("acme"));
+    }
+}
@@ -1,2 +1,11 @@
+pub mod admission;
+pub mod csv;
 pub mod element;
+pub mod examples;
 pub mod instance;
+pub mod list;
+pub mod namespace;
+pub mod request;
+pub mod search;
+pub mod tenant;
+pub mod workload;
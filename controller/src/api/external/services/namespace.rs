@@ -0,0 +1,100 @@
+use crate::api::external::services::tenant::key_default_namespace;
+use rusqlite::Connection;
+use tiny_http::Request;
+
+/// Header used by clients to send their default namespace
+pub const NAMESPACE_HEADER: &str = "X-Rik-Namespace";
+const DEFAULT_NAMESPACE: &str = "default";
+
+/// Namespace used when neither the request nor the key give one
+pub fn server_default_namespace() -> String {
+    std::env::var("DEFAULT_NAMESPACE").unwrap_or_else(|_| DEFAULT_NAMESPACE.to_string())
+}
+
+/// Default namespace sent by the client with the request, if any
+pub fn client_default_namespace(req: &Request) -> Option<String> {
+    req.headers()
+        .iter()
+        .find(|header| header.field.equiv(NAMESPACE_HEADER))
+        .map(|header| header.value.to_string())
+        .filter(|namespace| !namespace.is_empty())
+}
+
+/// Namespace a request applies to, `explicit` being the one it gives, see
+/// `resolve_namespace`. The token default is the one of the tenant whose API
+/// key authenticated the request.
+pub fn request_namespace(
+    req: &Request,
+    connection: &Connection,
+    explicit: Option<&str>,
+) -> Result<String, String> {
+    resolve_namespace(
+        explicit,
+        client_default_namespace(req).as_deref(),
+        key_default_namespace(connection).as_deref(),
+        &server_default_namespace(),
+    )
+}
+
+/// Find the namespace a request applies to.
+///
+/// The namespace explicitly given in the request wins, then the default
+/// namespace of the token, then the default namespace of the client and finally
+/// the server default. The client and token defaults must agree when both are
+/// given, so they can't silently disagree.
+fn resolve_namespace(
+    explicit: Option<&str>,
+    client_default: Option<&str>,
+    token_default: Option<&str>,
+    server_default: &str,
+) -> Result<String, String> {
+    if let (Some(client_default), Some(token_default)) = (client_default, token_default) {
+        if client_default != token_default {
+            return Err(format!(
+                "Namespace {} sent by the client conflicts with the token namespace {}",
+                client_default, token_default
+            ));
+        }
+    }
+
+    Ok(explicit
+        .or(token_default)
+        .or(client_default)
+        .unwrap_or(server_default)
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explicit_namespace_wins() {
+        let namespace = resolve_namespace(Some("dev"), Some("prod"), Some("prod"), "default");
+        assert_eq!(namespace, Ok("dev".to_string()));
+    }
+
+    #[test]
+    fn test_token_namespace_before_server_default() {
+        let namespace = resolve_namespace(None, None, Some("team"), "default");
+        assert_eq!(namespace, Ok("team".to_string()));
+    }
+
+    #[test]
+    fn test_client_namespace_before_server_default() {
+        let namespace = resolve_namespace(None, Some("team"), None, "default");
+        assert_eq!(namespace, Ok("team".to_string()));
+    }
+
+    #[test]
+    fn test_server_default_namespace() {
+        let namespace = resolve_namespace(None, None, None, "default");
+        assert_eq!(namespace, Ok("default".to_string()));
+    }
+
+    #[test]
+    fn test_conflicting_defaults_are_rejected() {
+        let namespace = resolve_namespace(Some("dev"), Some("prod"), Some("team"), "default");
+        assert!(namespace.is_err());
+    }
+}
@@ -0,0 +1,386 @@
+use crate::api::external::services::element::query_parameter;
+use crate::api::limits::max_body_bytes;
+use crate::api::response::{api_error_response, api_version};
+use crate::api::types::element::OnlyId;
+use crate::api::types::error::ApiError;
+use crate::api::validation::{FieldError, ValidateRequest};
+use crate::api::{RikError, ALREADY_EXISTS_CODE};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::error::Category;
+use serde_json::Value;
+use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+/// Id answered by the creations checked with `?dry_run=true`, which store nothing
+pub const DRY_RUN_ID: &str = "0";
+
+/// Media types of the YAML request bodies, `application/yaml` being the registered one
+const YAML_MEDIA_TYPES: [&str; 4] = [
+    "application/yaml",
+    "application/x-yaml",
+    "text/yaml",
+    "text/x-yaml",
+];
+
+/// Body of the `422` answers, for request bodies and workload definitions alike
+#[derive(Serialize, Debug)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<&'static str>,
+}
+
+/// Format of a request body, given by its `Content-Type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFormat {
+    Json,
+    Yaml,
+}
+
+impl BodyFormat {
+    /// Bodies are JSON unless they are declared as YAML, older clients do
+    /// not give a `Content-Type`
+    pub fn of(req: &tiny_http::Request) -> BodyFormat {
+        let yaml = req.headers().iter().any(|header| {
+            let media_type = header.value.as_str().split(';').next().unwrap_or_default();
+            header.field.equiv("Content-Type")
+                && YAML_MEDIA_TYPES
+                    .iter()
+                    .any(|yaml| media_type.trim().eq_ignore_ascii_case(yaml))
+        });
+        if yaml {
+            BodyFormat::Yaml
+        } else {
+            BodyFormat::Json
+        }
+    }
+
+    /// Deserialize a body of this format, without validating it
+    pub fn deserialize<T: DeserializeOwned>(self, content: &str) -> Result<T, RikError> {
+        match self {
+            BodyFormat::Json => Ok(serde_json::from_str(content)?),
+            BodyFormat::Yaml => Ok(serde_yaml::from_str(content)?),
+        }
+    }
+}
+
+impl fmt::Display for BodyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BodyFormat::Json => write!(f, "JSON"),
+            BodyFormat::Yaml => write!(f, "YAML"),
+        }
+    }
+}
+
+/// Deserialize and validate a request body of the given format, giving every
+/// error found
+pub fn parse_body<T>(content: &str, format: BodyFormat) -> Result<T, Vec<FieldError>>
+where
+    T: DeserializeOwned + ValidateRequest,
+{
+    if content.trim().is_empty() {
+        return Err(vec![FieldError::body(format!(
+            "The request body is empty, a {} object is expected",
+            format
+        ))]);
+    }
+    let value: Result<Value, String> = match format {
+        BodyFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+        BodyFormat::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+    };
+    let value = value.map_err(|e| {
+        vec![FieldError::body(format!(
+            "The request body is not valid {}: {}",
+            format, e
+        ))]
+    })?;
+    if !value.is_object() {
+        return Err(vec![FieldError::body(format!(
+            "The request body must be a {} object, not {}",
+            format,
+            json_type(&value)
+        ))]);
+    }
+
+    let errors = T::validate_value(&value);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let request: T = serde_json::from_value(value).map_err(|e| vec![field_error(&e)])?;
+    let errors = request.validate();
+    if errors.is_empty() {
+        Ok(request)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Read, deserialize and validate the body of a request, answering with a
+/// `422` listing the errors when it is not valid
+pub fn extract_request<T>(
+    req: &mut tiny_http::Request,
+) -> Result<T, tiny_http::Response<io::Cursor<Vec<u8>>>>
+where
+    T: DeserializeOwned + ValidateRequest,
+{
+    let content = read_body(req).map_err(|e| match e {
+        RikError::PayloadTooLarge(_) => e.response(),
+        e => validation_response(vec![FieldError::body(format!(
+            "Could not read the request body: {}",
+            e
+        ))]),
+    })?;
+    parse_body(&content, BodyFormat::of(req)).map_err(validation_response)
+}
+
+/// Read the body of a request, refused once it is larger than `max_body_bytes`
+pub fn read_body(req: &mut tiny_http::Request) -> Result<String, RikError> {
+    let limit = max_body_bytes();
+    // Bodies announcing their size are refused before reading anything
+    if req.body_length().is_some_and(|length| length > limit) {
+        return Err(RikError::PayloadTooLarge(limit));
+    }
+    read_limited(req.as_reader(), limit)
+}
+
+/// Read at most one byte more than `limit`, to tell whether the body is larger
+fn read_limited(reader: impl Read, limit: usize) -> Result<String, RikError> {
+    let mut content = String::new();
+    reader.take(limit as u64 + 1).read_to_string(&mut content)?;
+    if content.len() > limit {
+        return Err(RikError::PayloadTooLarge(limit));
+    }
+    Ok(content)
+}
+
+/// Header giving the version an element was read at, the element is only
+/// written while it is still at that version
+pub const IF_MATCH_HEADER: &str = "If-Match";
+
+/// Version sent in the `If-Match` header, quoted as an entity tag or not
+pub fn expected_version(req: &tiny_http::Request) -> Result<Option<i64>, RikError> {
+    let Some(header) = req
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(IF_MATCH_HEADER))
+    else {
+        return Ok(None);
+    };
+    let value = header.value.as_str().trim().trim_matches('"');
+    value.parse().map(Some).map_err(|_| {
+        RikError::InvalidBody(format!(
+            "{} must give the version of the element, not {}",
+            IF_MATCH_HEADER, header.value
+        ))
+    })
+}
+
+/// Id of the element a request applies to, taken from the path of the routes
+/// such as `DELETE /api/v0/workloads/:id`, or from the body of the
+/// `*.delete` routes kept for older clients
+pub fn extract_id(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+) -> Result<String, tiny_http::Response<io::Cursor<Vec<u8>>>> {
+    match params.find("id") {
+        Some(id) => Ok(id.to_string()),
+        None => extract_request::<OnlyId>(req).map(|request| request.id),
+    }
+}
+
+pub fn validation_response(errors: Vec<FieldError>) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    tiny_http::Response::from_string(
+        serde_json::to_string(&ValidationErrors {
+            errors,
+            version: api_version(),
+        })
+        .unwrap(),
+    )
+    .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+    .with_status_code(tiny_http::StatusCode::from(422))
+}
+
+/// Answer to the creation of an element whose name is already used, with the
+/// id of the existing element so callers may update it instead
+pub fn conflict_response(
+    message: impl Into<String>,
+    existing_id: &str,
+) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    let error = ApiError {
+        id: Some(existing_id.to_string()),
+        ..ApiError::new(ALREADY_EXISTS_CODE, message)
+    };
+    api_error_response(409, &error)
+}
+
+/// Whether a request asks, with `?dry_run=true`, to be checked as it would be
+/// handled, without writing anything nor sending anything to the core
+pub fn is_dry_run(req: &tiny_http::Request) -> bool {
+    query_parameter(req.url(), "dry_run") == Some("true")
+}
+
+/// Answer to the creation of an element, with the element as stored and the
+/// path it is read from in the `Location` header
+pub fn created_response(
+    location: &str,
+    element: &Value,
+) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    tiny_http::Response::from_string(element.to_string())
+        .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+        .with_header(tiny_http::Header::from_str(&format!("Location: {}", location)).unwrap())
+        .with_status_code(tiny_http::StatusCode::from(201))
+}
+
+/// Answer to a request checked with `?dry_run=true`, the object it would have
+/// been answered with marked with `"dry_run": true`
+pub fn dry_run_response(status: u16, mut body: Value) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    if let Some(object) = body.as_object_mut() {
+        object.insert(String::from("dry_run"), Value::Bool(true));
+    }
+    tiny_http::Response::from_string(body.to_string())
+        .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+        .with_status_code(tiny_http::StatusCode::from(status))
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Turn a deserialization error into an error on the field it names, serde
+/// only names the missing and unknown ones
+fn field_error(error: &serde_json::Error) -> FieldError {
+    let message = error.to_string();
+    if error.classify() != Category::Data {
+        return FieldError::body(message);
+    }
+    let named = |prefix: &str| {
+        message
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.split_once('`'))
+    };
+    if let Some((field, _)) = named("missing field `") {
+        return FieldError::new(field, "This field is required");
+    }
+    if let Some((field, rest)) = named("unknown field `") {
+        let expected = rest.trim_start_matches(", ");
+        return FieldError::new(field, format!("Unknown field, {}", expected));
+    }
+    FieldError::body(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug)]
+    #[serde(deny_unknown_fields)]
+    struct Scale {
+        id: String,
+        replicas: usize,
+    }
+
+    impl ValidateRequest for Scale {
+        fn validate(&self) -> Vec<FieldError> {
+            let mut errors = vec![];
+            if self.id.is_empty() {
+                errors.push(FieldError::new("id", "The id must not be empty"));
+            }
+            if self.replicas == 0 {
+                errors.push(FieldError::new(
+                    "replicas",
+                    "At least one replica is needed",
+                ));
+            }
+            errors
+        }
+    }
+
+    #[test]
+    fn test_parse_valid_request() {
+        let scale: Scale = parse_body(r#"{"id": "abc", "replicas": 2}"#, BodyFormat::Json).unwrap();
+        assert_eq!(scale.id, "abc");
+        assert_eq!(scale.replicas, 2);
+    }
+
+    #[test]
+    fn test_malformed_bodies() {
+        let errors = parse_body::<Scale>("", BodyFormat::Json).unwrap_err();
+        assert_eq!(errors[0].field, None);
+        assert!(errors[0].message.contains("empty"));
+
+        let errors = parse_body::<Scale>(r#""abc""#, BodyFormat::Json).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![FieldError::body(
+                "The request body must be a JSON object, not a string"
+            )]
+        );
+
+        let errors = parse_body::<Scale>(r#"{"id": "abc""#, BodyFormat::Json).unwrap_err();
+        assert!(errors[0]
+            .message
+            .starts_with("The request body is not valid JSON"));
+    }
+
+    #[test]
+    fn test_missing_and_unknown_fields() {
+        let errors = parse_body::<Scale>(r#"{"replicas": 2}"#, BodyFormat::Json).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![FieldError::new("id", "This field is required")]
+        );
+
+        let errors = parse_body::<Scale>(
+            r#"{"id": "abc", "replicas": 2, "force": true}"#,
+            BodyFormat::Json,
+        )
+        .unwrap_err();
+        assert_eq!(errors[0].field.as_deref(), Some("force"));
+        assert!(errors[0].message.contains("expected `id` or `replicas`"));
+    }
+
+    #[test]
+    fn test_every_validation_error_is_given() {
+        let errors =
+            parse_body::<Scale>(r#"{"id": "", "replicas": 0}"#, BodyFormat::Json).unwrap_err();
+        let fields: Vec<_> = errors.iter().filter_map(|e| e.field.as_deref()).collect();
+        assert_eq!(fields, vec!["id", "replicas"]);
+    }
+
+    #[test]
+    fn test_parse_yaml_request() {
+        let scale: Scale = parse_body("id: abc\nreplicas: 2\n", BodyFormat::Yaml).unwrap();
+        assert_eq!((scale.id.as_str(), scale.replicas), ("abc", 2));
+
+        let errors = parse_body::<Scale>("id: [abc", BodyFormat::Yaml).unwrap_err();
+        assert!(errors[0]
+            .message
+            .starts_with("The request body is not valid YAML"));
+        let errors = parse_body::<Scale>("- abc", BodyFormat::Yaml).unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "The request body must be a YAML object, not an array"
+        );
+    }
+
+    #[test]
+    fn test_read_limited_stops_past_the_limit() {
+        assert_eq!(read_limited("abcd".as_bytes(), 4).unwrap(), "abcd");
+        // An endless body is refused once the limit is crossed
+        assert!(matches!(
+            read_limited(io::repeat(b'a'), 1024),
+            Err(RikError::PayloadTooLarge(1024))
+        ));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::api::external::services::element::{decode_query_value, QueryParams};
+use crate::api::validation::FieldError;
+
+/// Names given by a search when no limit is asked
+const DEFAULT_LIMIT: usize = 20;
+/// Searches feed completions, longer lists are not worth their cost
+const MAX_LIMIT: usize = 100;
+
+/// Parameters of a search request
+#[derive(Debug, PartialEq, Eq)]
+pub struct SearchParams {
+    /// Type of the elements searched, such as `/workload/`
+    pub element_type: &'static str,
+    pub query: String,
+    pub limit: usize,
+    pub namespace: Option<String>,
+}
+
+impl SearchParams {
+    /// Parse the parameters of a search URL, giving every error found
+    pub fn parse(url: &str) -> Result<SearchParams, Vec<FieldError>> {
+        let mut errors = vec![];
+        let parameters = QueryParams::parse(url);
+        let mut value = |parameter: &'static str| {
+            let raw = parameters.raw(parameter)?;
+            let value = decode_query_value(raw);
+            if value.is_none() {
+                errors.push(FieldError::new(parameter, "This value is badly encoded"));
+            }
+            value
+        };
+        let [kind, query, limit, namespace] = ["kind", "q", "limit", "namespace"].map(&mut value);
+
+        let element_type = match kind.as_deref() {
+            Some("workload") => Some("/workload/"),
+            Some("instance") => Some("/instance/"),
+            _ => {
+                errors.push(FieldError::new(
+                    "kind",
+                    "The kind must be workload or instance",
+                ));
+                None
+            }
+        };
+        let query = query.filter(|query| !query.is_empty());
+        if query.is_none() && matches!(parameters.raw("q"), None | Some("")) {
+            errors.push(FieldError::new("q", "The text searched is required"));
+        }
+        let limit = match limit.map(|limit| limit.parse::<usize>()) {
+            None => DEFAULT_LIMIT,
+            Some(Ok(limit)) if limit > 0 && limit <= MAX_LIMIT => limit,
+            Some(_) => {
+                errors.push(FieldError::new(
+                    "limit",
+                    format!("The limit must be a number from 1 to {}", MAX_LIMIT),
+                ));
+                DEFAULT_LIMIT
+            }
+        };
+
+        match (element_type, query) {
+            (Some(element_type), Some(query)) if errors.is_empty() => Ok(SearchParams {
+                element_type,
+                query,
+                limit,
+                namespace,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(errors: Vec<FieldError>) -> Vec<String> {
+        errors.into_iter().filter_map(|error| error.field).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            SearchParams::parse("/api/v0/search?kind=workload&q=pay+ments&namespace=lab"),
+            Ok(SearchParams {
+                element_type: "/workload/",
+                query: String::from("pay ments"),
+                limit: DEFAULT_LIMIT,
+                namespace: Some(String::from("lab")),
+            })
+        );
+        assert_eq!(
+            SearchParams::parse("/api/v0/search?kind=instance&q=pay&limit=5")
+                .unwrap()
+                .limit,
+            5
+        );
+
+        assert_eq!(
+            fields(SearchParams::parse("/api/v0/search").unwrap_err()),
+            vec!["kind", "q"]
+        );
+        assert_eq!(
+            fields(SearchParams::parse("/api/v0/search?kind=node&q=&limit=500").unwrap_err()),
+            vec!["kind", "q", "limit"]
+        );
+        assert_eq!(
+            fields(SearchParams::parse("/api/v0/search?kind=workload&q=%zz").unwrap_err()),
+            vec!["q"]
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::api::auth::authenticated_tenant;
+use crate::api::external::services::admission::forbidden_character;
+use crate::api::types::element::tenant_segment;
+use crate::api::types::tenant::{Quota, QuotaUsage};
+use crate::api::RikError;
+use crate::database::RikRepository;
+use rusqlite::Connection;
+use tiny_http::Request;
+
+/// Header used by clients to act on behalf of a tenant
+pub const TENANT_HEADER: &str = "X-Rik-Tenant";
+/// Field of the value of a tenant holding its quota
+pub const QUOTA_FIELD: &str = "quota";
+/// Field of the value of a tenant holding the namespace of the requests
+/// authenticated with its key which do not give one
+pub const DEFAULT_NAMESPACE_FIELD: &str = "default_namespace";
+
+/// Tenant the request acts for: the one of its key, else the one sent by the
+/// client in the header, if any
+pub fn client_tenant(req: &Request) -> Option<String> {
+    authenticated_tenant().or_else(|| {
+        req.headers()
+            .iter()
+            .find(|header| header.field.equiv(TENANT_HEADER))
+            .map(|header| header.value.to_string())
+            .filter(|tenant| !tenant.is_empty())
+    })
+}
+
+/// Whether the request may act on an element owned by `owner`, the requests
+/// authenticated with a tenant key only reach the elements of their tenant
+pub fn caller_owns(owner: Option<&str>) -> bool {
+    authenticated_tenant().is_none_or(|tenant_id| owner == Some(tenant_id.as_str()))
+}
+
+/// Find the tenant an element is created for, the one given in the request
+/// body wins over the header. The tenant must exist, and be the one of the
+/// key authenticating the request.
+pub fn resolve_tenant(
+    connection: &Connection,
+    explicit: Option<&str>,
+    req: &Request,
+) -> Result<Option<String>, RikError> {
+    let Some(tenant_id) = explicit.map(str::to_string).or_else(|| client_tenant(req)) else {
+        return Ok(None);
+    };
+    if !caller_owns(Some(&tenant_id)) {
+        return Err(RikError::NotFound(format!(
+            "Tenant id {} not found",
+            tenant_id
+        )));
+    }
+    match RikRepository::find_one(connection, &tenant_id, "/tenant") {
+        Ok(_) => Ok(Some(tenant_id)),
+        Err(_) => Err(RikError::NotFound(format!(
+            "Tenant id {} not found",
+            tenant_id
+        ))),
+    }
+}
+
+/// Elements limited by the quota of a tenant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaResource {
+    Workloads,
+    Instances,
+}
+
+impl QuotaResource {
+    fn name(self) -> &'static str {
+        match self {
+            QuotaResource::Workloads => "workloads",
+            QuotaResource::Instances => "instances",
+        }
+    }
+
+    fn element_type(self) -> &'static str {
+        match self {
+            QuotaResource::Workloads => "/workload/",
+            QuotaResource::Instances => "/instance/",
+        }
+    }
+
+    fn limit(self, quota: &Quota) -> Option<usize> {
+        match self {
+            QuotaResource::Workloads => quota.max_workloads,
+            QuotaResource::Instances => quota.max_instances,
+        }
+    }
+}
+
+/// Quota held by the value of a tenant, if it has one
+pub fn parse_quota(value: &serde_json::Value) -> Result<Option<Quota>, RikError> {
+    match value.get(QUOTA_FIELD) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(quota) => serde_json::from_value(quota.clone())
+            .map(Some)
+            .map_err(|e| RikError::InvalidBody(format!("Invalid quota: {}", e))),
+    }
+}
+
+/// Default namespace given in the value of a tenant, if any
+pub fn parse_default_namespace(value: &serde_json::Value) -> Result<Option<String>, RikError> {
+    match value.get(DEFAULT_NAMESPACE_FIELD) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(serde_json::Value::String(namespace))
+            if !namespace.is_empty() && forbidden_character(namespace).is_none() =>
+        {
+            Ok(Some(namespace.clone()))
+        }
+        Some(namespace) => Err(RikError::InvalidBody(format!(
+            "Invalid default namespace {}, it must be a non-empty string without '/', '%' or spaces",
+            namespace
+        ))),
+    }
+}
+
+/// Default namespace of the tenant whose key authenticated the request, if
+/// it has one
+pub fn key_default_namespace(connection: &Connection) -> Option<String> {
+    let tenant_id = authenticated_tenant()?;
+    let tenant = RikRepository::find_one(connection, &tenant_id, "/tenant").ok()?;
+    parse_default_namespace(&tenant.value).ok().flatten()
+}
+
+/// Check that a tenant may own `requested` more elements, counting the ones
+/// it owns rather than reading them. Elements without a tenant have no quota,
+/// a quota lowered below the usage only refuses the new elements.
+pub fn check_quota(
+    connection: &Connection,
+    tenant_id: Option<&str>,
+    resource: QuotaResource,
+    requested: usize,
+) -> Result<(), RikError> {
+    let Some(tenant_id) = tenant_id else {
+        return Ok(());
+    };
+    let tenant = RikRepository::find_one(connection, &tenant_id.to_string(), "/tenant")
+        .map_err(|_| RikError::NotFound(format!("Tenant id {} not found", tenant_id)))?;
+    // A quota stored before it was validated does not block the tenant
+    let Some(limit) = parse_quota(&tenant.value)
+        .ok()
+        .flatten()
+        .and_then(|quota| resource.limit(&quota))
+    else {
+        return Ok(());
+    };
+    let usage = RikRepository::count_with_prefix(
+        connection,
+        &format!(
+            "{}{}",
+            resource.element_type(),
+            tenant_segment(Some(tenant_id))
+        ),
+    )?;
+    if usage + requested > limit {
+        return Err(RikError::QuotaExceeded(QuotaUsage {
+            tenant_id: tenant_id.to_string(),
+            resource: resource.name(),
+            usage,
+            limit,
+            requested,
+        }));
+    }
+    Ok(())
+}
@@ -0,0 +1,578 @@
+use crate::api::external::services::element::{
+    element_set_right_name, elements_set_right_name, query_parameter,
+};
+use crate::api::external::services::instance::workload_instances;
+use crate::api::external::services::list::{ListParams, Page};
+use crate::api::external::services::tenant::caller_owns;
+use crate::api::types::element::tenant_segment;
+use crate::api::types::element::Element;
+use crate::api::types::error::ApiError;
+use crate::api::types::workload::{DeleteCollection, DeleteResult, DeleteStatus};
+use crate::api::{correlation, ApiChannel, Crud, RikError};
+use crate::database::events::{EventRepository, DELETED_REASON};
+use crate::database::RikRepository;
+use definition::workload::{WorkloadDefinition, WorkloadKind, WORKLOAD_KINDS};
+use rusqlite::Connection;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::mpsc::Sender;
+
+/// Field of the workload element holding the manifest as submitted
+const RAW_MANIFEST_FIELD: &str = "raw_manifest";
+/// Larger manifests are stored in their normalized form only
+const MAX_RAW_MANIFEST_BYTES: usize = 64 * 1024;
+/// Header naming who sends a request, recorded when a protection is overridden
+pub const ACTOR_HEADER: &str = "X-Rik-Actor";
+/// Reason of the events recorded when a protected workload is deleted
+pub const PROTECTION_OVERRIDDEN_REASON: &str = "ProtectionOverridden";
+
+/// Whether the manifests of a kind may hold secrets, those are never kept as submitted
+fn bears_secrets(kind: &WorkloadKind) -> bool {
+    match kind {
+        WorkloadKind::Pod | WorkloadKind::Function => false,
+    }
+}
+
+/// Whether the client asked for the manifests as submitted with `?raw=true`
+pub fn wants_raw(url: &str) -> bool {
+    query_parameter(url, "raw") == Some("true")
+}
+
+/// Value stored for a workload: its normalized definition, along with the
+/// manifest as submitted when it can be kept
+pub fn stored_value(workload: &WorkloadDefinition, raw_manifest: &str) -> Value {
+    let mut value = serde_json::to_value(workload).unwrap();
+    if raw_manifest.len() <= MAX_RAW_MANIFEST_BYTES && !bears_secrets(&workload.kind) {
+        value[RAW_MANIFEST_FIELD] = Value::String(raw_manifest.to_string());
+    }
+    value
+}
+
+/// Manifest of a workload as submitted, if it was kept
+pub fn raw_manifest(element: &Element) -> Option<&str> {
+    element.value.get(RAW_MANIFEST_FIELD)?.as_str()
+}
+
+/// Value of a manifest as submitted, in JSON or in YAML
+fn manifest_value(manifest: &str) -> Option<Value> {
+    serde_json::from_str(manifest)
+        .ok()
+        .or_else(|| serde_yaml::from_str(manifest).ok())
+}
+
+/// Element as exposed by the API, with its normalized definition or with the
+/// manifest as submitted when `raw` is asked and it was kept
+pub fn workload_view(mut element: Element, raw: bool) -> Element {
+    let raw_value = raw_manifest(&element)
+        .filter(|_| raw)
+        .and_then(manifest_value);
+    match raw_value {
+        Some(raw_value) => element.value = raw_value,
+        None => {
+            if let Some(value) = element.value.as_object_mut() {
+                value.remove(RAW_MANIFEST_FIELD);
+            }
+        }
+    }
+    element
+}
+
+/// Kind named in a request, whatever its case
+fn parse_kind(kind: &str) -> Option<WorkloadKind> {
+    [WorkloadKind::Pod, WorkloadKind::Function]
+        .into_iter()
+        .find(|known| known.to_string().eq_ignore_ascii_case(kind))
+}
+
+/// Page of the workloads listed, only those of `params.kind` when it is
+/// given, which no workload has when the kind is unknown
+pub fn find_workloads_page(connection: &Connection, params: &ListParams) -> rusqlite::Result<Page> {
+    if params.kind.as_deref().map(parse_kind) == Some(None) {
+        return Ok(Page {
+            items: Vec::new(),
+            total: 0,
+        });
+    }
+    params.find_page("/workload/", |query, limit, offset| {
+        RikRepository::find_page(connection, query, limit, offset)
+    })
+}
+
+pub fn matches_selector(labels: &BTreeMap<String, String>, selector: &[(String, String)]) -> bool {
+    selector
+        .iter()
+        .all(|(key, value)| labels.get(key) == Some(value))
+}
+
+/// Workloads targeted by a deletion, and the results of the ids and names not found
+pub fn find_deletion_targets(
+    connection: &Connection,
+    request: &DeleteCollection,
+    namespace: &str,
+    selector: &[(String, String)],
+) -> (Vec<Element>, Vec<DeleteResult>) {
+    let mut targets: Vec<Element> = Vec::new();
+    let mut not_found = Vec::new();
+    let not_found_result = |id: &str, name: &str| DeleteResult {
+        id: id.to_string(),
+        name: name.to_string(),
+        status: DeleteStatus::NotFound,
+        message: None,
+    };
+
+    for id in &request.ids {
+        match RikRepository::find_one(connection, id, "/workload") {
+            Ok(mut element) if caller_owns(element.value["tenant_id"].as_str()) => {
+                element_set_right_name(&mut element);
+                targets.push(element);
+            }
+            _ => not_found.push(not_found_result(id, "")),
+        }
+    }
+
+    // The workloads of other tenants are not seen by a tenant key
+    let mut namespace_workloads: Vec<Element> =
+        RikRepository::find_all(connection, &format!("/workload/%/{}/", namespace))
+            .unwrap_or_default();
+    namespace_workloads.retain(|element| caller_owns(element.value["tenant_id"].as_str()));
+    elements_set_right_name(&mut namespace_workloads);
+    for name in &request.names {
+        match namespace_workloads
+            .iter()
+            .find(|element| element.name == *name)
+        {
+            Some(element) => targets.push(element.clone()),
+            None => not_found.push(not_found_result("", name)),
+        }
+    }
+    if request.selector.is_some() {
+        targets.extend(namespace_workloads.into_iter().filter(|element| {
+            serde_json::from_value::<WorkloadDefinition>(element.value.clone())
+                .map(|workload| matches_selector(&workload.labels, selector))
+                .unwrap_or(false)
+        }));
+    }
+
+    // A workload may be given by id and matched by the selector
+    let mut seen = Vec::new();
+    targets.retain(|element| {
+        let first = !seen.contains(&element.id);
+        seen.push(element.id.clone());
+        first
+    });
+    (targets, not_found)
+}
+
+/// Workload of a tenant namespace with the given name, whatever its kind
+pub fn find_workload_by_name(
+    connection: &Connection,
+    tenant_id: Option<&str>,
+    namespace: &str,
+    name: &str,
+) -> Option<Element> {
+    find_workloads_named(connection, tenant_id, namespace, name)
+        .ok()?
+        .into_iter()
+        .next()
+}
+
+/// Workloads of a tenant namespace with exactly that name, whatever their
+/// kind. There are several only in databases from before the names were unique.
+pub fn find_workloads_named(
+    connection: &Connection,
+    tenant_id: Option<&str>,
+    namespace: &str,
+    name: &str,
+) -> rusqlite::Result<Vec<Element>> {
+    let mut found = Vec::new();
+    for kind in WORKLOAD_KINDS {
+        found.extend(RikRepository::find_all_by_name(
+            connection,
+            &format!(
+                "/workload/{}{}/{}/{}",
+                tenant_segment(tenant_id),
+                kind,
+                namespace,
+                name
+            ),
+        )?);
+    }
+    Ok(found)
+}
+
+/// Who overrides the protection of the workloads deleted, when the request
+/// asks to with `?override_protection=true`.
+///
+/// API tokens carry no role yet, so any client may override a protection,
+/// and is known by the name it gives in the `X-Rik-Actor` header.
+pub fn protection_override(req: &tiny_http::Request) -> Option<String> {
+    if query_parameter(req.url(), "override_protection") != Some("true") {
+        return None;
+    }
+    let actor = req
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(ACTOR_HEADER))
+        .map(|header| header.value.to_string())
+        .filter(|actor| !actor.is_empty());
+    Some(actor.unwrap_or_else(|| String::from("an unnamed client")))
+}
+
+fn is_protected(workload: &Element) -> bool {
+    workload.value.get("protected") == Some(&Value::Bool(true))
+}
+
+/// Why the deletion of a workload is refused, when it is protected and its
+/// protection is not overridden
+pub fn protection_error(workload: &Element, overridden_by: Option<&str>) -> Option<String> {
+    (is_protected(workload) && overridden_by.is_none()).then(|| {
+        format!(
+            "Workload {} is protected, delete it with ?override_protection=true",
+            workload.name
+        )
+    })
+}
+
+/// Why the deletion of a workload is refused, when it still has instances
+/// and they are not deleted along with it with `?force=true`
+pub fn instances_error(
+    connection: &Connection,
+    workload: &Element,
+    force: bool,
+) -> Result<Option<ApiError>, RikError> {
+    if force {
+        return Ok(None);
+    }
+    let instances = workload_instances(connection, &workload.id)
+        .map_err(|_| RikError::Internal(String::from("Cannot find workload instances")))?;
+    if instances.is_empty() {
+        return Ok(None);
+    }
+    let ids: Vec<&str> = instances
+        .iter()
+        .map(|instance| instance.id.as_str())
+        .collect();
+    Ok(Some(ApiError {
+        details: Some(json!({ "count": ids.len(), "instance_ids": ids })),
+        ..ApiError::new(
+            "WorkloadHasInstances",
+            format!(
+                "Workload {} has {} instances, delete them first or use ?force=true",
+                workload.name,
+                ids.len()
+            ),
+        )
+    }))
+}
+
+/// Delete a workload, along with its instances when forced to. The deletion
+/// of a protected workload is recorded as an event naming who overrode its
+/// protection.
+///
+/// The core is only told to delete the instances once the deletion is committed.
+pub fn delete_workload(
+    connection: &Connection,
+    internal_sender: &Sender<ApiChannel>,
+    workload: &Element,
+    overridden_by: Option<&str>,
+    force: bool,
+) -> Result<(), String> {
+    let deletions = RikRepository::transaction(connection, |tx| {
+        remove_workload(tx, workload, overridden_by, force)
+    })
+    .map_err(|e| e.to_string())?;
+    send_deletions(internal_sender, deletions)
+}
+
+/// Delete a workload and record it in its events, giving the messages asking
+/// the core to delete its instances. They are only to be sent once the
+/// transaction the workload is deleted in is committed.
+pub fn remove_workload(
+    connection: &Connection,
+    workload: &Element,
+    overridden_by: Option<&str>,
+    force: bool,
+) -> Result<Vec<ApiChannel>, RikError> {
+    if let Some(error) = protection_error(workload, overridden_by) {
+        return Err(RikError::Internal(error));
+    }
+    if let Some(error) = instances_error(connection, workload, force)? {
+        return Err(RikError::Internal(error.message));
+    }
+    let definition: WorkloadDefinition = serde_json::from_value(workload.value.clone())
+        .map_err(|e| RikError::Internal(format!("Could not parse workload: {}", e)))?;
+    let instances = workload_instances(connection, &workload.id)
+        .map_err(|e| RikError::Internal(format!("Could not find instances: {}", e)))?;
+    let deletions = instances
+        .into_iter()
+        .map(|instance| ApiChannel {
+            action: Crud::Delete,
+            workload_id: Some(workload.id.clone()),
+            workload_definition: Some(definition.clone()),
+            instance_id: Some(instance.id),
+            overrides: None,
+            namespace: None,
+            tenant_id: None,
+            correlation_id: correlation::current(),
+        })
+        .collect();
+    let database_error = |action: &str, e: rusqlite::Error| {
+        RikError::Internal(format!("Could not {}: {}", action, e))
+    };
+    RikRepository::delete(connection, &workload.id)
+        .map_err(|e| database_error("delete workload", e))?;
+    EventRepository::insert(
+        connection,
+        &workload.id,
+        DELETED_REASON,
+        &format!(
+            "Workload {} deleted along with its instances",
+            workload.name
+        ),
+    )
+    .map_err(|e| database_error("record the deletion of the workload", e))?;
+
+    if let (true, Some(actor)) = (is_protected(workload), overridden_by) {
+        EventRepository::insert(
+            connection,
+            &workload.id,
+            PROTECTION_OVERRIDDEN_REASON,
+            &format!("Protected workload {} deleted by {}", workload.name, actor),
+        )
+        .map_err(|e| database_error("record the protection override", e))?;
+    }
+    Ok(deletions)
+}
+
+/// Ask the core to delete the instances of workloads deleted
+pub fn send_deletions(
+    internal_sender: &Sender<ApiChannel>,
+    deletions: Vec<ApiChannel>,
+) -> Result<(), String> {
+    for deletion in deletions {
+        internal_sender
+            .send(deletion)
+            .map_err(|e| format!("Could not delete instances: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::types::workload::parse_selector;
+    use crate::database::events::EventQuery;
+    use crate::database::RikDataBase;
+    use crate::tests::fixtures::db_connection;
+    use rstest::rstest;
+
+    const MANIFEST: &str = r#"{"apiVersion": "v0", "kind": "Pod", "name": "raw", "spec": {}}"#;
+
+    fn element(raw_manifest: &str) -> Element {
+        let workload: WorkloadDefinition = serde_json::from_str(MANIFEST).unwrap();
+        Element {
+            id: String::from("id"),
+            name: String::from("raw"),
+            value: stored_value(&workload, raw_manifest),
+            path: Default::default(),
+            created_at: None,
+            updated_at: None,
+            version: None,
+        }
+    }
+
+    #[test]
+    fn test_raw_manifest_kept_alongside_definition() {
+        let element = element(MANIFEST);
+        assert_eq!(raw_manifest(&element), Some(MANIFEST));
+        // The stored value is still a valid definition
+        assert!(serde_json::from_value::<WorkloadDefinition>(element.value.clone()).is_ok());
+
+        let normalized = workload_view(element.clone(), false);
+        assert_eq!(normalized.value.get(RAW_MANIFEST_FIELD), None);
+        assert_eq!(
+            normalized.value["spec"]["containers"],
+            serde_json::json!([])
+        );
+
+        let raw = workload_view(element, true);
+        assert_eq!(raw.value, serde_json::from_str::<Value>(MANIFEST).unwrap());
+    }
+
+    #[test]
+    fn test_large_raw_manifest_is_not_kept() {
+        let large_manifest = format!("{}{}", MANIFEST, " ".repeat(MAX_RAW_MANIFEST_BYTES));
+        let element = element(&large_manifest);
+        assert_eq!(raw_manifest(&element), None);
+        assert_eq!(
+            workload_view(element.clone(), true).value,
+            workload_view(element, false).value
+        );
+    }
+
+    fn insert_workload(connection: &Connection, namespace: &str, name: &str, env: &str) -> String {
+        let workload: WorkloadDefinition = serde_json::from_value(serde_json::json!({
+            "apiVersion": "v0",
+            "kind": "Pod",
+            "name": name,
+            "spec": {},
+            "labels": {"env": env}
+        }))
+        .unwrap();
+        RikRepository::insert(
+            connection,
+            &format!("/workload/Pod/{}/{}", namespace, name),
+            &serde_json::to_string(&workload).unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_selector() {
+        assert_eq!(
+            parse_selector("env=scratch, team=web"),
+            Ok(vec![
+                (String::from("env"), String::from("scratch")),
+                (String::from("team"), String::from("web"))
+            ])
+        );
+        assert_eq!(
+            parse_selector("env=scratch,team"),
+            Err(String::from(
+                "Invalid selector requirement \"team\" in env=scratch,team, expected key=value pairs separated by commas"
+            ))
+        );
+        assert!(parse_selector("=scratch").is_err());
+
+        let labels = BTreeMap::from([
+            (String::from("env"), String::from("scratch")),
+            (String::from("team"), String::from("web")),
+        ]);
+        assert!(matches_selector(
+            &labels,
+            &parse_selector("env=scratch").unwrap()
+        ));
+        assert!(!matches_selector(
+            &labels,
+            &parse_selector("env=scratch,team=api").unwrap()
+        ));
+    }
+
+    #[rstest]
+    fn test_find_deletion_targets(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let scratch = insert_workload(&connection, "lab", "scratch-1", "scratch");
+        insert_workload(&connection, "lab", "scratch-2", "scratch");
+        insert_workload(&connection, "lab", "kept", "prod");
+        insert_workload(&connection, "other", "scratch-3", "scratch");
+
+        let request = DeleteCollection {
+            ids: vec![scratch, String::from("missing-id")],
+            names: vec![String::from("kept"), String::from("missing")],
+            selector: Some(String::from("env=scratch")),
+            namespace: None,
+        };
+        let (targets, not_found) = find_deletion_targets(
+            &connection,
+            &request,
+            "lab",
+            &parse_selector("env=scratch").unwrap(),
+        );
+
+        let mut names: Vec<&str> = targets
+            .iter()
+            .map(|element| element.name.as_str())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["kept", "scratch-1", "scratch-2"]);
+        assert_eq!(not_found.len(), 2);
+        assert!(not_found
+            .iter()
+            .all(|result| result.status == DeleteStatus::NotFound));
+    }
+
+    #[rstest]
+    #[case::pods(Some("Pod"), vec!["web"])]
+    #[case::any_case(Some("function"), vec!["thumbnails"])]
+    #[case::unknown_kind(Some("Job"), vec![])]
+    #[case::every_kind(None, vec!["web", "thumbnails"])]
+    fn test_find_workloads_page_of_kind(
+        db_connection: std::sync::Arc<RikDataBase>,
+        #[case] kind: Option<&str>,
+        #[case] expected: Vec<&str>,
+    ) {
+        let connection = db_connection.open().unwrap();
+        connection.execute("DELETE FROM cluster", []).unwrap();
+        insert_workload(&connection, "lab", "web", "prod");
+        RikRepository::insert(
+            &connection,
+            "/workload/Function/lab/thumbnails",
+            r#"{"apiVersion": "v0", "kind": "Function", "name": "thumbnails", "spec": {}}"#,
+        )
+        .unwrap();
+
+        let params = ListParams {
+            kind: kind.map(str::to_string),
+            ..Default::default()
+        };
+        let page = find_workloads_page(&connection, &params).unwrap();
+        let names: Vec<&str> = page
+            .items
+            .iter()
+            .map(|element| element.name.as_str())
+            .collect();
+        assert_eq!(names, expected);
+        assert_eq!(page.total, expected.len());
+    }
+
+    #[rstest]
+    fn test_find_workload_by_name(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let web = insert_workload(&connection, "lab", "web", "prod");
+        insert_workload(&connection, "other", "api", "prod");
+
+        let found = find_workload_by_name(&connection, None, "lab", "web").unwrap();
+        assert_eq!(found.id, web);
+        // Only whole names in the namespace are matched
+        assert!(find_workload_by_name(&connection, None, "lab", "we").is_none());
+        assert!(find_workload_by_name(&connection, None, "lab", "api").is_none());
+        // Of the tenant only
+        assert!(find_workload_by_name(&connection, Some("acme"), "lab", "web").is_none());
+    }
+
+    #[rstest]
+    fn test_delete_protected_workload(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let id = RikRepository::insert(
+            &connection,
+            "/workload/Pod/lab/payments",
+            r#"{"apiVersion": "v0", "kind": "Pod", "name": "payments", "spec": {}, "protected": true}"#,
+        )
+        .unwrap();
+        let workload = RikRepository::find_one(&connection, &id, "/workload").unwrap();
+        let (sender, _receiver) = std::sync::mpsc::channel();
+
+        assert!(protection_error(&workload, None).is_some());
+        assert!(delete_workload(&connection, &sender, &workload, None, false).is_err());
+        assert!(RikRepository::find_one(&connection, &id, "/workload").is_ok());
+
+        assert_eq!(protection_error(&workload, Some("alice")), None);
+        delete_workload(&connection, &sender, &workload, Some("alice"), false).unwrap();
+        assert!(RikRepository::find_one(&connection, &id, "/workload").is_err());
+        let query = EventQuery {
+            element_id: Some(id),
+            ..Default::default()
+        };
+        let events = EventRepository::list(&connection, &query).unwrap().events;
+        let reasons: Vec<&str> = events.iter().map(|event| event.reason.as_str()).collect();
+        assert_eq!(reasons, [DELETED_REASON, PROTECTION_OVERRIDDEN_REASON]);
+        assert!(events[1].message.ends_with("deleted by alice"));
+    }
+
+    #[test]
+    fn test_wants_raw() {
+        assert!(wants_raw("/api/v0/workloads.get/id?raw=true"));
+        assert!(!wants_raw("/api/v0/workloads.get/id?raw=false"));
+        assert!(!wants_raw("/api/v0/workloads.get/id"));
+    }
+}
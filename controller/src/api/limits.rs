@@ -0,0 +1,32 @@
+use definition::workload::EnvLimits;
+
+/// Limit read from an environment variable, the default is kept when it is
+/// missing or invalid
+pub fn limit_from_env(variable: &str, default: usize) -> usize {
+    std::env::var(variable)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Largest request body read by default, 1 MiB
+const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Largest request body read, larger ones are refused before being read whole
+pub fn max_body_bytes() -> usize {
+    limit_from_env("MAX_REQUEST_BODY_BYTES", DEFAULT_MAX_BODY_BYTES)
+}
+
+/// Limits on the environment variables of workloads, configured for the whole cluster
+pub fn env_limits() -> EnvLimits {
+    let defaults = EnvLimits::default();
+    EnvLimits {
+        max_entries_per_container: limit_from_env(
+            "MAX_ENV_ENTRIES",
+            defaults.max_entries_per_container,
+        ),
+        max_name_length: limit_from_env("MAX_ENV_NAME_LENGTH", defaults.max_name_length),
+        max_value_length: limit_from_env("MAX_ENV_VALUE_LENGTH", defaults.max_value_length),
+        max_total_bytes: limit_from_env("MAX_ENV_TOTAL_BYTES", defaults.max_total_bytes),
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+
+/// Route label of the requests answered before being routed, or never routed
+pub const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Requests answered by the API, per route, method and status
+#[derive(Debug, Default)]
+pub struct ApiMetrics {
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+}
+
+impl ApiMetrics {
+    pub fn record(&self, route: &str, method: &str, status: u16) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((route.to_string(), method.to_string(), status))
+            .or_default() += 1;
+    }
+
+    /// Counters in the Prometheus text format, followed by the given gauges
+    /// as `(name, help, value)`
+    pub fn render(&self, gauges: &[(&str, &str, u64)]) -> String {
+        let mut text = String::from(
+            "# HELP rik_http_requests_total Requests answered by the API\n\
+             # TYPE rik_http_requests_total counter\n",
+        );
+        for ((route, method, status), count) in self.requests.lock().unwrap().iter() {
+            writeln!(
+                text,
+                "rik_http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+                escape_label(route),
+                escape_label(method),
+                status,
+                count
+            )
+            .unwrap();
+        }
+        for (name, help, value) in gauges {
+            writeln!(text, "# HELP {} {}", name, help).unwrap();
+            writeln!(text, "# TYPE {} gauge", name).unwrap();
+            writeln!(text, "{} {}", name, value).unwrap();
+        }
+        text
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Counters shared by every server thread, whatever their router
+pub fn api_metrics() -> &'static ApiMetrics {
+    static METRICS: OnceLock<ApiMetrics> = OnceLock::new();
+    METRICS.get_or_init(ApiMetrics::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = ApiMetrics::default();
+        metrics.record("/api/v0/workloads.get/:workloadid", "GET", 200);
+        metrics.record("/api/v0/workloads.get/:workloadid", "GET", 200);
+        metrics.record("/api/v0/workloads.get/:workloadid", "GET", 404);
+        let text = metrics.render(&[("rik_workloads", "Workloads stored", 2)]);
+        assert!(text.contains(
+            "rik_http_requests_total{route=\"/api/v0/workloads.get/:workloadid\",method=\"GET\",status=\"200\"} 2\n"
+        ));
+        assert!(text.contains("status=\"404\"} 1\n"));
+        assert!(text.ends_with("# TYPE rik_workloads gauge\nrik_workloads 2\n"));
+        assert_eq!(escape_label("a\"b\\"), "a\\\"b\\\\");
+    }
+}
@@ -1,13 +1,46 @@
+pub mod auth;
+pub mod concurrency;
+pub mod correlation;
+pub mod cors;
+pub mod drain;
 pub mod external;
+pub mod limits;
+pub mod metrics;
+pub mod rate_limit;
+pub mod read_only;
+pub mod response;
 pub mod types;
+pub mod validation;
 
-use definition::workload::WorkloadDefinition;
+use crate::api::response::api_error_response;
+use crate::api::types::error::ApiError;
+use crate::api::types::tenant::QuotaUsage;
+use crate::database::{UpdateError, VersionConflict};
+use definition::workload::{InstanceOverrides, WorkloadDefinition};
 use std::fmt::{Debug, Display, Formatter, Result};
+use std::io;
+
+/// Path of a request URL as routes are matched, without its query string
+/// nor a trailing slash
+pub fn request_path(url: &str) -> &str {
+    let path = url.split('?').next().unwrap_or_default();
+    match path.strip_suffix('/') {
+        Some(stripped) if !stripped.is_empty() => stripped,
+        _ => path,
+    }
+}
 
 #[derive(Debug)]
 pub enum Crud {
     Create = 0,
     Delete = 1,
+    Update = 2,
+    /// Only handled by the controller, the scheduler never receives it
+    Scale = 3,
+    /// Sent by the readiness probe to check the channel, ignored by the core
+    Ping = 4,
+    /// Bring an instance down then up again on its worker
+    Restart = 5,
 }
 
 impl From<i32> for Crud {
@@ -15,40 +48,145 @@ impl From<i32> for Crud {
         match value {
             0 => Crud::Create,
             1 => Crud::Delete,
+            2 => Crud::Update,
+            3 => Crud::Scale,
+            4 => Crud::Ping,
+            5 => Crud::Restart,
             _ => panic!("Invalid CRUD value"),
         }
     }
 }
 
+/// Error code of the creations of an element whose name is already used
+pub const ALREADY_EXISTS_CODE: &str = "AlreadyExists";
+/// Error code of the creations refused as they exceed the quota of a tenant
+pub const QUOTA_EXCEEDED_CODE: &str = "QuotaExceeded";
+/// Error code of the writes of an element which changed since it was read
+pub const VERSION_CONFLICT_CODE: &str = "VersionConflict";
+
 #[derive(Debug)]
 pub enum RikError {
     IoError(std::io::Error),
     HttpRequestError(serde_json::Error),
+    YamlRequestError(serde_yaml::Error),
     InternalCommunicationError(String),
     InvalidName(String),
+    /// Request which cannot be handled as sent
+    InvalidBody(String),
+    NotFound(String),
+    /// Name already used, along with the id of the element using it
+    Conflict(String, String),
+    /// Failure of the controller itself, such as a database error
+    Internal(String),
+    /// Request body larger than the given number of bytes
+    PayloadTooLarge(usize),
+    /// Creation exceeding the quota of a tenant
+    QuotaExceeded(QuotaUsage),
+    /// Element changed since the version it was read at
+    VersionConflict(VersionConflict),
 }
 impl Display for RikError {
     fn fmt(&self, f: &mut Formatter) -> Result {
         match *self {
             RikError::IoError(ref e) => write!(f, "{}", e),
             RikError::HttpRequestError(ref e) => write!(f, "{}", e),
+            RikError::YamlRequestError(ref e) => write!(f, "{}", e),
             RikError::InternalCommunicationError(ref e) => write!(f, "{}", e),
             RikError::InvalidName(ref e) => write!(f, "{}", e),
+            RikError::InvalidBody(ref e) => write!(f, "{}", e),
+            RikError::NotFound(ref e) => write!(f, "{}", e),
+            RikError::Conflict(ref e, _) => write!(f, "{}", e),
+            RikError::Internal(ref e) => write!(f, "{}", e),
+            RikError::PayloadTooLarge(limit) => {
+                write!(f, "The request body is larger than {} bytes", limit)
+            }
+            RikError::QuotaExceeded(ref usage) => write!(
+                f,
+                "Tenant {} may own {} {}, it owns {} and {} more were asked for",
+                usage.tenant_id, usage.limit, usage.resource, usage.usage, usage.requested
+            ),
+            RikError::VersionConflict(ref conflict) => match conflict.current {
+                Some(current) => write!(
+                    f,
+                    "Element {} is at version {}, not {}",
+                    conflict.id, current, conflict.expected
+                ),
+                None => write!(f, "Element {} was deleted", conflict.id),
+            },
         }
     }
 }
 
+impl RikError {
+    /// Status and error code of the answer to a request failing with this error
+    pub fn status(&self) -> (u16, &'static str) {
+        match self {
+            RikError::IoError(_)
+            | RikError::HttpRequestError(_)
+            | RikError::YamlRequestError(_)
+            | RikError::InvalidBody(_) => (400, "InvalidBody"),
+            RikError::InvalidName(_) => (400, "InvalidName"),
+            RikError::QuotaExceeded(_) => (403, QUOTA_EXCEEDED_CODE),
+            RikError::NotFound(_) => (404, "NotFound"),
+            RikError::Conflict(..) => (409, ALREADY_EXISTS_CODE),
+            RikError::VersionConflict(_) => (409, VERSION_CONFLICT_CODE),
+            RikError::PayloadTooLarge(_) => (413, "PayloadTooLarge"),
+            RikError::InternalCommunicationError(_) | RikError::Internal(_) => (500, "Internal"),
+        }
+    }
+
+    pub fn response(&self) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+        let (status, code) = self.status();
+        let mut error = ApiError::new(code, self.to_string());
+        match self {
+            RikError::HttpRequestError(e) => {
+                error.details = Some(serde_json::json!({ "line": e.line(), "column": e.column() }))
+            }
+            RikError::YamlRequestError(e) => {
+                error.details = e.location().map(|location| {
+                    serde_json::json!({ "line": location.line(), "column": location.column() })
+                })
+            }
+            RikError::Conflict(_, existing_id) => error.id = Some(existing_id.clone()),
+            RikError::QuotaExceeded(usage) => {
+                error.details = Some(serde_json::to_value(usage).unwrap())
+            }
+            RikError::VersionConflict(conflict) => {
+                error.details = Some(serde_json::to_value(conflict).unwrap())
+            }
+            _ => {}
+        }
+        api_error_response(status, &error)
+    }
+}
+
 impl std::error::Error for RikError {
     fn cause(&self) -> Option<&dyn std::error::Error> {
         match *self {
             RikError::IoError(ref e) => Some(e),
             RikError::HttpRequestError(ref e) => Some(e),
+            RikError::YamlRequestError(ref e) => Some(e),
             // TODO: Implement other errors
             _ => None,
         }
     }
 }
 
+impl From<rusqlite::Error> for RikError {
+    fn from(e: rusqlite::Error) -> RikError {
+        RikError::Internal(format!("Database error: {}", e))
+    }
+}
+
+impl From<UpdateError> for RikError {
+    fn from(e: UpdateError) -> RikError {
+        match e {
+            UpdateError::Conflict(conflict) => RikError::VersionConflict(conflict),
+            UpdateError::Database(e) => e.into(),
+        }
+    }
+}
+
 impl From<std::io::Error> for RikError {
     fn from(e: std::io::Error) -> RikError {
         RikError::IoError(e)
@@ -61,18 +199,58 @@ impl From<serde_json::Error> for RikError {
     }
 }
 
+impl From<serde_yaml::Error> for RikError {
+    fn from(e: serde_yaml::Error) -> RikError {
+        RikError::YamlRequestError(e)
+    }
+}
+
 pub struct ApiChannel {
     pub action: Crud,
     pub workload_id: Option<String>,
     pub instance_id: Option<String>,
     pub workload_definition: Option<WorkloadDefinition>,
+    /// Overrides already merged in the workload definition of the instance
+    pub overrides: Option<InstanceOverrides>,
+    /// Namespace of the instance, the default one is used when none is given
+    pub namespace: Option<String>,
+    /// Tenant owning the instance, if any
+    pub tenant_id: Option<String>,
+    /// Identifier of the operation, shared by the logs of every component
+    pub correlation_id: String,
 }
 impl Display for ApiChannel {
     fn fmt(&self, f: &mut Formatter) -> Result {
         write!(
             f,
-            "Action: {:?}, Workload id: {:?}, Instance id: {:?}",
-            self.action, self.workload_id, self.instance_id
+            "Action: {:?}, Workload id: {:?}, Instance id: {:?}, Correlation id: {}",
+            self.action, self.workload_id, self.instance_id, self.correlation_id
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_path() {
+        assert_eq!(
+            request_path("/api/v0/workloads.list"),
+            "/api/v0/workloads.list"
+        );
+        assert_eq!(
+            request_path("/api/v0/workloads.list/"),
+            "/api/v0/workloads.list"
+        );
+        assert_eq!(
+            request_path("/api/v0/workloads.list/?limit=1"),
+            "/api/v0/workloads.list"
+        );
+        assert_eq!(
+            request_path("/api/v0/workloads.list//"),
+            "/api/v0/workloads.list/"
+        );
+        assert_eq!(request_path("/"), "/");
+    }
+}
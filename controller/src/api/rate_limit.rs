@@ -0,0 +1,170 @@
+use crate::api::limits::limit_from_env;
+use crate::api::response::error_response;
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Error code of the requests refused because their client sends too many
+pub const RATE_LIMITED_CODE: &str = "RateLimited";
+/// Clients tracked before the ones whose bucket is full again are forgotten
+const MAX_TRACKED_CLIENTS: usize = 4096;
+
+/// Requests a client may send, on average and at once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimits {
+    /// Requests a second, `0` disables the limit
+    pub requests_per_second: usize,
+    /// Requests sent at once after a quiet period
+    pub burst: usize,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            requests_per_second: 50,
+            burst: 100,
+        }
+    }
+}
+
+impl RateLimits {
+    pub fn from_env() -> RateLimits {
+        let defaults = RateLimits::default();
+        RateLimits {
+            requests_per_second: limit_from_env(
+                "RATE_LIMIT_PER_SECOND",
+                defaults.requests_per_second,
+            ),
+            burst: limit_from_env("RATE_LIMIT_BURST", defaults.burst).max(1),
+        }
+    }
+}
+
+/// Requests a client may still send at once, refilled as time goes
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// Token buckets of the clients of the API, by address, shared by every
+/// server thread
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> RateLimiter {
+        RateLimiter {
+            limits,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token of the bucket of a client, or else give how long it has
+    /// to wait for the next one
+    pub fn check(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        if self.limits.requests_per_second == 0 {
+            return Ok(());
+        }
+        let rate = self.limits.requests_per_second as f64;
+        let burst = self.limits.burst as f64;
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            // A bucket full again is the same as a new one
+            let refill = Duration::from_secs_f64(burst / rate);
+            buckets.retain(|_, bucket| now.saturating_duration_since(bucket.refilled) < refill);
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: burst,
+            refilled: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.refilled);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * rate).min(burst);
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+/// Answer to a client sending too many requests, told to wait `wait`
+pub fn rate_limited(wait: Duration) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    // Whole seconds, rounded up so a client waiting them is let through
+    let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    error_response(
+        429,
+        RATE_LIMITED_CODE,
+        String::from("Too many requests sent, slow down"),
+    )
+    .with_header(
+        tiny_http::Header::from_str(&format!("Retry-After: {}", retry_after.max(1))).unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(requests_per_second: usize, burst: usize) -> RateLimiter {
+        RateLimiter::new(RateLimits {
+            requests_per_second,
+            burst,
+        })
+    }
+
+    #[test]
+    fn test_burst_then_rate() {
+        let limiter = limiter(2, 3);
+        let client = IpAddr::from([10, 0, 0, 1]);
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.check(client, start), Ok(()));
+        }
+        assert_eq!(
+            limiter.check(client, start),
+            Err(Duration::from_millis(500))
+        );
+        // Other clients have their own bucket
+        assert_eq!(limiter.check(IpAddr::from([10, 0, 0, 2]), start), Ok(()));
+
+        let later = start + Duration::from_millis(500);
+        assert_eq!(limiter.check(client, later), Ok(()));
+        assert!(limiter.check(client, later).is_err());
+        // Refilled up to the burst only
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(limiter.check(client, much_later), Ok(()));
+        }
+        assert!(limiter.check(client, much_later).is_err());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let limiter = limiter(0, 1);
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert_eq!(limiter.check(IpAddr::from([10, 0, 0, 1]), now), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let response = rate_limited(Duration::from_millis(1200));
+        assert_eq!(response.status_code().0, 429);
+        let retry_after = response
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Retry-After"))
+            .unwrap();
+        assert_eq!(retry_after.value.as_str(), "2");
+    }
+}
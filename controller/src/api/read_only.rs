@@ -0,0 +1,122 @@
+use crate::api::response::error_response;
+use serde::Serialize;
+use std::io;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use tracing::{event, Level};
+
+/// Error code of the mutations refused while the API is read-only
+pub const READ_ONLY_CODE: &str = "ReadOnly";
+/// Seconds clients are told to wait before sending a refused mutation again
+const RETRY_AFTER_SECONDS: u64 = 30;
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadOnlyStatus {
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// RFC 3339 date at which the mode was entered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+}
+
+/// Mode in which the API keeps answering reads while refusing mutations,
+/// e.g. during a database maintenance
+#[derive(Debug, Default)]
+pub struct ReadOnlyMode {
+    status: Mutex<ReadOnlyStatus>,
+}
+
+impl ReadOnlyMode {
+    /// Entered at startup when `READ_ONLY` is set to `true`
+    fn from_env() -> ReadOnlyMode {
+        let mode = ReadOnlyMode::default();
+        if std::env::var("READ_ONLY").is_ok_and(|value| value == "true") {
+            mode.set(true, Some(String::from("Started read-only")));
+        }
+        mode
+    }
+
+    pub fn set(&self, enabled: bool, reason: Option<String>) -> ReadOnlyStatus {
+        let mut status = self.status.lock().unwrap();
+        if enabled != status.enabled {
+            event!(
+                Level::WARN,
+                "API {} read-only mode: {}",
+                if enabled { "entering" } else { "leaving" },
+                reason.as_deref().unwrap_or("no reason given")
+            );
+        }
+        *status = match enabled {
+            true => ReadOnlyStatus {
+                enabled,
+                reason,
+                // Changing the reason does not reset the date
+                since: status
+                    .since
+                    .clone()
+                    .or_else(|| Some(chrono::Utc::now().to_rfc3339())),
+            },
+            false => ReadOnlyStatus::default(),
+        };
+        status.clone()
+    }
+
+    pub fn status(&self) -> ReadOnlyStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.status.lock().unwrap().enabled
+    }
+
+    /// Answer given to a mutation while read-only
+    pub fn response(&self) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+        let status = self.status();
+        let message = format!(
+            "The API is read-only: {}",
+            status.reason.as_deref().unwrap_or("no reason given")
+        );
+        error_response(503, READ_ONLY_CODE, message).with_header(
+            tiny_http::Header::from_str(&format!("Retry-After: {}", RETRY_AFTER_SECONDS)).unwrap(),
+        )
+    }
+}
+
+/// Read-only mode shared by every server thread
+pub fn read_only() -> &'static ReadOnlyMode {
+    static MODE: OnceLock<ReadOnlyMode> = OnceLock::new();
+    MODE.get_or_init(ReadOnlyMode::from_env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle_keeps_the_date() {
+        let mode = ReadOnlyMode::default();
+        assert!(!mode.is_enabled());
+
+        let entered = mode.set(true, Some(String::from("vacuum")));
+        let changed = mode.set(true, Some(String::from("backup")));
+        assert_eq!(changed.reason.as_deref(), Some("backup"));
+        assert_eq!(changed.since, entered.since);
+
+        assert_eq!(mode.set(false, None), ReadOnlyStatus::default());
+        assert!(!mode.is_enabled());
+    }
+
+    #[test]
+    fn test_refusal_response() {
+        let mode = ReadOnlyMode::default();
+        mode.set(true, Some(String::from("vacuum")));
+        let response = mode.response();
+
+        assert_eq!(response.status_code(), tiny_http::StatusCode::from(503));
+        assert!(response
+            .headers()
+            .iter()
+            .any(|header| header.field.equiv("Retry-After") && header.value == "30"));
+    }
+}
@@ -0,0 +1,46 @@
+use crate::api::types::error::ApiError;
+use std::cell::Cell;
+use std::io;
+use std::str::FromStr;
+
+thread_local! {
+    /// Version of the API the request handled by the current thread was sent to
+    static API_VERSION: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Answer with an error code clients can match on, along with a readable message
+pub fn error_response(
+    status: u16,
+    code: &str,
+    message: impl Into<String>,
+) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    api_error_response(status, &ApiError::new(code, message))
+}
+
+pub fn api_error_response(
+    status: u16,
+    error: &ApiError,
+) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    let error = ApiError {
+        version: api_version().map(String::from),
+        ..error.clone()
+    };
+    tiny_http::Response::from_string(serde_json::to_string(&error).unwrap())
+        .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+        .with_status_code(tiny_http::StatusCode::from(status))
+}
+
+/// Handle a request sent to the given version of the API, its error answers
+/// naming it. v0 requests are handled without one, so their answers stay as
+/// they always were.
+pub fn with_api_version<R>(version: Option<&'static str>, f: impl FnOnce() -> R) -> R {
+    let previous = API_VERSION.with(|current| current.replace(version));
+    let result = f();
+    API_VERSION.with(|current| current.set(previous));
+    result
+}
+
+/// Version of the API the request being handled was sent to, `None` for v0
+pub fn api_version() -> Option<&'static str> {
+    API_VERSION.with(Cell::get)
+}
@@ -0,0 +1,13 @@
+use crate::api::validation::ValidateRequest;
+use serde::{Deserialize, Serialize};
+
+/// Body of `admin.read_only`, entering or leaving the read-only mode of the API
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ReadOnlyChange {
+    pub enabled: bool,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl ValidateRequest for ReadOnlyChange {}
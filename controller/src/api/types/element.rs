@@ -1,16 +1,105 @@
+use crate::api::validation::{FieldError, ValidateRequest};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct OnlyId {
     pub id: String,
 }
 
+impl ValidateRequest for OnlyId {
+    fn validate(&self) -> Vec<FieldError> {
+        if self.id.trim().is_empty() {
+            return vec![FieldError::new("id", "The id must not be empty")];
+        }
+        vec![]
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Element {
     pub id: String,
     pub name: String,
     pub value: serde_json::Value,
+    /// Segments of the hierarchical name, only filled in list responses
+    #[serde(flatten, default)]
+    pub path: ElementPath,
+    /// RFC 3339 date the element was stored, none when it was not read from
+    /// the database
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    /// RFC 3339 date of the last change of the element
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+    /// Incremented by every update, to write the element only as it was read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<i64>,
+}
+
+/// Element found by a search, only its id and short name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NameMatch {
+    pub id: String,
+    pub name: String,
+}
+
+/// Segment of the hierarchical name of an element owned by a tenant, such as
+/// `/workload/{tenant}/{kind}/{namespace}/{name}`, empty without a tenant
+pub fn tenant_segment(tenant_id: Option<&str>) -> String {
+    tenant_id
+        .map(|tenant_id| format!("{}/", tenant_id))
+        .unwrap_or_default()
+}
+
+/// Segments of the hierarchical name of an element, such as
+/// `/workload/{tenant}/{kind}/{namespace}/{name}`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ElementPath {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+impl ElementPath {
+    /// Split a hierarchical name into its segments and its short name, names
+    /// which are not paths have no segments.
+    ///
+    /// Segments are read from the end as only the tenant is optional.
+    pub fn parse(full_name: &str) -> (ElementPath, &str) {
+        let Some(path) = full_name.strip_prefix('/') else {
+            return (ElementPath::default(), full_name);
+        };
+        let mut segments = path.split('/');
+        let element_type = segments.next().unwrap_or_default();
+        let mut segments: Vec<&str> = segments.collect();
+        let Some(name) = segments.pop() else {
+            return (ElementPath::default(), full_name);
+        };
+
+        let mut pop = |present: bool| {
+            if present {
+                segments.pop().map(str::to_string)
+            } else {
+                None
+            }
+        };
+        let namespace = pop(matches!(element_type, "workload" | "instance" | "volume"));
+        let kind = pop(matches!(element_type, "workload" | "instance"));
+        let tenant = pop(true);
+        let path = ElementPath {
+            full_name: Some(full_name.to_string()),
+            kind,
+            tenant,
+            namespace,
+        };
+        (path, name)
+    }
 }
 
 #[allow(dead_code)]
@@ -20,9 +109,23 @@ impl Element {
             id,
             name,
             value: serde_json::from_str(&value).unwrap(),
+            path: ElementPath::default(),
+            created_at: None,
+            updated_at: None,
+            version: None,
         }
     }
 
+    /// Element read from a row of `id, name, value, created_at, updated_at`
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Element> {
+        Ok(Element {
+            created_at: row.get(3)?,
+            updated_at: row.get(4)?,
+            version: row.get(5)?,
+            ..Element::new(row.get(0)?, row.get(1)?, row.get(2)?)
+        })
+    }
+
     pub fn set_name(&mut self, name: String) {
         self.name = name;
     }
@@ -40,3 +143,56 @@ impl fmt::Display for Element {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_paths() {
+        let (path, name) = ElementPath::parse("/workload/pods/default/web");
+        assert_eq!(name, "web");
+        assert_eq!(
+            path,
+            ElementPath {
+                full_name: Some(String::from("/workload/pods/default/web")),
+                kind: Some(String::from("pods")),
+                tenant: None,
+                namespace: Some(String::from("default")),
+            }
+        );
+
+        let (path, name) = ElementPath::parse("/instance/acme/pods/staging/web-1234");
+        assert_eq!(name, "web-1234");
+        assert_eq!(path.tenant.as_deref(), Some("acme"));
+        assert_eq!(path.kind.as_deref(), Some("pods"));
+        assert_eq!(path.namespace.as_deref(), Some("staging"));
+
+        let (path, name) = ElementPath::parse("/volume/default/data");
+        assert_eq!(name, "data");
+        assert_eq!(path.kind, None);
+        assert_eq!(path.namespace.as_deref(), Some("default"));
+    }
+
+    #[test]
+    fn test_parse_names_without_segments() {
+        let (path, name) = ElementPath::parse("/tenant/acme");
+        assert_eq!(name, "acme");
+        assert_eq!(path.namespace, None);
+        assert_eq!(path.full_name.as_deref(), Some("/tenant/acme"));
+
+        let (path, name) = ElementPath::parse("acme");
+        assert_eq!(name, "acme");
+        assert_eq!(path, ElementPath::default());
+    }
+
+    #[test]
+    fn test_parse_names_with_dots() {
+        let (path, name) = ElementPath::parse("/workload/function/default/api.v2.example");
+        assert_eq!(name, "api.v2.example");
+        assert_eq!(path.namespace.as_deref(), Some("default"));
+
+        let (_, name) = ElementPath::parse("/workload/pods/default/..");
+        assert_eq!(name, "..");
+    }
+}
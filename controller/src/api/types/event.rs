@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// Something that happened to an element, such as an instance changing status
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// Increases with every event, so it orders them and serves as a cursor
+    pub id: i64,
+    /// RFC 3339 date, in UTC
+    pub created_at: String,
+    pub element_id: String,
+    /// Tenant owning the element, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+    pub reason: String,
+    pub message: String,
+}
+
+/// Page of `events.list`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct EventPage {
+    pub events: Vec<Event>,
+    /// Cursor to give as `since` to get the following events, it is kept when
+    /// no event is left so it can be polled
+    pub next: i64,
+}
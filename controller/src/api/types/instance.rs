@@ -1,12 +1,24 @@
+use crate::api::validation::{FieldError, ValidateRequest};
+use definition::workload::InstanceOverrides;
 use names::Generator;
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct InstanceDefinition {
     pub name: Option<String>,
     pub workload_id: String,
     pub replicas: Option<usize>,
+    /// Changes merged onto the workload definition for these instances only
+    #[serde(default)]
+    pub overrides: Option<InstanceOverrides>,
+    /// Namespace of the instances, resolved from the defaults when not given
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// Tenant acting on the workload, the `X-Rik-Tenant` header when not given
+    #[serde(default)]
+    pub tenant_id: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -26,6 +38,48 @@ impl InstanceDefinition {
     }
 }
 
+impl ValidateRequest for InstanceDefinition {
+    fn validate(&self) -> Vec<FieldError> {
+        let mut errors = vec![];
+        if self.workload_id.trim().is_empty() {
+            errors.push(FieldError::new(
+                "workload_id",
+                "The workload id must not be empty",
+            ));
+        }
+        match self.replicas {
+            Some(0) => errors.push(FieldError::new(
+                "replicas",
+                "At least one replica must be created",
+            )),
+            Some(replicas) if replicas > 1 && self.name.is_some() => errors.push(FieldError::new(
+                "name",
+                "A name cannot be given with more than one replica",
+            )),
+            _ => {}
+        }
+        let image_tag = self
+            .overrides
+            .as_ref()
+            .and_then(|overrides| overrides.image_tag.as_deref());
+        if image_tag.is_some_and(|tag| tag.trim().is_empty()) {
+            errors.push(FieldError::new(
+                "overrides.image_tag",
+                "The image tag must not be empty",
+            ));
+        }
+        errors
+    }
+}
+
+/// Status an instance went to, in the history given by `instances.get/:id`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StatusChange {
+    pub status: String,
+    /// RFC 3339 date of the change
+    pub at: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Instance {
     pub id: usize,
@@ -50,6 +104,7 @@ impl InstanceStatus {
             4 => "Terminated".to_string(),
             5 => "Creating".to_string(),
             6 => "Destroying".to_string(),
+            7 => "Restarting".to_string(),
             _ => "Creating".to_string(),
         };
 
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+/// Answer of the list routes, a page of elements along with the amount of
+/// elements listed across every page
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ListResponse<T> {
+    pub items: Vec<T>,
+    /// Elements left by the filters before the page is cut
+    pub total: usize,
+    /// Revision of the database the elements were listed at, their changes
+    /// can be watched from it
+    pub revision: u64,
+}
@@ -1,3 +1,11 @@
+pub mod admin;
 pub mod element;
+pub mod error;
+pub mod event;
 pub mod instance;
+pub mod list;
+pub mod node;
 pub mod tenant;
+pub mod usage;
+pub mod volume;
+pub mod workload;
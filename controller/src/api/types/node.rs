@@ -0,0 +1,19 @@
+use crate::api::validation::{FieldError, ValidateRequest};
+use serde::{Deserialize, Serialize};
+
+/// Body of `nodes.cordon`, stopping or resuming the placement of instances on a node
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NodeCordon {
+    pub node: String,
+    pub cordoned: bool,
+}
+
+impl ValidateRequest for NodeCordon {
+    fn validate(&self) -> Vec<FieldError> {
+        if self.node.trim().is_empty() {
+            return vec![FieldError::new("node", "The node must not be empty")];
+        }
+        vec![]
+    }
+}
@@ -1,3 +1,4 @@
+use crate::api::validation::ValidateRequest;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -13,3 +14,34 @@ impl fmt::Display for Tenant {
         write!(f, "Id: {}, Name: {}", self.id, self.name)
     }
 }
+
+/// Elements a tenant may own at most, without limit when not given
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Quota {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_workloads: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_instances: Option<usize>,
+}
+
+/// Quota a creation would exceed, with what the tenant already owns
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct QuotaUsage {
+    pub tenant_id: String,
+    /// `workloads` or `instances`
+    pub resource: &'static str,
+    pub usage: usize,
+    pub limit: usize,
+    /// Elements the refused request would have created
+    pub requested: usize,
+}
+
+/// Body of `tenants.set_quota`, a `null` or missing quota lifts the limits
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TenantQuota {
+    pub id: String,
+    pub quota: Option<Quota>,
+}
+
+impl ValidateRequest for TenantQuota {}
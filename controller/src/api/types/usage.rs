@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+/// What the usage is rolled up by
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageGrouping {
+    #[default]
+    Tenant,
+    Workload,
+}
+
+impl UsageGrouping {
+    pub fn parse(value: &str) -> Option<UsageGrouping> {
+        match value {
+            "tenant" => Some(UsageGrouping::Tenant),
+            "workload" => Some(UsageGrouping::Workload),
+            _ => None,
+        }
+    }
+}
+
+/// Usage of a tenant, or of a workload, over the range of a report
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Usage {
+    /// None for the elements created without a tenant
+    pub tenant: Option<String>,
+    /// Only given when grouped by workload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workload_id: Option<String>,
+    /// Instances which existed during the range
+    pub instances: u64,
+    /// Time the instances existed during the range, from their creation to
+    /// their termination
+    pub instance_hours: f64,
+    /// Instances running again after having run before
+    pub restarts: u64,
+    pub failures: u64,
+}
+
+/// Answer of `usage`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UsageReport {
+    /// RFC 3339 dates, in UTC
+    pub from: String,
+    pub to: String,
+    pub group_by: UsageGrouping,
+    pub groups: Vec<Usage>,
+}
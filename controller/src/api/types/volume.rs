@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// Named persistent volume, stored on the node of the first instance using it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Volume {
+    pub name: String,
+    pub size_mb: u64,
+    /// Namespace of the volume, resolved from the defaults when not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Tenant owning the volume, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+    /// Node holding the volume, set once an instance using it is placed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    /// Instance currently using the volume
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bound_to: Option<String>,
+}
@@ -0,0 +1,163 @@
+use crate::api::validation::{FieldError, ValidateRequest};
+use definition::workload::{WorkloadDefinition, WORKLOAD_KINDS};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Workloads deleted by `workloads.delete_collection`, by id, by name or by
+/// label selector
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DeleteCollection {
+    #[serde(default)]
+    pub ids: Vec<String>,
+    /// Names of workloads of the namespace
+    #[serde(default)]
+    pub names: Vec<String>,
+    /// Labels the workloads of the namespace must all have, e.g. `env=scratch,team=web`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selector: Option<String>,
+    /// Namespace of the names and selector, resolved from the defaults when not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+/// Workload deleted by `workloads.delete`, by id or by name
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct WorkloadReference {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Name of a workload of the namespace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Namespace of the name, resolved from the defaults when not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+impl ValidateRequest for WorkloadReference {
+    fn validate(&self) -> Vec<FieldError> {
+        match (&self.id, &self.name) {
+            (Some(_), Some(_)) => vec![FieldError::body("Give either an id or a name, not both")],
+            (None, None) => vec![FieldError::body("An id or a name must be given")],
+            (Some(id), None) if id.trim().is_empty() => {
+                vec![FieldError::new("id", "The id must not be empty")]
+            }
+            (Some(_), None) if self.namespace.is_some() => vec![FieldError::new(
+                "namespace",
+                "The namespace is only given along with a name",
+            )],
+            (None, Some(name)) if name.trim().is_empty() => {
+                vec![FieldError::new("name", "The name must not be empty")]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// Replica count asked for a workload by `workloads.scale`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScaleWorkload {
+    pub id: String,
+    /// Signed so a negative count is answered with a `400` rather than a
+    /// deserialization error
+    pub replicas: i64,
+}
+
+impl ScaleWorkload {
+    /// Replica count to store, none when it is out of range
+    pub fn replicas(&self) -> Option<u16> {
+        u16::try_from(self.replicas).ok()
+    }
+}
+
+impl ValidateRequest for ScaleWorkload {
+    fn validate(&self) -> Vec<FieldError> {
+        if self.id.is_empty() {
+            return vec![FieldError::new("id", "The id must not be empty")];
+        }
+        vec![]
+    }
+}
+
+/// Checks on the cluster configuration are left to the admission pipeline
+impl ValidateRequest for WorkloadDefinition {
+    fn validate(&self) -> Vec<FieldError> {
+        self.validate_fields()
+            .into_iter()
+            .map(|error| FieldError::new(&error.field, error.reason))
+            .collect()
+    }
+
+    fn validate_value(value: &Value) -> Vec<FieldError> {
+        match value.get("kind").and_then(Value::as_str) {
+            Some(kind) if !WORKLOAD_KINDS.contains(&kind) => vec![FieldError::new(
+                "kind",
+                format!(
+                    "Unknown kind {}, expected one of {}",
+                    kind,
+                    WORKLOAD_KINDS.join(", ")
+                ),
+            )],
+            _ => vec![],
+        }
+    }
+}
+
+impl ValidateRequest for DeleteCollection {
+    fn validate(&self) -> Vec<FieldError> {
+        if self.ids.is_empty() && self.names.is_empty() && self.selector.is_none() {
+            return vec![FieldError::body("No ids, names or selector given")];
+        }
+        match self.selector.as_deref().map(parse_selector) {
+            Some(Err(e)) => vec![FieldError::new("selector", e)],
+            _ => vec![],
+        }
+    }
+}
+
+/// Labels a workload must all have, from a `key=value,key=value` selector
+pub fn parse_selector(selector: &str) -> Result<Vec<(String, String)>, String> {
+    selector
+        .split(',')
+        .map(|requirement| match requirement.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                Ok((key.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(format!(
+                "Invalid selector requirement \"{}\" in {}, expected key=value pairs separated by commas",
+                requirement.trim(),
+                selector
+            )),
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteStatus {
+    /// Would be deleted, only given by dry runs
+    Matched,
+    Deleted,
+    NotFound,
+    Failed,
+    /// Not deleted as an atomic deletion was aborted
+    Skipped,
+    /// Not deleted as the workload is protected
+    Protected,
+    /// Not deleted as the workload still has instances, without `?force=true`
+    HasInstances,
+}
+
+/// Outcome of the deletion of a single workload
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DeleteResult {
+    /// Identifier of the workload, empty when a name was not found
+    pub id: String,
+    /// Name of the workload, empty when an id was not found
+    pub name: String,
+    pub status: DeleteStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
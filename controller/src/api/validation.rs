@@ -0,0 +1,44 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// Error on a single field of a request body
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    /// Path of the field, e.g. `overrides.env`, none when the whole body is at fault
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> FieldError {
+        FieldError {
+            field: Some(field.to_string()),
+            message: message.into(),
+        }
+    }
+
+    pub fn body(message: impl Into<String>) -> FieldError {
+        FieldError {
+            field: None,
+            message: message.into(),
+        }
+    }
+}
+
+/// Checks run on a request body once it is deserialized, such as ranges or
+/// mutually exclusive fields
+pub trait ValidateRequest {
+    fn validate(&self) -> Vec<FieldError> {
+        vec![]
+    }
+
+    /// Checks run on the JSON body before it is deserialized, for the errors
+    /// serde would not attach to a field
+    fn validate_value(_value: &Value) -> Vec<FieldError>
+    where
+        Self: Sized,
+    {
+        vec![]
+    }
+}
@@ -1,18 +1,21 @@
 use crate::api::{ApiChannel, Crud, RikError};
-use crate::core::instance::Instance;
+use crate::core::instance::{Instance, SCHEDULING_FAILED_REASON};
 use crate::core::instance_repository::InstanceRepositoryImpl;
 use crate::core::instance_service::InstanceServiceImpl;
 use crate::core::worker_repository::WorkerRepositoryImpl;
 use crate::core::worker_service::WorkerServiceImpl;
+use crate::core::workload_queue::{WorkloadIntent, WorkloadQueue};
 use crate::core::{InstanceService, Listener, WorkerService};
 use crate::database::RikDataBase;
 use definition::workload::WorkloadDefinition;
+use definition::InstanceStatus;
 
 use proto::common::{InstanceMetric, WorkerMetric};
 use std::net::SocketAddr;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 use tracing::{error, event, Level};
 
 pub enum CoreInternalEvent {
@@ -25,8 +28,19 @@ pub enum CoreInternalEvent {
     Legacy(ApiChannel),
     CreateInstance(Instance, WorkloadDefinition),
     DeleteInstance(Instance, WorkloadDefinition),
+    RestartInstance(Instance, WorkloadDefinition),
+    RecycleInstances,
+    EvaluateMaintenance,
+    /// Apply the intents queued for the workloads
+    RunWorkloadQueue,
 }
 
+/// Interval between two checks of the instances lifetime, and of the
+/// replacements which did not run in time
+const RECYCLE_INTERVAL: Duration = Duration::from_secs(30);
+/// Interval between two evaluations of the maintenance windows
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(30);
+
 /// Core is meant to be a mediator between controller components
 /// It is responsible to forward properly actions and events to the right component
 /// It is also responsible to handle legacy events
@@ -36,6 +50,8 @@ pub enum CoreInternalEvent {
 pub struct Core {
     instance_service: InstanceServiceImpl,
     worker_service: WorkerServiceImpl,
+    /// Changes to the instances of the workloads, applied one workload at a time
+    workload_queue: WorkloadQueue,
 
     internal_receiver: Receiver<CoreInternalEvent>,
     internal_sender: Sender<CoreInternalEvent>,
@@ -53,6 +69,7 @@ impl Core {
         Ok(Core {
             instance_service: instance_svc,
             worker_service: worker_svc,
+            workload_queue: WorkloadQueue::default(),
             internal_receiver,
             internal_sender,
         })
@@ -77,10 +94,14 @@ impl Core {
         skip(self, notification),
         fields(
             workload_id = %notification.workload_id.as_ref().unwrap_or(&String::from("None")), 
-            instance_id = %notification.instance_id.as_ref().unwrap_or(&String::from("None"))
+            instance_id = %notification.instance_id.as_ref().unwrap_or(&String::from("None")),
+            correlation_id = %notification.correlation_id
         )
     )]
     pub async fn handle_legacy_notification(&mut self, notification: ApiChannel) {
+        if let Crud::Ping = notification.action {
+            return;
+        }
         if notification.workload_definition.is_none() {
             error!("Could not proceed legacy notification, no workload definition found");
             return;
@@ -100,18 +121,129 @@ impl Core {
                     .send(CoreInternalEvent::DeleteInstance(instance, definition))
                     .unwrap();
             }
+            Crud::Restart => {
+                let instance: Instance = notification.into();
+                self.internal_sender
+                    .send(CoreInternalEvent::RestartInstance(instance, definition))
+                    .unwrap();
+            }
+            // The instances of the workload are rolled out to its new definition
+            Crud::Update => {
+                let Some(workload_id) = notification.workload_id else {
+                    error!("Could not update workload, no workload id found");
+                    return;
+                };
+                let intent = WorkloadIntent::Update {
+                    definition: Box::new(definition),
+                };
+                self.submit_intents(vec![(workload_id, intent)]);
+            }
+            // The instances of the workload are brought to its new replica count
+            Crud::Scale => {
+                let Some(workload_id) = notification.workload_id else {
+                    error!("Could not scale workload, no workload id found");
+                    return;
+                };
+                let intent = WorkloadIntent::Scale {
+                    replicas: definition.replicas.unwrap_or_default(),
+                };
+                self.submit_intents(vec![(workload_id, intent)]);
+            }
+            Crud::Ping => {}
         };
     }
 
+    /// Periodically ask for the instances exceeding their lifetime to be recycled
+    fn run_recycle_timer(sender: Sender<CoreInternalEvent>) {
+        thread::spawn(move || loop {
+            thread::sleep(RECYCLE_INTERVAL);
+            if sender.send(CoreInternalEvent::RecycleInstances).is_err() {
+                break;
+            }
+        });
+    }
+
+    /// Periodically ask for the workers to be moved in and out of their maintenance windows
+    fn run_maintenance_timer(sender: Sender<CoreInternalEvent>) {
+        thread::spawn(move || loop {
+            thread::sleep(MAINTENANCE_INTERVAL);
+            if sender.send(CoreInternalEvent::EvaluateMaintenance).is_err() {
+                break;
+            }
+        });
+    }
+
+    /// Send the cordon of every worker to the scheduler, which forgets it when
+    /// restarted, then drain the workers entering a maintenance window
+    async fn apply_maintenance(&mut self) -> Result<(), RikError> {
+        let plan = self.worker_service.evaluate_maintenance()?;
+        for (worker_id, cordoned) in plan.cordons {
+            self.instance_service
+                .cordon_worker(&worker_id, cordoned)
+                .await?;
+        }
+        for worker_id in plan.drains {
+            let intents = self.instance_service.drain_intents(&worker_id)?;
+            self.submit_intents(intents);
+        }
+        Ok(())
+    }
+
+    /// Queue intents, they are applied once the events received meanwhile are
+    /// handled so the intents on a same workload get merged
+    fn submit_intents(&mut self, intents: Vec<(String, WorkloadIntent)>) {
+        if intents.is_empty() {
+            return;
+        }
+        for (workload_id, intent) in intents {
+            self.workload_queue.submit(&workload_id, intent);
+        }
+        self.internal_sender
+            .send(CoreInternalEvent::RunWorkloadQueue)
+            .unwrap();
+    }
+
+    async fn run_workload_queue(&mut self) {
+        while let Some((workload_id, change)) = self.workload_queue.take_next() {
+            if let Err(e) = self
+                .instance_service
+                .apply_workload_change(&workload_id, change)
+                .await
+            {
+                error!(
+                    "Failed to change instances of workload {}: {}",
+                    workload_id, e
+                );
+                self.instance_service.record_event(
+                    &workload_id,
+                    SCHEDULING_FAILED_REASON,
+                    &format!("Could not change the instances: {}", e),
+                );
+            }
+            self.workload_queue.finish(&workload_id);
+        }
+    }
+
     pub async fn listen_notification(mut self, receiver: Receiver<ApiChannel>) {
         self.instance_service.run_listen_thread();
         Core::run_legacy_listener(receiver, self.get_sender());
+        Core::run_recycle_timer(self.get_sender());
+        Core::run_maintenance_timer(self.get_sender());
         loop {
             let message = self.internal_receiver.recv().unwrap();
             match message {
-                CoreInternalEvent::InstanceStatusUpdate(instance_metric) => self
-                    .instance_service
-                    .handle_instance_status_update(instance_metric),
+                CoreInternalEvent::InstanceStatusUpdate(instance_metric) => {
+                    let running =
+                        InstanceStatus::from(instance_metric.status) == InstanceStatus::Running;
+                    self.instance_service
+                        .handle_instance_status_update(instance_metric);
+                    // The instances it replaces can go, now that it runs
+                    if running {
+                        if let Err(e) = self.instance_service.retire_replaced_instances().await {
+                            error!("Failed to retire replaced instances: {}", e);
+                        }
+                    }
+                }
                 CoreInternalEvent::WorkerStatusUpdate {
                     identifier,
                     address,
@@ -131,18 +263,67 @@ impl Core {
                 CoreInternalEvent::Legacy(notification) => {
                     self.handle_legacy_notification(notification).await
                 }
+                // The failures of the scheduler are kept as events of the instances
                 CoreInternalEvent::CreateInstance(instance, definition) => {
-                    self.instance_service
+                    let instance_id = instance.id.clone();
+                    if let Err(e) = self
+                        .instance_service
                         .create_instance(instance, definition)
                         .await
-                        .unwrap();
+                    {
+                        error!("Failed to create instance {}: {}", instance_id, e);
+                        self.instance_service.record_event(
+                            &instance_id,
+                            SCHEDULING_FAILED_REASON,
+                            &format!("Could not be created: {}", e),
+                        );
+                    }
                 }
                 CoreInternalEvent::DeleteInstance(instance, definition) => {
-                    self.instance_service
+                    let instance_id = instance.id.clone();
+                    if let Err(e) = self
+                        .instance_service
                         .delete_instance(instance, definition)
                         .await
-                        .unwrap();
+                    {
+                        error!("Failed to delete instance {}: {}", instance_id, e);
+                        self.instance_service.record_event(
+                            &instance_id,
+                            SCHEDULING_FAILED_REASON,
+                            &format!("Could not be deleted: {}", e),
+                        );
+                    }
+                }
+                CoreInternalEvent::RestartInstance(instance, definition) => {
+                    let instance_id = instance.id.clone();
+                    if let Err(e) = self
+                        .instance_service
+                        .restart_instance(instance, definition)
+                        .await
+                    {
+                        error!("Failed to restart instance {}: {}", instance_id, e);
+                        self.instance_service.record_event(
+                            &instance_id,
+                            SCHEDULING_FAILED_REASON,
+                            &format!("Could not be restarted: {}", e),
+                        );
+                    }
+                }
+                CoreInternalEvent::RecycleInstances => {
+                    if let Err(e) = self.instance_service.retire_replaced_instances().await {
+                        error!("Failed to retire replaced instances: {}", e);
+                    }
+                    match self.instance_service.recycle_intents() {
+                        Ok(intents) => self.submit_intents(intents),
+                        Err(e) => error!("Failed to recycle instances: {}", e),
+                    }
+                }
+                CoreInternalEvent::EvaluateMaintenance => {
+                    if let Err(e) = self.apply_maintenance().await {
+                        error!("Failed to apply maintenance windows: {}", e);
+                    }
                 }
+                CoreInternalEvent::RunWorkloadQueue => self.run_workload_queue().await,
             }
         }
     }
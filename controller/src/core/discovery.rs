@@ -0,0 +1,185 @@
+use crate::api::types::element::tenant_segment;
+use crate::core::instance::Instance;
+use crate::core::worker_repository::worker_address;
+use crate::database::RikRepository;
+use definition::workload::{EnvConfig, WorkloadDefinition, WorkloadKind, WORKLOAD_KINDS};
+use definition::InstanceStatus;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::net::SocketAddr;
+use tracing::{event, Level};
+
+/// Address at which a running instance of a workload can be reached
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub instance_id: String,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+/// Port a workload is reached on, functions are exposed on their node port
+fn workload_port(workload: &WorkloadDefinition) -> Option<u16> {
+    match workload.kind {
+        WorkloadKind::Function => workload
+            .spec
+            .function
+            .as_ref()
+            .and_then(|function| function.exposure.as_ref())
+            .map(|exposure| exposure.port),
+        WorkloadKind::Pod => workload
+            .spec
+            .containers
+            .iter()
+            .find_map(|container| container.ports.as_ref())
+            .map(|ports| ports.port),
+    }
+}
+
+/// Endpoints of the running instances of a workload of a tenant, sorted by
+/// instance.
+///
+/// Returns `None` when the tenant has no workload of that name in the namespace.
+pub fn find_endpoints(
+    connection: &Connection,
+    tenant_id: Option<&str>,
+    namespace: &str,
+    workload_name: &str,
+) -> Option<Vec<Endpoint>> {
+    let workload = WORKLOAD_KINDS.iter().find_map(|kind| {
+        let name = format!(
+            "/workload/{}{}/{}/{}",
+            tenant_segment(tenant_id),
+            kind,
+            namespace,
+            workload_name
+        );
+        RikRepository::find_all_by_name(connection, &name)
+            .ok()?
+            .into_iter()
+            .next()
+    })?;
+    let definition: WorkloadDefinition = serde_json::from_value(workload.value).ok()?;
+    let port = workload_port(&definition);
+
+    let mut endpoints: Vec<Endpoint> = RikRepository::find_all(connection, "/instance")
+        .ok()?
+        .into_iter()
+        .filter_map(|element| serde_json::from_value::<Instance>(element.value).ok())
+        .filter(|instance| {
+            instance.workload_id == workload.id && instance.status == InstanceStatus::Running
+        })
+        .filter_map(|instance| {
+            let worker = RikRepository::check_duplicate_name(
+                connection,
+                &format!("/worker/any/{}", instance.worker_id.as_ref()?),
+            )
+            .ok()?;
+            let address: SocketAddr = worker_address(worker.value)?.parse().ok()?;
+            Some(Endpoint {
+                instance_id: instance.id,
+                host: address.ip().to_string(),
+                port,
+            })
+        })
+        .collect();
+    endpoints.sort_by(|a, b| a.instance_id.cmp(&b.instance_id));
+    Some(endpoints)
+}
+
+/// Prefix of the environment variables describing a discovered workload
+fn env_prefix(workload_name: &str) -> String {
+    workload_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Environment variables giving the address of the first endpoint of a workload
+fn discovery_env(workload_name: &str, endpoints: &[Endpoint]) -> Vec<EnvConfig> {
+    let prefix = env_prefix(workload_name);
+    let endpoint = match endpoints.first() {
+        Some(endpoint) => endpoint,
+        None => return Vec::new(),
+    };
+
+    let mut env = vec![EnvConfig {
+        name: format!("{}_HOST", prefix),
+        value: endpoint.host.clone(),
+    }];
+    if let Some(port) = endpoint.port {
+        env.push(EnvConfig {
+            name: format!("{}_PORT", prefix),
+            value: port.to_string(),
+        });
+    }
+    env
+}
+
+/// Add the address of the workloads discovered by the containers to their environment,
+/// only the workloads of the same tenant are discovered.
+///
+/// Addresses are resolved once, when the instance is scheduled, and are not updated
+/// when the discovered instances move.
+pub fn inject_discovered_env(
+    connection: &Connection,
+    namespace: &str,
+    workload: &mut WorkloadDefinition,
+) {
+    let tenant_id = workload.tenant_id.clone();
+    for container in workload.spec.containers.iter_mut() {
+        for workload_name in &container.discover {
+            let endpoints =
+                find_endpoints(connection, tenant_id.as_deref(), namespace, workload_name)
+                    .unwrap_or_default();
+            let env = discovery_env(workload_name, &endpoints);
+            if env.is_empty() {
+                event!(
+                    Level::WARN,
+                    "Container {} discovers workload {} which has no running instance",
+                    container.name,
+                    workload_name
+                );
+                continue;
+            }
+            container.env.get_or_insert_with(Vec::new).extend(env);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discovery_env() {
+        let endpoints = vec![Endpoint {
+            instance_id: "big-bird-1234".to_string(),
+            host: "10.0.0.2".to_string(),
+            port: Some(8080),
+        }];
+        assert_eq!(
+            discovery_env("other-workload", &endpoints),
+            vec![
+                EnvConfig {
+                    name: "OTHER_WORKLOAD_HOST".to_string(),
+                    value: "10.0.0.2".to_string(),
+                },
+                EnvConfig {
+                    name: "OTHER_WORKLOAD_PORT".to_string(),
+                    value: "8080".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_discovery_env_without_endpoint() {
+        assert!(discovery_env("other-workload", &[]).is_empty());
+    }
+}
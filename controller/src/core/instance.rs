@@ -1,15 +1,37 @@
+use crate::api::types::element::tenant_segment;
 use crate::api::ApiChannel;
-use definition::workload::{Spec, WorkloadKind};
-use definition::InstanceStatus;
+use definition::workload::{InstanceOverrides, Spec, WorkloadKind};
+use definition::{set_condition, Condition, ConditionStatus, ConditionType, InstanceStatus};
 use names::{Generator, Name};
+use proto::common::InstanceCondition;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Reason given to instances replaced because of their age, to tell them apart from failures
+pub const RECYCLED_REASON: &str = "Recycled";
+pub const DRAINED_REASON: &str = "Drained";
+pub const ROLLED_OUT_REASON: &str = "RolledOut";
+pub const SCALED_DOWN_REASON: &str = "ScaledDown";
+/// Reason given to instances which booted from an image other than the declared one
+pub const IMAGE_HASH_MISMATCH_REASON: &str = "ImageHashMismatch";
+/// Reason given by the scheduler to instances moved to a less loaded worker
+pub const REBALANCED_REASON: &str = "Rebalanced";
+/// Reason of the event of an instance replaced by another one
+pub const RESCHEDULED_REASON: &str = "Rescheduled";
+/// Reason of the event of an instance brought down then up again on its worker
+pub const RESTARTED_REASON: &str = "Restarted";
+/// Reason of the event of an instance the scheduler could not be asked to place
+pub const SCHEDULING_FAILED_REASON: &str = "SchedulingFailed";
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Instance {
     /// Unique identifier of the workload
     pub workload_id: String,
-    /// Namespace for the current instance, static to default for now
+    /// Namespace for the current instance
     pub namespace: String,
+    /// Tenant owning the instance, the one of its workload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
     /// Name composed with two words separated by a dash and
     /// finish with 4 digits
     pub id: String,
@@ -19,6 +41,83 @@ pub struct Instance {
     pub status: InstanceStatus,
 
     pub spec: Spec,
+    /// Labels of the workload when the instance was created, so instances
+    /// are selected as their workload is
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Overrides merged in the spec, which then drifts from the workload definition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<InstanceOverrides>,
+    /// Detailed state of the instance, `status` is kept as a summary of it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<Condition>,
+    /// RFC 3339 date at which the controller created the instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    /// Worker the instance is placed on, once scheduled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_id: Option<String>,
+    /// Identifier of the last operation on the instance, found in the logs of
+    /// the controller, the scheduler and the riklet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// Image the instance booted from, reported by its worker once running
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<ImageProvenance>,
+    /// Last status the instance reported about itself, none when it never did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_status: Option<AppStatus>,
+    /// Instance started to replace this one, which is removed once it runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replaced_by: Option<String>,
+}
+
+/// Artifact an instance booted from, a cached image keeps the date of its
+/// original download
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ImageProvenance {
+    /// URL the image was downloaded from, after redirects
+    pub resolved_url: String,
+    /// SHA-256 of the content, verified by the worker
+    pub sha256: String,
+    /// RFC 3339 date of the download
+    pub downloaded_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl From<proto::common::ImageProvenance> for ImageProvenance {
+    fn from(value: proto::common::ImageProvenance) -> Self {
+        Self {
+            resolved_url: value.resolved_url,
+            sha256: value.sha256,
+            downloaded_at: value.downloaded_at,
+            etag: value.etag,
+            last_modified: value.last_modified,
+        }
+    }
+}
+
+/// Status reported by the code running in an instance, folded by its worker
+/// in the `Ready` condition
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AppStatus {
+    pub status: String,
+    pub ready: bool,
+    /// RFC 3339 date at which the worker received the report
+    pub reported_at: String,
+}
+
+impl From<proto::common::AppStatus> for AppStatus {
+    fn from(value: proto::common::AppStatus) -> Self {
+        Self {
+            status: value.status,
+            ready: value.ready,
+            reported_at: value.reported_at,
+        }
+    }
 }
 
 impl From<ApiChannel> for Instance {
@@ -26,11 +125,21 @@ impl From<ApiChannel> for Instance {
         let workload_definition = value.workload_definition.unwrap();
         Self {
             workload_id: value.workload_id.unwrap(),
-            namespace: String::from("default"),
+            namespace: value.namespace.unwrap_or_else(|| String::from("default")),
+            tenant_id: value.tenant_id,
             kind: workload_definition.kind,
             id: value.instance_id.unwrap(),
             status: InstanceStatus::Pending,
             spec: workload_definition.spec,
+            labels: workload_definition.labels,
+            overrides: value.overrides,
+            conditions: Self::initial_conditions(),
+            created_at: Some(chrono::Utc::now().to_rfc3339()),
+            worker_id: None,
+            correlation_id: Some(value.correlation_id),
+            provenance: None,
+            app_status: None,
+            replaced_by: None,
         }
     }
 }
@@ -40,19 +149,233 @@ impl Instance {
         Self {
             workload_id,
             namespace: String::from("default"),
+            tenant_id: None,
             kind,
             id: id.unwrap_or_else(Self::generate_name),
             status: InstanceStatus::Pending,
             spec,
+            labels: BTreeMap::new(),
+            overrides: None,
+            conditions: Self::initial_conditions(),
+            created_at: Some(chrono::Utc::now().to_rfc3339()),
+            worker_id: None,
+            correlation_id: None,
+            provenance: None,
+            app_status: None,
+            replaced_by: None,
+        }
+    }
+
+    /// Create a new instance meant to replace this one, as part of the given operation
+    pub fn replacement(&self, correlation_id: &str) -> Self {
+        Self {
+            workload_id: self.workload_id.clone(),
+            namespace: self.namespace.clone(),
+            tenant_id: self.tenant_id.clone(),
+            kind: self.kind.clone(),
+            id: Self::generate_name(),
+            status: InstanceStatus::Pending,
+            spec: self.spec.clone(),
+            labels: self.labels.clone(),
+            overrides: self.overrides.clone(),
+            conditions: Self::initial_conditions(),
+            created_at: Some(chrono::Utc::now().to_rfc3339()),
+            worker_id: None,
+            correlation_id: Some(correlation_id.to_string()),
+            provenance: None,
+            app_status: None,
+            replaced_by: None,
+        }
+    }
+
+    /// Age of the instance, unknown for instances created before it was recorded
+    pub fn age(&self, now: chrono::DateTime<chrono::Utc>) -> Option<chrono::Duration> {
+        let created_at = chrono::DateTime::parse_from_rfc3339(self.created_at.as_ref()?).ok()?;
+        Some(now.signed_duration_since(created_at))
+    }
+
+    /// Mark the instance as being replaced because it is too old
+    pub fn mark_recycled(&mut self, max_lifetime: u64) {
+        set_condition(
+            &mut self.conditions,
+            ConditionType::Terminating,
+            ConditionStatus::True,
+            RECYCLED_REASON,
+            &format!("Maximum lifetime of {} seconds exceeded", max_lifetime),
+            &chrono::Utc::now().to_rfc3339(),
+        );
+    }
+
+    /// Mark the instance as being replaced because its worker is drained
+    pub fn mark_drained(&mut self, worker_id: &str) {
+        set_condition(
+            &mut self.conditions,
+            ConditionType::Terminating,
+            ConditionStatus::True,
+            DRAINED_REASON,
+            &format!("Worker {} is drained for maintenance", worker_id),
+            &chrono::Utc::now().to_rfc3339(),
+        );
+    }
+
+    /// Mark the instance as being replaced by one following the new workload definition
+    pub fn mark_rolled_out(&mut self) {
+        set_condition(
+            &mut self.conditions,
+            ConditionType::Terminating,
+            ConditionStatus::True,
+            ROLLED_OUT_REASON,
+            "The workload definition changed",
+            &chrono::Utc::now().to_rfc3339(),
+        );
+    }
+
+    /// Mark the instance as being removed to run fewer replicas
+    pub fn mark_scaled_down(&mut self, replicas: u16) {
+        set_condition(
+            &mut self.conditions,
+            ConditionType::Terminating,
+            ConditionStatus::True,
+            SCALED_DOWN_REASON,
+            &format!("The workload is scaled down to {} replicas", replicas),
+            &chrono::Utc::now().to_rfc3339(),
+        );
+    }
+
+    /// Mark the instance as failed before being sent to the scheduler
+    pub fn mark_unschedulable(&mut self, reason: &str, message: &str) {
+        self.status = InstanceStatus::Failed;
+        set_condition(
+            &mut self.conditions,
+            ConditionType::Scheduled,
+            ConditionStatus::False,
+            reason,
+            message,
+            &chrono::Utc::now().to_rfc3339(),
+        );
+    }
+
+    /// Why the image the instance booted from is not the one its function
+    /// declares, a worker which did not report the image cannot have checked it
+    pub fn image_hash_mismatch(&self) -> Option<String> {
+        let declared = self.spec.function.as_ref()?.execution.sha256.as_ref()?;
+        match &self.provenance {
+            Some(provenance) if provenance.sha256.eq_ignore_ascii_case(declared) => None,
+            Some(provenance) => Some(format!(
+                "Booted from an image with the SHA-256 {}, {} is declared",
+                provenance.sha256, declared
+            )),
+            None => Some(format!(
+                "The worker did not report the image it booted from, {} is declared",
+                declared
+            )),
         }
     }
 
+    pub fn is_recycled(&self) -> bool {
+        self.conditions.iter().any(|condition| {
+            condition.condition_type == ConditionType::Terminating
+                && condition.status == ConditionStatus::True
+                && condition.reason == RECYCLED_REASON
+        })
+    }
+
+    /// Whether the instance runs, or is about to, and is not being removed
+    pub fn is_live(&self) -> bool {
+        let terminating = self.conditions.iter().any(|condition| {
+            condition.condition_type == ConditionType::Terminating
+                && condition.status == ConditionStatus::True
+        });
+        !terminating
+            && !matches!(
+                self.status,
+                InstanceStatus::Failed | InstanceStatus::Destroying | InstanceStatus::Terminated
+            )
+    }
+
     pub fn generate_name() -> String {
         let mut random_name_generator = Generator::with_naming(Name::Numbered);
         random_name_generator.next().unwrap()
     }
 
+    fn initial_conditions() -> Vec<Condition> {
+        let mut conditions = Vec::new();
+        set_condition(
+            &mut conditions,
+            ConditionType::Scheduled,
+            ConditionStatus::Unknown,
+            "Pending",
+            "Waiting for a worker",
+            &chrono::Utc::now().to_rfc3339(),
+        );
+        conditions
+    }
+
+    /// Update the conditions from a new status, then apply the conditions
+    /// reported along with it which are more precise
+    pub fn update_conditions(&mut self, status: &InstanceStatus, reported: &[InstanceCondition]) {
+        let now = chrono::Utc::now().to_rfc3339();
+        let derived: &[(ConditionType, ConditionStatus)] = match status {
+            InstanceStatus::Pending => &[(ConditionType::Scheduled, ConditionStatus::Unknown)],
+            InstanceStatus::Creating => &[(ConditionType::Scheduled, ConditionStatus::True)],
+            InstanceStatus::Running => &[
+                (ConditionType::Scheduled, ConditionStatus::True),
+                (ConditionType::ImagePulled, ConditionStatus::True),
+                (ConditionType::Booted, ConditionStatus::True),
+                (ConditionType::Ready, ConditionStatus::True),
+            ],
+            InstanceStatus::Failed | InstanceStatus::Restarting => {
+                &[(ConditionType::Ready, ConditionStatus::False)]
+            }
+            InstanceStatus::Destroying | InstanceStatus::Terminated => {
+                &[(ConditionType::Terminating, ConditionStatus::True)]
+            }
+        };
+        // Keep the recycling reason until the instance is gone
+        let recycled = self.is_recycled();
+        let keep = |condition_type: ConditionType| {
+            !(recycled && condition_type == ConditionType::Terminating)
+        };
+
+        for (condition_type, condition_status) in derived.iter().filter(|(t, _)| keep(*t)) {
+            set_condition(
+                &mut self.conditions,
+                *condition_type,
+                *condition_status,
+                &status.to_string(),
+                "",
+                &now,
+            );
+        }
+
+        for condition in reported {
+            if let (Some(condition_type), Some(condition_status)) = (
+                proto::common::ConditionType::from_i32(condition.r#type),
+                proto::common::ConditionStatus::from_i32(condition.status),
+            ) {
+                let condition_type = condition_type.into();
+                if !keep(condition_type) {
+                    continue;
+                }
+                set_condition(
+                    &mut self.conditions,
+                    condition_type,
+                    condition_status.into(),
+                    &condition.reason,
+                    &condition.message,
+                    &now,
+                );
+            }
+        }
+    }
+
     pub fn get_full_name(&self) -> String {
-        format!("/instance/{}/{}/{}", self.kind, self.namespace, self.id)
+        format!(
+            "/instance/{}{}/{}/{}",
+            tenant_segment(self.tenant_id.as_deref()),
+            self.kind,
+            self.namespace,
+            self.id
+        )
     }
 }
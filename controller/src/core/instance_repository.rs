@@ -1,7 +1,12 @@
 use crate::api::RikError;
 use crate::core::instance::Instance;
+use crate::core::volume::BindingFailure;
 use crate::core::InstanceRepository;
+use crate::core::{discovery, volume};
+use crate::database::events::EventRepository;
+use crate::database::workload_cache::find_workload;
 use crate::database::{RikDataBase, RikRepository};
+use definition::workload::WorkloadDefinition;
 use rusqlite::Connection;
 use std::sync::Arc;
 
@@ -27,17 +32,70 @@ impl InstanceRepositoryImpl {
 impl InstanceRepository for InstanceRepositoryImpl {
     fn fetch_instance(&self, instance_id: String) -> Result<Instance, RikError> {
         let conn = self.get_connection()?;
-        let element = RikRepository::check_duplicate_name(
-            &conn,
-            &format!("/instance/%/default/{}", &instance_id),
-        )
-        .map_err(|_| RikError::InvalidName(instance_id))?;
+        let element =
+            RikRepository::check_duplicate_name(&conn, &format!("/instance/%/%/{}", &instance_id))
+                .map_err(|_| RikError::InvalidName(instance_id))?;
 
         serde_json::from_value::<Instance>(element.value).map_err(|e| {
             RikError::InternalCommunicationError(format!("Could not parse instance: {}", e))
         })
     }
 
+    fn fetch_instances(&self) -> Result<Vec<Instance>, RikError> {
+        let conn = self.get_connection()?;
+        let elements = RikRepository::find_all(&conn, "/instance").map_err(|e| {
+            RikError::InternalCommunicationError(format!("Could not fetch instances: {}", e))
+        })?;
+
+        elements
+            .into_iter()
+            .map(|element| {
+                serde_json::from_value::<Instance>(element.value).map_err(|e| {
+                    RikError::InternalCommunicationError(format!("Could not parse instance: {}", e))
+                })
+            })
+            .collect()
+    }
+
+    fn fetch_workload(&self, workload_id: String) -> Result<WorkloadDefinition, RikError> {
+        let conn = self.get_connection()?;
+        find_workload(&conn, &workload_id)
+    }
+
+    fn inject_discovered_env(
+        &self,
+        namespace: &str,
+        workload_def: &mut WorkloadDefinition,
+    ) -> Result<(), RikError> {
+        let conn = self.get_connection()?;
+        discovery::inject_discovered_env(&conn, namespace, workload_def);
+        Ok(())
+    }
+
+    fn bind_volumes(
+        &self,
+        instance: &Instance,
+        workload_def: &mut WorkloadDefinition,
+    ) -> Result<Result<(), BindingFailure>, RikError> {
+        let conn = self.get_connection()?;
+        Ok(volume::bind_volumes(
+            &conn,
+            &instance.namespace,
+            &instance.id,
+            workload_def,
+        )?)
+    }
+
+    fn release_volumes(&self, instance_id: &str) -> Result<(), RikError> {
+        let conn = self.get_connection()?;
+        volume::release_volumes(&conn, instance_id).map_err(RikError::InternalCommunicationError)
+    }
+
+    fn pin_volumes(&self, instance_id: &str, node: &str) -> Result<(), RikError> {
+        let conn = self.get_connection()?;
+        volume::pin_volumes(&conn, instance_id, node).map_err(RikError::InternalCommunicationError)
+    }
+
     fn register_instance(&self, instance: Instance) -> Result<(), RikError> {
         let connection = self.get_connection()?;
         RikRepository::upsert(
@@ -59,6 +117,15 @@ impl InstanceRepository for InstanceRepositoryImpl {
             RikError::InternalCommunicationError(format!("Could not delete instance: {}", e))
         })
     }
+
+    fn record_event(&self, element_id: &str, reason: &str, message: &str) -> Result<(), RikError> {
+        let connection = self.get_connection()?;
+        EventRepository::insert(&connection, element_id, reason, message)
+            .map_err(|e| {
+                RikError::InternalCommunicationError(format!("Could not record event: {}", e))
+            })
+            .map(|_| ())
+    }
 }
 
 #[cfg(test)]
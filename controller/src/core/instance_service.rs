@@ -1,17 +1,23 @@
-use crate::api::{Crud, RikError};
+use crate::api::limits::limit_from_env;
+use crate::api::{correlation, Crud, RikError};
 use crate::core::core::CoreInternalEvent;
-use crate::core::instance::Instance;
+use crate::core::instance::{
+    Instance, IMAGE_HASH_MISMATCH_REASON, REBALANCED_REASON, RESCHEDULED_REASON,
+};
 use crate::core::instance_repository::InstanceRepositoryImpl;
+use crate::core::workload_queue::{plan_instances, PendingChange, Replacement, WorkloadIntent};
 use crate::core::{with_backoff, InstanceRepository, InstanceService, Listener};
+use crate::database::events::{CREATED_REASON, DELETED_REASON};
 use async_trait::async_trait;
 use definition::workload::{WorkloadDefinition, WorkloadKind};
 use definition::InstanceStatus;
 use dotenv::dotenv;
 use proto::common::worker_status::Status;
-use proto::common::InstanceMetric;
+use proto::common::{InstanceCondition, InstanceMetric};
 use proto::controller::controller_client::ControllerClient;
-use proto::controller::WorkloadScheduling;
+use proto::controller::{WorkerCordon, WorkloadScheduling};
 use rand::Rng;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::ops::Range;
 use std::str::FromStr;
@@ -20,6 +26,9 @@ use tracing::{error, event, info, Level};
 
 const WORKLOAD_PORTS: Range<u16> = 45000..50000;
 const DEFAULT_SCHEDULER_URL: &str = "http://localhost:4996";
+/// Seconds given to a replacement to run when not configured otherwise with
+/// `REPLACEMENT_TIMEOUT_SECONDS`
+const DEFAULT_REPLACEMENT_TIMEOUT_SECONDS: usize = 300;
 
 pub fn mutate_function_port(mut workload: WorkloadDefinition) -> WorkloadDefinition {
     let random_port = rand::thread_rng().gen_range(WORKLOAD_PORTS);
@@ -27,6 +36,79 @@ pub fn mutate_function_port(mut workload: WorkloadDefinition) -> WorkloadDefinit
     workload
 }
 
+/// Pick, for each workload, the oldest instance living longer than allowed.
+///
+/// Workloads with an instance that is not running yet, or already being
+/// recycled, are skipped so instances are replaced one at a time.
+fn select_expired_instances<'a>(
+    instances: &'a [Instance],
+    max_lifetimes: &HashMap<String, u64>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<&'a Instance> {
+    let mut by_workload: HashMap<&String, Vec<&Instance>> = HashMap::new();
+    for instance in instances {
+        by_workload
+            .entry(&instance.workload_id)
+            .or_default()
+            .push(instance);
+    }
+
+    by_workload
+        .into_iter()
+        .filter_map(|(workload_id, instances)| {
+            let max_lifetime = *max_lifetimes.get(workload_id)?;
+            if instances.iter().any(|instance| {
+                instance.status != InstanceStatus::Running || instance.is_recycled()
+            }) {
+                return None;
+            }
+            instances
+                .into_iter()
+                .filter_map(|instance| Some((instance, instance.age(now)?)))
+                .filter(|(_, age)| age.num_seconds() >= max_lifetime as i64)
+                .max_by_key(|(_, age)| *age)
+                .map(|(instance, _)| instance)
+        })
+        .collect()
+}
+
+/// Pick the instances to remove as their replacement runs, is gone, or did
+/// not run within the timeout.
+///
+/// The message tells why an instance is removed without a running replacement.
+fn select_retired_instances(
+    instances: &[Instance],
+    timeout: chrono::Duration,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<(&Instance, Option<String>)> {
+    instances
+        .iter()
+        .filter_map(|instance| {
+            let replacement_id = instance.replaced_by.as_ref()?;
+            let Some(replacement) = instances.iter().find(|other| &other.id == replacement_id)
+            else {
+                return Some((
+                    instance,
+                    Some(format!("Replacement {} is gone", replacement_id)),
+                ));
+            };
+            if replacement.status == InstanceStatus::Running {
+                return Some((instance, None));
+            }
+            // Instances without a creation date are replaced as soon as possible
+            let overdue = replacement.age(now).is_none_or(|age| age >= timeout);
+            overdue.then(|| {
+                let message = format!(
+                    "Replacement {} did not run within {} seconds",
+                    replacement_id,
+                    timeout.num_seconds()
+                );
+                (instance, Some(message))
+            })
+        })
+        .collect()
+}
+
 pub struct InstanceServiceImpl {
     client: ControllerClient<tonic::transport::Channel>,
     sender: Sender<CoreInternalEvent>,
@@ -64,6 +146,8 @@ impl Listener for InstanceServiceImpl {
                             })
                             .unwrap();
                     }
+                    // Sync reports stay between the scheduler and the workers
+                    Status::Sync(_) => {}
                 }
             }
         });
@@ -102,6 +186,7 @@ impl InstanceServiceImpl {
             definition: serde_json::to_string(&workload_def).unwrap(),
             action: action as i32,
             instance_id: instance.id.clone(),
+            correlation_id: instance.correlation_id.clone().unwrap_or_default(),
         };
         let request = tonic::Request::new(scheduling);
         self.client.schedule_instance(request).await?;
@@ -111,19 +196,45 @@ impl InstanceServiceImpl {
 
 #[async_trait]
 impl InstanceService for InstanceServiceImpl {
+    #[tracing::instrument(
+        skip_all,
+        fields(
+            instance_id = %instance.id,
+            correlation_id = %instance.correlation_id.as_deref().unwrap_or("none")
+        )
+    )]
     async fn create_instance(
         &mut self,
         mut instance: Instance,
         mut workload_def: WorkloadDefinition,
     ) -> Result<(), RikError> {
         event!(Level::INFO, "Schedule instance {}", instance.id);
+        // Instances are selected by the labels of the workload they are created from
+        instance.labels = workload_def.labels.clone();
 
         if instance.kind == WorkloadKind::Function {
             workload_def = mutate_function_port(workload_def);
         }
+        self.service
+            .inject_discovered_env(&instance.namespace, &mut workload_def)?;
+        if let Err((reason, message)) = self.service.bind_volumes(&instance, &mut workload_def)? {
+            // The instance is kept to tell why it never started
+            error!("Instance {} cannot be scheduled: {}", instance.id, message);
+            instance.mark_unschedulable(reason, &message);
+            instance.spec = workload_def.spec.clone();
+            let instance_id = instance.id.clone();
+            self.service.register_instance(instance)?;
+            self.record_event(&instance_id, reason, &message);
+            return Ok(());
+        }
 
         instance.spec = workload_def.spec.clone();
         self.service.register_instance(instance.clone())?;
+        self.record_event(
+            &instance.id,
+            CREATED_REASON,
+            &format!("Created for workload {}", instance.workload_id),
+        );
         self.schedule_instance(instance, workload_def, Crud::Create)
             .await
             .map_err(|e| {
@@ -131,12 +242,31 @@ impl InstanceService for InstanceServiceImpl {
             })
     }
 
+    #[tracing::instrument(
+        skip_all,
+        fields(
+            instance_id = %instance.id,
+            correlation_id = %instance.correlation_id.as_deref().unwrap_or("none")
+        )
+    )]
     async fn delete_instance(
         &mut self,
         instance: Instance,
         workload_def: WorkloadDefinition,
     ) -> Result<(), RikError> {
         event!(Level::INFO, "Unschedule instance {}", instance.id);
+        // The deletion is the last operation on the instance
+        if let Ok(mut stored) = self.service.fetch_instance(instance.id.clone()) {
+            if stored.correlation_id != instance.correlation_id {
+                stored.correlation_id = instance.correlation_id.clone();
+                self.service.register_instance(stored)?;
+            }
+        }
+        self.record_event(
+            &instance.id,
+            DELETED_REASON,
+            &format!("Deleted from workload {}", instance.workload_id),
+        );
         self.schedule_instance(instance, workload_def, Crud::Delete)
             .await
             .map_err(|e| {
@@ -144,18 +274,326 @@ impl InstanceService for InstanceServiceImpl {
             })
     }
 
-    fn handle_instance_status_update(&mut self, instance_metric: InstanceMetric) {
-        let new_status = InstanceStatus::from(instance_metric.status);
+    #[tracing::instrument(
+        skip_all,
+        fields(
+            instance_id = %instance.id,
+            correlation_id = %instance.correlation_id.as_deref().unwrap_or("none")
+        )
+    )]
+    async fn restart_instance(
+        &mut self,
+        instance: Instance,
+        workload_def: WorkloadDefinition,
+    ) -> Result<(), RikError> {
+        event!(Level::INFO, "Restart instance {}", instance.id);
+        // The API recorded the restart, only the operation it is part of changes
+        if let Ok(mut stored) = self.service.fetch_instance(instance.id.clone()) {
+            if stored.correlation_id != instance.correlation_id {
+                stored.correlation_id = instance.correlation_id.clone();
+                self.service.register_instance(stored)?;
+            }
+        }
+        self.schedule_instance(instance, workload_def, Crud::Restart)
+            .await
+            .map_err(|e| {
+                RikError::InternalCommunicationError(format!("Could not schedule instance: {}", e))
+            })
+    }
+
+    fn record_event(&self, instance_id: &str, reason: &str, message: &str) {
+        // The change itself is made, a missing event is not worth failing it
+        if let Err(e) = self.service.record_event(instance_id, reason, message) {
+            error!("Failed to record event of instance {}: {}", instance_id, e);
+        }
+    }
+
+    fn recycle_intents(&mut self) -> Result<Vec<(String, WorkloadIntent)>, RikError> {
+        let instances = self.service.fetch_instances()?;
+
+        let mut workloads = HashMap::new();
+        for instance in &instances {
+            if workloads.contains_key(&instance.workload_id) {
+                continue;
+            }
+            if let Ok(workload) = self.service.fetch_workload(instance.workload_id.clone()) {
+                workloads.insert(instance.workload_id.clone(), workload);
+            }
+        }
+        // A volume is bound to a single instance, so the replacement of an instance
+        // using volumes could not start before the instance is gone
+        let max_lifetimes: HashMap<String, u64> = workloads
+            .iter()
+            .filter(|(_, workload)| workload.volumes().is_empty())
+            .filter_map(|(id, workload)| {
+                Some((id.clone(), workload.max_instance_lifetime_seconds?))
+            })
+            .collect();
+
+        Ok(
+            select_expired_instances(&instances, &max_lifetimes, chrono::Utc::now())
+                .into_iter()
+                .map(|instance| {
+                    let intent = WorkloadIntent::Replace {
+                        instance_id: instance.id.clone(),
+                        reason: Replacement::Expired {
+                            max_lifetime: max_lifetimes[&instance.workload_id],
+                        },
+                    };
+                    (instance.workload_id.clone(), intent)
+                })
+                .collect(),
+        )
+    }
+
+    async fn cordon_worker(&mut self, worker_id: &str, cordoned: bool) -> Result<(), RikError> {
+        let request = tonic::Request::new(WorkerCordon {
+            worker_id: worker_id.to_string(),
+            cordoned,
+        });
+        self.client.set_worker_cordon(request).await.map_err(|e| {
+            RikError::InternalCommunicationError(format!("Could not cordon worker: {}", e))
+        })?;
+        Ok(())
+    }
+
+    fn drain_intents(
+        &mut self,
+        worker_id: &str,
+    ) -> Result<Vec<(String, WorkloadIntent)>, RikError> {
+        Ok(self
+            .service
+            .fetch_instances()?
+            .into_iter()
+            .filter(|instance| instance.worker_id.as_deref() == Some(worker_id))
+            .filter(|instance| instance.status == InstanceStatus::Running)
+            .map(|instance| {
+                let intent = WorkloadIntent::Replace {
+                    instance_id: instance.id,
+                    reason: Replacement::Drained {
+                        worker_id: worker_id.to_string(),
+                    },
+                };
+                (instance.workload_id, intent)
+            })
+            .collect())
+    }
+
+    async fn apply_workload_change(
+        &mut self,
+        workload_id: &str,
+        change: PendingChange,
+    ) -> Result<(), RikError> {
+        let instances: Vec<Instance> = self
+            .service
+            .fetch_instances()?
+            .into_iter()
+            .filter(|instance| instance.workload_id == workload_id)
+            .collect();
+        let workload_def = match &change.definition {
+            Some(definition) => definition.clone(),
+            None => self.service.fetch_workload(workload_id.to_string())?,
+        };
+        let plan = plan_instances(&instances, &change);
+        let find = |instance_id: &str| {
+            instances
+                .iter()
+                .find(|instance| instance.id == instance_id)
+                .cloned()
+        };
+        // The merged intents make a single operation
+        let correlation_id = correlation::new_id();
+
+        for (instance_id, reason) in plan.replace {
+            let Some(mut instance) = find(&instance_id) else {
+                continue;
+            };
+            event!(
+                Level::INFO,
+                correlation_id = %correlation_id,
+                "Replacing instance {} of workload {}, {}",
+                instance.id,
+                workload_id,
+                reason
+            );
+
+            // Start the replacement, the old instance is removed once it runs
+            let mut instance_def = workload_def.clone();
+            if let Some(overrides) = &instance.overrides {
+                overrides.apply(&mut instance_def);
+            }
+            let replacement = instance.replacement(&correlation_id);
+            let replacement_id = replacement.id.clone();
+            self.create_instance(replacement, instance_def.clone())
+                .await?;
+            self.record_event(
+                &instance.id,
+                RESCHEDULED_REASON,
+                &format!("Replaced by instance {}, {}", replacement_id, reason),
+            );
+
+            match reason {
+                Replacement::Expired { max_lifetime } => instance.mark_recycled(max_lifetime),
+                Replacement::Drained { worker_id } => instance.mark_drained(&worker_id),
+                Replacement::Rollout => instance.mark_rolled_out(),
+            }
+            instance.correlation_id = Some(correlation_id.clone());
+            // A volume is bound to a single instance, so the replacement cannot
+            // run before the old instance is gone
+            if !workload_def.volumes().is_empty() {
+                self.service.register_instance(instance.clone())?;
+                self.delete_instance(instance, instance_def).await?;
+                continue;
+            }
+            instance.replaced_by = Some(replacement_id);
+            self.service.register_instance(instance)?;
+        }
+
+        for instance_id in plan.delete {
+            let Some(mut instance) = find(&instance_id) else {
+                continue;
+            };
+            event!(
+                Level::INFO,
+                correlation_id = %correlation_id,
+                "Removing instance {} of workload {} to scale it down",
+                instance.id,
+                workload_id
+            );
+            instance.mark_scaled_down(change.replicas.unwrap_or_default());
+            instance.correlation_id = Some(correlation_id.clone());
+            self.service.register_instance(instance.clone())?;
+            self.delete_instance(instance, workload_def.clone()).await?;
+        }
+
+        for _ in 0..plan.create {
+            let mut instance = Instance::new(
+                workload_id.to_string(),
+                workload_def.kind.clone(),
+                None,
+                workload_def.spec.clone(),
+            );
+            if let Some(existing) = instances.first() {
+                instance.namespace = existing.namespace.clone();
+                instance.tenant_id = existing.tenant_id.clone();
+            }
+            instance.correlation_id = Some(correlation_id.clone());
+            self.create_instance(instance, workload_def.clone()).await?;
+        }
+        Ok(())
+    }
+
+    async fn retire_replaced_instances(&mut self) -> Result<(), RikError> {
+        let instances = self.service.fetch_instances()?;
+        let timeout = chrono::Duration::seconds(limit_from_env(
+            "REPLACEMENT_TIMEOUT_SECONDS",
+            DEFAULT_REPLACEMENT_TIMEOUT_SECONDS,
+        ) as i64);
+        let retired: Vec<(Instance, Option<String>)> =
+            select_retired_instances(&instances, timeout, chrono::Utc::now())
+                .into_iter()
+                .map(|(instance, message)| (instance.clone(), message))
+                .collect();
+
+        for (mut instance, message) in retired {
+            let mut instance_def = match self.service.fetch_workload(instance.workload_id.clone()) {
+                Ok(definition) => definition,
+                // The instances of a deleted workload are removed along with it
+                Err(e) => {
+                    error!("Failed to retire instance {}: {}", instance.id, e);
+                    continue;
+                }
+            };
+            if let Some(overrides) = &instance.overrides {
+                overrides.apply(&mut instance_def);
+            }
+            if let Some(message) = message {
+                error!("Removing instance {}: {}", instance.id, message);
+                self.record_event(&instance.id, RESCHEDULED_REASON, &message);
+            }
+            // The instance is removed once, whatever happens to its replacement next
+            instance.replaced_by = None;
+            self.service.register_instance(instance.clone())?;
+            self.delete_instance(instance, instance_def).await?;
+        }
+        Ok(())
+    }
+
+    fn handle_instance_status_update(&mut self, mut instance_metric: InstanceMetric) {
+        let mut new_status = InstanceStatus::from(instance_metric.status);
         let mut instance = self
             .service
             .fetch_instance(instance_metric.instance_id.clone())
             .unwrap();
+        if let Some(provenance) = instance_metric.provenance.take() {
+            instance.provenance = Some(provenance.into());
+        }
+        if let Some(app_status) = instance_metric.app_status.take() {
+            instance.app_status = Some(app_status.into());
+        }
+        // An instance is never recorded as running an image other than the declared one
+        if new_status == InstanceStatus::Running {
+            if let Some(message) = instance.image_hash_mismatch() {
+                error!(
+                    "Instance {} failed the image check: {}",
+                    instance.id, message
+                );
+                new_status = InstanceStatus::Failed;
+                instance_metric.conditions.push(InstanceCondition {
+                    r#type: proto::common::ConditionType::Booted.into(),
+                    status: proto::common::ConditionStatus::False.into(),
+                    reason: String::from(IMAGE_HASH_MISMATCH_REASON),
+                    message,
+                });
+            }
+        }
         info!(
-            "Instance {}, status update, {} -> {}",
-            instance.id, instance.status, &new_status
+            correlation_id = instance.correlation_id.as_deref().unwrap_or("none"),
+            "Instance {}, status update, {} -> {}", instance.id, instance.status, &new_status
         );
 
+        // The status history of the instance
+        if new_status != instance.status {
+            if let Err(e) = self.service.record_event(
+                &instance.id,
+                &new_status.to_string(),
+                &format!("Status changed from {} to {}", instance.status, new_status),
+            ) {
+                error!("Failed to record status of instance {}: {}", instance.id, e);
+            }
+        }
+        // Moves between workers, the message names both of them
+        for condition in instance_metric
+            .conditions
+            .iter()
+            .filter(|condition| condition.reason == REBALANCED_REASON)
+        {
+            if let Err(e) =
+                self.service
+                    .record_event(&instance.id, REBALANCED_REASON, &condition.message)
+            {
+                error!("Failed to record move of instance {}: {}", instance.id, e);
+            }
+        }
+        instance.update_conditions(&new_status, &instance_metric.conditions);
         instance.status = new_status;
+        if let Some(worker_id) = instance_metric.worker_id {
+            if let Err(e) = self.service.pin_volumes(&instance.id, &worker_id) {
+                error!("Failed to pin volumes of instance {}: {}", instance.id, e);
+            }
+            instance.worker_id = Some(worker_id);
+        }
+        if matches!(
+            instance.status,
+            InstanceStatus::Terminated | InstanceStatus::Failed
+        ) {
+            if let Err(e) = self.service.release_volumes(&instance.id) {
+                error!(
+                    "Failed to release volumes of instance {}: {}",
+                    instance.id, e
+                );
+            }
+        }
 
         let repo_update_rs = match instance.status {
             InstanceStatus::Terminated => self.service.delete_instance(instance),
@@ -170,3 +608,115 @@ impl InstanceService for InstanceServiceImpl {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::instance::ImageProvenance;
+    use definition::workload::{Spec, WorkloadKind};
+
+    fn running_instance(workload_id: &str, id: &str, age_seconds: i64) -> Instance {
+        let mut instance = Instance::new(
+            workload_id.to_string(),
+            WorkloadKind::Pod,
+            Some(id.to_string()),
+            Spec {
+                containers: vec![],
+                function: None,
+            },
+        );
+        instance.status = InstanceStatus::Running;
+        instance.created_at =
+            Some((chrono::Utc::now() - chrono::Duration::seconds(age_seconds)).to_rfc3339());
+        instance
+    }
+
+    #[test]
+    fn test_select_oldest_expired_instance() {
+        let instances = vec![
+            running_instance("workload", "young", 10),
+            running_instance("workload", "old", 200),
+            running_instance("workload", "older", 300),
+            running_instance("unlimited", "ancient", 1000),
+        ];
+        let max_lifetimes = HashMap::from([(String::from("workload"), 100)]);
+
+        let expired = select_expired_instances(&instances, &max_lifetimes, chrono::Utc::now());
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, "older");
+    }
+
+    #[test]
+    fn test_skip_workload_being_recycled() {
+        let mut recycled = running_instance("workload", "recycled", 300);
+        recycled.mark_recycled(100);
+        let instances = vec![recycled, running_instance("workload", "old", 200)];
+        let max_lifetimes = HashMap::from([(String::from("workload"), 100)]);
+
+        let expired = select_expired_instances(&instances, &max_lifetimes, chrono::Utc::now());
+        assert!(expired.is_empty());
+    }
+
+    #[test]
+    fn test_retire_once_replacement_runs() {
+        let timeout = chrono::Duration::seconds(300);
+        let mut old = running_instance("workload", "old", 600);
+        old.mark_recycled(100);
+        old.replaced_by = Some(String::from("new"));
+        let mut replacement = running_instance("workload", "new", 10);
+        replacement.status = InstanceStatus::Creating;
+
+        let mut instances = vec![old, replacement];
+        assert!(select_retired_instances(&instances, timeout, chrono::Utc::now()).is_empty());
+
+        instances[1].status = InstanceStatus::Running;
+        let retired = select_retired_instances(&instances, timeout, chrono::Utc::now());
+        assert_eq!(retired.len(), 1);
+        assert_eq!(retired[0].0.id, "old");
+        assert_eq!(retired[0].1, None);
+    }
+
+    #[test]
+    fn test_retire_without_running_replacement() {
+        let timeout = chrono::Duration::seconds(300);
+        let mut old = running_instance("workload", "old", 600);
+        old.replaced_by = Some(String::from("new"));
+        let mut replacement = running_instance("workload", "new", 400);
+        replacement.status = InstanceStatus::Failed;
+
+        let overdue = vec![old.clone(), replacement];
+        let retired = select_retired_instances(&overdue, timeout, chrono::Utc::now());
+        assert!(retired[0].1.as_deref().unwrap().contains("did not run"));
+
+        let gone = vec![old];
+        let retired = select_retired_instances(&gone, timeout, chrono::Utc::now());
+        assert!(retired[0].1.as_deref().unwrap().contains("is gone"));
+    }
+    #[test]
+    fn test_image_hash_mismatch() {
+        let declared = "ab".repeat(32);
+        let mut instance = running_instance("workload", "function", 10);
+        instance.spec.function = Some(
+            serde_json::from_value(serde_json::json!({
+                "execution": { "rootfs": "https://example.com/rootfs.ext4", "sha256": declared },
+                "exposure": null,
+            }))
+            .unwrap(),
+        );
+        assert!(instance.image_hash_mismatch().is_some());
+
+        let mut provenance = ImageProvenance {
+            resolved_url: String::from("https://example.com/rootfs.ext4"),
+            sha256: "AB".repeat(32),
+            downloaded_at: String::from("2026-10-16T08:00:00Z"),
+            etag: None,
+            last_modified: None,
+        };
+        instance.provenance = Some(provenance.clone());
+        assert_eq!(instance.image_hash_mismatch(), None);
+
+        provenance.sha256 = "cd".repeat(32);
+        instance.provenance = Some(provenance);
+        assert!(instance.image_hash_mismatch().is_some());
+    }
+}
@@ -0,0 +1,252 @@
+use crate::api::validation::{FieldError, ValidateRequest};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Source of the current time, so the maintenance schedule can be tested
+pub trait Clock: Send {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Recurrence {
+    Daily,
+    Weekly,
+}
+
+impl Recurrence {
+    fn period(&self) -> Duration {
+        match self {
+            Recurrence::Daily => Duration::days(1),
+            Recurrence::Weekly => Duration::weeks(1),
+        }
+    }
+}
+
+/// Period during which a node is cordoned, and optionally drained
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceWindow {
+    /// Identifier of the worker
+    pub node: String,
+    /// RFC 3339 date of the first occurrence
+    pub start: String,
+    pub duration_seconds: u64,
+    /// Windows without recurrence only happen once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Recurrence>,
+    /// Move the instances of the node to other nodes when the window opens
+    #[serde(default)]
+    pub drain: bool,
+}
+
+impl MaintenanceWindow {
+    fn start(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.start)
+            .ok()
+            .map(|start| start.with_timezone(&Utc))
+    }
+
+    /// Whether an occurrence of the window is in progress
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        let Some(start) = self.start() else {
+            return false;
+        };
+        if now < start {
+            return false;
+        }
+        let mut elapsed = now - start;
+        if let Some(recurrence) = self.recurrence {
+            elapsed = Duration::seconds(elapsed.num_seconds() % recurrence.period().num_seconds());
+        }
+        elapsed < Duration::seconds(self.duration_seconds as i64)
+    }
+}
+
+impl ValidateRequest for MaintenanceWindow {
+    fn validate(&self) -> Vec<FieldError> {
+        let mut errors = vec![];
+        if self.node.trim().is_empty() {
+            errors.push(FieldError::new("node", "The node must not be empty"));
+        }
+        if self.start().is_none() {
+            errors.push(FieldError::new(
+                "start",
+                "The start must be an RFC 3339 date, e.g. 2023-06-01T02:00:00Z",
+            ));
+        }
+        if self.duration_seconds == 0 {
+            errors.push(FieldError::new(
+                "duration_seconds",
+                "The window must last at least a second",
+            ));
+        }
+        if let Some(recurrence) = self.recurrence {
+            if self.duration_seconds as i64 >= recurrence.period().num_seconds() {
+                errors.push(FieldError::new(
+                    "duration_seconds",
+                    "The window must be shorter than its recurrence",
+                ));
+            }
+        }
+        errors
+    }
+}
+
+/// Who cordoned a node, it stays cordoned while any of them does so the
+/// maintenance schedule never lifts a manual cordon
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CordonState {
+    #[serde(default)]
+    pub manual: bool,
+    /// Identifier of the maintenance window holding the node
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maintenance: Option<String>,
+}
+
+impl CordonState {
+    pub fn is_cordoned(&self) -> bool {
+        self.manual || self.maintenance.is_some()
+    }
+}
+
+/// What the scheduler has to be told after evaluating the maintenance windows
+#[derive(Debug, Default)]
+pub struct MaintenancePlan {
+    /// Whether each worker is cordoned
+    pub cordons: Vec<(String, bool)>,
+    /// Workers entering a window asking for them to be drained
+    pub drains: Vec<String>,
+}
+
+/// Maintenance window a node enters or leaves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MaintenanceTransition {
+    Enter { window_id: String, drain: bool },
+    Leave { window_id: String },
+}
+
+/// Transition of a node given the windows, identified by their id, and the
+/// window currently holding it
+pub fn plan_maintenance(
+    node: &str,
+    windows: &[(String, MaintenanceWindow)],
+    current: Option<&str>,
+    now: DateTime<Utc>,
+) -> Option<MaintenanceTransition> {
+    let open = windows
+        .iter()
+        .find(|(_, window)| window.node == node && window.is_open(now));
+    match (open, current) {
+        (Some((window_id, _)), Some(current)) if window_id == current => None,
+        (Some((window_id, window)), _) => Some(MaintenanceTransition::Enter {
+            window_id: window_id.clone(),
+            drain: window.drain,
+        }),
+        (None, Some(current)) => Some(MaintenanceTransition::Leave {
+            window_id: current.to_string(),
+        }),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(date)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn window(recurrence: Option<Recurrence>) -> MaintenanceWindow {
+        MaintenanceWindow {
+            node: String::from("node-1"),
+            start: String::from("2023-06-01T02:00:00Z"),
+            duration_seconds: 3600,
+            recurrence,
+            drain: true,
+        }
+    }
+
+    #[test]
+    fn test_window_occurrences() {
+        let once = window(None);
+        assert!(!once.is_open(date("2023-06-01T01:59:59Z")));
+        assert!(once.is_open(date("2023-06-01T02:30:00Z")));
+        assert!(!once.is_open(date("2023-06-01T03:00:00Z")));
+        assert!(!once.is_open(date("2023-06-02T02:30:00Z")));
+
+        let daily = window(Some(Recurrence::Daily));
+        assert!(daily.is_open(date("2023-06-02T02:30:00Z")));
+        assert!(!daily.is_open(date("2023-06-02T12:00:00Z")));
+
+        let weekly = window(Some(Recurrence::Weekly));
+        assert!(!weekly.is_open(date("2023-06-02T02:30:00Z")));
+        assert!(weekly.is_open(date("2023-06-08T02:30:00Z")));
+    }
+
+    #[test]
+    fn test_validate_window() {
+        let mut invalid = window(Some(Recurrence::Daily));
+        invalid.start = String::from("tomorrow");
+        invalid.duration_seconds = 86400;
+        let fields: Vec<_> = invalid
+            .validate()
+            .into_iter()
+            .filter_map(|error| error.field)
+            .collect();
+        assert_eq!(fields, vec!["start", "duration_seconds"]);
+        assert!(window(None).validate().is_empty());
+    }
+
+    #[test]
+    fn test_plan_maintenance() {
+        let windows = vec![(String::from("w1"), window(None))];
+        let during = date("2023-06-01T02:30:00Z");
+        let after = date("2023-06-01T04:00:00Z");
+
+        assert_eq!(
+            plan_maintenance("node-1", &windows, None, during),
+            Some(MaintenanceTransition::Enter {
+                window_id: String::from("w1"),
+                drain: true
+            })
+        );
+        assert_eq!(
+            plan_maintenance("node-1", &windows, Some("w1"), during),
+            None
+        );
+        assert_eq!(
+            plan_maintenance("node-1", &windows, Some("w1"), after),
+            Some(MaintenanceTransition::Leave {
+                window_id: String::from("w1")
+            })
+        );
+        assert_eq!(plan_maintenance("node-2", &windows, None, during), None);
+    }
+
+    #[test]
+    fn test_manual_cordon_outlives_maintenance() {
+        let state = CordonState {
+            manual: true,
+            maintenance: None,
+        };
+        assert!(state.is_cordoned());
+        let state = CordonState {
+            manual: false,
+            maintenance: Some(String::from("w1")),
+        };
+        assert!(state.is_cordoned());
+        assert!(!CordonState::default().is_cordoned());
+    }
+}
@@ -1,9 +1,14 @@
 use crate::api::RikError;
+use crate::core::volume::BindingFailure;
 
 use crate::core::instance::Instance;
+use crate::core::maintenance::{CordonState, MaintenancePlan, MaintenanceWindow};
+use crate::core::worker_repository::NodeCapacity;
+use crate::core::workload_queue::{PendingChange, WorkloadIntent};
 use async_trait::async_trait;
 use backoff::ExponentialBackoff;
 use definition::workload::WorkloadDefinition;
+use definition::NodeCondition;
 use proto::common::{InstanceMetric, WorkerMetric};
 use std::future::Future;
 use std::net::SocketAddr;
@@ -11,11 +16,15 @@ use std::time::Duration;
 use tracing::{event, Level};
 
 pub mod core;
+pub mod discovery;
 pub mod instance;
 mod instance_repository;
 mod instance_service;
-mod worker_repository;
+pub mod maintenance;
+pub mod volume;
+pub(crate) mod worker_repository;
 mod worker_service;
+pub mod workload_queue;
 
 trait Listener {
     fn run_listen_thread(&mut self);
@@ -33,13 +42,53 @@ trait InstanceService {
         instance: Instance,
         workload_def: WorkloadDefinition,
     ) -> Result<(), RikError>;
+    /// Ask the worker of an instance to bring it down then up again
+    async fn restart_instance(
+        &mut self,
+        instance: Instance,
+        workload_def: WorkloadDefinition,
+    ) -> Result<(), RikError>;
+    /// Remove the instances whose replacement runs, or did not run in time
+    async fn retire_replaced_instances(&mut self) -> Result<(), RikError>;
     fn handle_instance_status_update(&mut self, instance_metric: InstanceMetric);
+    /// Intents replacing the instances living longer than allowed, by workload
+    fn recycle_intents(&mut self) -> Result<Vec<(String, WorkloadIntent)>, RikError>;
+    /// Stop or resume placing new instances on a worker
+    async fn cordon_worker(&mut self, worker_id: &str, cordoned: bool) -> Result<(), RikError>;
+    /// Intents replacing the instances running on a worker by instances placed
+    /// elsewhere, by workload
+    fn drain_intents(&mut self, worker_id: &str)
+        -> Result<Vec<(String, WorkloadIntent)>, RikError>;
+    /// Change the instances of a workload as the merged intents ask
+    async fn apply_workload_change(
+        &mut self,
+        workload_id: &str,
+        change: PendingChange,
+    ) -> Result<(), RikError>;
+    /// Keep track of something that happened to an instance, listed by `events.list`
+    fn record_event(&self, instance_id: &str, reason: &str, message: &str);
 }
 
 trait InstanceRepository {
     fn fetch_instance(&self, instance_id: String) -> Result<Instance, RikError>;
+    fn fetch_instances(&self) -> Result<Vec<Instance>, RikError>;
+    fn fetch_workload(&self, workload_id: String) -> Result<WorkloadDefinition, RikError>;
+    fn inject_discovered_env(
+        &self,
+        namespace: &str,
+        workload_def: &mut WorkloadDefinition,
+    ) -> Result<(), RikError>;
+    fn bind_volumes(
+        &self,
+        instance: &Instance,
+        workload_def: &mut WorkloadDefinition,
+    ) -> Result<Result<(), BindingFailure>, RikError>;
+    fn release_volumes(&self, instance_id: &str) -> Result<(), RikError>;
+    fn pin_volumes(&self, instance_id: &str, node: &str) -> Result<(), RikError>;
     fn register_instance(&self, instance: Instance) -> Result<(), RikError>;
     fn delete_instance(&self, instance: Instance) -> Result<(), RikError>;
+    /// Keep track of something that happened to an element, listed by `events.list`
+    fn record_event(&self, element_id: &str, reason: &str, message: &str) -> Result<(), RikError>;
 }
 
 trait WorkerService {
@@ -49,11 +98,32 @@ trait WorkerService {
         address: SocketAddr,
         metric: WorkerMetric,
     ) -> Result<(), RikError>;
+    /// Move the workers in and out of their maintenance windows
+    fn evaluate_maintenance(&mut self) -> Result<MaintenancePlan, RikError>;
 }
 
 trait WorkerRepository {
     fn fetch_worker_address(&self, worker_id: String) -> Result<String, RikError>;
     fn register_worker(&self, worker_id: String, address: String) -> Result<(), RikError>;
+    fn fetch_worker_conditions(&self, worker_id: String) -> Result<Vec<NodeCondition>, RikError>;
+    fn update_worker_conditions(
+        &self,
+        worker_id: String,
+        conditions: Vec<NodeCondition>,
+    ) -> Result<(), RikError>;
+    fn update_worker_capacity(
+        &self,
+        worker_id: String,
+        capacity: NodeCapacity,
+    ) -> Result<(), RikError>;
+    fn update_worker_queued_placements(
+        &self,
+        worker_id: String,
+        queued_placements: u32,
+    ) -> Result<(), RikError>;
+    fn fetch_worker_cordons(&self) -> Result<Vec<(String, CordonState)>, RikError>;
+    fn update_worker_cordon(&self, worker_id: String, cordon: CordonState) -> Result<(), RikError>;
+    fn fetch_maintenance_windows(&self) -> Result<Vec<(String, MaintenanceWindow)>, RikError>;
 }
 
 /// Create an exponential backoff function that retries a function until it succeeds or the timeout
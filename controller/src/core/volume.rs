@@ -0,0 +1,220 @@
+use crate::api::types::element::Element;
+use crate::api::types::volume::Volume;
+use crate::database::RikRepository;
+use definition::workload::WorkloadDefinition;
+use rusqlite::Connection;
+
+/// Reason and message explaining why volumes could not be bound
+pub type BindingFailure = (&'static str, String);
+
+/// Reason given to instances requesting a volume already used by another instance
+pub const VOLUME_ALREADY_BOUND: &str = "VolumeAlreadyBound";
+/// Reason given to instances requesting a volume that does not exist
+pub const VOLUME_NOT_FOUND: &str = "VolumeNotFound";
+
+pub fn volume_element_name(namespace: &str, name: &str) -> String {
+    format!("/volume/{}/{}", namespace, name)
+}
+
+/// Volume of the given name, the name of another volume may start with it
+pub fn find_volume(
+    connection: &Connection,
+    namespace: &str,
+    name: &str,
+) -> Option<(Element, Volume)> {
+    let element =
+        RikRepository::find_all_by_name(connection, &volume_element_name(namespace, name))
+            .ok()?
+            .into_iter()
+            .next()?;
+    let volume = serde_json::from_value(element.value.clone()).ok()?;
+    Some((element, volume))
+}
+
+fn save_volume(connection: &Connection, element: &Element, volume: &Volume) -> Result<(), String> {
+    RikRepository::update(
+        connection,
+        &element.id,
+        &serde_json::to_string(volume).unwrap(),
+    )
+    .map_err(|e| format!("Could not update volume {}: {}", volume.name, e))
+}
+
+/// Check a volume can be bound to an instance, returns the reason and message otherwise
+pub fn check_binding(volume: &Volume, instance_id: &str) -> Result<(), BindingFailure> {
+    match &volume.bound_to {
+        Some(bound_to) if bound_to != instance_id => Err((
+            VOLUME_ALREADY_BOUND,
+            format!(
+                "Volume {} is already bound to instance {}",
+                volume.name, bound_to
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Bind the volumes of a workload to an instance and complete their definition.
+///
+/// No volume is bound when one of them cannot be, the reason and message of the
+/// failure are returned instead. The volumes are read and bound in a single
+/// transaction, so two instances are never bound the same volume.
+pub fn bind_volumes(
+    connection: &Connection,
+    namespace: &str,
+    instance_id: &str,
+    workload: &mut WorkloadDefinition,
+) -> rusqlite::Result<Result<(), BindingFailure>> {
+    RikRepository::transaction(connection, |transaction| {
+        let mut volumes = Vec::new();
+        for requested in workload.volumes() {
+            let Some((element, volume)) = find_volume(transaction, namespace, &requested.name)
+            else {
+                return Ok(Err((
+                    VOLUME_NOT_FOUND,
+                    format!("Volume {} does not exist", requested.name),
+                )));
+            };
+            if let Err(failure) = check_binding(&volume, instance_id) {
+                return Ok(Err(failure));
+            }
+            volumes.push((element, volume));
+        }
+
+        for (requested, (element, mut volume)) in workload.volumes_mut().iter_mut().zip(volumes) {
+            requested.id = element.id.clone();
+            requested.size_mb = volume.size_mb;
+            requested.node = volume.node.clone();
+            volume.bound_to = Some(instance_id.to_string());
+            RikRepository::update(
+                transaction,
+                &element.id,
+                &serde_json::to_string(&volume).unwrap(),
+            )?;
+        }
+        Ok(Ok(()))
+    })
+}
+
+/// Volumes bound to an instance
+fn bound_volumes(connection: &Connection, instance_id: &str) -> Vec<(Element, Volume)> {
+    RikRepository::find_all(connection, "/volume")
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|element| {
+            let volume: Volume = serde_json::from_value(element.value.clone()).ok()?;
+            (volume.bound_to.as_deref() == Some(instance_id)).then_some((element, volume))
+        })
+        .collect()
+}
+
+/// Release the volumes bound to an instance, so a replacement can use them
+pub fn release_volumes(connection: &Connection, instance_id: &str) -> Result<(), String> {
+    for (element, mut volume) in bound_volumes(connection, instance_id) {
+        volume.bound_to = None;
+        save_volume(connection, &element, &volume)?;
+    }
+    Ok(())
+}
+
+/// Pin the volumes of an instance to the node it is placed on, the first time
+pub fn pin_volumes(connection: &Connection, instance_id: &str, node: &str) -> Result<(), String> {
+    for (element, mut volume) in bound_volumes(connection, instance_id) {
+        if volume.node.is_none() {
+            volume.node = Some(node.to_string());
+            save_volume(connection, &element, &volume)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::RikDataBase;
+    use crate::tests::fixtures::db_connection;
+    use rstest::rstest;
+
+    fn volume(bound_to: Option<&str>) -> Volume {
+        Volume {
+            name: "data".to_string(),
+            size_mb: 64,
+            namespace: None,
+            tenant_id: None,
+            node: None,
+            bound_to: bound_to.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_check_binding() {
+        assert!(check_binding(&volume(None), "instance").is_ok());
+        assert!(check_binding(&volume(Some("instance")), "instance").is_ok());
+
+        let (reason, message) = check_binding(&volume(Some("other")), "instance").unwrap_err();
+        assert_eq!(reason, VOLUME_ALREADY_BOUND);
+        assert_eq!(message, "Volume data is already bound to instance other");
+    }
+
+    #[rstest]
+    fn test_bind_volumes(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let workload = |names: &[&str]| -> WorkloadDefinition {
+            let volumes: Vec<_> = names
+                .iter()
+                .map(|name| serde_json::json!({ "name": name, "mountPath": "/data" }))
+                .collect();
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": "v0",
+                "kind": "Function",
+                "name": "db",
+                "spec": { "function": {
+                    "execution": { "rootfs": "https://example.com/rootfs.ext4" },
+                    "exposure": null,
+                    "volumes": volumes,
+                } },
+            }))
+            .unwrap()
+        };
+        let insert = |name: &str, bound_to: Option<&str>| {
+            let volume = Volume {
+                name: name.to_string(),
+                ..volume(bound_to)
+            };
+            RikRepository::insert(
+                &connection,
+                &volume_element_name("lab", name),
+                &serde_json::to_string(&volume).unwrap(),
+            )
+            .unwrap()
+        };
+        let data = insert("data", None);
+        insert("data-2", Some("other"));
+        let bound_to = |id: &str| {
+            let element = RikRepository::find_one(&connection, &id.to_string(), "/volume").unwrap();
+            element.value["bound_to"].as_str().map(String::from)
+        };
+
+        // The name of another volume starting with the requested one does not match
+        let mut missing = workload(&["dat"]);
+        let (reason, _) = bind_volumes(&connection, "lab", "instance", &mut missing)
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(reason, VOLUME_NOT_FOUND);
+
+        // Nothing is bound when one of the volumes cannot be
+        let mut both = workload(&["data", "data-2"]);
+        let (reason, _) = bind_volumes(&connection, "lab", "instance", &mut both)
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(reason, VOLUME_ALREADY_BOUND);
+        assert_eq!(bound_to(&data), None);
+
+        let mut one = workload(&["data"]);
+        bind_volumes(&connection, "lab", "instance", &mut one)
+            .unwrap()
+            .unwrap();
+        assert_eq!(bound_to(&data).as_deref(), Some("instance"));
+        assert_eq!(one.volumes()[0].id, data);
+    }
+}
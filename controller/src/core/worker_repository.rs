@@ -1,9 +1,155 @@
+use crate::api::limits::limit_from_env;
+use crate::api::types::element::Element;
 use crate::api::RikError;
+use crate::core::maintenance::{CordonState, MaintenanceWindow};
 use crate::core::WorkerRepository;
 use crate::database::{RikDataBase, RikRepository};
+use chrono::{DateTime, Utc};
+use definition::NodeCondition;
 use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::sync::Arc;
 
+/// Workers not heard from for longer are listed as `NotReady`
+const DEFAULT_HEARTBEAT_TIMEOUT_SECONDS: usize = 60;
+
+/// Resources a worker advertises in its metrics
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct NodeCapacity {
+    pub cpu_cores: u64,
+    pub memory_bytes: u64,
+}
+
+impl NodeCapacity {
+    /// Capacity given by the metrics a riklet sends, as serialized by `node_metrics`
+    pub(crate) fn from_metrics(metrics: &str) -> Option<NodeCapacity> {
+        let metrics: serde_json::Value = serde_json::from_str(metrics).ok()?;
+        Some(NodeCapacity {
+            cpu_cores: metrics["cpu"]["total"].as_u64()?,
+            memory_bytes: metrics["memory"]["total"].as_u64()?,
+        })
+    }
+}
+
+/// Stored representation of a worker
+#[derive(Serialize, Deserialize, Default)]
+struct WorkerRecord {
+    address: String,
+    #[serde(default)]
+    conditions: Vec<NodeCondition>,
+    #[serde(default)]
+    cordon: CordonState,
+    /// When the worker connected from its current address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    connected_at: Option<String>,
+    /// When the worker last sent its metrics
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_heartbeat: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    capacity: Option<NodeCapacity>,
+    /// Placements the scheduler holds back until the worker has the budget for them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    queued_placements: Option<u32>,
+}
+
+impl WorkerRecord {
+    /// Workers used to be stored as their address only
+    fn from_value(value: serde_json::Value) -> Result<WorkerRecord, serde_json::Error> {
+        match value {
+            serde_json::Value::String(address) => Ok(WorkerRecord {
+                address,
+                ..Default::default()
+            }),
+            value => serde_json::from_value(value),
+        }
+    }
+
+    /// Whether the worker sent its metrics recently enough
+    fn is_ready(&self, now: DateTime<Utc>) -> bool {
+        let timeout = limit_from_env(
+            "NODE_HEARTBEAT_TIMEOUT_SECONDS",
+            DEFAULT_HEARTBEAT_TIMEOUT_SECONDS,
+        );
+        self.last_heartbeat
+            .as_deref()
+            .and_then(|heartbeat| DateTime::parse_from_rfc3339(heartbeat).ok())
+            .is_some_and(|heartbeat| {
+                now.signed_duration_since(heartbeat).num_seconds() <= timeout as i64
+            })
+    }
+}
+
+/// Node listed by `nodes.list`, from its stored worker. Its status is `Ready`
+/// while it sends its metrics, `NotReady` once it stops.
+pub(crate) fn node_view(mut element: Element, now: DateTime<Utc>) -> Element {
+    if let Ok(worker) = WorkerRecord::from_value(element.value.clone()) {
+        element.value = json!({
+            "hostname": element.name,
+            "address": worker.address,
+            "status": if worker.is_ready(now) { "Ready" } else { "NotReady" },
+            "connected_at": worker.connected_at,
+            "last_heartbeat": worker.last_heartbeat,
+            "cordoned": worker.cordon.is_cordoned(),
+            "capacity": worker.capacity,
+            "queued_placements": worker.queued_placements.unwrap_or(0),
+            "conditions": worker.conditions,
+        });
+    }
+    element
+}
+
+/// Address of a worker from its stored representation
+pub(crate) fn worker_address(value: serde_json::Value) -> Option<String> {
+    WorkerRecord::from_value(value)
+        .ok()
+        .map(|worker| worker.address)
+}
+
+fn read_worker(connection: &Connection, worker_id: &str) -> Result<WorkerRecord, RikError> {
+    // "any" might correspond to the feature the worker can execute in the future
+    // (container riklet vs dummy riklet vs function riklet)
+    let element =
+        RikRepository::check_duplicate_name(connection, &format!("/worker/any/{}", worker_id))
+            .map_err(|_| RikError::InvalidName(worker_id.to_string()))?;
+
+    WorkerRecord::from_value(element.value)
+        .map_err(|e| RikError::InternalCommunicationError(format!("Could not parse worker: {}", e)))
+}
+
+fn write_worker(
+    connection: &Connection,
+    worker_id: &str,
+    worker: &WorkerRecord,
+) -> Result<(), RikError> {
+    match RikRepository::upsert(
+        connection,
+        &worker_id.to_string(),
+        &format!("/worker/any/{}", worker_id),
+        &serde_json::to_string(worker).unwrap(),
+        "/worker",
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(RikError::InternalCommunicationError(format!(
+            "Could not register worker: {}",
+            e
+        ))),
+    }
+}
+
+/// Cordon or uncordon a worker by hand, the scheduler learns about it on the
+/// next maintenance evaluation
+pub(crate) fn set_manual_cordon(
+    connection: &Connection,
+    worker_id: &str,
+    cordoned: bool,
+) -> Result<CordonState, RikError> {
+    let mut worker = read_worker(connection, worker_id)?;
+    worker.cordon.manual = cordoned;
+    write_worker(connection, worker_id, &worker)?;
+    Ok(worker.cordon)
+}
+
 pub struct WorkerRepositoryImpl {
     database: Arc<RikDataBase>,
 }
@@ -21,37 +167,103 @@ impl WorkerRepositoryImpl {
             ))
         })
     }
+
+    fn fetch_worker(&self, worker_id: String) -> Result<WorkerRecord, RikError> {
+        read_worker(&self.get_connection()?, &worker_id)
+    }
+
+    fn save_worker(&self, worker_id: String, worker: &WorkerRecord) -> Result<(), RikError> {
+        write_worker(&self.get_connection()?, &worker_id, worker)
+    }
 }
 
 impl WorkerRepository for WorkerRepositoryImpl {
     fn fetch_worker_address(&self, worker_id: String) -> Result<String, RikError> {
-        let conn = self.get_connection()?;
-        // "any" might correspond to the feature the worker can execute in the future
-        // (container riklet vs dummy riklet vs function riklet)
-        let element =
-            RikRepository::check_duplicate_name(&conn, &format!("/worker/any/{}", &worker_id))
-                .map_err(|_| RikError::InvalidName(worker_id))?;
-
-        serde_json::from_value::<String>(element.value).map_err(|e| {
-            RikError::InternalCommunicationError(format!("Could not parse worker: {}", e))
-        })
+        Ok(self.fetch_worker(worker_id)?.address)
     }
 
     fn register_worker(&self, worker_id: String, address: String) -> Result<(), RikError> {
+        // Keep the known conditions and cordon of the worker
+        let mut worker = self.fetch_worker(worker_id.clone()).unwrap_or_default();
+        let now = Utc::now();
+        // A worker coming back, or from elsewhere, connected again
+        if worker.address != address || !worker.is_ready(now) {
+            worker.connected_at = Some(now.to_rfc3339());
+        }
+        worker.address = address;
+        worker.last_heartbeat = Some(now.to_rfc3339());
+        self.save_worker(worker_id, &worker)
+    }
+
+    fn fetch_worker_conditions(&self, worker_id: String) -> Result<Vec<NodeCondition>, RikError> {
+        Ok(self.fetch_worker(worker_id)?.conditions)
+    }
+
+    fn update_worker_conditions(
+        &self,
+        worker_id: String,
+        conditions: Vec<NodeCondition>,
+    ) -> Result<(), RikError> {
+        let mut worker = self.fetch_worker(worker_id.clone())?;
+        worker.conditions = conditions;
+        self.save_worker(worker_id, &worker)
+    }
+
+    fn update_worker_capacity(
+        &self,
+        worker_id: String,
+        capacity: NodeCapacity,
+    ) -> Result<(), RikError> {
+        let mut worker = self.fetch_worker(worker_id.clone())?;
+        worker.capacity = Some(capacity);
+        self.save_worker(worker_id, &worker)
+    }
+
+    fn update_worker_queued_placements(
+        &self,
+        worker_id: String,
+        queued_placements: u32,
+    ) -> Result<(), RikError> {
+        let mut worker = self.fetch_worker(worker_id.clone())?;
+        worker.queued_placements = Some(queued_placements);
+        self.save_worker(worker_id, &worker)
+    }
+
+    fn fetch_worker_cordons(&self) -> Result<Vec<(String, CordonState)>, RikError> {
         let connection = self.get_connection()?;
-        match RikRepository::upsert(
-            &connection,
-            &worker_id,
-            &format!("/worker/any/{}", &worker_id),
-            &serde_json::to_string(&address).unwrap(),
-            "/worker",
-        ) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(RikError::InternalCommunicationError(format!(
-                "Could not register worker: {}",
+        let elements = RikRepository::find_all(&connection, "/worker/any/").map_err(|e| {
+            RikError::InternalCommunicationError(format!("Could not fetch workers: {}", e))
+        })?;
+        Ok(elements
+            .into_iter()
+            .filter_map(|element| {
+                let worker = WorkerRecord::from_value(element.value).ok()?;
+                Some((element.id, worker.cordon))
+            })
+            .collect())
+    }
+
+    fn update_worker_cordon(&self, worker_id: String, cordon: CordonState) -> Result<(), RikError> {
+        let mut worker = self.fetch_worker(worker_id.clone())?;
+        worker.cordon = cordon;
+        self.save_worker(worker_id, &worker)
+    }
+
+    fn fetch_maintenance_windows(&self) -> Result<Vec<(String, MaintenanceWindow)>, RikError> {
+        let connection = self.get_connection()?;
+        let elements = RikRepository::find_all(&connection, "/maintenance/").map_err(|e| {
+            RikError::InternalCommunicationError(format!(
+                "Could not fetch maintenance windows: {}",
                 e
-            ))),
-        }
+            ))
+        })?;
+        Ok(elements
+            .into_iter()
+            .filter_map(|element| {
+                let window = serde_json::from_value(element.value).ok()?;
+                Some((element.id, window))
+            })
+            .collect())
     }
 }
 
@@ -94,6 +306,63 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[rstest]
+    fn test_update_worker_conditions(db_connection: std::sync::Arc<RikDataBase>) {
+        let worker_repository = WorkerRepositoryImpl::new(db_connection);
+        let worker_id = "test-worker-conditions";
+        worker_repository
+            .register_worker(worker_id.to_string(), "http://localhost:8080".to_string())
+            .unwrap();
+
+        let conditions = vec![NodeCondition {
+            condition_type: definition::NodeConditionType::DiskPressure,
+            active: true,
+            message: "/var/lib/riklet is 95% full".to_string(),
+        }];
+        worker_repository
+            .update_worker_conditions(worker_id.to_string(), conditions.clone())
+            .unwrap();
+
+        // Registering again keeps the conditions
+        worker_repository
+            .register_worker(worker_id.to_string(), "http://localhost:8081".to_string())
+            .unwrap();
+
+        let fetched_conditions = worker_repository
+            .fetch_worker_conditions(worker_id.to_string())
+            .unwrap();
+        assert_eq!(fetched_conditions, conditions);
+    }
+
+    #[rstest]
+    fn test_cordon_survives_registration(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let worker_repository = WorkerRepositoryImpl::new(db_connection);
+        let worker_id = "test-worker-cordon";
+        worker_repository
+            .register_worker(worker_id.to_string(), "http://localhost:8080".to_string())
+            .unwrap();
+
+        set_manual_cordon(&connection, worker_id, true).unwrap();
+        worker_repository
+            .update_worker_cordon(
+                worker_id.to_string(),
+                CordonState {
+                    manual: true,
+                    maintenance: Some("window".to_string()),
+                },
+            )
+            .unwrap();
+        worker_repository
+            .register_worker(worker_id.to_string(), "http://localhost:8081".to_string())
+            .unwrap();
+
+        let cordons = worker_repository.fetch_worker_cordons().unwrap();
+        let (_, cordon) = cordons.iter().find(|(id, _)| id == worker_id).unwrap();
+        assert!(cordon.manual);
+        assert_eq!(cordon.maintenance.as_deref(), Some("window"));
+    }
+
     #[rstest]
     fn test_update_worker_addr(db_connection: std::sync::Arc<RikDataBase>) {
         let worker_repository = WorkerRepositoryImpl::new(db_connection);
@@ -113,4 +382,38 @@ mod tests {
             .unwrap();
         assert_eq!(fetched_address, new_address);
     }
+
+    #[rstest]
+    fn test_node_status(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let worker_repository = WorkerRepositoryImpl::new(db_connection);
+        let worker_id = "test-worker-status";
+        worker_repository
+            .register_worker(worker_id.to_string(), "10.0.0.1:4995".to_string())
+            .unwrap();
+        let mut element =
+            RikRepository::find_by_name(&connection, &format!("/worker/any/{}", worker_id))
+                .unwrap();
+        element.name = worker_id.to_string();
+
+        let now = Utc::now();
+        let node = node_view(element.clone(), now);
+        assert_eq!(node.value["hostname"], worker_id);
+        assert_eq!(node.value["address"], "10.0.0.1:4995");
+        assert_eq!(node.value["status"], "Ready");
+        assert_eq!(node.value["cordoned"], false);
+        assert!(node.value["connected_at"].is_string());
+
+        // Stale nodes are kept, and listed as not ready
+        let later = now + chrono::Duration::seconds(DEFAULT_HEARTBEAT_TIMEOUT_SECONDS as i64 + 1);
+        assert_eq!(node_view(element, later).value["status"], "NotReady");
+
+        // Workers stored before their heartbeats were recorded are not ready
+        let legacy = Element::new(
+            worker_id.to_string(),
+            worker_id.to_string(),
+            String::from(r#""10.0.0.1:4995""#),
+        );
+        assert_eq!(node_view(legacy, now).value["status"], "NotReady");
+    }
 }
@@ -1,16 +1,29 @@
 use crate::api::RikError;
-use crate::core::worker_repository::WorkerRepositoryImpl;
+use crate::core::maintenance::{
+    plan_maintenance, Clock, MaintenancePlan, MaintenanceTransition, SystemClock,
+};
+use crate::core::worker_repository::{NodeCapacity, WorkerRepositoryImpl};
 use crate::core::{WorkerRepository, WorkerService};
+use definition::NodeCondition;
 use proto::common::WorkerMetric;
 use std::net::SocketAddr;
+use tracing::{event, Level};
 
 pub struct WorkerServiceImpl {
     repository: WorkerRepositoryImpl,
+    clock: Box<dyn Clock>,
 }
 
 impl WorkerServiceImpl {
     pub fn new(repository: WorkerRepositoryImpl) -> WorkerServiceImpl {
-        WorkerServiceImpl { repository }
+        WorkerServiceImpl::with_clock(repository, Box::new(SystemClock))
+    }
+
+    pub fn with_clock(
+        repository: WorkerRepositoryImpl,
+        clock: Box<dyn Clock>,
+    ) -> WorkerServiceImpl {
+        WorkerServiceImpl { repository, clock }
     }
 }
 
@@ -19,9 +32,253 @@ impl WorkerService for WorkerServiceImpl {
         &mut self,
         identifier: String,
         address: SocketAddr,
-        _metric: WorkerMetric,
+        metric: WorkerMetric,
     ) -> Result<(), RikError> {
+        let capacity = NodeCapacity::from_metrics(&metric.metrics);
+        let queued_placements = metric.queued_placements;
+        let conditions: Vec<NodeCondition> = metric
+            .conditions
+            .into_iter()
+            .filter_map(|condition| NodeCondition::try_from(condition).ok())
+            .collect();
+        let previous = self
+            .repository
+            .fetch_worker_conditions(identifier.clone())
+            .unwrap_or_default();
+        let is_active = |conditions: &[NodeCondition], condition: &NodeCondition| {
+            conditions
+                .iter()
+                .any(|other| other.active && other.condition_type == condition.condition_type)
+        };
+
+        for condition in conditions.iter().filter(|condition| condition.active) {
+            if !is_active(&previous, condition) {
+                event!(
+                    Level::WARN,
+                    "Worker {} reports {}: {}",
+                    identifier,
+                    condition.condition_type,
+                    condition.message
+                );
+            }
+        }
+        for condition in previous.iter().filter(|condition| condition.active) {
+            if !is_active(&conditions, condition) {
+                event!(
+                    Level::INFO,
+                    "Worker {} no longer reports {}",
+                    identifier,
+                    condition.condition_type
+                );
+            }
+        }
+
         self.repository
-            .register_worker(identifier, address.to_string())
+            .register_worker(identifier.clone(), address.to_string())?;
+        if let Some(capacity) = capacity {
+            self.repository
+                .update_worker_capacity(identifier.clone(), capacity)?;
+        }
+        if let Some(queued_placements) = queued_placements {
+            self.repository
+                .update_worker_queued_placements(identifier.clone(), queued_placements)?;
+        }
+        self.repository
+            .update_worker_conditions(identifier, conditions)
+    }
+
+    fn evaluate_maintenance(&mut self) -> Result<MaintenancePlan, RikError> {
+        let now = self.clock.now();
+        let windows = self.repository.fetch_maintenance_windows()?;
+        let mut plan = MaintenancePlan::default();
+
+        for (worker_id, mut cordon) in self.repository.fetch_worker_cordons()? {
+            match plan_maintenance(&worker_id, &windows, cordon.maintenance.as_deref(), now) {
+                Some(MaintenanceTransition::Enter { window_id, drain }) => {
+                    event!(
+                        Level::INFO,
+                        "Worker {} enters maintenance window {}, cordoned{}",
+                        worker_id,
+                        window_id,
+                        if drain { " and drained" } else { "" }
+                    );
+                    cordon.maintenance = Some(window_id);
+                    self.repository
+                        .update_worker_cordon(worker_id.clone(), cordon.clone())?;
+                    if drain {
+                        plan.drains.push(worker_id.clone());
+                    }
+                }
+                Some(MaintenanceTransition::Leave { window_id }) => {
+                    event!(
+                        Level::INFO,
+                        "Worker {} leaves maintenance window {}{}",
+                        worker_id,
+                        window_id,
+                        if cordon.manual {
+                            ", it stays cordoned by hand"
+                        } else {
+                            ""
+                        }
+                    );
+                    cordon.maintenance = None;
+                    self.repository
+                        .update_worker_cordon(worker_id.clone(), cordon.clone())?;
+                }
+                None => {}
+            }
+            plan.cordons.push((worker_id, cordon.is_cordoned()));
+        }
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::maintenance::MaintenanceWindow;
+    use crate::core::worker_repository::node_view;
+    use crate::database::{RikDataBase, RikRepository};
+    use crate::tests::fixtures::db_connection;
+    use chrono::{DateTime, Utc};
+    use rstest::rstest;
+    use std::sync::{Arc, Mutex};
+
+    struct FixedClock(Arc<Mutex<DateTime<Utc>>>);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn date(date: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(date)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[rstest]
+    fn test_maintenance_window_cordons_and_drains(db_connection: Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let worker_id = "test-worker-maintenance";
+        let window = MaintenanceWindow {
+            node: worker_id.to_string(),
+            start: String::from("2023-06-01T02:00:00Z"),
+            duration_seconds: 3600,
+            recurrence: None,
+            drain: true,
+        };
+        let window_id = RikRepository::insert(
+            &connection,
+            &format!("/maintenance/{}", worker_id),
+            &serde_json::to_string(&window).unwrap(),
+        )
+        .unwrap();
+
+        let now = Arc::new(Mutex::new(date("2023-06-01T01:00:00Z")));
+        let repository = WorkerRepositoryImpl::new(db_connection);
+        repository
+            .register_worker(worker_id.to_string(), "http://localhost:8080".to_string())
+            .unwrap();
+        let mut service =
+            WorkerServiceImpl::with_clock(repository, Box::new(FixedClock(now.clone())));
+        let cordoned = |cordons: &[(String, bool)]| {
+            cordons
+                .iter()
+                .find(|(id, _)| id == worker_id)
+                .map(|(_, cordoned)| *cordoned)
+                .unwrap()
+        };
+
+        let plan = service.evaluate_maintenance().unwrap();
+        assert!(!cordoned(&plan.cordons));
+        assert!(plan.drains.is_empty());
+
+        *now.lock().unwrap() = date("2023-06-01T02:10:00Z");
+        let plan = service.evaluate_maintenance().unwrap();
+        assert!(cordoned(&plan.cordons));
+        assert_eq!(plan.drains, vec![worker_id.to_string()]);
+
+        // The node is only drained when entering the window
+        *now.lock().unwrap() = date("2023-06-01T02:20:00Z");
+        let plan = service.evaluate_maintenance().unwrap();
+        assert!(cordoned(&plan.cordons));
+        assert!(plan.drains.is_empty());
+
+        *now.lock().unwrap() = date("2023-06-01T03:10:00Z");
+        let plan = service.evaluate_maintenance().unwrap();
+        assert!(!cordoned(&plan.cordons));
+
+        RikRepository::delete(&connection, &window_id).unwrap();
+    }
+
+    #[rstest]
+    fn test_metrics_record_capacity(db_connection: Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let worker_id = "test-worker-capacity";
+        let mut service = WorkerServiceImpl::new(WorkerRepositoryImpl::new(db_connection));
+        let metric = |metrics: &str| WorkerMetric {
+            metrics: metrics.to_string(),
+            ..Default::default()
+        };
+        let capacity = || {
+            RikRepository::find_one(&connection, &worker_id.to_string(), "/worker/any/")
+                .unwrap()
+                .value["capacity"]
+                .clone()
+        };
+
+        service
+            .handle_metric_update(
+                worker_id.to_string(),
+                "127.0.0.1:4995".parse().unwrap(),
+                metric(r#"{"cpu": {"total": 8, "free": 80.5}, "memory": {"total": 16000000000, "free": 8000000000}, "disks": []}"#),
+            )
+            .unwrap();
+        assert_eq!(
+            capacity(),
+            serde_json::json!({ "cpu_cores": 8, "memory_bytes": 16000000000u64 })
+        );
+
+        // Metrics which cannot be read keep the known capacity
+        service
+            .handle_metric_update(
+                worker_id.to_string(),
+                "127.0.0.1:4995".parse().unwrap(),
+                metric("not json"),
+            )
+            .unwrap();
+        assert_eq!(capacity()["cpu_cores"], 8);
+    }
+
+    #[rstest]
+    fn test_metrics_record_queued_placements(db_connection: Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let worker_id = "test-worker-queued";
+        let mut service = WorkerServiceImpl::new(WorkerRepositoryImpl::new(db_connection));
+        let mut update = |queued_placements: Option<u32>| {
+            let metric = WorkerMetric {
+                queued_placements,
+                ..Default::default()
+            };
+            service
+                .handle_metric_update(
+                    worker_id.to_string(),
+                    "127.0.0.1:4995".parse().unwrap(),
+                    metric,
+                )
+                .unwrap();
+            let element =
+                RikRepository::find_one(&connection, &worker_id.to_string(), "/worker/any/")
+                    .unwrap();
+            node_view(element, Utc::now()).value["queued_placements"].clone()
+        };
+
+        assert_eq!(update(None), 0);
+        assert_eq!(update(Some(7)), 7);
+        // Metrics sent by the worker itself leave the count of the scheduler
+        assert_eq!(update(None), 7);
+        assert_eq!(update(Some(0)), 0);
     }
 }
@@ -0,0 +1,399 @@
+use crate::core::instance::Instance;
+use definition::workload::WorkloadDefinition;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+
+/// Why an instance is replaced by a new one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Replacement {
+    /// The instance lived longer than its workload allows
+    Expired { max_lifetime: u64 },
+    /// The worker of the instance is drained
+    Drained { worker_id: String },
+    /// The definition of the workload changed
+    Rollout,
+}
+
+impl Display for Replacement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Replacement::Expired { max_lifetime } => {
+                write!(f, "maximum lifetime of {} seconds exceeded", max_lifetime)
+            }
+            Replacement::Drained { worker_id } => write!(f, "worker {} is drained", worker_id),
+            Replacement::Rollout => write!(f, "the workload definition changed"),
+        }
+    }
+}
+
+/// Change wanted on the instances of a workload
+#[derive(Debug, Clone)]
+pub enum WorkloadIntent {
+    /// Run this many instances
+    Scale { replicas: u16 },
+    /// Replace every instance by one following the definition
+    Update { definition: Box<WorkloadDefinition> },
+    /// Replace a single instance
+    Replace {
+        instance_id: String,
+        reason: Replacement,
+    },
+}
+
+/// Intents submitted for a workload since its instances were last changed,
+/// merged so they never undo each other
+#[derive(Debug, Clone, Default)]
+pub struct PendingChange {
+    /// The last replica count asked for, the current one is kept otherwise
+    pub replicas: Option<u16>,
+    /// The last definition rolled out, it also applies to the instances
+    /// created to reach the replica count
+    pub definition: Option<WorkloadDefinition>,
+    pub replacements: Vec<(String, Replacement)>,
+}
+
+impl PendingChange {
+    fn merge(&mut self, intent: WorkloadIntent) {
+        match intent {
+            WorkloadIntent::Scale { replicas } => self.replicas = Some(replicas),
+            WorkloadIntent::Update { definition } => self.definition = Some(*definition),
+            WorkloadIntent::Replace {
+                instance_id,
+                reason,
+            } => {
+                if !self.replacements.iter().any(|(id, _)| *id == instance_id) {
+                    self.replacements.push((instance_id, reason));
+                }
+            }
+        }
+    }
+}
+
+/// Serialize the changes made to the instances of each workload.
+///
+/// Intents are queued by workload, a workload is handed out once at a time
+/// and intents submitted meanwhile wait, merged, for it to be finished.
+/// Different workloads are handed out independently.
+#[derive(Default)]
+pub struct WorkloadQueue {
+    pending: HashMap<String, PendingChange>,
+    /// Workloads with pending intents, in the order they were first submitted
+    order: VecDeque<String>,
+    running: HashSet<String>,
+}
+
+impl WorkloadQueue {
+    pub fn submit(&mut self, workload_id: &str, intent: WorkloadIntent) {
+        if !self.pending.contains_key(workload_id) {
+            self.order.push_back(workload_id.to_string());
+        }
+        self.pending
+            .entry(workload_id.to_string())
+            .or_default()
+            .merge(intent);
+    }
+
+    /// Take the intents of the first workload which is not already being
+    /// changed, it must then be given back to `finish`
+    pub fn take_next(&mut self) -> Option<(String, PendingChange)> {
+        let position = self
+            .order
+            .iter()
+            .position(|workload_id| !self.running.contains(workload_id))?;
+        let workload_id = self.order.remove(position)?;
+        let change = self.pending.remove(&workload_id)?;
+        self.running.insert(workload_id.clone());
+        Some((workload_id, change))
+    }
+
+    pub fn finish(&mut self, workload_id: &str) {
+        self.running.remove(workload_id);
+    }
+}
+
+/// Changes to make to the instances of a workload
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct InstancePlan {
+    /// Instances replaced by a new one, which is started first
+    pub replace: Vec<(String, Replacement)>,
+    /// Instances removed to reach the replica count
+    pub delete: Vec<String>,
+    /// Number of new instances needed to reach the replica count
+    pub create: usize,
+}
+
+/// Plan the changes bringing the instances of a workload to what the intents ask.
+///
+/// Only the instances neither failed nor terminating are counted. Scaling down
+/// removes the instances waiting for a replacement first, then the oldest, and
+/// a rollout replaces all the instances left.
+pub fn plan_instances(instances: &[Instance], change: &PendingChange) -> InstancePlan {
+    let replacement = |instance: &Instance| {
+        change
+            .replacements
+            .iter()
+            .find(|(id, _)| *id == instance.id)
+            .map(|(_, reason)| reason.clone())
+    };
+
+    let mut live: Vec<&Instance> = instances
+        .iter()
+        .filter(|instance| instance.is_live())
+        .collect();
+    live.sort_by_key(|instance| (replacement(instance).is_none(), instance.created_at.clone()));
+
+    let replicas = change
+        .replicas
+        .map(usize::from)
+        .unwrap_or_else(|| live.len());
+    let surplus = live.len().saturating_sub(replicas);
+    let (removed, kept) = live.split_at(surplus);
+
+    InstancePlan {
+        replace: kept
+            .iter()
+            .filter_map(|instance| match change.definition {
+                Some(_) => Some((instance.id.clone(), Replacement::Rollout)),
+                None => replacement(instance).map(|reason| (instance.id.clone(), reason)),
+            })
+            .collect(),
+        delete: removed.iter().map(|instance| instance.id.clone()).collect(),
+        create: replicas - kept.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definition::workload::{Container, Spec, WorkloadKind};
+    use definition::InstanceStatus;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    fn definition(image: &str) -> WorkloadDefinition {
+        WorkloadDefinition {
+            api_version: String::from("v0"),
+            kind: WorkloadKind::Pod,
+            name: String::from("web"),
+            spec: Spec {
+                containers: vec![Container {
+                    name: String::from("web"),
+                    image: image.to_string(),
+                    image_archive_url: None,
+                    image_archive_sha256: None,
+                    env: None,
+                    ports: None,
+                    discover: vec![],
+                }],
+                function: None,
+            },
+            replicas: None,
+            max_instance_lifetime_seconds: None,
+            labels: Default::default(),
+            rebalanceable: false,
+            min_ready_replicas: None,
+            protected: false,
+            namespace: None,
+            tenant_id: None,
+        }
+    }
+
+    fn running_instance(id: &str, created_at: &str, definition: &WorkloadDefinition) -> Instance {
+        let mut instance = Instance::new(
+            String::from("workload"),
+            definition.kind.clone(),
+            Some(id.to_string()),
+            definition.spec.clone(),
+        );
+        instance.status = InstanceStatus::Running;
+        instance.created_at = Some(created_at.to_string());
+        instance
+    }
+
+    /// Apply a plan the way the instance service does, the replaced and
+    /// deleted instances are gone at once
+    fn apply(instances: &mut Vec<Instance>, plan: InstancePlan, definition: &WorkloadDefinition) {
+        let created = plan.replace.len() + plan.create;
+        instances.retain(|instance| {
+            !plan.delete.contains(&instance.id)
+                && !plan.replace.iter().any(|(id, _)| *id == instance.id)
+        });
+        for _ in 0..created {
+            instances.push(running_instance(
+                &Instance::generate_name(),
+                "2023-06-02T00:00:00Z",
+                definition,
+            ));
+        }
+    }
+
+    #[test]
+    fn test_merge_intents() {
+        let mut queue = WorkloadQueue::default();
+        queue.submit("workload", WorkloadIntent::Scale { replicas: 5 });
+        queue.submit(
+            "workload",
+            WorkloadIntent::Update {
+                definition: Box::new(definition("nginx:2")),
+            },
+        );
+        queue.submit("workload", WorkloadIntent::Scale { replicas: 2 });
+
+        let (workload_id, change) = queue.take_next().unwrap();
+        assert_eq!(workload_id, "workload");
+        assert_eq!(change.replicas, Some(2));
+        assert_eq!(change.definition, Some(definition("nginx:2")));
+        assert!(queue.take_next().is_none());
+    }
+
+    #[test]
+    fn test_workload_is_handed_out_once_at_a_time() {
+        let mut queue = WorkloadQueue::default();
+        queue.submit("first", WorkloadIntent::Scale { replicas: 1 });
+        queue.submit("second", WorkloadIntent::Scale { replicas: 1 });
+
+        let (first, _) = queue.take_next().unwrap();
+        queue.submit(&first, WorkloadIntent::Scale { replicas: 3 });
+        let (second, _) = queue.take_next().unwrap();
+        assert_eq!((first.as_str(), second.as_str()), ("first", "second"));
+        // Intents on a workload being changed wait for it to be finished
+        assert!(queue.take_next().is_none());
+
+        queue.finish("first");
+        let (workload_id, change) = queue.take_next().unwrap();
+        assert_eq!(workload_id, "first");
+        assert_eq!(change.replicas, Some(3));
+    }
+
+    #[test]
+    fn test_scale_down_removes_replaced_instances_first() {
+        let current = definition("nginx:1");
+        let instances = vec![
+            running_instance("old", "2023-06-01T00:00:00Z", &current),
+            running_instance("drained", "2023-06-01T02:00:00Z", &current),
+            running_instance("young", "2023-06-01T03:00:00Z", &current),
+        ];
+        let mut change = PendingChange::default();
+        change.merge(WorkloadIntent::Replace {
+            instance_id: String::from("drained"),
+            reason: Replacement::Drained {
+                worker_id: String::from("node-1"),
+            },
+        });
+        change.merge(WorkloadIntent::Scale { replicas: 1 });
+
+        let plan = plan_instances(&instances, &change);
+        assert_eq!(
+            plan,
+            InstancePlan {
+                replace: vec![],
+                delete: vec![String::from("drained"), String::from("old")],
+                create: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_terminating_instances_are_not_counted() {
+        let current = definition("nginx:1");
+        let mut recycled = running_instance("recycled", "2023-06-01T00:00:00Z", &current);
+        recycled.mark_recycled(3600);
+        let instances = vec![
+            recycled,
+            running_instance("live", "2023-06-01T01:00:00Z", &current),
+        ];
+        let mut change = PendingChange::default();
+        change.merge(WorkloadIntent::Scale { replicas: 2 });
+        change.merge(WorkloadIntent::Replace {
+            instance_id: String::from("recycled"),
+            reason: Replacement::Expired { max_lifetime: 3600 },
+        });
+
+        let plan = plan_instances(&instances, &change);
+        assert_eq!(
+            plan,
+            InstancePlan {
+                replace: vec![],
+                delete: vec![],
+                create: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_concurrent_scale_and_update() {
+        let current = definition("nginx:1");
+        let target = definition("nginx:2");
+
+        for replicas in [1, 3, 6] {
+            let instances: Vec<Instance> = (0..3)
+                .map(|i| {
+                    running_instance(
+                        &format!("instance-{}", i),
+                        &format!("2023-06-01T0{}:00:00Z", i),
+                        &current,
+                    )
+                })
+                .collect();
+            let queue = Arc::new(Mutex::new(WorkloadQueue::default()));
+            let instances = Arc::new(Mutex::new(instances));
+            let stored = Arc::new(Mutex::new(current.clone()));
+
+            // Drain the queue while the intents come in, as the core does, the
+            // instances created by a scale follow the last stored definition
+            let executor = {
+                let queue = queue.clone();
+                let instances = instances.clone();
+                let stored = stored.clone();
+                move || loop {
+                    let next = queue.lock().unwrap().take_next();
+                    let Some((workload_id, change)) = next else {
+                        break;
+                    };
+                    let mut stored = stored.lock().unwrap();
+                    if let Some(definition) = &change.definition {
+                        *stored = definition.clone();
+                    }
+                    let mut instances = instances.lock().unwrap();
+                    let plan = plan_instances(&instances, &change);
+                    apply(&mut instances, plan, &stored);
+                    queue.lock().unwrap().finish(&workload_id);
+                }
+            };
+            let scale = {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for replicas in [2, 5, replicas] {
+                        queue
+                            .lock()
+                            .unwrap()
+                            .submit("workload", WorkloadIntent::Scale { replicas });
+                    }
+                })
+            };
+            let update = {
+                let queue = queue.clone();
+                let target = target.clone();
+                thread::spawn(move || {
+                    queue.lock().unwrap().submit(
+                        "workload",
+                        WorkloadIntent::Update {
+                            definition: Box::new(target),
+                        },
+                    );
+                })
+            };
+            let running = thread::spawn(executor.clone());
+            scale.join().unwrap();
+            update.join().unwrap();
+            running.join().unwrap();
+            executor();
+
+            let instances = instances.lock().unwrap();
+            assert_eq!(instances.len(), replicas as usize);
+            assert!(instances
+                .iter()
+                .all(|instance| instance.spec == target.spec && instance.is_live()));
+        }
+    }
+}
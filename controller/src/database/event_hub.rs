@@ -0,0 +1,243 @@
+use crate::api::types::event::Event;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, OnceLock, Weak};
+use std::time::Duration;
+
+/// Events waiting to be sent to a watch at most, a watch falling further
+/// behind is closed
+pub const WATCH_BUFFER_SIZE: usize = 256;
+
+#[derive(Debug, Default)]
+struct Buffer {
+    events: VecDeque<Event>,
+    /// Set once the buffer overflowed, the watch has lost events
+    lagging: bool,
+    /// Set once the watch is asked to end, e.g. by a shutdown
+    closed: bool,
+}
+
+/// What a watch gets when waiting for events
+#[derive(Debug, PartialEq, Eq)]
+pub enum Received {
+    Events(Vec<Event>),
+    /// The watch fell too far behind and must be closed
+    Lagging,
+    /// Nothing happened in time
+    Timeout,
+    /// The watch was asked to end, once its buffered events are sent
+    Closed,
+}
+
+/// Bounded buffer of the events recorded since a watch started
+#[derive(Debug)]
+pub struct Subscriber {
+    element_id: Option<String>,
+    capacity: usize,
+    buffer: Mutex<Buffer>,
+    ready: Condvar,
+}
+
+impl Subscriber {
+    fn push(&self, event: &Event) {
+        if self
+            .element_id
+            .as_ref()
+            .is_some_and(|element_id| *element_id != event.element_id)
+        {
+            return;
+        }
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.lagging {
+            return;
+        }
+        if buffer.events.len() >= self.capacity {
+            // Nothing is sent past a gap, the events are dropped at once
+            buffer.events.clear();
+            buffer.lagging = true;
+        } else {
+            buffer.events.push_back(event.clone());
+        }
+        self.ready.notify_one();
+    }
+
+    /// Events buffered so far, waiting at most `timeout` for one
+    pub fn receive(&self, timeout: Duration) -> Received {
+        let buffer = self.buffer.lock().unwrap();
+        let (mut buffer, _) = self
+            .ready
+            .wait_timeout_while(buffer, timeout, |buffer| {
+                buffer.events.is_empty() && !buffer.lagging && !buffer.closed
+            })
+            .unwrap();
+        if buffer.lagging {
+            Received::Lagging
+        } else if !buffer.events.is_empty() {
+            Received::Events(buffer.events.drain(..).collect())
+        } else if buffer.closed {
+            Received::Closed
+        } else {
+            Received::Timeout
+        }
+    }
+
+    /// Ask the watch to end, it is woken if waiting
+    pub fn close(&self) {
+        self.buffer.lock().unwrap().closed = true;
+        self.ready.notify_one();
+    }
+}
+
+/// Hands the recorded events to the watches.
+///
+/// Watches subscribe before reading the events they missed from the
+/// database, so no event falls in between. A subscriber is forgotten once
+/// its watch drops it.
+#[derive(Debug)]
+pub struct EventHub {
+    subscribers: Mutex<Vec<Weak<Subscriber>>>,
+    capacity: usize,
+}
+
+impl EventHub {
+    pub fn new(capacity: usize) -> EventHub {
+        EventHub {
+            subscribers: Mutex::new(Vec::new()),
+            capacity,
+        }
+    }
+
+    /// Start buffering the events of an element, or of every element
+    pub fn subscribe(&self, element_id: Option<String>) -> Arc<Subscriber> {
+        let subscriber = Arc::new(Subscriber {
+            element_id,
+            capacity: self.capacity,
+            buffer: Mutex::new(Buffer::default()),
+            ready: Condvar::new(),
+        });
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push(Arc::downgrade(&subscriber));
+        subscriber
+    }
+
+    /// Record an event with `record` and hand it to the watches.
+    ///
+    /// Events are recorded one at a time, so the watches get them in the
+    /// order of their ids.
+    pub fn publish_with<E>(&self, record: impl FnOnce() -> Result<Event, E>) -> Result<Event, E> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let event = record()?;
+        subscribers.retain(|subscriber| match subscriber.upgrade() {
+            Some(subscriber) => {
+                subscriber.push(&event);
+                true
+            }
+            None => false,
+        });
+        Ok(event)
+    }
+
+    pub fn subscribers(&self) -> usize {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|subscriber| subscriber.strong_count() > 0)
+            .count()
+    }
+}
+
+pub fn event_hub() -> &'static EventHub {
+    static HUB: OnceLock<EventHub> = OnceLock::new();
+    HUB.get_or_init(|| EventHub::new(WATCH_BUFFER_SIZE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn publish(hub: &EventHub, id: i64, element_id: &str) {
+        hub.publish_with(|| {
+            Ok::<_, ()>(Event {
+                id,
+                created_at: String::new(),
+                element_id: element_id.to_string(),
+                tenant_id: None,
+                reason: String::from("Running"),
+                message: String::new(),
+            })
+        })
+        .unwrap();
+    }
+
+    fn ids(received: Received) -> Vec<i64> {
+        match received {
+            Received::Events(events) => events.iter().map(|event| event.id).collect(),
+            other => panic!("expected events, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_subscribers_get_their_events() {
+        let hub = EventHub::new(10);
+        let every = hub.subscribe(None);
+        let filtered = hub.subscribe(Some(String::from("b")));
+        for (id, element_id) in [(1, "a"), (2, "b"), (3, "a")] {
+            publish(&hub, id, element_id);
+        }
+
+        assert_eq!(ids(every.receive(Duration::ZERO)), vec![1, 2, 3]);
+        assert_eq!(ids(filtered.receive(Duration::ZERO)), vec![2]);
+        assert_eq!(every.receive(Duration::ZERO), Received::Timeout);
+
+        drop(filtered);
+        publish(&hub, 4, "b");
+        assert_eq!(hub.subscribers(), 1);
+    }
+
+    #[test]
+    fn test_slow_subscriber_is_closed() {
+        let hub = EventHub::new(2);
+        let slow = hub.subscribe(None);
+        for id in 1..=3 {
+            publish(&hub, id, "a");
+        }
+        assert_eq!(slow.receive(Duration::ZERO), Received::Lagging);
+
+        // It stays closed, even once the events are gone
+        publish(&hub, 4, "a");
+        assert_eq!(slow.receive(Duration::ZERO), Received::Lagging);
+    }
+
+    #[test]
+    fn test_closed_subscriber_gets_its_events_first() {
+        let hub = Arc::new(EventHub::new(10));
+        let subscriber = hub.subscribe(None);
+        publish(&hub, 1, "a");
+        subscriber.close();
+        assert_eq!(ids(subscriber.receive(Duration::ZERO)), vec![1]);
+        assert_eq!(subscriber.receive(Duration::ZERO), Received::Closed);
+
+        // A waiting watch is woken
+        let waiting = hub.subscribe(None);
+        let closer = {
+            let waiting = waiting.clone();
+            std::thread::spawn(move || waiting.close())
+        };
+        assert_eq!(waiting.receive(Duration::from_secs(10)), Received::Closed);
+        closer.join().unwrap();
+    }
+
+    #[test]
+    fn test_receive_waits_for_events() {
+        let hub = Arc::new(EventHub::new(10));
+        let subscriber = hub.subscribe(None);
+        let publisher = {
+            let hub = hub.clone();
+            std::thread::spawn(move || publish(&hub, 1, "a"))
+        };
+        assert_eq!(ids(subscriber.receive(Duration::from_secs(10))), vec![1]);
+        publisher.join().unwrap();
+    }
+}
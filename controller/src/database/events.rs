@@ -0,0 +1,423 @@
+use crate::api::limits::limit_from_env;
+use crate::api::types::event::{Event, EventPage};
+use crate::database::event_hub::event_hub;
+use crate::database::metrics::timed;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, Result};
+use std::sync::OnceLock;
+
+/// Events in a page when not asked otherwise
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+/// Events in a page at most, whatever is asked
+pub const MAX_PAGE_SIZE: usize = 500;
+/// Events kept when not configured otherwise with `MAX_EVENTS`
+const DEFAULT_MAX_EVENTS: usize = 1000;
+
+/// Reasons of the events recorded whatever the type of the element
+pub const CREATED_REASON: &str = "Created";
+pub const DELETED_REASON: &str = "Deleted";
+
+/// Events kept, the older ones are pruned as events are recorded
+pub fn max_events() -> usize {
+    static MAX_EVENTS: OnceLock<usize> = OnceLock::new();
+    *MAX_EVENTS.get_or_init(|| limit_from_env("MAX_EVENTS", DEFAULT_MAX_EVENTS).max(1))
+}
+
+/// Where a list of events starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventCursor {
+    /// After the event with this id
+    After(i64),
+    /// At or after this date
+    Since(DateTime<Utc>),
+}
+
+impl EventCursor {
+    /// Parse an event id, or an RFC 3339 date
+    pub fn parse(value: &str) -> Option<EventCursor> {
+        if let Ok(id) = value.parse::<i64>() {
+            return Some(EventCursor::After(id));
+        }
+        DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|date| EventCursor::Since(date.with_timezone(&Utc)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EventQuery {
+    pub since: Option<EventCursor>,
+    pub element_id: Option<String>,
+    /// Only the events of the elements of this tenant
+    pub tenant_id: Option<String>,
+    /// Capped to `MAX_PAGE_SIZE`
+    pub limit: usize,
+}
+
+impl Default for EventQuery {
+    fn default() -> Self {
+        EventQuery {
+            since: None,
+            element_id: None,
+            tenant_id: None,
+            limit: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+/// Dates are stored with a fixed format so they compare as text
+pub fn format_date(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+pub struct EventRepository {}
+impl EventRepository {
+    /// Record an event, watches get it as well. Only the last `max_events`
+    /// events are kept.
+    ///
+    /// The event belongs to the tenant of its element, or of the previous
+    /// events of the element once it is deleted.
+    pub fn insert(
+        connection: &Connection,
+        element_id: &str,
+        reason: &str,
+        message: &str,
+    ) -> Result<i64> {
+        EventRepository::insert_keeping(connection, element_id, reason, message, max_events())
+    }
+
+    fn insert_keeping(
+        connection: &Connection,
+        element_id: &str,
+        reason: &str,
+        message: &str,
+        kept: usize,
+    ) -> Result<i64> {
+        let event = event_hub().publish_with(|| {
+            timed("insert_event", || {
+                let created_at = format_date(Utc::now());
+                let tenant_id: Option<String> = connection.query_row(
+                    "SELECT coalesce(
+                        (SELECT json_extract(value, '$.tenant_id') FROM cluster WHERE id = ?1),
+                        (SELECT tenant_id FROM events WHERE element_id = ?1 ORDER BY id DESC LIMIT 1)
+                    )",
+                    [element_id],
+                    |row| row.get(0),
+                )?;
+                connection.execute(
+                    "INSERT INTO events (created_at, element_id, tenant_id, reason, message)
+                    VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![created_at, element_id, tenant_id, reason, message],
+                )?;
+                let id = connection.last_insert_rowid();
+                connection.execute(
+                    "DELETE FROM events WHERE id <= ?1",
+                    [id - kept as i64],
+                )?;
+                Ok(Event {
+                    id,
+                    created_at,
+                    element_id: element_id.to_string(),
+                    tenant_id,
+                    reason: reason.to_string(),
+                    message: message.to_string(),
+                })
+            })
+        })?;
+        Ok(event.id)
+    }
+
+    /// Events a watch missed after the event `after`, None when the watch
+    /// cannot resume: more than a page was missed, some were pruned, or the
+    /// id is unknown
+    pub fn replay(
+        connection: &Connection,
+        after: i64,
+        element_id: Option<String>,
+        tenant_id: Option<String>,
+    ) -> Result<Option<EventPage>> {
+        let (missed, first, last): (i64, i64, i64) = timed("count_missed_events", || {
+            connection.query_row(
+                "SELECT (SELECT count(*) FROM events WHERE id > ?1),
+                    (SELECT ifnull(min(id), 0) FROM events),
+                    (SELECT ifnull(max(id), 0) FROM events)",
+                [after],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+        })?;
+        // Events between `after` and the first one kept were pruned
+        let pruned = first > 0 && after < first - 1;
+        if after < 0 || after > last || pruned || missed > MAX_PAGE_SIZE as i64 {
+            return Ok(None);
+        }
+        let query = EventQuery {
+            since: Some(EventCursor::After(after)),
+            element_id,
+            tenant_id,
+            limit: MAX_PAGE_SIZE,
+        };
+        EventRepository::list(connection, &query).map(Some)
+    }
+
+    /// Last `limit` events of an element with one of the given reasons, the
+    /// oldest first
+    pub fn latest(
+        connection: &Connection,
+        element_id: &str,
+        reasons: &[String],
+        limit: usize,
+    ) -> Result<Vec<Event>> {
+        timed("latest_events", || {
+            let mut stmt = connection.prepare_cached(
+                "SELECT id, created_at, element_id, tenant_id, reason, message FROM events
+                WHERE element_id = ?1 AND reason IN (SELECT value FROM json_each(?2))
+                ORDER BY id DESC LIMIT ?3",
+            )?;
+            let reasons = serde_json::to_string(reasons).unwrap();
+            let mut events = stmt
+                .query_map(params![element_id, reasons, limit as i64], |row| {
+                    Ok(Event {
+                        id: row.get(0)?,
+                        created_at: row.get(1)?,
+                        element_id: row.get(2)?,
+                        tenant_id: row.get(3)?,
+                        reason: row.get(4)?,
+                        message: row.get(5)?,
+                    })
+                })?
+                .collect::<Result<Vec<Event>>>()?;
+            events.reverse();
+            Ok(events)
+        })
+    }
+
+    /// Page of events in the order they happened.
+    ///
+    /// Pages follow the ids rather than an offset, so events added while
+    /// going through the pages are neither skipped nor seen twice.
+    pub fn list(connection: &Connection, query: &EventQuery) -> Result<EventPage> {
+        timed("list_events", || {
+            let (after, since) = match &query.since {
+                Some(EventCursor::After(id)) => (*id, None),
+                Some(EventCursor::Since(date)) => (0, Some(format_date(*date))),
+                None => (0, None),
+            };
+            let mut stmt = connection.prepare_cached(
+                "SELECT id, created_at, element_id, tenant_id, reason, message FROM events
+                WHERE id > ?1 AND (?2 IS NULL OR created_at >= ?2)
+                    AND (?3 IS NULL OR element_id = ?3)
+                    AND (?4 IS NULL OR tenant_id = ?4)
+                ORDER BY id LIMIT ?5",
+            )?;
+            let events = stmt
+                .query_map(
+                    params![
+                        after,
+                        since,
+                        query.element_id,
+                        query.tenant_id,
+                        query.limit.min(MAX_PAGE_SIZE) as i64
+                    ],
+                    |row| {
+                        Ok(Event {
+                            id: row.get(0)?,
+                            created_at: row.get(1)?,
+                            element_id: row.get(2)?,
+                            tenant_id: row.get(3)?,
+                            reason: row.get(4)?,
+                            message: row.get(5)?,
+                        })
+                    },
+                )?
+                .collect::<Result<Vec<Event>>>()?;
+
+            let next = match events.last() {
+                Some(event) => event.id,
+                // Later events get higher ids than any existing one
+                None if since.is_some() => {
+                    connection.query_row("SELECT ifnull(max(id), 0) FROM events", [], |row| {
+                        row.get(0)
+                    })?
+                }
+                None => after,
+            };
+            Ok(EventPage { events, next })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::RikDataBase;
+    use crate::tests::fixtures::db_connection;
+    use rstest::rstest;
+
+    #[rstest]
+    fn test_pages_follow_ids(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        for i in 0..5 {
+            EventRepository::insert(&connection, "instance", "Running", &format!("event {}", i))
+                .unwrap();
+        }
+
+        let mut query = EventQuery {
+            limit: 2,
+            ..Default::default()
+        };
+        let mut seen = vec![];
+        loop {
+            let page = EventRepository::list(&connection, &query).unwrap();
+            if page.events.is_empty() {
+                // The cursor is kept so the events can be polled
+                assert_eq!(page.next, *seen.last().unwrap());
+                break;
+            }
+            assert!(page.events.len() <= 2);
+            // Events added while going through the pages come last
+            EventRepository::insert(&connection, "instance", "Running", "added").unwrap();
+            seen.extend(page.events.iter().map(|event| event.id));
+            query.since = Some(EventCursor::After(page.next));
+            if seen.len() > 20 {
+                break;
+            }
+        }
+
+        let mut expected = seen.clone();
+        expected.sort_unstable();
+        expected.dedup();
+        assert_eq!(seen, expected);
+        // Each page adds an event, so the pages end once they outrun them
+        assert!(seen.len() >= 5);
+    }
+
+    #[rstest]
+    fn test_filter_and_page_size(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        for i in 0..MAX_PAGE_SIZE + 10 {
+            let element_id = if i % 2 == 0 { "even" } else { "odd" };
+            EventRepository::insert(&connection, element_id, "Pending", "").unwrap();
+        }
+
+        let page = EventRepository::list(
+            &connection,
+            &EventQuery {
+                limit: usize::MAX,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(page.events.len(), MAX_PAGE_SIZE);
+
+        let page = EventRepository::list(
+            &connection,
+            &EventQuery {
+                element_id: Some(String::from("odd")),
+                limit: 3,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let ids: Vec<i64> = page.events.iter().map(|event| event.id).collect();
+        assert_eq!(ids, vec![2, 4, 6]);
+    }
+
+    #[rstest]
+    fn test_latest_with_reasons(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        for reason in ["Creating", "Rebalanced", "Running", "Failed", "Running"] {
+            EventRepository::insert(&connection, "instance", reason, "").unwrap();
+        }
+        EventRepository::insert(&connection, "other", "Running", "").unwrap();
+
+        let reasons = vec![String::from("Running"), String::from("Failed")];
+        let events = EventRepository::latest(&connection, "instance", &reasons, 2).unwrap();
+        let ids: Vec<i64> = events.iter().map(|event| event.id).collect();
+        assert_eq!(ids, vec![4, 5]);
+        let events = EventRepository::latest(&connection, "instance", &reasons, 10).unwrap();
+        let reasons: Vec<&str> = events.iter().map(|event| event.reason.as_str()).collect();
+        assert_eq!(reasons, vec!["Running", "Failed", "Running"]);
+    }
+
+    #[rstest]
+    fn test_since_date(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        EventRepository::insert(&connection, "instance", "Pending", "").unwrap();
+
+        let future = EventQuery {
+            since: EventCursor::parse("2999-01-01T00:00:00Z"),
+            ..Default::default()
+        };
+        let page = EventRepository::list(&connection, &future).unwrap();
+        assert!(page.events.is_empty());
+        assert_eq!(page.next, 1);
+
+        let past = EventQuery {
+            since: EventCursor::parse("2020-01-01T00:00:00+02:00"),
+            ..Default::default()
+        };
+        assert_eq!(
+            EventRepository::list(&connection, &past)
+                .unwrap()
+                .events
+                .len(),
+            1
+        );
+        assert_eq!(EventCursor::parse("12"), Some(EventCursor::After(12)));
+        assert_eq!(EventCursor::parse("yesterday"), None);
+    }
+    #[rstest]
+    fn test_replay_recent_versions_only(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        for i in 0..MAX_PAGE_SIZE + 2 {
+            let element_id = if i % 2 == 0 { "even" } else { "odd" };
+            EventRepository::insert(&connection, element_id, "Pending", "").unwrap();
+        }
+        let last = (MAX_PAGE_SIZE + 2) as i64;
+
+        let page = EventRepository::replay(&connection, last - 4, Some(String::from("odd")), None)
+            .unwrap()
+            .unwrap();
+        let ids: Vec<i64> = page.events.iter().map(|event| event.id).collect();
+        assert_eq!(ids, vec![last - 2, last]);
+        let page = EventRepository::replay(&connection, last, None, None)
+            .unwrap()
+            .unwrap();
+        assert!(page.events.is_empty());
+
+        // Too old, or a version the controller never gave
+        assert_eq!(
+            EventRepository::replay(&connection, 1, None, None).unwrap(),
+            None
+        );
+        assert_eq!(
+            EventRepository::replay(&connection, last + 1, None, None).unwrap(),
+            None
+        );
+    }
+
+    #[rstest]
+    fn test_old_events_are_pruned(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        for _ in 0..5 {
+            EventRepository::insert_keeping(&connection, "instance", "Pending", "", 3).unwrap();
+        }
+        let page = EventRepository::list(&connection, &EventQuery::default()).unwrap();
+        let ids: Vec<i64> = page.events.iter().map(|event| event.id).collect();
+        assert_eq!(ids, vec![3, 4, 5]);
+
+        // The pruned events cannot be replayed
+        assert_eq!(
+            EventRepository::replay(&connection, 1, None, None).unwrap(),
+            None
+        );
+        assert_eq!(
+            EventRepository::replay(&connection, 2, None, None)
+                .unwrap()
+                .unwrap()
+                .events
+                .len(),
+            3
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use crate::database::RikDataBase;
+use rusqlite::ErrorCode;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{event, Level};
+
+/// Upper bounds of the query latency buckets, in milliseconds
+const LATENCY_BUCKETS_MS: [f64; 11] = [
+    0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0,
+];
+/// Interval between two samples of the database files and rows
+const STORAGE_SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations per bucket, the last one holds the observations above all bounds
+    buckets: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    count: u64,
+    sum_ms: f64,
+}
+
+/// Cumulative amount of observations up to a bound, `le` is `None` for the last one
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Bucket {
+    pub le: Option<f64>,
+    pub count: u64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    pub count: u64,
+    pub sum_ms: f64,
+    /// Upper bound of the bucket holding the 99th percentile, `None` without
+    /// observations or when above all bounds
+    pub p99_ms: Option<f64>,
+    pub buckets: Vec<Bucket>,
+}
+
+impl Histogram {
+    fn observe(&mut self, latency: Duration) {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| latency_ms <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum_ms += latency_ms;
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let bounds = LATENCY_BUCKETS_MS.iter().copied().map(Some).chain([None]);
+        let mut cumulative = 0;
+        let buckets: Vec<Bucket> = bounds
+            .zip(self.buckets.iter())
+            .map(|(le, count)| {
+                cumulative += count;
+                Bucket {
+                    le,
+                    count: cumulative,
+                }
+            })
+            .collect();
+
+        let p99_rank = (self.count * 99).div_ceil(100);
+        let p99_ms = buckets
+            .iter()
+            .find(|bucket| self.count > 0 && bucket.count >= p99_rank)
+            .and_then(|bucket| bucket.le);
+
+        HistogramSnapshot {
+            count: self.count,
+            sum_ms: self.sum_ms,
+            p99_ms,
+            buckets,
+        }
+    }
+}
+
+/// Last sample of the database files and content
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageSample {
+    pub file_size_bytes: u64,
+    pub wal_size_bytes: u64,
+    /// Rows per element type, e.g. `workload` or `instance`
+    pub rows_per_prefix: BTreeMap<String, u64>,
+    /// Schema version, as stored in the `user_version` pragma
+    pub migration_version: i64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DatabaseMetricsSnapshot {
+    pub queries: BTreeMap<&'static str, HistogramSnapshot>,
+    pub busy_errors: u64,
+    pub storage: Option<StorageSample>,
+}
+
+/// Query latencies and storage health of the controller database
+#[derive(Debug, Default)]
+pub struct DatabaseMetrics {
+    queries: Mutex<HashMap<&'static str, Histogram>>,
+    busy_errors: AtomicU64,
+    storage: Mutex<Option<StorageSample>>,
+}
+
+impl DatabaseMetrics {
+    fn observe<T>(&self, method: &'static str, latency: Duration, result: &rusqlite::Result<T>) {
+        self.queries
+            .lock()
+            .unwrap()
+            .entry(method)
+            .or_default()
+            .observe(latency);
+        if let Err(rusqlite::Error::SqliteFailure(error, _)) = result {
+            if matches!(
+                error.code,
+                ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked
+            ) {
+                self.busy_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> DatabaseMetricsSnapshot {
+        DatabaseMetricsSnapshot {
+            queries: self
+                .queries
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(method, histogram)| (*method, histogram.snapshot()))
+                .collect(),
+            busy_errors: self.busy_errors.load(Ordering::Relaxed),
+            storage: self.storage.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Metrics shared by every connection to the database
+pub fn database_metrics() -> &'static DatabaseMetrics {
+    static METRICS: OnceLock<DatabaseMetrics> = OnceLock::new();
+    METRICS.get_or_init(DatabaseMetrics::default)
+}
+
+/// Run a repository query, recording its latency under the method name
+pub fn timed<T>(
+    method: &'static str,
+    query: impl FnOnce() -> rusqlite::Result<T>,
+) -> rusqlite::Result<T> {
+    let start = Instant::now();
+    let result = query();
+    database_metrics().observe(method, start.elapsed(), &result);
+    result
+}
+
+/// Periodically sample the size of the database files and the rows it holds
+pub fn run_storage_sampler(database: Arc<RikDataBase>) {
+    thread::spawn(move || loop {
+        match database.sample_storage() {
+            Ok(sample) => *database_metrics().storage.lock().unwrap() = Some(sample),
+            Err(e) => event!(Level::WARN, "Could not sample database storage: {}", e),
+        }
+        thread::sleep(STORAGE_SAMPLE_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let mut histogram = Histogram::default();
+        for _ in 0..98 {
+            histogram.observe(Duration::from_micros(300));
+        }
+        histogram.observe(Duration::from_millis(20));
+        histogram.observe(Duration::from_secs(2));
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 100);
+        assert_eq!(snapshot.buckets[0].count, 98);
+        assert_eq!(snapshot.buckets[5].le, Some(25.0));
+        assert_eq!(snapshot.buckets[5].count, 99);
+        assert_eq!(snapshot.buckets.last().unwrap().count, 100);
+        assert_eq!(snapshot.p99_ms, Some(25.0));
+    }
+
+    #[test]
+    fn test_p99_above_all_bounds() {
+        let mut histogram = Histogram::default();
+        histogram.observe(Duration::from_secs(2));
+        assert_eq!(histogram.snapshot().p99_ms, None);
+        assert_eq!(Histogram::default().snapshot().p99_ms, None);
+    }
+}
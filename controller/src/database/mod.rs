@@ -1,10 +1,204 @@
+pub mod event_hub;
+pub mod events;
+pub mod metrics;
+pub mod pool;
+pub mod query;
+pub mod revisions;
+pub mod usage;
+pub mod workload_cache;
+
 use crate::api::types::element::Element;
+use crate::database::metrics::{timed, StorageSample};
+use crate::database::query::ElementQuery;
+use crate::database::revisions::change_notifier;
+use crate::database::workload_cache::workload_cache;
 
 use dotenv::dotenv;
-use rusqlite::{params, Connection, Result};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
+/// Schema changes, the version of the schema is the amount applied
+const MIGRATIONS: &[&str] = &[
+    // Fields of the instance summaries, extracted from the values when they are
+    // written so listing instances does not parse them. Stored columns cannot be
+    // added to a table, so it is rebuilt.
+    "CREATE TABLE cluster_v1 (
+        id              TEXT PRIMARY KEY,
+        name            TEXT NOT NULL,
+        value           BLOB NOT NULL,
+        namespace       TEXT GENERATED ALWAYS AS (json_extract(value, '$.namespace')) STORED,
+        workload_id     TEXT GENERATED ALWAYS AS (json_extract(value, '$.workload_id')) STORED,
+        kind            TEXT GENERATED ALWAYS AS (json_extract(value, '$.kind')) STORED,
+        status          TEXT GENERATED ALWAYS AS (json_extract(value, '$.status')) STORED,
+        node            TEXT GENERATED ALWAYS AS (json_extract(value, '$.worker_id')) STORED,
+        created_at      TEXT GENERATED ALWAYS AS (json_extract(value, '$.created_at')) STORED,
+        overrides       TEXT GENERATED ALWAYS AS (json_extract(value, '$.overrides')) STORED,
+        conditions      TEXT GENERATED ALWAYS AS (json_extract(value, '$.conditions')) STORED
+    );
+    INSERT INTO cluster_v1 (id, name, value) SELECT id, name, value FROM cluster;
+    DROP TABLE cluster;
+    ALTER TABLE cluster_v1 RENAME TO cluster;
+    CREATE INDEX cluster_name_index ON cluster (name);
+    CREATE INDEX cluster_name_id_index ON cluster (name,id);",
+    // Events get increasing ids, never reused, which order them and serve as
+    // cursors that stay valid while events are added
+    "CREATE TABLE events (
+        id              INTEGER PRIMARY KEY AUTOINCREMENT,
+        created_at      TEXT NOT NULL,
+        element_id      TEXT NOT NULL,
+        reason          TEXT NOT NULL,
+        message         TEXT NOT NULL
+    );
+    CREATE INDEX events_element_index ON events (element_id, id);",
+    // Ledger of the instances, kept after they are deleted so their usage can
+    // be rolled up. The triggers keep it up to date whichever way instances
+    // are written, instance ids can be reused so rows get their own id.
+    "CREATE TABLE instance_usage (
+        id              INTEGER PRIMARY KEY AUTOINCREMENT,
+        instance_id     TEXT NOT NULL,
+        name            TEXT NOT NULL,
+        workload_id     TEXT,
+        created_at      TEXT,
+        terminated_at   TEXT
+    );
+    CREATE INDEX instance_usage_instance_index ON instance_usage (instance_id);
+    INSERT INTO instance_usage (instance_id, name, workload_id, created_at)
+        SELECT id, name, workload_id, created_at FROM cluster WHERE name LIKE '/instance/%';
+    CREATE TRIGGER instance_usage_insert AFTER INSERT ON cluster
+    WHEN NEW.name LIKE '/instance/%'
+    BEGIN
+        INSERT INTO instance_usage (instance_id, name, workload_id, created_at)
+        VALUES (NEW.id, NEW.name, NEW.workload_id, NEW.created_at);
+    END;
+    CREATE TRIGGER instance_usage_delete AFTER DELETE ON cluster
+    WHEN OLD.name LIKE '/instance/%'
+    BEGIN
+        UPDATE instance_usage SET terminated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        WHERE instance_id = OLD.id AND terminated_at IS NULL;
+    END;",
+    // Tenants are told apart by their name, two of them cannot share one. Other elements
+    // may, such as the maintenance windows of a node. Duplicates created before
+    // are dropped, the first one inserted is kept.
+    "DELETE FROM cluster WHERE name LIKE '/tenant%' AND rowid NOT IN (
+        SELECT MIN(rowid) FROM cluster WHERE name LIKE '/tenant%' GROUP BY name
+    );
+    CREATE UNIQUE INDEX cluster_tenant_name_index ON cluster (name) WHERE name LIKE '/tenant%';",
+    // Every change of an element gets the next revision, the deletions are kept
+    // in `revisions` for the watches to see them. Revisions are allocated by the
+    // ids of `revisions`, never reused, the other rows are dropped right away.
+    // The deletions of the last 10000 revisions are kept, see `REVISION_HISTORY`.
+    "ALTER TABLE cluster ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+    CREATE INDEX cluster_revision_index ON cluster (revision);
+    CREATE TABLE revisions (
+        id              INTEGER PRIMARY KEY AUTOINCREMENT,
+        element_id      TEXT NOT NULL,
+        name            TEXT NOT NULL,
+        deleted         INTEGER NOT NULL
+    );
+    INSERT INTO revisions (element_id, name, deleted) SELECT id, name, 0 FROM cluster;
+    UPDATE cluster SET revision = (SELECT id FROM revisions WHERE element_id = cluster.id);
+    DELETE FROM revisions;
+    CREATE TRIGGER revision_insert AFTER INSERT ON cluster
+    BEGIN
+        INSERT INTO revisions (element_id, name, deleted) VALUES (NEW.id, NEW.name, 0);
+        UPDATE cluster SET revision = (SELECT max(id) FROM revisions) WHERE id = NEW.id;
+        DELETE FROM revisions WHERE deleted = 0;
+    END;
+    CREATE TRIGGER revision_update AFTER UPDATE OF name, value ON cluster
+    BEGIN
+        INSERT INTO revisions (element_id, name, deleted) VALUES (NEW.id, NEW.name, 0);
+        UPDATE cluster SET revision = (SELECT max(id) FROM revisions) WHERE id = NEW.id;
+        DELETE FROM revisions WHERE deleted = 0;
+    END;
+    CREATE TRIGGER revision_delete AFTER DELETE ON cluster
+    BEGIN
+        INSERT INTO revisions (element_id, name, deleted) VALUES (OLD.id, OLD.name, 1);
+        DELETE FROM revisions WHERE id <= (SELECT max(id) FROM revisions) - 10000;
+    END;",
+    // Dates the elements were stored and last changed, `created_at` being the
+    // generated column of the instance creation dates. Elements stored before
+    // get the date of the migration.
+    "ALTER TABLE cluster ADD COLUMN inserted_at TEXT;
+    ALTER TABLE cluster ADD COLUMN updated_at TEXT;
+    UPDATE cluster SET inserted_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+        updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now');",
+    // Version of each element, from 1 and incremented by every update, so a
+    // client can only write an element as it read it
+    "ALTER TABLE cluster ADD COLUMN version INTEGER NOT NULL DEFAULT 1;",
+    // Tenant of the element of each event, kept once the element is deleted so
+    // the keys of a tenant are only shown the events of its elements
+    "ALTER TABLE events ADD COLUMN tenant_id TEXT;
+    UPDATE events SET tenant_id = (
+        SELECT json_extract(value, '$.tenant_id') FROM cluster WHERE id = events.element_id
+    );",
+];
+/// Time a connection waits for the database to be unlocked by another one,
+/// such as the one of another API thread
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Version of the schema, stored in the `user_version` pragma
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+/// Insertion of an element, its id being given
+const INSERT_ELEMENT: &str = "INSERT INTO cluster (id, name, value, inserted_at, updated_at)
+    VALUES (?1, ?2, ?3, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+        strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))";
+
+thread_local! {
+    /// Elements written in the transaction of `RikRepository::transaction`
+    /// open on the current thread, None when there is none
+    static PENDING_WRITES: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Invalidate the cached definition of the element written, if any, and wake
+/// the watches. Both wait for the transaction of `RikRepository::transaction`
+/// writing it to commit, until then readers would cache the element again or
+/// be woken for nothing.
+fn written(connection: &Connection, id: Option<&str>) {
+    let deferred = !connection.is_autocommit()
+        && PENDING_WRITES.with(|pending| match pending.borrow_mut().as_mut() {
+            Some(written) => {
+                written.extend(id.map(str::to_string));
+                true
+            }
+            None => false,
+        });
+    if !deferred {
+        if let Some(id) = id {
+            workload_cache().invalidate(id);
+        }
+        change_notifier().notify();
+    }
+}
+
+/// Writes of a transaction, the cached definitions of the elements written
+/// are invalidated once it ends, whether it commits, is rolled back or panics
+struct DeferredWrites {
+    /// Writes of a transaction of another connection, open meanwhile
+    previous: Option<Vec<String>>,
+}
+
+impl DeferredWrites {
+    fn start() -> DeferredWrites {
+        DeferredWrites {
+            previous: PENDING_WRITES.with(|pending| pending.replace(Some(Vec::new()))),
+        }
+    }
+}
+
+impl Drop for DeferredWrites {
+    fn drop(&mut self) {
+        let written = PENDING_WRITES.with(|pending| pending.replace(self.previous.take()));
+        for id in written.unwrap_or_default() {
+            workload_cache().invalidate(&id);
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub struct RikDataBase {
     name: String,
@@ -28,93 +222,460 @@ impl RikDataBase {
             CREATE INDEX IF NOT EXISTS cluster_name_index ON cluster (name);
             CREATE INDEX IF NOT EXISTS cluster_name_id_index ON cluster (name,id);",
         )?;
+        Self::migrate(&connection)
+    }
+
+    /// Bring the schema up to `SCHEMA_VERSION`, as recorded in `user_version`
+    fn migrate(connection: &Connection) -> Result<()> {
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version >= SCHEMA_VERSION {
+            return Ok(());
+        }
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            connection.execute_batch(&format!(
+                "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+                migration,
+                index + 1
+            ))?;
+        }
         Ok(())
     }
 
     pub fn drop_tables(&self) {}
 
-    pub fn open(&self) -> Result<Connection> {
+    /// Path of the database file, its directory is created when missing
+    pub fn path(&self) -> String {
         dotenv().ok();
         let file_path =
             std::env::var("DATABASE_LOCATION").unwrap_or("/var/lib/rik/data/".to_string());
         std::fs::create_dir_all(&file_path).unwrap();
 
-        let database_path = format!("{}{}.db", file_path, self.name);
-        Connection::open(database_path)
+        format!("{}{}.db", file_path, self.name)
+    }
+
+    pub fn open(&self) -> Result<Connection> {
+        let connection = Connection::open(self.path())?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(connection)
+    }
+
+    /// Size of the database files, rows per element type and schema version
+    pub fn sample_storage(&self) -> Result<StorageSample> {
+        let path = self.path();
+        let file_size = |path: &str| std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+
+        let connection = self.open()?;
+        let mut stmt = connection.prepare("SELECT name FROM cluster")?;
+        let mut rows_per_prefix: BTreeMap<String, u64> = BTreeMap::new();
+        for name in stmt.query_map([], |row| row.get::<_, String>(0))? {
+            let name = name?;
+            let prefix = name.trim_start_matches('/').split('/').next().unwrap_or("");
+            *rows_per_prefix.entry(prefix.to_string()).or_default() += 1;
+        }
+        let migration_version =
+            connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+        Ok(StorageSample {
+            file_size_bytes: file_size(&path),
+            wal_size_bytes: file_size(&format!("{}-wal", path)),
+            rows_per_prefix,
+            migration_version,
+        })
+    }
+}
+
+/// An element was not at the version it was expected to be written at
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct VersionConflict {
+    pub id: String,
+    pub expected: i64,
+    /// Version the element is at, none when it was deleted
+    pub current: Option<i64>,
+}
+
+#[derive(Debug)]
+pub enum UpdateError {
+    Conflict(VersionConflict),
+    Database(rusqlite::Error),
+}
+
+impl From<rusqlite::Error> for UpdateError {
+    fn from(e: rusqlite::Error) -> UpdateError {
+        UpdateError::Database(e)
     }
 }
 
 pub struct RikRepository {}
 impl RikRepository {
     pub fn insert(connection: &Connection, name: &str, value: &str) -> Result<String> {
-        let id = Uuid::new_v4().to_string();
-        connection
-            .execute(
-                "INSERT INTO cluster (id, name, value) VALUES (?1, ?2, ?3)",
-                params![id, name, value],
-            )
-            .unwrap();
-        Ok(id)
+        timed("insert", || {
+            let id = Uuid::new_v4().to_string();
+            connection
+                .prepare_cached(INSERT_ELEMENT)?
+                .execute(params![id, name, value])?;
+            written(connection, None);
+            Ok(id)
+        })
+    }
+
+    /// Insert `(name, value)` elements with a single statement, all of them
+    /// or none, giving their ids in the same order. They are inserted in the
+    /// transaction of the connection if it is in one, in their own otherwise.
+    pub fn insert_many(connection: &Connection, items: &[(String, String)]) -> Result<Vec<String>> {
+        timed("insert_many", || {
+            let insert = |connection: &Connection| -> Result<Vec<String>> {
+                let mut stmt = connection.prepare_cached(INSERT_ELEMENT)?;
+                items
+                    .iter()
+                    .map(|(name, value)| {
+                        let id = Uuid::new_v4().to_string();
+                        stmt.execute(params![id, name, value])?;
+                        Ok(id)
+                    })
+                    .collect()
+            };
+            let ids = if connection.is_autocommit() {
+                let transaction = connection.unchecked_transaction()?;
+                let ids = insert(&transaction)?;
+                transaction.commit()?;
+                ids
+            } else {
+                insert(connection)?
+            };
+            written(connection, None);
+            Ok(ids)
+        })
     }
 
     pub fn delete(connection: &Connection, id: &String) -> Result<()> {
-        connection.execute("DELETE FROM cluster WHERE id = (?1)", params![id])?;
-        Ok(())
+        timed("delete", || {
+            connection
+                .prepare_cached("DELETE FROM cluster WHERE id = ?1")?
+                .execute(params![id])?;
+            written(connection, Some(id));
+            Ok(())
+        })
     }
 
     pub fn find_one(connection: &Connection, id: &String, element_type: &str) -> Result<Element> {
-        let mut stmt = connection.prepare(&format!(
-            "SELECT id, name, value FROM cluster WHERE id = '{}' AND name LIKE '{}%'",
-            id, element_type
-        ))?;
-        match stmt.query_row([], |row| {
-            Ok(Element::new(row.get(0)?, row.get(1)?, row.get(2)?))
-        }) {
-            Ok(element) => Ok(element),
-            Err(err) => Err(err),
-        }
+        timed("find_one", || {
+            let mut stmt = connection.prepare_cached(
+                "SELECT id, name, value, inserted_at, updated_at, version FROM cluster
+                WHERE id = ?1 AND name LIKE ?2 || '%'",
+            )?;
+            stmt.query_row(params![id, element_type], Element::from_row)
+        })
+    }
+
+    /// Element of exactly that name, unlike `check_duplicate_name` which
+    /// matches the names starting with it
+    pub fn find_by_name(connection: &Connection, name: &str) -> Result<Element> {
+        timed("find_by_name", || {
+            let mut stmt = connection.prepare_cached(
+                "SELECT id, name, value, inserted_at, updated_at, version FROM cluster
+                WHERE name = ?1 ORDER BY rowid LIMIT 1",
+            )?;
+            stmt.query_row(params![name], Element::from_row)
+        })
+    }
+
+    /// Every element of exactly that name, in the order they were inserted.
+    /// Names are unique for most types, this also finds the duplicates
+    /// stored before they were.
+    pub fn find_all_by_name(connection: &Connection, name: &str) -> Result<Vec<Element>> {
+        timed("find_all_by_name", || {
+            let mut stmt = connection.prepare_cached(
+                "SELECT id, name, value, inserted_at, updated_at, version FROM cluster
+                WHERE name = ?1 ORDER BY rowid",
+            )?;
+            let elements = stmt.query_map(params![name], Element::from_row)?;
+            elements.collect()
+        })
     }
 
     pub fn check_duplicate_name(connection: &Connection, name: &str) -> Result<Element> {
-        let mut stmt = connection.prepare(&format!(
-            "SELECT id, name, value FROM cluster WHERE name LIKE '{}%'",
-            name
-        ))?;
-        match stmt.query_row([], |row| {
-            Ok(Element::new(row.get(0)?, row.get(1)?, row.get(2)?))
-        }) {
-            Ok(element) => Ok(element),
-            Err(err) => Err(err),
-        }
+        timed("check_duplicate_name", || {
+            let mut stmt = connection.prepare_cached(
+                "SELECT id, name, value, inserted_at, updated_at, version FROM cluster
+                WHERE name LIKE ?1 || '%'",
+            )?;
+            stmt.query_row(params![name], Element::from_row)
+        })
+    }
+
+    /// Page of the elements selected by `query`, in its order, along with the
+    /// amount of elements it selects. Only the page is read, the count goes
+    /// through the same filters.
+    pub fn find_page(
+        connection: &Connection,
+        query: &ElementQuery,
+        limit: usize,
+        offset: usize,
+    ) -> Result<(Vec<Element>, usize)> {
+        timed("find_page", || {
+            let (source, mut values) = query.source();
+            let (order, order_values) = query.order();
+            values.extend(order_values);
+            values.extend([sql_integer(limit).into(), sql_integer(offset).into()]);
+            let mut stmt = connection.prepare_cached(&format!(
+                "SELECT id, name, value, inserted_at, updated_at, version FROM {}
+                ORDER BY {} LIMIT ? OFFSET ?",
+                source, order
+            ))?;
+            let elements = stmt
+                .query_map(params_from_iter(values), Element::from_row)?
+                .collect::<Result<Vec<Element>>>()?;
+            Ok((elements, RikRepository::count_matching(connection, query)?))
+        })
+    }
+
+    /// Amount of elements selected by `query`
+    pub fn count_matching(connection: &Connection, query: &ElementQuery) -> Result<usize> {
+        timed("count_matching", || {
+            let (source, values) = query.source();
+            connection
+                .prepare_cached(&format!("SELECT COUNT(*) FROM {}", source))?
+                .query_row(params_from_iter(values), |row| row.get(0))
+        })
+    }
+
+    /// Elements of a type whose value has a top-level `field` equal to `value`,
+    /// such as the instances of a workload
+    pub fn find_by_value_field(
+        connection: &Connection,
+        element_type: &str,
+        field: &str,
+        value: &str,
+    ) -> Result<Vec<Element>> {
+        timed("find_by_value_field", || {
+            let mut stmt = connection.prepare_cached(
+                "SELECT id, name, value, inserted_at, updated_at, version FROM cluster
+                WHERE name LIKE ?1 || '%'
+                AND json_extract(value, '$.' || ?2) = ?3 ORDER BY rowid",
+            )?;
+            let elements =
+                stmt.query_map(params![element_type, field, value], Element::from_row)?;
+            elements.collect()
+        })
+    }
+
+    /// Ids and full names of the elements of a type in a namespace whose name
+    /// contains `query`, ignoring the case. Names starting with it come
+    /// first, then the shortest ones.
+    ///
+    /// Only the index of the names is read, the values are never loaded.
+    pub fn search_names(
+        connection: &Connection,
+        element_type: &str,
+        namespace: &str,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>> {
+        timed("search_names", || {
+            // The index of the names alone is the smallest to go through,
+            // the ids are only read for the names matched
+            let mut stmt = connection.prepare_cached(
+                "SELECT id, name FROM cluster INDEXED BY cluster_name_index
+                WHERE name >= ?1 AND name < ?2 AND name LIKE ?3 ESCAPE '\\'
+                ORDER BY name NOT LIKE ?4 ESCAPE '\\', length(name), name
+                LIMIT ?5",
+            )?;
+            // Above every name of the type
+            let end = format!("{}\u{10ffff}", element_type);
+            let parent = format!("{}%/{}/", escape_like(element_type), escape_like(namespace));
+            let query = escape_like(query);
+            let names = stmt.query_map(
+                params![
+                    element_type,
+                    end,
+                    format!("{}%{}%", parent, query),
+                    format!("{}{}%", parent, query),
+                    sql_integer(limit)
+                ],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            names.collect()
+        })
+    }
+
+    /// Amount of elements of a type
+    pub fn count(connection: &Connection, element_type: &str) -> Result<usize> {
+        connection
+            .prepare_cached("SELECT COUNT(*) FROM cluster WHERE name LIKE ?1 || '%'")?
+            .query_row([element_type], |row| row.get(0))
     }
 
-    // TODO: add pagination
+    /// Amount of elements whose name starts with `prefix`, taken as is where
+    /// `count` lets `_` and `%` match any character, such as the workloads of
+    /// a tenant with `/workload/{tenant}/`
+    pub fn count_with_prefix(connection: &Connection, prefix: &str) -> Result<usize> {
+        timed("count_with_prefix", || {
+            connection
+                .prepare_cached(
+                    "SELECT COUNT(*) FROM cluster WHERE substr(name, 1, length(?1)) = ?1",
+                )?
+                .query_row([prefix], |row| row.get(0))
+        })
+    }
+
+    /// Elements of a type, in the order they were inserted as the pages of
+    /// `find_page` when they are not sorted, whatever was deleted in between
     pub fn find_all(connection: &Connection, element_type: &str) -> Result<Vec<Element>> {
-        let mut stmt = connection
-            .prepare(&format!(
-                "SELECT id, name, value FROM cluster WHERE name LIKE '{}%'",
-                element_type
-            ))
-            .unwrap();
-        let elements_iter = stmt
-            .query_map([], |row| {
-                Ok(Element::new(row.get(0)?, row.get(1)?, row.get(2)?))
-            })
-            .unwrap();
+        timed("find_all", || {
+            let mut stmt = connection.prepare_cached(
+                "SELECT id, name, value, inserted_at, updated_at, version FROM cluster
+                WHERE name LIKE ?1 || '%' ORDER BY rowid",
+            )?;
+            let elements = stmt.query_map([element_type], Element::from_row)?;
+            elements.collect()
+        })
+    }
 
-        let mut elements: Vec<Element> = Vec::new();
-        for element in elements_iter {
-            elements.push(element?);
-        }
-        Ok(elements)
+    /// Summaries of the instances, read from the generated columns so the
+    /// values are not parsed. The conditions are only read when asked for,
+    /// the correlation id is extracted from the value.
+    ///
+    /// Only `limit` summaries of the instances selected by `query` are read
+    /// after the first `offset` ones, in its order.
+    pub fn find_instance_summaries(
+        connection: &Connection,
+        query: &ElementQuery,
+        with_conditions: bool,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Element>> {
+        timed("find_instance_summaries", || {
+            let (source, source_values) = query.source();
+            let (order, order_values) = query.order();
+            let mut stmt = connection.prepare_cached(&format!(
+                "SELECT id, name,
+                    namespace, workload_id, kind, status, node, created_at, overrides,
+                    iif(?, conditions, NULL), json_extract(value, '$.correlation_id'),
+                    json_extract(value, '$.labels'), inserted_at, updated_at, version
+                FROM {}
+                ORDER BY {} LIMIT ? OFFSET ?",
+                source, order
+            ))?;
+            let mut values = vec![with_conditions.into()];
+            values.extend(source_values);
+            values.extend(order_values);
+            values.extend([sql_integer(limit).into(), sql_integer(offset).into()]);
+            let summaries = stmt.query_map(params_from_iter(values), |row| {
+                let mut value = serde_json::Map::new();
+                for (index, field) in ["namespace", "workload_id", "kind", "status"]
+                    .into_iter()
+                    .enumerate()
+                {
+                    let text: Option<String> = row.get(index + 2)?;
+                    value.insert(field.to_string(), text.into());
+                }
+                let optional: [(&str, Option<String>); 3] = [
+                    ("created_at", row.get(7)?),
+                    ("worker_id", row.get(6)?),
+                    ("correlation_id", row.get(10)?),
+                ];
+                for (field, text) in optional {
+                    if let Some(text) = text {
+                        value.insert(field.to_string(), text.into());
+                    }
+                }
+                // Small JSON documents, unlike the spec left in the value
+                for (index, field) in [(8, "overrides"), (9, "conditions"), (11, "labels")] {
+                    let json: Option<String> = row.get(index)?;
+                    if let Some(parsed) = json.and_then(|json| serde_json::from_str(&json).ok()) {
+                        value.insert(field.to_string(), parsed);
+                    }
+                }
+                Ok(Element {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    value: value.into(),
+                    path: Default::default(),
+                    created_at: row.get(12)?,
+                    updated_at: row.get(13)?,
+                    version: row.get(14)?,
+                })
+            })?;
+            summaries.collect()
+        })
     }
 
     pub fn update(connection: &Connection, id: &String, value: &String) -> Result<()> {
-        connection.execute(
-            "UPDATE cluster SET value=(?1) WHERE id = (?2)",
-            params![value, id],
-        )?;
-        Ok(())
+        timed("update", || {
+            connection
+                .prepare_cached(
+                    "UPDATE cluster SET value = ?1, version = version + 1,
+                    updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+                    WHERE id = ?2",
+                )?
+                .execute(params![value, id])?;
+            written(connection, Some(id));
+            Ok(())
+        })
+    }
+
+    /// Same as `update`, only when the element is still at the version it
+    /// was read at, giving its new version. The element is otherwise left as
+    /// is, and the version it is at is given in the conflict.
+    pub fn update_if_version(
+        connection: &Connection,
+        id: &str,
+        value: &str,
+        expected_version: i64,
+    ) -> std::result::Result<i64, UpdateError> {
+        let updated = timed("update_if_version", || {
+            let version = connection
+                .prepare_cached(
+                    "UPDATE cluster SET value = ?1, version = version + 1,
+                    updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+                    WHERE id = ?2 AND version = ?3 RETURNING version",
+                )?
+                .query_row(params![value, id, expected_version], |row| row.get(0))
+                .optional()?;
+            if let Some(version) = version {
+                return Ok(Ok(version));
+            }
+            let current = connection
+                .prepare_cached("SELECT version FROM cluster WHERE id = ?1")?
+                .query_row(params![id], |row| row.get(0))
+                .optional()?;
+            Ok(Err(VersionConflict {
+                id: id.to_string(),
+                expected: expected_version,
+                current,
+            }))
+        });
+        let version = updated?.map_err(UpdateError::Conflict)?;
+        written(connection, Some(id));
+        Ok(version)
+    }
+
+    /// Run `f` in a transaction, committed when it succeeds and rolled back
+    /// otherwise, so either every write of `f` is kept or none is.
+    ///
+    /// When the connection is already in a transaction `f` runs in it, its
+    /// writes are committed or rolled back along with those of the caller.
+    ///
+    /// The cached definitions of the elements written are invalidated, and
+    /// the watches woken, once the outermost transaction commits.
+    pub fn transaction<T, E>(
+        connection: &Connection,
+        f: impl FnOnce(&Connection) -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E>
+    where
+        E: From<rusqlite::Error>,
+    {
+        if !connection.is_autocommit() {
+            return f(connection);
+        }
+        // Ended after the transaction, so nothing is cached from it
+        let deferred = DeferredWrites::start();
+        let transaction = connection.unchecked_transaction()?;
+        let result = f(&transaction)?;
+        transaction.commit()?;
+        drop(deferred);
+        change_notifier().notify();
+        Ok(result)
     }
 
     pub fn upsert(
@@ -124,26 +685,46 @@ impl RikRepository {
         value: &String,
         element_type: &str,
     ) -> Result<String> {
-        if RikRepository::find_one(connection, id, element_type).is_ok() {
-            RikRepository::update(connection, id, value)?;
-            Ok(id.to_string())
-        } else {
-            connection
-                .execute(
-                    "INSERT INTO cluster (id, name, value) VALUES (?1, ?2, ?3)",
-                    params![id, name, value],
-                )
-                .unwrap();
-            Ok(id.to_string())
-        }
+        timed("upsert", || {
+            if RikRepository::find_one(connection, id, element_type).is_ok() {
+                RikRepository::update(connection, id, value)?;
+                Ok(id.to_string())
+            } else {
+                connection
+                    .prepare_cached(INSERT_ELEMENT)?
+                    .execute(params![id, name, value])?;
+                written(connection, None);
+                Ok(id.to_string())
+            }
+        })
     }
 }
 
+/// Match a value as is in a `LIKE` pattern escaped by `\`
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// SQLite integers are signed, larger values are clamped, which leaves
+/// `usize::MAX` as no limit
+fn sql_integer(value: usize) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
 #[cfg(test)]
 mod test {
-    use crate::database::{RikDataBase, RikRepository};
+    use crate::database::query::ElementQuery;
+    use crate::database::revisions::RevisionRepository;
+    use crate::database::{
+        RikDataBase, RikRepository, UpdateError, VersionConflict, SCHEMA_VERSION,
+    };
     use crate::tests::fixtures::db_connection;
     use rstest::rstest;
+    use rusqlite::params;
+    use std::time::Duration;
     use uuid::Uuid;
 
     #[rstest]
@@ -194,6 +775,392 @@ mod test {
         assert_eq!(elements.len(), 2);
     }
 
+    #[rstest]
+    fn test_find_page(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        connection.execute("DELETE FROM cluster", []).unwrap();
+        let ids: Vec<String> = (0..5)
+            .map(|index| {
+                let name = format!("/workload/pods/default/web-{}", index);
+                RikRepository::insert(&connection, &name, "{}").unwrap()
+            })
+            .collect();
+        RikRepository::insert(&connection, "/tenant/acme", "{}").unwrap();
+        let workloads = ElementQuery::of_type("/workload");
+
+        let (page, total) = RikRepository::find_page(&connection, &workloads, 2, 1).unwrap();
+        let page_ids: Vec<String> = page.into_iter().map(|element| element.id).collect();
+        assert_eq!(page_ids, ids[1..3]);
+        assert_eq!(total, 5);
+
+        let (page, total) =
+            RikRepository::find_page(&connection, &workloads, usize::MAX, 4).unwrap();
+        assert_eq!((page.len(), total), (1, 5));
+        let (page, _) = RikRepository::find_page(&connection, &workloads, 10, 10).unwrap();
+        assert!(page.is_empty());
+    }
+
+    #[rstest]
+    fn test_find_page_filters(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        connection.execute("DELETE FROM cluster", []).unwrap();
+        for (name, value) in [
+            (
+                "/workload/Pod/default/web",
+                r#"{"labels": {"tier": "front"}}"#,
+            ),
+            (
+                "/workload/acme/Pod/default/api",
+                r#"{"labels": {"tier": 1}}"#,
+            ),
+            // Quotes and backslashes are kept in the segments of the names
+            ("/workload/Function/prod/\"web\\", r#"{"replicas": 2}"#),
+            ("/workload/Pod/Pod/default", r#"{"replicas": 1}"#),
+        ] {
+            RikRepository::insert(&connection, name, value).unwrap();
+        }
+        let names = |query: ElementQuery| {
+            let (page, total) = RikRepository::find_page(&connection, &query, 1, 0).unwrap();
+            let all = RikRepository::find_page(&connection, &query, usize::MAX, 0)
+                .unwrap()
+                .0;
+            assert_eq!(total, all.len());
+            assert_eq!(
+                total,
+                RikRepository::count_matching(&connection, &query).unwrap()
+            );
+            assert!(page.len() <= 1);
+            all.into_iter()
+                .map(|element| element.name)
+                .collect::<Vec<String>>()
+        };
+        let query = |query: ElementQuery| ElementQuery {
+            element_type: String::from("/workload/"),
+            ..query
+        };
+
+        assert_eq!(
+            names(query(ElementQuery {
+                namespace: Some(String::from("default")),
+                ..Default::default()
+            })),
+            [
+                "/workload/Pod/default/web",
+                "/workload/acme/Pod/default/api"
+            ]
+        );
+        assert_eq!(
+            names(query(ElementQuery {
+                kind: Some(String::from("pod")),
+                name: Some(String::from("de")),
+                ..Default::default()
+            })),
+            ["/workload/Pod/Pod/default"]
+        );
+        assert_eq!(
+            names(query(ElementQuery {
+                name: Some(String::from("\"web")),
+                ..Default::default()
+            })),
+            ["/workload/Function/prod/\"web\\"]
+        );
+        assert_eq!(
+            names(query(ElementQuery {
+                tenant: Some(String::from("acme")),
+                ..Default::default()
+            })),
+            ["/workload/acme/Pod/default/api"]
+        );
+        // Only labels given as strings are selected
+        assert_eq!(
+            names(query(ElementQuery {
+                labels: vec![(String::from("tier"), String::from("front"))],
+                ..Default::default()
+            })),
+            ["/workload/Pod/default/web"]
+        );
+        assert!(names(query(ElementQuery {
+            labels: vec![(String::from("tier"), String::from("1"))],
+            ..Default::default()
+        }))
+        .is_empty());
+        // Workloads without replicas come last in both directions, in the
+        // order they were inserted
+        let sorted = |descending: bool| {
+            names(query(ElementQuery {
+                sort: Some(("/value/replicas", descending)),
+                ..Default::default()
+            }))
+        };
+        let unsorted = [
+            "/workload/Pod/default/web",
+            "/workload/acme/Pod/default/api",
+        ];
+        assert_eq!(
+            sorted(false)[..2],
+            [
+                "/workload/Pod/Pod/default",
+                "/workload/Function/prod/\"web\\"
+            ]
+        );
+        assert_eq!(
+            sorted(true)[..2],
+            [
+                "/workload/Function/prod/\"web\\",
+                "/workload/Pod/Pod/default"
+            ]
+        );
+        assert_eq!(sorted(false)[2..], unsorted);
+        assert_eq!(sorted(true)[2..], unsorted);
+    }
+
+    #[rstest]
+    fn test_transaction(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let failed: rusqlite::Result<()> = RikRepository::transaction(&connection, |tx| {
+            RikRepository::insert(tx, "/workload/Pod/lab/a", "{}")?;
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        });
+        assert!(failed.is_err());
+        assert_eq!(RikRepository::count(&connection, "/workload").unwrap(), 0);
+
+        let ids = RikRepository::transaction(&connection, |tx| {
+            Ok::<_, rusqlite::Error>(vec![
+                RikRepository::insert(tx, "/workload/Pod/lab/a", "{}")?,
+                RikRepository::insert(tx, "/workload/Pod/lab/b", "{}")?,
+            ])
+        })
+        .unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(RikRepository::count(&connection, "/workload").unwrap(), 2);
+
+        // An insert failing midway rolls back those before it
+        connection
+            .execute_batch(
+                "CREATE TEMP TRIGGER fail_insert BEFORE INSERT ON cluster
+                WHEN NEW.name = '/workload/Pod/lab/d'
+                BEGIN SELECT RAISE(ABORT, 'injected failure'); END;",
+            )
+            .unwrap();
+        let failed = RikRepository::transaction(&connection, |tx| {
+            RikRepository::insert(tx, "/workload/Pod/lab/c", "{}")?;
+            RikRepository::insert(tx, "/workload/Pod/lab/d", "{}")
+        });
+        assert!(failed.is_err());
+        assert!(connection.is_autocommit());
+        assert_eq!(RikRepository::count(&connection, "/workload").unwrap(), 2);
+
+        // A nested transaction is rolled back along with the one of the caller
+        let failed: rusqlite::Result<()> = RikRepository::transaction(&connection, |tx| {
+            RikRepository::transaction(tx, |tx| {
+                RikRepository::insert(tx, "/workload/Pod/lab/c", "{}")
+            })?;
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        });
+        assert!(failed.is_err());
+        assert_eq!(RikRepository::count(&connection, "/workload").unwrap(), 2);
+    }
+
+    #[rstest]
+    fn test_count_with_prefix(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        for name in [
+            "/workload/acme/Pod/lab/a",
+            "/workload/acme/Pod/lab/b",
+            "/workload/acme_2/Pod/lab/a",
+            "/workload/Pod/lab/a",
+        ] {
+            RikRepository::insert(&connection, name, "{}").unwrap();
+        }
+        let count = |prefix| RikRepository::count_with_prefix(&connection, prefix).unwrap();
+        assert_eq!(count("/workload/acme/"), 2);
+        // Unlike LIKE patterns, `_` only matches itself
+        assert_eq!(count("/workload/acme_"), 1);
+        assert_eq!(count("/workload/"), 4);
+        assert_eq!(count("/instance/acme/"), 0);
+    }
+
+    #[rstest]
+    fn test_insert_many(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let items: Vec<(String, String)> = ["a", "b", "c"]
+            .iter()
+            .map(|name| (format!("/tenant/{}", name), String::from("{}")))
+            .collect();
+        let ids = RikRepository::insert_many(&connection, &items).unwrap();
+        let stored = RikRepository::find_all(&connection, "/tenant/").unwrap();
+        assert_eq!(
+            stored.iter().map(|element| &element.id).collect::<Vec<_>>(),
+            ids.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(stored[2].name, "/tenant/c");
+
+        // Tenant names are unique, none of the elements is kept
+        let items = vec![
+            (String::from("/tenant/d"), String::from("{}")),
+            (String::from("/tenant/a"), String::from("{}")),
+        ];
+        assert!(RikRepository::insert_many(&connection, &items).is_err());
+        assert_eq!(RikRepository::count(&connection, "/tenant/").unwrap(), 3);
+
+        // Inserted in the transaction of the caller, rolled back along with it
+        let result: rusqlite::Result<Vec<String>> = RikRepository::transaction(&connection, |tx| {
+            RikRepository::insert_many(tx, &items[..1])?;
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        });
+        assert!(result.is_err());
+        assert_eq!(RikRepository::count(&connection, "/tenant/").unwrap(), 3);
+    }
+
+    #[rstest]
+    fn test_unique_tenant_names(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        // Back to the schema before tenant names were unique, elements had
+        // revisions, dates and versions, and events a tenant
+        connection
+            .execute_batch(&format!(
+                "ALTER TABLE events DROP COLUMN tenant_id;
+                ALTER TABLE cluster DROP COLUMN version;
+                ALTER TABLE cluster DROP COLUMN inserted_at;
+                ALTER TABLE cluster DROP COLUMN updated_at;
+                DROP TRIGGER revision_insert;
+                DROP TRIGGER revision_update;
+                DROP TRIGGER revision_delete;
+                DROP TABLE revisions;
+                DROP INDEX cluster_revision_index;
+                ALTER TABLE cluster DROP COLUMN revision;
+                DROP INDEX cluster_tenant_name_index;
+                PRAGMA user_version = {};",
+                SCHEMA_VERSION - 5
+            ))
+            .unwrap();
+        let insert = |name: &str| {
+            let id = Uuid::new_v4().to_string();
+            connection
+                .execute(
+                    "INSERT INTO cluster (id, name, value) VALUES (?1, ?2, '{}')",
+                    params![id, name],
+                )
+                .unwrap();
+            id
+        };
+        let first = insert("/tenant/acme");
+        insert("/tenant/acme");
+        insert("/tenant/acme-corp");
+        insert("/maintenance/node");
+
+        RikDataBase::migrate(&connection).unwrap();
+        let tenants = RikRepository::find_all(&connection, "/tenant").unwrap();
+        assert_eq!(tenants.len(), 2);
+        let acme = RikRepository::find_by_name(&connection, "/tenant/acme").unwrap();
+        assert_eq!(acme.id, first);
+        // Elements written before get a revision, and the date of the migration
+        let changes = RevisionRepository::changes_since(&connection, 0, "/")
+            .unwrap()
+            .unwrap();
+        assert_eq!(changes.len(), 3);
+        assert!(acme.created_at.is_some());
+        assert_eq!(acme.created_at, acme.updated_at);
+
+        assert!(RikRepository::insert(&connection, "/tenant/acme", "{}").is_err());
+        RikRepository::insert(&connection, "/maintenance/node", "{}").unwrap();
+        assert!(RikRepository::find_by_name(&connection, "/tenant/acm").is_err());
+    }
+
+    #[rstest]
+    fn test_element_dates(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let id = RikRepository::insert(&connection, "/workload/Pod/lab/web", "{}").unwrap();
+        let inserted = RikRepository::find_one(&connection, &id, "/workload").unwrap();
+        let created_at = inserted.created_at.clone().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(&created_at).is_ok());
+        assert_eq!(inserted.updated_at, inserted.created_at);
+
+        std::thread::sleep(Duration::from_millis(5));
+        RikRepository::update(&connection, &id, &String::from(r#"{"replicas": 2}"#)).unwrap();
+        let updated = RikRepository::find_all(&connection, "/workload/").unwrap();
+        assert_eq!(updated[0].created_at.as_ref(), Some(&created_at));
+        assert!(updated[0].updated_at.as_ref().unwrap() > &created_at);
+        let serialized = serde_json::to_value(&updated[0]).unwrap();
+        assert_eq!(serialized["created_at"], created_at.as_str());
+    }
+
+    #[rstest]
+    fn test_update_if_version(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let id = RikRepository::insert(&connection, "/workload/Pod/lab/web", "{}").unwrap();
+        let version = |id: &String| {
+            RikRepository::find_one(&connection, id, "/workload")
+                .unwrap()
+                .version
+        };
+        assert_eq!(version(&id), Some(1));
+        RikRepository::update(&connection, &id, &String::from("{}")).unwrap();
+        assert_eq!(version(&id), Some(2));
+
+        let written = RikRepository::update_if_version(&connection, &id, r#"{"a": 1}"#, 2);
+        assert_eq!(written.unwrap(), 3);
+        // Read at a version since changed, the element is left as is
+        let stale = RikRepository::update_if_version(&connection, &id, r#"{"a": 2}"#, 2);
+        let Err(UpdateError::Conflict(conflict)) = stale else {
+            panic!("The stale write was not refused");
+        };
+        assert_eq!((conflict.expected, conflict.current), (2, Some(3)));
+        let stored = RikRepository::find_one(&connection, &id, "/workload").unwrap();
+        assert_eq!(
+            (stored.value["a"].as_i64(), stored.version),
+            (Some(1), Some(3))
+        );
+
+        RikRepository::delete(&connection, &id).unwrap();
+        let deleted = RikRepository::update_if_version(&connection, &id, "{}", 3);
+        assert!(matches!(
+            deleted,
+            Err(UpdateError::Conflict(VersionConflict { current: None, .. }))
+        ));
+    }
+
+    #[rstest]
+    fn test_concurrent_updates(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let id = RikRepository::insert(&connection, "/workload/Pod/lab/web", "{}").unwrap();
+
+        // Both read the element at version 1, a single one may write it
+        for round in 1..=10 {
+            let barrier = std::sync::Barrier::new(2);
+            let written: Vec<bool> = std::thread::scope(|scope| {
+                let writers: Vec<_> = (0..2)
+                    .map(|writer| {
+                        let (db, id, barrier) = (&db_connection, &id, &barrier);
+                        scope.spawn(move || {
+                            let connection = db.open().unwrap();
+                            let value = format!(r#"{{"writer": {}}}"#, writer);
+                            barrier.wait();
+                            match RikRepository::update_if_version(&connection, id, &value, round) {
+                                Ok(version) => {
+                                    assert_eq!(version, round + 1);
+                                    true
+                                }
+                                Err(UpdateError::Conflict(conflict)) => {
+                                    assert_eq!(conflict.current, Some(round + 1));
+                                    false
+                                }
+                                Err(UpdateError::Database(e)) => panic!("{}", e),
+                            }
+                        })
+                    })
+                    .collect();
+                writers
+                    .into_iter()
+                    .map(|writer| writer.join().unwrap())
+                    .collect()
+            });
+            assert_eq!(written.iter().filter(|written| **written).count(), 1);
+        }
+        let stored = RikRepository::find_one(&connection, &id, "/workload").unwrap();
+        assert_eq!(stored.version, Some(11));
+    }
+
     #[rstest]
     fn test_check_duplicate_name(db_connection: std::sync::Arc<RikDataBase>) {
         let connection = db_connection.open().unwrap();
@@ -258,4 +1225,242 @@ mod test {
             serde_json::json!({"data": "test_updated"})
         );
     }
+
+    #[rstest]
+    fn test_sample_storage(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        for name in [
+            "/workload/pods/default/sampled",
+            "/instance/pods/default/sampled-1",
+            "/instance/pods/default/sampled-2",
+        ] {
+            RikRepository::insert(&connection, name, "{}").unwrap();
+        }
+
+        let sample = db_connection.sample_storage().unwrap();
+        assert!(sample.file_size_bytes > 0);
+        assert_eq!(sample.rows_per_prefix.get("workload"), Some(&1));
+        assert_eq!(sample.rows_per_prefix.get("instance"), Some(&2));
+        assert_eq!(sample.migration_version, SCHEMA_VERSION);
+    }
+
+    #[rstest]
+    fn test_find_by_value_field(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        for (name, workload_id) in [
+            ("/instance/pods/default/web-1", "web"),
+            ("/instance/pods/default/api-1", "api"),
+            ("/instance/pods/default/web-2", "web"),
+        ] {
+            let value = serde_json::json!({ "workload_id": workload_id });
+            RikRepository::insert(&connection, name, &value.to_string()).unwrap();
+        }
+        let value = serde_json::json!({ "workload_id": "web" }).to_string();
+        RikRepository::insert(&connection, "/volume/default/web", &value).unwrap();
+
+        let names = |workload_id: &str| -> Vec<String> {
+            RikRepository::find_by_value_field(
+                &connection,
+                "/instance/",
+                "workload_id",
+                workload_id,
+            )
+            .unwrap()
+            .into_iter()
+            .map(|element| element.name)
+            .collect()
+        };
+        assert_eq!(
+            names("web"),
+            [
+                "/instance/pods/default/web-1",
+                "/instance/pods/default/web-2"
+            ]
+        );
+        assert!(names("unknown").is_empty());
+    }
+
+    #[rstest]
+    fn test_find_instance_summaries(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let instance = serde_json::json!({
+            "workload_id": "42",
+            "namespace": "default",
+            "id": "summarized-1234",
+            "kind": "Pod",
+            "status": "Running",
+            "spec": {"containers": [{"name": "web", "image": "nginx"}]},
+            "overrides": {"image_tag": "latest"},
+            "conditions": [{"type": "Scheduled", "status": "True"}],
+            "created_at": "2023-06-01T10:00:00+00:00",
+            "worker_id": "node-1",
+            "correlation_id": "req-1"
+        });
+        let id = RikRepository::insert(
+            &connection,
+            "/instance/pods/default/summarized-1234",
+            &instance.to_string(),
+        )
+        .unwrap();
+        RikRepository::insert(&connection, "/workload/pods/default/summarized", "{}").unwrap();
+
+        let summaries = RikRepository::find_instance_summaries(
+            &connection,
+            &ElementQuery::of_type("/instance/"),
+            false,
+            usize::MAX,
+            0,
+        )
+        .unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].id, id);
+        assert_eq!(summaries[0].name, "/instance/pods/default/summarized-1234");
+        assert_eq!(
+            summaries[0].value,
+            serde_json::json!({
+                "workload_id": "42",
+                "namespace": "default",
+                "kind": "Pod",
+                "status": "Running",
+                "overrides": {"image_tag": "latest"},
+                "created_at": "2023-06-01T10:00:00+00:00",
+                "worker_id": "node-1",
+                "correlation_id": "req-1"
+            })
+        );
+
+        let summaries = RikRepository::find_instance_summaries(
+            &connection,
+            &ElementQuery::of_type("/instance/"),
+            true,
+            usize::MAX,
+            0,
+        )
+        .unwrap();
+        assert_eq!(summaries[0].value["conditions"], instance["conditions"]);
+    }
+
+    #[rstest]
+    fn test_search_names(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        connection.execute("DELETE FROM cluster", []).unwrap();
+        for name in [
+            "/workload/Pod/default/api-payments",
+            "/workload/Pod/default/payments",
+            "/workload/Function/default/payments-worker",
+            "/workload/Pod/other/payments",
+            "/workload/Pod/default/50%_off",
+            "/instance/Pod/default/payments-1234",
+        ] {
+            RikRepository::insert(&connection, name, "{}").unwrap();
+        }
+        let search = |query: &str, limit: usize| -> Vec<String> {
+            RikRepository::search_names(&connection, "/workload/", "default", query, limit)
+                .unwrap()
+                .into_iter()
+                .map(|(_, name)| name)
+                .collect()
+        };
+
+        // Prefix matches first, then the shortest names
+        assert_eq!(
+            search("PAY", 10),
+            [
+                "/workload/Pod/default/payments",
+                "/workload/Function/default/payments-worker",
+                "/workload/Pod/default/api-payments",
+            ]
+        );
+        assert_eq!(search("pay", 1), ["/workload/Pod/default/payments"]);
+        // Wildcards are matched as is
+        assert_eq!(search("%_", 10), ["/workload/Pod/default/50%_off"]);
+        assert!(search("_p", 10).is_empty());
+    }
+
+    #[rstest]
+    fn test_search_names_among_many(db_connection: std::sync::Arc<RikDataBase>) {
+        let mut connection = db_connection.open().unwrap();
+        let transaction = connection.transaction().unwrap();
+        for index in 0..5_000 {
+            let name = format!("/workload/Pod/default/service-{}", index);
+            RikRepository::insert(&transaction, &name, "{\"spec\": {}}").unwrap();
+        }
+        transaction.commit().unwrap();
+
+        let search = |query: &str| {
+            RikRepository::search_names(&connection, "/workload/", "default", query, 20)
+                .unwrap()
+                .into_iter()
+                .map(|(_, name)| name)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            search("service-4999"),
+            ["/workload/Pod/default/service-4999"]
+        );
+        let names = search("99");
+        assert_eq!(names.len(), 20);
+        assert_eq!(names[0], "/workload/Pod/default/service-99");
+        assert!(search("missing").is_empty());
+    }
+
+    #[rstest]
+    fn test_insert_many_thousands(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let items: Vec<(String, String)> = (0..5_000)
+            .map(|index| {
+                let name = format!("/workload/Pod/default/service-{}", index);
+                (name, String::from("{\"spec\": {}}"))
+            })
+            .collect();
+
+        let ids = RikRepository::insert_many(&connection, &items).unwrap();
+        assert_eq!(ids.len(), 5_000);
+        assert_eq!(
+            RikRepository::count(&connection, "/workload/").unwrap(),
+            5_000
+        );
+    }
+
+    #[rstest]
+    fn test_list_many_instance_summaries(db_connection: std::sync::Arc<RikDataBase>) {
+        let mut connection = db_connection.open().unwrap();
+        let transaction = connection.transaction().unwrap();
+        for index in 0..1_000 {
+            let instance = serde_json::json!({
+                "workload_id": "42",
+                "namespace": "default",
+                "id": format!("instance-{}", index),
+                "kind": "Pod",
+                "status": "Running",
+                "spec": {"containers": [{"name": "web", "image": "nginx", "env": [
+                    {"name": "MODE", "value": "production"}
+                ]}]},
+                "conditions": [{"type": "Scheduled", "status": "True"}],
+                "created_at": "2023-06-01T10:00:00+00:00"
+            });
+            RikRepository::insert(
+                &transaction,
+                &format!("/instance/pods/default/instance-{}", index),
+                &instance.to_string(),
+            )
+            .unwrap();
+        }
+        transaction.commit().unwrap();
+
+        let summaries = RikRepository::find_instance_summaries(
+            &connection,
+            &ElementQuery::of_type("/instance/"),
+            false,
+            usize::MAX,
+            0,
+        )
+        .unwrap();
+        assert_eq!(summaries.len(), 1_000);
+        assert_eq!(summaries[999].name, "/instance/pods/default/instance-999");
+        // The spec is left in the value
+        assert!(summaries
+            .iter()
+            .all(|summary| summary.value.get("spec").is_none()));
+    }
 }
@@ -0,0 +1,210 @@
+use crate::database::RikDataBase;
+use rusqlite::Connection;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Why a connection could not be taken from the pool
+#[derive(Debug)]
+pub enum PoolError {
+    /// Every connection stayed in use until the timeout
+    Exhausted(Duration),
+    Open(rusqlite::Error),
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::Exhausted(timeout) => {
+                write!(f, "No database connection was free after {:?}", timeout)
+            }
+            PoolError::Open(e) => write!(f, "Cannot open a database connection: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    idle: Vec<Connection>,
+    /// Connections open, idle or in use
+    open: usize,
+}
+
+/// Connections of the database shared by the requests, so they keep their
+/// prepared statements. At most `size` are open, opened on demand.
+pub struct ConnectionPool {
+    database: Arc<RikDataBase>,
+    size: usize,
+    timeout: Duration,
+    state: Mutex<PoolState>,
+    released: Condvar,
+}
+
+impl ConnectionPool {
+    /// Pool of at most `size` connections, waiting at most `timeout` for one
+    /// to be free
+    pub fn new(database: Arc<RikDataBase>, size: usize, timeout: Duration) -> Arc<ConnectionPool> {
+        Arc::new(ConnectionPool {
+            database,
+            size: size.max(1),
+            timeout,
+            state: Mutex::new(PoolState::default()),
+            released: Condvar::new(),
+        })
+    }
+
+    pub fn database(&self) -> &Arc<RikDataBase> {
+        &self.database
+    }
+
+    /// A free connection, opened when none is idle and the pool is not full,
+    /// given back to the pool when dropped
+    pub fn get(self: &Arc<Self>) -> Result<PooledConnection, PoolError> {
+        let deadline = Instant::now() + self.timeout;
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(connection) = state.idle.pop() {
+                return Ok(self.pooled(connection));
+            }
+            if state.open < self.size {
+                state.open += 1;
+                // Opened without the lock, the others may take a connection meanwhile
+                drop(state);
+                return match self.database.open() {
+                    Ok(connection) => Ok(self.pooled(connection)),
+                    Err(e) => {
+                        self.forget();
+                        Err(PoolError::Open(e))
+                    }
+                };
+            }
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                return Err(PoolError::Exhausted(self.timeout));
+            }
+            state = self.released.wait_timeout(state, timeout).unwrap().0;
+        }
+    }
+
+    /// Connections open, idle or in use
+    #[cfg(test)]
+    pub fn open(&self) -> usize {
+        self.state.lock().unwrap().open
+    }
+
+    fn pooled(self: &Arc<Self>, connection: Connection) -> PooledConnection {
+        PooledConnection {
+            pool: self.clone(),
+            connection: Some(connection),
+        }
+    }
+
+    /// Free the place of a connection closed rather than given back
+    fn forget(&self) {
+        self.state.lock().unwrap().open -= 1;
+        self.released.notify_one();
+    }
+}
+
+/// Connection taken from a [`ConnectionPool`], given back when dropped
+pub struct PooledConnection {
+    pool: Arc<ConnectionPool>,
+    connection: Option<Connection>,
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let Some(connection) = self.connection.take() else {
+            return;
+        };
+        // A handler which panicked may have left a transaction open, the
+        // connection is closed so that it is rolled back
+        if !connection.is_autocommit() {
+            drop(connection);
+            self.pool.forget();
+            return;
+        }
+        self.pool.state.lock().unwrap().idle.push(connection);
+        self.pool.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::query::ElementQuery;
+    use crate::database::RikRepository;
+    use crate::tests::fixtures::db_connection;
+    use rstest::rstest;
+    use std::thread;
+
+    #[rstest]
+    fn test_connections_are_reused(db_connection: Arc<RikDataBase>) {
+        let pool = ConnectionPool::new(db_connection, 2, Duration::from_millis(10));
+        {
+            let first = pool.get().unwrap();
+            let _second = pool.get().unwrap();
+            RikRepository::insert(&first, "/tenant/acme", "{}").unwrap();
+            assert!(matches!(pool.get(), Err(PoolError::Exhausted(_))));
+        }
+        let connection = pool.get().unwrap();
+        assert_eq!(RikRepository::count(&connection, "/tenant/").unwrap(), 1);
+        assert_eq!(pool.open(), 2);
+
+        // A connection left in a transaction is closed rather than reused
+        connection.execute_batch("BEGIN").unwrap();
+        drop(connection);
+        assert_eq!(pool.open(), 1);
+    }
+
+    #[rstest]
+    fn test_waits_for_a_free_connection(db_connection: Arc<RikDataBase>) {
+        let pool = ConnectionPool::new(db_connection, 1, Duration::from_secs(5));
+        let connection = pool.get().unwrap();
+        let waiting = {
+            let pool = pool.clone();
+            thread::spawn(move || pool.get().map(|_| ()))
+        };
+        thread::sleep(Duration::from_millis(50));
+        drop(connection);
+        assert!(waiting.join().unwrap().is_ok());
+        assert_eq!(pool.open(), 1);
+    }
+
+    #[rstest]
+    fn test_concurrent_lists(db_connection: Arc<RikDataBase>) {
+        let mut connection = db_connection.open().unwrap();
+        let transaction = connection.transaction().unwrap();
+        for index in 0..100 {
+            let name = format!("/workload/Pod/default/service-{}", index);
+            RikRepository::insert(&transaction, &name, "{\"spec\": {}}").unwrap();
+        }
+        transaction.commit().unwrap();
+
+        let pool = ConnectionPool::new(db_connection, 4, Duration::from_secs(5));
+        let workloads = ElementQuery::of_type("/workload/");
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        let connection = pool.get().unwrap();
+                        let (page, total) =
+                            RikRepository::find_page(&connection, &workloads, 20, 0).unwrap();
+                        assert_eq!((page.len(), total), (20, 100));
+                    }
+                });
+            }
+        });
+        // Threads waited for the connections of the others
+        assert!(pool.open() <= 4);
+    }
+}
@@ -0,0 +1,112 @@
+use rusqlite::types::Value;
+
+/// Segments of the name of an element as a JSON array, such as
+/// `["", "workload", "Pod", "default", "web"]`. The name is quoted first, the
+/// escapes of the quotes and backslashes it may have never hold a `/`.
+const NAME_SEGMENTS: &str = r#"'[' || replace(json_quote(name), '/', '","') || ']'"#;
+
+/// Filters and order of a list of elements, applied by the database so the
+/// elements left out are neither read nor counted.
+///
+/// The name segments are read as `ElementPath::parse` splits them. Tenants,
+/// kinds and namespaces are only filtered on for the types whose names have
+/// a kind and a namespace, the workloads and the instances.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ElementQuery {
+    /// Type of the elements listed, such as `/workload/`
+    pub element_type: String,
+    /// Prefix of the names of the elements, without their path
+    pub name: Option<String>,
+    pub tenant: Option<String>,
+    /// Kind of the elements, whatever its case
+    pub kind: Option<String>,
+    pub namespace: Option<String>,
+    /// Labels the elements must all have
+    pub labels: Vec<(String, String)>,
+    pub workload_id: Option<String>,
+    /// JSON pointer of the field sorted on, in the elements as listed, and
+    /// whether the order is descending. Elements are listed in the order
+    /// they were inserted otherwise.
+    pub sort: Option<(&'static str, bool)>,
+}
+
+impl ElementQuery {
+    pub fn of_type(element_type: &str) -> ElementQuery {
+        ElementQuery {
+            element_type: element_type.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Elements selected, as a table to read from, along with the values of
+    /// its parameters. The rowid of the elements is kept as `position`.
+    pub(super) fn source(&self) -> (String, Vec<Value>) {
+        let mut conditions = vec!["name LIKE ? || '%'"];
+        let mut values = vec![Value::from(self.element_type.clone())];
+        if let Some(name) = &self.name {
+            conditions.push("substr(segments ->> '$[#-1]', 1, length(?)) = ?");
+            values.extend([name.clone().into(), name.clone().into()]);
+        }
+        if let Some(tenant) = &self.tenant {
+            // The tenant is the segment after the type, when there is one
+            conditions.push("json_array_length(segments) = 6 AND segments ->> '$[2]' = ?");
+            values.push(tenant.clone().into());
+        }
+        if let Some(kind) = &self.kind {
+            conditions.push("segments ->> '$[#-3]' = ? COLLATE NOCASE");
+            values.push(kind.clone().into());
+        }
+        if let Some(namespace) = &self.namespace {
+            conditions.push("segments ->> '$[#-2]' = ?");
+            values.push(namespace.clone().into());
+        }
+        for (key, value) in &self.labels {
+            conditions.push(
+                "EXISTS (SELECT 1 FROM json_each(elements.value, '$.labels') AS label
+                WHERE label.key = ? AND label.type = 'text' AND label.atom = ?)",
+            );
+            values.extend([key.clone().into(), value.clone().into()]);
+        }
+        if let Some(workload_id) = &self.workload_id {
+            conditions.push("workload_id = ?");
+            values.push(workload_id.clone().into());
+        }
+        let source = format!(
+            "(SELECT *, rowid AS position, {} AS segments FROM cluster) AS elements WHERE {}",
+            NAME_SEGMENTS,
+            conditions.join(" AND ")
+        );
+        (source, values)
+    }
+
+    /// Order of the elements, along with the values of its parameters. The
+    /// elements missing the field sorted on come last in both directions,
+    /// those with the same value are left in the order they were inserted.
+    pub(super) fn order(&self) -> (String, Vec<Value>) {
+        let Some((pointer, descending)) = self.sort else {
+            return (String::from("position"), vec![]);
+        };
+        let direction = if descending { "DESC" } else { "ASC" };
+        // These fields are not part of the JSON value of the elements
+        let (column, values) = match pointer {
+            "/name" => ("segments ->> '$[#-1]'", vec![]),
+            "/id" => ("id", vec![]),
+            "/created_at" => ("inserted_at", vec![]),
+            "/updated_at" => ("updated_at", vec![]),
+            pointer => {
+                let field = pointer.strip_prefix("/value").unwrap_or(pointer);
+                let path = Value::from(format!("${}", field.replace('/', ".")));
+                // Once for each time the column is read
+                ("value ->> ?", vec![path.clone(), path])
+            }
+        };
+        (
+            format!(
+                "{column} IS NULL, {column} {direction}, position",
+                column = column,
+                direction = direction
+            ),
+            values,
+        )
+    }
+}
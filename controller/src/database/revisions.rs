@@ -0,0 +1,244 @@
+use crate::api::types::element::Element;
+use crate::database::metrics::timed;
+use rusqlite::{params, Connection, Result};
+use serde::Serialize;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::Instant;
+
+/// Revisions whose deletions are kept, a watch further behind must list the
+/// elements again. Kept in sync with the `revision_delete` trigger.
+pub const REVISION_HISTORY: i64 = 10_000;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeType {
+    /// The element was created or updated
+    Put,
+    Deleted,
+}
+
+/// Last change of an element, a deleted element has no value
+#[derive(Serialize, Debug, Clone)]
+pub struct Change {
+    pub revision: i64,
+    #[serde(rename = "type")]
+    pub change_type: ChangeType,
+    pub element: Element,
+}
+
+/// Wakes the watches up when elements change, they then read the changes
+/// from the database. Written by [`RikRepository`](crate::database::RikRepository).
+#[derive(Debug, Default)]
+pub struct ChangeNotifier {
+    /// Bumped on every change
+    generation: Mutex<u64>,
+    changed: Condvar,
+}
+
+impl ChangeNotifier {
+    pub fn generation(&self) -> u64 {
+        *self.generation.lock().unwrap()
+    }
+
+    pub fn notify(&self) {
+        *self.generation.lock().unwrap() += 1;
+        self.changed.notify_all();
+    }
+
+    /// Wait at most until `deadline` for a change after `generation`, taken
+    /// before reading the changes so none falls in between
+    pub fn wait(&self, generation: u64, deadline: Instant) {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let current = self.generation.lock().unwrap();
+        let _unused = self
+            .changed
+            .wait_timeout_while(current, timeout, |current| *current == generation)
+            .unwrap();
+    }
+}
+
+pub fn change_notifier() -> &'static ChangeNotifier {
+    static NOTIFIER: OnceLock<ChangeNotifier> = OnceLock::new();
+    NOTIFIER.get_or_init(ChangeNotifier::default)
+}
+
+pub struct RevisionRepository {}
+impl RevisionRepository {
+    /// Revision of the last change, `0` before any
+    pub fn current(connection: &Connection) -> Result<i64> {
+        timed("current_revision", || {
+            connection.query_row(
+                "SELECT ifnull((SELECT seq FROM sqlite_sequence WHERE name = 'revisions'), 0)",
+                [],
+                |row| row.get(0),
+            )
+        })
+    }
+
+    /// Last changes of the elements of a type after the revision `since`, in
+    /// the order they happened. `None` when deletions since then are not
+    /// kept anymore, the elements must be listed again.
+    ///
+    /// Deletions are left out from `0`, the changes being the elements.
+    pub fn changes_since(
+        connection: &Connection,
+        since: i64,
+        element_type: &str,
+    ) -> Result<Option<Vec<Change>>> {
+        if since > 0 && since < RevisionRepository::current(connection)? - REVISION_HISTORY {
+            return Ok(None);
+        }
+        timed("changes_since", || {
+            let mut stmt = connection.prepare_cached(
+                "SELECT revision, id, name, value, inserted_at, updated_at FROM cluster
+                WHERE revision > ?1 AND name LIKE ?2 || '%'
+                ORDER BY revision",
+            )?;
+            let mut changes = stmt
+                .query_map(params![since, element_type], |row| {
+                    Ok(Change {
+                        revision: row.get(0)?,
+                        change_type: ChangeType::Put,
+                        element: Element {
+                            created_at: row.get(4)?,
+                            updated_at: row.get(5)?,
+                            ..Element::new(row.get(1)?, row.get(2)?, row.get(3)?)
+                        },
+                    })
+                })?
+                .collect::<Result<Vec<Change>>>()?;
+            if since > 0 {
+                let mut stmt = connection.prepare_cached(
+                    "SELECT id, element_id, name FROM revisions
+                    WHERE id > ?1 AND deleted = 1 AND name LIKE ?2 || '%'
+                    ORDER BY id",
+                )?;
+                let deletions = stmt.query_map(params![since, element_type], |row| {
+                    Ok(Change {
+                        revision: row.get(0)?,
+                        change_type: ChangeType::Deleted,
+                        element: Element::new(row.get(1)?, row.get(2)?, String::from("null")),
+                    })
+                })?;
+                for deletion in deletions {
+                    changes.push(deletion?);
+                }
+                changes.sort_by_key(|change| change.revision);
+            }
+            Ok(Some(changes))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{RikDataBase, RikRepository};
+    use crate::tests::fixtures::db_connection;
+    use rstest::rstest;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn revisions(changes: &[Change]) -> Vec<(i64, ChangeType, &str)> {
+        changes
+            .iter()
+            .map(|change| {
+                (
+                    change.revision,
+                    change.change_type,
+                    change.element.id.as_str(),
+                )
+            })
+            .collect()
+    }
+
+    #[rstest]
+    fn test_changes_since(db_connection: Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let start = RevisionRepository::current(&connection).unwrap();
+        let first = RikRepository::insert(&connection, "/instance/a", "{}").unwrap();
+        let second = RikRepository::insert(&connection, "/instance/b", "{}").unwrap();
+        RikRepository::insert(&connection, "/workload/c", "{}").unwrap();
+        RikRepository::update(
+            &connection,
+            &first,
+            &String::from(r#"{"status":"Running"}"#),
+        )
+        .unwrap();
+        RikRepository::delete(&connection, &second).unwrap();
+        assert_eq!(RevisionRepository::current(&connection).unwrap(), start + 5);
+
+        let changes = RevisionRepository::changes_since(&connection, start + 1, "/instance")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            revisions(&changes),
+            vec![
+                (start + 4, ChangeType::Put, first.as_str()),
+                (start + 5, ChangeType::Deleted, second.as_str()),
+            ]
+        );
+        assert_eq!(changes[0].element.value["status"], "Running");
+        assert_eq!(changes[1].element.value, serde_json::Value::Null);
+
+        // From the start, the elements are the changes
+        let changes = RevisionRepository::changes_since(&connection, 0, "/instance")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            revisions(&changes),
+            vec![(start + 4, ChangeType::Put, first.as_str())]
+        );
+        assert!(
+            RevisionRepository::changes_since(&connection, start + 5, "/instance")
+                .unwrap()
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[rstest]
+    fn test_old_deletions_are_forgotten(db_connection: Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let id = RikRepository::insert(&connection, "/instance/a", "{}").unwrap();
+        RikRepository::delete(&connection, &id).unwrap();
+        connection
+            .execute(
+                "UPDATE sqlite_sequence SET seq = seq + ?1 WHERE name = 'revisions'",
+                [REVISION_HISTORY],
+            )
+            .unwrap();
+        let id = RikRepository::insert(&connection, "/instance/b", "{}").unwrap();
+        RikRepository::delete(&connection, &id).unwrap();
+        assert!(
+            RevisionRepository::changes_since(&connection, 1, "/instance")
+                .unwrap()
+                .is_none()
+        );
+        let kept: Vec<String> = connection
+            .prepare("SELECT element_id FROM revisions")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(kept, vec![id]);
+    }
+
+    #[test]
+    fn test_wait_for_a_change() {
+        let notifier = Arc::new(ChangeNotifier::default());
+        let generation = notifier.generation();
+        let changer = {
+            let notifier = notifier.clone();
+            std::thread::spawn(move || notifier.notify())
+        };
+        notifier.wait(generation, Instant::now() + Duration::from_secs(10));
+        changer.join().unwrap();
+        assert_eq!(notifier.generation(), generation + 1);
+
+        let started = Instant::now();
+        notifier.wait(generation + 1, started + Duration::from_millis(20));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+}
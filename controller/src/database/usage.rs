@@ -0,0 +1,378 @@
+use crate::api::types::element::ElementPath;
+use crate::api::types::usage::{Usage, UsageGrouping, UsageReport};
+use crate::database::events::format_date;
+use crate::database::metrics::timed;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// Rows read by each query, so computing a report never holds the database
+/// for long
+const CHUNK_SIZE: i64 = 500;
+/// Range of a report when no start is given
+const DEFAULT_RANGE_HOURS: i64 = 24;
+/// Time a report is served from the cache
+const USAGE_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(60);
+/// Amount of reports kept in the cache
+const USAGE_CACHE_CAPACITY: usize = 64;
+
+/// Parameters of a usage report, as given in the request
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct UsageQuery {
+    pub tenant: Option<String>,
+    /// `DEFAULT_RANGE_HOURS` before the end when not given
+    pub from: Option<DateTime<Utc>>,
+    /// Now when not given
+    pub to: Option<DateTime<Utc>>,
+    pub group_by: UsageGrouping,
+}
+
+impl UsageQuery {
+    /// Start and end of the report
+    pub fn range(&self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let to = self.to.unwrap_or(now);
+        let from = self
+            .from
+            .unwrap_or_else(|| to - Duration::hours(DEFAULT_RANGE_HOURS));
+        (from, to)
+    }
+}
+
+fn parse_date(date: Option<String>) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(&date?)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+pub struct UsageRepository {}
+impl UsageRepository {
+    /// Roll up the usage of the instances over the range of the query.
+    ///
+    /// Instance-hours come from the instance ledger, kept up to date by the
+    /// database when instances are inserted and deleted, instances still
+    /// running count until the end of the range. Restarts and failures come
+    /// from the status history.
+    pub fn report(
+        connection: &Connection,
+        query: &UsageQuery,
+        now: DateTime<Utc>,
+    ) -> Result<UsageReport> {
+        timed("usage_report", || {
+            let (from, to) = query.range(now);
+            let key = |name: &str, workload_id: Option<String>| {
+                let (path, _) = ElementPath::parse(name);
+                let workload_id = match query.group_by {
+                    UsageGrouping::Workload => workload_id,
+                    UsageGrouping::Tenant => None,
+                };
+                (path.tenant, workload_id)
+            };
+            let mut groups: BTreeMap<(Option<String>, Option<String>), Usage> = BTreeMap::new();
+            // Group of each instance, to attribute its events
+            let mut instances = HashMap::new();
+
+            let mut stmt = connection.prepare_cached(
+                "SELECT id, instance_id, name, workload_id, created_at, terminated_at
+                FROM instance_usage
+                WHERE id > ?1 AND created_at <= ?2
+                    AND (terminated_at IS NULL OR terminated_at >= ?3)
+                ORDER BY id LIMIT ?4",
+            )?;
+            let mut after = 0;
+            loop {
+                let rows = stmt
+                    .query_map(
+                        params![after, format_date(to), format_date(from), CHUNK_SIZE],
+                        |row| {
+                            Ok((
+                                row.get::<_, i64>(0)?,
+                                row.get::<_, String>(1)?,
+                                row.get::<_, String>(2)?,
+                                row.get::<_, Option<String>>(3)?,
+                                row.get::<_, Option<String>>(4)?,
+                                row.get::<_, Option<String>>(5)?,
+                            ))
+                        },
+                    )?
+                    .collect::<Result<Vec<_>>>()?;
+                let Some((last, ..)) = rows.last() else {
+                    break;
+                };
+                after = *last;
+
+                for (_, instance_id, name, workload_id, created_at, terminated_at) in rows {
+                    let key = key(&name, workload_id);
+                    if query.tenant.is_some() && key.0 != query.tenant {
+                        continue;
+                    }
+                    let usage = groups.entry(key.clone()).or_insert_with(|| Usage {
+                        tenant: key.0.clone(),
+                        workload_id: key.1.clone(),
+                        ..Default::default()
+                    });
+                    usage.instances += 1;
+                    // Instances created before the date was recorded have no known duration
+                    if let Some(created_at) = parse_date(created_at) {
+                        let start = created_at.max(from);
+                        let end = parse_date(terminated_at).unwrap_or(now).min(to);
+                        if end > start {
+                            usage.instance_hours +=
+                                (end - start).num_milliseconds() as f64 / 3_600_000.0;
+                        }
+                    }
+                    instances.insert(instance_id, key);
+                }
+            }
+
+            let mut stmt = connection.prepare_cached(
+                "SELECT id, element_id, reason, EXISTS (
+                    SELECT 1 FROM events earlier
+                    WHERE earlier.element_id = events.element_id
+                        AND earlier.reason = 'Running' AND earlier.id < events.id
+                )
+                FROM events
+                WHERE id > ?1 AND created_at >= ?2 AND created_at < ?3
+                    AND reason IN ('Running', 'Failed')
+                ORDER BY id LIMIT ?4",
+            )?;
+            let mut after = 0;
+            loop {
+                let rows = stmt
+                    .query_map(
+                        params![after, format_date(from), format_date(to), CHUNK_SIZE],
+                        |row| {
+                            Ok((
+                                row.get::<_, i64>(0)?,
+                                row.get::<_, String>(1)?,
+                                row.get::<_, String>(2)?,
+                                row.get::<_, bool>(3)?,
+                            ))
+                        },
+                    )?
+                    .collect::<Result<Vec<_>>>()?;
+                let Some((last, ..)) = rows.last() else {
+                    break;
+                };
+                after = *last;
+
+                for (_, instance_id, reason, has_run) in rows {
+                    let Some(usage) = instances
+                        .get(&instance_id)
+                        .and_then(|key| groups.get_mut(key))
+                    else {
+                        continue;
+                    };
+                    match reason.as_str() {
+                        "Failed" => usage.failures += 1,
+                        _ if has_run => usage.restarts += 1,
+                        _ => {}
+                    }
+                }
+            }
+
+            Ok(UsageReport {
+                from: format_date(from),
+                to: format_date(to),
+                group_by: query.group_by,
+                groups: groups.into_values().collect(),
+            })
+        })
+    }
+}
+
+/// Reports recently computed, so identical queries repeated by dashboards do
+/// not read the whole ledger again
+#[derive(Debug, Default)]
+pub struct UsageCache {
+    reports: Mutex<HashMap<UsageQuery, (Instant, UsageReport)>>,
+}
+
+impl UsageCache {
+    /// Cached report of the query, computed with `compute` when missing or expired
+    pub fn get_or_compute<E>(
+        &self,
+        query: &UsageQuery,
+        compute: impl FnOnce() -> std::result::Result<UsageReport, E>,
+    ) -> std::result::Result<UsageReport, E> {
+        if let Some((computed_at, report)) = self.reports.lock().unwrap().get(query) {
+            if computed_at.elapsed() < USAGE_CACHE_TTL {
+                return Ok(report.clone());
+            }
+        }
+
+        // The lock is not held while computing, which reads the database
+        let report = compute()?;
+        let mut reports = self.reports.lock().unwrap();
+        reports.retain(|_, (computed_at, _)| computed_at.elapsed() < USAGE_CACHE_TTL);
+        if reports.len() >= USAGE_CACHE_CAPACITY {
+            let oldest = reports
+                .iter()
+                .min_by_key(|(_, (computed_at, _))| *computed_at)
+                .map(|(query, _)| query.clone());
+            if let Some(oldest) = oldest {
+                reports.remove(&oldest);
+            }
+        }
+        reports.insert(query.clone(), (Instant::now(), report.clone()));
+        Ok(report)
+    }
+}
+
+/// Cache shared by the API handlers
+pub fn usage_cache() -> &'static UsageCache {
+    static CACHE: OnceLock<UsageCache> = OnceLock::new();
+    CACHE.get_or_init(UsageCache::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::events::EventRepository;
+    use crate::database::{RikDataBase, RikRepository};
+    use crate::tests::fixtures::db_connection;
+    use rstest::rstest;
+
+    fn date(date: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(date)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn insert_instance(connection: &Connection, name: &str, workload_id: &str, created_at: &str) {
+        let (_, id) = ElementPath::parse(name);
+        let instance = serde_json::json!({
+            "id": id,
+            "workload_id": workload_id,
+            "status": "Pending",
+            "created_at": created_at,
+        });
+        RikRepository::upsert(
+            connection,
+            &id.to_string(),
+            &name.to_string(),
+            &instance.to_string(),
+            "/instance",
+        )
+        .unwrap();
+    }
+
+    #[rstest]
+    fn test_instance_hours(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        insert_instance(
+            &connection,
+            "/instance/acme/pods/default/web-1",
+            "web",
+            "2023-06-01T10:00:00+00:00",
+        );
+        insert_instance(
+            &connection,
+            "/instance/acme/pods/default/api-1",
+            "api",
+            "2023-06-01T11:00:00+00:00",
+        );
+        insert_instance(
+            &connection,
+            "/instance/pods/default/other-1",
+            "other",
+            "2023-06-01T10:00:00+00:00",
+        );
+        // Terminated instances leave the cluster but stay in the ledger
+        connection
+            .execute("DELETE FROM cluster WHERE id = 'api-1'", [])
+            .unwrap();
+        connection
+            .execute(
+                "UPDATE instance_usage SET terminated_at = '2023-06-01T11:30:00.000Z'
+                WHERE instance_id = 'api-1'",
+                [],
+            )
+            .unwrap();
+
+        let query = UsageQuery {
+            tenant: Some(String::from("acme")),
+            from: Some(date("2023-06-01T00:00:00Z")),
+            to: Some(date("2023-06-02T00:00:00Z")),
+            group_by: UsageGrouping::Workload,
+        };
+        // Running instances count until now, here within the range
+        let now = date("2023-06-01T12:00:00Z");
+        let report = UsageRepository::report(&connection, &query, now).unwrap();
+        let hours: Vec<_> = report
+            .groups
+            .iter()
+            .map(|usage| (usage.workload_id.as_deref().unwrap(), usage.instance_hours))
+            .collect();
+        assert_eq!(hours, vec![("api", 0.5), ("web", 2.0)]);
+
+        let report = UsageRepository::report(
+            &connection,
+            &UsageQuery {
+                from: Some(date("2023-06-01T11:00:00Z")),
+                group_by: UsageGrouping::Tenant,
+                ..query
+            },
+            date("2023-06-03T00:00:00Z"),
+        )
+        .unwrap();
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].instances, 2);
+        assert_eq!(report.groups[0].instance_hours, 13.0 + 0.5);
+    }
+
+    #[rstest]
+    fn test_restarts_and_failures(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let created_at = Utc::now() - Duration::hours(1);
+        insert_instance(
+            &connection,
+            "/instance/pods/default/flaky-1",
+            "flaky",
+            &created_at.to_rfc3339(),
+        );
+        for reason in ["Running", "Failed", "Running", "Failed", "Running"] {
+            EventRepository::insert(&connection, "flaky-1", reason, "").unwrap();
+        }
+
+        let report =
+            UsageRepository::report(&connection, &UsageQuery::default(), Utc::now()).unwrap();
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].tenant, None);
+        assert_eq!(report.groups[0].restarts, 2);
+        assert_eq!(report.groups[0].failures, 2);
+    }
+
+    #[test]
+    fn test_reports_are_cached() {
+        let cache = UsageCache::default();
+        let query = UsageQuery::default();
+        let report = |instances| UsageReport {
+            from: String::new(),
+            to: String::new(),
+            group_by: UsageGrouping::Tenant,
+            groups: vec![Usage {
+                instances,
+                ..Default::default()
+            }],
+        };
+
+        let first = cache
+            .get_or_compute(&query, || Ok::<_, ()>(report(1)))
+            .unwrap();
+        let second = cache
+            .get_or_compute(&query, || Ok::<_, ()>(report(2)))
+            .unwrap();
+        assert_eq!(first, second);
+
+        let other = UsageQuery {
+            group_by: UsageGrouping::Workload,
+            ..Default::default()
+        };
+        let third = cache
+            .get_or_compute(&other, || Ok::<_, ()>(report(3)))
+            .unwrap();
+        assert_eq!(third.groups[0].instances, 3);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::api::RikError;
+use crate::database::RikRepository;
+use definition::workload::WorkloadDefinition;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// Amount of workload definitions kept in the cache
+const WORKLOAD_CACHE_CAPACITY: usize = 256;
+
+/// Usage counters of the workload cache
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// Parsed definitions and the tick they were last used at
+    entries: HashMap<String, (WorkloadDefinition, u64)>,
+    tick: u64,
+    /// Bumped on every invalidation, definitions loaded across an invalidation
+    /// may be outdated and are not cached
+    generation: u64,
+}
+
+/// Least recently used cache of parsed workload definitions.
+///
+/// Entries are invalidated whenever the database element they come from is
+/// updated or deleted, see [`RikRepository`].
+#[derive(Debug)]
+pub struct WorkloadCache {
+    state: Mutex<CacheState>,
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl WorkloadCache {
+    pub fn new(capacity: usize) -> WorkloadCache {
+        WorkloadCache {
+            state: Mutex::new(CacheState::default()),
+            capacity,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Cached definition of a workload, loaded with `load` when missing
+    pub fn get_or_load<E>(
+        &self,
+        workload_id: &str,
+        load: impl FnOnce() -> Result<WorkloadDefinition, E>,
+    ) -> Result<WorkloadDefinition, E> {
+        let generation = {
+            let mut state = self.state.lock().unwrap();
+            state.tick += 1;
+            let tick = state.tick;
+            if let Some((definition, last_used)) = state.entries.get_mut(workload_id) {
+                *last_used = tick;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(definition.clone());
+            }
+            state.generation
+        };
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        // The lock is not held while loading, so the cache does not serialize
+        // the database accesses
+        let definition = load()?;
+
+        let mut state = self.state.lock().unwrap();
+        if state.generation == generation {
+            if state.entries.len() >= self.capacity {
+                let oldest = state
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, (_, last_used))| *last_used)
+                    .map(|(id, _)| id.clone());
+                if let Some(oldest) = oldest {
+                    state.entries.remove(&oldest);
+                }
+            }
+            let tick = state.tick;
+            state
+                .entries
+                .insert(workload_id.to_string(), (definition.clone(), tick));
+        }
+        Ok(definition)
+    }
+
+    pub fn invalidate(&self, workload_id: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        state.entries.remove(workload_id);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.state.lock().unwrap().entries.len(),
+        }
+    }
+}
+
+/// Cache shared by the API and the core of the controller
+pub fn workload_cache() -> &'static WorkloadCache {
+    static CACHE: OnceLock<WorkloadCache> = OnceLock::new();
+    CACHE.get_or_init(|| WorkloadCache::new(WORKLOAD_CACHE_CAPACITY))
+}
+
+/// Definition of a workload, from the cache when possible
+pub fn find_workload(
+    connection: &Connection,
+    workload_id: &str,
+) -> Result<WorkloadDefinition, RikError> {
+    workload_cache().get_or_load(workload_id, || {
+        let element = RikRepository::find_one(connection, &workload_id.to_string(), "/workload")
+            .map_err(|_| RikError::InvalidName(workload_id.to_string()))?;
+        serde_json::from_value(element.value).map_err(|e| {
+            RikError::InternalCommunicationError(format!("Could not parse workload: {}", e))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::RikDataBase;
+    use crate::tests::fixtures::db_connection;
+    use rstest::rstest;
+
+    fn definition(name: &str) -> WorkloadDefinition {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "v0",
+            "kind": "Pod",
+            "name": name,
+            "spec": {"containers": []},
+            "replicas": 1
+        }))
+        .unwrap()
+    }
+
+    fn insert_workload(connection: &Connection, name: &str) -> String {
+        RikRepository::insert(
+            connection,
+            &format!("/workload/Pod/default/{}", name),
+            &serde_json::to_string(&definition(name)).unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let cache = WorkloadCache::new(2);
+        let load = |name: &str| Ok::<_, ()>(definition(name));
+
+        cache.get_or_load("a", || load("a")).unwrap();
+        cache.get_or_load("b", || load("b")).unwrap();
+        cache.get_or_load("a", || load("a")).unwrap();
+        cache.get_or_load("c", || load("c")).unwrap();
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 3, 2));
+        assert_eq!(
+            cache.get_or_load("a", || Err(())).unwrap().name,
+            String::from("a")
+        );
+        assert!(cache.get_or_load("b", || Err(())).is_err());
+    }
+
+    #[test]
+    fn test_definition_loaded_across_invalidation_is_not_cached() {
+        let cache = WorkloadCache::new(2);
+
+        cache
+            .get_or_load("a", || {
+                // An update lands while the previous definition is being read
+                cache.invalidate("a");
+                Ok::<_, ()>(definition("old"))
+            })
+            .unwrap();
+
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[rstest]
+    fn test_update_invalidates_cached_definition(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let workload_id = insert_workload(&connection, "cached-update");
+        assert_eq!(
+            find_workload(&connection, &workload_id).unwrap().name,
+            "cached-update"
+        );
+
+        RikRepository::update(
+            &connection,
+            &workload_id,
+            &serde_json::to_string(&definition("updated")).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            find_workload(&connection, &workload_id).unwrap().name,
+            "updated"
+        );
+    }
+
+    #[rstest]
+    fn test_delete_invalidates_cached_definition(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let workload_id = insert_workload(&connection, "cached-delete");
+        assert!(find_workload(&connection, &workload_id).is_ok());
+
+        RikRepository::delete(&connection, &workload_id).unwrap();
+
+        assert!(find_workload(&connection, &workload_id).is_err());
+    }
+
+    #[rstest]
+    fn test_transaction_invalidates_once_committed(db_connection: std::sync::Arc<RikDataBase>) {
+        let connection = db_connection.open().unwrap();
+        let reader = db_connection.open().unwrap();
+        let workload_id = insert_workload(&connection, "cached-transaction");
+
+        RikRepository::transaction(&connection, |tx| {
+            RikRepository::delete(tx, &workload_id)?;
+            // Read meanwhile as it was before the transaction, and cached again
+            assert!(find_workload(&reader, &workload_id).is_ok());
+            Ok::<_, rusqlite::Error>(())
+        })
+        .unwrap();
+
+        assert!(find_workload(&reader, &workload_id).is_err());
+    }
+}
@@ -3,9 +3,13 @@ mod core;
 mod database;
 mod tests;
 
+use std::io::Write;
 use std::sync::mpsc::channel;
 use std::thread;
+use std::time::Duration;
 
+use crate::api::limits::limit_from_env;
+use crate::database::metrics::run_storage_sampler;
 use crate::database::RikDataBase;
 use api::{external, ApiChannel};
 use tracing::{event, metadata::LevelFilter, Level};
@@ -15,6 +19,13 @@ use tracing_subscriber::{
 
 use crate::core::core::Core;
 use tokio::runtime::Builder;
+use tokio::signal::ctrl_c;
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Seconds the requests in flight are given to be answered when stopping
+const DEFAULT_SHUTDOWN_GRACE_SECONDS: usize = 10;
+/// Interval between two checks of the threads taking the requests of the API
+const WORKER_SUPERVISION_INTERVAL: Duration = Duration::from_secs(5);
 
 fn logger_setup() {
     tracing_subscriber::registry()
@@ -33,27 +44,65 @@ async fn main() {
     event!(Level::INFO, "Starting Rik");
     let db = RikDataBase::new(String::from("rik"));
     db.init_tables().unwrap();
+    run_storage_sampler(db.clone());
 
     let (legacy_sender, legacy_receiver) = channel::<ApiChannel>();
 
     let internal_api = Core::new(db.clone())
         .await
         .expect("Failed to create internal API");
-    let external_api = external::Server::new(legacy_sender);
-    let mut threads = Vec::new();
+    let config = match external::ServerConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            event!(Level::ERROR, "Invalid API address: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let external_api = external::Server::new(legacy_sender, config);
 
-    threads.push(thread::spawn(move || {
+    // The core runs until the process exits
+    thread::spawn(move || {
         let future = async move { internal_api.listen_notification(legacy_receiver).await };
         Builder::new_multi_thread()
             .enable_all()
             .build()
             .unwrap()
             .block_on(future)
-    }));
+    });
+
+    let mut server = match external_api.run(db) {
+        Ok(server) => server,
+        Err(e) => {
+            event!(Level::ERROR, "{}", e);
+            std::process::exit(1);
+        }
+    };
 
-    threads.push(thread::spawn(move || external_api.run(db)));
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    let mut supervision = tokio::time::interval(WORKER_SUPERVISION_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = supervision.tick() => {
+                server.respawn_workers();
+            }
+        }
+    }
+    let grace = Duration::from_secs(limit_from_env(
+        "SHUTDOWN_GRACE_SECONDS",
+        DEFAULT_SHUTDOWN_GRACE_SECONDS,
+    ) as u64);
+    server.shutdown(grace);
+    // Log lines still buffered are written before exiting
+    let _ = std::io::stdout().flush();
+}
 
-    for thread in threads {
-        thread.join().unwrap();
+/// Wait for the controller to be asked to stop, by SIGINT or SIGTERM
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Cannot listen to SIGTERM");
+    tokio::select! {
+        _ = ctrl_c() => event!(Level::INFO, "Received SIGINT, shutting down"),
+        _ = terminate.recv() => event!(Level::INFO, "Received SIGTERM, shutting down"),
     }
 }
@@ -10,6 +10,8 @@ pub fn db_connection() -> std::sync::Arc<RikDataBase> {
     let mut generator = Generator::default();
     std::env::set_var("DATABASE_LOCATION", "/tmp/riktest");
     let db = RikDataBase::new(generator.next().unwrap());
+    // Names are reused across runs, tests start from an empty database
+    let _ = std::fs::remove_file(db.path());
     db.init_tables().unwrap();
     db
 }
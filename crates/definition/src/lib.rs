@@ -4,37 +4,91 @@ use serde::{Deserialize, Serialize};
 
 pub mod workload {
     use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
     use std::fmt::Display;
     use tracing::error;
 
     const DEFAULT_FUNCTION_RUNTIME_PORT: u16 = 8080;
 
+    /// Schemes of the function rootfs URLs a riklet can fetch
+    pub const ROOTFS_SCHEMES: [&str; 4] = ["http", "https", "file", "s3"];
+
+    /// Kinds of workloads, as written in the definitions
+    pub const WORKLOAD_KINDS: [&str; 2] = ["Pod", "Function"];
+
+    /// Workload names are DNS labels, so they can be used in host names
+    const MAX_NAME_LENGTH: usize = 63;
+
     #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
     pub struct EnvConfig {
         pub name: String,
         pub value: String,
     }
 
+    /// Transport protocol of a forwarded port
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub enum PortProtocol {
+        #[default]
+        #[serde(alias = "tcp")]
+        TCP,
+        #[serde(alias = "udp")]
+        UDP,
+    }
+
+    impl PortProtocol {
+        /// Name of the protocol as understood by iptables
+        pub fn iptables_name(&self) -> &'static str {
+            match self {
+                PortProtocol::TCP => "tcp",
+                PortProtocol::UDP => "udp",
+            }
+        }
+    }
+
+    impl Display for PortProtocol {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
     #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
     pub struct PortConfig {
         pub port: u16,
         pub target_port: u16,
-        pub protocol: Option<String>,
+        pub protocol: Option<PortProtocol>,
         pub r#type: String,
     }
 
     #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
     pub struct Container {
         pub name: String,
+        /// Image pulled from a registry, unless the container is imported from
+        /// `image_archive_url`
+        #[serde(default, skip_serializing_if = "String::is_empty")]
         pub image: String,
+        /// OCI image archive, or tarball of a root filesystem, the nodes
+        /// download and import instead of pulling an image
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub image_archive_url: Option<url::Url>,
+        /// Expected SHA-256 of the image archive, in hexadecimal
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub image_archive_sha256: Option<String>,
         pub env: Option<Vec<EnvConfig>>,
         pub ports: Option<PortConfig>,
+        /// Workloads whose address is given to the container through
+        /// `<WORKLOAD>_HOST` and `<WORKLOAD>_PORT` environment variables
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub discover: Vec<String>,
     }
 
     #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
     pub struct FunctionExecution {
         /// Remote URL to a RootFS, must be accessible from the runtime
         pub rootfs: url::Url,
+        /// Expected SHA-256 of the RootFS, in hexadecimal, nodes refuse to
+        /// boot another content
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub sha256: Option<String>,
     }
 
     #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -52,17 +106,126 @@ pub mod workload {
         pub target_port: u16,
         #[serde(rename = "type")]
         pub port_type: NetworkPortExposureType,
+        #[serde(default)]
+        pub protocol: PortProtocol,
     }
 
     impl FunctionPort {
         /// Create a FunctionPort and bind it to the default port 3000
         /// All our runtimes only use this port
-        pub fn new(port: u16) -> Self {
+        pub fn new(port: u16, protocol: PortProtocol) -> Self {
             Self {
                 port,
                 target_port: DEFAULT_FUNCTION_RUNTIME_PORT,
                 port_type: NetworkPortExposureType::NodePort,
+                protocol,
+            }
+        }
+    }
+
+    /// Persistent volume attached to a function, created beforehand with the volumes API
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct FunctionVolume {
+        /// Name of the volume, in the namespace of the instance
+        pub name: String,
+        /// Unique identifier of the volume, filled by the controller
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        pub id: String,
+        /// Size of the volume, filled by the controller
+        #[serde(default)]
+        pub size_mb: u64,
+        /// Node holding the volume, filled by the controller once known
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub node: Option<String>,
+    }
+
+    /// Traffic from a function instance dropped by its node
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct EgressRule {
+        /// Destination network, e.g. `169.254.169.254/32`
+        pub cidr: String,
+        /// Destination port, on both TCP and UDP unless a protocol is given
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub port: Option<u16>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub protocol: Option<PortProtocol>,
+    }
+
+    impl EgressRule {
+        /// Check the destination is an IPv4 network, such as `10.0.0.0/8`
+        pub fn validate(&self) -> Result<(), String> {
+            let valid = self
+                .cidr
+                .split_once('/')
+                .map(|(address, prefix)| {
+                    address.parse::<std::net::Ipv4Addr>().is_ok()
+                        && prefix.parse::<u8>().is_ok_and(|prefix| prefix <= 32)
+                })
+                .unwrap_or(false);
+            if !valid {
+                return Err(format!(
+                    "Egress destination {} is not an IPv4 network",
+                    self.cidr
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    /// Egress policy of a function, layered on the rules of its node.
+    ///
+    /// It can only deny more traffic, so any other field is rejected when
+    /// deserializing.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+    #[serde(deny_unknown_fields)]
+    pub struct EgressPolicy {
+        #[serde(default)]
+        pub deny: Vec<EgressRule>,
+    }
+
+    /// TCP check of a port of a function instance.
+    ///
+    /// The instance is running once a connection is accepted, and failed once
+    /// `failure_threshold` checks in a row were refused.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    #[serde(deny_unknown_fields)]
+    pub struct Probe {
+        /// Port checked inside the instance, the target port of the exposure when unset
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub port: Option<u16>,
+        #[serde(default)]
+        pub initial_delay_seconds: u32,
+        #[serde(default = "Probe::default_period_seconds")]
+        pub period_seconds: u32,
+        #[serde(default = "Probe::default_timeout_seconds")]
+        pub timeout_seconds: u32,
+        #[serde(default = "Probe::default_failure_threshold")]
+        pub failure_threshold: u32,
+    }
+
+    impl Probe {
+        fn default_period_seconds() -> u32 {
+            10
+        }
+
+        fn default_timeout_seconds() -> u32 {
+            1
+        }
+
+        fn default_failure_threshold() -> u32 {
+            3
+        }
+
+        pub fn validate(&self) -> Result<(), String> {
+            if self.period_seconds == 0 || self.timeout_seconds == 0 {
+                return Err(String::from(
+                    "Probe period and timeout must be at least one second",
+                ));
+            }
+            if self.failure_threshold == 0 {
+                return Err(String::from("Probe failure threshold must be at least 1"));
             }
+            Ok(())
         }
     }
 
@@ -70,6 +233,13 @@ pub mod workload {
     pub struct Function {
         pub execution: FunctionExecution,
         pub exposure: Option<FunctionPort>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub volumes: Vec<FunctionVolume>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub egress_policy: Option<EgressPolicy>,
+        /// Checked once the instance booted, before reporting it running
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub startup_probe: Option<Probe>,
     }
 
     #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -113,25 +283,864 @@ pub mod workload {
         pub api_version: String,
         pub kind: WorkloadKind,
         pub name: String,
+        /// Namespace of the workload, the one of the request when unset
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub namespace: Option<String>,
+        /// Tenant owning the workload, the one of the request when unset
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub tenant_id: Option<String>,
         pub spec: Spec,
         pub replicas: Option<u16>,
+        /// Instances older than this are replaced by new ones, one at a time
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub max_instance_lifetime_seconds: Option<u64>,
+        /// Free form key value pairs, used to select workloads
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        pub labels: BTreeMap<String, String>,
+        /// Instances may be moved to less loaded nodes by the scheduler
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pub rebalanceable: bool,
+        /// Running instances kept while instances are moved, all of them but
+        /// one when unset
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub min_ready_replicas: Option<u16>,
+        /// Deletions are refused unless they override the protection
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pub protected: bool,
+    }
+
+    /// Limits on the environment variables of a workload, applied cluster-wide
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct EnvLimits {
+        pub max_entries_per_container: usize,
+        pub max_name_length: usize,
+        pub max_value_length: usize,
+        /// Names and values of all the containers of a workload
+        pub max_total_bytes: usize,
+    }
+
+    impl Default for EnvLimits {
+        fn default() -> Self {
+            EnvLimits {
+                max_entries_per_container: 128,
+                max_name_length: 256,
+                max_value_length: 4096,
+                max_total_bytes: 32768,
+            }
+        }
+    }
+
+    impl Container {
+        /// A container runs either a registry image or an image archive
+        fn validate_image(&self) -> Result<(), String> {
+            match (self.image.is_empty(), &self.image_archive_url) {
+                (true, None) => {
+                    return Err(format!(
+                        "Container {} needs an image or an image_archive_url",
+                        self.name
+                    ))
+                }
+                (false, Some(_)) => {
+                    return Err(format!(
+                    "Container {} has both an image and an image_archive_url, only one is allowed",
+                    self.name
+                ))
+                }
+                (true, Some(url)) if !ROOTFS_SCHEMES.contains(&url.scheme()) => {
+                    return Err(format!(
+                        "Image archive {} cannot be fetched, the supported schemes are {}",
+                        url,
+                        ROOTFS_SCHEMES.join(", ")
+                    ))
+                }
+                _ => {}
+            }
+            match (&self.image_archive_sha256, &self.image_archive_url) {
+                (Some(_), None) => Err(format!(
+                    "Container {} has an image_archive_sha256 without an image_archive_url",
+                    self.name
+                )),
+                (Some(hash), Some(_)) => validate_sha256("Image archive", hash),
+                (None, _) => Ok(()),
+            }
+        }
+    }
+
+    /// Field of a definition which is not valid, along with the reason
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct InvalidField {
+        /// Path of the field, e.g. `spec.containers[0].name`
+        pub field: String,
+        pub reason: String,
+    }
+
+    impl InvalidField {
+        fn new(field: impl Into<String>, reason: impl Into<String>) -> InvalidField {
+            InvalidField {
+                field: field.into(),
+                reason: reason.into(),
+            }
+        }
+    }
+
+    /// Lowercase letters, digits and `-`, starting and ending with a letter or a digit
+    fn validate_name(name: &str) -> Result<(), String> {
+        if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+            return Err(format!(
+                "The name must be between 1 and {} characters long",
+                MAX_NAME_LENGTH
+            ));
+        }
+        let allowed = |byte: u8| byte.is_ascii_lowercase() || byte.is_ascii_digit();
+        let bytes = name.as_bytes();
+        if !bytes.iter().all(|&byte| allowed(byte) || byte == b'-')
+            || !allowed(bytes[0])
+            || !allowed(bytes[bytes.len() - 1])
+        {
+            return Err(format!(
+                "{} is not a valid name, use lowercase letters, digits and '-', starting and ending with a letter or a digit",
+                name
+            ));
+        }
+        Ok(())
+    }
+
+    /// Hashes are declared as SHA-256 in hexadecimal
+    fn validate_sha256(what: &str, hash: &str) -> Result<(), String> {
+        if hash.len() != 64 || !hash.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(format!(
+                "{} hash {} is not a SHA-256 in hexadecimal",
+                what, hash
+            ));
+        }
+        Ok(())
     }
 
     impl WorkloadDefinition {
+        /// Check the definition against the cluster limits, the error names the
+        /// offending variable
+        pub fn validate(&self, limits: &EnvLimits) -> Result<(), String> {
+            let mut total_bytes = 0;
+            for container in &self.spec.containers {
+                container.validate_image()?;
+                let env = container.env.as_deref().unwrap_or_default();
+                if env.len() > limits.max_entries_per_container {
+                    return Err(format!(
+                        "Container {} has {} environment variables, the maximum is {}",
+                        container.name,
+                        env.len(),
+                        limits.max_entries_per_container
+                    ));
+                }
+                for variable in env {
+                    if variable.name.len() > limits.max_name_length {
+                        return Err(format!(
+                            "Environment variable {} of container {} has a name longer than {} bytes",
+                            variable.name, container.name, limits.max_name_length
+                        ));
+                    }
+                    if variable.value.len() > limits.max_value_length {
+                        return Err(format!(
+                            "Environment variable {} of container {} has a value longer than {} bytes",
+                            variable.name, container.name, limits.max_value_length
+                        ));
+                    }
+                    total_bytes += variable.name.len() + variable.value.len();
+                    if total_bytes > limits.max_total_bytes {
+                        return Err(format!(
+                            "Environment variable {} of container {} exceeds the {} bytes allowed for the environment of a workload",
+                            variable.name, container.name, limits.max_total_bytes
+                        ));
+                    }
+                }
+            }
+            let egress_rules = self
+                .spec
+                .function
+                .iter()
+                .filter_map(|function| function.egress_policy.as_ref())
+                .flat_map(|policy| policy.deny.iter());
+            for rule in egress_rules {
+                rule.validate()?;
+            }
+            if let Some(function) = &self.spec.function {
+                if let Some(probe) = &function.startup_probe {
+                    probe.validate()?;
+                    if probe.port.is_none() && function.exposure.is_none() {
+                        return Err(String::from(
+                            "Startup probe needs a port when the function is not exposed",
+                        ));
+                    }
+                }
+                let rootfs = &function.execution.rootfs;
+                if !ROOTFS_SCHEMES.contains(&rootfs.scheme()) {
+                    return Err(format!(
+                        "RootFS {} cannot be fetched, the supported schemes are {}",
+                        rootfs,
+                        ROOTFS_SCHEMES.join(", ")
+                    ));
+                }
+            }
+            let declared_hash = self
+                .spec
+                .function
+                .as_ref()
+                .and_then(|function| function.execution.sha256.as_deref());
+            if let Some(hash) = declared_hash {
+                validate_sha256("RootFS", hash)?;
+            }
+            Ok(())
+        }
+
+        /// Check the shape of the definition, every invalid field is given.
+        ///
+        /// Unlike `validate`, it does not depend on the cluster configuration.
+        pub fn validate_fields(&self) -> Vec<InvalidField> {
+            let mut errors = vec![];
+            if let Err(reason) = validate_name(&self.name) {
+                errors.push(InvalidField::new("name", reason));
+            }
+            match (&self.kind, &self.spec.function) {
+                (WorkloadKind::Pod, _) if self.spec.containers.is_empty() => errors.push(
+                    InvalidField::new("spec.containers", "A pod needs at least one container"),
+                ),
+                (WorkloadKind::Function, None) => errors.push(InvalidField::new(
+                    "spec.function",
+                    "A function needs a function.execution.rootfs to boot",
+                )),
+                _ => {}
+            }
+            if self.max_instance_lifetime_seconds == Some(0) {
+                errors.push(InvalidField::new(
+                    "max_instance_lifetime_seconds",
+                    "The lifetime of an instance must be at least one second",
+                ));
+            }
+
+            for (index, container) in self.spec.containers.iter().enumerate() {
+                let field = |name: &str| format!("spec.containers[{}].{}", index, name);
+                let duplicate = self.spec.containers[..index]
+                    .iter()
+                    .any(|other| other.name == container.name);
+                if duplicate {
+                    errors.push(InvalidField::new(
+                        field("name"),
+                        format!("Container name {} is already used", container.name),
+                    ));
+                }
+                if let Some(ports) = &container.ports {
+                    if ports.port == 0 {
+                        errors.push(InvalidField::new(field("ports.port"), "Ports start at 1"));
+                    }
+                    if ports.target_port == 0 {
+                        errors.push(InvalidField::new(
+                            field("ports.target_port"),
+                            "Ports start at 1",
+                        ));
+                    }
+                }
+            }
+
+            let exposure = self
+                .spec
+                .function
+                .as_ref()
+                .and_then(|function| function.exposure.as_ref());
+            if let Some(exposure) = exposure {
+                if exposure.port == 0 {
+                    errors.push(InvalidField::new(
+                        "spec.function.exposure.port",
+                        "Ports start at 1",
+                    ));
+                }
+                if exposure.target_port == 0 {
+                    errors.push(InvalidField::new(
+                        "spec.function.exposure.targetPort",
+                        "Ports start at 1",
+                    ));
+                }
+            }
+            errors
+        }
+
         /// Determine whether the workload is a kind function
         pub fn is_function(&self) -> bool {
             self.kind == WorkloadKind::Function
         }
 
+        /// Persistent volumes used by the workload
+        pub fn volumes(&self) -> &[FunctionVolume] {
+            self.spec
+                .function
+                .as_ref()
+                .map(|function| function.volumes.as_slice())
+                .unwrap_or_default()
+        }
+
+        pub fn volumes_mut(&mut self) -> &mut [FunctionVolume] {
+            self.spec
+                .function
+                .as_mut()
+                .map(|function| function.volumes.as_mut_slice())
+                .unwrap_or_default()
+        }
+
+        /// Node the workload must run on, as its volumes are stored there
+        pub fn required_node(&self) -> Option<&str> {
+            self.volumes()
+                .iter()
+                .find_map(|volume| volume.node.as_deref())
+        }
+
         pub fn set_function_port(&mut self, port: u16) {
             if !self.is_function() {
                 error!("Cannot set function port on non-function workload");
             }
             if let Some(function) = &mut self.spec.function {
-                function.exposure = Some(FunctionPort::new(port));
+                // The protocol asked by the workload is kept
+                let protocol = function
+                    .exposure
+                    .as_ref()
+                    .map(|exposure| exposure.protocol)
+                    .unwrap_or_default();
+                function.exposure = Some(FunctionPort::new(port, protocol));
             }
         }
     }
+
+    /// Changes applied on top of a workload definition for a single instance.
+    ///
+    /// Only a restricted set of fields can be overridden, any other field
+    /// is rejected when deserializing.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+    #[serde(deny_unknown_fields)]
+    pub struct InstanceOverrides {
+        /// Environment variables added to every container, replacing the ones
+        /// with the same name
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub env: Option<Vec<EnvConfig>>,
+        /// Tag replacing the image tag of every container
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub image_tag: Option<String>,
+    }
+
+    impl InstanceOverrides {
+        /// Names of the overridden fields
+        pub fn fields(&self) -> Vec<&'static str> {
+            let mut fields = Vec::new();
+            if self.env.is_some() {
+                fields.push("env");
+            }
+            if self.image_tag.is_some() {
+                fields.push("image_tag");
+            }
+            fields
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.fields().is_empty()
+        }
+
+        /// Merge the overrides onto a workload definition
+        pub fn apply(&self, workload: &mut WorkloadDefinition) {
+            for container in workload.spec.containers.iter_mut() {
+                if let Some(env) = &self.env {
+                    let container_env = container.env.get_or_insert_with(Vec::new);
+                    for variable in env {
+                        container_env.retain(|existing| existing.name != variable.name);
+                        container_env.push(variable.clone());
+                    }
+                }
+                // Image archives have no tag to replace
+                if let (Some(tag), false) = (&self.image_tag, container.image.is_empty()) {
+                    container.image = replace_image_tag(&container.image, tag);
+                }
+            }
+        }
+    }
+
+    /// Replace the tag (or digest) of an image reference, keeping the registry port intact
+    fn replace_image_tag(image: &str, tag: &str) -> String {
+        let name_start = image.rfind('/').map(|index| index + 1).unwrap_or(0);
+        let name_end = image[name_start..]
+            .find([':', '@'])
+            .map(|index| name_start + index)
+            .unwrap_or(image.len());
+        format!("{}:{}", &image[..name_end], tag)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn workload() -> WorkloadDefinition {
+            WorkloadDefinition {
+                api_version: String::from("v0"),
+                kind: WorkloadKind::Pod,
+                name: String::from("nginx"),
+                spec: Spec {
+                    containers: vec![Container {
+                        name: String::from("nginx"),
+                        image: String::from("registry:5000/library/nginx:1.23"),
+                        image_archive_url: None,
+                        image_archive_sha256: None,
+                        env: Some(vec![EnvConfig {
+                            name: String::from("MODE"),
+                            value: String::from("production"),
+                        }]),
+                        ports: None,
+                        discover: vec![],
+                    }],
+                    function: None,
+                },
+                replicas: None,
+                max_instance_lifetime_seconds: None,
+                labels: BTreeMap::new(),
+                rebalanceable: false,
+                min_ready_replicas: None,
+                protected: false,
+                namespace: None,
+                tenant_id: None,
+            }
+        }
+
+        #[test]
+        fn test_apply_overrides() {
+            let overrides = InstanceOverrides {
+                env: Some(vec![EnvConfig {
+                    name: String::from("MODE"),
+                    value: String::from("debug"),
+                }]),
+                image_tag: Some(String::from("latest")),
+            };
+            let mut workload = workload();
+            overrides.apply(&mut workload);
+
+            let container = &workload.spec.containers[0];
+            assert_eq!(container.image, "registry:5000/library/nginx:latest");
+            assert_eq!(
+                container.env,
+                Some(vec![EnvConfig {
+                    name: String::from("MODE"),
+                    value: String::from("debug"),
+                }])
+            );
+            assert_eq!(overrides.fields(), vec!["env", "image_tag"]);
+        }
+
+        #[test]
+        fn test_reject_unknown_overrides() {
+            let overrides = serde_json::from_str::<InstanceOverrides>(r#"{"kind": "Function"}"#);
+            assert!(overrides.is_err());
+        }
+
+        #[test]
+        fn test_replace_image_tag() {
+            assert_eq!(replace_image_tag("nginx", "1.0"), "nginx:1.0");
+            assert_eq!(replace_image_tag("nginx:1.25", "1.0"), "nginx:1.0");
+            assert_eq!(
+                replace_image_tag("nginx@sha256:0123abcd", "1.0"),
+                "nginx:1.0"
+            );
+            assert_eq!(
+                replace_image_tag("registry:5000/library/nginx", "1.0"),
+                "registry:5000/library/nginx:1.0"
+            );
+        }
+
+        #[test]
+        fn test_port_protocols() {
+            let ports: PortConfig = serde_json::from_str(
+                r#"{"port": 53, "target_port": 53, "protocol": "UDP", "type": "NodePort"}"#,
+            )
+            .unwrap();
+            assert_eq!(ports.protocol, Some(PortProtocol::UDP));
+            assert!(serde_json::from_str::<PortConfig>(
+                r#"{"port": 53, "target_port": 53, "protocol": "SCTP", "type": "NodePort"}"#,
+            )
+            .is_err());
+
+            let exposure: FunctionPort =
+                serde_json::from_str(r#"{"port": 53, "targetPort": 53, "type": "NodePort"}"#)
+                    .unwrap();
+            assert_eq!(exposure.protocol, PortProtocol::TCP);
+        }
+
+        #[test]
+        fn test_function_port_keeps_protocol() {
+            let mut function = workload();
+            function.kind = WorkloadKind::Function;
+            function.spec.function = Some(Function {
+                execution: FunctionExecution {
+                    rootfs: url::Url::parse("https://example.com/rootfs.ext4").unwrap(),
+                    sha256: None,
+                },
+                exposure: Some(FunctionPort::new(53, PortProtocol::UDP)),
+                volumes: vec![],
+                egress_policy: None,
+                startup_probe: None,
+            });
+
+            function.set_function_port(45053);
+            let exposure = function.spec.function.unwrap().exposure.unwrap();
+            assert_eq!(exposure.port, 45053);
+            assert_eq!(exposure.protocol, PortProtocol::UDP);
+        }
+
+        #[test]
+        fn test_required_node_from_volumes() {
+            let mut function = workload();
+            function.kind = WorkloadKind::Function;
+            function.spec.function = Some(Function {
+                execution: FunctionExecution {
+                    rootfs: url::Url::parse("https://example.com/rootfs.ext4").unwrap(),
+                    sha256: None,
+                },
+                exposure: None,
+                volumes: serde_json::from_str(r#"[{"name": "cache"}, {"name": "data"}]"#).unwrap(),
+                egress_policy: None,
+                startup_probe: None,
+            });
+            assert_eq!(function.volumes().len(), 2);
+            assert_eq!(function.required_node(), None);
+
+            function.volumes_mut()[1].node = Some(String::from("node-1"));
+            assert_eq!(function.required_node(), Some("node-1"));
+            assert!(workload().volumes().is_empty());
+        }
+
+        #[test]
+        fn test_egress_policy_only_denies() {
+            let policy: EgressPolicy = serde_json::from_str(
+                r#"{"deny": [{"cidr": "10.0.0.0/8"}, {"cidr": "192.168.1.1/32", "port": 5000, "protocol": "TCP"}]}"#,
+            )
+            .unwrap();
+            assert_eq!(policy.deny.len(), 2);
+            assert!(policy.deny.iter().all(|rule| rule.validate().is_ok()));
+            assert!(
+                serde_json::from_str::<EgressPolicy>(r#"{"allow": [{"cidr": "0.0.0.0/0"}]}"#)
+                    .is_err()
+            );
+
+            for cidr in ["10.0.0.0", "10.0.0.0/33", "fd00::/8", "example.com/32"] {
+                let rule = EgressRule {
+                    cidr: cidr.to_string(),
+                    port: None,
+                    protocol: None,
+                };
+                assert!(rule.validate().is_err(), "{} should be invalid", cidr);
+            }
+        }
+
+        #[test]
+        fn test_validate_rootfs_hash() {
+            let mut function = workload();
+            function.kind = WorkloadKind::Function;
+            function.spec.function = Some(Function {
+                execution: FunctionExecution {
+                    rootfs: url::Url::parse("https://example.com/rootfs.ext4").unwrap(),
+                    sha256: Some("ab".repeat(32)),
+                },
+                exposure: None,
+                volumes: vec![],
+                egress_policy: None,
+                startup_probe: None,
+            });
+            assert!(function.validate(&EnvLimits::default()).is_ok());
+
+            for hash in ["abc", "zz".repeat(32).as_str()] {
+                function.spec.function.as_mut().unwrap().execution.sha256 = Some(hash.to_string());
+                assert!(function.validate(&EnvLimits::default()).is_err());
+            }
+        }
+
+        #[test]
+        fn test_validate_rootfs_scheme() {
+            let mut function = workload();
+            function.kind = WorkloadKind::Function;
+            function.spec.function = Some(Function {
+                execution: FunctionExecution {
+                    rootfs: url::Url::parse("s3://images/rootfs.ext4").unwrap(),
+                    sha256: None,
+                },
+                exposure: None,
+                volumes: vec![],
+                egress_policy: None,
+                startup_probe: None,
+            });
+            assert!(function.validate(&EnvLimits::default()).is_ok());
+
+            for rootfs in ["ftp://example.com/rootfs.ext4", "oci://registry/rootfs:1.0"] {
+                function.spec.function.as_mut().unwrap().execution.rootfs =
+                    url::Url::parse(rootfs).unwrap();
+                assert!(function.validate(&EnvLimits::default()).is_err());
+            }
+        }
+
+        #[test]
+        fn test_validate_fields() {
+            assert!(workload().validate_fields().is_empty());
+
+            let mut pod = workload();
+            pod.name = String::from("Web_1");
+            let mut sidecar = pod.spec.containers[0].clone();
+            sidecar.ports = Some(PortConfig {
+                port: 0,
+                target_port: 80,
+                protocol: None,
+                r#type: String::from("NodePort"),
+            });
+            pod.spec.containers.push(sidecar);
+            let fields: Vec<String> = pod
+                .validate_fields()
+                .into_iter()
+                .map(|error| error.field)
+                .collect();
+            assert_eq!(
+                fields,
+                vec![
+                    "name",
+                    "spec.containers[1].name",
+                    "spec.containers[1].ports.port"
+                ]
+            );
+
+            for name in ["-web", "web-", &"a".repeat(64), ""] {
+                assert!(validate_name(name).is_err(), "{}", name);
+            }
+            assert!(validate_name("2-distro").is_ok());
+
+            let mut empty = workload();
+            empty.spec.containers.clear();
+            assert_eq!(empty.validate_fields()[0].field, "spec.containers");
+            empty.kind = WorkloadKind::Function;
+            assert_eq!(empty.validate_fields()[0].field, "spec.function");
+
+            let mut recycled = workload();
+            recycled.max_instance_lifetime_seconds = Some(0);
+            assert_eq!(
+                recycled.validate_fields()[0].field,
+                "max_instance_lifetime_seconds"
+            );
+            recycled.max_instance_lifetime_seconds = Some(1);
+            assert!(recycled.validate_fields().is_empty());
+        }
+
+        #[test]
+        fn test_validate_image_archive() {
+            let mut pod = workload();
+            let archive = url::Url::parse("https://example.com/nginx.tar").unwrap();
+            // Both an image and an archive
+            pod.spec.containers[0].image_archive_url = Some(archive);
+            assert!(pod.validate(&EnvLimits::default()).is_err());
+
+            pod.spec.containers[0].image = String::new();
+            pod.spec.containers[0].image_archive_sha256 = Some("ab".repeat(32));
+            assert!(pod.validate(&EnvLimits::default()).is_ok());
+
+            pod.spec.containers[0].image_archive_sha256 = Some(String::from("abc"));
+            assert!(pod.validate(&EnvLimits::default()).is_err());
+            pod.spec.containers[0].image_archive_url = None;
+            // Neither an image nor an archive
+            pod.spec.containers[0].image_archive_sha256 = None;
+            assert!(pod.validate(&EnvLimits::default()).is_err());
+        }
+
+        #[test]
+        fn test_validate_startup_probe() {
+            let mut function = workload();
+            function.kind = WorkloadKind::Function;
+            function.spec.function = Some(Function {
+                execution: FunctionExecution {
+                    rootfs: url::Url::parse("https://example.com/rootfs.ext4").unwrap(),
+                    sha256: None,
+                },
+                exposure: Some(FunctionPort::new(8080, PortProtocol::TCP)),
+                volumes: vec![],
+                egress_policy: None,
+                startup_probe: Some(
+                    serde_json::from_str(r#"{"period_seconds": 5, "failure_threshold": 24}"#)
+                        .unwrap(),
+                ),
+            });
+            assert!(function.validate(&EnvLimits::default()).is_ok());
+            let probe = function
+                .spec
+                .function
+                .as_ref()
+                .unwrap()
+                .startup_probe
+                .clone();
+            assert_eq!(probe.as_ref().unwrap().timeout_seconds, 1);
+
+            let spec = function.spec.function.as_mut().unwrap();
+            spec.exposure = None;
+            assert!(function.validate(&EnvLimits::default()).is_err());
+
+            let spec = function.spec.function.as_mut().unwrap();
+            spec.startup_probe.as_mut().unwrap().port = Some(8080);
+            assert!(function.validate(&EnvLimits::default()).is_ok());
+
+            let spec = function.spec.function.as_mut().unwrap();
+            spec.startup_probe.as_mut().unwrap().failure_threshold = 0;
+            assert!(function.validate(&EnvLimits::default()).is_err());
+            assert!(serde_json::from_str::<Probe>(r#"{"http_get": "/health"}"#).is_err());
+        }
+
+        #[test]
+        fn test_validate_env_limits() {
+            let limits = EnvLimits {
+                max_entries_per_container: 2,
+                max_name_length: 8,
+                max_value_length: 16,
+                max_total_bytes: 24,
+            };
+            assert!(workload().validate(&limits).is_ok());
+
+            let with_env = |env: Vec<(&str, &str)>| {
+                let mut workload = workload();
+                workload.spec.containers[0].env = Some(
+                    env.into_iter()
+                        .map(|(name, value)| EnvConfig {
+                            name: name.to_string(),
+                            value: value.to_string(),
+                        })
+                        .collect(),
+                );
+                workload
+            };
+
+            let error = with_env(vec![("A", ""), ("B", ""), ("C", "")])
+                .validate(&limits)
+                .unwrap_err();
+            assert!(error.contains("3 environment variables"));
+            let error = with_env(vec![("TOO_LONG_NAME", "")])
+                .validate(&limits)
+                .unwrap_err();
+            assert!(error.contains("TOO_LONG_NAME"));
+            let error = with_env(vec![("BLOB", &"a".repeat(17))])
+                .validate(&limits)
+                .unwrap_err();
+            assert!(error.contains("BLOB"));
+            let error = with_env(vec![("FIRST", &"a".repeat(16)), ("SECOND", "abc")])
+                .validate(&limits)
+                .unwrap_err();
+            assert!(error.contains("SECOND"));
+        }
+    }
+}
+
+/// Aspect of an instance lifecycle tracked by a condition
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionType {
+    Scheduled,
+    ImagePulled,
+    Booted,
+    Ready,
+    Terminating,
+}
+
+impl Display for ConditionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionStatus {
+    True,
+    False,
+    Unknown,
+}
+
+impl Display for ConditionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Observed state of one aspect of an instance
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    #[serde(rename = "type")]
+    pub condition_type: ConditionType,
+    pub status: ConditionStatus,
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default)]
+    pub message: String,
+    /// RFC 3339 date of the last status change
+    #[serde(rename = "lastTransitionTime")]
+    pub last_transition_time: String,
+}
+
+/// Set a condition in a list of conditions.
+///
+/// The transition time is only updated when the status of the condition changes.
+pub fn set_condition(
+    conditions: &mut Vec<Condition>,
+    condition_type: ConditionType,
+    status: ConditionStatus,
+    reason: &str,
+    message: &str,
+    now: &str,
+) {
+    match conditions
+        .iter_mut()
+        .find(|condition| condition.condition_type == condition_type)
+    {
+        Some(condition) => {
+            if condition.status != status {
+                condition.status = status;
+                condition.last_transition_time = now.to_string();
+            }
+            condition.reason = reason.to_string();
+            condition.message = message.to_string();
+        }
+        None => conditions.push(Condition {
+            condition_type,
+            status,
+            reason: reason.to_string(),
+            message: message.to_string(),
+            last_transition_time: now.to_string(),
+        }),
+    }
+}
+
+/// Problem a node can report about itself
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeConditionType {
+    /// A disk used by the node is nearly full
+    DiskPressure,
+    /// KVM cannot be used, functions cannot run
+    KvmUnavailable,
+    /// Cached images cannot be trusted
+    ImageCacheCorrupted,
+    /// The system clock is not synchronized
+    ClockUnsynced,
+}
+
+impl NodeConditionType {
+    /// Whether a node reporting this condition must not receive new instances
+    pub fn prevents_scheduling(&self) -> bool {
+        matches!(self, NodeConditionType::DiskPressure)
+    }
+}
+
+impl Display for NodeConditionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Result of a node check
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NodeCondition {
+    #[serde(rename = "type")]
+    pub condition_type: NodeConditionType,
+    pub active: bool,
+    #[serde(default)]
+    pub message: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -142,6 +1151,20 @@ pub enum InstanceStatus {
     Terminated,
     Creating,
     Destroying,
+    /// Brought down then up again in place, keeping its id
+    Restarting,
+}
+
+impl InstanceStatus {
+    pub const ALL: [InstanceStatus; 7] = [
+        InstanceStatus::Pending,
+        InstanceStatus::Running,
+        InstanceStatus::Failed,
+        InstanceStatus::Terminated,
+        InstanceStatus::Creating,
+        InstanceStatus::Destroying,
+        InstanceStatus::Restarting,
+    ];
 }
 
 impl Display for InstanceStatus {
@@ -153,6 +1176,7 @@ impl Display for InstanceStatus {
             InstanceStatus::Terminated => write!(f, "Terminated"),
             InstanceStatus::Creating => write!(f, "Creating"),
             InstanceStatus::Destroying => write!(f, "Destroying"),
+            InstanceStatus::Restarting => write!(f, "Restarting"),
         }
     }
 }
@@ -166,6 +1190,7 @@ impl From<InstanceStatus> for i32 {
             InstanceStatus::Terminated => 4,
             InstanceStatus::Creating => 5,
             InstanceStatus::Destroying => 6,
+            InstanceStatus::Restarting => 7,
         }
     }
 }
@@ -178,6 +1203,7 @@ impl From<i32> for InstanceStatus {
             4 => InstanceStatus::Terminated,
             5 => InstanceStatus::Creating,
             6 => InstanceStatus::Destroying,
+            7 => InstanceStatus::Restarting,
             _ => InstanceStatus::Pending,
         }
     }
@@ -1,6 +1,13 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("./src/controller.proto")?;
+    // Statuses are moved once per report, boxing them is not worth it
+    let builder = || {
+        tonic_build::configure().type_attribute(
+            "common.WorkerStatus.status",
+            "#[allow(clippy::large_enum_variant)]",
+        )
+    };
+    builder().compile(&["./src/controller.proto"], &["./src"])?;
     tonic_build::compile_protos("google/protobuf/empty.proto")?;
-    tonic_build::compile_protos("./src/worker.proto")?;
+    builder().compile(&["./src/worker.proto"], &["./src"])?;
     Ok(())
 }
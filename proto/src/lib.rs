@@ -1,4 +1,7 @@
-use common::{worker_status::Status, InstanceMetric, ResourceStatus, WorkloadRequestKind};
+use common::{
+    worker_status::Status, AppStatus, ImageProvenance, InstanceCondition, InstanceMetric,
+    ResourceStatus, WorkloadRequestKind,
+};
 use definition::InstanceStatus;
 use std::ops::Deref;
 pub mod common {
@@ -17,6 +20,7 @@ impl From<i32> for WorkloadRequestKind {
     fn from(w: i32) -> Self {
         match w {
             1 => WorkloadRequestKind::Destroy,
+            2 => WorkloadRequestKind::Restart,
             _ => WorkloadRequestKind::Create,
         }
     }
@@ -25,6 +29,7 @@ impl From<i32> for WorkloadRequestKind {
 impl From<i32> for ResourceStatus {
     fn from(w: i32) -> Self {
         match w {
+            7 => ResourceStatus::Restarting,
             6 => ResourceStatus::Destroying,
             5 => ResourceStatus::Creating,
             4 => ResourceStatus::Terminated,
@@ -46,20 +51,143 @@ impl From<ResourceStatus> for InstanceStatus {
             ResourceStatus::Terminated => InstanceStatus::Terminated,
             ResourceStatus::Creating => InstanceStatus::Creating,
             ResourceStatus::Destroying => InstanceStatus::Destroying,
+            ResourceStatus::Restarting => InstanceStatus::Restarting,
         }
     }
 }
 
+impl From<common::ConditionType> for definition::ConditionType {
+    fn from(value: common::ConditionType) -> Self {
+        match value {
+            common::ConditionType::Scheduled => definition::ConditionType::Scheduled,
+            common::ConditionType::ImagePulled => definition::ConditionType::ImagePulled,
+            common::ConditionType::Booted => definition::ConditionType::Booted,
+            common::ConditionType::Ready => definition::ConditionType::Ready,
+            common::ConditionType::Terminating => definition::ConditionType::Terminating,
+        }
+    }
+}
+
+impl From<common::ConditionStatus> for definition::ConditionStatus {
+    fn from(value: common::ConditionStatus) -> Self {
+        match value {
+            common::ConditionStatus::Unknown => definition::ConditionStatus::Unknown,
+            common::ConditionStatus::True => definition::ConditionStatus::True,
+            common::ConditionStatus::False => definition::ConditionStatus::False,
+        }
+    }
+}
+
+impl From<common::RuntimeKind> for definition::workload::WorkloadKind {
+    fn from(value: common::RuntimeKind) -> Self {
+        match value {
+            common::RuntimeKind::Pod => definition::workload::WorkloadKind::Pod,
+            common::RuntimeKind::Function => definition::workload::WorkloadKind::Function,
+        }
+    }
+}
+
+impl From<definition::workload::WorkloadKind> for common::RuntimeKind {
+    fn from(value: definition::workload::WorkloadKind) -> Self {
+        match value {
+            definition::workload::WorkloadKind::Pod => common::RuntimeKind::Pod,
+            definition::workload::WorkloadKind::Function => common::RuntimeKind::Function,
+        }
+    }
+}
+
+impl From<common::NodeConditionType> for definition::NodeConditionType {
+    fn from(value: common::NodeConditionType) -> Self {
+        match value {
+            common::NodeConditionType::DiskPressure => definition::NodeConditionType::DiskPressure,
+            common::NodeConditionType::KvmUnavailable => {
+                definition::NodeConditionType::KvmUnavailable
+            }
+            common::NodeConditionType::ImageCacheCorrupted => {
+                definition::NodeConditionType::ImageCacheCorrupted
+            }
+            common::NodeConditionType::ClockUnsynced => {
+                definition::NodeConditionType::ClockUnsynced
+            }
+        }
+    }
+}
+
+impl From<definition::NodeConditionType> for common::NodeConditionType {
+    fn from(value: definition::NodeConditionType) -> Self {
+        match value {
+            definition::NodeConditionType::DiskPressure => common::NodeConditionType::DiskPressure,
+            definition::NodeConditionType::KvmUnavailable => {
+                common::NodeConditionType::KvmUnavailable
+            }
+            definition::NodeConditionType::ImageCacheCorrupted => {
+                common::NodeConditionType::ImageCacheCorrupted
+            }
+            definition::NodeConditionType::ClockUnsynced => {
+                common::NodeConditionType::ClockUnsynced
+            }
+        }
+    }
+}
+
+impl From<definition::NodeCondition> for common::NodeCondition {
+    fn from(value: definition::NodeCondition) -> Self {
+        common::NodeCondition {
+            r#type: common::NodeConditionType::from(value.condition_type).into(),
+            active: value.active,
+            message: value.message,
+        }
+    }
+}
+
+impl TryFrom<common::NodeCondition> for definition::NodeCondition {
+    type Error = i32;
+
+    /// Fails with the unknown condition type when it cannot be decoded
+    fn try_from(value: common::NodeCondition) -> Result<Self, Self::Error> {
+        let condition_type =
+            common::NodeConditionType::from_i32(value.r#type).ok_or(value.r#type)?;
+        Ok(definition::NodeCondition {
+            condition_type: condition_type.into(),
+            active: value.active,
+            message: value.message,
+        })
+    }
+}
+
 pub extern crate protobuf;
 
+/// Hash of an instance definition, used by the scheduler and the workers to
+/// compare desired and running instances without resending the definitions
+pub fn definition_hash(definition: &str) -> String {
+    // FNV-1a, unlike the standard hasher it is stable across builds
+    let hash = definition
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
 pub enum WorkloadAction {
     CREATE,
     DELETE,
+    RESTART,
 }
 
 pub struct WorkerStatus(pub common::WorkerStatus);
 impl WorkerStatus {
     pub fn new(identifier: String, instance_id: String, status: InstanceStatus) -> Self {
+        Self::with_conditions(identifier, instance_id, status, Vec::new())
+    }
+
+    /// Build an instance status along with the conditions observed by the node
+    pub fn with_conditions(
+        identifier: String,
+        instance_id: String,
+        status: InstanceStatus,
+        conditions: Vec<InstanceCondition>,
+    ) -> Self {
         Self(common::WorkerStatus {
             identifier,
             host_address: None,
@@ -67,9 +195,29 @@ impl WorkerStatus {
                 instance_id,
                 status: status.into(),
                 metrics: "".to_string(),
+                conditions,
+                worker_id: None,
+                provenance: None,
+                app_status: None,
             })),
         })
     }
+
+    /// Attach the status the instance reported about itself
+    pub fn with_app_status(mut self, app_status: Option<AppStatus>) -> Self {
+        if let Some(Status::Instance(metric)) = &mut self.0.status {
+            metric.app_status = app_status;
+        }
+        self
+    }
+
+    /// Attach the image the instance booted from
+    pub fn with_provenance(mut self, provenance: Option<ImageProvenance>) -> Self {
+        if let Some(Status::Instance(metric)) = &mut self.0.status {
+            metric.provenance = provenance;
+        }
+        self
+    }
 }
 
 impl Deref for WorkerStatus {
@@ -85,6 +233,7 @@ impl From<i32> for WorkloadAction {
         match value {
             0 => WorkloadAction::CREATE,
             1 => WorkloadAction::DELETE,
+            2 => WorkloadAction::RESTART,
             _ => panic!("Unknown workload action"),
         }
     }
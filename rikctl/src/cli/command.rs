@@ -1,4 +1,4 @@
-use crate::cli::resource::{CreateResource, GetMultipleResource};
+use crate::cli::resource::{CreateResource, DeleteResource, DescribeResource, GetMultipleResource};
 use crate::cli::Handler;
 use clap::Args;
 
@@ -32,3 +32,33 @@ impl GetMultipleCommand {
         }
     }
 }
+
+/// Describe a single resource of the cluster.
+#[derive(Debug, Args)]
+pub struct DescribeCommand {
+    #[clap(subcommand)]
+    resource: DescribeResource,
+}
+
+impl DescribeCommand {
+    pub fn command(self) -> Box<dyn Handler> {
+        match self.resource {
+            DescribeResource::Instance(handler) => Box::new(handler),
+        }
+    }
+}
+
+/// Delete resources of the cluster.
+#[derive(Debug, Args)]
+pub struct DeleteCommand {
+    #[clap(subcommand)]
+    resource: DeleteResource,
+}
+
+impl DeleteCommand {
+    pub fn command(self) -> Box<dyn Handler> {
+        match self.resource {
+            DeleteResource::Workloads(handler) => Box::new(handler),
+        }
+    }
+}
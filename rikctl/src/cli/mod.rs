@@ -1,7 +1,10 @@
 pub mod command;
 mod resource;
 
-use crate::cli::command::{CreateCommand, GetMultipleCommand};
+use crate::cli::command::{CreateCommand, DeleteCommand, DescribeCommand, GetMultipleCommand};
+use crate::cli::resource::event::ShowEvents;
+use crate::cli::resource::example::ShowExample;
+use crate::cli::resource::search::CompleteNames;
 use anyhow::Result;
 use async_trait::async_trait;
 use clap::{Parser, Subcommand};
@@ -28,6 +31,17 @@ pub enum Command {
     Create(CreateCommand),
     /// Fetch a resource from a cluster
     Get(GetMultipleCommand),
+    /// Show the details of a resource from a cluster
+    Describe(DescribeCommand),
+    /// Delete resources from a cluster
+    Delete(DeleteCommand),
+    /// Print an example manifest, e.g. `rikctl example function > function.json`
+    Example(ShowExample),
+    /// List what happened in the cluster, such as instances changing status
+    Events(ShowEvents),
+    /// Print the names starting with or containing a text, for shell completions
+    #[clap(hide = true)]
+    Complete(CompleteNames),
 }
 
 /// Command line interface to interact with a RIK Cluster
@@ -43,6 +57,11 @@ impl CommandLineInterface {
         match self.command {
             Command::Create(subcommand) => subcommand.command(),
             Command::Get(subcommand) => subcommand.command(),
+            Command::Describe(subcommand) => subcommand.command(),
+            Command::Delete(subcommand) => subcommand.command(),
+            Command::Example(handler) => Box::new(handler),
+            Command::Events(handler) => Box::new(handler),
+            Command::Complete(handler) => Box::new(handler),
         }
     }
 }
@@ -0,0 +1,158 @@
+use crate::cli::resource::DisplayResource;
+use crate::cli::Handler;
+use crate::core::client::{Client, EventClient, Watch};
+use crate::core::config::Configuration;
+use crate::core::event::{Event, WatchMessage};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use clap::Args;
+use prettytable::row;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Delay before following the events again once a watch is cut
+const WATCH_RETRY_DELAY: Duration = Duration::from_secs(2);
+/// Attempts to follow the events again once the controller shut down, it
+/// is given that long to be back
+const MAX_RECONNECT_ATTEMPTS: u32 = 30;
+
+#[derive(Debug, Args)]
+pub struct ShowEvents {
+    /// Only show the events after this event id, or from this RFC 3339 date
+    #[clap(long)]
+    pub since: Option<String>,
+    /// Only show the events of this element, e.g. an instance id
+    #[clap(long)]
+    pub element: Option<String>,
+    /// Keep waiting for new events
+    #[clap(short, long)]
+    pub watch: bool,
+}
+
+#[async_trait]
+impl Handler for ShowEvents {
+    async fn handler(&self) -> Result<()> {
+        let config = Configuration::load()?;
+        let client = Client::init(config.cluster);
+        let (events, mut next) = client
+            .get_all_events(self.since.as_deref(), self.element.as_deref())
+            .await?;
+        events.into_table().printstd();
+        if !self.watch {
+            return Ok(());
+        }
+
+        // Watches resume after the last event shown, the events missed
+        // while they were cut are replayed or, when too many, listed again
+        let mut reconnect_attempts = 0;
+        'watch: loop {
+            let watch = match client.watch_events(next, self.element.as_deref()).await {
+                Ok(watch) => watch,
+                // The controller which shut down may not be back yet
+                Err(e) if (1..MAX_RECONNECT_ATTEMPTS).contains(&reconnect_attempts) => {
+                    warn!("Cannot follow the events again yet: {}", e);
+                    reconnect_attempts += 1;
+                    tokio::time::sleep(WATCH_RETRY_DELAY).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            match watch {
+                Watch::Unavailable(retry_after) => {
+                    reconnect_attempts += 1;
+                    if reconnect_attempts > MAX_RECONNECT_ATTEMPTS {
+                        return Err(anyhow!("The controller takes no watch"));
+                    }
+                    tokio::time::sleep(retry_after).await;
+                    continue;
+                }
+                Watch::Expired => {
+                    let (events, last) = client
+                        .get_all_events(Some(&next.to_string()), self.element.as_deref())
+                        .await?;
+                    events.iter().for_each(print_event);
+                    next = last;
+                    continue;
+                }
+                Watch::Stream(mut stream) => loop {
+                    match stream.next().await {
+                        Ok(Some(WatchMessage::Event(event))) => {
+                            print_event(&event);
+                            next = event.id;
+                            reconnect_attempts = 0;
+                        }
+                        Ok(Some(WatchMessage::Bookmark(reason))) => {
+                            info!(
+                                "Watch closed by the controller, {}, following again",
+                                reason
+                            );
+                            reconnect_attempts = 1;
+                            continue 'watch;
+                        }
+                        Ok(Some(WatchMessage::Closed(reason))) => {
+                            warn!("Watch closed by the controller: {}", reason);
+                            break;
+                        }
+                        Ok(None) => break,
+                        Err(e) => {
+                            warn!("Watch cut: {}", e);
+                            break;
+                        }
+                    }
+                },
+            }
+            tokio::time::sleep(WATCH_RETRY_DELAY).await;
+        }
+    }
+}
+
+fn print_event(event: &Event) {
+    println!(
+        "{}  {}  {}  {}",
+        event.created_at, event.element_id, event.reason, event.message
+    );
+}
+
+impl DisplayResource for Vec<Event> {
+    #[tracing::instrument(name = "DisplayResource::event::into_table", skip(self))]
+    fn into_table(&self) -> prettytable::Table {
+        let mut table = Self::new_table();
+        table.set_titles(row!["DATE", "ELEMENT", "REASON", "MESSAGE"]);
+        if self.is_empty() {
+            table.add_row(row!["", "", "", ""]);
+        }
+        for event in self {
+            table.add_row(row![
+                event.created_at,
+                event.element_id,
+                event.reason,
+                event.message
+            ]);
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn display_events_table() {
+        let events = vec![Event {
+            id: 1,
+            created_at: "2023-06-01T02:00:00.000000Z".to_string(),
+            element_id: "quiet-river-1234".to_string(),
+            reason: "Running".to_string(),
+            message: "Status changed from Creating to Running".to_string(),
+        }];
+
+        let table = events.into_table();
+
+        let expected_output = r#" DATE                         ELEMENT           REASON   MESSAGE 
+ 2023-06-01T02:00:00.000000Z  quiet-river-1234  Running  Status changed from Creating to Running 
+"#;
+        assert_eq!(table.to_string(), expected_output);
+    }
+}
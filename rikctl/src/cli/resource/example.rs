@@ -0,0 +1,68 @@
+use crate::cli::resource::DisplayResource;
+use crate::cli::Handler;
+use crate::core::client::{Client, ExampleClient};
+use crate::core::config::Configuration;
+use crate::core::example::ExampleSummary;
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::Args;
+use prettytable::row;
+
+#[derive(Debug, Args)]
+pub struct ShowExample {
+    /// Name of the example, the available examples are listed when omitted
+    pub name: Option<String>,
+}
+
+#[async_trait]
+impl Handler for ShowExample {
+    async fn handler(&self) -> Result<()> {
+        let config = Configuration::load()?;
+        let client = Client::init(config.cluster);
+        match &self.name {
+            Some(name) => {
+                // Printed as served so it can be redirected to a file
+                println!("{}", client.get_example(name).await?.trim_end());
+            }
+            None => client.get_examples().await?.into_table().printstd(),
+        }
+        Ok(())
+    }
+}
+
+impl DisplayResource for Vec<ExampleSummary> {
+    #[tracing::instrument(name = "DisplayResource::example::into_table", skip(self))]
+    fn into_table(&self) -> prettytable::Table {
+        let mut table = Self::new_table();
+        table.set_titles(row!["NAME", "KIND", "DESCRIPTION"]);
+        if self.is_empty() {
+            table.add_row(row!["", "", ""]);
+        }
+        for example in self {
+            table.add_row(row![example.name, example.kind, example.description]);
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn display_examples_table() {
+        let examples = vec![ExampleSummary {
+            name: "function".to_string(),
+            kind: "Function".to_string(),
+            description: "Function exposed on a node port".to_string(),
+        }];
+
+        let table = examples.into_table();
+
+        let expected_output = r#" NAME      KIND      DESCRIPTION 
+ function  Function  Function exposed on a node port 
+"#;
+        assert_eq!(table.to_string(), expected_output);
+    }
+}
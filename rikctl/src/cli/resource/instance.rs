@@ -1,10 +1,10 @@
-use crate::core::client::{Client, ResponseEntity};
-use crate::core::instance::Instance;
+use crate::core::client::{Client, EventClient, ResponseEntity};
+use crate::core::instance::{Condition, EnvVariable, Instance, InstanceOverrides};
 use crate::{
     cli::Handler,
     core::{client::InstanceClient, config::Configuration},
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use clap::Args;
 use prettytable::row;
@@ -17,6 +17,42 @@ pub struct CreateInstance {
 
     #[clap(short, long)]
     pub replicas: Option<usize>,
+
+    /// Override an environment variable for this instance only (NAME=VALUE)
+    #[clap(short, long)]
+    pub env: Vec<String>,
+
+    /// Override the image tag for this instance only
+    #[clap(long)]
+    pub image_tag: Option<String>,
+}
+
+impl CreateInstance {
+    fn overrides(&self) -> Result<Option<InstanceOverrides>> {
+        let env = self
+            .env
+            .iter()
+            .map(|variable| match variable.split_once('=') {
+                Some((name, value)) => Ok(EnvVariable {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                }),
+                None => Err(anyhow!(
+                    "Invalid environment variable {}, expected NAME=VALUE",
+                    variable
+                )),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let overrides = InstanceOverrides {
+            env: Some(env).filter(|env| !env.is_empty()),
+            image_tag: self.image_tag.clone(),
+        };
+        if overrides.fields().is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(overrides))
+    }
 }
 
 #[async_trait]
@@ -26,7 +62,7 @@ impl Handler for CreateInstance {
         let config = Configuration::load()?;
 
         Client::init(config.cluster)
-            .create_instance(&self.workload_id, &self.replicas)
+            .create_instance(&self.workload_id, &self.replicas, &self.overrides()?)
             .await?;
 
         println!(
@@ -53,16 +89,92 @@ impl Handler for GetMultipleInstance {
     }
 }
 
+#[derive(Debug, Args)]
+pub struct DescribeInstance {
+    /// ID or name of the instance
+    pub instance: String,
+}
+
+#[async_trait]
+impl Handler for DescribeInstance {
+    async fn handler(&self) -> Result<()> {
+        let config = Configuration::load()?;
+        let client = Client::init(config.cluster);
+        let instance = client.get_instance(&self.instance).await?;
+        let (events, _) = client.get_all_events(None, Some(&instance.id)).await?;
+
+        println!("Name:   {}", instance.name);
+        println!("Status: {}", instance.value.status);
+        if let Some(correlation_id) = &instance.value.correlation_id {
+            println!("Correlation id: {}", correlation_id);
+        }
+        if let Some(provenance) = &instance.value.provenance {
+            println!("Image:");
+            println!("  URL:           {}", provenance.resolved_url);
+            println!("  SHA-256:       {}", provenance.sha256);
+            println!("  Downloaded at: {}", provenance.downloaded_at);
+            if let Some(etag) = &provenance.etag {
+                println!("  ETag:          {}", etag);
+            }
+            if let Some(last_modified) = &provenance.last_modified {
+                println!("  Last-Modified: {}", last_modified);
+            }
+        }
+        println!("Conditions:");
+        instance.value.conditions.into_table().printstd();
+        println!("Events:");
+        events.into_table().printstd();
+        Ok(())
+    }
+}
+
+impl DisplayResource for Vec<Condition> {
+    #[tracing::instrument(name = "DisplayResource::condition::into_table", skip(self))]
+    fn into_table(&self) -> prettytable::Table {
+        let mut table = Self::new_table();
+        table.set_titles(row![
+            "TYPE",
+            "STATUS",
+            "REASON",
+            "MESSAGE",
+            "LAST TRANSITION"
+        ]);
+        if self.is_empty() {
+            table.add_row(row!["", "", "", "", ""]);
+        }
+        for condition in self {
+            table.add_row(row![
+                condition.condition_type,
+                condition.status,
+                condition.reason,
+                condition.message,
+                condition.last_transition_time
+            ]);
+        }
+        table
+    }
+}
+
 impl DisplayResource for Vec<ResponseEntity<Instance>> {
     #[tracing::instrument(name = "DisplayResource::instance::into_table", skip(self))]
     fn into_table(&self) -> prettytable::Table {
         let mut table = Self::new_table();
-        table.set_titles(row!["ID", "NAME", "STATUS"]);
+        table.set_titles(row!["ID", "NAME", "STATUS", "OVERRIDES"]);
         if self.is_empty() {
-            table.add_row(row!["", "", ""]);
+            table.add_row(row!["", "", "", ""]);
         }
         for instance in self {
-            table.add_row(row![instance.id, instance.name, instance.value.status]);
+            // Make drift from the workload definition visible
+            let overrides = match &instance.value.overrides {
+                Some(overrides) => overrides.fields().join(","),
+                None => String::from("-"),
+            };
+            table.add_row(row![
+                instance.id,
+                instance.name,
+                instance.value.status,
+                overrides
+            ]);
         }
         table
     }
@@ -75,6 +187,10 @@ mod tests {
     fn create_instance() -> Instance {
         Instance {
             status: "Running".to_string(),
+            overrides: None,
+            conditions: vec![],
+            correlation_id: None,
+            provenance: None,
         }
     }
 
@@ -89,14 +205,40 @@ mod tests {
             ResponseEntity {
                 id: "abcd".to_string(),
                 name: "instance-2".to_string(),
-                value: create_instance(),
+                value: Instance {
+                    status: "Running".to_string(),
+                    overrides: Some(InstanceOverrides {
+                        env: None,
+                        image_tag: Some("debug".to_string()),
+                    }),
+                    conditions: vec![],
+                    correlation_id: None,
+                    provenance: None,
+                },
             },
         ];
 
         let table = instances.into_table();
-        let expected_output = r#" ID    NAME        STATUS 
- abde  instance-1  Running 
- abcd  instance-2  Running 
+        let expected_output = r#" ID    NAME        STATUS   OVERRIDES 
+ abde  instance-1  Running  - 
+ abcd  instance-2  Running  image_tag 
+"#;
+        assert_eq!(table.to_string(), expected_output);
+    }
+
+    #[test]
+    fn display_conditions_table() {
+        let conditions = vec![Condition {
+            condition_type: "Ready".to_string(),
+            status: "False".to_string(),
+            reason: "Failed".to_string(),
+            message: "".to_string(),
+            last_transition_time: "2023-05-01T10:00:00+00:00".to_string(),
+        }];
+
+        let table = conditions.into_table();
+        let expected_output = r#" TYPE   STATUS  REASON  MESSAGE  LAST TRANSITION 
+ Ready  False   Failed           2023-05-01T10:00:00+00:00 
 "#;
         assert_eq!(table.to_string(), expected_output);
     }
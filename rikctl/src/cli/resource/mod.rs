@@ -1,8 +1,11 @@
+pub mod event;
+pub mod example;
 mod instance;
+pub mod search;
 mod workload;
 
-use crate::cli::resource::instance::{CreateInstance, GetMultipleInstance};
-use crate::cli::resource::workload::{CreateWorkload, GetMultipleWorkload};
+use crate::cli::resource::instance::{CreateInstance, DescribeInstance, GetMultipleInstance};
+use crate::cli::resource::workload::{CreateWorkload, DeleteWorkloads, GetMultipleWorkload};
 use clap::Subcommand;
 use prettytable::{format, Table};
 
@@ -20,6 +23,18 @@ pub enum GetMultipleResource {
     Workloads(GetMultipleWorkload),
 }
 
+#[derive(Debug, Subcommand)]
+pub enum DescribeResource {
+    /// Describe an instance and its conditions
+    Instance(DescribeInstance),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DeleteResource {
+    /// Delete workloads by id or by label selector
+    Workloads(DeleteWorkloads),
+}
+
 /// Trait which defines how resources should be displayed
 trait DisplayResource<T = Self>
 where
@@ -0,0 +1,51 @@
+use crate::cli::Handler;
+use crate::core::client::{Client, SearchClient};
+use crate::core::config::Configuration;
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::{Args, ValueEnum};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CompletedResource {
+    Workloads,
+    Instances,
+}
+
+impl CompletedResource {
+    /// Kind of the elements searched, as named by the search route
+    fn search_kind(self) -> &'static str {
+        match self {
+            CompletedResource::Workloads => "workload",
+            CompletedResource::Instances => "instance",
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct CompleteNames {
+    /// Resources whose names are completed
+    #[clap(value_enum)]
+    pub resource: CompletedResource,
+    /// Text typed so far
+    pub text: String,
+}
+
+#[async_trait]
+impl Handler for CompleteNames {
+    async fn handler(&self) -> Result<()> {
+        // Nothing is completed before the first character, listing every name
+        // is the job of `rikctl get`
+        if self.text.is_empty() {
+            return Ok(());
+        }
+        let config = Configuration::load()?;
+        let client = Client::init(config.cluster);
+        for found in client
+            .search_names(self.resource.search_kind(), &self.text)
+            .await?
+        {
+            println!("{}", found.name);
+        }
+        Ok(())
+    }
+}
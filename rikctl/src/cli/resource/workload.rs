@@ -1,13 +1,14 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use clap::Args;
+use clap::{Args, ValueEnum};
 use prettytable::row;
+use std::io::{self, Write};
 use std::path::PathBuf;
 
 use crate::cli::Handler;
 use crate::core::client::{Client, ResponseEntity, WorkloadClient};
 use crate::core::config::Configuration;
-use crate::core::workload::Workload;
+use crate::core::workload::{DeleteCollection, DeleteResult, Workload};
 
 use super::DisplayResource;
 
@@ -41,8 +42,18 @@ impl Handler for CreateWorkload {
     }
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum WorkloadOutput {
+    /// Add the protection of each workload
+    Wide,
+}
+
 #[derive(Debug, Args)]
-pub struct GetMultipleWorkload {}
+pub struct GetMultipleWorkload {
+    /// Format of the list
+    #[clap(short, long, value_enum)]
+    pub output: Option<WorkloadOutput>,
+}
 
 #[async_trait]
 impl Handler for GetMultipleWorkload {
@@ -51,12 +62,29 @@ impl Handler for GetMultipleWorkload {
         let config = Configuration::load()?;
         let workloads = Client::init(config.cluster).get_workloads().await?;
 
-        let table = workloads.into_table();
+        let table = match self.output {
+            Some(WorkloadOutput::Wide) => wide_table(&workloads),
+            None => workloads.into_table(),
+        };
         table.printstd();
         Ok(())
     }
 }
 
+/// Table of the workloads along with their protection
+fn wide_table(workloads: &Vec<ResponseEntity<Workload>>) -> prettytable::Table {
+    let mut table = workloads.into_table();
+    table.set_titles(row!["ID", "NAME", "KIND", "CONTAINERS", "PROTECTED"]);
+    for (row, workload) in table.row_iter_mut().zip(workloads) {
+        row.add_cell(prettytable::Cell::new(if workload.value.protected {
+            "yes"
+        } else {
+            "no"
+        }));
+    }
+    table
+}
+
 impl DisplayResource for Vec<ResponseEntity<Workload>> {
     #[tracing::instrument(name = "DisplayResource::workload::into_table", skip(self))]
     fn into_table(&self) -> prettytable::Table {
@@ -77,6 +105,98 @@ impl DisplayResource for Vec<ResponseEntity<Workload>> {
     }
 }
 
+#[derive(Debug, Args)]
+pub struct DeleteWorkloads {
+    /// IDs of the workloads to delete
+    pub ids: Vec<String>,
+
+    /// Delete the workloads having all these labels, e.g. `env=scratch,team=web`
+    #[clap(short = 'l', long)]
+    pub selector: Option<String>,
+
+    /// Delete without asking for a confirmation
+    #[clap(short, long)]
+    pub yes: bool,
+
+    /// Also delete protected workloads, the deletion is recorded as an event
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[async_trait]
+impl Handler for DeleteWorkloads {
+    #[tracing::instrument(name = "DeleteWorkloads::handler", skip(self))]
+    async fn handler(&self) -> Result<()> {
+        if self.ids.is_empty() && self.selector.is_none() {
+            return Err(anyhow!("Give workload IDs or a label selector"));
+        }
+        let config = Configuration::load()?;
+        let client = Client::init(config.cluster);
+        let request = DeleteCollection {
+            ids: self.ids.clone(),
+            selector: self.selector.clone(),
+        };
+
+        let matched = client
+            .delete_workloads(&request, true, None, self.force)
+            .await?;
+        let count = matched
+            .iter()
+            .filter(|result| result.status == "matched")
+            .count();
+        matched.into_table().printstd();
+        if count == 0 {
+            println!("No workload to delete");
+            return Ok(());
+        }
+
+        if !self.yes {
+            print!("Delete {} workloads? [y/N] ", count);
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                println!("Aborted");
+                return Ok(());
+            }
+        }
+
+        let results = client
+            .delete_workloads(&request, false, Some(count), self.force)
+            .await?;
+        results.into_table().printstd();
+
+        let failed = results
+            .iter()
+            .filter(|result| result.status != "deleted")
+            .count();
+        if failed > 0 {
+            return Err(anyhow!("{} workloads were not deleted", failed));
+        }
+        Ok(())
+    }
+}
+
+impl DisplayResource for Vec<DeleteResult> {
+    #[tracing::instrument(name = "DisplayResource::delete_result::into_table", skip(self))]
+    fn into_table(&self) -> prettytable::Table {
+        let mut table = Self::new_table();
+        table.set_titles(row!["ID", "NAME", "STATUS", "MESSAGE"]);
+        if self.is_empty() {
+            table.add_row(row!["", "", "", ""]);
+        }
+        for result in self {
+            table.add_row(row![
+                result.id,
+                result.name,
+                result.status,
+                result.message.as_deref().unwrap_or_default()
+            ]);
+        }
+        table
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -88,6 +208,9 @@ mod tests {
             kind: "Workload".to_string(),
             api_version: "v1".to_string(),
             name: name.to_string(),
+            namespace: None,
+            labels: Default::default(),
+            protected: false,
             spec: Spec { containers: vec![] },
         }
     }
@@ -111,6 +234,56 @@ mod tests {
         let expected_output = r#" ID    NAME        KIND      CONTAINERS 
  abde  workload-1  Workload  0 
  abcd  workload-2  Workload  0 
+"#;
+        assert_eq!(table.to_string(), expected_output);
+    }
+
+    #[test]
+    fn display_wide_workloads_table() {
+        let mut protected = create_workload("workload-2");
+        protected.protected = true;
+        let workloads = vec![
+            ResponseEntity {
+                id: "abde".to_string(),
+                name: "workload-1".to_string(),
+                value: create_workload("workload-1"),
+            },
+            ResponseEntity {
+                id: "abcd".to_string(),
+                name: "workload-2".to_string(),
+                value: protected,
+            },
+        ];
+
+        let table = wide_table(&workloads);
+        let expected_output = r#" ID    NAME        KIND      CONTAINERS  PROTECTED 
+ abde  workload-1  Workload  0           no 
+ abcd  workload-2  Workload  0           yes 
+"#;
+        assert_eq!(table.to_string(), expected_output);
+    }
+
+    #[test]
+    fn display_delete_results_table() {
+        let results = vec![
+            DeleteResult {
+                id: "abde".to_string(),
+                name: "workload-1".to_string(),
+                status: "deleted".to_string(),
+                message: None,
+            },
+            DeleteResult {
+                id: "".to_string(),
+                name: "workload-2".to_string(),
+                status: "not_found".to_string(),
+                message: Some("Workload not found".to_string()),
+            },
+        ];
+
+        let table = results.into_table();
+        let expected_output = r#" ID    NAME        STATUS     MESSAGE 
+ abde  workload-1  deleted     
+       workload-2  not_found  Workload not found 
 "#;
         assert_eq!(table.to_string(), expected_output);
     }
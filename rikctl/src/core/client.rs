@@ -1,13 +1,27 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use reqwest::Client as HttpClient;
+use reqwest::{Client as HttpClient, RequestBuilder};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
 use crate::core::config;
-use crate::core::workload::Workload;
+use crate::core::event::{Event, EventPage, EventStreamParser, WatchMessage};
+use crate::core::example::ExampleSummary;
+use crate::core::workload::{DeleteCollection, DeleteResult, Workload};
+use std::collections::VecDeque;
+use std::time::Duration;
 
-use super::instance::Instance;
+use super::instance::{Instance, InstanceOverrides};
+
+/// Header carrying the namespace of the current context
+const NAMESPACE_HEADER: &str = "X-Rik-Namespace";
+/// Header naming who overrides the protection of a workload
+const ACTOR_HEADER: &str = "X-Rik-Actor";
+/// Error code of the changes refused by a read-only controller
+const READ_ONLY_CODE: &str = "ReadOnly";
+/// Wait before opening again a watch refused without a `Retry-After`
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
 
 /// `ResponseEntity` holds data about an entity
 /// returned by the API.
@@ -18,20 +32,125 @@ pub struct ResponseEntity<T> {
     pub value: T,
 }
 
+/// `ListPage` is a page of a paginated list, along with the amount of
+/// entities listed across every page.
+#[derive(Debug, Deserialize)]
+pub struct ListPage<T> {
+    pub items: Vec<ResponseEntity<T>>,
+    pub total: usize,
+}
+
 #[async_trait]
 pub trait WorkloadClient {
     async fn get_workloads(&self) -> Result<Vec<ResponseEntity<Workload>>>;
     async fn create_workload(&self, workload: &Workload) -> Result<String>;
     async fn delete_workload(&self, workload: &str) -> Result<String>;
+    async fn delete_workloads(
+        &self,
+        request: &DeleteCollection,
+        dry_run: bool,
+        confirm_count: Option<usize>,
+        override_protection: bool,
+    ) -> Result<Vec<DeleteResult>>;
 }
 
 #[async_trait]
 pub trait InstanceClient {
     async fn get_instances(&self) -> Result<Vec<ResponseEntity<Instance>>>;
-    async fn create_instance(&self, workload_id: &str, replicas: &Option<usize>) -> Result<()>;
+    async fn get_instance(&self, instance: &str) -> Result<ResponseEntity<Instance>>;
+    async fn create_instance(
+        &self,
+        workload_id: &str,
+        replicas: &Option<usize>,
+        overrides: &Option<InstanceOverrides>,
+    ) -> Result<()>;
     async fn delete_instance(&self, workload_id: &str) -> Result<String>;
 }
 
+/// `NameMatch` is a workload or an instance found by a search.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct NameMatch {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResults {
+    items: Vec<NameMatch>,
+}
+
+#[async_trait]
+pub trait SearchClient {
+    /// Workloads or instances of the context namespace whose name contains
+    /// `query`, those starting with it first
+    async fn search_names(&self, kind: &str, query: &str) -> Result<Vec<NameMatch>>;
+}
+
+#[async_trait]
+pub trait ExampleClient {
+    async fn get_examples(&self) -> Result<Vec<ExampleSummary>>;
+    /// Manifest of an example, as served by the controller
+    async fn get_example(&self, name: &str) -> Result<String>;
+}
+
+#[async_trait]
+pub trait EventClient {
+    /// Page of events after the cursor, an event id or an RFC 3339 date
+    async fn get_events(&self, since: Option<&str>, element_id: Option<&str>) -> Result<EventPage>;
+
+    /// Every event after the cursor, going through the pages
+    async fn get_all_events(
+        &self,
+        since: Option<&str>,
+        element_id: Option<&str>,
+    ) -> Result<(Vec<Event>, i64)> {
+        let mut page = self.get_events(since, element_id).await?;
+        let mut events = vec![];
+        while !page.events.is_empty() {
+            events.append(&mut page.events);
+            page = self
+                .get_events(Some(&page.next.to_string()), element_id)
+                .await?;
+        }
+        Ok((events, page.next))
+    }
+
+    /// Follow the events after the event `resume_from`
+    async fn watch_events(&self, resume_from: i64, element_id: Option<&str>) -> Result<Watch>;
+}
+
+/// `Watch` is the answer of the controller to a watch of the events.
+pub enum Watch {
+    Stream(Box<EventStream>),
+    /// The events missed are too many to be replayed, they must be listed again
+    Expired,
+    /// The controller takes no watch for now, e.g. while it shuts down, it
+    /// is to be watched again after the delay
+    Unavailable(Duration),
+}
+
+/// `EventStream` gives the messages of a watch as they come.
+pub struct EventStream {
+    response: reqwest::Response,
+    parser: EventStreamParser,
+    pending: VecDeque<WatchMessage>,
+}
+
+impl EventStream {
+    /// Next message, None once the controller ended the stream
+    pub async fn next(&mut self) -> Result<Option<WatchMessage>> {
+        loop {
+            if let Some(message) = self.pending.pop_front() {
+                return Ok(Some(message));
+            }
+            match self.response.chunk().await? {
+                Some(chunk) => self.pending.extend(self.parser.feed(&chunk)),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
 /// `Client` provides the ability to interact
 /// with the cluster controller by using HTTP Protocol.
 #[derive(Debug)]
@@ -43,6 +162,11 @@ pub struct Client {
 
     /// The internal HTTP client used to make requests.
     http_client: HttpClient,
+
+    /// Namespace of the current context, sent with every request.
+    namespace: Option<String>,
+    /// Token of the controller, sent with every request.
+    token: Option<String>,
 }
 
 impl Client {
@@ -50,6 +174,8 @@ impl Client {
         Self {
             endpoint: config.server,
             http_client: HttpClient::new(),
+            namespace: config.namespace,
+            token: config.token,
         }
     }
 
@@ -57,26 +183,105 @@ impl Client {
     pub fn endpoint(&self, path: &str) -> String {
         format!("{}/{}", self.endpoint, path)
     }
+
+    /// Add the context namespace and token to a request
+    fn with_namespace(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        };
+        match &self.namespace {
+            Some(namespace) => request.header(NAMESPACE_HEADER, namespace),
+            None => request,
+        }
+    }
+
+    fn get(&self, path: &str) -> RequestBuilder {
+        self.with_namespace(self.http_client.get(self.endpoint(path)))
+    }
+
+    fn post(&self, path: &str) -> RequestBuilder {
+        self.with_namespace(self.http_client.post(self.endpoint(path)))
+    }
+
+    /// Every entity of a paginated list, going through the pages
+    async fn get_all<T: DeserializeOwned>(&self, path: &str) -> Result<Vec<ResponseEntity<T>>> {
+        let mut entities = vec![];
+        loop {
+            let response = self
+                .get(&format!("{}?offset={}", path, entities.len()))
+                .send()
+                .await?;
+            let status = response.status();
+            let text = response.text().await?;
+            if !status.is_success() {
+                return Err(anyhow!("{}", text));
+            }
+            let mut page: ListPage<T> = serde_json::from_str(&text)?;
+            // Entities deleted meanwhile may leave fewer than the total
+            if page.items.is_empty() {
+                return Ok(entities);
+            }
+            entities.append(&mut page.items);
+            if entities.len() >= page.total {
+                return Ok(entities);
+            }
+        }
+    }
+}
+
+/// Fail with a hint when the controller refused a change as it is read-only
+async fn refuse_read_only(response: reqwest::Response) -> Result<reqwest::Response> {
+    if response.status() != reqwest::StatusCode::SERVICE_UNAVAILABLE {
+        return Ok(response);
+    }
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    let text = response.text().await?;
+    Err(anyhow!(
+        "{}",
+        read_only_hint(&text, retry_after.as_deref()).unwrap_or(text)
+    ))
+}
+
+/// Delay given by a `Retry-After` header in seconds
+fn retry_delay(retry_after: Option<&str>) -> Duration {
+    retry_after
+        .and_then(|seconds| seconds.trim().parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_RETRY_AFTER)
+}
+
+fn read_only_hint(body: &str, retry_after: Option<&str>) -> Option<String> {
+    let error: Value = serde_json::from_str(body).ok()?;
+    if error["code"] != READ_ONLY_CODE {
+        return None;
+    }
+    Some(format!(
+        "{}\nHint: the controller only accepts reads for now, retry in {} seconds or ask an administrator to leave the read-only mode",
+        error["message"].as_str().unwrap_or_default(),
+        retry_after.unwrap_or("a few")
+    ))
 }
 
 #[async_trait]
 impl WorkloadClient for Client {
     async fn get_workloads(&self) -> Result<Vec<ResponseEntity<Workload>>> {
-        let endpoint = self.endpoint("api/v0/workloads.list");
-        let response = self.http_client.get(endpoint).send().await?;
-        let data: Vec<ResponseEntity<Workload>> = serde_json::from_str(&response.text().await?)?;
-        Ok(data)
+        self.get_all("api/v0/workloads.list").await
     }
 
     async fn create_workload(&self, workload: &Workload) -> Result<String> {
-        let endpoint = self.endpoint("api/v0/workloads.create");
+        let endpoint = "api/v0/workloads.create";
 
         let response = self
-            .http_client
             .post(endpoint)
             .body(serde_json::to_string(workload)?)
             .send()
             .await?;
+        let response = refuse_read_only(response).await?;
 
         let json: Value = serde_json::from_str(&response.text().await?)?;
         Ok(json["id"].to_string())
@@ -85,20 +290,81 @@ impl WorkloadClient for Client {
     async fn delete_workload(&self, _workload_name: &str) -> Result<String> {
         Ok(String::from("Not implemented yet"))
     }
+
+    async fn delete_workloads(
+        &self,
+        request: &DeleteCollection,
+        dry_run: bool,
+        confirm_count: Option<usize>,
+        override_protection: bool,
+    ) -> Result<Vec<DeleteResult>> {
+        let mut query = vec![];
+        if dry_run {
+            query.push(String::from("dry_run=true"));
+        } else if let Some(count) = confirm_count {
+            query.push(format!("confirm_count={}", count));
+        }
+        if override_protection {
+            query.push(String::from("override_protection=true"));
+        }
+        let mut endpoint = String::from("api/v0/workloads.delete_collection");
+        if !query.is_empty() {
+            endpoint.push_str(&format!("?{}", query.join("&")));
+        }
+
+        let mut builder = self.post(&endpoint);
+        // The controller records who overrode the protection of a workload
+        if let (true, Ok(user)) = (override_protection, std::env::var("USER")) {
+            builder = builder.header(ACTOR_HEADER, user);
+        }
+        let response = builder.body(serde_json::to_string(request)?).send().await?;
+        let response = refuse_read_only(response).await?;
+        let status = response.status();
+        let text = response.text().await?;
+        // An aborted atomic deletion still reports the result of every workload
+        if !status.is_success() && status != reqwest::StatusCode::CONFLICT {
+            return Err(anyhow!("{}", text));
+        }
+
+        let mut json: Value = serde_json::from_str(&text)?;
+        Ok(serde_json::from_value(json["results"].take())?)
+    }
 }
 #[async_trait]
 impl InstanceClient for Client {
     async fn get_instances(&self) -> Result<Vec<ResponseEntity<Instance>>> {
-        let endpoint = self.endpoint("api/v0/instances.list");
-        let response = self.http_client.get(endpoint).send().await?;
-        let data: Vec<ResponseEntity<Instance>> = serde_json::from_str(&response.text().await?)?;
-        Ok(data)
+        self.get_all("api/v0/instances.list").await
+    }
+
+    async fn get_instance(&self, instance: &str) -> Result<ResponseEntity<Instance>> {
+        // Conditions are only exposed by the v1 API
+        let data: Vec<ResponseEntity<Instance>> = self.get_all("api/v1/instances.list").await?;
+        let id = data
+            .into_iter()
+            .find(|entity| entity.id == instance || entity.name == instance)
+            .map(|entity| entity.id)
+            .ok_or_else(|| anyhow!("Instance {} not found", instance))?;
+
+        // The whole instance, with the image it booted from
+        let response = self
+            .get(&format!("api/v0/instances.get/{}", id))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Instance {} not found", instance));
+        }
+        Ok(serde_json::from_str(&response.text().await?)?)
     }
 
-    async fn create_instance(&self, workload_id: &str, replicas: &Option<usize>) -> Result<()> {
-        let endpoint = self.endpoint("api/v0/instances.create");
+    async fn create_instance(
+        &self,
+        workload_id: &str,
+        replicas: &Option<usize>,
+        overrides: &Option<InstanceOverrides>,
+    ) -> Result<()> {
+        let endpoint = "api/v0/instances.create";
 
-        let body = match replicas {
+        let mut body = match replicas {
             Some(replicas) => json!({
                 "workload_id": workload_id,
                 "replicas": replicas,
@@ -107,31 +373,135 @@ impl InstanceClient for Client {
             "workload_id": workload_id,
             }),
         };
+        if let Some(overrides) = overrides {
+            body["overrides"] = serde_json::to_value(overrides)?;
+        }
 
-        let _response = self
-            .http_client
-            .post(endpoint)
-            .body(body.to_string())
-            .send()
-            .await?;
+        let response = self.post(endpoint).body(body.to_string()).send().await?;
+        refuse_read_only(response).await?;
         Ok(())
     }
 
     async fn delete_instance(&self, workload_id: &str) -> Result<String> {
-        let endpoint = self.endpoint("api/v0/instances.delete");
+        let endpoint = "api/v0/instances.delete";
 
         let body = json!({
             "id": workload_id,
         });
 
+        let response = self.post(endpoint).body(body.to_string()).send().await?;
+        let response = refuse_read_only(response).await?;
+
+        let json: Value = serde_json::from_str(&response.text().await?)?;
+        Ok(json.to_string())
+    }
+}
+
+#[async_trait]
+impl ExampleClient for Client {
+    async fn get_examples(&self) -> Result<Vec<ExampleSummary>> {
+        let response = self.get("api/v0/examples").send().await?;
+        Ok(serde_json::from_str(&response.text().await?)?)
+    }
+
+    async fn get_example(&self, name: &str) -> Result<String> {
         let response = self
-            .http_client
-            .post(endpoint)
-            .body(body.to_string())
+            .get(&format!("api/v0/examples/{}", name))
             .send()
             .await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow!("{}", text));
+        }
+        Ok(text)
+    }
+}
 
-        let json: Value = serde_json::from_str(&response.text().await?)?;
-        Ok(json.to_string())
+#[async_trait]
+impl SearchClient for Client {
+    async fn search_names(&self, kind: &str, query: &str) -> Result<Vec<NameMatch>> {
+        let response = self
+            .get("api/v0/search")
+            .query(&[("kind", kind), ("q", query)])
+            .send()
+            .await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow!("{}", text));
+        }
+        Ok(serde_json::from_str::<SearchResults>(&text)?.items)
+    }
+}
+
+#[async_trait]
+impl EventClient for Client {
+    async fn get_events(&self, since: Option<&str>, element_id: Option<&str>) -> Result<EventPage> {
+        let mut query = vec![];
+        if let Some(since) = since {
+            query.push(("since", since));
+        }
+        if let Some(element_id) = element_id {
+            query.push(("element_id", element_id));
+        }
+        let response = self.get("api/v0/events.list").query(&query).send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow!("{}", text));
+        }
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    async fn watch_events(&self, resume_from: i64, element_id: Option<&str>) -> Result<Watch> {
+        let resume_from = resume_from.to_string();
+        let mut query = vec![("resume_from", resume_from.as_str())];
+        if let Some(element_id) = element_id {
+            query.push(("element_id", element_id));
+        }
+        let response = self.get("api/v0/events.watch").query(&query).send().await?;
+        let status = response.status();
+        if status == reqwest::StatusCode::GONE {
+            return Ok(Watch::Expired);
+        }
+        if status == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok());
+            return Ok(Watch::Unavailable(retry_delay(retry_after)));
+        }
+        if !status.is_success() {
+            return Err(anyhow!("{}", response.text().await?));
+        }
+        Ok(Watch::Stream(Box::new(EventStream {
+            response,
+            parser: EventStreamParser::default(),
+            pending: VecDeque::new(),
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_only_hint() {
+        let body = r#"{"code": "ReadOnly", "message": "The API is read-only: vacuum"}"#;
+        let hint = read_only_hint(body, Some("30")).unwrap();
+        assert!(hint.starts_with("The API is read-only: vacuum\n"));
+        assert!(hint.contains("retry in 30 seconds"));
+
+        assert_eq!(read_only_hint("Service Unavailable", None), None);
+        assert_eq!(read_only_hint(r#"{"code": "Overloaded"}"#, None), None);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(Some("5")), Duration::from_secs(5));
+        assert_eq!(retry_delay(Some("soon")), DEFAULT_RETRY_AFTER);
+        assert_eq!(retry_delay(None), DEFAULT_RETRY_AFTER);
     }
 }
@@ -19,6 +19,12 @@ pub struct Configuration {
 pub struct Cluster {
     pub name: String,
     pub server: String,
+    /// Namespace sent with every request, the server default is used when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Bearer token of the controller, when it requires one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
 }
 
 impl Default for Cluster {
@@ -26,6 +32,8 @@ impl Default for Cluster {
         Self {
             name: "rik.local".to_string(),
             server: "http://127.0.0.1:5000".to_string(),
+            namespace: None,
+            token: None,
         }
     }
 }
@@ -158,4 +166,22 @@ cluster:
         assert_eq!(config.cluster.server, "http://test.com");
         std::env::remove_var(CONFIG_LOCATION_KEY);
     }
+
+    #[test]
+    #[serial]
+    fn provide_config_namespace() {
+        let config_str = r#"
+cluster:
+    name: test
+    server: http://test.com
+    namespace: team
+        "#;
+        let _config_file = write_config_from_string(config_str);
+        let path = _config_file.path().to_string_lossy().to_string();
+
+        std::env::set_var(CONFIG_LOCATION_KEY, path);
+        let config = Configuration::load().expect("Should be able to load configuration");
+        assert_eq!(config.cluster.namespace, Some("team".to_string()));
+        std::env::remove_var(CONFIG_LOCATION_KEY);
+    }
 }
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+/// `Event` is something that happened to an element of the cluster,
+/// such as an instance changing status.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Event {
+    pub id: i64,
+    pub created_at: String,
+    pub element_id: String,
+    pub reason: String,
+    pub message: String,
+}
+
+/// `EventPage` holds a page of events and the cursor of the following one.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EventPage {
+    pub events: Vec<Event>,
+    pub next: i64,
+}
+
+/// `WatchMessage` is something sent on a watch of the events.
+#[derive(Debug, PartialEq)]
+pub enum WatchMessage {
+    Event(Event),
+    /// The controller closed the watch, e.g. because it fell behind
+    Closed(String),
+    /// The controller shuts down, the watch is to be opened again from the
+    /// last event received
+    Bookmark(String),
+}
+
+/// `EventStreamParser` splits the server-sent events of a watch into messages,
+/// whatever the chunks they come in.
+#[derive(Debug, Default)]
+pub struct EventStreamParser {
+    buffer: Vec<u8>,
+}
+
+impl EventStreamParser {
+    /// Messages completed by a chunk of the stream
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<WatchMessage> {
+        self.buffer.extend_from_slice(chunk);
+        let mut messages = vec![];
+        while let Some(end) = self.buffer.windows(2).position(|window| window == b"\n\n") {
+            let frame: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            if let Some(message) = Self::parse_frame(&String::from_utf8_lossy(&frame)) {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    /// Comments, such as the keep-alives, give nothing
+    fn parse_frame(frame: &str) -> Option<WatchMessage> {
+        let mut kind = None;
+        let mut data = None;
+        for line in frame.lines() {
+            if let Some(value) = line.strip_prefix("event:") {
+                kind = Some(value.trim());
+            } else if let Some(value) = line.strip_prefix("data:") {
+                data = Some(value.trim());
+            }
+        }
+        let data = data?;
+        let field = |name: &str| {
+            serde_json::from_str::<serde_json::Value>(data)
+                .ok()
+                .and_then(|reason| reason[name].as_str().map(str::to_string))
+                .unwrap_or_else(|| data.to_string())
+        };
+        match kind {
+            Some("close") => Some(WatchMessage::Closed(field("message"))),
+            Some("bookmark") => Some(WatchMessage::Bookmark(field("reason"))),
+            _ => serde_json::from_str(data).ok().map(WatchMessage::Event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_event_stream() {
+        let mut parser = EventStreamParser::default();
+        let stream = concat!(
+            ": keep-alive\n\n",
+            "id: 7\ndata: {\"id\":7,\"created_at\":\"2023-06-01T02:00:00.000000Z\",",
+            "\"element_id\":\"quiet-river-1234\",\"reason\":\"Running\",\"message\":\"\"}\n\n",
+            "event: close\ndata: {\"reason\":\"SlowConsumer\",\"message\":\"resume from event 7\"}\n\n",
+        );
+        // Frames are split across chunks
+        let (first, second) = stream.as_bytes().split_at(40);
+        let mut messages = parser.feed(first);
+        assert!(messages.is_empty());
+        messages.extend(parser.feed(second));
+
+        assert_eq!(messages.len(), 2);
+        match &messages[0] {
+            WatchMessage::Event(event) => assert_eq!(event.id, 7),
+            other => panic!("expected an event, got {:?}", other),
+        }
+        assert_eq!(
+            messages[1],
+            WatchMessage::Closed(String::from("resume from event 7"))
+        );
+
+        let bookmark = "event: bookmark\nid: 7\ndata: {\"type\":\"BOOKMARK\",\"reason\":\"server shutting down\",\"resume_from\":7}\n\n";
+        assert_eq!(
+            parser.feed(bookmark.as_bytes()),
+            vec![WatchMessage::Bookmark(String::from("server shutting down"))]
+        );
+    }
+}
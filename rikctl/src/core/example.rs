@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+/// `ExampleSummary` describes a manifest example served by the controller.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExampleSummary {
+    pub name: String,
+    pub kind: String,
+    pub description: String,
+}
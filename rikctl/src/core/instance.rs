@@ -3,4 +3,73 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Instance {
     pub status: String,
+    /// Overrides applied on top of the workload definition, if any
+    #[serde(default)]
+    pub overrides: Option<InstanceOverrides>,
+    /// Detailed state of the instance, only returned by the v1 API
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    /// Identifier of the last operation on the instance, to search the logs with
+    #[serde(default)]
+    pub correlation_id: Option<String>,
+    /// Image the instance booted from, only returned by `instances.get`
+    #[serde(default)]
+    pub provenance: Option<ImageProvenance>,
+}
+
+/// `ImageProvenance` hold the artifact an instance booted from.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImageProvenance {
+    pub resolved_url: String,
+    pub sha256: String,
+    pub downloaded_at: String,
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+}
+
+/// `Condition` hold the observed state of one aspect of an instance.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Condition {
+    #[serde(rename = "type")]
+    pub condition_type: String,
+    pub status: String,
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default)]
+    pub message: String,
+    #[serde(rename = "lastTransitionTime")]
+    pub last_transition_time: String,
+}
+
+/// `EnvVariable` hold an environment variable of a container.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EnvVariable {
+    pub name: String,
+    pub value: String,
+}
+
+/// `InstanceOverrides` hold the fields of the workload definition
+/// overridden for a single instance.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct InstanceOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<Vec<EnvVariable>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_tag: Option<String>,
+}
+
+impl InstanceOverrides {
+    /// Names of the overridden fields
+    pub fn fields(&self) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.env.is_some() {
+            fields.push("env");
+        }
+        if self.image_tag.is_some() {
+            fields.push("image_tag");
+        }
+        fields
+    }
 }
@@ -1,4 +1,6 @@
 pub mod client;
 pub mod config;
+pub mod event;
+pub mod example;
 pub mod instance;
 pub mod workload;
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::path::PathBuf;
 
@@ -9,6 +10,14 @@ pub struct Workload {
     pub api_version: String,
     pub kind: String,
     pub name: String,
+    /// Namespace of the workload, the one of the current context when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Protected workloads are only deleted when forced
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub protected: bool,
     pub spec: Spec,
 }
 
@@ -24,7 +33,37 @@ pub struct Spec {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Container {
     pub name: String,
+    /// Empty when the container is imported from `image_archive_url`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub image: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_archive_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_archive_sha256: Option<String>,
+    /// Workloads whose address is given to the container at schedule time
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub discover: Vec<String>,
+}
+
+/// Workloads to delete at once, by id or by label selector
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DeleteCollection {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selector: Option<String>,
+}
+
+/// Outcome of the deletion of a single workload
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteResult {
+    pub id: String,
+    pub name: String,
+    /// One of `matched`, `deleted`, `not_found`, `failed`, `skipped`,
+    /// `protected` or `has_instances`
+    pub status: String,
+    #[serde(default)]
+    pub message: Option<String>,
 }
 
 /// Workload related errors
@@ -0,0 +1,76 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use tar::Archive;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// File every OCI image layout holds at its root
+const OCI_LAYOUT_FILE: &str = "oci-layout";
+
+/// Whether a tar archive holds an OCI image layout, rather than a root
+/// filesystem.
+///
+/// Compressed archives are refused, as neither skopeo nor umoci import them.
+pub fn is_oci_archive(path: &Path) -> io::Result<bool> {
+    let mut magic = [0u8; 2];
+    if File::open(path)?.read(&mut magic)? == magic.len() && magic == GZIP_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "compressed image archives are not supported",
+        ));
+    }
+
+    let mut archive = Archive::new(File::open(path)?);
+    for entry in archive.entries()? {
+        if entry?.path()?.as_os_str() == OCI_LAYOUT_FILE {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tar::{Builder, Header};
+
+    /// Tar archive of files, each given by its name and content
+    fn archive(archive_name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let path = std::env::temp_dir().join(archive_name);
+        let mut builder = Builder::new(File::create(&path).unwrap());
+        for (name, content) in files {
+            let mut header = Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        builder.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_detect_oci_archive() {
+        let layout = archive(
+            "oci-layout.tar",
+            &[
+                ("index.json", "{}"),
+                ("./oci-layout", r#"{"imageLayoutVersion": "1.0.0"}"#),
+            ],
+        );
+        assert!(is_oci_archive(&layout).unwrap());
+
+        let rootfs = archive(
+            "rootfs.tar",
+            &[("bin/sh", &"x".repeat(700)), ("etc/oci-layout", "")],
+        );
+        assert!(!is_oci_archive(&rootfs).unwrap());
+
+        let compressed = std::env::temp_dir().join("rootfs.tar.gz");
+        std::fs::write(&compressed, [GZIP_MAGIC[0], GZIP_MAGIC[1], 0x08, 0x00]).unwrap();
+        assert!(is_oci_archive(&compressed).is_err());
+    }
+}
@@ -3,9 +3,12 @@ use crate::skopeo::{Skopeo, SkopeoConfiguration};
 use crate::umoci::{Umoci, UmociConfiguration, UnpackArgs};
 use crate::*;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tracing::{event, Level};
 
+/// Command of the images imported from a root filesystem tarball
+const ROOTFS_COMMAND: &str = "/bin/sh";
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct ImageManagerConfiguration {
     pub oci_manager: UmociConfiguration,
@@ -77,6 +80,62 @@ impl ImageManager {
 
         event!(Level::DEBUG, "{} copied into {}", image_str, image_path);
 
+        self.unpack(&mut image, &image_path).await?;
+
+        event!(Level::INFO, "Successfully pulled image {}", image_str);
+
+        Ok(image)
+    }
+
+    /// Import an image archive, either an OCI image layout or the tarball of
+    /// a root filesystem, which runs `/bin/sh`.
+    ///
+    /// Archives are named by the SHA-256 of their content, so an archive is
+    /// only imported once whatever the URL it was downloaded from.
+    pub async fn import(&mut self, archive: &Path, sha256: &str) -> Result<Image> {
+        let bundle_directory = self.config.oci_manager.bundles_directory.clone().unwrap();
+        let mut image = Image::from(&format!("archive-{}:latest", sha256));
+
+        if !image.should_be_pulled(&bundle_directory) {
+            event!(Level::INFO, "Using the imported image {}", image.oci);
+            let bundle = bundle_directory.join(image.get_uuid());
+            image.set_bundle(bundle.to_str().unwrap());
+            return Ok(image);
+        }
+
+        event!(Level::INFO, "Importing image archive {}", archive.display());
+        let oci_layout = archive::is_oci_archive(archive).map_err(Error::InvalidPathError)?;
+        let image_path = if oci_layout {
+            self.skopeo
+                .copy(
+                    &format!("oci-archive:{}", archive.display()),
+                    &image.get_hashed_oci(),
+                    Default::default(),
+                )
+                .await?
+        } else {
+            let layout = self
+                .config
+                .image_puller
+                .images_directory
+                .clone()
+                .unwrap()
+                .join(format!("{}-{}", image.name, image.get_hash()));
+            self.umoci
+                .create_from_rootfs(&layout, &image.tag, archive, ROOTFS_COMMAND)
+                .await?;
+            layout.display().to_string()
+        };
+
+        self.unpack(&mut image, &image_path).await?;
+
+        event!(Level::INFO, "Successfully imported image {}", image.oci);
+
+        Ok(image)
+    }
+
+    /// Unpack the image found in an OCI layout as the bundle of the image
+    async fn unpack(&self, image: &mut Image, image_path: &str) -> Result<()> {
         let bundle = self
             .umoci
             .unpack(
@@ -92,9 +151,6 @@ impl ImageManager {
             .await?;
 
         image.set_bundle(&bundle[..]);
-
-        event!(Level::INFO, "Successfully pulled image {}", image_str);
-
-        Ok(image)
+        Ok(())
     }
 }
@@ -1,5 +1,6 @@
 use async_trait::async_trait;
 
+pub mod archive;
 pub mod image;
 pub mod image_manager;
 pub mod skopeo;
@@ -1,7 +1,7 @@
 use crate::*;
 use serde::{Deserialize, Serialize};
 use shared::utils::find_binary;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::time::Duration;
 use tokio::process::Command;
@@ -74,6 +74,49 @@ impl Umoci {
 
         Ok(bundle_path)
     }
+
+    /// Create an image in a new layout out of a root filesystem tarball,
+    /// running `command` unless the container gives another one
+    pub async fn create_from_rootfs(
+        &self,
+        layout: &Path,
+        tag: &str,
+        rootfs: &Path,
+        command: &str,
+    ) -> Result<()> {
+        event!(
+            Level::DEBUG,
+            "Creating image {}:{} from {}",
+            layout.display(),
+            tag,
+            rootfs.display()
+        );
+        let layout = layout.to_str().unwrap();
+        let image = format!("{}:{}", layout, tag);
+        self.exec(&args(&["init", "--layout", layout])).await?;
+        self.exec(&args(&["new", "--image", &image])).await?;
+        self.exec(&args(&[
+            "raw",
+            "add-layer",
+            "--image",
+            &image,
+            rootfs.to_str().unwrap(),
+        ]))
+        .await?;
+        self.exec(&args(&[
+            "config",
+            "--image",
+            &image,
+            "--config.cmd",
+            command,
+        ]))
+        .await?;
+        Ok(())
+    }
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
 }
 
 impl Args for Umoci {
@@ -0,0 +1,246 @@
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Handle used to swap the filter of the tracing subscriber
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Settings of the riklet changed while it runs, through the admin channel
+pub struct RuntimeControls {
+    log_filter: LogFilterHandle,
+    /// Directives of the current filter
+    directives: Mutex<String>,
+    /// Woken up to run a reconciliation pass without waiting for the next one
+    pub reconcile: Notify,
+    log_filter_changes: AtomicU64,
+    reconciliations: AtomicU64,
+}
+
+/// Answer of the `status` command
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct ControlsStatus {
+    pub log_filter: String,
+    pub log_filter_changes: u64,
+    pub reconciliations: u64,
+}
+
+impl RuntimeControls {
+    pub fn new(log_filter: LogFilterHandle, directives: String) -> Arc<RuntimeControls> {
+        Arc::new(RuntimeControls {
+            log_filter,
+            directives: Mutex::new(directives),
+            reconcile: Notify::new(),
+            log_filter_changes: AtomicU64::new(0),
+            reconciliations: AtomicU64::new(0),
+        })
+    }
+
+    /// Replace the tracing filter, e.g. with `riklet=debug,info`
+    pub fn set_log_filter(&self, directives: &str) -> Result<(), String> {
+        let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+        self.log_filter.reload(filter).map_err(|e| e.to_string())?;
+        let previous = std::mem::replace(
+            &mut *self.directives.lock().unwrap(),
+            directives.to_string(),
+        );
+        self.log_filter_changes.fetch_add(1, Ordering::Relaxed);
+        info!("Log filter changed from {} to {}", previous, directives);
+        Ok(())
+    }
+
+    pub fn request_reconciliation(&self) {
+        self.reconciliations.fetch_add(1, Ordering::Relaxed);
+        info!("Reconciliation pass requested");
+        self.reconcile.notify_one();
+    }
+
+    pub fn status(&self) -> ControlsStatus {
+        ControlsStatus {
+            log_filter: self.directives.lock().unwrap().clone(),
+            log_filter_changes: self.log_filter_changes.load(Ordering::Relaxed),
+            reconciliations: self.reconciliations.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Answer a line sent on the admin channel
+    pub fn handle_command(&self, line: &str) -> String {
+        let (command, argument) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        match command {
+            "status" => serde_json::to_string(&self.status()).unwrap(),
+            "log-filter" => match self.set_log_filter(argument.trim()) {
+                Ok(()) => String::from("ok"),
+                Err(e) => format!("error: invalid filter: {}", e),
+            },
+            "reconcile" => {
+                self.request_reconciliation();
+                String::from("ok")
+            }
+            _ => format!(
+                "error: unknown command {}, expected status, log-filter or reconcile",
+                command
+            ),
+        }
+    }
+}
+
+/// Listen on a unix socket only the user running the riklet can use, a socket
+/// left by a previous riklet is replaced
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Serve the admin channel, one command per line, each answered by a line
+pub async fn serve(listener: UnixListener, controls: Arc<RuntimeControls>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!("Could not accept an admin connection: {}", e);
+                continue;
+            }
+        };
+        let controls = controls.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &controls).await {
+                warn!("Admin connection closed: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(stream: UnixStream, controls: &RuntimeControls) -> io::Result<()> {
+    // The socket permissions are set once it exists, the peer is checked as
+    // well so nobody can connect in between
+    let peer = stream.peer_cred()?.uid();
+    let owner = nix::unistd::Uid::effective().as_raw();
+    if peer != owner {
+        warn!("Admin connection refused for user {}", peer);
+        return Ok(());
+    }
+
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let answer = controls.handle_command(&line);
+        writer.write_all(format!("{}\n", answer).as_bytes()).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use tracing::span;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::Layer;
+
+    /// Counts the spans reaching the layers below the filter
+    struct SpanCounter(Arc<AtomicUsize>);
+
+    impl<S: tracing::Subscriber> Layer<S> for SpanCounter {
+        fn on_new_span(&self, _: &span::Attributes<'_>, _: &span::Id, _: Context<'_, S>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn controls(
+        directives: &str,
+    ) -> (
+        Arc<RuntimeControls>,
+        impl tracing::Subscriber,
+        Arc<AtomicUsize>,
+    ) {
+        let (filter, handle) = reload::Layer::new(EnvFilter::new(directives));
+        let spans = Arc::new(AtomicUsize::new(0));
+        let subscriber = tracing_subscriber::registry()
+            .with(filter)
+            .with(SpanCounter(spans.clone()));
+        (
+            RuntimeControls::new(handle, directives.to_string()),
+            subscriber,
+            spans,
+        )
+    }
+
+    #[test]
+    fn test_log_filter_change_affects_spans() {
+        let (controls, subscriber, spans) = controls("info");
+        tracing::subscriber::with_default(subscriber, || {
+            let _ = tracing::debug_span!("boot").entered();
+            assert_eq!(spans.load(Ordering::SeqCst), 0);
+
+            assert_eq!(controls.handle_command("log-filter debug"), "ok");
+            let _ = tracing::debug_span!("boot").entered();
+            assert_eq!(spans.load(Ordering::SeqCst), 1);
+
+            assert_eq!(controls.handle_command("log-filter warn"), "ok");
+            let _ = tracing::info_span!("boot").entered();
+            assert_eq!(spans.load(Ordering::SeqCst), 1);
+        });
+
+        assert_eq!(
+            controls.status(),
+            ControlsStatus {
+                log_filter: String::from("warn"),
+                log_filter_changes: 2,
+                reconciliations: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid_commands_are_rejected() {
+        let (controls, _, _) = controls("info");
+        assert!(controls
+            .handle_command("log-filter riklet=loud")
+            .starts_with("error: invalid filter"));
+        assert!(controls.handle_command("restart").starts_with("error"));
+        assert_eq!(controls.status().log_filter, "info");
+        assert_eq!(controls.status().log_filter_changes, 0);
+    }
+
+    #[tokio::test]
+    async fn test_admin_socket() {
+        let path = std::env::temp_dir()
+            .join(format!("riklet-{}", uuid::Uuid::new_v4()))
+            .join("admin.sock");
+        let (controls, _, _) = controls("info");
+        let listener = bind(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        tokio::spawn(serve(listener, controls.clone()));
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream.write_all(b"reconcile\nstatus\n").await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            r#"{"log_filter":"info","log_filter_changes":0,"reconciliations":1}"#
+        );
+        // The reconciliation waits for whoever runs it
+        controls.reconcile.notified().await;
+    }
+}
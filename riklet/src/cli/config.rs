@@ -1,5 +1,6 @@
 use clap::Parser;
 use cri::container::RuncConfiguration;
+use definition::workload::{EgressRule, WorkloadKind};
 use oci::image_manager::ImageManagerConfiguration;
 use oci::skopeo::SkopeoConfiguration;
 use oci::umoci::UmociConfiguration;
@@ -11,7 +12,11 @@ use std::time::Duration;
 use thiserror::Error;
 
 use super::CliConfiguration;
-use crate::constants::DEFAULT_COMMAND_TIMEOUT;
+use crate::constants::{
+    DEFAULT_ADMIN_SOCKET, DEFAULT_COMMAND_TIMEOUT, DEFAULT_GUEST_STATUS_PORT, DEFAULT_LOGS_PORT,
+    DEFAULT_ROOTFS_CACHE_DIRECTORY,
+};
+use crate::runtime::fetcher::s3::S3Configuration;
 use tracing::{event, Level};
 
 #[derive(Debug, Error)]
@@ -36,8 +41,87 @@ type Result<T> = std::result::Result<T, ConfigurationError>;
 pub struct Configuration {
     pub master_ip: String,
     pub log_level: String,
+    /// Maximum number of placements the scheduler may send per interval,
+    /// the scheduler default applies when unset
+    #[serde(default)]
+    pub max_placements: Option<u32>,
+    /// Kinds of workloads advertised to the scheduler, detected from the node when unset
+    #[serde(default)]
+    pub supported_kinds: Option<Vec<WorkloadKind>>,
     pub runner: RuncConfiguration,
     pub manager: ImageManagerConfiguration,
+    #[serde(default)]
+    pub node_checks: NodeChecksConfiguration,
+    /// Traffic dropped from every function instance to the host and beyond,
+    /// everything else is allowed
+    #[serde(default)]
+    pub egress_deny: Vec<EgressRule>,
+    /// Directory of the root filesystems downloaded for functions
+    #[serde(default = "default_rootfs_cache_directory")]
+    pub rootfs_cache_directory: PathBuf,
+    /// Unix socket of the admin channel, only the user running the riklet can use it
+    #[serde(default = "default_admin_socket")]
+    pub admin_socket: PathBuf,
+    /// Port on which function instances report their own status, only the
+    /// addresses of the instances are answered
+    #[serde(default = "default_guest_status_port")]
+    pub guest_status_port: u16,
+    /// Port on which the controller reads the logs of the instances
+    #[serde(default = "default_logs_port")]
+    pub logs_port: u16,
+    /// Store of the `s3://` root filesystems
+    #[serde(default)]
+    pub s3: S3Configuration,
+    /// Directories `file://` root filesystems may be read from, none by default
+    /// so workloads cannot copy the files of the node
+    #[serde(default)]
+    pub file_image_directories: Vec<PathBuf>,
+}
+
+fn default_rootfs_cache_directory() -> PathBuf {
+    PathBuf::from(DEFAULT_ROOTFS_CACHE_DIRECTORY)
+}
+
+fn default_admin_socket() -> PathBuf {
+    PathBuf::from(DEFAULT_ADMIN_SOCKET)
+}
+
+fn default_guest_status_port() -> u16 {
+    DEFAULT_GUEST_STATUS_PORT
+}
+
+fn default_logs_port() -> u16 {
+    DEFAULT_LOGS_PORT
+}
+
+/// Local checks reporting node problems to the scheduler
+#[derive(Deserialize, Debug, Serialize, PartialEq, Eq, Clone)]
+#[serde(default)]
+pub struct NodeChecksConfiguration {
+    /// Seconds between two runs of the checks, results are reused in between
+    pub interval: u64,
+    /// Report DiskPressure when a disk used by the riklet is more used than this percentage
+    pub disk_pressure: bool,
+    pub disk_pressure_threshold: u8,
+    /// Report KvmUnavailable when /dev/kvm cannot be opened
+    pub kvm: bool,
+    /// Report ImageCacheCorrupted when cached images cannot be read or are empty
+    pub image_cache: bool,
+    /// Report ClockUnsynced when the kernel clock is not synchronized
+    pub clock: bool,
+}
+
+impl Default for NodeChecksConfiguration {
+    fn default() -> Self {
+        Self {
+            interval: 60,
+            disk_pressure: true,
+            disk_pressure_threshold: 90,
+            kvm: true,
+            image_cache: true,
+            clock: true,
+        }
+    }
 }
 
 impl Configuration {
@@ -133,6 +217,8 @@ impl Default for Configuration {
         Self {
             master_ip: String::from("http://127.0.0.1:4995"),
             log_level: String::from("info"),
+            max_placements: None,
+            supported_kinds: None,
             runner: RuncConfiguration {
                 debug: false,
                 rootless: false,
@@ -155,6 +241,14 @@ impl Default for Configuration {
                     ..Default::default()
                 },
             },
+            node_checks: NodeChecksConfiguration::default(),
+            egress_deny: vec![],
+            rootfs_cache_directory: default_rootfs_cache_directory(),
+            admin_socket: default_admin_socket(),
+            guest_status_port: default_guest_status_port(),
+            logs_port: default_logs_port(),
+            s3: S3Configuration::default(),
+            file_image_directories: vec![],
         }
     }
 }
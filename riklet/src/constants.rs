@@ -3,5 +3,22 @@ pub const DEFAULT_COMMAND_TIMEOUT: u64 = 30000;
 /// A path to a directory which will contain the firecracker VMs
 pub const DEFAULT_FIRECRACKER_WORKSPACE: &str = "/var/lib/riklet/vm";
 
+/// A path to a directory which will contain the persistent volumes of functions,
+/// they are kept when instances are destroyed
+pub const DEFAULT_VOLUMES_DIRECTORY: &str = "/var/lib/riklet/volumes";
+
+/// A path to a directory which will contain the root filesystems of functions,
+/// stored by content hash
+pub const DEFAULT_ROOTFS_CACHE_DIRECTORY: &str = "/var/lib/riklet/rootfs";
+
+/// Unix socket of the admin channel, used to change settings while the riklet runs
+pub const DEFAULT_ADMIN_SOCKET: &str = "/run/riklet/admin.sock";
+
+/// Port on which guests report their status, on every address of the node
+pub const DEFAULT_GUEST_STATUS_PORT: u16 = 8053;
+
+/// Port on which the controller reads the logs of the instances
+pub const DEFAULT_LOGS_PORT: u16 = 8054;
+
 /// IPv4 adresse mask that is used to configure IP address for the guest VM and host interface
 pub const DEFAULT_FIRECRACKER_NETWORK_MASK: u8 = 30;
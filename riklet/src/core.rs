@@ -1,21 +1,45 @@
+use crate::admin::{self, RuntimeControls};
 use crate::banner;
 use crate::cli::config::{Configuration, ConfigurationError};
 use crate::emitters::metrics_emitter::MetricsEmitter;
+use crate::guest_status::{self, AppStatusReport, GuestRegistry};
+use crate::instance_logs::{self, instance_logs};
+use crate::node_checks::{supported_kinds, NodeChecks};
 use crate::runtime::network::{GlobalRuntimeNetwork, NetworkError, RuntimeNetwork};
-use crate::runtime::{DynamicRuntimeManager, Runtime, RuntimeConfigurator, RuntimeError};
+use crate::runtime::probe::{self, STARTUP_TIMEOUT};
+use crate::runtime::rootfs_cache::{
+    migrate_legacy_cache, open_files, RootfsCache, LEGACY_CACHE_DIRECTORY,
+};
+use crate::runtime::{
+    restart, tear_down, DynamicRuntimeManager, Runtime, RuntimeConfigurator, RuntimeError,
+};
 use crate::structs::{EventEmitter, WorkloadDefinition};
+use crate::sync::StateDiff;
+use definition::workload::Probe;
 use definition::InstanceStatus;
-use proto::common::WorkerRegistration;
+use proto::common::worker_status::Status;
+use proto::common::{
+    ConditionStatus, ConditionType, InstanceCondition, RuntimeKind, SyncReport, WorkerRegistration,
+};
 use proto::worker::worker_client::WorkerClient;
-use proto::worker::InstanceScheduling;
-use proto::{WorkerStatus, WorkloadAction};
+use proto::worker::{DesiredState, InstanceScheduling};
+use proto::{definition_hash, WorkerStatus, WorkloadAction};
 use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
 
 use thiserror::Error;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use tonic::{transport::Channel, Request, Streaming};
-use tracing::{debug, error, event, info, Level};
+use tracing::{debug, error, event, info, warn, Level};
 
 const METRICS_UPDATER_INTERVAL: u64 = 15 * 1000;
+/// Guest reports waiting to be forwarded, the guests are answered meanwhile
+const APP_STATUS_BUFFER: usize = 64;
 
 #[derive(Error, Debug)]
 pub enum RikletError {
@@ -50,6 +74,11 @@ pub struct Riklet {
     // Can be pod or function runtimes
     // The key is the instance id
     runtimes: HashMap<String, Box<dyn Runtime>>,
+    /// Hash of the definition each running instance was created with,
+    /// compared to the desired state sent by the scheduler
+    definition_hashes: HashMap<String, String>,
+    /// Startup probes of the instances booted but not reported running yet
+    startup_probes: HashMap<String, JoinHandle<()>>,
     /// Holds the global network configuration which includes basic iptables
     /// rules and chains used by all workloads
     ///
@@ -57,10 +86,19 @@ pub struct Riklet {
     ///  it is necessary to keep ownership of this field so that the [Drop] trait
     /// is not called too early, but only when [Riklet] is dropped
     network: GlobalRuntimeNetwork,
+    /// Settings changed through the admin channel
+    controls: Arc<RuntimeControls>,
+    /// Instances allowed to report their own status, by guest address
+    guests: Arc<GuestRegistry>,
 }
 
 impl Riklet {
+    #[tracing::instrument(skip_all, fields(correlation_id = %workload.correlation_id))]
     async fn handle_workload(&mut self, workload: &InstanceScheduling) -> Result<()> {
+        if let Some(desired_state) = &workload.desired_state {
+            return self.sync_desired_state(desired_state).await;
+        }
+
         info!(
             "Instance scheduling received for instance: {}",
             &workload.instance_id
@@ -74,10 +112,14 @@ impl Riklet {
 
         match &workload.action.into() {
             WorkloadAction::CREATE => {
-                self.create_workload(workload, dynamic_runtime_manager)
+                self.create_workload(workload, &workload_definition, dynamic_runtime_manager)
                     .await?
             }
             WorkloadAction::DELETE => self.delete_workload(workload).await?,
+            WorkloadAction::RESTART => {
+                self.restart_workload(workload, &workload_definition, dynamic_runtime_manager)
+                    .await?
+            }
         };
 
         Ok(())
@@ -86,6 +128,7 @@ impl Riklet {
     async fn create_workload(
         &mut self,
         workload: &InstanceScheduling,
+        workload_definition: &WorkloadDefinition,
         dynamic_runtime_manager: DynamicRuntimeManager<'_>,
     ) -> Result<()> {
         let instance_id: &String = &workload.instance_id;
@@ -97,23 +140,109 @@ impl Riklet {
             .await
         {
             Err(e) => {
-                self.send_status(InstanceStatus::Failed, instance_id)
-                    .await
-                    .unwrap_or_else(|e| {
-                        error!("Error while sending status: {}", e);
-                    });
+                self.report_boot_failure(instance_id, &e).await;
                 return Err(RikletError::RuntimeManagerError(e));
             }
             Ok(runtime) => {
-                self.runtimes.insert(instance_id.clone(), runtime);
-
-                self.send_status(InstanceStatus::Running, instance_id)
-                    .await?;
+                self.register_runtime(workload, workload_definition, runtime)
+                    .await
             }
         }
         Ok(())
     }
 
+    /// Brings an instance down then up again with the same runtime, so it
+    /// keeps its id and network. An instance this node does not run is
+    /// created instead, the restart of a stopped instance boots it.
+    #[tracing::instrument(skip_all, fields(instance_id = %workload.instance_id))]
+    async fn restart_workload(
+        &mut self,
+        workload: &InstanceScheduling,
+        workload_definition: &WorkloadDefinition,
+        dynamic_runtime_manager: DynamicRuntimeManager<'_>,
+    ) -> Result<()> {
+        let instance_id: &String = &workload.instance_id;
+        if !self.runtimes.contains_key(instance_id) {
+            info!("Instance {} is not running, booting it", instance_id);
+            return self
+                .create_workload(workload, workload_definition, dynamic_runtime_manager)
+                .await;
+        }
+        self.send_status(InstanceStatus::Restarting, instance_id)
+            .await?;
+
+        // The instance must not be reported running before it is up again
+        if let Some(startup_probe) = self.startup_probes.remove(instance_id) {
+            startup_probe.abort();
+        }
+        self.guests.unregister(instance_id);
+        let mut runtime = self.runtimes.remove(instance_id).unwrap();
+        match restart(runtime.as_mut()).await {
+            Err(e) => {
+                // The runtime is torn down by then, nothing is left to stop
+                instance_logs().remove(instance_id);
+                self.definition_hashes.remove(instance_id);
+                self.report_boot_failure(instance_id, &e).await;
+                Err(RikletError::RuntimeManagerError(e))
+            }
+            Ok(()) => {
+                self.register_runtime(workload, workload_definition, runtime)
+                    .await;
+                Ok(())
+            }
+        }
+    }
+
+    /// Report an instance failed as its runtime did not boot
+    async fn report_boot_failure(&self, instance_id: &str, e: &RuntimeError) {
+        let condition = InstanceCondition {
+            r#type: ConditionType::Booted.into(),
+            status: ConditionStatus::False.into(),
+            reason: String::from("RuntimeError"),
+            message: e.to_string(),
+        };
+        self.send_status_with_conditions(InstanceStatus::Failed, instance_id, vec![condition])
+            .await
+            .unwrap_or_else(|e| {
+                error!("Error while sending status: {}", e);
+            });
+    }
+
+    /// Keep the runtime of an instance which booted, and report it running
+    /// once its startup probe, if it has one, succeeded
+    async fn register_runtime(
+        &mut self,
+        workload: &InstanceScheduling,
+        workload_definition: &WorkloadDefinition,
+        runtime: Box<dyn Runtime>,
+    ) {
+        let instance_id: &String = &workload.instance_id;
+        let provenance = runtime.provenance();
+        let address = runtime.address();
+        if let Some(address) = address {
+            self.guests.register(address, instance_id);
+        }
+        instance_logs().register(instance_id);
+        self.runtimes.insert(instance_id.clone(), runtime);
+        self.definition_hashes
+            .insert(instance_id.clone(), definition_hash(&workload.definition));
+
+        let status = WorkerStatus::with_conditions(
+            self.hostname.clone(),
+            instance_id.clone(),
+            InstanceStatus::Running,
+            Vec::new(),
+        )
+        .with_provenance(provenance);
+        match workload_definition.get_startup_probe().zip(address) {
+            Some(((probe, port), address)) => {
+                self.start_startup_probe(instance_id, probe, SocketAddr::new(address, port), status)
+                    .await
+            }
+            None => self.emit_status(status).await,
+        }
+    }
+
     /// Deletes an instance and its runtime
     ///
     /// Expected lifecycle is:
@@ -122,50 +251,223 @@ impl Riklet {
     #[tracing::instrument(skip_all, fields(instance_id = %workload.instance_id))]
     async fn delete_workload(&mut self, workload: &InstanceScheduling) -> Result<()> {
         debug!("Delete workload");
-        let instance_id: &String = &workload.instance_id;
+        self.terminate_instance(&workload.instance_id).await
+    }
+
+    /// Report the instance running once its startup probe succeeded, or failed
+    /// when the probe gave up, without holding the workloads received meanwhile.
+    ///
+    /// A failed instance is no longer desired, so the next sync with the
+    /// scheduler stops it.
+    async fn start_startup_probe(
+        &mut self,
+        instance_id: &str,
+        probe: Probe,
+        address: SocketAddr,
+        running: WorkerStatus,
+    ) {
+        let booted = InstanceCondition {
+            r#type: ConditionType::Booted.into(),
+            status: ConditionStatus::True.into(),
+            reason: String::from("StartupProbe"),
+            message: format!("Waiting for {} to accept connections", address),
+        };
+        self.send_status_with_conditions(InstanceStatus::Creating, instance_id, vec![booted])
+            .await
+            .unwrap_or_else(|e| error!("Error while sending status: {}", e));
+
+        let client = self.client.clone();
+        let hostname = self.hostname.clone();
+        let id = instance_id.to_string();
+        let task = tokio::spawn(async move {
+            let status = match probe::wait_for_startup(&probe, address).await {
+                Ok(()) => {
+                    info!("Instance {} passed its startup probe", id);
+                    running
+                }
+                Err(message) => {
+                    warn!("Instance {} did not start: {}", id, message);
+                    let condition = InstanceCondition {
+                        r#type: ConditionType::Ready.into(),
+                        status: ConditionStatus::False.into(),
+                        reason: String::from(STARTUP_TIMEOUT),
+                        message,
+                    };
+                    WorkerStatus::with_conditions(
+                        hostname,
+                        id,
+                        InstanceStatus::Failed,
+                        vec![condition],
+                    )
+                }
+            };
+            MetricsEmitter::emit_event(client, vec![status.0])
+                .await
+                .unwrap_or_else(|err| {
+                    event!(Level::ERROR, "Error while sending status : {:?}", err)
+                });
+        });
+        if let Some(previous) = self.startup_probes.insert(instance_id.to_string(), task) {
+            previous.abort();
+        }
+    }
 
-        let instance = self
+    /// Destroy an instance and unregister its runtime, without reporting it.
+    ///
+    /// The runtime is unregistered even when its teardown failed, there is
+    /// nothing left to stop with it.
+    async fn stop_instance(&mut self, instance_id: &str) -> Result<()> {
+        // The instance must not be reported running once stopped
+        if let Some(startup_probe) = self.startup_probes.remove(instance_id) {
+            startup_probe.abort();
+        }
+        self.guests.unregister(instance_id);
+        instance_logs().remove(instance_id);
+        let mut instance = self
             .runtimes
-            .get_mut(instance_id)
-            .ok_or_else(|| RikletError::InvalidInput(instance_id.clone()))?;
+            .remove(instance_id)
+            .ok_or_else(|| RikletError::InvalidInput(instance_id.to_string()))?;
+        self.definition_hashes.remove(instance_id);
 
-        instance
-            .down()
+        tear_down(instance.as_mut())
             .await
-            .map_err(RikletError::RuntimeManagerError)?;
+            .map_err(RikletError::RuntimeManagerError)
+    }
 
-        self.send_status(InstanceStatus::Terminated, instance_id)
-            .await?;
+    /// Stop an instance and report it terminated, along with the error of its
+    /// teardown when it failed
+    async fn terminate_instance(&mut self, instance_id: &str) -> Result<()> {
+        let torn_down = match self.stop_instance(instance_id).await {
+            Ok(()) => Ok(()),
+            Err(RikletError::RuntimeManagerError(e)) => {
+                error!("Error while stopping instance {}: {}", instance_id, e);
+                Err(e)
+            }
+            Err(e) => return Err(e),
+        };
+        self.emit_status(terminated_status(&self.hostname, instance_id, torn_down))
+            .await;
+        Ok(())
+    }
+
+    /// Compare the running instances with the desired state sent by the scheduler.
+    ///
+    /// Undesired instances are terminated. Outdated instances are stopped and, along
+    /// with the missing ones, reported so the scheduler sends their definition again.
+    async fn sync_desired_state(&mut self, desired_state: &DesiredState) -> Result<()> {
+        let diff = StateDiff::new(desired_state, &self.definition_hashes);
+        if diff.is_empty() {
+            debug!("Running instances match the desired state");
+            return Ok(());
+        }
+        warn!(
+            "Running instances differ from the desired state: {:?}",
+            diff
+        );
+
+        for instance_id in &diff.outdated {
+            self.stop_instance(instance_id)
+                .await
+                .unwrap_or_else(|e| error!("Error while stopping instance: {}", e));
+        }
+        for instance_id in &diff.undesired {
+            self.terminate_instance(instance_id)
+                .await
+                .unwrap_or_else(|e| error!("Error while terminating instance: {}", e));
+        }
 
-        self.runtimes.remove(instance_id);
+        let report = proto::common::WorkerStatus {
+            identifier: self.hostname.clone(),
+            host_address: None,
+            status: Some(Status::Sync(SyncReport {
+                missing_instances: diff.missing.into_iter().chain(diff.outdated).collect(),
+                stopped_instances: diff.undesired,
+            })),
+        };
+        MetricsEmitter::emit_event(self.client.clone(), vec![report])
+            .await
+            .unwrap_or_else(|err| {
+                event!(Level::ERROR, "Error while sending sync report : {:?}", err)
+            });
         Ok(())
     }
 
-    #[tracing::instrument(skip(self), fields(instance_id = %instance_id, status = %status))]
+    /// Forward the status an instance reported about itself, in its `Ready`
+    /// condition. The instance keeps its status, creating until its startup
+    /// probe passed.
+    async fn report_app_status(&self, report: AppStatusReport) {
+        if !self.runtimes.contains_key(&report.instance_id) {
+            debug!("Status of stopped instance {} dropped", report.instance_id);
+            return;
+        }
+        let status = match self.startup_probes.get(&report.instance_id) {
+            Some(probe) if !probe.is_finished() => InstanceStatus::Creating,
+            _ => InstanceStatus::Running,
+        };
+        debug!(
+            "Instance {} reported {} (ready: {})",
+            report.instance_id, report.status, report.ready
+        );
+        let status = WorkerStatus::with_conditions(
+            self.hostname.clone(),
+            report.instance_id.clone(),
+            status,
+            vec![report.condition()],
+        )
+        .with_app_status(Some(report.app_status()));
+        self.emit_status(status).await;
+    }
+
     async fn send_status(&self, status: InstanceStatus, instance_id: &str) -> Result<()> {
+        self.send_status_with_conditions(status, instance_id, Vec::new())
+            .await
+    }
+
+    #[tracing::instrument(skip(self, conditions), fields(instance_id = %instance_id, status = %status))]
+    async fn send_status_with_conditions(
+        &self,
+        status: InstanceStatus,
+        instance_id: &str,
+        conditions: Vec<InstanceCondition>,
+    ) -> Result<()> {
         info!("Update instance status");
 
-        let status = WorkerStatus::new(self.hostname.clone(), instance_id.to_string(), status);
+        let status = WorkerStatus::with_conditions(
+            self.hostname.clone(),
+            instance_id.to_string(),
+            status,
+            conditions,
+        );
+        self.emit_status(status).await;
+        Ok(())
+    }
 
+    async fn emit_status(&self, status: WorkerStatus) {
         MetricsEmitter::emit_event(self.client.clone(), vec![status.0])
             .await
             .unwrap_or_else(|err| event!(Level::ERROR, "Error while sending status : {:?}", err));
-        Ok(())
     }
 
     pub async fn run(&mut self) -> Result<()> {
         self.start_metrics_updater();
+        self.start_admin_channel();
+        let (app_status_sender, mut app_statuses) = mpsc::channel(APP_STATUS_BUFFER);
+        self.start_guest_status(app_status_sender).await;
+        self.start_logs_endpoint().await;
         info!("Riklet is running");
 
-        while let Some(workload) = self
-            .stream
-            .message()
-            .await
-            .map_err(RikletError::MessageStatusError)?
-        {
-            self.handle_workload(&workload).await.unwrap_or_else(|e| {
-                error!("Error while handling workload: {}", e);
-            })
+        loop {
+            tokio::select! {
+                message = self.stream.message() => {
+                    let Some(workload) = message.map_err(RikletError::MessageStatusError)? else {
+                        break;
+                    };
+                    self.handle_workload(&workload).await.unwrap_or_else(|e| {
+                        error!("Error while handling workload: {}", e);
+                    })
+                }
+                Some(report) = app_statuses.recv() => self.report_app_status(report).await,
+            }
         }
         Ok(())
     }
@@ -174,22 +476,79 @@ impl Riklet {
         event!(Level::INFO, "Starting metrics updater");
         let client = self.client.clone();
         let hostname = self.hostname.clone();
+        let checks = NodeChecks::new(&self.config);
+        let controls = self.controls.clone();
 
         tokio::spawn(async move {
-            let mut metrics_emitter = MetricsEmitter::new(hostname.clone(), client.clone());
+            let mut metrics_emitter = MetricsEmitter::new(hostname.clone(), client.clone(), checks);
             metrics_emitter
-                .emit_interval(METRICS_UPDATER_INTERVAL)
+                .emit_interval(METRICS_UPDATER_INTERVAL, &controls)
                 .await;
         });
     }
 
-    pub async fn new() -> Result<Self> {
+    /// The riklet keeps running without the admin channel, only logging why
+    fn start_admin_channel(&self) {
+        let path = &self.config.admin_socket;
+        match admin::bind(path) {
+            Ok(listener) => {
+                event!(Level::INFO, "Admin channel listening on {}", path.display());
+                tokio::spawn(admin::serve(listener, self.controls.clone()));
+            }
+            Err(e) => error!("Could not open the admin channel {}: {}", path.display(), e),
+        }
+    }
+
+    /// Instances keep running without the logs endpoint, their logs are only
+    /// read from the node then
+    async fn start_logs_endpoint(&self) {
+        let address = SocketAddr::from((Ipv4Addr::UNSPECIFIED, self.config.logs_port));
+        match TcpListener::bind(address).await {
+            Ok(listener) => {
+                event!(Level::INFO, "Logs endpoint listening on {}", address);
+                tokio::spawn(instance_logs::serve(listener, instance_logs()));
+            }
+            Err(e) => error!("Could not open the logs endpoint {}: {}", address, e),
+        }
+    }
+
+    /// Instances keep running without the guest status endpoint, only
+    /// reporting their status through their probes
+    async fn start_guest_status(&self, reports: mpsc::Sender<AppStatusReport>) {
+        let address = SocketAddr::from((Ipv4Addr::UNSPECIFIED, self.config.guest_status_port));
+        match TcpListener::bind(address).await {
+            Ok(listener) => {
+                event!(
+                    Level::INFO,
+                    "Guest status endpoint listening on {}",
+                    address
+                );
+                tokio::spawn(guest_status::serve(listener, self.guests.clone(), reports));
+            }
+            Err(e) => error!(
+                "Could not open the guest status endpoint {}: {}",
+                address, e
+            ),
+        }
+    }
+
+    pub async fn new(controls: Arc<RuntimeControls>) -> Result<Self> {
         event!(Level::DEBUG, "Riklet bootstraping process started.");
         banner();
         let hostname = gethostname::gethostname().into_string().unwrap();
 
         let config = Configuration::load().map_err(RikletError::ConfigurationError)?;
 
+        // Root filesystems downloaded by older riklets, before the cache was keyed by content
+        if let Err(e) = migrate_legacy_cache(
+            &RootfsCache::new(&config.rootfs_cache_directory),
+            Path::new(LEGACY_CACHE_DIRECTORY),
+            &open_files(),
+            SystemTime::now(),
+        ) {
+            warn!("Could not migrate the legacy root filesystems: {}", e);
+        }
+
         let mut client = WorkerClient::connect(config.master_ip.clone())
             .await
             .map_err(RikletError::ConnectionError)?;
@@ -198,6 +557,11 @@ impl Riklet {
         event!(Level::DEBUG, "Node's registration to the master");
         let request = Request::new(WorkerRegistration {
             hostname: hostname.clone(),
+            max_placements: config.max_placements,
+            supported_kinds: supported_kinds(&config)
+                .into_iter()
+                .map(|kind| RuntimeKind::from(kind) as i32)
+                .collect(),
         });
         let stream = client.register(request).await.unwrap().into_inner();
 
@@ -213,8 +577,12 @@ impl Riklet {
             client,
             stream,
             runtimes: HashMap::<String, Box<dyn Runtime>>::new(),
+            definition_hashes: HashMap::new(),
+            startup_probes: HashMap::new(),
             config,
             network: global_runtime_network,
+            controls,
+            guests: Arc::new(GuestRegistry::default()),
         })
     }
 
@@ -232,3 +600,27 @@ impl Riklet {
         Ok(())
     }
 }
+
+/// Status of a stopped instance. It is terminated even when its teardown
+/// failed, as it no longer runs, the error is given in a condition.
+pub(crate) fn terminated_status(
+    hostname: &str,
+    instance_id: &str,
+    torn_down: std::result::Result<(), RuntimeError>,
+) -> WorkerStatus {
+    let conditions = match torn_down {
+        Ok(()) => Vec::new(),
+        Err(e) => vec![InstanceCondition {
+            r#type: ConditionType::Terminating.into(),
+            status: ConditionStatus::False.into(),
+            reason: String::from("TeardownError"),
+            message: e.to_string(),
+        }],
+    };
+    WorkerStatus::with_conditions(
+        hostname.to_string(),
+        instance_id.to_string(),
+        InstanceStatus::Terminated,
+        conditions,
+    )
+}
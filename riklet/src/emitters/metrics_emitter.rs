@@ -1,3 +1,5 @@
+use crate::admin::RuntimeControls;
+use crate::node_checks::NodeChecks;
 use crate::structs::EventEmitter;
 use futures_util::stream;
 use node_metrics::metrics_manager::MetricsManager;
@@ -13,21 +15,28 @@ pub struct MetricsEmitter {
     manager: MetricsManager,
     identifier: String,
     client: WorkerClient<Channel>,
+    checks: NodeChecks,
 }
 
 impl MetricsEmitter {
-    pub fn new(identifier: String, client: WorkerClient<Channel>) -> Self {
+    pub fn new(identifier: String, client: WorkerClient<Channel>, checks: NodeChecks) -> Self {
         Self {
             manager: MetricsManager::new(),
             identifier,
             client,
+            checks,
         }
     }
 
-    pub async fn emit_interval(&mut self, interval: u64) {
+    /// Emit the metrics at every interval, or right after a reconciliation
+    /// pass asked for through the admin channel
+    pub async fn emit_interval(&mut self, interval: u64, controls: &RuntimeControls) {
         loop {
             self.emit().await;
-            tokio::time::sleep(Duration::from_millis(interval)).await;
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_millis(interval)) => {}
+                _ = controls.reconcile.notified() => self.checks.reconcile(),
+            }
         }
     }
 
@@ -39,6 +48,13 @@ impl MetricsEmitter {
             status: Some(proto::common::worker_status::Status::Worker(WorkerMetric {
                 status: 2,
                 metrics: node_metric.to_json().unwrap(),
+                conditions: self
+                    .checks
+                    .conditions()
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+                queued_placements: None,
             })),
         };
         MetricsEmitter::emit_event(self.client.clone(), vec![worker_status])
@@ -0,0 +1,262 @@
+//! Faults injected in the riklet to exercise its error paths, such as a
+//! registry failing every download, without the infrastructure that
+//! produces them.
+//!
+//! Faults are only injected by riklets built with the `fault-injection`
+//! feature, the hooks do nothing otherwise. Such a riklet reads its plan from
+//! `RIKLET_FAULTS`, e.g. `download=1,2;boot_delay=90;destroy` fails the first
+//! two downloads, delays every boot by 90 seconds and fails the teardown of
+//! every instance.
+
+#[cfg(feature = "fault-injection")]
+use once_cell::sync::Lazy;
+#[cfg(feature = "fault-injection")]
+use std::collections::BTreeSet;
+#[cfg(feature = "fault-injection")]
+use std::sync::Mutex;
+#[cfg(feature = "fault-injection")]
+use std::time::Duration;
+
+#[cfg(feature = "fault-injection")]
+const FAULTS_VARIABLE: &str = "RIKLET_FAULTS";
+
+#[cfg(feature = "fault-injection")]
+static PLAN: Lazy<Mutex<FaultPlan>> = Lazy::new(|| {
+    let plan = match std::env::var(FAULTS_VARIABLE) {
+        Ok(plan) => {
+            FaultPlan::parse(&plan).unwrap_or_else(|e| panic!("Invalid {}: {}", FAULTS_VARIABLE, e))
+        }
+        Err(_) => FaultPlan::default(),
+    };
+    Mutex::new(plan)
+});
+
+/// Faults to inject in the riklet
+#[cfg(feature = "fault-injection")]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FaultPlan {
+    /// Downloads failing, counted from 1
+    failed_downloads: BTreeSet<usize>,
+    /// Downloads started since the plan was installed
+    downloads: usize,
+    /// Wait before booting each instance
+    boot_delay: Option<Duration>,
+    /// Whether the teardown of every instance fails, once it is done
+    failed_destroy: bool,
+}
+
+#[cfg(feature = "fault-injection")]
+impl FaultPlan {
+    /// Fail the `nth` download, counted from 1
+    #[cfg(test)]
+    pub fn fail_download(mut self, nth: usize) -> Self {
+        self.failed_downloads.insert(nth);
+        self
+    }
+
+    #[cfg(test)]
+    pub fn delay_boot(mut self, delay: Duration) -> Self {
+        self.boot_delay = Some(delay);
+        self
+    }
+
+    #[cfg(test)]
+    pub fn fail_destroy(mut self) -> Self {
+        self.failed_destroy = true;
+        self
+    }
+
+    /// Plan written as `;` separated faults, see the module documentation
+    fn parse(plan: &str) -> Result<FaultPlan, String> {
+        let mut parsed = FaultPlan::default();
+        for fault in plan.split(';').map(str::trim).filter(|f| !f.is_empty()) {
+            let (name, value) = fault.split_once('=').unwrap_or((fault, ""));
+            match name {
+                "download" => {
+                    for nth in value.split(',') {
+                        let nth = nth
+                            .trim()
+                            .parse()
+                            .map_err(|_| format!("Invalid download number {}", nth))?;
+                        parsed.failed_downloads.insert(nth);
+                    }
+                }
+                "boot_delay" => {
+                    let seconds = value
+                        .parse()
+                        .map_err(|_| format!("Invalid boot delay {}", value))?;
+                    parsed.boot_delay = Some(Duration::from_secs(seconds));
+                }
+                "destroy" => parsed.failed_destroy = true,
+                _ => return Err(format!("Unknown fault {}", name)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Replace the faults injected in the whole riklet
+#[cfg(all(test, feature = "fault-injection"))]
+pub fn install(plan: FaultPlan) {
+    *PLAN.lock().unwrap() = plan;
+}
+
+/// Whether the download about to start fails
+#[cfg(feature = "fault-injection")]
+pub fn download_fails() -> bool {
+    let mut plan = PLAN.lock().unwrap();
+    plan.downloads += 1;
+    let nth = plan.downloads;
+    plan.failed_downloads.contains(&nth)
+}
+
+#[cfg(not(feature = "fault-injection"))]
+pub fn download_fails() -> bool {
+    false
+}
+
+/// Wait before booting an instance
+#[cfg(feature = "fault-injection")]
+pub async fn delay_boot() {
+    let delay = PLAN.lock().unwrap().boot_delay;
+    if let Some(delay) = delay {
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(not(feature = "fault-injection"))]
+pub async fn delay_boot() {}
+
+/// Whether the teardown of an instance fails
+#[cfg(feature = "fault-injection")]
+pub fn destroy_fails() -> bool {
+    PLAN.lock().unwrap().failed_destroy
+}
+
+#[cfg(not(feature = "fault-injection"))]
+pub fn destroy_fails() -> bool {
+    false
+}
+
+#[cfg(all(test, feature = "fault-injection"))]
+mod tests {
+    use super::*;
+    use crate::core::terminated_status;
+    use crate::runtime::fetcher::file::FileFetcher;
+    use crate::runtime::fetcher::{fetch_with_retries, FetchError, DOWNLOAD_ATTEMPTS};
+    use crate::runtime::{boot, tear_down, Runtime, RuntimeError};
+    use async_trait::async_trait;
+    use definition::InstanceStatus;
+    use proto::common::worker_status::Status;
+    use proto::common::{ConditionStatus, ConditionType};
+    use serial_test::serial;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use url::Url;
+
+    /// Runtime recording the calls it receives
+    #[derive(Default)]
+    struct RecordingRuntime {
+        calls: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    #[async_trait]
+    impl Runtime for RecordingRuntime {
+        async fn up(&mut self) -> crate::runtime::Result<()> {
+            self.calls.lock().unwrap().push("up");
+            Ok(())
+        }
+
+        async fn down(&mut self) -> crate::runtime::Result<()> {
+            self.calls.lock().unwrap().push("down");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            FaultPlan::parse("download=1, 3;boot_delay=90; destroy"),
+            Ok(FaultPlan::default()
+                .fail_download(1)
+                .fail_download(3)
+                .delay_boot(Duration::from_secs(90))
+                .fail_destroy())
+        );
+        assert_eq!(FaultPlan::parse(""), Ok(FaultPlan::default()));
+        assert!(FaultPlan::parse("download=first").is_err());
+        assert!(FaultPlan::parse("reboot").is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_download_retries_exhausted() {
+        let directory = std::env::temp_dir().join(format!("riklet-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        let image = directory.join("rootfs.ext4");
+        fs::write(&image, "rootfs").unwrap();
+        let url = Url::from_file_path(&image).unwrap();
+        let destination = directory.join("download");
+        let fetcher = FileFetcher::new(vec![directory.clone()]);
+
+        // A failure is retried
+        install(FaultPlan::default().fail_download(1));
+        fetch_with_retries(&fetcher, &url, &destination).unwrap();
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "rootfs");
+
+        let mut plan = FaultPlan::default();
+        for nth in 1..=DOWNLOAD_ATTEMPTS {
+            plan = plan.fail_download(nth);
+        }
+        install(plan);
+        let error = fetch_with_retries(&fetcher, &url, &destination).unwrap_err();
+        assert!(matches!(error, FetchError::Status { status: 503 }));
+        // Nothing is left of the attempts
+        assert!(!destination.exists());
+        install(FaultPlan::default());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_boot_timeout_cleans_up() {
+        install(FaultPlan::default().delay_boot(Duration::from_secs(5)));
+        let mut runtime = RecordingRuntime::default();
+        let error = boot(&mut runtime, Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RuntimeError::BootTimeout(_)));
+        // What was set up before the timeout is torn down
+        assert_eq!(*runtime.calls.lock().unwrap(), vec!["down"]);
+
+        install(FaultPlan::default());
+        let mut runtime = RecordingRuntime::default();
+        boot(&mut runtime, Duration::from_millis(100))
+            .await
+            .unwrap();
+        assert_eq!(*runtime.calls.lock().unwrap(), vec!["up"]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_destroy_failure_still_reports_terminated() {
+        install(FaultPlan::default().fail_destroy());
+        let mut runtime = RecordingRuntime::default();
+        let torn_down = tear_down(&mut runtime).await;
+        assert!(torn_down.is_err());
+        // The teardown was still done
+        assert_eq!(*runtime.calls.lock().unwrap(), vec!["down"]);
+
+        let status = terminated_status("node", "instance", torn_down).0;
+        let Some(Status::Instance(instance)) = status.status else {
+            panic!("Expected an instance status, got {:?}", status.status);
+        };
+        assert_eq!(instance.status, i32::from(InstanceStatus::Terminated));
+        assert_eq!(instance.conditions.len(), 1);
+        assert_eq!(
+            instance.conditions[0].r#type,
+            ConditionType::Terminating as i32
+        );
+        assert_eq!(instance.conditions[0].status, ConditionStatus::False as i32);
+        install(FaultPlan::default());
+    }
+}
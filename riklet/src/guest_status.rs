@@ -0,0 +1,395 @@
+use proto::common::{AppStatus, ConditionStatus, ConditionType, InstanceCondition};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::Sender;
+use tokio::time::timeout;
+use tracing::{debug, warn};
+
+/// Path guests post their status to
+pub const STATUS_PATH: &str = "/status";
+/// Reason of the `Ready` condition taken from the status reported by a guest
+pub const APP_STATUS_REASON: &str = "AppStatus";
+/// Longest status accepted, reports are meant to be short states
+const MAX_STATUS_LENGTH: usize = 64;
+const MAX_BODY_LENGTH: usize = 1024;
+const MAX_HEADER_LINES: usize = 32;
+/// Shortest time between two reports of an instance, the others are refused
+const MIN_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+/// Time a guest has to send its whole request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Body posted by a guest, e.g. `{"status": "warming cache", "ready": false}`
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct GuestReport {
+    pub status: String,
+    #[serde(default)]
+    pub ready: bool,
+}
+
+impl GuestReport {
+    fn validate(&self) -> Result<(), String> {
+        if self.status.trim().is_empty() {
+            return Err(String::from("status must not be empty"));
+        }
+        if self.status.chars().count() > MAX_STATUS_LENGTH {
+            return Err(format!(
+                "status must be at most {} characters long",
+                MAX_STATUS_LENGTH
+            ));
+        }
+        if self.status.chars().any(char::is_control) {
+            return Err(String::from("status must not hold control characters"));
+        }
+        Ok(())
+    }
+}
+
+/// Status reported by the guest of an instance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppStatusReport {
+    pub instance_id: String,
+    pub status: String,
+    pub ready: bool,
+    /// RFC 3339 date at which the report was received
+    pub reported_at: String,
+}
+
+impl AppStatusReport {
+    /// `Ready` condition of the instance, as told by its guest
+    pub fn condition(&self) -> InstanceCondition {
+        let status = match self.ready {
+            true => ConditionStatus::True,
+            false => ConditionStatus::False,
+        };
+        InstanceCondition {
+            r#type: ConditionType::Ready.into(),
+            status: status.into(),
+            reason: String::from(APP_STATUS_REASON),
+            message: self.status.clone(),
+        }
+    }
+
+    pub fn app_status(&self) -> AppStatus {
+        AppStatus {
+            status: self.status.clone(),
+            ready: self.ready,
+            reported_at: self.reported_at.clone(),
+        }
+    }
+}
+
+struct Guest {
+    instance_id: String,
+    last_report: Option<Instant>,
+}
+
+/// Instances allowed to report their status, by the address of their guest
+#[derive(Default)]
+pub struct GuestRegistry {
+    guests: Mutex<HashMap<IpAddr, Guest>>,
+}
+
+impl GuestRegistry {
+    pub fn register(&self, address: IpAddr, instance_id: &str) {
+        let guest = Guest {
+            instance_id: instance_id.to_string(),
+            last_report: None,
+        };
+        self.guests.lock().unwrap().insert(address, guest);
+    }
+
+    pub fn unregister(&self, instance_id: &str) {
+        self.guests
+            .lock()
+            .unwrap()
+            .retain(|_, guest| guest.instance_id != instance_id);
+    }
+
+    /// Instance a report is accepted from, the answer given to the guest
+    /// otherwise
+    fn accept(&self, address: IpAddr, now: Instant) -> Result<String, Answer> {
+        let mut guests = self.guests.lock().unwrap();
+        let guest = guests.get_mut(&address).ok_or(Answer::Forbidden)?;
+        if let Some(last_report) = guest.last_report {
+            if now.duration_since(last_report) < MIN_REPORT_INTERVAL {
+                return Err(Answer::TooManyRequests);
+            }
+        }
+        guest.last_report = Some(now);
+        Ok(guest.instance_id.clone())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Answer {
+    Accepted,
+    BadRequest(String),
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    TooManyRequests,
+}
+
+impl Answer {
+    fn to_http(&self) -> String {
+        let (status, message) = match self {
+            Answer::Accepted => ("204 No Content", String::new()),
+            Answer::BadRequest(message) => ("400 Bad Request", message.clone()),
+            Answer::Forbidden => (
+                "403 Forbidden",
+                String::from("only instances may report their status"),
+            ),
+            Answer::NotFound => ("404 Not Found", format!("post to {}", STATUS_PATH)),
+            Answer::MethodNotAllowed => ("405 Method Not Allowed", String::from("use POST")),
+            Answer::TooManyRequests => (
+                "429 Too Many Requests",
+                format!("at most one report every {:?}", MIN_REPORT_INTERVAL),
+            ),
+        };
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            message.len(),
+            message
+        )
+    }
+}
+
+/// Serve the status endpoint of the guests, the accepted reports are sent to
+/// be forwarded to the scheduler
+pub async fn serve(
+    listener: TcpListener,
+    registry: Arc<GuestRegistry>,
+    reports: Sender<AppStatusReport>,
+) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Could not accept a guest connection: {}", e);
+                continue;
+            }
+        };
+        let registry = registry.clone();
+        let reports = reports.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, peer, &registry, &reports).await {
+                debug!("Guest connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    registry: &GuestRegistry,
+    reports: &Sender<AppStatusReport>,
+) -> io::Result<()> {
+    let answer = match timeout(REQUEST_TIMEOUT, read_report(&mut stream)).await {
+        Ok(Ok(Ok(report))) => accept_report(registry, peer.ip(), report, reports).await,
+        Ok(Ok(Err(answer))) => answer,
+        Ok(Err(e)) => return Err(e),
+        Err(_) => Answer::BadRequest(String::from("request not received in time")),
+    };
+    if answer != Answer::Accepted {
+        debug!("Guest report from {} refused: {:?}", peer, answer);
+    }
+    stream.write_all(answer.to_http().as_bytes()).await?;
+    stream.shutdown().await
+}
+
+async fn accept_report(
+    registry: &GuestRegistry,
+    address: IpAddr,
+    report: GuestReport,
+    reports: &Sender<AppStatusReport>,
+) -> Answer {
+    let instance_id = match registry.accept(address, Instant::now()) {
+        Ok(instance_id) => instance_id,
+        Err(answer) => return answer,
+    };
+    let report = AppStatusReport {
+        instance_id,
+        status: report.status,
+        ready: report.ready,
+        reported_at: chrono::Utc::now().to_rfc3339(),
+    };
+    match reports.send(report).await {
+        Ok(()) => Answer::Accepted,
+        Err(_) => Answer::BadRequest(String::from("the node is shutting down")),
+    }
+}
+
+/// Read a `POST /status` request, with a JSON body of a known length. The
+/// whole request is read before answering, so the guest gets the answer
+async fn read_report(stream: &mut TcpStream) -> io::Result<Result<GuestReport, Answer>> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = None;
+    for _ in 0..MAX_HEADER_LINES {
+        line.clear();
+        reader.read_line(&mut line).await?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let body = match content_length {
+        Some(length) if length <= MAX_BODY_LENGTH => {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await?;
+            Some(body)
+        }
+        _ => None,
+    };
+
+    if path != STATUS_PATH {
+        return Ok(Err(Answer::NotFound));
+    }
+    if method != "POST" {
+        return Ok(Err(Answer::MethodNotAllowed));
+    }
+    let Some(body) = body else {
+        return Ok(Err(Answer::BadRequest(format!(
+            "a Content-Length of at most {} bytes is required",
+            MAX_BODY_LENGTH
+        ))));
+    };
+    let report: GuestReport = match serde_json::from_slice(&body) {
+        Ok(report) => report,
+        Err(e) => return Ok(Err(Answer::BadRequest(e.to_string()))),
+    };
+    Ok(report
+        .validate()
+        .map(|()| report)
+        .map_err(Answer::BadRequest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{Runtime, RuntimeError};
+    use async_trait::async_trait;
+    use std::net::Ipv4Addr;
+    use tokio::sync::mpsc::channel;
+
+    /// Runtime of an instance whose guest runs on the loopback address
+    struct StubRuntime;
+
+    #[async_trait]
+    impl Runtime for StubRuntime {
+        async fn up(&mut self) -> Result<(), RuntimeError> {
+            Ok(())
+        }
+
+        async fn down(&mut self) -> Result<(), RuntimeError> {
+            Ok(())
+        }
+
+        fn address(&self) -> Option<IpAddr> {
+            Some(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        }
+    }
+
+    /// Post a body from the guest, as the example client does, giving the
+    /// status line of the answer
+    async fn post(address: SocketAddr, path: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: node\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            path,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer).await.unwrap();
+        answer.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[test]
+    fn test_validate_report() {
+        let report = |status: &str| GuestReport {
+            status: status.to_string(),
+            ready: true,
+        };
+        assert!(report("warming cache").validate().is_ok());
+        assert!(report(" ").validate().is_err());
+        assert!(report(&"x".repeat(MAX_STATUS_LENGTH + 1))
+            .validate()
+            .is_err());
+        assert!(report("ready\nnow").validate().is_err());
+    }
+
+    #[test]
+    fn test_reports_are_rate_limited() {
+        let registry = GuestRegistry::default();
+        let guest = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let now = Instant::now();
+        assert_eq!(registry.accept(guest, now), Err(Answer::Forbidden));
+
+        registry.register(guest, "instance");
+        assert_eq!(registry.accept(guest, now), Ok(String::from("instance")));
+        assert_eq!(
+            registry.accept(guest, now + Duration::from_millis(100)),
+            Err(Answer::TooManyRequests)
+        );
+        assert!(registry.accept(guest, now + MIN_REPORT_INTERVAL).is_ok());
+
+        registry.unregister("instance");
+        assert_eq!(
+            registry.accept(guest, now + MIN_REPORT_INTERVAL * 2),
+            Err(Answer::Forbidden)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_guest_reports_its_status() {
+        let runtime = StubRuntime;
+        let registry = Arc::new(GuestRegistry::default());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, mut receiver) = channel(8);
+        tokio::spawn(serve(listener, registry.clone(), sender));
+
+        // Not registered yet, as before the instance booted
+        let body = r#"{"status": "warming cache", "ready": false}"#;
+        assert!(post(address, STATUS_PATH, body).await.contains("403"));
+
+        registry.register(runtime.address().unwrap(), "hello-1234");
+        assert!(post(address, STATUS_PATH, body).await.contains("204"));
+        let report = receiver.recv().await.unwrap();
+        assert_eq!(report.instance_id, "hello-1234");
+        assert_eq!(report.app_status().status, "warming cache");
+        let condition = report.condition();
+        assert_eq!(condition.r#type, i32::from(ConditionType::Ready));
+        assert_eq!(condition.status, i32::from(ConditionStatus::False));
+        assert_eq!(condition.reason, APP_STATUS_REASON);
+
+        let ready = r#"{"status": "ready", "ready": true}"#;
+        assert!(post(address, STATUS_PATH, ready).await.contains("429"));
+        assert!(post(address, "/metadata", ready).await.contains("404"));
+        assert!(post(address, STATUS_PATH, r#"{"ready": true}"#)
+            .await
+            .contains("400"));
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tracing::{debug, warn};
+
+/// Path the logs of an instance are read from, followed by its id
+pub const LOGS_PATH: &str = "/logs/";
+/// Lines kept for each instance, the oldest are dropped first
+const MAX_RETAINED_LINES: usize = 1000;
+/// Lines answered when the request gives no `tail`
+const DEFAULT_TAIL: usize = 100;
+const MAX_HEADER_LINES: usize = 32;
+/// Time a client has to send its whole request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Last lines written by the instances on their console
+#[derive(Default)]
+pub struct InstanceLogs {
+    lines: Mutex<HashMap<String, VecDeque<String>>>,
+}
+
+impl InstanceLogs {
+    /// Keep the logs of an instance, which has written none yet
+    pub fn register(&self, instance_id: &str) {
+        self.lines
+            .lock()
+            .unwrap()
+            .entry(instance_id.to_string())
+            .or_default();
+    }
+
+    pub fn append(&self, instance_id: &str, line: &str) {
+        let mut logs = self.lines.lock().unwrap();
+        let lines = logs.entry(instance_id.to_string()).or_default();
+        if lines.len() == MAX_RETAINED_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line.to_string());
+    }
+
+    /// Last `count` lines of an instance, the oldest first
+    pub fn tail(&self, instance_id: &str, count: usize) -> Option<Vec<String>> {
+        let logs = self.lines.lock().unwrap();
+        let lines = logs.get(instance_id)?;
+        Some(
+            lines
+                .iter()
+                .skip(lines.len().saturating_sub(count))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    pub fn remove(&self, instance_id: &str) {
+        self.lines.lock().unwrap().remove(instance_id);
+    }
+}
+
+/// Logs of the instances of the node
+pub fn instance_logs() -> &'static InstanceLogs {
+    static LOGS: OnceLock<InstanceLogs> = OnceLock::new();
+    LOGS.get_or_init(InstanceLogs::default)
+}
+
+/// Keep the lines read from the console of an instance, until it is closed
+pub async fn capture<R: AsyncRead + Unpin>(logs: &InstanceLogs, instance_id: &str, console: R) {
+    let mut lines = BufReader::new(console).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => logs.append(instance_id, line.trim_end_matches('\r')),
+            Ok(None) => break,
+            // Terminals are read until they fail, once the instance stopped
+            Err(e) => {
+                debug!("Stopped reading the console of {}: {}", instance_id, e);
+                break;
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Answer {
+    Lines(Vec<String>),
+    BadRequest(String),
+    NotFound(String),
+    MethodNotAllowed,
+}
+
+impl Answer {
+    fn to_http(&self) -> String {
+        let (status, body) = match self {
+            Answer::Lines(lines) => (
+                "200 OK",
+                lines.iter().map(|line| format!("{}\n", line)).collect(),
+            ),
+            Answer::BadRequest(message) => ("400 Bad Request", message.clone()),
+            Answer::NotFound(message) => ("404 Not Found", message.clone()),
+            Answer::MethodNotAllowed => ("405 Method Not Allowed", String::from("use GET")),
+        };
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+}
+
+/// Answer a `GET /logs/<instance id>?tail=N` request line
+fn answer(logs: &InstanceLogs, request_line: &str) -> Answer {
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let Some(instance_id) = path.strip_prefix(LOGS_PATH).filter(|id| !id.is_empty()) else {
+        return Answer::NotFound(format!("get {}<instance id>", LOGS_PATH));
+    };
+    if method != "GET" {
+        return Answer::MethodNotAllowed;
+    }
+    let mut tail = DEFAULT_TAIL;
+    for (name, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        if name == "tail" {
+            match value.parse() {
+                Ok(value) => tail = value,
+                Err(_) => return Answer::BadRequest(String::from("tail must be a number")),
+            }
+        }
+    }
+    match logs.tail(instance_id, tail) {
+        Some(lines) => Answer::Lines(lines),
+        None => Answer::NotFound(format!("no logs for instance {}", instance_id)),
+    }
+}
+
+/// Serve the logs of the instances to the controller
+pub async fn serve(listener: TcpListener, logs: &'static InstanceLogs) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Could not accept a logs connection: {}", e);
+                continue;
+            }
+        };
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, logs).await {
+                debug!("Logs connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, logs: &InstanceLogs) -> io::Result<()> {
+    let answer = match timeout(REQUEST_TIMEOUT, read_request_line(&mut stream)).await {
+        Ok(Ok(request_line)) => answer(logs, &request_line),
+        Ok(Err(e)) => return Err(e),
+        Err(_) => Answer::BadRequest(String::from("request not received in time")),
+    };
+    stream.write_all(answer.to_http().as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Read a request without a body, giving its request line
+async fn read_request_line(stream: &mut TcpStream) -> io::Result<String> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let mut line = String::new();
+    for _ in 0..MAX_HEADER_LINES {
+        line.clear();
+        reader.read_line(&mut line).await?;
+        if line.trim_end().is_empty() {
+            break;
+        }
+    }
+    Ok(request_line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn test_only_the_last_lines_are_kept() {
+        let logs = InstanceLogs::default();
+        assert_eq!(logs.tail("web-1", 10), None);
+        logs.register("web-1");
+        assert_eq!(logs.tail("web-1", 10), Some(vec![]));
+
+        for index in 0..MAX_RETAINED_LINES + 5 {
+            logs.append("web-1", &format!("line {}", index));
+        }
+        let lines = logs.tail("web-1", MAX_RETAINED_LINES * 2).unwrap();
+        assert_eq!(lines.len(), MAX_RETAINED_LINES);
+        assert_eq!(lines[0], "line 5");
+        assert_eq!(
+            logs.tail("web-1", 2).unwrap(),
+            [
+                format!("line {}", MAX_RETAINED_LINES + 3),
+                format!("line {}", MAX_RETAINED_LINES + 4)
+            ]
+        );
+
+        logs.remove("web-1");
+        assert_eq!(logs.tail("web-1", 10), None);
+    }
+
+    #[tokio::test]
+    async fn test_console_is_captured() {
+        let logs = InstanceLogs::default();
+        capture(&logs, "web-1", "booting\r\nready\r\n".as_bytes()).await;
+        assert_eq!(logs.tail("web-1", 10).unwrap(), ["booting", "ready"]);
+    }
+
+    #[test]
+    fn test_answer() {
+        let logs = InstanceLogs::default();
+        for line in ["one", "two", "three"] {
+            logs.append("web-1", line);
+        }
+        assert_eq!(
+            answer(&logs, "GET /logs/web-1?tail=2 HTTP/1.1"),
+            Answer::Lines(vec![String::from("two"), String::from("three")])
+        );
+        assert_eq!(
+            answer(&logs, "GET /logs/web-1 HTTP/1.1"),
+            Answer::Lines(vec![
+                String::from("one"),
+                String::from("two"),
+                String::from("three")
+            ])
+        );
+        assert!(matches!(
+            answer(&logs, "GET /logs/web-1?tail=all HTTP/1.1"),
+            Answer::BadRequest(_)
+        ));
+        assert!(matches!(
+            answer(&logs, "GET /logs/web-2 HTTP/1.1"),
+            Answer::NotFound(_)
+        ));
+        assert!(matches!(
+            answer(&logs, "GET /status HTTP/1.1"),
+            Answer::NotFound(_)
+        ));
+        assert_eq!(
+            answer(&logs, "POST /logs/web-1 HTTP/1.1"),
+            Answer::MethodNotAllowed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_logs_are_served() {
+        let logs = instance_logs();
+        logs.append("served-1", "hello");
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, logs));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(b"GET /logs/served-1?tail=5 HTTP/1.1\r\nHost: node\r\n\r\n")
+            .await
+            .unwrap();
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer).await.unwrap();
+        assert!(answer.starts_with("HTTP/1.1 200 OK"));
+        assert!(answer.ends_with("\r\n\r\nhello\n"));
+    }
+}
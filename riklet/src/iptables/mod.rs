@@ -134,6 +134,9 @@ type Result<T> = std::result::Result<T, IptablesError>;
 /// this interface makes able to develop on other platform
 pub trait MutateIptables {
     fn create(&mut self, rule: &Rule) -> Result<()>;
+    /// Same as [MutateIptables::create], but the rule is inserted first in its
+    /// chain so the rules already there can't shadow it
+    fn create_first(&mut self, rule: &Rule) -> Result<()>;
     fn create_chain(&mut self, chain: &Chain, table: &Table) -> Result<()>;
     fn delete(&mut self, rule: &Rule) -> Result<()>;
     fn delete_chain(&mut self, chain: &Chain, table: &Table) -> Result<()>;
@@ -31,6 +31,25 @@ impl MutateIptables for Iptables {
             .map_err(|e| IptablesError::LoadFailed(e.to_string()))
             .map(|_| self.rules.push(rule.clone()))
     }
+
+    fn create_first(&mut self, rule: &Rule) -> Result<()> {
+        trace!("Tries to insert iptables rule {}", rule);
+        self.validate_combo_table_chain(rule.table.clone(), rule.chain.clone())?;
+        if self.exists(rule)? {
+            trace!("Could not insert rule {}", rule);
+            return Err(IptablesError::AlreadyExist(rule.clone()));
+        }
+        self.inner
+            .insert(
+                &rule.table.to_string(),
+                &rule.chain.to_string(),
+                &rule.rule,
+                1,
+            )
+            .map_err(|e| IptablesError::LoadFailed(e.to_string()))
+            .map(|_| self.rules.push(rule.clone()))
+    }
+
     /// Tries to delete a rule, in case it does not exist it will throw [IptablesError::AlreadyDeleted]
     /// ## Example
     /// ```
@@ -12,6 +12,14 @@ impl MutateIptables for Iptables {
         ))
     }
 
+    /// Implementation is not supported on other platform than linux
+    fn create_first(&mut self, _: &Rule) -> Result<()> {
+        warn!("Rule creation is not supported on this platform, skipping");
+        Err(IptablesError::LoadFailed(
+            "Not supported on this platform".to_string(),
+        ))
+    }
+
     /// Implementation is not supported on other platform than linux
     fn delete(&mut self, _: &Rule) -> Result<()> {
         warn!("Rule deletion is not supported on this platform, skipping");
@@ -1,20 +1,29 @@
+mod admin;
 mod cli;
 mod constants;
 mod core;
 mod emitters;
+mod faults;
+mod guest_status;
+mod instance_logs;
 mod iptables;
 mod net_utils;
+mod node_checks;
+mod persistence;
 mod runtime;
 mod structs;
+mod sync;
 
+use crate::admin::RuntimeControls;
 use crate::core::Riklet;
 use anyhow::{Context, Result};
+use std::sync::Arc;
 
 use tokio::signal::ctrl_c;
 use tokio::signal::unix::{signal, SignalKind};
 use tracing::{error, info, metadata::LevelFilter};
 use tracing_subscriber::{
-    fmt, prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt, EnvFilter,
+    fmt, prelude::__tracing_subscriber_SubscriberExt, reload, util::SubscriberInitExt, EnvFilter,
 };
 
 pub fn banner() {
@@ -30,20 +39,24 @@ pub fn banner() {
     );
 }
 
-pub fn init_logger() -> Result<()> {
+/// Install the tracing subscriber, its filter can be changed through the
+/// returned controls
+pub fn init_logger() -> Result<Arc<RuntimeControls>> {
+    let directives = std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| String::from("info"));
+    let (filter, handle) = reload::Layer::new(
+        EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .from_env_lossy(),
+    );
     tracing_subscriber::registry()
+        .with(filter)
         .with(fmt::layer())
-        .with(
-            EnvFilter::builder()
-                .with_default_directive(LevelFilter::INFO.into())
-                .from_env_lossy(),
-        )
         .init();
-    Ok(())
+    Ok(RuntimeControls::new(handle, directives))
 }
 
-async fn serve() -> Result<()> {
-    let mut riklet = Riklet::new().await.unwrap_or_else(|e| {
+async fn serve(controls: Arc<RuntimeControls>) -> Result<()> {
+    let mut riklet = Riklet::new(controls).await.unwrap_or_else(|e| {
         error!(
             "An error occured during the bootstraping process of the Riklet. {}",
             e
@@ -72,7 +85,7 @@ async fn serve() -> Result<()> {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    init_logger()?;
+    let controls = init_logger()?;
 
     // If the process doesn't have root privileges, exit and display error.
     if !nix::unistd::Uid::effective().is_root() {
@@ -80,7 +93,7 @@ async fn main() -> Result<()> {
         std::process::exit(1);
     }
 
-    serve().await?;
+    serve(controls).await?;
 
     info!("Riklet stopped");
 
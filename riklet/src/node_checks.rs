@@ -0,0 +1,260 @@
+use crate::cli::config::{Configuration, NodeChecksConfiguration};
+use crate::constants::DEFAULT_FIRECRACKER_WORKSPACE;
+use crate::runtime::rootfs_cache::{
+    migrate_legacy_cache, open_files, RootfsCache, LEGACY_CACHE_DIRECTORY,
+};
+use definition::workload::WorkloadKind;
+use definition::{NodeCondition, NodeConditionType};
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, info, warn};
+
+/// Device used to run functions
+const KVM_DEVICE: &str = "/dev/kvm";
+/// Command used to run containers when none is configured
+const RUNC_COMMAND: &str = "runc";
+/// Clock state returned by adjtimex when the clock is not synchronized
+const TIME_ERROR: libc::c_int = 5;
+
+/// Periodic local checks of the node health.
+///
+/// Checks are only run once per interval, the last results are reused
+/// for the heartbeats sent in between.
+pub struct NodeChecks {
+    config: NodeChecksConfiguration,
+    /// Paths whose disk usage is watched
+    disk_paths: Vec<PathBuf>,
+    images_directory: Option<PathBuf>,
+    rootfs_cache: RootfsCache,
+    last_run: Option<Instant>,
+    conditions: Vec<NodeCondition>,
+}
+
+impl NodeChecks {
+    pub fn new(configuration: &Configuration) -> Self {
+        let mut disk_paths = vec![
+            PathBuf::from(DEFAULT_FIRECRACKER_WORKSPACE),
+            configuration.rootfs_cache_directory.clone(),
+        ];
+        let images_directory = configuration.manager.image_puller.images_directory.clone();
+        disk_paths.extend(images_directory.clone());
+        disk_paths.extend(configuration.manager.oci_manager.bundles_directory.clone());
+
+        Self {
+            config: configuration.node_checks.clone(),
+            disk_paths,
+            images_directory,
+            rootfs_cache: RootfsCache::new(&configuration.rootfs_cache_directory),
+            last_run: None,
+            conditions: Vec::new(),
+        }
+    }
+
+    /// Conditions of the node, checks are run again if the interval is elapsed
+    pub fn conditions(&mut self) -> Vec<NodeCondition> {
+        let interval = Duration::from_secs(self.config.interval);
+        let up_to_date = matches!(self.last_run, Some(last_run) if last_run.elapsed() < interval);
+        if !up_to_date {
+            self.conditions = self.run();
+            self.last_run = Some(Instant::now());
+        }
+        self.conditions.clone()
+    }
+
+    /// Clean up the root filesystem cache now, and run the checks again
+    /// instead of reusing their last results
+    pub fn reconcile(&mut self) {
+        info!("Running a reconciliation pass");
+        if let Err(e) = migrate_legacy_cache(
+            &self.rootfs_cache,
+            Path::new(LEGACY_CACHE_DIRECTORY),
+            &open_files(),
+            SystemTime::now(),
+        ) {
+            warn!("Could not migrate the legacy root filesystems: {}", e);
+        }
+        self.last_run = None;
+    }
+
+    fn run(&self) -> Vec<NodeCondition> {
+        debug!("Running node checks");
+        let mut conditions = Vec::new();
+        if self.config.disk_pressure {
+            conditions.push(self.check_disk_pressure());
+        }
+        if self.config.kvm {
+            conditions.push(Self::check_kvm());
+        }
+        if self.config.image_cache {
+            conditions.push(self.check_image_cache());
+        }
+        if self.config.clock {
+            conditions.push(Self::check_clock());
+        }
+        for condition in conditions.iter().filter(|condition| condition.active) {
+            warn!("{}: {}", condition.condition_type, condition.message);
+        }
+        conditions
+    }
+
+    fn check_disk_pressure(&self) -> NodeCondition {
+        let threshold = self.config.disk_pressure_threshold as u64;
+        let full_paths: Vec<String> = self
+            .disk_paths
+            .iter()
+            .filter_map(|path| {
+                let usage = disk_usage(path)?;
+                (usage >= threshold).then(|| format!("{} is {}% full", path.display(), usage))
+            })
+            .collect();
+
+        condition(NodeConditionType::DiskPressure, full_paths.join(", "))
+    }
+
+    fn check_kvm() -> NodeCondition {
+        let message = match OpenOptions::new().read(true).write(true).open(KVM_DEVICE) {
+            Ok(_) => String::new(),
+            Err(e) => format!("Cannot open {}: {}", KVM_DEVICE, e),
+        };
+        condition(NodeConditionType::KvmUnavailable, message)
+    }
+
+    /// Only looks at the first level of the cache directories to stay cheap
+    fn check_image_cache(&self) -> NodeCondition {
+        let mut problems = Vec::new();
+        if let Some(images_directory) = &self.images_directory {
+            if images_directory.exists() && fs::read_dir(images_directory).is_err() {
+                problems.push(format!("Cannot read {}", images_directory.display()));
+            }
+        }
+
+        if let Ok(entries) = fs::read_dir(self.rootfs_cache.blobs_directory()) {
+            for entry in entries.flatten() {
+                let rootfs = entry.path();
+                if let Ok(metadata) = fs::metadata(&rootfs) {
+                    if metadata.len() == 0 {
+                        problems.push(format!("{} is empty", rootfs.display()));
+                    }
+                }
+            }
+        }
+
+        condition(NodeConditionType::ImageCacheCorrupted, problems.join(", "))
+    }
+
+    fn check_clock() -> NodeCondition {
+        // SAFETY: adjtimex only reads the clock state when no mode is set
+        let state = unsafe {
+            let mut timex: libc::timex = std::mem::zeroed();
+            libc::adjtimex(&mut timex)
+        };
+        let message = match state {
+            TIME_ERROR => String::from("System clock is not synchronized"),
+            -1 => format!(
+                "Cannot read the clock state: {}",
+                std::io::Error::last_os_error()
+            ),
+            _ => String::new(),
+        };
+        condition(NodeConditionType::ClockUnsynced, message)
+    }
+}
+
+/// Kinds of workloads the node can run.
+///
+/// The configured kinds win, otherwise functions need KVM and pods need runc.
+pub fn supported_kinds(configuration: &Configuration) -> Vec<WorkloadKind> {
+    if let Some(kinds) = &configuration.supported_kinds {
+        return kinds.clone();
+    }
+
+    let mut kinds = Vec::new();
+    if runc_available(configuration) {
+        kinds.push(WorkloadKind::Pod);
+    }
+    if OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(KVM_DEVICE)
+        .is_ok()
+    {
+        kinds.push(WorkloadKind::Function);
+    }
+    kinds
+}
+
+fn runc_available(configuration: &Configuration) -> bool {
+    if let Some(command) = &configuration.runner.command {
+        return command.is_file();
+    }
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|path| path.join(RUNC_COMMAND).is_file()))
+        .unwrap_or(false)
+}
+
+/// A condition is active when a problem has been described
+fn condition(condition_type: NodeConditionType, message: String) -> NodeCondition {
+    NodeCondition {
+        condition_type,
+        active: !message.is_empty(),
+        message,
+    }
+}
+
+/// Percentage of the disk used, for the disk holding the given path
+fn disk_usage(path: &Path) -> Option<u64> {
+    let stat = nix::sys::statvfs::statvfs(path).ok()?;
+    let blocks = stat.blocks() as u64;
+    if blocks == 0 {
+        return None;
+    }
+    let available = stat.blocks_available() as u64;
+    Some(100 - available * 100 / blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_condition_active_with_message() {
+        let active = condition(
+            NodeConditionType::DiskPressure,
+            String::from("/ is 95% full"),
+        );
+        assert!(active.active);
+
+        let inactive = condition(NodeConditionType::DiskPressure, String::new());
+        assert!(!inactive.active);
+    }
+
+    #[test]
+    fn test_disabled_checks_are_not_reported() {
+        let configuration = Configuration {
+            node_checks: NodeChecksConfiguration {
+                interval: 60,
+                disk_pressure: false,
+                disk_pressure_threshold: 90,
+                kvm: false,
+                image_cache: false,
+                clock: false,
+            },
+            ..Default::default()
+        };
+        let mut checks = NodeChecks::new(&configuration);
+        assert!(checks.conditions().is_empty());
+    }
+
+    #[test]
+    fn test_configured_kinds_are_not_detected() {
+        let configuration = Configuration {
+            supported_kinds: Some(vec![WorkloadKind::Function]),
+            ..Default::default()
+        };
+        assert_eq!(
+            supported_kinds(&configuration),
+            vec![WorkloadKind::Function]
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Files the riklet keeps across restarts, written so a crash never leaves
+//! them half written.
+//!
+//! A state file holds a header line with the version of its content and the
+//! SHA-256 of the content, followed by the content as JSON:
+//!
+//! ```text
+//! rik-state 1 3c47ef97...
+//! {"urls": {...}}
+//! ```
+
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing::{event, Level};
+
+const HEADER_PREFIX: &str = "rik-state";
+
+/// Replace the content of a file, readers see either the previous content or
+/// the new one, even after a crash.
+///
+/// The content is written to a temporary file of the same directory, flushed
+/// to the disk, then renamed over the file, and the rename itself is flushed.
+pub fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let directory = path.parent().unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(directory)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = directory.join(format!(".{}.{}.tmp", name, uuid::Uuid::new_v4()));
+
+    let written = File::create(&temporary).and_then(|mut file| {
+        file.write_all(content)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&temporary, path)) {
+        let _ = fs::remove_file(&temporary);
+        return Err(e);
+    }
+    sync_directory(directory)
+}
+
+/// Flush the entries of a directory, such as a file renamed in it
+pub fn sync_directory(directory: &Path) -> io::Result<()> {
+    File::open(directory)?.sync_all()
+}
+
+/// Write a state file, see the module documentation for its format
+pub fn write_state<T: Serialize>(path: &Path, version: u32, state: &T) -> io::Result<()> {
+    let content = serde_json::to_vec(state)?;
+    let mut file = format!(
+        "{} {} {:x}\n",
+        HEADER_PREFIX,
+        version,
+        Sha256::digest(&content)
+    )
+    .into_bytes();
+    file.extend(content);
+    write_atomic(path, &file)
+}
+
+/// Why a state file could not be read
+#[derive(Debug, PartialEq, Eq)]
+enum Corruption {
+    Truncated,
+    Checksum,
+    Version(u32),
+    Content(String),
+}
+
+/// Read a state file written by `write_state`, `None` when there is none.
+///
+/// A corrupt file, or one of another version, is moved aside with a
+/// `.corrupt-<date>` suffix and `None` is given, so the caller starts over
+/// and the file can still be looked at. Files written as plain JSON before
+/// the header was added are read as version 1.
+pub fn read_state<T: DeserializeOwned>(path: &Path, version: u32) -> Option<T> {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            event!(Level::ERROR, "Could not read {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    match parse_state(&content, version) {
+        Ok(state) => Some(state),
+        Err(corruption) => {
+            let quarantine = quarantine_path(path);
+            event!(
+                Level::ERROR,
+                "State file {} is unusable ({:?}), moved to {}",
+                path.display(),
+                corruption,
+                quarantine.display()
+            );
+            if let Err(e) = fs::rename(path, &quarantine) {
+                event!(
+                    Level::ERROR,
+                    "Could not move aside {}: {}",
+                    path.display(),
+                    e
+                );
+            }
+            None
+        }
+    }
+}
+
+fn parse_state<T: DeserializeOwned>(content: &[u8], version: u32) -> Result<T, Corruption> {
+    let content_error = |e: serde_json::Error| Corruption::Content(e.to_string());
+    if !content.starts_with(HEADER_PREFIX.as_bytes()) {
+        // Plain JSON written before the header, an empty file is a truncated one
+        if version != 1 || content.is_empty() {
+            return Err(Corruption::Truncated);
+        }
+        return serde_json::from_slice(content).map_err(content_error);
+    }
+
+    let newline = content
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .ok_or(Corruption::Truncated)?;
+    let header = std::str::from_utf8(&content[..newline]).map_err(|_| Corruption::Truncated)?;
+    let body = &content[newline + 1..];
+    let mut fields = header.split(' ').skip(1);
+    let (Some(found_version), Some(checksum), None) = (fields.next(), fields.next(), fields.next())
+    else {
+        return Err(Corruption::Truncated);
+    };
+    let found_version: u32 = found_version.parse().map_err(|_| Corruption::Truncated)?;
+    if format!("{:x}", Sha256::digest(body)) != checksum {
+        return Err(Corruption::Checksum);
+    }
+    if found_version != version {
+        return Err(Corruption::Version(found_version));
+    }
+    serde_json::from_slice(body).map_err(content_error)
+}
+
+fn quarantine_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".corrupt-{}",
+        Utc::now().format("%Y%m%dT%H%M%S%.6fZ")
+    ));
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Default)]
+    struct State {
+        urls: BTreeMap<String, String>,
+    }
+
+    fn temporary_directory() -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("riklet-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn state() -> State {
+        State {
+            urls: BTreeMap::from([
+                (String::from("https://a/rootfs.ext4"), "a".repeat(64)),
+                (String::from("https://b/rootfs.ext4"), "b".repeat(64)),
+            ]),
+        }
+    }
+
+    fn quarantined(directory: &Path) -> usize {
+        fs::read_dir(directory)
+            .unwrap()
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().contains(".corrupt-"))
+            .count()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = temporary_directory().join("state.json");
+        assert_eq!(read_state::<State>(&path, 1), None);
+
+        write_state(&path, 1, &state()).unwrap();
+        assert_eq!(read_state(&path, 1), Some(state()));
+        // No temporary file is left behind
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_truncated_at_any_point() {
+        let directory = temporary_directory();
+        let path = directory.join("state.json");
+        write_state(&path, 1, &state()).unwrap();
+        let content = fs::read(&path).unwrap();
+
+        for length in 0..content.len() {
+            fs::write(&path, &content[..length]).unwrap();
+            // Never a partial state
+            assert_eq!(read_state::<State>(&path, 1), None, "length {}", length);
+            assert!(!path.exists());
+        }
+        assert_eq!(quarantined(&directory), content.len());
+    }
+
+    #[test]
+    fn test_corrupt_content() {
+        let directory = temporary_directory();
+        let path = directory.join("state.json");
+        write_state(&path, 1, &state()).unwrap();
+        let mut content = fs::read(&path).unwrap();
+        let last = content.len() - 3;
+        content[last] = b'c';
+        fs::write(&path, &content).unwrap();
+
+        assert_eq!(
+            parse_state::<State>(&content, 1).unwrap_err(),
+            Corruption::Checksum
+        );
+        assert_eq!(read_state::<State>(&path, 1), None);
+        assert_eq!(quarantined(&directory), 1);
+    }
+
+    #[test]
+    fn test_versions() {
+        let path = temporary_directory().join("state.json");
+        write_state(&path, 2, &state()).unwrap();
+        let content = fs::read(&path).unwrap();
+        assert_eq!(
+            parse_state::<State>(&content, 1).unwrap_err(),
+            Corruption::Version(2)
+        );
+
+        // Files written before the header
+        let legacy = serde_json::to_vec(&state()).unwrap();
+        assert_eq!(parse_state::<State>(&legacy, 1), Ok(state()));
+        assert!(parse_state::<State>(&legacy, 2).is_err());
+    }
+}
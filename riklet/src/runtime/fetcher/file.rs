@@ -0,0 +1,149 @@
+use super::{FetchError, Fetched, Fetcher, Result};
+use crate::runtime::rootfs_cache::Download;
+use chrono::{DateTime, Utc};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Images in files of the node, mostly for development.
+///
+/// Files are copied rather than hard-linked, as the microVMs write to their
+/// root filesystem, and read again for every instance as they may change.
+/// Only the files of the allowed directories are read, so a workload cannot
+/// copy any file of the node into its instances.
+pub struct FileFetcher {
+    allowed_directories: Vec<PathBuf>,
+}
+
+impl FileFetcher {
+    pub fn new(allowed_directories: Vec<PathBuf>) -> Self {
+        FileFetcher {
+            allowed_directories,
+        }
+    }
+
+    /// Path of the file of a URL, with the links resolved, once it is known
+    /// to be in an allowed directory
+    fn path(&self, url: &Url) -> Result<PathBuf> {
+        let path = url
+            .to_file_path()
+            .map_err(|_| FetchError::InvalidUrl(url.to_string()))?;
+        let path = path
+            .canonicalize()
+            .map_err(|source| FetchError::File { path, source })?;
+        let allowed = self
+            .allowed_directories
+            .iter()
+            .filter_map(|directory| directory.canonicalize().ok())
+            .any(|directory| path.starts_with(directory));
+        if !allowed {
+            return Err(FetchError::ForbiddenPath(path));
+        }
+        Ok(path)
+    }
+
+    fn download(url: &Url, path: &Path) -> Result<Download> {
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|source| FetchError::File {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        Ok(Download {
+            resolved_url: url.to_string(),
+            etag: None,
+            last_modified: Some(
+                DateTime::<Utc>::from(modified)
+                    .format("%a, %d %b %Y %H:%M:%S GMT")
+                    .to_string(),
+            ),
+        })
+    }
+}
+
+impl Fetcher for FileFetcher {
+    fn schemes(&self) -> &[&'static str] {
+        &["file"]
+    }
+
+    fn resolve(&self, url: &Url) -> Result<Fetched> {
+        let path = self.path(url)?;
+        let content = File::open(&path).map_err(|source| FetchError::File {
+            path: path.clone(),
+            source,
+        })?;
+        Ok(Fetched {
+            content: Box::new(content),
+            download: Self::download(url, &path)?,
+        })
+    }
+
+    fn cacheable(&self) -> bool {
+        false
+    }
+
+    fn fetch(&self, url: &Url, destination: &Path) -> Result<Download> {
+        let path = self.path(url)?;
+        let download = Self::download(url, &path)?;
+        fs::copy(&path, destination).map_err(|source| FetchError::File { path, source })?;
+        Ok(download)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fetch_local_file() {
+        let directory = std::env::temp_dir().join(format!("riklet-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        let image = directory.join("rootfs.ext4");
+        fs::write(&image, "rootfs").unwrap();
+        let url = Url::from_file_path(&image).unwrap();
+
+        let destination = directory.join("download.part");
+        let fetcher = FileFetcher::new(vec![directory.clone()]);
+        let download = fetcher.fetch(&url, &destination).unwrap();
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "rootfs");
+        assert_eq!(download.resolved_url, url.to_string());
+        assert!(matches!(
+            FileFetcher::new(vec![]).fetch(&url, &destination),
+            Err(FetchError::ForbiddenPath(_))
+        ));
+        assert!(download.last_modified.unwrap().ends_with(" GMT"));
+
+        let missing = Url::from_file_path(directory.join("missing.ext4")).unwrap();
+        assert!(matches!(
+            fetcher.fetch(&missing, &destination),
+            Err(FetchError::File { .. })
+        ));
+        let remote = Url::parse("file://host/rootfs.ext4").unwrap();
+        assert!(matches!(
+            fetcher.resolve(&remote),
+            Err(FetchError::InvalidUrl(_))
+        ));
+    }
+
+    #[test]
+    fn test_reject_files_outside_allowed_directories() {
+        let root = std::env::temp_dir().join(format!("riklet-{}", uuid::Uuid::new_v4()));
+        let images = root.join("images");
+        fs::create_dir_all(&images).unwrap();
+        fs::write(root.join("secret"), "secret").unwrap();
+        let fetcher = FileFetcher::new(vec![images.clone()]);
+
+        // Neither through `..` nor through a link in the allowed directory
+        let escaped = Url::parse(&format!("file://{}/../secret", images.display())).unwrap();
+        assert!(matches!(
+            fetcher.resolve(&escaped),
+            Err(FetchError::ForbiddenPath(_))
+        ));
+        std::os::unix::fs::symlink(root.join("secret"), images.join("link.ext4")).unwrap();
+        let linked = Url::from_file_path(images.join("link.ext4")).unwrap();
+        assert!(matches!(
+            fetcher.resolve(&linked),
+            Err(FetchError::ForbiddenPath(_))
+        ));
+    }
+}
@@ -0,0 +1,98 @@
+use super::{FetchError, Fetched, Fetcher, Result};
+use crate::runtime::rootfs_cache::Download;
+use curl::easy::{Easy, List};
+use std::io::Cursor;
+use tracing::{event, Level};
+use url::Url;
+
+/// Images served over http(s)
+pub struct HttpFetcher;
+
+impl Fetcher for HttpFetcher {
+    fn schemes(&self) -> &[&'static str] {
+        &["http", "https"]
+    }
+
+    fn resolve(&self, url: &Url) -> Result<Fetched> {
+        let response = get(url.as_str(), &[])?;
+        if response.status != 200 {
+            return Err(FetchError::Status {
+                status: response.status,
+            });
+        }
+        Ok(Fetched {
+            content: Box::new(Cursor::new(response.body)),
+            download: response.download,
+        })
+    }
+}
+
+pub struct Response {
+    pub status: u32,
+    pub body: Vec<u8>,
+    pub download: Download,
+}
+
+/// Get a URL, following redirects, the body is kept in memory
+pub fn get(url: &str, headers: &[(String, String)]) -> Result<Response> {
+    event!(Level::DEBUG, "Downloading image from {}", url);
+    let transfer_error = |source| FetchError::Transfer {
+        url: url.to_string(),
+        source,
+    };
+
+    let mut easy = Easy::new();
+    let mut body = Vec::new();
+    let mut response_headers = Vec::new();
+    easy.url(url).map_err(transfer_error)?;
+    easy.follow_location(true).map_err(transfer_error)?;
+    let mut list = List::new();
+    for (name, value) in headers {
+        list.append(&format!("{}: {}", name, value))
+            .map_err(transfer_error)?;
+    }
+    easy.http_headers(list).map_err(transfer_error)?;
+
+    {
+        let mut transfer = easy.transfer();
+        transfer
+            .write_function(|data| {
+                body.extend_from_slice(data);
+                Ok(data.len())
+            })
+            .map_err(transfer_error)?;
+        transfer
+            .header_function(|header| {
+                // Headers of a redirection are replaced by the ones of the next response
+                let header = String::from_utf8_lossy(header);
+                if header.starts_with("HTTP/") {
+                    response_headers.clear();
+                } else if let Some((name, value)) = header.split_once(':') {
+                    response_headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+                }
+                true
+            })
+            .map_err(transfer_error)?;
+        transfer.perform().map_err(transfer_error)?;
+    }
+
+    let header = |name: &str| {
+        response_headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.clone())
+    };
+    Ok(Response {
+        status: easy.response_code().map_err(transfer_error)?,
+        download: Download {
+            resolved_url: easy
+                .effective_url()
+                .map_err(transfer_error)?
+                .unwrap_or(url)
+                .to_string(),
+            etag: header("etag"),
+            last_modified: header("last-modified"),
+        },
+        body,
+    })
+}
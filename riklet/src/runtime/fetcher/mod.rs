@@ -0,0 +1,322 @@
+pub mod file;
+pub mod http;
+pub mod s3;
+
+use super::rootfs_cache::{Download, Provenance, RootfsCache};
+use super::RuntimeError;
+use crate::faults;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{event, warn, Level};
+use url::Url;
+
+use self::file::FileFetcher;
+use self::http::HttpFetcher;
+use self::s3::{S3Configuration, S3Fetcher};
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("No fetcher for {0} URLs")]
+    UnsupportedScheme(String),
+    #[error("Invalid image URL {0}")]
+    InvalidUrl(String),
+    #[error("Transfer of {url} failed: {source}")]
+    Transfer { url: String, source: curl::Error },
+    #[error("Response code from registry: {status}")]
+    Status { status: u32 },
+    #[error("Cannot read {}: {source}", path.display())]
+    File { path: PathBuf, source: io::Error },
+    #[error("{} is not in a directory allowed for file:// images", .0.display())]
+    ForbiddenPath(PathBuf),
+    #[error("S3 answered {status} for {url}: {code}, {message}")]
+    S3 {
+        url: String,
+        status: u32,
+        code: String,
+        message: String,
+    },
+    #[error("No S3 credentials, set them in the s3 section of the configuration or in AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY")]
+    MissingCredentials,
+    #[error("Cannot write the image: {0}")]
+    Write(io::Error),
+}
+
+type Result<T> = std::result::Result<T, FetchError>;
+
+/// Attempts of a download before giving up
+pub const DOWNLOAD_ATTEMPTS: usize = 3;
+/// Wait before the second attempt of a download, doubled for each of the next ones
+const DOWNLOAD_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+impl FetchError {
+    /// Whether the same download may succeed when tried again
+    fn is_transient(&self) -> bool {
+        match self {
+            FetchError::Transfer { .. } => true,
+            FetchError::Status { status } | FetchError::S3 { status, .. } => {
+                *status == 429 || *status >= 500
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<FetchError> for RuntimeError {
+    fn from(error: FetchError) -> Self {
+        match error {
+            FetchError::Transfer { source, .. } => RuntimeError::FetchingError(source),
+            FetchError::Status { .. } => RuntimeError::Error(error.to_string()),
+            FetchError::File { source, .. } | FetchError::Write(source) => {
+                RuntimeError::IoError(source)
+            }
+            FetchError::S3 { .. } | FetchError::MissingCredentials => {
+                RuntimeError::S3Error(error.to_string())
+            }
+            FetchError::UnsupportedScheme(_)
+            | FetchError::InvalidUrl(_)
+            | FetchError::ForbiddenPath(_) => RuntimeError::UnsupportedImage(error.to_string()),
+        }
+    }
+}
+
+/// Content of an image, and where it comes from
+pub struct Fetched {
+    pub content: Box<dyn Read + Send>,
+    pub download: Download,
+}
+
+/// Gets the images of some URL schemes
+pub trait Fetcher: Send + Sync {
+    /// Schemes of the URLs handled, e.g. `https`
+    fn schemes(&self) -> &[&'static str];
+
+    /// Content of an image, with the response it came with
+    fn resolve(&self, url: &Url) -> Result<Fetched>;
+
+    /// Whether the content of a URL is kept for the next instances, rather
+    /// than fetched again
+    fn cacheable(&self) -> bool {
+        true
+    }
+
+    /// Write an image to `destination`, a file of the cache
+    fn fetch(&self, url: &Url, destination: &Path) -> Result<Download> {
+        let mut fetched = self.resolve(url)?;
+        let mut file = File::create(destination).map_err(FetchError::Write)?;
+        io::copy(&mut fetched.content, &mut file).map_err(FetchError::Write)?;
+        Ok(fetched.download)
+    }
+}
+
+/// Write an image to `destination`, trying again after the failures that may
+/// not happen again. Nothing is left at `destination` when every attempt failed.
+pub fn fetch_with_retries(
+    fetcher: &dyn Fetcher,
+    url: &Url,
+    destination: &Path,
+) -> Result<Download> {
+    let mut delay = DOWNLOAD_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        // Injected failures stand for a registry out of service
+        let fetched = if faults::download_fails() {
+            Err(FetchError::Status { status: 503 })
+        } else {
+            fetcher.fetch(url, destination)
+        };
+        match fetched {
+            Err(e) if e.is_transient() && attempt < DOWNLOAD_ATTEMPTS => {
+                warn!(
+                    "Download {}/{} of {} failed, trying again in {:?}: {}",
+                    attempt, DOWNLOAD_ATTEMPTS, url, delay, e
+                );
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => {
+                let _ = fs::remove_file(destination);
+                return Err(e);
+            }
+            Ok(download) => return Ok(download),
+        }
+    }
+}
+
+/// Content of a URL from the cache, downloaded into it when missing.
+///
+/// Only a content matching the expected SHA-256 is used.
+pub fn fetch_cached(
+    fetcher: &dyn Fetcher,
+    cache: &RootfsCache,
+    url: &Url,
+    expected_sha256: Option<&str>,
+) -> std::result::Result<(PathBuf, Provenance), RuntimeError> {
+    if fetcher.cacheable() {
+        if let Some(cached) = cache.get(url.as_str(), expected_sha256) {
+            return Ok(cached);
+        }
+    }
+    download_into_cache(fetcher, cache, url, expected_sha256)
+}
+
+/// Download the content of a URL into the cache, with its provenance
+pub fn download_into_cache(
+    fetcher: &dyn Fetcher,
+    cache: &RootfsCache,
+    url: &Url,
+    expected_sha256: Option<&str>,
+) -> std::result::Result<(PathBuf, Provenance), RuntimeError> {
+    let download = cache.download_path().map_err(RuntimeError::IoError)?;
+    event!(
+        Level::DEBUG,
+        "Fetching image from {} to {}",
+        url,
+        download.display()
+    );
+    let response = fetch_with_retries(fetcher, url, &download).map_err(|e| {
+        event!(Level::ERROR, "Error while fetching image: {}", e);
+        RuntimeError::from(e)
+    })?;
+    cache
+        .insert(url.as_str(), &download, response, expected_sha256)
+        .map_err(RuntimeError::IoError)
+}
+
+/// Fetchers chosen by the scheme of the image URL.
+///
+/// Other sources, such as OCI artifact references, are added by registering
+/// a fetcher for their scheme.
+pub struct Fetchers {
+    fetchers: Vec<Box<dyn Fetcher>>,
+}
+
+impl Fetchers {
+    /// `file://` images are only read from `file_directories`
+    pub fn new(s3: S3Configuration, file_directories: Vec<PathBuf>) -> Self {
+        Fetchers {
+            fetchers: vec![
+                Box::new(HttpFetcher),
+                Box::new(FileFetcher::new(file_directories)),
+                Box::new(S3Fetcher::new(s3)),
+            ],
+        }
+    }
+
+    /// Handle the schemes of a fetcher, in place of the fetcher handling them
+    /// until now
+    #[allow(dead_code)]
+    pub fn register(&mut self, fetcher: Box<dyn Fetcher>) {
+        self.fetchers.insert(0, fetcher);
+    }
+
+    pub fn for_url(&self, url: &Url) -> Result<&dyn Fetcher> {
+        self.fetchers
+            .iter()
+            .find(|fetcher| fetcher.schemes().contains(&url.scheme()))
+            .map(|fetcher| fetcher.as_ref())
+            .ok_or_else(|| FetchError::UnsupportedScheme(url.scheme().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    struct OciFetcher;
+
+    impl Fetcher for OciFetcher {
+        fn schemes(&self) -> &[&'static str] {
+            &["oci"]
+        }
+
+        fn resolve(&self, url: &Url) -> Result<Fetched> {
+            Ok(Fetched {
+                content: Box::new(Cursor::new(b"rootfs".to_vec())),
+                download: Download {
+                    resolved_url: url.to_string(),
+                    ..Default::default()
+                },
+            })
+        }
+    }
+
+    #[test]
+    fn test_fetcher_chosen_by_scheme() {
+        let mut fetchers = Fetchers::new(S3Configuration::default(), vec![]);
+        let url = |url: &str| Url::parse(url).unwrap();
+        assert_eq!(
+            fetchers
+                .for_url(&url("https://a/rootfs.ext4"))
+                .unwrap()
+                .schemes(),
+            ["http", "https"]
+        );
+        assert!(!fetchers
+            .for_url(&url("file:///images/rootfs.ext4"))
+            .unwrap()
+            .cacheable());
+        assert!(matches!(
+            fetchers.for_url(&url("oci://registry/rootfs:1.0")),
+            Err(FetchError::UnsupportedScheme(scheme)) if scheme == "oci"
+        ));
+
+        fetchers.register(Box::new(OciFetcher));
+        let destination =
+            std::env::temp_dir().join(format!("riklet-{}.ext4", uuid::Uuid::new_v4()));
+        let oci = url("oci://registry/rootfs:1.0");
+        fetchers
+            .for_url(&oci)
+            .unwrap()
+            .fetch(&oci, &destination)
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(destination).unwrap(), "rootfs");
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        assert!(FetchError::Status { status: 503 }.is_transient());
+        assert!(FetchError::Status { status: 429 }.is_transient());
+        assert!(!FetchError::Status { status: 404 }.is_transient());
+        assert!(!FetchError::MissingCredentials.is_transient());
+    }
+
+    #[test]
+    fn test_errors_into_runtime_errors() {
+        let status = FetchError::Status { status: 404 };
+        assert!(matches!(
+            RuntimeError::from(status),
+            RuntimeError::Error(message) if message == "Response code from registry: 404"
+        ));
+        let file = FetchError::File {
+            path: PathBuf::from("/images/rootfs.ext4"),
+            source: io::Error::from(io::ErrorKind::NotFound),
+        };
+        assert!(matches!(
+            RuntimeError::from(file),
+            RuntimeError::IoError(error) if error.kind() == io::ErrorKind::NotFound
+        ));
+        let s3 = FetchError::S3 {
+            url: String::from("s3://images/rootfs.ext4"),
+            status: 403,
+            code: String::from("AccessDenied"),
+            message: String::from("Access Denied"),
+        };
+        assert!(matches!(
+            RuntimeError::from(s3),
+            RuntimeError::S3Error(message) if message.contains("AccessDenied")
+        ));
+        assert!(matches!(
+            RuntimeError::from(FetchError::MissingCredentials),
+            RuntimeError::S3Error(_)
+        ));
+        assert!(matches!(
+            RuntimeError::from(FetchError::UnsupportedScheme(String::from("oci"))),
+            RuntimeError::UnsupportedImage(_)
+        ));
+    }
+}
@@ -1,5 +1,5 @@
 use crate::cli::config::Configuration as CliConfiguration;
-use crate::constants::DEFAULT_FIRECRACKER_WORKSPACE;
+use crate::constants::{DEFAULT_FIRECRACKER_WORKSPACE, DEFAULT_VOLUMES_DIRECTORY};
 use crate::net_utils::generate_mac_addr;
 use crate::runtime::Result;
 use crate::{
@@ -8,25 +8,38 @@ use crate::{
     structs::WorkloadDefinition,
 };
 use async_trait::async_trait;
-use curl::easy::Easy;
 use firepilot::builder::drive::DriveBuilder;
 use firepilot::builder::executor::FirecrackerExecutorBuilder;
 use firepilot::builder::kernel::KernelBuilder;
 use firepilot::builder::network_interface::NetworkInterfaceBuilder;
 use firepilot::builder::{Builder, Configuration};
 use firepilot::machine::Machine;
+use proto::common::ImageProvenance;
 use proto::worker::InstanceScheduling;
 use std::{
     fs,
     fs::File,
-    io::Write,
+    net::IpAddr,
     path::{Path, PathBuf},
+    process::Command,
 };
 use tracing::{debug, error, event, trace, Level};
+use url::Url;
 
+use super::fetcher::{download_into_cache, Fetchers};
+use super::rootfs_cache::{Provenance, RootfsCache, LEGACY_CACHE_DIRECTORY};
 use super::{network::function_network::FunctionRuntimeNetwork, Runtime, RuntimeManager};
 
 const BOOT_ARGS_STATIC: &str = "console=ttyS0 reboot=k nomodules random.trust_cpu=on panic=1 pci=off tsc=reliable i8042.nokbd i8042.noaux quiet loglevel=0";
+/// Longest kernel command line accepted by x86 kernels, longer ones are truncated
+/// and the microVM does not boot
+const MAX_KERNEL_ARGS_LENGTH: usize = 2048;
+
+/// Drive id of a volume in the microVM, also naming its backing file on the
+/// host. Firecracker only accepts letters, digits and underscores in ids.
+fn volume_drive_id(volume_id: &str) -> String {
+    format!("volume_{}", volume_id.replace('-', "_"))
+}
 
 struct FunctionRuntime {
     id: String,
@@ -34,6 +47,10 @@ struct FunctionRuntime {
     function_config: FnConfiguration,
     /// Rootfs path on host
     file_path: String,
+    /// Where the rootfs was downloaded from
+    provenance: Provenance,
+    /// Persistent volumes attached to the microVM, as drive id and path on host
+    volumes: Vec<(String, PathBuf)>,
     network: FunctionRuntimeNetwork,
     /// microVM instance, expected to be None when nothing is running, and expected to
     /// to be fullfilled when the microVM is running
@@ -51,6 +68,13 @@ impl FunctionRuntime {
             BOOT_ARGS_STATIC, self.network.guest_ip, self.network.host_ip, self.network.mask_long
         );
         trace!(kernel_args = %kernel_args, "Kernel args");
+        if kernel_args.len() >= MAX_KERNEL_ARGS_LENGTH {
+            return Err(RuntimeError::Error(format!(
+                "Kernel args are {} bytes long, the maximum is {}",
+                kernel_args.len(),
+                MAX_KERNEL_ARGS_LENGTH - 1
+            )));
+        }
         let kernel_location = self
             .function_config
             .kernel_location
@@ -85,12 +109,21 @@ impl FunctionRuntime {
             .try_build()
             .map_err(RuntimeError::FirepilotConfiguration)?;
 
-        let config = Configuration::new(self.id.clone())
+        let mut config = Configuration::new(self.id.clone())
             .with_kernel(kernel)
             .with_drive(drive)
             .with_interface(net_iface)
             .with_executor(executor);
 
+        for (drive_id, path) in &self.volumes {
+            let volume = DriveBuilder::new()
+                .with_drive_id(drive_id.clone())
+                .with_path_on_host(path.clone())
+                .try_build()
+                .map_err(RuntimeError::FirepilotConfiguration)?;
+            config = config.with_drive(volume);
+        }
+
         Ok(config)
     }
 }
@@ -108,9 +141,10 @@ impl Runtime for FunctionRuntime {
             .map_err(RuntimeError::NetworkError)?;
 
         let vm_config = self.generate_microvm_config()?;
-        let mut machine = Machine::new();
 
-        // Copy files and spawn the microVM socket, but it doesn't start the microVM
+        // Copy files and spawn the microVM socket, but it doesn't start the microVM.
+        // The machine is kept from now on, so a failed boot can kill it
+        let machine = self.machine.insert(Machine::new());
         machine
             .create(vm_config)
             .await
@@ -126,16 +160,16 @@ impl Runtime for FunctionRuntime {
         machine
             .start()
             .await
-            .map_err(RuntimeError::FirecrackerError)?;
-        self.machine = Some(machine);
-        Ok(())
+            .map_err(RuntimeError::FirecrackerError)
     }
 
     #[tracing::instrument(skip(self), fields(id = %self.id))]
     async fn down(&mut self) -> Result<()> {
         debug!("Destroying function runtime vm");
-        let machine = match self.machine.as_mut() {
-            Some(machine) => Ok(machine),
+        // The network is destroyed even when the microVM could not be killed,
+        // or was never created as the boot failed before
+        let killed = match self.machine.take() {
+            Some(mut machine) => machine.kill().await.map_err(RuntimeError::FirecrackerError),
             None => {
                 error!("Trying to stop a microVM that is not running");
                 Err(RuntimeError::NotRunning(format!(
@@ -143,88 +177,132 @@ impl Runtime for FunctionRuntime {
                     self.id
                 )))
             }
-        }?;
-
-        machine
-            .kill()
-            .await
-            .map_err(RuntimeError::FirecrackerError)?;
-        debug!("microVM properly stopped");
+        };
+        if killed.is_ok() {
+            debug!("microVM properly stopped");
+        }
 
         debug!("Destroying function runtime network");
-        self.network
+        let destroyed = self
+            .network
             .destroy()
             .await
-            .map_err(RuntimeError::NetworkError)
+            .map_err(RuntimeError::NetworkError);
+        killed.and(destroyed)
+    }
+
+    fn provenance(&self) -> Option<ImageProvenance> {
+        Some(self.provenance.clone().into())
+    }
+
+    fn address(&self) -> Option<IpAddr> {
+        Some(IpAddr::V4(self.network.guest_ip))
     }
 }
 
 pub struct FunctionRuntimeManager {}
 
 impl FunctionRuntimeManager {
-    fn download_image(&self, url: &String, file_path: &String) -> super::Result<()> {
-        event!(
-            Level::DEBUG,
-            "Downloading image from {} to {}",
-            url,
-            file_path
-        );
+    /// Fetch the rootfs image on the system if it is not cached, the root
+    /// filesystem an older riklet downloaded for the workload is adopted first.
+    ///
+    /// Only a content matching the SHA-256 declared by the workload is used.
+    fn create_fs(
+        &self,
+        workload_definition: &WorkloadDefinition,
+        cache: &RootfsCache,
+        fetchers: &Fetchers,
+    ) -> super::Result<(String, Provenance)> {
+        let rootfs_url = workload_definition
+            .get_rootfs_url()
+            .ok_or_else(|| RuntimeError::Error("Rootfs url not found".to_string()))?;
+        let expected_sha256 = workload_definition.get_rootfs_sha256();
+        let expected_sha256 = expected_sha256.as_deref();
+        let url = Url::parse(&rootfs_url)
+            .map_err(|_| RuntimeError::UnsupportedImage(rootfs_url.clone()))?;
+        let fetcher = fetchers.for_url(&url)?;
 
-        let mut easy = Easy::new();
-        let mut buffer = Vec::new();
-        easy.url(url).map_err(RuntimeError::FetchingError)?;
-        easy.follow_location(true)
-            .map_err(RuntimeError::FetchingError)?;
-
-        {
-            let mut transfer = easy.transfer();
-            transfer
-                .write_function(|data| {
-                    buffer.extend_from_slice(data);
-                    Ok(data.len())
-                })
-                .map_err(RuntimeError::FetchingError)?;
-            transfer.perform().map_err(RuntimeError::FetchingError)?;
+        if fetcher.cacheable() {
+            if let Some((path, provenance)) = cache.get(&rootfs_url, expected_sha256) {
+                return Ok((path.display().to_string(), provenance));
+            }
+            if let Some((path, provenance)) = cache
+                .adopt_legacy(
+                    Path::new(LEGACY_CACHE_DIRECTORY),
+                    &workload_definition.name,
+                    &rootfs_url,
+                    expected_sha256,
+                )
+                .map_err(RuntimeError::IoError)?
+            {
+                event!(
+                    Level::INFO,
+                    "Adopted the legacy root filesystem of workload {}",
+                    workload_definition.name
+                );
+                return Ok((path.display().to_string(), provenance));
+            }
         }
 
-        let response_code = easy.response_code().map_err(RuntimeError::FetchingError)?;
-        if response_code != 200 {
-            return Err(RuntimeError::Error(format!(
-                "Response code from registry: {}",
-                response_code
-            )));
-        }
+        let (path, provenance) = download_into_cache(fetcher, cache, &url, expected_sha256)?;
+        Ok((path.display().to_string(), provenance))
+    }
 
-        {
-            event!(Level::DEBUG, "Writing data to {}", file_path);
-            let mut file = File::create(file_path).map_err(RuntimeError::IoError)?;
-            file.write_all(buffer.as_slice())
-                .map_err(RuntimeError::IoError)?;
+    /// Create the backing files of the persistent volumes that do not exist yet.
+    ///
+    /// Files are never removed with the instance, so a volume keeps its data
+    /// for the next instance it is attached to.
+    fn create_volumes(
+        &self,
+        workload_definition: &WorkloadDefinition,
+    ) -> super::Result<Vec<(String, PathBuf)>> {
+        let mut volumes = Vec::new();
+        for volume in workload_definition.get_volumes() {
+            if volume.id.is_empty() {
+                return Err(RuntimeError::Error(format!(
+                    "Volume {} was not resolved by the controller",
+                    volume.name
+                )));
+            }
+            let drive_id = volume_drive_id(&volume.id);
+            let path = Path::new(DEFAULT_VOLUMES_DIRECTORY).join(format!("{}.ext4", drive_id));
+            if !path.exists() {
+                self.format_volume(&path, volume.size_mb).map_err(|e| {
+                    event!(
+                        Level::ERROR,
+                        "Error while creating volume {}: {}",
+                        volume.name,
+                        e
+                    );
+                    let _ = fs::remove_file(&path);
+                    e
+                })?;
+            }
+            volumes.push((drive_id, path));
         }
-
-        Ok(())
+        Ok(volumes)
     }
 
-    /// Download the rootfs image on the system if it does not exist
-    fn create_fs(&self, workload_definition: &WorkloadDefinition) -> super::Result<String> {
-        let rootfs_url = workload_definition
-            .get_rootfs_url()
-            .ok_or_else(|| RuntimeError::Error("Rootfs url not found".to_string()))?;
-
-        let download_directory = format!("/tmp/{}", &workload_definition.name);
-        let file_path = format!("{}/rootfs.ext4", &download_directory);
-        let file_pathbuf = Path::new(&file_path);
-
-        if !file_pathbuf.exists() {
-            fs::create_dir(&download_directory).map_err(RuntimeError::IoError)?;
+    fn format_volume(&self, path: &Path, size_mb: u64) -> super::Result<()> {
+        event!(Level::DEBUG, "Creating volume {}", path.display());
+        fs::create_dir_all(DEFAULT_VOLUMES_DIRECTORY).map_err(RuntimeError::IoError)?;
+        File::create(path)
+            .and_then(|file| file.set_len(size_mb * 1024 * 1024))
+            .map_err(RuntimeError::IoError)?;
 
-            self.download_image(&rootfs_url, &file_path).map_err(|e| {
-                event!(Level::ERROR, "Error while downloading image: {}", e);
-                fs::remove_dir_all(&download_directory).expect("Error while removing directory");
-                e
-            })?;
+        let output = Command::new("mkfs.ext4")
+            .arg("-q")
+            .arg("-F")
+            .arg(path)
+            .output()
+            .map_err(RuntimeError::IoError)?;
+        if !output.status.success() {
+            return Err(RuntimeError::Error(format!(
+                "mkfs.ext4 failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
         }
-        Ok(file_path)
+        Ok(())
     }
 }
 
@@ -232,19 +310,41 @@ impl RuntimeManager for FunctionRuntimeManager {
     fn create_runtime(
         &self,
         workload: InstanceScheduling,
-        _config: CliConfiguration,
+        config: CliConfiguration,
     ) -> super::Result<Box<dyn Runtime>> {
         event!(Level::DEBUG, "Function workload detected");
         let workload_definition: WorkloadDefinition =
             serde_json::from_str(workload.definition.as_str())
                 .map_err(RuntimeError::ParsingError)?;
 
+        let (file_path, provenance) = self.create_fs(
+            &workload_definition,
+            &RootfsCache::new(&config.rootfs_cache_directory),
+            &Fetchers::new(config.s3.clone(), config.file_image_directories.clone()),
+        )?;
+
         Ok(Box::new(FunctionRuntime {
             function_config: FnConfiguration::load(),
-            file_path: self.create_fs(&workload_definition)?,
-            network: FunctionRuntimeNetwork::new(&workload).map_err(RuntimeError::NetworkError)?,
+            file_path,
+            provenance,
+            volumes: self.create_volumes(&workload_definition)?,
+            network: FunctionRuntimeNetwork::new(&workload, &config.egress_deny)
+                .map_err(RuntimeError::NetworkError)?,
             machine: None,
             id: workload.instance_id,
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_drive_id() {
+        assert_eq!(
+            volume_drive_id("0b6e3c1a-5d2f-4b8e-9a47-2f1c3e5d7a90"),
+            "volume_0b6e3c1a_5d2f_4b8e_9a47_2f1c3e5d7a90"
+        );
+    }
+}
@@ -1,18 +1,29 @@
 pub mod network;
 
+pub mod fetcher;
 pub mod function_runtime;
 pub mod pod_runtime;
+pub mod probe;
+pub mod rootfs_cache;
 
 use self::{
     function_runtime::FunctionRuntimeManager, network::NetworkError, pod_runtime::PodRuntimeManager,
 };
+use crate::faults;
 use crate::{cli::config::Configuration, structs::WorkloadDefinition};
 use async_trait::async_trait;
 use firepilot::{builder::BuilderError, machine::FirepilotError};
+use proto::common::ImageProvenance;
 use proto::worker::InstanceScheduling;
 use std::fmt::Debug;
+use std::net::IpAddr;
+use std::time::Duration;
 use thiserror::Error;
-use tracing::error;
+use tokio::time::timeout;
+use tracing::{error, warn};
+
+/// Longest time an instance may take to boot, it is torn down after that
+const BOOT_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[derive(Debug, Error)]
 pub enum RuntimeError {
@@ -45,6 +56,15 @@ pub enum RuntimeError {
 
     #[error("Runtime expected to be running: {0}")]
     NotRunning(String),
+
+    #[error("S3 error: {0}")]
+    S3Error(String),
+
+    #[error("Unsupported image: {0}")]
+    UnsupportedImage(String),
+
+    #[error("Instance did not boot within {0:?}")]
+    BootTimeout(Duration),
 }
 
 type Result<T> = std::result::Result<T, RuntimeError>;
@@ -53,6 +73,16 @@ type Result<T> = std::result::Result<T, RuntimeError>;
 pub trait Runtime: Send + Sync {
     async fn up(&mut self) -> Result<()>;
     async fn down(&mut self) -> Result<()>;
+
+    /// Image the instance booted from, if it has one
+    fn provenance(&self) -> Option<ImageProvenance> {
+        None
+    }
+
+    /// Address the instance can be reached at from the node, to probe it
+    fn address(&self) -> Option<IpAddr> {
+        None
+    }
 }
 
 #[async_trait]
@@ -70,12 +100,58 @@ pub trait RuntimeManager: Send + Sync {
         config: Configuration,
     ) -> Result<Box<dyn Runtime>> {
         let mut runtime = self.create_runtime(workload.clone(), config.clone())?;
-        runtime.up().await?;
+        boot(runtime.as_mut(), BOOT_TIMEOUT).await?;
 
         Ok(runtime)
     }
 }
 
+/// Bring a runtime up, tearing down what was set up when it fails or does not
+/// boot within `limit`
+pub async fn boot(runtime: &mut dyn Runtime, limit: Duration) -> Result<()> {
+    let booted = timeout(limit, async {
+        faults::delay_boot().await;
+        runtime.up().await
+    })
+    .await
+    .unwrap_or(Err(RuntimeError::BootTimeout(limit)));
+
+    if let Err(e) = &booted {
+        error!("Instance did not boot, tearing it down: {}", e);
+        runtime
+            .down()
+            .await
+            .unwrap_or_else(|e| error!("Error while tearing down the instance: {}", e));
+    }
+    booted
+}
+
+/// Bring a runtime down then up again, keeping what it was given when
+/// created, such as the id and the network of its instance. It is booted even
+/// when it could not be brought down, the instance may have stopped already.
+pub async fn restart(runtime: &mut dyn Runtime) -> Result<()> {
+    if let Err(e) = runtime.down().await {
+        warn!(
+            "Could not bring the instance down, booting it anyway: {}",
+            e
+        );
+    }
+    boot(runtime, BOOT_TIMEOUT).await
+}
+
+/// Bring a runtime down. The teardown is done as far as possible even when
+/// it fails, the runtime must not be used anymore.
+pub async fn tear_down(runtime: &mut dyn Runtime) -> Result<()> {
+    runtime.down().await?;
+    // Injected failures stand for a network which could not be removed
+    if faults::destroy_fails() {
+        return Err(RuntimeError::NetworkError(NetworkError::Error(
+            String::from("Injected teardown failure"),
+        )));
+    }
+    Ok(())
+}
+
 enum WorkloadKind {
     Function,
     Pod,
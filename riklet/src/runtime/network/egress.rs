@@ -0,0 +1,122 @@
+use definition::workload::{EgressRule, PortProtocol};
+
+use crate::iptables::{rule::Rule, Chain, Table};
+
+/// Rules dropping the traffic sent by the guest behind a tap interface.
+///
+/// Traffic to the host itself goes through INPUT while traffic routed through
+/// the host goes through FORWARD, so every rule is installed on both. Rules
+/// given twice, e.g. by the node and the workload, are only kept once.
+pub fn egress_rules(tap: &str, deny: &[EgressRule]) -> Vec<Rule> {
+    let mut rules: Vec<Rule> = Vec::new();
+    for egress in deny {
+        for chain in [Chain::Input, Chain::Forward] {
+            for matcher in destination_matchers(egress) {
+                let rule = Rule {
+                    rule: format!("-i {} {} -j DROP", tap, matcher),
+                    chain: chain.clone(),
+                    table: Table::Filter,
+                };
+                if !rules.contains(&rule) {
+                    rules.push(rule);
+                }
+            }
+        }
+    }
+    rules
+}
+
+/// Iptables matchers of the destination of a rule, ports need a protocol so a
+/// port without one is matched on both TCP and UDP
+fn destination_matchers(egress: &EgressRule) -> Vec<String> {
+    let port = match egress.port {
+        Some(port) => port,
+        None => {
+            return vec![match egress.protocol {
+                Some(protocol) => format!("-d {} -p {}", egress.cidr, protocol.iptables_name()),
+                None => format!("-d {}", egress.cidr),
+            }]
+        }
+    };
+    let protocols = match egress.protocol {
+        Some(protocol) => vec![protocol],
+        None => vec![PortProtocol::TCP, PortProtocol::UDP],
+    };
+    protocols
+        .into_iter()
+        .map(|protocol| {
+            format!(
+                "-d {} -p {} --dport {}",
+                egress.cidr,
+                protocol.iptables_name(),
+                port
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deny(cidr: &str, port: Option<u16>, protocol: Option<PortProtocol>) -> EgressRule {
+        EgressRule {
+            cidr: cidr.to_string(),
+            port,
+            protocol,
+        }
+    }
+
+    #[test]
+    fn test_rules_on_input_and_forward() {
+        let rules = egress_rules("rikfn0", &[deny("169.254.169.254/32", None, None)]);
+        assert_eq!(
+            rules,
+            vec![
+                Rule::new(
+                    Chain::Input,
+                    Table::Filter,
+                    "-i rikfn0 -d 169.254.169.254/32 -j DROP".to_string()
+                ),
+                Rule::new(
+                    Chain::Forward,
+                    Table::Filter,
+                    "-i rikfn0 -d 169.254.169.254/32 -j DROP".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_port_without_protocol_matches_tcp_and_udp() {
+        let rules = egress_rules("rikfn0", &[deny("10.0.0.1/32", Some(5000), None)]);
+        let input: Vec<&str> = rules
+            .iter()
+            .filter(|rule| rule.chain == Chain::Input)
+            .map(|rule| rule.rule.as_str())
+            .collect();
+        assert_eq!(
+            input,
+            vec![
+                "-i rikfn0 -d 10.0.0.1/32 -p tcp --dport 5000 -j DROP",
+                "-i rikfn0 -d 10.0.0.1/32 -p udp --dport 5000 -j DROP",
+            ]
+        );
+
+        let rules = egress_rules(
+            "rikfn0",
+            &[deny("10.0.0.1/32", Some(53), Some(PortProtocol::UDP))],
+        );
+        assert_eq!(rules.len(), 2);
+        assert!(rules
+            .iter()
+            .all(|rule| rule.rule == "-i rikfn0 -d 10.0.0.1/32 -p udp --dport 53 -j DROP"));
+    }
+
+    #[test]
+    fn test_duplicated_rules_are_kept_once() {
+        let node = deny("10.0.0.0/8", None, None);
+        let rules = egress_rules("rikfn0", &[node.clone(), node]);
+        assert_eq!(rules.len(), 2);
+    }
+}
@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use definition::workload::{EgressRule, PortProtocol};
 use ipnetwork::Ipv4Network;
 use proto::worker::InstanceScheduling;
 use std::net::Ipv4Addr;
@@ -11,7 +12,8 @@ use crate::{
     structs::WorkloadDefinition,
 };
 
-use super::{NetworkError, Result, RuntimeNetwork, IP_ALLOCATOR};
+use super::egress::egress_rules;
+use super::{NetworkError, Result, RuntimeNetwork, HOST_PORTS, IP_ALLOCATOR};
 
 pub struct FunctionRuntimeNetwork {
     /// Unique identifier for the function deployment
@@ -23,11 +25,16 @@ pub struct FunctionRuntimeNetwork {
     pub guest_ip: Ipv4Addr,
     /// Host tap interface IP
     pub host_ip: Ipv4Addr,
-    /// A mapping of exposed port to internal port
-    pub port_mapping: Vec<(u16, u16)>,
+    /// A mapping of exposed port to internal port, for a protocol
+    pub port_mapping: Vec<(u16, u16, PortProtocol)>,
     /// A unique name for the tap interface
     pub tap: Option<String>,
+    /// Traffic dropped from the guest, rules of the node then of the workload
+    pub egress_deny: Vec<EgressRule>,
     pub iptables: Iptables,
+    /// Whether the subnet and host ports were given back, they may be reused by
+    /// another instance afterwards so they must not be released twice
+    pub released: bool,
 }
 
 impl FunctionRuntimeNetwork {
@@ -40,19 +47,38 @@ impl FunctionRuntimeNetwork {
     /// The IPv4 range given to the machine will be taken from the global
     /// [IP_ALLOCATOR] which is a singleton that keeps track of the available
     /// IPv4 networks
-    pub fn new(workload: &InstanceScheduling) -> Result<Self> {
+    pub fn new(workload: &InstanceScheduling, node_egress_deny: &[EgressRule]) -> Result<Self> {
         let mask_long: &str = "255.255.255.252";
 
         let workload_definition: WorkloadDefinition =
             serde_json::from_str(workload.definition.as_str())
                 .map_err(NetworkError::ParsingError)?;
+        let port_mapping = workload_definition.get_port_mapping();
+        // Workloads can only add rules to the ones of the node
+        let mut egress_deny = node_egress_deny.to_vec();
+        egress_deny.extend(workload_definition.get_egress_rules());
+
+        // A host port can only be forwarded to one workload per protocol
+        HOST_PORTS
+            .lock()
+            .unwrap()
+            .reserve(&host_ports(&port_mapping))
+            .map_err(|(port, protocol)| {
+                NetworkError::Error(format!("Host port {}/{} is already used", port, protocol))
+            })?;
 
         // Alocate ip range for tap interface and firecracker micro VM
         let subnet = IP_ALLOCATOR
             .lock()
             .unwrap()
             .allocate_subnet()
-            .ok_or_else(|| NetworkError::Error("No more internal ip available".to_string()))?;
+            .ok_or_else(|| {
+                HOST_PORTS
+                    .lock()
+                    .unwrap()
+                    .release(&host_ports(&port_mapping));
+                NetworkError::Error("No more internal ip available".to_string())
+            })?;
 
         let guest_ip = subnet
             .nth(1)
@@ -67,9 +93,11 @@ impl FunctionRuntimeNetwork {
             host_ip,
             guest_ip,
             identifier: workload.instance_id.clone(),
-            port_mapping: workload_definition.get_port_mapping(),
+            port_mapping,
             tap: None,
+            egress_deny,
             iptables: Iptables::new(false).map_err(NetworkError::IptablesError)?,
+            released: false,
         })
     }
 
@@ -80,13 +108,20 @@ impl FunctionRuntimeNetwork {
             .ok_or_else(|| NetworkError::Error("Tap interface name not found".to_string()))
     }
 
+    fn generate_egress_rules(&self) -> Result<Vec<Rule>> {
+        Ok(egress_rules(&self.tap_name()?, &self.egress_deny))
+    }
+
     fn generate_iptables_rules(&self) -> Vec<Rule> {
         let mut rules = Vec::new();
-        for (exposed_port, internal_port) in self.port_mapping.iter() {
+        for (exposed_port, internal_port, protocol) in self.port_mapping.iter() {
             let rule = Rule {
                 rule: format!(
-                    "-p tcp --dport {} -j DNAT --to-destination {}:{}",
-                    exposed_port, self.guest_ip, internal_port
+                    "-p {} --dport {} -j DNAT --to-destination {}:{}",
+                    protocol.iptables_name(),
+                    exposed_port,
+                    self.guest_ip,
+                    internal_port
                 ),
                 chain: get_iptables_riklet_chain(),
                 table: Table::Nat,
@@ -109,22 +144,53 @@ impl FunctionRuntimeNetwork {
         Ok(())
     }
 
-    /// Remove previously created iptable rules on the host
+    /// Insert iptables rules dropping the traffic denied to the guest
+    #[tracing::instrument(skip(self), fields(instance_id = %self.identifier))]
+    fn up_egress(&mut self) -> Result<()> {
+        debug!("Create egress iptables rules");
+        for rule in self.generate_egress_rules()? {
+            self.iptables
+                .create_first(&rule)
+                .map_err(NetworkError::IptablesError)?;
+        }
+        Ok(())
+    }
+
+    /// Remove previously created iptable rules on the host.
+    ///
+    /// Rules already removed are skipped, so the network can be torn down again
+    /// after a partial failure.
     #[tracing::instrument(skip(self), fields(instance_id = %self.identifier))]
     fn down_routing(&mut self) -> Result<()> {
         debug!("Delete iptables rules");
-        let rules = self.generate_iptables_rules();
+        let mut rules = self.generate_iptables_rules();
+        if self.tap.is_some() {
+            rules.extend(self.generate_egress_rules()?);
+        }
         for rule in rules {
-            self.iptables
-                .delete(&rule)
-                .map_err(NetworkError::IptablesError)?;
+            if self
+                .iptables
+                .exists(&rule)
+                .map_err(NetworkError::IptablesError)?
+            {
+                self.iptables
+                    .delete(&rule)
+                    .map_err(NetworkError::IptablesError)?;
+            }
         }
         Ok(())
     }
 
-    /// Release allocated IPs
-    fn release_network(&self) -> Result<()> {
+    /// Release allocated IPs and host ports
+    fn release_network(&mut self) -> Result<()> {
+        if self.released {
+            return Ok(());
+        }
         debug!("Release subnet IPs");
+        match HOST_PORTS.lock() {
+            Ok(mut registry) => registry.release(&host_ports(&self.port_mapping)),
+            Err(e) => error!("Couldn't free host ports, reason: {}", e),
+        }
 
         let subnet = Ipv4Network::new(self.host_ip, 30)
             .map_err(|e| NetworkError::Error(format!("Fail to get function subnet {}", e)))?;
@@ -134,10 +200,19 @@ impl FunctionRuntimeNetwork {
             Err(e) => error!("Couldn't free subnet {}, reason: {}", subnet, e),
         }
 
+        self.released = true;
         Ok(())
     }
 }
 
+/// Host ports of a port mapping along with their protocol
+fn host_ports(port_mapping: &[(u16, u16, PortProtocol)]) -> Vec<(u16, PortProtocol)> {
+    port_mapping
+        .iter()
+        .map(|(host_port, _, protocol)| (*host_port, *protocol))
+        .collect()
+}
+
 #[async_trait]
 impl RuntimeNetwork for FunctionRuntimeNetwork {
     #[tracing::instrument(skip(self), fields(identifier = %self.identifier))]
@@ -168,6 +243,7 @@ impl RuntimeNetwork for FunctionRuntimeNetwork {
             .await
             .map_err(|e| NetworkError::InterfaceIPError(e.to_string()))?;
 
+        self.up_egress()?;
         self.up_routing()?;
         Ok(())
     }
@@ -185,6 +261,7 @@ impl RuntimeNetwork for FunctionRuntimeNetwork {
 mod tests {
     use std::{net::Ipv4Addr, process::Command};
 
+    use definition::workload::{EgressRule, PortProtocol};
     use serial_test::serial;
     use tracing::trace;
 
@@ -234,7 +311,7 @@ mod tests {
 
     fn create_function_network_rt(
         tap_name: &str,
-        port_mapping: &Vec<(u16, u16)>,
+        port_mapping: &Vec<(u16, u16, PortProtocol)>,
     ) -> FunctionRuntimeNetwork {
         FunctionRuntimeNetwork {
             identifier: "test".to_string(),
@@ -243,7 +320,9 @@ mod tests {
             guest_ip: Ipv4Addr::new(10, 0, 0, 1),
             port_mapping: port_mapping.clone(),
             tap: Some(tap_name.to_string()),
+            egress_deny: vec![],
             iptables: Iptables::new(true).unwrap(),
+            released: false,
         }
     }
 
@@ -264,7 +343,10 @@ mod tests {
         let result = network.init().await;
         assert!(result.is_ok());
 
-        let exposed_port = vec![(8080, 8080)];
+        let exposed_port = vec![
+            (8080, 8080, PortProtocol::TCP),
+            (5353, 53, PortProtocol::UDP),
+        ];
         let mut fn_rt = create_function_network_rt("riklet010", &exposed_port);
         open_tap_shell(fn_rt.tap_name().unwrap().as_str()).unwrap();
         fn_rt.up_routing().unwrap();
@@ -273,11 +355,14 @@ mod tests {
         let mut rules: Vec<Rule> = vec![];
 
         // Register expected rules
-        for (exposed_port, internal_port) in fn_rt.port_mapping.iter() {
+        for (exposed_port, internal_port, protocol) in fn_rt.port_mapping.iter() {
             let rule = Rule {
                 rule: format!(
-                    "-p tcp --dport {} -j DNAT --to-destination {}:{}",
-                    exposed_port, fn_rt.guest_ip, internal_port
+                    "-p {} --dport {} -j DNAT --to-destination {}:{}",
+                    protocol.iptables_name(),
+                    exposed_port,
+                    fn_rt.guest_ip,
+                    internal_port
                 ),
                 chain: get_iptables_riklet_chain(),
                 table: Table::Nat,
@@ -299,4 +384,39 @@ mod tests {
         }
         close_tap_shell(fn_rt.tap_name().unwrap().as_str()).unwrap();
     }
+
+    #[tokio::test]
+    #[serial]
+    async fn apply_egress_rules_and_teardown_twice() {
+        let mut fn_rt = create_function_network_rt("riklet011", &vec![]);
+        fn_rt.egress_deny = vec![
+            EgressRule {
+                cidr: "169.254.169.254/32".to_string(),
+                port: None,
+                protocol: None,
+            },
+            EgressRule {
+                cidr: "10.0.0.2/32".to_string(),
+                port: Some(5000),
+                protocol: Some(PortProtocol::TCP),
+            },
+        ];
+        open_tap_shell(fn_rt.tap_name().unwrap().as_str()).unwrap();
+        fn_rt.up_egress().unwrap();
+
+        let ipt = Iptables::new(false).unwrap();
+        let rules = fn_rt.generate_egress_rules().unwrap();
+        assert_eq!(rules.len(), 4);
+        for rule in &rules {
+            assert!(ipt.exists(rule).unwrap());
+        }
+
+        fn_rt.down_routing().unwrap();
+        // A second teardown must not fail nor leave anything behind
+        fn_rt.down_routing().unwrap();
+        for rule in &rules {
+            assert!(!ipt.exists(rule).unwrap());
+        }
+        close_tap_shell(fn_rt.tap_name().unwrap().as_str()).unwrap();
+    }
 }
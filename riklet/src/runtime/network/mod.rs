@@ -1,5 +1,7 @@
+pub mod egress;
 pub mod function_network;
 pub mod pod_network;
+pub mod port_registry;
 
 use async_trait::async_trait;
 use once_cell::sync::Lazy;
@@ -10,6 +12,7 @@ use thiserror::Error;
 
 use crate::iptables::rule::Rule;
 use crate::iptables::{Chain, Iptables, IptablesError, MutateIptables, Table};
+use port_registry::PortRegistry;
 
 // Initialize Singleton for IpAllocator
 static IP_ALLOCATOR: Lazy<Mutex<IpAllocator>> = Lazy::new(|| {
@@ -17,6 +20,9 @@ static IP_ALLOCATOR: Lazy<Mutex<IpAllocator>> = Lazy::new(|| {
     Mutex::new(ip_allocator)
 });
 
+// Host ports forwarded to the workloads of the node
+static HOST_PORTS: Lazy<Mutex<PortRegistry>> = Lazy::new(|| Mutex::new(PortRegistry::default()));
+
 #[derive(Debug, Error)]
 pub enum NetworkError {
     #[error("Network error: {0}")]
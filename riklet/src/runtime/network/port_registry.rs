@@ -0,0 +1,54 @@
+use definition::workload::PortProtocol;
+use std::collections::HashSet;
+
+/// Host ports forwarded to workloads.
+///
+/// A port is forwarded to a single workload per protocol, so TCP 53 and UDP 53
+/// can be used by two different workloads.
+#[derive(Debug, Default)]
+pub struct PortRegistry {
+    ports: HashSet<(u16, PortProtocol)>,
+}
+
+impl PortRegistry {
+    /// Reserve all the given ports, or none of them if one is already used
+    pub fn reserve(&mut self, ports: &[(u16, PortProtocol)]) -> Result<(), (u16, PortProtocol)> {
+        if let Some(used) = ports.iter().find(|port| self.ports.contains(port)) {
+            return Err(*used);
+        }
+        self.ports.extend(ports.iter().copied());
+        Ok(())
+    }
+
+    pub fn release(&mut self, ports: &[(u16, PortProtocol)]) {
+        for port in ports {
+            self.ports.remove(port);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_port_with_different_protocols() {
+        let mut registry = PortRegistry::default();
+        assert!(registry.reserve(&[(53, PortProtocol::TCP)]).is_ok());
+        assert!(registry.reserve(&[(53, PortProtocol::UDP)]).is_ok());
+        assert_eq!(
+            registry.reserve(&[(8080, PortProtocol::TCP), (53, PortProtocol::UDP)]),
+            Err((53, PortProtocol::UDP))
+        );
+        // Nothing is reserved when a port is already used
+        assert!(registry.reserve(&[(8080, PortProtocol::TCP)]).is_ok());
+    }
+
+    #[test]
+    fn test_released_port_can_be_reserved() {
+        let mut registry = PortRegistry::default();
+        registry.reserve(&[(53, PortProtocol::UDP)]).unwrap();
+        registry.release(&[(53, PortProtocol::UDP)]);
+        assert!(registry.reserve(&[(53, PortProtocol::UDP)]).is_ok());
+    }
+}
@@ -9,13 +9,42 @@ use cri::{
     container::{CreateArgs, Runc},
 };
 
+use crate::instance_logs::{capture, instance_logs};
+use nix::sys::socket::{recvmsg, ControlMessageOwned, MsgFlags};
 use oci::image_manager::ImageManager;
+use proto::common::ImageProvenance;
 use proto::worker::InstanceScheduling;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, IoSliceMut};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::path::PathBuf;
 use tracing::{error, event, Level};
 
+use super::fetcher::{fetch_cached, Fetchers};
+use super::rootfs_cache::{Provenance, RootfsCache};
 use super::{network::pod_network::PodRuntimeNetwork, Runtime, RuntimeManager};
 
+/// Terminal of a container, runc sends its master side on the console socket
+fn receive_terminal(stream: &std::os::unix::net::UnixStream) -> io::Result<Option<File>> {
+    // The name of the terminal comes along, it is not used
+    let mut name = [0u8; 4096];
+    let mut iov = [IoSliceMut::new(&mut name)];
+    let mut space = nix::cmsg_space!([RawFd; 1]);
+    let message = recvmsg::<()>(
+        stream.as_raw_fd(),
+        &mut iov,
+        Some(&mut space),
+        MsgFlags::empty(),
+    )?;
+    let terminal = message.cmsgs().find_map(|cmsg| match cmsg {
+        ControlMessageOwned::ScmRights(fds) => fds.first().copied(),
+        _ => None,
+    });
+    // The descriptor was just received, nothing else owns it
+    Ok(terminal.map(|fd| unsafe { File::from_raw_fd(fd) }))
+}
+
 #[derive(Debug)]
 struct PodRuntime {
     image_manager: ImageManager,
@@ -23,6 +52,8 @@ struct PodRuntime {
     network: PodRuntimeNetwork,
     container_runtime: Runc,
     instance_id: String,
+    /// Image archives downloaded for the containers, by container name
+    archives: HashMap<String, (PathBuf, Provenance)>,
 }
 
 #[async_trait]
@@ -39,23 +70,45 @@ impl Runtime for PodRuntime {
 
         for container in containers {
             if let Some(id) = container.id {
-                let image = &self
-                    .image_manager
-                    .pull(&container.image[..])
-                    .await
-                    .map_err(RuntimeError::OciError)?;
+                let image = match self.archives.get(&container.name) {
+                    Some((archive, provenance)) => {
+                        self.image_manager.import(archive, &provenance.sha256).await
+                    }
+                    None => self.image_manager.pull(&container.image[..]).await,
+                }
+                .map_err(RuntimeError::OciError)?;
 
                 // New console socket for the container
                 let socket_path = PathBuf::from(format!("/tmp/{}", &id));
                 let console_socket =
                     ConsoleSocket::new(&socket_path).map_err(RuntimeError::CriError)?;
 
+                // The console of the container is kept as the logs of the instance
+                let instance_id = self.instance_id.clone();
                 tokio::spawn(async move {
                     if let Some(unix_listener) = console_socket.get_listener().as_ref() {
-                        match unix_listener.accept().await {
-                            Ok((stream, _socket_addr)) => {
-                                Box::leak(Box::new(stream));
+                        let terminal = match unix_listener.accept().await {
+                            Ok((stream, _socket_addr)) => match stream.into_std() {
+                                Ok(stream) => tokio::task::spawn_blocking(move || {
+                                    stream.set_nonblocking(false)?;
+                                    receive_terminal(&stream)
+                                })
+                                .await
+                                .unwrap_or_else(|e| Err(io::Error::new(io::ErrorKind::Other, e))),
+                                Err(e) => Err(e),
+                            },
+                            Err(e) => Err(e),
+                        };
+                        match terminal {
+                            Ok(Some(terminal)) => {
+                                capture(
+                                    instance_logs(),
+                                    &instance_id,
+                                    tokio::fs::File::from_std(terminal),
+                                )
+                                .await
                             }
+                            Ok(None) => event!(Level::WARN, "No PTY master received"),
                             Err(err) => {
                                 event!(Level::ERROR, "Receive PTY master error : {:?}", err)
                             }
@@ -90,10 +143,46 @@ impl Runtime for PodRuntime {
         error!("Down not implemented for pod runtime");
         Ok(())
     }
+
+    /// Instances report a single image, the one of their first container
+    /// imported from an archive
+    fn provenance(&self) -> Option<ImageProvenance> {
+        self.workload_definition
+            .spec
+            .containers
+            .iter()
+            .find_map(|container| self.archives.get(&container.name))
+            .map(|(_, provenance)| provenance.clone().into())
+    }
 }
 
 pub struct PodRuntimeManager {}
 
+impl PodRuntimeManager {
+    /// Download the image archives of the containers which are not in the
+    /// cache, only a content matching the declared SHA-256 is used
+    fn fetch_archives(
+        &self,
+        workload_definition: &WorkloadDefinition,
+        cache: &RootfsCache,
+        fetchers: &Fetchers,
+    ) -> super::Result<HashMap<String, (PathBuf, Provenance)>> {
+        let mut archives = HashMap::new();
+        for container in &workload_definition.spec.containers {
+            if let Some(url) = &container.image_archive_url {
+                let archive = fetch_cached(
+                    fetchers.for_url(url)?,
+                    cache,
+                    url,
+                    container.image_archive_sha256.as_deref(),
+                )?;
+                archives.insert(container.name.clone(), archive);
+            }
+        }
+        Ok(archives)
+    }
+}
+
 impl RuntimeManager for PodRuntimeManager {
     fn create_runtime(
         &self,
@@ -104,6 +193,11 @@ impl RuntimeManager for PodRuntimeManager {
             serde_json::from_str(workload.definition.as_str())
                 .map_err(RuntimeError::ParsingError)?;
         let instance_id: String = workload.instance_id;
+        let archives = self.fetch_archives(
+            &workload_definition,
+            &RootfsCache::new(&config.rootfs_cache_directory),
+            &Fetchers::new(config.s3.clone(), config.file_image_directories.clone()),
+        )?;
 
         Ok(Box::new(PodRuntime {
             image_manager: ImageManager::new(config.manager.clone())
@@ -112,6 +206,7 @@ impl RuntimeManager for PodRuntimeManager {
             network: PodRuntimeNetwork::new(),
             container_runtime: Runc::new(config.runner).map_err(RuntimeError::CriError)?,
             instance_id,
+            archives,
         }))
     }
 }
@@ -0,0 +1,92 @@
+use definition::workload::Probe;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+use tracing::debug;
+
+/// Reason of the condition given to instances whose startup probe never succeeded
+pub const STARTUP_TIMEOUT: &str = "StartupTimeout";
+
+/// Wait for `address` to accept a TCP connection.
+///
+/// Fails with the last error once the probe failed `failure_threshold` times
+/// in a row, so a slow instance gets up to `initial_delay_seconds` plus
+/// `failure_threshold * period_seconds` to start.
+pub async fn wait_for_startup(probe: &Probe, address: SocketAddr) -> Result<(), String> {
+    sleep(Duration::from_secs(probe.initial_delay_seconds.into())).await;
+    let period = Duration::from_secs(probe.period_seconds.into());
+    let connect_timeout = Duration::from_secs(probe.timeout_seconds.into());
+
+    let mut failure = String::new();
+    for attempt in 1..=probe.failure_threshold {
+        match timeout(connect_timeout, TcpStream::connect(address)).await {
+            Ok(Ok(_)) => return Ok(()),
+            Ok(Err(e)) => failure = e.to_string(),
+            Err(_) => failure = format!("no connection within {:?}", connect_timeout),
+        }
+        debug!(
+            "Startup probe {}/{} on {} failed: {}",
+            attempt, probe.failure_threshold, address, failure
+        );
+        if attempt < probe.failure_threshold {
+            sleep(period).await;
+        }
+    }
+    Err(format!(
+        "{} failed {} startup probes in a row: {}",
+        address, probe.failure_threshold, failure
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    fn probe(failure_threshold: u32) -> Probe {
+        Probe {
+            port: None,
+            initial_delay_seconds: 0,
+            period_seconds: 1,
+            timeout_seconds: 1,
+            failure_threshold,
+        }
+    }
+
+    /// Address of a port nothing listens on yet
+    fn free_address() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_slow_start() {
+        let address = free_address();
+        // Stub instance listening only after a while
+        let stub = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(1500));
+            let listener = TcpListener::bind(address).unwrap();
+            listener.accept().unwrap();
+        });
+
+        let started = Instant::now();
+        assert!(wait_for_startup(&probe(5), address).await.is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(1500));
+        stub.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_startup_timeout() {
+        let address = free_address();
+
+        let started = Instant::now();
+        let error = wait_for_startup(&probe(2), address).await.unwrap_err();
+        assert!(error.contains("failed 2 startup probes in a row"));
+        // No wait after the last attempt
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,557 @@
+use crate::persistence::{read_state, sync_directory, write_atomic, write_state};
+use chrono::{DateTime, SecondsFormat, Utc};
+use proto::common::ImageProvenance;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{event, Level};
+
+/// Directory where older riklets downloaded root filesystems, as `<workload>/rootfs.ext4`
+pub const LEGACY_CACHE_DIRECTORY: &str = "/tmp";
+const LEGACY_ROOTFS_FILE: &str = "rootfs.ext4";
+/// Time given to workloads to come back and adopt their legacy root filesystem
+/// before it is deleted
+const LEGACY_GRACE_PERIOD: Duration = Duration::from_secs(7 * 24 * 3600);
+
+const INDEX_FILE: &str = "index.json";
+/// Version of the content of the index file
+const INDEX_VERSION: u32 = 1;
+const BLOBS_DIRECTORY: &str = "blobs";
+const DOWNLOADS_DIRECTORY: &str = "downloads";
+/// Holds the date of the first migration pass, when the grace period started
+const MIGRATION_MARKER: &str = "legacy-migration";
+
+#[derive(Serialize, Deserialize, Default)]
+struct CacheIndex {
+    /// Hash of the content downloaded from each URL
+    urls: BTreeMap<String, String>,
+    /// Legacy files already adopted, they can be deleted without waiting
+    #[serde(default)]
+    adopted: BTreeSet<PathBuf>,
+    /// Where the content of each URL came from, as first downloaded
+    #[serde(default)]
+    provenance: BTreeMap<String, Provenance>,
+}
+
+/// Response of the registry a root filesystem was downloaded from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Download {
+    /// URL of the content, after redirects
+    pub resolved_url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// Artifact a root filesystem comes from, kept in the index so a reused root
+/// filesystem gives the date of its original download
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    pub resolved_url: String,
+    /// Hash of the content, which is also the name of the blob
+    pub sha256: String,
+    /// RFC 3339 date, in UTC
+    pub downloaded_at: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl From<Provenance> for ImageProvenance {
+    fn from(provenance: Provenance) -> Self {
+        ImageProvenance {
+            resolved_url: provenance.resolved_url,
+            sha256: provenance.sha256,
+            downloaded_at: provenance.downloaded_at,
+            etag: provenance.etag,
+            last_modified: provenance.last_modified,
+        }
+    }
+}
+
+impl Provenance {
+    fn new(download: Download, sha256: String, downloaded_at: SystemTime) -> Self {
+        Provenance {
+            resolved_url: download.resolved_url,
+            sha256,
+            downloaded_at: DateTime::<Utc>::from(downloaded_at)
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            etag: download.etag,
+            last_modified: download.last_modified,
+        }
+    }
+}
+
+/// Root filesystems of functions, and image archives of containers, stored by
+/// the hash of their content.
+///
+/// An index gives the hash of the content downloaded from each URL, so a
+/// root filesystem shared by several workloads is only stored once.
+pub struct RootfsCache {
+    directory: PathBuf,
+}
+
+impl RootfsCache {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// Directory of the root filesystems themselves
+    pub fn blobs_directory(&self) -> PathBuf {
+        self.directory.join(BLOBS_DIRECTORY)
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.blobs_directory().join(format!("{}.ext4", hash))
+    }
+
+    /// A missing or corrupt index only costs downloads, the blobs are kept
+    /// and recorded again as their URLs are downloaded
+    fn read_index(&self) -> CacheIndex {
+        read_state(&self.directory.join(INDEX_FILE), INDEX_VERSION).unwrap_or_default()
+    }
+
+    fn write_index(&self, index: &CacheIndex) -> io::Result<()> {
+        write_state(&self.directory.join(INDEX_FILE), INDEX_VERSION, index)
+    }
+
+    /// Cached root filesystem of a URL, if it was downloaded, with the
+    /// provenance of its download. A content other than the expected one is
+    /// not given, so it is downloaded again.
+    pub fn get(&self, url: &str, expected_sha256: Option<&str>) -> Option<(PathBuf, Provenance)> {
+        let mut index = self.read_index();
+        let hash = index.urls.remove(url)?;
+        if !matches_expected(&hash, expected_sha256) {
+            return None;
+        }
+        let path = self.blob_path(&hash);
+        let metadata = fs::metadata(&path).ok()?;
+        // Content cached before provenance was recorded, dated by its blob
+        let provenance = index.provenance.remove(url).unwrap_or_else(|| {
+            Provenance::new(
+                Download {
+                    resolved_url: url.to_string(),
+                    ..Default::default()
+                },
+                hash.clone(),
+                metadata.modified().unwrap_or(UNIX_EPOCH),
+            )
+        });
+        Some((path, provenance))
+    }
+
+    /// Path to download a root filesystem to, before giving it to `insert`
+    pub fn download_path(&self) -> io::Result<PathBuf> {
+        let directory = self.directory.join(DOWNLOADS_DIRECTORY);
+        fs::create_dir_all(&directory)?;
+        Ok(directory.join(format!("{}.part", uuid::Uuid::new_v4())))
+    }
+
+    /// Move a downloaded file in the cache as the content of a URL.
+    ///
+    /// A content other than the expected one is deleted and nothing is
+    /// recorded.
+    pub fn insert(
+        &self,
+        url: &str,
+        file: &Path,
+        download: Download,
+        expected_sha256: Option<&str>,
+    ) -> io::Result<(PathBuf, Provenance)> {
+        self.store(url, file, expected_sha256, |hash| {
+            Provenance::new(download, hash, SystemTime::now())
+        })
+    }
+
+    fn store(
+        &self,
+        url: &str,
+        file: &Path,
+        expected_sha256: Option<&str>,
+        provenance: impl FnOnce(String) -> Provenance,
+    ) -> io::Result<(PathBuf, Provenance)> {
+        let hash = hash_file(file)?;
+        if !matches_expected(&hash, expected_sha256) {
+            fs::remove_file(file)?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "content of {} has the SHA-256 {}, {} was expected",
+                    url,
+                    hash,
+                    expected_sha256.unwrap_or_default()
+                ),
+            ));
+        }
+        let path = self.blob_path(&hash);
+        fs::create_dir_all(self.blobs_directory())?;
+        if path.exists() {
+            fs::remove_file(file)?;
+        } else {
+            // The index never refers to a blob which may not be on the disk
+            File::open(file)?.sync_all()?;
+            fs::rename(file, &path)?;
+            sync_directory(&self.blobs_directory())?;
+        }
+
+        let provenance = provenance(hash.clone());
+        let mut index = self.read_index();
+        index.urls.insert(url.to_string(), hash);
+        index.provenance.insert(url.to_string(), provenance.clone());
+        self.write_index(&index)?;
+        Ok((path, provenance))
+    }
+
+    /// Adopt the root filesystem an older riklet downloaded for a workload,
+    /// sparing a download.
+    ///
+    /// The legacy layout only recorded the workload name, which the older
+    /// riklets trusted to give the same content, it is dated by the legacy
+    /// file and not adopted when another content is expected. The legacy file
+    /// is copied, as it may be on another file system, and left to the
+    /// migration pass.
+    pub fn adopt_legacy(
+        &self,
+        legacy_directory: &Path,
+        workload_name: &str,
+        url: &str,
+        expected_sha256: Option<&str>,
+    ) -> io::Result<Option<(PathBuf, Provenance)>> {
+        let legacy = legacy_directory
+            .join(workload_name)
+            .join(LEGACY_ROOTFS_FILE);
+        let modified = match fs::metadata(&legacy) {
+            Ok(metadata) if metadata.len() > 0 => metadata.modified().unwrap_or(UNIX_EPOCH),
+            _ => return Ok(None),
+        };
+
+        let download = self.download_path()?;
+        fs::copy(&legacy, &download)?;
+        let adopted = self.store(url, &download, expected_sha256, |hash| {
+            let download = Download {
+                resolved_url: url.to_string(),
+                ..Default::default()
+            };
+            Provenance::new(download, hash, modified)
+        });
+        let adopted = match adopted {
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                event!(
+                    Level::WARN,
+                    "Legacy root filesystem of workload {} not adopted: {}",
+                    workload_name,
+                    e
+                );
+                return Ok(None);
+            }
+            adopted => adopted?,
+        };
+        let mut index = self.read_index();
+        index.adopted.insert(legacy);
+        self.write_index(&index)?;
+        Ok(Some(adopted))
+    }
+
+    /// Date of the first migration pass, recorded when missing
+    fn migration_started(&self, now: SystemTime) -> io::Result<SystemTime> {
+        let marker = self.directory.join(MIGRATION_MARKER);
+        if let Some(seconds) = fs::read_to_string(&marker)
+            .ok()
+            .and_then(|content| content.trim().parse::<u64>().ok())
+        {
+            return Ok(UNIX_EPOCH + Duration::from_secs(seconds));
+        }
+        let seconds = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        write_atomic(&marker, seconds.to_string().as_bytes())?;
+        Ok(now)
+    }
+}
+
+/// Hashes are compared in lowercase, as they are written by `hash_file`
+fn matches_expected(hash: &str, expected_sha256: Option<&str>) -> bool {
+    expected_sha256.is_none_or(|expected| expected.eq_ignore_ascii_case(hash))
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Root filesystems found in the legacy layout, other files are left alone
+fn legacy_files(legacy_directory: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(legacy_directory) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|directory| {
+            let names: Vec<_> = fs::read_dir(directory)
+                .map(|entries| entries.flatten().map(|entry| entry.file_name()).collect())
+                .unwrap_or_default();
+            names.len() == 1 && names[0] == LEGACY_ROOTFS_FILE
+        })
+        .map(|directory| directory.join(LEGACY_ROOTFS_FILE))
+        .collect()
+}
+
+/// Files open by a process of the node, read from `/proc`
+pub fn open_files() -> HashSet<PathBuf> {
+    let Ok(processes) = fs::read_dir("/proc") else {
+        return HashSet::new();
+    };
+    processes
+        .flatten()
+        .filter_map(|process| fs::read_dir(process.path().join("fd")).ok())
+        .flat_map(|descriptors| descriptors.flatten())
+        .filter_map(|descriptor| fs::read_link(descriptor.path()).ok())
+        .collect()
+}
+
+/// Outcome of a migration pass
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MigrationSummary {
+    pub found: usize,
+    pub deleted: usize,
+    /// Files kept because a process has them open
+    pub in_use: usize,
+    /// Files kept until the end of the grace period
+    pub waiting: usize,
+    pub freed_bytes: u64,
+}
+
+impl Display for MigrationSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} found, {} deleted ({} MiB freed), {} in use, {} waiting for the grace period",
+            self.found,
+            self.deleted,
+            self.freed_bytes / (1024 * 1024),
+            self.in_use,
+            self.waiting
+        )
+    }
+}
+
+/// Clean up the root filesystems left by older riklets in the legacy layout.
+///
+/// They are kept for a grace period starting with the first pass, so the
+/// workloads created again adopt them instead of downloading, then deleted.
+/// Adopted files are deleted at once, and files open by a process are never
+/// deleted. Running the pass again is harmless.
+pub fn migrate_legacy_cache(
+    cache: &RootfsCache,
+    legacy_directory: &Path,
+    in_use: &HashSet<PathBuf>,
+    now: SystemTime,
+) -> io::Result<MigrationSummary> {
+    let files = legacy_files(legacy_directory);
+    let mut summary = MigrationSummary {
+        found: files.len(),
+        ..Default::default()
+    };
+    if files.is_empty() {
+        return Ok(summary);
+    }
+
+    let started = cache.migration_started(now)?;
+    let expired = now.duration_since(started).unwrap_or_default() >= LEGACY_GRACE_PERIOD;
+    let mut index = cache.read_index();
+    for file in files {
+        if in_use.contains(&file) {
+            summary.in_use += 1;
+            continue;
+        }
+        if !expired && !index.adopted.contains(&file) {
+            summary.waiting += 1;
+            continue;
+        }
+        let size = fs::metadata(&file).map_or(0, |metadata| metadata.len());
+        fs::remove_file(&file)?;
+        if let Some(directory) = file.parent() {
+            let _ = fs::remove_dir(directory);
+        }
+        index.adopted.remove(&file);
+        summary.deleted += 1;
+        summary.freed_bytes += size;
+    }
+    cache.write_index(&index)?;
+
+    event!(
+        Level::WARN,
+        "Root filesystems left in {} by an older riklet: {}",
+        legacy_directory.display(),
+        summary
+    );
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_directory() -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("riklet-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn legacy_rootfs(legacy_directory: &Path, workload_name: &str, content: &str) -> PathBuf {
+        let directory = legacy_directory.join(workload_name);
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(LEGACY_ROOTFS_FILE);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_identical_content_is_stored_once() {
+        let cache = RootfsCache::new(temporary_directory());
+        for url in ["https://a/rootfs.ext4", "https://b/rootfs.ext4"] {
+            let download = cache.download_path().unwrap();
+            fs::write(&download, "rootfs").unwrap();
+            cache
+                .insert(url, &download, Download::default(), None)
+                .unwrap();
+        }
+
+        let (first, _) = cache.get("https://a/rootfs.ext4", None).unwrap();
+        let (second, _) = cache.get("https://b/rootfs.ext4", None).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(cache.get("https://c/rootfs.ext4", None), None);
+    }
+
+    #[test]
+    fn test_reused_content_keeps_its_provenance() {
+        let cache = RootfsCache::new(temporary_directory());
+        let url = "https://a/rootfs.ext4";
+        let hash = "3c47ef972d531d524daa15fa33dd885dd23de6221bbd10a29eb42ecfcf2ef422";
+        let download = cache.download_path().unwrap();
+        fs::write(&download, "rootfs").unwrap();
+
+        // A download which is not the declared content is never recorded
+        let other = "0".repeat(64);
+        let e = cache
+            .insert(url, &download, Download::default(), Some(&other))
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(!download.exists());
+        assert_eq!(cache.get(url, None), None);
+
+        fs::write(&download, "rootfs").unwrap();
+        let response = Download {
+            resolved_url: String::from("https://mirror/rootfs.ext4"),
+            etag: Some(String::from("\"v1\"")),
+            last_modified: None,
+        };
+        let (_, inserted) = cache
+            .insert(url, &download, response, Some(&hash.to_uppercase()))
+            .unwrap();
+        assert_eq!(inserted.sha256, hash);
+        assert_eq!(inserted.resolved_url, "https://mirror/rootfs.ext4");
+
+        std::thread::sleep(Duration::from_millis(1100));
+        let (_, reused) = cache.get(url, Some(hash)).unwrap();
+        assert_eq!(reused, inserted);
+        // Another declared content is downloaded again
+        assert_eq!(cache.get(url, Some(&other)), None);
+    }
+
+    #[test]
+    fn test_truncated_index_keeps_the_blobs() {
+        let directory = temporary_directory();
+        let cache = RootfsCache::new(&directory);
+        let url = "https://a/rootfs.ext4";
+        let download = cache.download_path().unwrap();
+        fs::write(&download, "rootfs").unwrap();
+        let (blob, _) = cache
+            .insert(url, &download, Download::default(), None)
+            .unwrap();
+        let index = fs::read(directory.join(INDEX_FILE)).unwrap();
+
+        for length in 0..index.len() {
+            // Index torn by a crash of a riklet without atomic writes
+            fs::write(directory.join(INDEX_FILE), &index[..length]).unwrap();
+            assert_eq!(cache.get(url, None), None);
+
+            let download = cache.download_path().unwrap();
+            fs::write(&download, "rootfs").unwrap();
+            let (inserted, _) = cache
+                .insert(url, &download, Download::default(), None)
+                .unwrap();
+            assert_eq!(inserted, blob);
+        }
+        assert_eq!(fs::read_dir(cache.blobs_directory()).unwrap().count(), 1);
+        assert_eq!(cache.get(url, None).unwrap().0, blob);
+    }
+
+    #[test]
+    fn test_adopted_files_are_deleted_at_once() {
+        let legacy_directory = temporary_directory();
+        let cache = RootfsCache::new(temporary_directory());
+        let adopted = legacy_rootfs(&legacy_directory, "adopted", "rootfs");
+        let other = legacy_rootfs(&legacy_directory, "other", "other rootfs");
+        fs::write(legacy_directory.join("unrelated"), "").unwrap();
+
+        assert_eq!(
+            cache
+                .adopt_legacy(
+                    &legacy_directory,
+                    "other",
+                    "https://b/rootfs.ext4",
+                    Some(&"0".repeat(64))
+                )
+                .unwrap(),
+            None
+        );
+        let (path, provenance) = cache
+            .adopt_legacy(&legacy_directory, "adopted", "https://a/rootfs.ext4", None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "rootfs");
+        let modified = fs::metadata(&adopted).unwrap().modified().unwrap();
+        assert_eq!(
+            provenance.downloaded_at,
+            Provenance::new(Download::default(), String::new(), modified).downloaded_at
+        );
+
+        let now = SystemTime::now();
+        let summary =
+            migrate_legacy_cache(&cache, &legacy_directory, &HashSet::new(), now).unwrap();
+        assert_eq!((summary.found, summary.deleted, summary.waiting), (2, 1, 1));
+        assert!(!adopted.exists());
+        assert!(other.exists());
+        assert!(legacy_directory.join("unrelated").exists());
+
+        // Running it again changes nothing until the grace period ends
+        let summary =
+            migrate_legacy_cache(&cache, &legacy_directory, &HashSet::new(), now).unwrap();
+        assert_eq!((summary.found, summary.deleted, summary.waiting), (1, 0, 1));
+        let later = now + LEGACY_GRACE_PERIOD;
+        let summary =
+            migrate_legacy_cache(&cache, &legacy_directory, &HashSet::new(), later).unwrap();
+        assert_eq!(summary.deleted, 1);
+        assert!(!other.exists());
+    }
+
+    #[test]
+    fn test_open_files_are_never_deleted() {
+        let legacy_directory = temporary_directory();
+        let cache = RootfsCache::new(temporary_directory());
+        let running = legacy_rootfs(&legacy_directory, "running", "rootfs");
+
+        let later = SystemTime::now() + LEGACY_GRACE_PERIOD;
+        cache.migration_started(SystemTime::now()).unwrap();
+        let in_use = HashSet::from([running.clone()]);
+        let summary = migrate_legacy_cache(&cache, &legacy_directory, &in_use, later).unwrap();
+        assert_eq!((summary.deleted, summary.in_use), (0, 1));
+        assert!(running.exists());
+    }
+}
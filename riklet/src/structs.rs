@@ -1,3 +1,4 @@
+use definition::workload::{EgressPolicy, EgressRule, PortProtocol, Probe};
 use serde::{Deserialize, Serialize};
 use shared::utils::get_random_hash;
 use tracing::{event, warn, Level};
@@ -20,7 +21,7 @@ pub struct EnvConfig {
 pub struct PortConfig {
     pub port: u16,
     pub target_port: u16,
-    pub protocol: Option<String>,
+    pub protocol: Option<PortProtocol>,
     pub r#type: String,
 }
 
@@ -28,7 +29,14 @@ pub struct PortConfig {
 pub struct Container {
     pub id: Option<String>,
     pub name: String,
+    /// Empty when the container is imported from `image_archive_url`
+    #[serde(default)]
     pub image: String,
+    #[serde(default)]
+    pub image_archive_url: Option<url::Url>,
+    /// Expected SHA-256 of the image archive
+    #[serde(default)]
+    pub image_archive_sha256: Option<String>,
     pub env: Option<Vec<EnvConfig>>,
     pub ports: Option<PortConfig>,
 }
@@ -43,6 +51,9 @@ impl Container {
 pub struct FunctionExecution {
     /// Remote URL to a RootFS, must be accessible from the runtime
     pub rootfs: url::Url,
+    /// Expected SHA-256 of the RootFS
+    #[serde(default)]
+    pub sha256: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -60,12 +71,29 @@ pub struct FunctionPort {
     pub target_port: u16,
     #[serde(rename = "type")]
     pub port_type: NetworkPortExposureType,
+    #[serde(default)]
+    pub protocol: PortProtocol,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FunctionVolume {
+    pub name: String,
+    /// Unique identifier of the volume, given by the controller
+    #[serde(default)]
+    pub id: String,
+    pub size_mb: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Function {
     pub execution: FunctionExecution,
     pub exposure: Option<FunctionPort>,
+    #[serde(default)]
+    pub volumes: Vec<FunctionVolume>,
+    #[serde(default)]
+    pub egress_policy: Option<EgressPolicy>,
+    #[serde(default)]
+    pub startup_probe: Option<Probe>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -99,6 +127,34 @@ impl WorkloadDefinition {
         containers
     }
 
+    pub fn get_volumes(&self) -> Vec<FunctionVolume> {
+        self.spec
+            .function
+            .as_ref()
+            .map(|function| function.volumes.clone())
+            .unwrap_or_default()
+    }
+
+    /// Traffic the workload denies itself on top of the node rules
+    pub fn get_egress_rules(&self) -> Vec<EgressRule> {
+        self.spec
+            .function
+            .as_ref()
+            .and_then(|function| function.egress_policy.as_ref())
+            .map(|policy| policy.deny.clone())
+            .unwrap_or_default()
+    }
+
+    /// Startup probe of the workload, along with the port it checks
+    pub fn get_startup_probe(&self) -> Option<(Probe, u16)> {
+        let function = self.spec.function.as_ref()?;
+        let probe = function.startup_probe.clone()?;
+        let port = probe
+            .port
+            .or_else(|| function.exposure.as_ref().map(|e| e.target_port))?;
+        Some((probe, port))
+    }
+
     pub fn get_rootfs_url(&self) -> Option<String> {
         self.spec
             .function
@@ -106,21 +162,28 @@ impl WorkloadDefinition {
             .map(|v| v.execution.rootfs.to_string())
     }
 
-    /// Give expected ports exposed by the workload.
-    /// Returns a tuple of (host_port, target_port)
-    #[tracing::instrument(skip(self), fields(self.name))]
-    pub fn get_port_mapping(&self) -> Vec<(u16, u16)> {
-        let mut port_mapping = Vec::<(u16, u16)>::new();
-        let function_exposure = self
-            .spec
+    pub fn get_rootfs_sha256(&self) -> Option<String> {
+        self.spec
             .function
             .as_ref()
-            .and_then(|f| f.exposure.as_ref().map(|e| (e.port, e.target_port)));
+            .and_then(|v| v.execution.sha256.clone())
+    }
 
-        if let Some((host_port, target_port)) = function_exposure {
+    /// Give expected ports exposed by the workload.
+    /// Returns a tuple of (host_port, target_port, protocol)
+    #[tracing::instrument(skip(self), fields(self.name))]
+    pub fn get_port_mapping(&self) -> Vec<(u16, u16, PortProtocol)> {
+        let mut port_mapping = Vec::<(u16, u16, PortProtocol)>::new();
+        let function_exposure = self.spec.function.as_ref().and_then(|f| {
+            f.exposure
+                .as_ref()
+                .map(|e| (e.port, e.target_port, e.protocol))
+        });
+
+        if let Some((host_port, target_port, protocol)) = function_exposure {
             // FIXME: This is a domain violation, as we want to get away from binding to this "FUNCTION_RUNTIME" things
             // which refers to a specific implementation of a VM
-            port_mapping.push((host_port, target_port));
+            port_mapping.push((host_port, target_port, protocol));
         } else {
             warn!("No port mapping found for workload {}", self.name);
         }
@@ -143,12 +206,17 @@ mod tests {
                 function: Some(Function {
                     execution: FunctionExecution {
                         rootfs: url::Url::parse("http://localhost:8080").unwrap(),
+                        sha256: None,
                     },
                     exposure: Some(FunctionPort {
                         port: 8080,
                         target_port: 8081,
                         port_type: NetworkPortExposureType::NodePort,
+                        protocol: PortProtocol::UDP,
                     }),
+                    volumes: vec![],
+                    egress_policy: None,
+                    startup_probe: None,
                 }),
             },
         };
@@ -159,6 +227,7 @@ mod tests {
         assert_eq!(port_mapping[0].0, 8080);
         // internal port
         assert_eq!(port_mapping[0].1, 8081);
+        assert_eq!(port_mapping[0].2, PortProtocol::UDP);
     }
 
     #[test]
@@ -0,0 +1,94 @@
+use proto::worker::DesiredState;
+use std::collections::HashMap;
+
+/// Differences between the instances running on the node and the ones it should run
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    /// Desired instances the node does not run
+    pub missing: Vec<String>,
+    /// Desired instances running with another definition
+    pub outdated: Vec<String>,
+    /// Instances running although they are not desired anymore
+    pub undesired: Vec<String>,
+}
+
+impl StateDiff {
+    /// Compare the desired state with the definition hash of each running instance
+    pub fn new(desired_state: &DesiredState, running: &HashMap<String, String>) -> StateDiff {
+        let mut diff = StateDiff::default();
+        for desired in &desired_state.instances {
+            match running.get(&desired.instance_id) {
+                None => diff.missing.push(desired.instance_id.clone()),
+                Some(hash) if *hash != desired.definition_hash => {
+                    diff.outdated.push(desired.instance_id.clone())
+                }
+                Some(_) => {}
+            }
+        }
+
+        diff.undesired = running
+            .keys()
+            .filter(|instance_id| {
+                !desired_state
+                    .instances
+                    .iter()
+                    .any(|desired| desired.instance_id == **instance_id)
+            })
+            .cloned()
+            .collect();
+        diff.undesired.sort();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.outdated.is_empty() && self.undesired.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::worker::DesiredInstance;
+
+    fn desired(instance_id: &str, definition_hash: &str) -> DesiredInstance {
+        DesiredInstance {
+            instance_id: instance_id.to_string(),
+            definition_hash: definition_hash.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_diff_with_desired_state() {
+        let desired_state = DesiredState {
+            instances: vec![
+                desired("unchanged", "aaaa"),
+                desired("changed", "bbbb"),
+                desired("lost", "cccc"),
+            ],
+        };
+        let running = HashMap::from([
+            ("unchanged".to_string(), "aaaa".to_string()),
+            ("changed".to_string(), "dddd".to_string()),
+            ("leftover".to_string(), "eeee".to_string()),
+        ]);
+
+        assert_eq!(
+            StateDiff::new(&desired_state, &running),
+            StateDiff {
+                missing: vec!["lost".to_string()],
+                outdated: vec!["changed".to_string()],
+                undesired: vec!["leftover".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_diff_in_sync() {
+        let desired_state = DesiredState {
+            instances: vec![desired("unchanged", "aaaa")],
+        };
+        let running = HashMap::from([("unchanged".to_string(), "aaaa".to_string())]);
+
+        assert!(StateDiff::new(&desired_state, &running).is_empty());
+    }
+}
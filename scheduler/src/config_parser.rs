@@ -1,23 +1,51 @@
+use crate::state_manager::placement_limiter::{DEFAULT_MAX_PLACEMENTS, DEFAULT_PLACEMENT_INTERVAL};
+use crate::state_manager::rebalancer::{
+    RebalanceMode, DEFAULT_REBALANCE_INTERVAL, DEFAULT_REBALANCE_MAX_MOVES,
+    DEFAULT_REBALANCE_THRESHOLD,
+};
+use crate::state_manager::sync::DEFAULT_SYNC_INTERVAL;
 use clap::{App, Arg};
 use std::error::Error;
 use std::fmt;
 use std::net::SocketAddrV4;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct ConfigParser {
     pub workers_endpoint: SocketAddrV4,
     pub controller_endpoint: SocketAddrV4,
     pub verbosity_level: String,
+    pub max_placements: u32,
+    pub placement_interval: Duration,
+    pub sync_interval: Duration,
+    pub rebalance_mode: RebalanceMode,
+    pub rebalance_interval: Duration,
+    pub rebalance_max_moves: u32,
+    pub rebalance_threshold: f64,
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ConfigParserError {
     InvalidWorkersEndpoint,
     InvalidControllersEndpoint,
+    InvalidMaxPlacements,
+    InvalidPlacementInterval,
+    InvalidSyncInterval,
+    InvalidRebalanceMode,
+    InvalidRebalanceInterval,
+    InvalidRebalanceMaxMoves,
+    InvalidRebalanceThreshold,
 }
 
 impl ConfigParser {
     pub fn new() -> Result<ConfigParser, ConfigParserError> {
+        let default_max_placements = DEFAULT_MAX_PLACEMENTS.to_string();
+        let default_placement_interval = DEFAULT_PLACEMENT_INTERVAL.as_secs().to_string();
+        let default_sync_interval = DEFAULT_SYNC_INTERVAL.as_secs().to_string();
+        let default_rebalance_interval = DEFAULT_REBALANCE_INTERVAL.as_secs().to_string();
+        let default_rebalance_max_moves = DEFAULT_REBALANCE_MAX_MOVES.to_string();
+        let default_rebalance_threshold = DEFAULT_REBALANCE_THRESHOLD.to_string();
         let matches = App::new("RIK scheduler")
             .version("1.0")
             .author("Polytech Montpellier - DO3 - 2023")
@@ -39,6 +67,63 @@ impl ConfigParser {
                     .takes_value(true)
                     .default_value("0.0.0.0:4996"),
             )
+            .arg(
+                Arg::with_name("max_placements")
+                    .long("max-placements")
+                    .value_name("MAX_PLACEMENTS")
+                    .help("Maximum number of placements sent to a worker per interval, when the worker does not advertise its own")
+                    .takes_value(true)
+                    .default_value(&default_max_placements),
+            )
+            .arg(
+                Arg::with_name("placement_interval")
+                    .long("placement-interval")
+                    .value_name("SECONDS")
+                    .help("Length in seconds of the placement rate limiting interval")
+                    .takes_value(true)
+                    .default_value(&default_placement_interval),
+            )
+            .arg(
+                Arg::with_name("sync_interval")
+                    .long("sync-interval")
+                    .value_name("SECONDS")
+                    .help("Interval in seconds between two syncs of the desired state to the workers")
+                    .takes_value(true)
+                    .default_value(&default_sync_interval),
+            )
+            .arg(
+                Arg::with_name("rebalance")
+                    .long("rebalance")
+                    .value_name("MODE")
+                    .help("Move instances of rebalanceable workloads to less loaded workers, or only log the moves with simulate")
+                    .takes_value(true)
+                    .possible_values(&["off", "simulate", "on"])
+                    .default_value("off"),
+            )
+            .arg(
+                Arg::with_name("rebalance_interval")
+                    .long("rebalance-interval")
+                    .value_name("SECONDS")
+                    .help("Interval in seconds between two rebalancing passes")
+                    .takes_value(true)
+                    .default_value(&default_rebalance_interval),
+            )
+            .arg(
+                Arg::with_name("rebalance_max_moves")
+                    .long("rebalance-max-moves")
+                    .value_name("MOVES")
+                    .help("Maximum number of instances moved per rebalancing pass")
+                    .takes_value(true)
+                    .default_value(&default_rebalance_max_moves),
+            )
+            .arg(
+                Arg::with_name("rebalance_threshold")
+                    .long("rebalance-threshold")
+                    .value_name("VARIANCE")
+                    .help("Decrease of the variance of the instances per worker a move must bring")
+                    .takes_value(true)
+                    .default_value(&default_rebalance_threshold),
+            )
             .get_matches();
 
         let workers_ip: SocketAddrV4 = matches
@@ -53,10 +138,65 @@ impl ConfigParser {
             .parse()
             .map_err(|_| ConfigParserError::InvalidControllersEndpoint)?;
 
+        let max_placements: u32 = matches
+            .value_of("max_placements")
+            .unwrap()
+            .parse()
+            .map_err(|_| ConfigParserError::InvalidMaxPlacements)?;
+
+        let placement_interval: u64 = matches
+            .value_of("placement_interval")
+            .unwrap()
+            .parse()
+            .map_err(|_| ConfigParserError::InvalidPlacementInterval)?;
+
+        let sync_interval: u64 = matches
+            .value_of("sync_interval")
+            .unwrap()
+            .parse()
+            .ok()
+            .filter(|interval| *interval > 0)
+            .ok_or(ConfigParserError::InvalidSyncInterval)?;
+
+        let rebalance_mode: RebalanceMode = matches
+            .value_of("rebalance")
+            .unwrap()
+            .parse()
+            .map_err(|_| ConfigParserError::InvalidRebalanceMode)?;
+
+        let rebalance_interval: u64 = matches
+            .value_of("rebalance_interval")
+            .unwrap()
+            .parse()
+            .ok()
+            .filter(|interval| *interval > 0)
+            .ok_or(ConfigParserError::InvalidRebalanceInterval)?;
+
+        let rebalance_max_moves: u32 = matches
+            .value_of("rebalance_max_moves")
+            .unwrap()
+            .parse()
+            .map_err(|_| ConfigParserError::InvalidRebalanceMaxMoves)?;
+
+        let rebalance_threshold: f64 = matches
+            .value_of("rebalance_threshold")
+            .unwrap()
+            .parse()
+            .ok()
+            .filter(|threshold: &f64| *threshold >= 0.0)
+            .ok_or(ConfigParserError::InvalidRebalanceThreshold)?;
+
         Ok(ConfigParser {
             workers_endpoint: workers_ip,
             controller_endpoint: controllers_ip,
             verbosity_level: ConfigParser::get_verbosity_level(matches.occurrences_of("v")),
+            max_placements,
+            placement_interval: Duration::from_secs(placement_interval),
+            sync_interval: Duration::from_secs(sync_interval),
+            rebalance_mode,
+            rebalance_interval: Duration::from_secs(rebalance_interval),
+            rebalance_max_moves,
+            rebalance_threshold,
         })
     }
 
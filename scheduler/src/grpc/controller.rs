@@ -1,7 +1,7 @@
 use crate::grpc::GRPCService;
 use proto::common::WorkerStatus;
 use proto::controller::controller_server::Controller as ControllerClient;
-use proto::controller::WorkloadScheduling;
+use proto::controller::{WorkerCordon, WorkloadScheduling};
 use scheduler::Send;
 use scheduler::{Event, WorkloadRequest};
 use tokio::sync::mpsc::channel;
@@ -23,7 +23,8 @@ impl ControllerClient for GRPCService {
             Status::invalid_argument(e.to_string())
         })?;
 
-        self.send(Event::ScheduleRequest(parsed_body)).await?;
+        self.send(Event::ScheduleRequest(Box::new(parsed_body)))
+            .await?;
 
         Ok(Response::new(()))
     }
@@ -42,6 +43,18 @@ impl ControllerClient for GRPCService {
 
         Ok(Response::new(ReceiverStream::new(stream_rx)))
     }
+
+    async fn set_worker_cordon(
+        &self,
+        request: Request<WorkerCordon>,
+    ) -> Result<Response<()>, Status> {
+        let WorkerCordon {
+            worker_id,
+            cordoned,
+        } = request.into_inner();
+        self.send(Event::Cordon(worker_id, cordoned)).await?;
+        Ok(Response::new(()))
+    }
 }
 
 #[cfg(test)]
@@ -66,19 +79,30 @@ mod tests {
                 kind: WorkloadKind::Pod,
                 name: "workload-debian".to_string(),
                 replicas: Some(2),
+                max_instance_lifetime_seconds: None,
+                labels: Default::default(),
+                rebalanceable: false,
+                min_ready_replicas: None,
+                protected: false,
+                namespace: None,
+                tenant_id: None,
                 spec: Spec {
                     function: None,
                     containers: vec![Container {
                         name: " debian".to_string(),
                         image: "debian:latest".to_string(),
+                        image_archive_url: None,
+                        image_archive_sha256: None,
                         env: None,
                         ports: None,
+                        discover: vec![],
                     }],
                 },
             })
             .map_err(|e| Status::invalid_argument(e.to_string()))?,
             action: WorkloadRequestKind::Create.into(),
             instance_id: "".to_string(),
+            correlation_id: "req-1".to_string(),
         };
 
         let mock_request = Request::new(workload.clone());
@@ -91,7 +115,7 @@ mod tests {
                 workload
                     .unpack()
                     .map_err(|e| { Status::invalid_argument(e.to_string()) })?,
-                content
+                *content
             ),
             _ => assert!(false),
         };
@@ -145,6 +169,24 @@ mod tests {
         };
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_set_worker_cordon() {
+        let (sender, mut receiver) = channel::<Event>(1024);
+
+        let service = GRPCService::new(sender);
+
+        let mock_request = Request::new(WorkerCordon {
+            worker_id: "worker-1".to_string(),
+            cordoned: true,
+        });
+        service.set_worker_cordon(mock_request).await.unwrap();
+
+        assert!(matches!(
+            receiver.recv().await.unwrap(),
+            Event::Cordon(worker_id, true) if worker_id == "worker-1"
+        ));
+    }
 }
 
 trait UnPacker<T> {
@@ -1,6 +1,6 @@
 use crate::grpc::GRPCService;
 use proto::common::worker_status::Status;
-use proto::common::{WorkerRegistration, WorkerStatus};
+use proto::common::{RuntimeKind, WorkerRegistration, WorkerStatus};
 use proto::worker::worker_server::Worker as WorkerClient;
 use scheduler::Event;
 use scheduler::{Send, WorkerRegisterChannelType};
@@ -28,7 +28,22 @@ impl WorkerClient for GRPCService {
             }
             hostname => Ok(hostname.clone()),
         }?;
-        self.send(Event::Register(stream_tx, addr, body)).await?;
+        let max_placements = _request.get_ref().max_placements;
+        let supported_kinds = _request
+            .get_ref()
+            .supported_kinds
+            .iter()
+            .filter_map(|kind| RuntimeKind::from_i32(*kind))
+            .map(Into::into)
+            .collect();
+        self.send(Event::Register(
+            stream_tx,
+            addr,
+            body,
+            max_placements,
+            supported_kinds,
+        ))
+        .await?;
 
         Ok(Response::new(ReceiverStream::new(stream_rx)))
     }
@@ -58,6 +73,7 @@ impl WorkerClient for GRPCService {
                     self.send(Event::InstanceMetricsUpdate(identifier, metrics))
                         .await?
                 }
+                Status::Sync(report) => self.send(Event::SyncReport(identifier, report)).await?,
             };
         }
 
@@ -68,6 +84,7 @@ impl WorkerClient for GRPCService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use definition::workload::WorkloadKind;
     use proto::worker::InstanceScheduling;
     use std::net::SocketAddr;
     use tokio::sync::mpsc::error::SendError;
@@ -82,13 +99,16 @@ mod tests {
 
         let mock_request = Request::new(WorkerRegistration {
             hostname: hostname.clone(),
+            max_placements: None,
+            supported_kinds: vec![RuntimeKind::Function as i32],
         });
 
         let _ = service.register(mock_request).await;
 
         let message = receiver.recv().await.unwrap();
         match message {
-            Event::Register(_, socket, host) => {
+            Event::Register(_, socket, host, _, supported_kinds) => {
+                assert_eq!(supported_kinds, vec![WorkloadKind::Function]);
                 assert_eq!(hostname, host);
                 let default_socket: SocketAddr = "0.0.0.0:0".parse().unwrap();
                 assert_eq!(default_socket, socket);
@@ -105,6 +125,8 @@ mod tests {
 
         let mock_request = Request::new(WorkerRegistration {
             hostname: "".to_string(),
+            max_placements: None,
+            supported_kinds: vec![],
         });
         let fallback = service.register(mock_request).await;
         assert!(fallback.is_err());
@@ -123,13 +145,15 @@ mod tests {
 
         let mock_request = Request::new(WorkerRegistration {
             hostname: hostname.clone(),
+            max_placements: None,
+            supported_kinds: vec![],
         });
 
         service.register(mock_request).await?;
 
         let message = receiver.recv().await.unwrap();
         match message {
-            Event::Register(_, _, _) => assert!(true),
+            Event::Register(_, _, _, _, _) => assert!(true),
             _ => assert!(false),
         };
         Ok(())
@@ -145,6 +169,8 @@ mod tests {
 
         let mock_request = Request::new(WorkerRegistration {
             hostname: hostname.clone(),
+            max_placements: None,
+            supported_kinds: vec![],
         });
 
         let mut stream = service
@@ -156,7 +182,7 @@ mod tests {
 
         let message = receiver.recv().await.unwrap();
         match message {
-            Event::Register(sender, _, _) => {
+            Event::Register(sender, _, _, _, _) => {
                 sender.send(Err(tonic::Status::cancelled("Sample"))).await?;
                 let rcv = stream.recv().await.unwrap();
                 assert!(rcv.is_err());
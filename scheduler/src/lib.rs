@@ -1,6 +1,7 @@
-use definition::workload::WorkloadDefinition;
+use definition::workload::{WorkloadDefinition, WorkloadKind};
+use definition::NodeCondition;
 use node_metrics::metrics::Metrics;
-use proto::common::{InstanceMetric, WorkerMetric, WorkerStatus, WorkloadRequestKind};
+use proto::common::{InstanceMetric, SyncReport, WorkerMetric, WorkerStatus, WorkloadRequestKind};
 use proto::controller::WorkloadScheduling;
 use proto::worker::InstanceScheduling;
 use std::error::Error;
@@ -20,11 +21,18 @@ pub type WorkerRegisterChannelType = Result<InstanceScheduling, tonic::Status>;
 #[derive(Debug)]
 pub enum Event {
     /// Workers register to the Scheduler so they can serve
-    /// the cluster
-    Register(Sender<WorkerRegisterChannelType>, SocketAddr, String),
+    /// the cluster, they can optionally advertise the maximum amount of
+    /// placements they accept per interval and the kinds of workloads they run
+    Register(
+        Sender<WorkerRegisterChannelType>,
+        SocketAddr,
+        String,
+        Option<u32>,
+        Vec<WorkloadKind>,
+    ),
     /// Controller can send workload, we use the verb Schedule to describe
     /// this event
-    ScheduleRequest(WorkloadRequest),
+    ScheduleRequest(Box<WorkloadRequest>),
     /// The StateManager uses this event to send a workload to a worker
     /// String is for the worker id
     Schedule(String, InstanceScheduling),
@@ -38,7 +46,9 @@ pub enum Event {
     /// use proto::common::{WorkerMetric};
     /// let metrics = WorkerMetric {
     ///     status: 1,
-    ///     metrics: "{metricA: 10, metricB: 100}".to_string()
+    ///     metrics: "{metricA: 10, metricB: 100}".to_string(),
+    ///     conditions: vec![],
+    ///     queued_placements: None,
     /// };
     /// ```
     WorkerMetric(String, WorkerMetric),
@@ -52,13 +62,22 @@ pub enum Event {
     /// let metrics = InstanceMetric {
     ///     status: 1,
     ///     metrics: "{metricA: 10, metricB: 100}".to_string(),
-    ///     instance_id: "test".to_string()
+    ///     instance_id: "test".to_string(),
+    ///     conditions: vec![],
+    ///     worker_id: None,
+    ///     provenance: None,
+    ///     app_status: None,
     /// };
     /// ```
     InstanceMetric(String, InstanceMetric),
     /// Metrics received from workers to tell about themselves
     /// These metrics will be used inside the state manager
     InstanceMetricsUpdate(String, InstanceMetric),
+    /// Differences a worker found with the desired state it was sent,
+    /// the string is the worker identifier
+    SyncReport(String, SyncReport),
+    /// Controller cordons or uncordons the worker with the given identifier
+    Cordon(String, bool),
 }
 
 #[derive(Debug)]
@@ -160,6 +179,17 @@ pub struct Worker {
     state: WorkerState,
     /// Most recent metric the worker has on its state
     metric: Option<Metrics>,
+    /// Maximum placements per interval advertised by the worker, the scheduler
+    /// default is used when none is given
+    max_placements: Option<u32>,
+    /// Amount of instances waiting for the worker placement budget
+    queued_placements: usize,
+    /// Problems reported by the worker about itself
+    conditions: Vec<NodeCondition>,
+    /// Kinds of workloads the worker can run, workers advertising none run all of them
+    supported_kinds: Vec<WorkloadKind>,
+    /// Set by the controller to stop placing new instances on the worker
+    cordoned: bool,
 }
 
 impl Worker {
@@ -170,9 +200,88 @@ impl Worker {
             addr,
             state: WorkerState::NotReady,
             metric: None,
+            max_placements: None,
+            queued_placements: 0,
+            conditions: Vec::new(),
+            supported_kinds: Vec::new(),
+            cordoned: false,
         }
     }
 
+    pub fn set_max_placements(&mut self, max_placements: Option<u32>) {
+        self.max_placements = max_placements;
+    }
+
+    pub fn get_max_placements(&self) -> Option<u32> {
+        self.max_placements
+    }
+
+    pub fn set_queued_placements(&mut self, queued_placements: usize) {
+        self.queued_placements = queued_placements;
+    }
+
+    pub fn get_queued_placements(&self) -> usize {
+        self.queued_placements
+    }
+
+    /// Metrics of the worker as sent to the controller, along with the
+    /// placements queued on it
+    pub fn status_metric(&self, metric: WorkerMetric) -> WorkerMetric {
+        WorkerMetric {
+            queued_placements: Some(self.queued_placements as u32),
+            ..metric
+        }
+    }
+
+    /// Update the conditions of the worker, returns whether they changed
+    pub fn set_conditions(&mut self, conditions: Vec<NodeCondition>) -> bool {
+        if self.conditions == conditions {
+            return false;
+        }
+        for condition in conditions.iter().filter(|condition| condition.active) {
+            info!(
+                "Worker {} reports {}: {}",
+                self.id, condition.condition_type, condition.message
+            );
+        }
+        self.conditions = conditions;
+        true
+    }
+
+    pub fn get_conditions(&self) -> &Vec<NodeCondition> {
+        &self.conditions
+    }
+
+    /// Whether the worker can receive new instances given its conditions and cordon
+    pub fn is_schedulable(&self) -> bool {
+        !self.cordoned
+            && !self
+                .conditions
+                .iter()
+                .any(|condition| condition.active && condition.condition_type.prevents_scheduling())
+    }
+
+    pub fn set_cordoned(&mut self, cordoned: bool) {
+        self.cordoned = cordoned;
+    }
+
+    pub fn is_cordoned(&self) -> bool {
+        self.cordoned
+    }
+
+    pub fn set_supported_kinds(&mut self, supported_kinds: Vec<WorkloadKind>) {
+        self.supported_kinds = supported_kinds;
+    }
+
+    pub fn get_supported_kinds(&self) -> &Vec<WorkloadKind> {
+        &self.supported_kinds
+    }
+
+    /// Whether the worker can run workloads of the given kind
+    pub fn supports_kind(&self, kind: &WorkloadKind) -> bool {
+        self.supported_kinds.is_empty() || self.supported_kinds.contains(kind)
+    }
+
     pub fn set_channel(&mut self, sender: Sender<WorkerRegisterChannelType>) {
         self.channel = sender;
     }
@@ -235,6 +344,7 @@ pub struct WorkloadRequest {
     pub definition: WorkloadDefinition,
     pub action: WorkloadRequestKind,
     pub instance_id: String,
+    pub correlation_id: String,
 }
 
 impl WorkloadRequest {
@@ -244,9 +354,41 @@ impl WorkloadRequest {
             definition: serde_json::from_str(&workload.definition)?,
             action: match workload.action {
                 1 => WorkloadRequestKind::Destroy,
+                2 => WorkloadRequestKind::Restart,
                 _ => WorkloadRequestKind::Create,
             },
             instance_id: workload.instance_id,
+            correlation_id: workload.correlation_id,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_metric_exposes_queued_placements() {
+        let (channel, _receiver) = tokio::sync::mpsc::channel(1);
+        let mut worker = Worker::new(
+            String::from("worker-1"),
+            channel,
+            "127.0.0.1:4995".parse().unwrap(),
+        );
+        let metric = WorkerMetric {
+            status: 2,
+            metrics: String::from("{}"),
+            conditions: vec![],
+            queued_placements: None,
+        };
+        assert_eq!(
+            worker.status_metric(metric.clone()).queued_placements,
+            Some(0)
+        );
+
+        worker.set_queued_placements(12);
+        let reported = worker.status_metric(metric);
+        assert_eq!(reported.queued_placements, Some(12));
+        assert_eq!(reported.metrics, "{}");
+    }
+}
@@ -4,8 +4,11 @@ mod state_manager;
 
 use crate::config_parser::ConfigParser;
 use crate::grpc::GRPCService;
+use crate::state_manager::placement_limiter::PlacementLimiter;
+use crate::state_manager::rebalancer::{RebalanceMode, Rebalancer};
 use crate::state_manager::{StateManager, StateManagerEvent};
 
+use definition::workload::WorkloadKind;
 use proto::common::worker_status::Status;
 use proto::common::{ResourceStatus, WorkerMetric as WorkerMetricProto, WorkerStatus};
 use proto::controller::controller_server::ControllerServer;
@@ -19,6 +22,7 @@ use tracing_subscriber::{fmt, EnvFilter};
 
 use std::net::{SocketAddr, SocketAddrV4};
 use std::sync::Arc;
+use std::time::Duration;
 use tracing::{debug, error, info, warn};
 
 use tokio::sync::mpsc::{channel, Receiver, Sender};
@@ -37,6 +41,9 @@ impl Manager {
     async fn run(
         workers_listener: SocketAddrV4,
         controllers_listener: SocketAddrV4,
+        placement_limiter: PlacementLimiter,
+        sync_interval: Duration,
+        rebalancer: Rebalancer,
     ) -> Result<Manager, Box<dyn std::error::Error>> {
         let (sender, receiver) = channel::<Event>(1024);
         let (state_sender, receiver_sender) = channel::<StateManagerEvent>(1024);
@@ -49,9 +56,13 @@ impl Manager {
         };
         instance.run_workers_listener(workers_listener, sender.clone());
         instance.run_controllers_listener(controllers_listener, sender.clone());
+        instance.run_sync_timer(sync_interval);
+        if rebalancer.mode() != RebalanceMode::Off {
+            instance.run_rebalance_timer(rebalancer.interval());
+        }
         let workers = instance.workers.clone();
         tokio::spawn(async move {
-            let mut sm = StateManager::new(sender.clone(), workers);
+            let mut sm = StateManager::new(sender.clone(), workers, placement_limiter, rebalancer);
             if let Err(e) = sm.run(receiver_sender).await {
                 error!("StateManager failed, reason: {}", e);
             }
@@ -88,15 +99,73 @@ impl Manager {
         });
     }
 
+    /// Periodically send the workers the complete set of instances they should run
+    fn run_sync_timer(&self, sync_interval: Duration) {
+        let state_manager = self.state_manager.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(sync_interval);
+            // The first tick completes immediately, before any worker registered
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if state_manager
+                    .send(StateManagerEvent::Sync(None))
+                    .await
+                    .is_err()
+                {
+                    error!("StateManager is in failed state, stopping desired state syncs");
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Periodically move instances from the most loaded workers to the least loaded ones
+    fn run_rebalance_timer(&self, rebalance_interval: Duration) {
+        let state_manager = self.state_manager.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(rebalance_interval);
+            // Workers have not registered yet on the first tick
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if state_manager
+                    .send(StateManagerEvent::Rebalance)
+                    .await
+                    .is_err()
+                {
+                    error!("StateManager is in failed state, stopping rebalancing");
+                    return;
+                }
+            }
+        });
+    }
+
     async fn listen(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         while let Some(e) = self.channel.recv().await {
             match e {
-                Event::Register(channel, addr, hostname) => {
-                    if let Err(e) = self.register(channel.clone(), addr, hostname.clone()).await {
+                Event::Register(channel, addr, hostname, max_placements, supported_kinds) => {
+                    if let Err(e) = self
+                        .register(
+                            channel.clone(),
+                            addr,
+                            hostname.clone(),
+                            max_placements,
+                            supported_kinds,
+                        )
+                        .await
+                    {
                         error!(
                             "Failed to register worker {} ({}), reason: {}",
                             hostname, addr, e
                         )
+                    } else if self
+                        .state_manager
+                        .send(StateManagerEvent::Sync(Some(hostname)))
+                        .await
+                        .is_err()
+                    {
+                        error!("StateManager is in failed state, cannot sync registered worker");
                     }
                 }
                 Event::ScheduleRequest(workload) => {
@@ -112,6 +181,12 @@ impl Manager {
                     }
                 }
                 Event::Schedule(worker_id, instance) => {
+                    debug!(
+                        correlation_id = %instance.correlation_id,
+                        "Sending instance {} to worker {}",
+                        instance.instance_id,
+                        worker_id
+                    );
                     if let Some(sender) = self.get_worker_sender(&worker_id).await {
                         if let Err(e) = sender.send(Ok(instance)).await {
                             error!(
@@ -148,6 +223,16 @@ impl Manager {
                             Ok(metric) => worker.set_metrics(metric),
                             Err(e) => warn!("Could not deserialize metrics, error: {}", e),
                         };
+                        if let Some(controller) = &self.controller {
+                            let message = WorkerStatus {
+                                identifier: worker.id.clone(),
+                                status: Some(Status::Worker(worker.status_metric(data))),
+                                host_address: Some(worker.addr.to_string()),
+                            };
+                            if let Err(e) = controller.send(Ok(message)).await {
+                                error!("Failed to send WorkerMetric to controller, reason: {}", e);
+                            }
+                        }
                     } else {
                         warn!(
                             "Received metrics for a unknown worker ({}), ignoring",
@@ -169,10 +254,13 @@ impl Manager {
                         }
                     }
                 }
-                Event::InstanceMetricsUpdate(_, metrics) => {
+                Event::InstanceMetricsUpdate(identifier, metrics) => {
                     if self
                         .state_manager
-                        .send(StateManagerEvent::InstanceUpdate(metrics))
+                        .send(StateManagerEvent::InstanceUpdate(
+                            identifier,
+                            Box::new(metrics),
+                        ))
                         .await
                         .is_err()
                     {
@@ -181,6 +269,16 @@ impl Manager {
                         );
                     }
                 }
+                Event::SyncReport(identifier, report) => {
+                    if self
+                        .state_manager
+                        .send(StateManagerEvent::SyncReport(identifier, report))
+                        .await
+                        .is_err()
+                    {
+                        error!("StateManager is in failed state, cannot forward SyncReport");
+                    }
+                }
                 Event::WorkerMetricsUpdate(identifier, metrics) => {
                     if self
                         .state_manager
@@ -193,6 +291,18 @@ impl Manager {
                         );
                     }
                 }
+                Event::Cordon(identifier, cordoned) => {
+                    let mut workers = self.workers.lock().await;
+                    match workers.iter_mut().find(|worker| worker.id.eq(&*identifier)) {
+                        Some(worker) => {
+                            if worker.is_cordoned() != cordoned {
+                                info!("Worker {} cordoned: {}", identifier, cordoned);
+                            }
+                            worker.set_cordoned(cordoned);
+                        }
+                        None => warn!("Cannot cordon unknown worker {}, ignoring", identifier),
+                    }
+                }
             }
         }
         Ok(())
@@ -217,6 +327,8 @@ impl Manager {
         channel: Sender<WorkerRegisterChannelType>,
         addr: SocketAddr,
         hostname: String,
+        max_placements: Option<u32>,
+        supported_kinds: Vec<WorkloadKind>,
     ) -> Result<(), SchedulerError> {
         let mut workers = self.workers.lock().await;
         if let Some(worker) = workers.iter_mut().find(|worker| worker.id.eq(&*hostname)) {
@@ -234,6 +346,8 @@ impl Manager {
             } else {
                 info!("Worker {} is back ready", hostname);
                 worker.set_channel(channel);
+                worker.set_max_placements(max_placements);
+                worker.set_supported_kinds(supported_kinds);
                 if let Some(controller) = &self.controller {
                     let metrics = match serde_json::to_string(&worker.get_metrics()) {
                         Ok(metric) => Some(metric),
@@ -245,10 +359,17 @@ impl Manager {
                     let worker_metrics = WorkerMetricProto {
                         status: ResourceStatus::Running as i32,
                         metrics: metrics.unwrap_or_default(),
+                        conditions: worker
+                            .get_conditions()
+                            .iter()
+                            .cloned()
+                            .map(Into::into)
+                            .collect(),
+                        queued_placements: None,
                     };
                     let message = WorkerStatus {
                         identifier: worker.id.clone(),
-                        status: Some(Status::Worker(worker_metrics)),
+                        status: Some(Status::Worker(worker.status_metric(worker_metrics))),
                         host_address: Some(worker.addr.to_string()),
                     };
                     match controller.send(Ok(message)).await {
@@ -261,10 +382,14 @@ impl Manager {
                 }
             }
         } else {
-            let worker = Worker::new(hostname, channel, addr);
+            let mut worker = Worker::new(hostname, channel, addr);
+            worker.set_max_placements(max_placements);
+            worker.set_supported_kinds(supported_kinds);
             info!(
-                "Worker {} is now registered, ip: {}",
-                worker.id, worker.addr
+                "Worker {} is now registered, ip: {}, supported kinds: {:?}",
+                worker.id,
+                worker.addr,
+                worker.get_supported_kinds()
             );
             if let Some(controller) = &self.controller {
                 let metrics = match serde_json::to_string(&worker.get_metrics()) {
@@ -277,10 +402,12 @@ impl Manager {
                 let worker_metrics = WorkerMetricProto {
                     status: ResourceStatus::Running as i32,
                     metrics: metrics.unwrap_or_default(),
+                    conditions: vec![],
+                    queued_placements: None,
                 };
                 let message = WorkerStatus {
                     identifier: worker.id.clone(),
-                    status: Some(Status::Worker(worker_metrics)),
+                    status: Some(Status::Worker(worker.status_metric(worker_metrics))),
                     host_address: Some(worker.addr.to_string()),
                 };
                 match controller.send(Ok(message)).await {
@@ -309,7 +436,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         )
         .init();
     info!("Starting up...");
-    let manager = Manager::run(config.workers_endpoint, config.controller_endpoint);
+    let placement_limiter = PlacementLimiter::new(config.max_placements, config.placement_interval);
+    let rebalancer = Rebalancer::new(
+        config.rebalance_mode,
+        config.rebalance_interval,
+        config.rebalance_max_moves,
+        config.rebalance_threshold,
+    );
+    let manager = Manager::run(
+        config.workers_endpoint,
+        config.controller_endpoint,
+        placement_limiter,
+        config.sync_interval,
+        rebalancer,
+    );
     manager.await?;
     Ok(())
 }
@@ -0,0 +1,105 @@
+use definition::workload::WorkloadKind;
+use std::collections::HashMap;
+
+/// Reason given to instances no ready worker can run
+pub const NO_NODE_SUPPORTS_KIND: &str = "NoNodeSupportsKind";
+
+/// Kinds of workloads each worker can run.
+///
+/// Workers that did not advertise any kind at registration are considered
+/// able to run all of them.
+#[derive(Debug, Default)]
+pub struct KindSupport {
+    kinds: HashMap<String, Vec<WorkloadKind>>,
+}
+
+impl KindSupport {
+    pub fn new(kinds: HashMap<String, Vec<WorkloadKind>>) -> KindSupport {
+        KindSupport { kinds }
+    }
+
+    pub fn supports(&self, worker_id: &str, kind: &WorkloadKind) -> bool {
+        match self.kinds.get(worker_id) {
+            Some(kinds) => kinds.is_empty() || kinds.contains(kind),
+            None => true,
+        }
+    }
+
+    /// Next worker of the rotation able to run the given kind.
+    ///
+    /// At most `workers` workers are tried, so a full turn of the rotation is done
+    /// before concluding no worker supports the kind.
+    pub fn next_worker<'a>(
+        &self,
+        rotation: &mut impl Iterator<Item = &'a String>,
+        workers: usize,
+        kind: &WorkloadKind,
+    ) -> Option<String> {
+        rotation
+            .take(workers)
+            .find(|worker_id| self.supports(worker_id, kind))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mixed_workers() -> (Vec<String>, KindSupport) {
+        let workers = vec![
+            "pod-node".to_string(),
+            "function-node".to_string(),
+            "legacy-node".to_string(),
+        ];
+        let support = KindSupport::new(HashMap::from([
+            ("pod-node".to_string(), vec![WorkloadKind::Pod]),
+            ("function-node".to_string(), vec![WorkloadKind::Function]),
+            ("legacy-node".to_string(), vec![]),
+        ]));
+        (workers, support)
+    }
+
+    #[test]
+    fn test_workers_without_kinds_support_all() {
+        let (_, support) = mixed_workers();
+        assert!(support.supports("legacy-node", &WorkloadKind::Pod));
+        assert!(support.supports("legacy-node", &WorkloadKind::Function));
+        assert!(!support.supports("pod-node", &WorkloadKind::Function));
+    }
+
+    #[test]
+    fn test_next_worker_skips_unsupported_kinds() {
+        let (workers, support) = mixed_workers();
+        let mut rotation = workers.iter().cycle();
+
+        let picked: Vec<String> = (0..3)
+            .filter_map(|_| {
+                support.next_worker(&mut rotation, workers.len(), &WorkloadKind::Function)
+            })
+            .collect();
+        assert_eq!(
+            picked,
+            vec!["function-node", "legacy-node", "function-node"]
+        );
+    }
+
+    #[test]
+    fn test_next_worker_without_supporting_worker() {
+        let workers = ["pod-node".to_string()];
+        let support = KindSupport::new(HashMap::from([(
+            "pod-node".to_string(),
+            vec![WorkloadKind::Pod],
+        )]));
+        let mut rotation = workers.iter().cycle();
+
+        assert_eq!(
+            support.next_worker(&mut rotation, workers.len(), &WorkloadKind::Function),
+            None
+        );
+        assert_eq!(
+            support.next_worker(&mut rotation, workers.len(), &WorkloadKind::Pod),
+            Some("pod-node".to_string())
+        );
+    }
+}
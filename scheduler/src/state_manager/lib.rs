@@ -2,6 +2,7 @@ use proto::common::ResourceStatus;
 
 pub fn int_to_resource_status(status: &i32) -> ResourceStatus {
     match status {
+        7 => ResourceStatus::Restarting,
         6 => ResourceStatus::Destroying,
         5 => ResourceStatus::Creating,
         4 => ResourceStatus::Terminated,
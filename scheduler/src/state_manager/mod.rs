@@ -1,25 +1,48 @@
+pub mod kind_support;
 mod lib;
+pub mod placement_limiter;
+pub mod rebalancer;
+pub mod sync;
 
+use crate::state_manager::kind_support::{KindSupport, NO_NODE_SUPPORTS_KIND};
 use crate::state_manager::lib::int_to_resource_status;
-use definition::workload::WorkloadDefinition;
-use proto::common::{InstanceMetric, ResourceStatus, WorkerMetric, WorkloadRequestKind};
+use crate::state_manager::placement_limiter::PlacementLimiter;
+use crate::state_manager::rebalancer::{Move, RebalanceMode, Rebalancer, REBALANCED};
+use crate::state_manager::sync::{desired_states, missing_placements, sync_message};
+use definition::workload::{WorkloadDefinition, WorkloadKind};
+use definition::NodeCondition;
+use proto::common::{
+    ConditionStatus, ConditionType, InstanceCondition, InstanceMetric, ResourceStatus, SyncReport,
+    WorkerMetric, WorkloadRequestKind,
+};
 use proto::worker::InstanceScheduling;
 use rand::seq::IteratorRandom;
 use scheduler::{Event, SchedulerError, Worker, WorkerState, WorkloadRequest};
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::Mutex;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+
+/// Reason given to instances whose volumes are on a worker that is not ready
+const VOLUME_NODE_NOT_READY: &str = "VolumeNodeNotReady";
 
 #[derive(Debug)]
 pub enum StateManagerEvent {
-    Schedule(WorkloadRequest),
+    Schedule(Box<WorkloadRequest>),
     #[allow(dead_code)]
     Shutdown,
-    InstanceUpdate(InstanceMetric),
+    /// Status of an instance, reported by the given worker
+    InstanceUpdate(String, Box<InstanceMetric>),
     WorkerUpdate(String, WorkerMetric),
+    /// Send the desired state to the given worker, or to all ready workers
+    Sync(Option<String>),
+    SyncReport(String, SyncReport),
+    /// Move instances from the most loaded workers to the least loaded ones
+    Rebalance,
 }
 
 impl fmt::Display for StateManagerEvent {
@@ -32,15 +55,24 @@ pub struct StateManager {
     state: HashMap<String, Workload>,
     workers: Arc<Mutex<Vec<Worker>>>,
     manager_channel: Sender<Event>,
+    placement_limiter: PlacementLimiter,
+    rebalancer: Rebalancer,
 }
 
 impl StateManager {
-    pub fn new(manager_channel: Sender<Event>, workers: Arc<Mutex<Vec<Worker>>>) -> StateManager {
+    pub fn new(
+        manager_channel: Sender<Event>,
+        workers: Arc<Mutex<Vec<Worker>>>,
+        placement_limiter: PlacementLimiter,
+        rebalancer: Rebalancer,
+    ) -> StateManager {
         StateManager {
             // We define a mini capacity
             state: HashMap::with_capacity(20),
             manager_channel,
             workers,
+            placement_limiter,
+            rebalancer,
         }
     }
 
@@ -54,20 +86,42 @@ impl StateManager {
                     info!("Shutting down StateManager");
                     return Ok(());
                 }
-                StateManagerEvent::Schedule(workload) => self.process_schedule_request(workload),
-                StateManagerEvent::InstanceUpdate(metrics) => {
-                    let _ = self
-                        .manager_channel
-                        .send(Event::InstanceMetric(
-                            "scheduler".to_string(),
-                            metrics.clone(),
-                        ))
-                        .await;
-                    self.process_instance_update(metrics)
+                StateManagerEvent::Schedule(workload) => {
+                    self.process_schedule_request(*workload).await
+                }
+                StateManagerEvent::InstanceUpdate(worker_id, metrics) => {
+                    if self.is_stale_update(&worker_id, &metrics) {
+                        debug!(
+                            "Ignoring status of instance {} from worker {}, it moved to another worker",
+                            metrics.instance_id, worker_id
+                        );
+                        Ok(())
+                    } else {
+                        let _ = self
+                            .manager_channel
+                            .send(Event::InstanceMetric(
+                                "scheduler".to_string(),
+                                (*metrics).clone(),
+                            ))
+                            .await;
+                        self.process_instance_update(*metrics)
+                    }
                 }
                 StateManagerEvent::WorkerUpdate(identifier, metrics) => {
                     self.process_metric_update(identifier, metrics).await
                 }
+                StateManagerEvent::Sync(worker_id) => {
+                    self.sync_desired_state(worker_id).await;
+                    Ok(())
+                }
+                StateManagerEvent::SyncReport(worker_id, report) => {
+                    self.process_sync_report(worker_id, report).await;
+                    Ok(())
+                }
+                StateManagerEvent::Rebalance => {
+                    self.rebalance().await;
+                    Ok(())
+                }
             };
             self.scan_workers().await;
             self.update_state().await;
@@ -87,15 +141,25 @@ impl StateManager {
             }
         }
 
+        for worker_id in &deactivated_workers {
+            self.placement_limiter.reset(worker_id);
+        }
+
         // In the case we deactivated any worker, we want to reschedule the instances linked to that
         let mut instances_to_delete = Vec::new();
         let instances = self.state.iter_mut();
         {
             for (id, workload) in instances {
-                for (instance_id, instance) in workload.instances.iter() {
+                for (instance_id, instance) in workload.instances.iter_mut() {
                     if let Some(worker_id) = &instance.worker_id {
                         if deactivated_workers.contains(worker_id) {
-                            instances_to_delete.push((id.clone(), instance_id.clone()));
+                            // Placements still queued on the worker were never sent,
+                            // they can go back to the pending queue
+                            if instance.is_pending() {
+                                instance.set_worker(None);
+                            } else {
+                                instances_to_delete.push((id.clone(), instance_id.clone()));
+                            }
                         }
                     }
                 }
@@ -109,6 +173,16 @@ impl StateManager {
         }
     }
 
+    /// Whether a status comes from a worker the instance was moved away from,
+    /// e.g. the termination of the instance evicted by a rebalancing
+    fn is_stale_update(&self, worker_id: &str, metrics: &InstanceMetric) -> bool {
+        self.state
+            .values()
+            .find_map(|workload| workload.instances.get(&metrics.instance_id))
+            .and_then(|instance| instance.worker_id.as_deref())
+            .is_some_and(|current| current != worker_id)
+    }
+
     fn process_instance_update(&mut self, metrics: InstanceMetric) -> Result<(), SchedulerError> {
         debug!(
             "[process_instance_update] Instance {} and received {} status",
@@ -150,18 +224,37 @@ impl StateManager {
         identifier: String,
         metrics: WorkerMetric,
     ) -> Result<(), SchedulerError> {
+        let conditions = metrics
+            .conditions
+            .iter()
+            .cloned()
+            .filter_map(|condition| NodeCondition::try_from(condition).ok())
+            .collect();
+
         let mut lock = self.workers.lock().await;
-        if let Some(worker) = lock.iter_mut().find(|worker| worker.id.eq(&identifier)) {
-            if int_to_resource_status(&metrics.status) == ResourceStatus::Running {
-                worker.set_state(WorkerState::Ready);
+        let conditions_changed =
+            if let Some(worker) = lock.iter_mut().find(|worker| worker.id.eq(&identifier)) {
+                if int_to_resource_status(&metrics.status) == ResourceStatus::Running {
+                    worker.set_state(WorkerState::Ready);
+                } else {
+                    worker.set_state(WorkerState::NotReady);
+                }
+                worker.set_conditions(conditions)
             } else {
-                worker.set_state(WorkerState::NotReady);
-            }
-        } else {
-            error!(
-                "Received metrics for worker {} but could not find registration associated",
-                identifier
-            );
+                error!(
+                    "Received metrics for worker {} but could not find registration associated",
+                    identifier
+                );
+                false
+            };
+        drop(lock);
+
+        // Let the controller know about the new conditions of the worker
+        if conditions_changed {
+            let _ = self
+                .manager_channel
+                .send(Event::WorkerMetric(identifier, metrics))
+                .await;
         }
 
         Ok(())
@@ -175,6 +268,42 @@ impl StateManager {
             return;
         }
 
+        let now = Instant::now();
+        let max_placements = self.get_workers_max_placements().await;
+        let kind_support = KindSupport::new(self.get_workers_supported_kinds().await);
+
+        // Count placements still being created on each worker, and put back
+        // in the queue the instances queued on workers that are not ready anymore
+        let mut in_flight: HashMap<String, u32> = HashMap::new();
+        for workload in self.state.values_mut() {
+            for instance in workload.instances.values_mut() {
+                match &instance.worker_id {
+                    Some(worker_id)
+                        if instance.is_pending() && !ready_workers.contains(worker_id) =>
+                    {
+                        instance.set_worker(None);
+                    }
+                    Some(worker_id) if instance.status == ResourceStatus::Creating => {
+                        *in_flight.entry(worker_id.clone()).or_default() += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut budgets: HashMap<String, u32> = ready_workers
+            .iter()
+            .map(|worker_id| {
+                let budget = self.placement_limiter.available(
+                    worker_id,
+                    max_placements.get(worker_id).copied().flatten(),
+                    in_flight.get(worker_id).copied().unwrap_or(0),
+                    now,
+                );
+                (worker_id.clone(), budget)
+            })
+            .collect();
+
         let mut workers = ready_workers.iter().cycle();
         // Scheduling of new instances
         for (_id, workload) in self.state.iter_mut() {
@@ -188,10 +317,84 @@ impl StateManager {
                 .collect();
 
             for instance in pending_instances {
-                let worker = workers.next().unwrap();
+                // Instances are queued on a worker until it has enough budget
+                // to receive them
+                let worker = match &instance.worker_id {
+                    Some(worker) => worker.clone(),
+                    None => {
+                        let kind = &workload.definition.kind;
+                        let placement = match instance.definition.required_node() {
+                            // Volumes are stored on a single node
+                            Some(node) if ready_workers.iter().any(|worker| worker == node) => {
+                                Ok(node.to_string())
+                            }
+                            Some(node) => Err((
+                                VOLUME_NODE_NOT_READY,
+                                format!("Worker {} holding the volumes is not ready", node),
+                            )),
+                            None => kind_support
+                                .next_worker(&mut workers, ready_workers.len(), kind)
+                                .ok_or_else(|| {
+                                    (
+                                        NO_NODE_SUPPORTS_KIND,
+                                        format!("No ready worker supports {} workloads", kind),
+                                    )
+                                }),
+                        };
+
+                        match placement {
+                            Ok(worker) => {
+                                instance.set_parked(None);
+                                instance.set_worker(Some(worker.clone()));
+                                worker
+                            }
+                            Err((reason, message)) => {
+                                // Stay in the queue until a worker able to run the
+                                // instance is ready, the controller is told only once
+                                if instance.set_parked(Some(reason)) {
+                                    info!("{}, instance {} stays pending", message, instance.id);
+                                    let _ = self
+                                        .manager_channel
+                                        .send(Event::InstanceMetric(
+                                            "scheduler".to_string(),
+                                            InstanceMetric {
+                                                status: ResourceStatus::Pending.into(),
+                                                metrics: format!(
+                                                    "\"workload_id\": \"{}\"",
+                                                    workload.id.clone()
+                                                ),
+                                                instance_id: instance.id.clone(),
+                                                conditions: vec![InstanceCondition {
+                                                    r#type: ConditionType::Scheduled.into(),
+                                                    status: ConditionStatus::False.into(),
+                                                    reason: String::from(reason),
+                                                    message,
+                                                }],
+                                                worker_id: None,
+                                                provenance: None,
+                                                app_status: None,
+                                            },
+                                        ))
+                                        .await;
+                                }
+                                continue;
+                            }
+                        }
+                    }
+                };
 
-                instance.set_worker(Some(worker.clone()));
+                match budgets.get_mut(&worker) {
+                    Some(budget) if *budget > 0 => *budget -= 1,
+                    _ => continue,
+                }
+                self.placement_limiter.record(&worker, now);
                 instance.set_status(ResourceStatus::Creating);
+                info!(
+                    correlation_id = %instance.correlation_id,
+                    "Placing instance {} on worker {}",
+                    instance.id,
+                    worker
+                );
 
                 let _ = self
                     .manager_channel
@@ -202,6 +405,8 @@ impl StateManager {
                             action: WorkloadRequestKind::Create as i32,
                             definition: serde_json::to_string(&instance.definition.clone())
                                 .unwrap(),
+                            desired_state: None,
+                            correlation_id: instance.correlation_id.clone(),
                         },
                     ))
                     .await;
@@ -213,6 +418,15 @@ impl StateManager {
                             status: ResourceStatus::Creating.into(),
                             metrics: format!("\"workload_id\": \"{}\"", workload.id.clone()),
                             instance_id: instance.id.clone(),
+                            conditions: vec![InstanceCondition {
+                                r#type: ConditionType::Scheduled.into(),
+                                status: ConditionStatus::True.into(),
+                                reason: String::from("Placed"),
+                                message: format!("Instance placed on worker {}", worker),
+                            }],
+                            worker_id: Some(worker.clone()),
+                            provenance: None,
+                            app_status: None,
                         },
                     ))
                     .await;
@@ -234,7 +448,11 @@ impl StateManager {
                 // as if we keep the destroying state, it will loop here and spam riklet of events
                 instance.is_destroying = true;
 
-                info!("Deleting instance {}", instance.id.clone());
+                info!(
+                    correlation_id = %instance.correlation_id,
+                    "Deleting instance {}",
+                    instance.id.clone()
+                );
 
                 let _ = self
                     .manager_channel
@@ -245,6 +463,8 @@ impl StateManager {
                             action: WorkloadRequestKind::Destroy as i32,
                             definition: serde_json::to_string(&instance.definition.clone())
                                 .unwrap(),
+                            desired_state: None,
+                            correlation_id: instance.correlation_id.clone(),
                         },
                     ))
                     .await;
@@ -256,12 +476,23 @@ impl StateManager {
                             status: ResourceStatus::Destroying.into(),
                             metrics: format!("\"workload_id\": \"{}\"", workload.id.clone()),
                             instance_id: instance.id.clone(),
+                            conditions: vec![InstanceCondition {
+                                r#type: ConditionType::Terminating.into(),
+                                status: ConditionStatus::True.into(),
+                                reason: String::from("DeletionRequested"),
+                                message: String::new(),
+                            }],
+                            worker_id: None,
+                            provenance: None,
+                            app_status: None,
                         },
                     ))
                     .await;
             }
         }
 
+        self.update_queued_placements().await;
+
         let mut to_be_deleted = Vec::new();
         for key in self.state.keys().clone() {
             if let Some(workload) = self.state.get(key) {
@@ -277,7 +508,134 @@ impl StateManager {
         }
     }
 
-    fn process_schedule_request(&mut self, request: WorkloadRequest) -> Result<(), SchedulerError> {
+    /// Send the complete set of instances they should be running to the workers,
+    /// as a safety net for scheduling messages lost on the way
+    async fn sync_desired_state(&self, worker_id: Option<String>) {
+        let workers = match worker_id {
+            Some(worker_id) => vec![worker_id],
+            None => {
+                let workers = self.workers.lock().await;
+                workers
+                    .iter()
+                    .filter(|worker| worker.is_ready())
+                    .map(|worker| worker.id.clone())
+                    .collect()
+            }
+        };
+
+        for (worker_id, desired_state) in desired_states(&self.state, &workers) {
+            debug!(
+                "Syncing {} desired instances to worker {}",
+                desired_state.instances.len(),
+                worker_id
+            );
+            let _ = self
+                .manager_channel
+                .send(Event::Schedule(worker_id, sync_message(desired_state)))
+                .await;
+        }
+    }
+
+    /// Send again the instances a worker reported missing after a sync
+    async fn process_sync_report(&self, worker_id: String, report: SyncReport) {
+        if !report.stopped_instances.is_empty() {
+            info!(
+                "Worker {} stopped undesired instances {:?}",
+                worker_id, report.stopped_instances
+            );
+        }
+
+        for placement in missing_placements(&self.state, &worker_id, &report.missing_instances) {
+            warn!(
+                "Worker {} misses instance {}, sending it again",
+                worker_id, placement.instance_id
+            );
+            let _ = self
+                .manager_channel
+                .send(Event::Schedule(worker_id.clone(), placement))
+                .await;
+        }
+    }
+
+    /// Move instances to less loaded workers, or only log the moves when simulating
+    async fn rebalance(&mut self) {
+        let workers = self.get_workers_ready().await;
+        let kind_support = KindSupport::new(self.get_workers_supported_kinds().await);
+        let moves = self.rebalancer.plan(&self.state, &workers, &kind_support);
+        if moves.is_empty() {
+            debug!("Rebalancing found no instance to move");
+        }
+
+        for instance_move in moves {
+            match self.rebalancer.mode() {
+                RebalanceMode::Simulate => info!("Rebalancing would move {}", instance_move),
+                RebalanceMode::On => self.move_instance(instance_move).await,
+                RebalanceMode::Off => {}
+            }
+        }
+    }
+
+    /// Evict an instance from its worker and queue it on another one, where it
+    /// is placed once the worker has the budget for it
+    async fn move_instance(&mut self, instance_move: Move) {
+        let Some(instance) = self
+            .state
+            .get_mut(&instance_move.workload_id)
+            .and_then(|workload| workload.instances.get_mut(&instance_move.instance_id))
+        else {
+            return;
+        };
+        info!(
+            correlation_id = %instance.correlation_id,
+            "Rebalancing, moving {}",
+            instance_move
+        );
+        instance.set_status(ResourceStatus::Pending);
+        instance.set_parked(None);
+        instance.set_worker(Some(instance_move.to.clone()));
+
+        let _ = self
+            .manager_channel
+            .send(Event::Schedule(
+                instance_move.from.clone(),
+                InstanceScheduling {
+                    instance_id: instance.id.clone(),
+                    action: WorkloadRequestKind::Destroy as i32,
+                    definition: serde_json::to_string(&instance.definition).unwrap(),
+                    desired_state: None,
+                    correlation_id: instance.correlation_id.clone(),
+                },
+            ))
+            .await;
+        let _ = self
+            .manager_channel
+            .send(Event::InstanceMetric(
+                "scheduler".to_string(),
+                InstanceMetric {
+                    status: ResourceStatus::Pending.into(),
+                    metrics: format!("\"workload_id\": \"{}\"", instance_move.workload_id),
+                    instance_id: instance.id.clone(),
+                    conditions: vec![InstanceCondition {
+                        r#type: ConditionType::Scheduled.into(),
+                        status: ConditionStatus::False.into(),
+                        reason: String::from(REBALANCED),
+                        message: format!(
+                            "Moved from worker {} to worker {}",
+                            instance_move.from, instance_move.to
+                        ),
+                    }],
+                    worker_id: None,
+                    provenance: None,
+                    app_status: None,
+                },
+            ))
+            .await;
+    }
+
+    async fn process_schedule_request(
+        &mut self,
+        request: WorkloadRequest,
+    ) -> Result<(), SchedulerError> {
         debug!(
             "[process_schedule_request] Received workload id {}, action: {:#?}",
             request.workload_id, request.action
@@ -286,20 +644,26 @@ impl StateManager {
         match request.action {
             WorkloadRequestKind::Create => self.action_create_workload(request),
             WorkloadRequestKind::Destroy => self.action_destroy_instance(request),
+            WorkloadRequestKind::Restart => self.action_restart_instance(request).await,
         }
     }
 
     #[tracing::instrument(
         skip(self),
-        fields(workload_id = %request.workload_id, instance_id = %request.instance_id),
+        fields(
+            workload_id = %request.workload_id,
+            instance_id = %request.instance_id,
+            correlation_id = %request.correlation_id,
+        ),
     )]
     fn action_create_workload(&mut self, request: WorkloadRequest) -> Result<(), SchedulerError> {
-        let instance = WorkloadInstance::new(
+        let mut instance = WorkloadInstance::new(
             request.instance_id.clone(),
             ResourceStatus::Pending,
             None,
             request.definition.clone(),
         );
+        instance.set_correlation_id(request.correlation_id.clone());
         if let Some(workload) = self.state.get_mut(&request.workload_id) {
             if workload.status == ResourceStatus::Destroying {
                 error!("Cannot double replicas while workload is being destroyed");
@@ -372,7 +736,11 @@ impl StateManager {
 
     #[tracing::instrument(
         skip(self),
-        fields(workload_id = %request.workload_id, instance_id = %request.instance_id),
+        fields(
+            workload_id = %request.workload_id,
+            instance_id = %request.instance_id,
+            correlation_id = %request.correlation_id,
+        ),
     )]
     fn action_destroy_instance(&mut self, request: WorkloadRequest) -> Result<(), SchedulerError> {
         let workload = self.state.get_mut(&request.workload_id);
@@ -407,6 +775,7 @@ impl StateManager {
 
         let instance = instance.unwrap();
         instance.set_status(ResourceStatus::Destroying);
+        instance.set_correlation_id(request.correlation_id.clone());
 
         if workload.replicas > *def_replicas {
             self.action_minus_replicas(&request.workload_id, def_replicas)?;
@@ -424,6 +793,60 @@ impl StateManager {
         Ok(())
     }
 
+    /// Forward the restart of an instance to the worker it is placed on, which
+    /// keeps its id. An instance not placed yet boots once it is.
+    #[tracing::instrument(
+        skip(self),
+        fields(
+            workload_id = %request.workload_id,
+            instance_id = %request.instance_id,
+            correlation_id = %request.correlation_id,
+        ),
+    )]
+    async fn action_restart_instance(
+        &mut self,
+        request: WorkloadRequest,
+    ) -> Result<(), SchedulerError> {
+        let Some(instance) = self
+            .state
+            .get_mut(&request.workload_id)
+            .and_then(|workload| workload.instances.get_mut(&request.instance_id))
+        else {
+            error!(
+                "Requested instance {} for workload {} is not known",
+                request.instance_id, request.workload_id
+            );
+            return Err(SchedulerError::InstanceNotExisting(request.instance_id));
+        };
+        instance.set_correlation_id(request.correlation_id.clone());
+        let Some(worker_id) = instance.worker_id.clone() else {
+            info!(
+                "Instance {} is not placed yet, nothing to restart",
+                instance.id
+            );
+            return Ok(());
+        };
+
+        info!(
+            "Restarting instance {} on worker {}",
+            instance.id, worker_id
+        );
+        let _ = self
+            .manager_channel
+            .send(Event::Schedule(
+                worker_id,
+                InstanceScheduling {
+                    instance_id: instance.id.clone(),
+                    action: WorkloadRequestKind::Restart as i32,
+                    definition: serde_json::to_string(&instance.definition).unwrap(),
+                    desired_state: None,
+                    correlation_id: instance.correlation_id.clone(),
+                },
+            ))
+            .await;
+        Ok(())
+    }
+
     #[allow(dead_code)]
     async fn get_eligible_worker(&self) -> Option<String> {
         let workers = self.workers.lock().await;
@@ -436,11 +859,54 @@ impl StateManager {
         None
     }
 
+    async fn get_workers_supported_kinds(&self) -> HashMap<String, Vec<WorkloadKind>> {
+        let workers = self.workers.lock().await;
+        workers
+            .iter()
+            .map(|worker| (worker.id.clone(), worker.get_supported_kinds().clone()))
+            .collect()
+    }
+
+    async fn get_workers_max_placements(&self) -> HashMap<String, Option<u32>> {
+        let workers = self.workers.lock().await;
+        workers
+            .iter()
+            .map(|worker| (worker.id.clone(), worker.get_max_placements()))
+            .collect()
+    }
+
+    /// Report on each worker the amount of placements waiting for its budget
+    async fn update_queued_placements(&self) {
+        let mut queued: HashMap<&String, usize> = HashMap::new();
+        for workload in self.state.values() {
+            for instance in workload.instances.values() {
+                if let Some(worker_id) = instance.worker_id.as_ref() {
+                    if instance.is_pending() {
+                        *queued.entry(worker_id).or_default() += 1;
+                    }
+                }
+            }
+        }
+
+        let mut workers = self.workers.lock().await;
+        for worker in workers.iter_mut() {
+            let count = queued.get(&worker.id).copied().unwrap_or(0);
+            if count > 0 {
+                debug!(
+                    worker_id = %worker.id,
+                    queued_placements = count,
+                    "Placements queued on worker"
+                );
+            }
+            worker.set_queued_placements(count);
+        }
+    }
+
     async fn get_workers_ready(&self) -> Vec<String> {
         let workers = self.workers.lock().await;
         workers
             .iter()
-            .filter(|worker| worker.is_ready())
+            .filter(|worker| worker.is_ready() && worker.is_schedulable())
             .map(|worker| worker.id.clone())
             .collect()
     }
@@ -468,6 +934,10 @@ pub struct WorkloadInstance {
     definition: WorkloadDefinition,
     /// Flag to indicate that this instance is being destroyed
     is_destroying: bool,
+    /// Reason why the instance waits in the queue although workers are ready
+    parked: Option<&'static str>,
+    /// Identifier of the last operation on the instance, given by the controller
+    correlation_id: String,
 }
 
 impl WorkloadInstance {
@@ -483,9 +953,15 @@ impl WorkloadInstance {
             worker_id,
             definition,
             is_destroying: false,
+            parked: None,
+            correlation_id: String::new(),
         }
     }
 
+    pub fn set_correlation_id(&mut self, correlation_id: String) {
+        self.correlation_id = correlation_id;
+    }
+
     pub fn set_worker(&mut self, worker: Option<String>) {
         debug!(
             "WorkloadInstance {} was assigned to worker {}",
@@ -495,6 +971,13 @@ impl WorkloadInstance {
         self.worker_id = worker;
     }
 
+    /// Update why the instance is parked, returns whether it changed
+    pub fn set_parked(&mut self, parked: Option<&'static str>) -> bool {
+        let changed = self.parked != parked;
+        self.parked = parked;
+        changed
+    }
+
     pub fn is_pending(&self) -> bool {
         self.status == ResourceStatus::Pending
     }
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Default amount of placements a worker can receive per interval
+pub const DEFAULT_MAX_PLACEMENTS: u32 = 10;
+/// Default length of a placement interval
+pub const DEFAULT_PLACEMENT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Smooth placement bursts on workers.
+///
+/// A worker cannot receive more than `max_placements` new instances per `interval`,
+/// and cannot have more than `max_placements` instances still being created. The
+/// remaining instances stay queued on the worker until earlier placements report
+/// a Running or Failed status.
+#[derive(Debug)]
+pub struct PlacementLimiter {
+    max_placements: u32,
+    interval: Duration,
+    /// Start of the current interval and the amount of placements sent during it,
+    /// per worker
+    windows: HashMap<String, (Instant, u32)>,
+}
+
+impl PlacementLimiter {
+    pub fn new(max_placements: u32, interval: Duration) -> PlacementLimiter {
+        PlacementLimiter {
+            max_placements,
+            interval,
+            windows: HashMap::new(),
+        }
+    }
+
+    /// Amount of placements that can still be sent to a worker right now.
+    ///
+    /// `advertised` is the limit given by the worker at registration, if any, and
+    /// `in_flight` the amount of instances the worker is currently creating.
+    pub fn available(
+        &mut self,
+        worker_id: &str,
+        advertised: Option<u32>,
+        in_flight: u32,
+        now: Instant,
+    ) -> u32 {
+        let max_placements = advertised.unwrap_or(self.max_placements);
+        let sent = match self.windows.get(worker_id) {
+            Some((start, sent)) if now.duration_since(*start) < self.interval => *sent,
+            _ => 0,
+        };
+        max_placements
+            .saturating_sub(sent)
+            .min(max_placements.saturating_sub(in_flight))
+    }
+
+    /// Record a placement sent to a worker
+    pub fn record(&mut self, worker_id: &str, now: Instant) {
+        let window = self
+            .windows
+            .entry(worker_id.to_string())
+            .or_insert((now, 0));
+        if now.duration_since(window.0) >= self.interval {
+            *window = (now, 0);
+        }
+        window.1 += 1;
+    }
+
+    /// Forget about a worker, e.g. when it is not ready anymore
+    pub fn reset(&mut self, worker_id: &str) {
+        self.windows.remove(worker_id);
+    }
+}
+
+impl Default for PlacementLimiter {
+    fn default() -> Self {
+        PlacementLimiter::new(DEFAULT_MAX_PLACEMENTS, DEFAULT_PLACEMENT_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_placements_per_interval() {
+        let mut limiter = PlacementLimiter::new(2, Duration::from_secs(10));
+        let now = Instant::now();
+
+        assert_eq!(limiter.available("worker", None, 0, now), 2);
+        limiter.record("worker", now);
+        limiter.record("worker", now);
+        assert_eq!(limiter.available("worker", None, 0, now), 0);
+
+        // A new interval gives back the whole budget
+        let later = now + Duration::from_secs(10);
+        assert_eq!(limiter.available("worker", None, 0, later), 2);
+    }
+
+    #[test]
+    fn test_limit_in_flight_placements() {
+        let mut limiter = PlacementLimiter::new(3, Duration::from_secs(10));
+        let now = Instant::now();
+
+        assert_eq!(limiter.available("worker", None, 2, now), 1);
+        assert_eq!(limiter.available("worker", None, 5, now), 0);
+    }
+
+    #[test]
+    fn test_advertised_limit_overrides_default() {
+        let mut limiter = PlacementLimiter::new(10, Duration::from_secs(10));
+        let now = Instant::now();
+
+        assert_eq!(limiter.available("worker", Some(1), 0, now), 1);
+        limiter.record("worker", now);
+        assert_eq!(limiter.available("worker", Some(1), 0, now), 0);
+        assert_eq!(limiter.available("other", Some(1), 0, now), 1);
+    }
+
+    #[test]
+    fn test_reset_worker() {
+        let mut limiter = PlacementLimiter::new(1, Duration::from_secs(10));
+        let now = Instant::now();
+
+        limiter.record("worker", now);
+        assert_eq!(limiter.available("worker", None, 0, now), 0);
+        limiter.reset("worker");
+        assert_eq!(limiter.available("worker", None, 0, now), 1);
+    }
+}
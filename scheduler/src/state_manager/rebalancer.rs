@@ -0,0 +1,379 @@
+use crate::state_manager::kind_support::KindSupport;
+use crate::state_manager::Workload;
+use proto::common::ResourceStatus;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Default interval between two rebalancing passes
+pub const DEFAULT_REBALANCE_INTERVAL: Duration = Duration::from_secs(300);
+/// Default amount of instances moved per pass
+pub const DEFAULT_REBALANCE_MAX_MOVES: u32 = 1;
+/// Default decrease of the variance of the instances per worker a move must bring
+pub const DEFAULT_REBALANCE_THRESHOLD: f64 = 0.5;
+
+/// Reason of the condition given to the instances moved to another worker
+pub const REBALANCED: &str = "Rebalanced";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RebalanceMode {
+    Off,
+    /// Proposed moves are logged, no instance is moved
+    Simulate,
+    On,
+}
+
+impl FromStr for RebalanceMode {
+    type Err = ();
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "off" => Ok(RebalanceMode::Off),
+            "simulate" => Ok(RebalanceMode::Simulate),
+            "on" => Ok(RebalanceMode::On),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Instance to evict from a worker and to place on another one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Move {
+    pub workload_id: String,
+    pub instance_id: String,
+    pub from: String,
+    pub to: String,
+    /// Variance of the instances per worker before and after the move
+    pub variance_before: f64,
+    pub variance_after: f64,
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "instance {} of workload {} from worker {} to worker {}, variance {:.2} -> {:.2}",
+            self.instance_id,
+            self.workload_id,
+            self.from,
+            self.to,
+            self.variance_before,
+            self.variance_after
+        )
+    }
+}
+
+/// Spread the instances of workloads opting in over the workers, e.g. once
+/// new workers joined the cluster.
+///
+/// The utilization of a worker is the amount of instances placed on it. A
+/// workload keeps at least `min_ready_replicas` running instances, all of
+/// them but one by default, so its instances are moved one at a time.
+#[derive(Debug, Clone)]
+pub struct Rebalancer {
+    mode: RebalanceMode,
+    interval: Duration,
+    max_moves: u32,
+    threshold: f64,
+}
+
+impl Rebalancer {
+    pub fn new(mode: RebalanceMode, interval: Duration, max_moves: u32, threshold: f64) -> Self {
+        Rebalancer {
+            mode,
+            interval,
+            max_moves,
+            threshold,
+        }
+    }
+
+    pub fn mode(&self) -> RebalanceMode {
+        self.mode
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Moves to do now, the ones decreasing the variance the most first.
+    ///
+    /// `workers` are the workers able to receive new instances, instances
+    /// placed elsewhere are left alone.
+    pub fn plan(
+        &self,
+        state: &HashMap<String, Workload>,
+        workers: &[String],
+        kind_support: &KindSupport,
+    ) -> Vec<Move> {
+        let mut workers = workers.to_vec();
+        workers.sort();
+        let mut loads: HashMap<&str, i64> = workers
+            .iter()
+            .map(|worker_id| (worker_id.as_str(), 0))
+            .collect();
+        for instance in state
+            .values()
+            .flat_map(|workload| workload.instances.values())
+        {
+            let placed = matches!(
+                instance.status,
+                ResourceStatus::Pending | ResourceStatus::Creating | ResourceStatus::Running
+            );
+            if let Some(load) = instance
+                .worker_id
+                .as_deref()
+                .filter(|_| placed)
+                .and_then(|worker_id| loads.get_mut(worker_id))
+            {
+                *load += 1;
+            }
+        }
+
+        let mut disruptions: HashMap<&str, usize> = state
+            .values()
+            .filter(|workload| workload.definition.rebalanceable)
+            .map(|workload| (workload.id.as_str(), disruption_budget(workload)))
+            .collect();
+        let mut candidates: Vec<(&Workload, &str, &str)> = state
+            .values()
+            .filter(|workload| {
+                workload.definition.rebalanceable && workload.status != ResourceStatus::Destroying
+            })
+            .flat_map(|workload| {
+                workload
+                    .instances
+                    .values()
+                    .filter(|instance| instance.status == ResourceStatus::Running)
+                    // Volumes are stored on a single node
+                    .filter(|instance| instance.definition.required_node().is_none())
+                    .filter_map(move |instance| {
+                        let worker_id = instance.worker_id.as_deref()?;
+                        Some((workload, instance.id.as_str(), worker_id))
+                    })
+            })
+            .filter(|(_, _, worker_id)| loads.contains_key(worker_id))
+            .collect();
+        candidates.sort_by_key(|(workload, instance_id, _)| (workload.id.as_str(), *instance_id));
+
+        let mut moves = Vec::new();
+        let mut moved = HashSet::new();
+        while moves.len() < self.max_moves as usize {
+            let mut best: Option<(i64, &Workload, &str, &str, &str)> = None;
+            for &(workload, instance_id, from) in &candidates {
+                if moved.contains(instance_id) || disruptions[workload.id.as_str()] == 0 {
+                    continue;
+                }
+                for to in workers.iter().map(String::as_str) {
+                    if to == from || !kind_support.supports(to, &workload.definition.kind) {
+                        continue;
+                    }
+                    let gain = loads[from] - loads[to];
+                    if best.is_none_or(|(best_gain, ..)| gain > best_gain) {
+                        best = Some((gain, workload, instance_id, from, to));
+                    }
+                }
+            }
+            // Moving to a worker with a single instance less only swaps them
+            let Some((_, workload, instance_id, from, to)) = best.filter(|best| best.0 > 1) else {
+                break;
+            };
+
+            let variance_before = variance(&loads);
+            *loads.get_mut(from).unwrap() -= 1;
+            *loads.get_mut(to).unwrap() += 1;
+            let variance_after = variance(&loads);
+            if variance_before - variance_after < self.threshold {
+                break;
+            }
+            moved.insert(instance_id);
+            *disruptions.get_mut(workload.id.as_str()).unwrap() -= 1;
+            moves.push(Move {
+                workload_id: workload.id.clone(),
+                instance_id: instance_id.to_string(),
+                from: from.to_string(),
+                to: to.to_string(),
+                variance_before,
+                variance_after,
+            });
+        }
+        moves
+    }
+}
+
+/// Running instances of a workload that can be evicted now
+fn disruption_budget(workload: &Workload) -> usize {
+    let desired = workload
+        .instances
+        .values()
+        .filter(|instance| instance.status != ResourceStatus::Destroying)
+        .count();
+    let running = workload
+        .instances
+        .values()
+        .filter(|instance| instance.status == ResourceStatus::Running)
+        .count();
+    let min_ready = workload
+        .definition
+        .min_ready_replicas
+        .map(usize::from)
+        .unwrap_or_else(|| desired.saturating_sub(1));
+    running.saturating_sub(min_ready)
+}
+
+fn variance(loads: &HashMap<&str, i64>) -> f64 {
+    if loads.is_empty() {
+        return 0.0;
+    }
+    let count = loads.len() as f64;
+    let mean = loads.values().sum::<i64>() as f64 / count;
+    loads
+        .values()
+        .map(|load| (*load as f64 - mean).powi(2))
+        .sum::<f64>()
+        / count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_manager::WorkloadInstance;
+    use definition::workload::{WorkloadDefinition, WorkloadKind};
+
+    fn workload(id: &str, rebalanceable: bool, instances: &[(&str, &str)]) -> Workload {
+        let mut definition: WorkloadDefinition = serde_json::from_str(
+            r#"{"apiVersion": "v0", "kind": "Pod", "name": "nginx", "spec": {}, "replicas": 1}"#,
+        )
+        .unwrap();
+        definition.rebalanceable = rebalanceable;
+        Workload {
+            replicas: instances.len() as u16,
+            definition: definition.clone(),
+            instances: instances
+                .iter()
+                .map(|(instance_id, worker_id)| {
+                    let instance = WorkloadInstance::new(
+                        instance_id.to_string(),
+                        ResourceStatus::Running,
+                        Some(worker_id.to_string()),
+                        definition.clone(),
+                    );
+                    (instance.id.clone(), instance)
+                })
+                .collect(),
+            status: ResourceStatus::Running,
+            id: id.to_string(),
+        }
+    }
+
+    fn state(workloads: Vec<Workload>) -> HashMap<String, Workload> {
+        workloads
+            .into_iter()
+            .map(|workload| (workload.id.clone(), workload))
+            .collect()
+    }
+
+    fn workers(workers: &[&str]) -> Vec<String> {
+        workers.iter().map(|worker| worker.to_string()).collect()
+    }
+
+    fn rebalancer(max_moves: u32) -> Rebalancer {
+        Rebalancer::new(
+            RebalanceMode::On,
+            DEFAULT_REBALANCE_INTERVAL,
+            max_moves,
+            DEFAULT_REBALANCE_THRESHOLD,
+        )
+    }
+
+    #[test]
+    fn test_move_to_new_worker() {
+        let state = state(vec![
+            workload("web", true, &[("web-1", "old"), ("web-2", "old")]),
+            workload("api", true, &[("api-1", "old"), ("api-2", "old")]),
+        ]);
+        let moves = rebalancer(5).plan(&state, &workers(&["old", "new"]), &KindSupport::default());
+
+        // One instance per workload, as the others must stay running
+        let moved: Vec<(&str, &str, &str)> = moves
+            .iter()
+            .map(|m| (m.instance_id.as_str(), m.from.as_str(), m.to.as_str()))
+            .collect();
+        assert_eq!(
+            moved,
+            vec![("api-1", "old", "new"), ("web-1", "old", "new")]
+        );
+        assert_eq!(moves[0].variance_before, 4.0);
+        assert_eq!(moves[1].variance_after, 0.0);
+    }
+
+    #[test]
+    fn test_max_moves_and_threshold() {
+        let state = state(vec![
+            workload("web", true, &[("web-1", "old")]),
+            workload("api", true, &[("api-1", "old")]),
+            workload("db", true, &[("db-1", "old")]),
+        ]);
+        let workers = workers(&["old", "new"]);
+
+        assert_eq!(
+            rebalancer(1)
+                .plan(&state, &workers, &KindSupport::default())
+                .len(),
+            1
+        );
+        // Once at 2 and 1 instances, another move would only swap the workers
+        assert_eq!(
+            rebalancer(5)
+                .plan(&state, &workers, &KindSupport::default())
+                .len(),
+            1
+        );
+        let strict = Rebalancer::new(RebalanceMode::On, DEFAULT_REBALANCE_INTERVAL, 5, 10.0);
+        assert!(strict
+            .plan(&state, &workers, &KindSupport::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_disruption_limits() {
+        let mut pinned = workload("web", true, &[("web-1", "old"), ("web-2", "old")]);
+        pinned.definition.min_ready_replicas = Some(2);
+        let mut degraded = workload("api", true, &[("api-1", "old"), ("api-2", "old")]);
+        degraded
+            .instances
+            .get_mut("api-2")
+            .unwrap()
+            .set_status(ResourceStatus::Creating);
+        let state = state(vec![
+            pinned,
+            degraded,
+            workload("db", false, &[("db-1", "old"), ("db-2", "old")]),
+        ]);
+
+        assert!(rebalancer(5)
+            .plan(&state, &workers(&["old", "new"]), &KindSupport::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_moves_only_to_workers_supporting_kind() {
+        let state = state(vec![workload(
+            "web",
+            true,
+            &[("web-1", "old"), ("web-2", "old")],
+        )]);
+        let kind_support = KindSupport::new(HashMap::from([
+            ("functions".to_string(), vec![WorkloadKind::Function]),
+            ("pods".to_string(), vec![WorkloadKind::Pod]),
+        ]));
+        let moves = rebalancer(1).plan(
+            &state,
+            &workers(&["old", "functions", "pods"]),
+            &kind_support,
+        );
+
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].to, "pods");
+    }
+}
@@ -0,0 +1,188 @@
+use crate::state_manager::{Workload, WorkloadInstance};
+use proto::common::{ResourceStatus, WorkloadRequestKind};
+use proto::definition_hash;
+use proto::worker::{DesiredInstance, DesiredState, InstanceScheduling};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Default interval between two syncs of the desired state to the workers
+pub const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Whether an instance was sent to its worker and should be running there
+fn is_desired(instance: &WorkloadInstance) -> bool {
+    instance.worker_id.is_some()
+        && matches!(
+            instance.status,
+            ResourceStatus::Creating | ResourceStatus::Running
+        )
+}
+
+fn instance_definition(instance: &WorkloadInstance) -> String {
+    serde_json::to_string(&instance.definition).unwrap()
+}
+
+/// Instances each of the given workers should be running
+pub fn desired_states(
+    state: &HashMap<String, Workload>,
+    workers: &[String],
+) -> HashMap<String, DesiredState> {
+    let mut desired: HashMap<String, DesiredState> = workers
+        .iter()
+        .map(|worker_id| (worker_id.clone(), DesiredState::default()))
+        .collect();
+
+    for instance in state
+        .values()
+        .flat_map(|workload| workload.instances.values())
+        .filter(|instance| is_desired(instance))
+    {
+        if let Some(desired_state) = desired.get_mut(instance.worker_id.as_ref().unwrap()) {
+            desired_state.instances.push(DesiredInstance {
+                instance_id: instance.id.clone(),
+                definition_hash: definition_hash(&instance_definition(instance)),
+            });
+        }
+    }
+
+    for desired_state in desired.values_mut() {
+        desired_state
+            .instances
+            .sort_by(|a, b| a.instance_id.cmp(&b.instance_id));
+    }
+    desired
+}
+
+/// Message carrying the desired state of a worker
+pub fn sync_message(desired_state: DesiredState) -> InstanceScheduling {
+    InstanceScheduling {
+        instance_id: String::new(),
+        definition: String::new(),
+        action: WorkloadRequestKind::Create as i32,
+        desired_state: Some(desired_state),
+        correlation_id: String::new(),
+    }
+}
+
+/// Creations to send again for the instances a worker reported missing.
+///
+/// Instances that moved to another worker or are not desired anymore since the
+/// sync was sent are skipped.
+pub fn missing_placements(
+    state: &HashMap<String, Workload>,
+    worker_id: &str,
+    missing_instances: &[String],
+) -> Vec<InstanceScheduling> {
+    state
+        .values()
+        .flat_map(|workload| workload.instances.values())
+        .filter(|instance| {
+            missing_instances.contains(&instance.id)
+                && is_desired(instance)
+                && instance.worker_id.as_deref() == Some(worker_id)
+        })
+        .map(|instance| InstanceScheduling {
+            instance_id: instance.id.clone(),
+            definition: instance_definition(instance),
+            action: WorkloadRequestKind::Create as i32,
+            desired_state: None,
+            correlation_id: instance.correlation_id.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definition::workload::WorkloadDefinition;
+
+    fn instance(id: &str, worker_id: Option<&str>, status: ResourceStatus) -> WorkloadInstance {
+        let definition: WorkloadDefinition = serde_json::from_str(
+            r#"{"apiVersion": "v0", "kind": "Pod", "name": "nginx", "spec": {}, "replicas": 2}"#,
+        )
+        .unwrap();
+        WorkloadInstance::new(
+            id.to_string(),
+            status,
+            worker_id.map(String::from),
+            definition,
+        )
+    }
+
+    fn state() -> HashMap<String, Workload> {
+        let instances = vec![
+            instance("running", Some("node-1"), ResourceStatus::Running),
+            instance("creating", Some("node-1"), ResourceStatus::Creating),
+            instance("queued", Some("node-1"), ResourceStatus::Pending),
+            instance("destroying", Some("node-1"), ResourceStatus::Destroying),
+            instance("elsewhere", Some("node-2"), ResourceStatus::Running),
+        ];
+        let workload = Workload {
+            replicas: 5,
+            definition: instances[0].definition.clone(),
+            instances: instances
+                .into_iter()
+                .map(|instance| (instance.id.clone(), instance))
+                .collect(),
+            status: ResourceStatus::Running,
+            id: "workload".to_string(),
+        };
+        HashMap::from([(workload.id.clone(), workload)])
+    }
+
+    #[test]
+    fn test_desired_states_only_hold_sent_instances() {
+        let desired = desired_states(&state(), &["node-1".to_string(), "node-3".to_string()]);
+
+        let node_1: Vec<&str> = desired["node-1"]
+            .instances
+            .iter()
+            .map(|instance| instance.instance_id.as_str())
+            .collect();
+        assert_eq!(node_1, vec!["creating", "running"]);
+        assert!(desired["node-3"].instances.is_empty());
+        assert!(!desired.contains_key("node-2"));
+    }
+
+    #[test]
+    fn test_definition_hash_matches_sent_definition() {
+        let state = state();
+        let desired = desired_states(&state, &["node-1".to_string()]);
+        let placements = missing_placements(&state, "node-1", &["running".to_string()]);
+
+        assert_eq!(
+            desired["node-1"].instances[1].definition_hash,
+            definition_hash(&placements[0].definition)
+        );
+    }
+
+    #[test]
+    fn test_missing_placements_skip_instances_not_desired_on_worker() {
+        let missing = vec![
+            "running".to_string(),
+            "queued".to_string(),
+            "destroying".to_string(),
+            "elsewhere".to_string(),
+            "unknown".to_string(),
+        ];
+        let placements = missing_placements(&state(), "node-1", &missing);
+
+        assert_eq!(placements.len(), 1);
+        assert_eq!(placements[0].instance_id, "running");
+        assert_eq!(placements[0].desired_state, None);
+    }
+
+    #[test]
+    fn test_missing_placements_keep_correlation_id() {
+        let mut state = state();
+        state
+            .get_mut("workload")
+            .unwrap()
+            .instances
+            .get_mut("running")
+            .unwrap()
+            .set_correlation_id("req-1".to_string());
+        let placements = missing_placements(&state, "node-1", &["running".to_string()]);
+
+        assert_eq!(placements[0].correlation_id, "req-1");
+    }
+}